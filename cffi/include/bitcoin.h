@@ -0,0 +1,62 @@
+/*
+ * C bindings for transaction and PSBT handling.
+ *
+ * Every function returns BTC_OK on success, one of the BTC_ERR_ codes
+ * below 1000 for a misuse of the bindings, or the stable error code of the
+ * library error that occurred: the code of its kind times 1000 plus the
+ * number of the error within its kind. Nothing is written to the output
+ * arguments on failure.
+ *
+ * Handles must be released with btc_tx_free and btc_psbt_free, and buffers
+ * returned by the library with btc_buffer_free.
+ */
+
+#ifndef BITCOIN_CFFI_H
+#define BITCOIN_CFFI_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define BTC_OK 0
+#define BTC_ERR_NULL_POINTER 1
+#define BTC_ERR_PANIC 2
+#define BTC_ERR_CANNOT_FINALIZE 3
+
+typedef struct BtcTx BtcTx;
+typedef struct BtcPsbt BtcPsbt;
+
+typedef struct {
+    uint8_t *data;
+    size_t len;
+} BtcBuffer;
+
+void btc_buffer_free(BtcBuffer buffer);
+
+uint32_t btc_tx_decode(const uint8_t *data, size_t len, BtcTx **out);
+uint32_t btc_tx_encode(const BtcTx *tx, BtcBuffer *out);
+/* The ids are written in serialization order, the reverse of hex display */
+uint32_t btc_tx_txid(const BtcTx *tx, uint8_t (*out)[32]);
+uint32_t btc_tx_wtxid(const BtcTx *tx, uint8_t (*out)[32]);
+void btc_tx_free(BtcTx *tx);
+
+uint32_t btc_psbt_parse(const uint8_t *data, size_t len, BtcPsbt **out);
+uint32_t btc_psbt_serialize(const BtcPsbt *psbt, BtcBuffer *out);
+/* Signatures are made by the caller; the library cannot sign */
+uint32_t btc_psbt_add_partial_sig(BtcPsbt *psbt, size_t index,
+                                  const uint8_t *pubkey, size_t pubkey_len,
+                                  const uint8_t *sig, size_t sig_len);
+uint32_t btc_psbt_add_tap_key_sig(BtcPsbt *psbt, size_t index,
+                                  const uint8_t *sig, size_t sig_len);
+uint32_t btc_psbt_finalize(BtcPsbt *psbt);
+uint32_t btc_psbt_extract_tx(const BtcPsbt *psbt, BtcTx **out);
+void btc_psbt_free(BtcPsbt *psbt);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* BITCOIN_CFFI_H */
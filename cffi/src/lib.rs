@@ -0,0 +1,494 @@
+//! C Bindings
+//!
+//! Exposes transaction and PSBT handling to programs written in other
+//! languages, through the functions declared in `include/bitcoin.h`.
+//!
+//! Transactions and PSBTs are handed out as opaque handles, which the
+//! caller must release with `btc_tx_free` and `btc_psbt_free`. Byte
+//! strings returned by the library are `BtcBuffer`s, released with
+//! `btc_buffer_free`.
+//!
+//! Every function returns a status: `BTC_OK` on success, one of the
+//! `BTC_ERR_` codes below 1000 for a misuse of the bindings, or the stable
+//! `ErrorCode` of the library error that occurred. Nothing is written to
+//! the output arguments on failure.
+
+#![deny(missing_docs)]
+
+extern crate bitcoin;
+
+use std::panic::{self, AssertUnwindSafe};
+use std::{ptr, slice};
+
+use bitcoin::blockdata::script::Builder;
+use bitcoin::consensus::encode::{deserialize, serialize};
+use bitcoin::hashes::Hash;
+use bitcoin::util::psbt::{self, Input, PartiallySignedTransaction};
+use bitcoin::{ErrorCode, Script, Transaction, TxOut};
+
+/// Success
+pub const BTC_OK: u32 = 0;
+/// A pointer argument was null
+pub const BTC_ERR_NULL_POINTER: u32 = 1;
+/// The library panicked, which is a bug
+pub const BTC_ERR_PANIC: u32 = 2;
+/// An input has no signatures the finalizer can use, or spends an output
+/// of a type it does not know
+pub const BTC_ERR_CANNOT_FINALIZE: u32 = 3;
+
+/// A transaction
+pub struct BtcTx(Transaction);
+
+/// A PSBT
+pub struct BtcPsbt(PartiallySignedTransaction);
+
+/// Bytes owned by the library
+#[repr(C)]
+pub struct BtcBuffer {
+    /// The bytes, or null if there are none
+    pub data: *mut u8,
+    /// The number of bytes
+    pub len: usize,
+}
+
+impl BtcBuffer {
+    fn from_vec(data: Vec<u8>) -> BtcBuffer {
+        let len = data.len();
+        if len == 0 {
+            return BtcBuffer {
+                data: ptr::null_mut(),
+                len: 0,
+            };
+        }
+        let data = Box::into_raw(data.into_boxed_slice()) as *mut u8;
+        BtcBuffer { data, len }
+    }
+}
+
+fn code<E: ErrorCode>(e: E) -> u32 {
+    e.code()
+}
+
+/// Runs `f`, turning a panic into `BTC_ERR_PANIC` so that it does not
+/// unwind into the caller
+fn guard<F: FnOnce() -> Result<(), u32>>(f: F) -> u32 {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => BTC_OK,
+        Ok(Err(status)) => status,
+        Err(_) => BTC_ERR_PANIC,
+    }
+}
+
+unsafe fn bytes<'a>(data: *const u8, len: usize) -> Result<&'a [u8], u32> {
+    if len == 0 {
+        Ok(&[])
+    } else if data.is_null() {
+        Err(BTC_ERR_NULL_POINTER)
+    } else {
+        Ok(slice::from_raw_parts(data, len))
+    }
+}
+
+unsafe fn deref<'a, T>(p: *const T) -> Result<&'a T, u32> {
+    p.as_ref().ok_or(BTC_ERR_NULL_POINTER)
+}
+
+unsafe fn deref_mut<'a, T>(p: *mut T) -> Result<&'a mut T, u32> {
+    p.as_mut().ok_or(BTC_ERR_NULL_POINTER)
+}
+
+unsafe fn write<T>(out: *mut T, value: T) -> Result<(), u32> {
+    if out.is_null() {
+        return Err(BTC_ERR_NULL_POINTER);
+    }
+    ptr::write(out, value);
+    Ok(())
+}
+
+/// Releases the bytes of a buffer returned by the library
+///
+/// # Safety
+///
+/// `buffer` must have been returned by the library and not released
+/// already.
+#[no_mangle]
+pub unsafe extern "C" fn btc_buffer_free(buffer: BtcBuffer) {
+    if !buffer.data.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(
+            buffer.data,
+            buffer.len,
+        )));
+    }
+}
+
+/// Decodes a transaction from its consensus encoding
+///
+/// # Safety
+///
+/// `data` must point to `len` readable bytes, and `out` to a writable
+/// handle.
+#[no_mangle]
+pub unsafe extern "C" fn btc_tx_decode(data: *const u8, len: usize, out: *mut *mut BtcTx) -> u32 {
+    guard(|| {
+        let tx: Transaction = deserialize(bytes(data, len)?).map_err(code)?;
+        write(out, Box::into_raw(Box::new(BtcTx(tx))))
+    })
+}
+
+/// Encodes a transaction
+///
+/// # Safety
+///
+/// `tx` must be a live handle and `out` must point to a writable buffer.
+#[no_mangle]
+pub unsafe extern "C" fn btc_tx_encode(tx: *const BtcTx, out: *mut BtcBuffer) -> u32 {
+    guard(|| {
+        let tx = &deref(tx)?.0;
+        write(out, BtcBuffer::from_vec(serialize(tx)))
+    })
+}
+
+/// Writes the txid of a transaction to the 32 bytes at `out`, in the
+/// order it is serialized in, which is the reverse of its hex display
+///
+/// # Safety
+///
+/// `tx` must be a live handle and `out` must point to 32 writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn btc_tx_txid(tx: *const BtcTx, out: *mut [u8; 32]) -> u32 {
+    guard(|| {
+        let txid = deref(tx)?.0.txid();
+        write(out, txid.into_inner())
+    })
+}
+
+/// Writes the wtxid of a transaction to the 32 bytes at `out`, in the
+/// same order as `btc_tx_txid`
+///
+/// # Safety
+///
+/// As for `btc_tx_txid`.
+#[no_mangle]
+pub unsafe extern "C" fn btc_tx_wtxid(tx: *const BtcTx, out: *mut [u8; 32]) -> u32 {
+    guard(|| {
+        let wtxid = deref(tx)?.0.wtxid();
+        write(out, wtxid.into_inner())
+    })
+}
+
+/// Releases a transaction. Does nothing given null.
+///
+/// # Safety
+///
+/// `tx` must be null or a live handle, which must not be used again.
+#[no_mangle]
+pub unsafe extern "C" fn btc_tx_free(tx: *mut BtcTx) {
+    if !tx.is_null() {
+        drop(Box::from_raw(tx));
+    }
+}
+
+/// Decodes a PSBT from its binary encoding, checking that the UTXOs of
+/// its inputs are consistent with its transaction
+///
+/// # Safety
+///
+/// `data` must point to `len` readable bytes, and `out` to a writable
+/// handle.
+#[no_mangle]
+pub unsafe extern "C" fn btc_psbt_parse(
+    data: *const u8,
+    len: usize,
+    out: *mut *mut BtcPsbt,
+) -> u32 {
+    guard(|| {
+        let psbt =
+            PartiallySignedTransaction::deserialize_strict(bytes(data, len)?).map_err(code)?;
+        write(out, Box::into_raw(Box::new(BtcPsbt(psbt))))
+    })
+}
+
+/// Encodes a PSBT in binary
+///
+/// # Safety
+///
+/// `psbt` must be a live handle and `out` must point to a writable
+/// buffer.
+#[no_mangle]
+pub unsafe extern "C" fn btc_psbt_serialize(psbt: *const BtcPsbt, out: *mut BtcBuffer) -> u32 {
+    guard(|| {
+        let psbt = &deref(psbt)?.0;
+        write(out, BtcBuffer::from_vec(serialize(psbt)))
+    })
+}
+
+/// Adds the ECDSA signature of `pubkey` to the input at `index`, as a
+/// signer does. This library cannot sign, so the signature has to be
+/// made by the caller.
+///
+/// # Safety
+///
+/// `psbt` must be a live handle not in use elsewhere, and `pubkey` and
+/// `sig` must point to `pubkey_len` and `sig_len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn btc_psbt_add_partial_sig(
+    psbt: *mut BtcPsbt,
+    index: usize,
+    pubkey: *const u8,
+    pubkey_len: usize,
+    sig: *const u8,
+    sig_len: usize,
+) -> u32 {
+    guard(|| {
+        let psbt = &mut deref_mut(psbt)?.0;
+        let pubkey = bytes(pubkey, pubkey_len)?.to_vec();
+        let sig = bytes(sig, sig_len)?.to_vec();
+        psbt.assert_signable(index).map_err(code)?;
+        psbt.inputs[index].partial_sigs.insert(pubkey, sig);
+        Ok(())
+    })
+}
+
+/// Adds the taproot key path signature to the input at `index`, as a
+/// signer does
+///
+/// # Safety
+///
+/// `psbt` must be a live handle not in use elsewhere, and `sig` must
+/// point to `sig_len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn btc_psbt_add_tap_key_sig(
+    psbt: *mut BtcPsbt,
+    index: usize,
+    sig: *const u8,
+    sig_len: usize,
+) -> u32 {
+    guard(|| {
+        let psbt = &mut deref_mut(psbt)?.0;
+        let sig = bytes(sig, sig_len)?.to_vec();
+        psbt.assert_signable(index).map_err(code)?;
+        psbt.inputs[index].tap_key_sig = Some(sig);
+        Ok(())
+    })
+}
+
+/// Finalizes every input of a PSBT which is not final yet, as the BIP174
+/// finalizer does. Inputs spending P2PKH, P2WPKH, P2SH-P2WPKH and taproot
+/// outputs can be finalized. Stops at the first input which cannot be,
+/// leaving the inputs before it finalized.
+///
+/// # Safety
+///
+/// `psbt` must be a live handle not in use elsewhere.
+#[no_mangle]
+pub unsafe extern "C" fn btc_psbt_finalize(psbt: *mut BtcPsbt) -> u32 {
+    guard(|| {
+        let psbt = &mut deref_mut(psbt)?.0;
+        for index in 0..psbt.inputs.len() {
+            finalize(psbt, index)?;
+        }
+        Ok(())
+    })
+}
+
+/// Extracts the network transaction from a finalized PSBT, which is left
+/// as it was
+///
+/// # Safety
+///
+/// `psbt` must be a live handle and `out` must point to a writable
+/// handle.
+#[no_mangle]
+pub unsafe extern "C" fn btc_psbt_extract_tx(psbt: *const BtcPsbt, out: *mut *mut BtcTx) -> u32 {
+    guard(|| {
+        let psbt = deref(psbt)?.0.clone();
+        let tx = psbt.extract_tx_checked().map_err(code)?;
+        write(out, Box::into_raw(Box::new(BtcTx(tx))))
+    })
+}
+
+/// Releases a PSBT. Does nothing given null.
+///
+/// # Safety
+///
+/// `psbt` must be null or a live handle, which must not be used again.
+#[no_mangle]
+pub unsafe extern "C" fn btc_psbt_free(psbt: *mut BtcPsbt) {
+    if !psbt.is_null() {
+        drop(Box::from_raw(psbt));
+    }
+}
+
+/// The output spent by the input at `index`
+fn spent_utxo(psbt: &PartiallySignedTransaction, index: usize) -> Option<&TxOut> {
+    let input = &psbt.inputs[index];
+    if let Some(ref utxo) = input.witness_utxo {
+        return Some(utxo);
+    }
+    let vout = psbt.global.unsigned_tx.input[index].previous_output.vout as usize;
+    input
+        .non_witness_utxo
+        .as_ref()
+        .and_then(|tx| tx.output.get(vout))
+}
+
+/// The scriptSig and witness spending `spent` with the single signature
+/// `input` holds
+fn single_sig_spend(input: &Input, spent: &Script) -> Option<(Script, Vec<Vec<u8>>)> {
+    let (pubkey, sig) = input.partial_sigs.iter().next()?;
+    if spent.is_p2pkh() {
+        let script_sig = Builder::new()
+            .push_slice(sig)
+            .push_slice(pubkey)
+            .into_script();
+        Some((script_sig, vec![]))
+    } else if spent.is_v0_p2wpkh() {
+        Some((Script::new(), vec![sig.clone(), pubkey.clone()]))
+    } else if spent.is_p2sh() {
+        let redeem_script = input.redeem_script.as_ref()?;
+        if !redeem_script.is_v0_p2wpkh() {
+            return None;
+        }
+        let script_sig = Builder::new()
+            .push_slice(redeem_script.as_bytes())
+            .into_script();
+        Some((script_sig, vec![sig.clone(), pubkey.clone()]))
+    } else {
+        None
+    }
+}
+
+fn finalize(psbt: &mut PartiallySignedTransaction, index: usize) -> Result<(), u32> {
+    if psbt.inputs[index].is_finalized() {
+        return Ok(());
+    }
+    let spent = match spent_utxo(psbt, index) {
+        Some(utxo) => utxo.script_pubkey.clone(),
+        None => return Err(code(psbt::Error::MissingUtxo(index))),
+    };
+    let result = if spent.is_v1_p2tr() {
+        match psbt.inputs[index].tap_key_sig.clone() {
+            Some(sig) => psbt.finalize_input(index, Script::new(), vec![sig]),
+            None => psbt.finalize_tap_script_path(index),
+        }
+    } else {
+        let (script_sig, witness) =
+            single_sig_spend(&psbt.inputs[index], &spent).ok_or(BTC_ERR_CANNOT_FINALIZE)?;
+        psbt.finalize_input(index, script_sig, witness)
+    };
+    result.map_err(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bitcoin::blockdata::transaction::{OutPoint, TxIn};
+    use bitcoin::{PubkeyHash, Txid};
+
+    fn unsigned_tx() -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid::hash(&[1]), 0),
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value: 9_000,
+                script_pubkey: Script::new_p2pkh(&PubkeyHash::hash(&[2])),
+            }],
+        }
+    }
+
+    unsafe fn take(buffer: BtcBuffer) -> Vec<u8> {
+        let data = slice::from_raw_parts(buffer.data, buffer.len).to_vec();
+        btc_buffer_free(buffer);
+        data
+    }
+
+    #[test]
+    fn tx() {
+        let encoded = serialize(&unsigned_tx());
+        unsafe {
+            let mut tx = ptr::null_mut();
+            assert_eq!(
+                btc_tx_decode(encoded.as_ptr(), encoded.len(), &mut tx),
+                BTC_OK
+            );
+
+            let mut buffer = BtcBuffer {
+                data: ptr::null_mut(),
+                len: 0,
+            };
+            assert_eq!(btc_tx_encode(tx, &mut buffer), BTC_OK);
+            assert_eq!(take(buffer), encoded);
+
+            let mut txid = [0; 32];
+            assert_eq!(btc_tx_txid(tx, &mut txid), BTC_OK);
+            assert_eq!(txid, unsigned_tx().txid().into_inner());
+            let mut wtxid = [0; 32];
+            assert_eq!(btc_tx_wtxid(tx, &mut wtxid), BTC_OK);
+            assert_eq!(wtxid, txid);
+            btc_tx_free(tx);
+
+            // Errors carry the library's codes
+            let mut tx = ptr::null_mut();
+            let status = btc_tx_decode(encoded.as_ptr(), encoded.len() - 1, &mut tx);
+            assert_eq!(status / 1000, bitcoin::ErrorKind::Encode.code());
+            assert!(tx.is_null());
+            assert_eq!(btc_tx_decode(ptr::null(), 1, &mut tx), BTC_ERR_NULL_POINTER);
+            assert_eq!(btc_tx_txid(ptr::null(), &mut txid), BTC_ERR_NULL_POINTER);
+            btc_tx_free(ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn psbt() {
+        let mut unsigned = PartiallySignedTransaction::from_unsigned_tx(unsigned_tx()).unwrap();
+        unsigned.inputs[0].witness_utxo = Some(TxOut {
+            value: 10_000,
+            script_pubkey: Script::new_v0_wpkh(&Hash::hash(&[3])),
+        });
+        let encoded = serialize(&unsigned);
+        unsafe {
+            let mut psbt = ptr::null_mut();
+            assert_eq!(
+                btc_psbt_parse(encoded.as_ptr(), encoded.len(), &mut psbt),
+                BTC_OK
+            );
+
+            // Not signed yet
+            let mut tx = ptr::null_mut();
+            assert_eq!(btc_psbt_finalize(psbt), BTC_ERR_CANNOT_FINALIZE);
+            assert_eq!(
+                btc_psbt_extract_tx(psbt, &mut tx),
+                psbt::Error::InputNotFinalized(0).code()
+            );
+
+            let (pubkey, sig) = ([2; 33], [0x30; 71]);
+            let status = btc_psbt_add_partial_sig(psbt, 1, pubkey.as_ptr(), 33, sig.as_ptr(), 71);
+            assert_eq!(status, psbt::Error::InputIndexOutOfBounds(1).code());
+            let status = btc_psbt_add_partial_sig(psbt, 0, pubkey.as_ptr(), 33, sig.as_ptr(), 71);
+            assert_eq!(status, BTC_OK);
+
+            let mut buffer = BtcBuffer {
+                data: ptr::null_mut(),
+                len: 0,
+            };
+            assert_eq!(btc_psbt_serialize(psbt, &mut buffer), BTC_OK);
+            let signed: PartiallySignedTransaction = deserialize(&take(buffer)).unwrap();
+            assert_eq!(signed.inputs[0].partial_sigs.len(), 1);
+
+            assert_eq!(btc_psbt_finalize(psbt), BTC_OK);
+            assert_eq!(btc_psbt_extract_tx(psbt, &mut tx), BTC_OK);
+            let extracted = &(*tx).0;
+            assert_eq!(
+                extracted.input[0].witness,
+                vec![sig.to_vec(), pubkey.to_vec()]
+            );
+            btc_tx_free(tx);
+            btc_psbt_free(psbt);
+        }
+    }
+}
@@ -0,0 +1,317 @@
+//! Connects to a peer and scans its first compact block filters for a
+//! script.
+//!
+//! Usage: `cargo run --example compact_filter_peer -- <network> <host:port>
+//! <script hex> [<proxy host:port>]`. The peer is dialed, through the SOCKS5
+//! proxy if one is given, and the `version` handshake is done as a light
+//! client would: the peer's software, height and services are printed, and
+//! it is checked with a `ping` that the connection works.
+//!
+//! If the peer serves compact filters, the headers following the genesis
+//! block are fetched, up to the 1000 blocks one `getcfilters` request
+//! covers, along with the filter hashes of those blocks from `cfheaders`.
+//! Each BIP158 filter received is checked against its hash and matched
+//! against the script, and the blocks which may pay to or spend from it are
+//! printed. The headers are only checked to form a chain with valid proof
+//! of work; a wallet would cross-check them with other peers.
+
+extern crate bitcoin;
+
+use std::env;
+use std::io::Cursor;
+use std::iter;
+use std::net::{Shutdown, SocketAddr, TcpStream};
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bitcoin::blockdata::block::BlockHeader;
+use bitcoin::consensus::encode::{serialize, Decodable, Encodable, VarInt};
+use bitcoin::hashes::hex::FromHex;
+use bitcoin::hashes::{sha256d, Hash};
+use bitcoin::network::config::Config;
+use bitcoin::network::constants::{Network, ServiceFlags, PROTOCOL_VERSION};
+use bitcoin::network::dialer::{Dialer, Target};
+use bitcoin::network::message::{CommandString, NetworkMessage, RawNetworkMessage};
+use bitcoin::network::message_network::VersionMessage;
+use bitcoin::network::nonce::{NonceManager, DEFAULT_NONCE_CAPACITY};
+use bitcoin::network::Address;
+use bitcoin::util::bip158::{BlockFilter, FilterBatch, ScriptSet};
+use bitcoin::{BlockHash, Script};
+
+/// The filter type of basic BIP158 filters
+const BASIC_FILTER: u8 = 0;
+/// The most filters one `getcfilters` request can ask for
+const MAX_FILTERS: usize = 1000;
+
+fn fail(msg: String) -> ! {
+    eprintln!("error: {}", msg);
+    process::exit(1);
+}
+
+fn parse_target(arg: &str) -> Target {
+    if let Ok(addr) = arg.parse::<SocketAddr>() {
+        return Target::Socket(addr);
+    }
+    let mut parts = arg.rsplitn(2, ':');
+    match (parts.next().map(str::parse), parts.next()) {
+        (Some(Ok(port)), Some(host)) => Target::Host(host.to_owned(), port),
+        _ => fail(format!("invalid peer {}", arg)),
+    }
+}
+
+/// The hash of the genesis block of `network`
+fn genesis_hash(network: Network) -> BlockHash {
+    let hex = match network {
+        Network::Bitcoin => "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f",
+        Network::Testnet => "000000000933ea01ad0ee984209779baaec3ced90fa3f408719526f8d77f4943",
+        Network::Regtest => "0f9188f13cb7b2c71f2a335e3a4fc328bf5beb436012afca590b1a11466e2206",
+    };
+    BlockHash::from_hex(hex).expect("valid hash")
+}
+
+fn decode<T: Decodable>(cursor: &mut Cursor<&[u8]>, what: &str) -> T {
+    T::consensus_decode(cursor).unwrap_or_else(|e| fail(format!("invalid {}: {}", what, e)))
+}
+
+struct Peer {
+    stream: TcpStream,
+    config: Config,
+}
+
+impl Peer {
+    fn send(&mut self, payload: NetworkMessage) {
+        let msg = RawNetworkMessage {
+            magic: self.config.magic(),
+            payload,
+        };
+        msg.consensus_encode(&mut self.stream)
+            .unwrap_or_else(|e| fail(format!("cannot send: {}", e)));
+    }
+
+    /// The next message, answering pings on the way
+    fn receive(&mut self) -> NetworkMessage {
+        loop {
+            let msg = RawNetworkMessage::consensus_decode(&mut self.stream)
+                .unwrap_or_else(|e| fail(format!("cannot receive: {}", e)));
+            if msg.magic != self.config.magic() {
+                fail(format!("peer is on another network: {}", msg.magic));
+            }
+            match msg.payload {
+                NetworkMessage::Ping(nonce) => self.send(NetworkMessage::Pong(nonce)),
+                payload => return payload,
+            }
+        }
+    }
+
+    /// Sends a message which has no `NetworkMessage` variant of its own
+    fn send_raw(&mut self, command: &'static str, payload: Vec<u8>) {
+        let command = CommandString::try_from_str(command).expect("valid command");
+        self.send(NetworkMessage::Unknown { command, payload });
+    }
+
+    /// The payload of the next message with `command`, skipping any others
+    fn receive_raw(&mut self, command: &str) -> Vec<u8> {
+        loop {
+            match self.receive() {
+                NetworkMessage::Unknown {
+                    command: c,
+                    payload,
+                } => {
+                    if c.as_ref() == command {
+                        return payload;
+                    }
+                }
+                NetworkMessage::Reject(reject) => fail(format!("rejected: {:?}", reject)),
+                _ => {}
+            }
+        }
+    }
+
+    /// Exchanges `version` and `verack` messages, returning the peer's
+    /// `version`
+    fn handshake(&mut self, nonces: &mut NonceManager) -> VersionMessage {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+        let local = self
+            .stream
+            .local_addr()
+            .unwrap_or_else(|e| fail(e.to_string()));
+        let remote = self
+            .stream
+            .peer_addr()
+            .unwrap_or_else(|e| fail(e.to_string()));
+        let user_agent = self
+            .config
+            .user_agent()
+            .unwrap_or_else(|e| fail(e.to_string()));
+        let version = nonces.version_message(
+            self.config.services,
+            now,
+            Address::new(&remote, ServiceFlags::NONE),
+            Address::new(&local, self.config.services),
+            user_agent,
+            0,
+        );
+        self.send(NetworkMessage::Version(version));
+
+        let mut their_version = None;
+        let mut verack = false;
+        while their_version.is_none() || !verack {
+            match self.receive() {
+                NetworkMessage::Version(v) => {
+                    if let Err(e) = nonces.check_incoming(&v) {
+                        fail(e.to_string());
+                    }
+                    self.send(NetworkMessage::Verack);
+                    their_version = Some(v);
+                }
+                NetworkMessage::Verack => verack = true,
+                NetworkMessage::Reject(reject) => fail(format!("rejected: {:?}", reject)),
+                _ => {}
+            }
+        }
+        their_version.expect("loop ends once received")
+    }
+}
+
+/// Fetches the headers following the genesis block, returning the hashes
+/// of at most `MAX_FILTERS` blocks from the genesis block on
+fn fetch_block_hashes(peer: &mut Peer, network: Network) -> Vec<BlockHash> {
+    let genesis = genesis_hash(network);
+    let mut request = serialize(&PROTOCOL_VERSION);
+    request.extend(serialize(&VarInt(1)));
+    request.extend(serialize(&genesis));
+    request.extend(serialize(&BlockHash::from_inner([0; 32])));
+    peer.send_raw("getheaders", request);
+
+    let payload = peer.receive_raw("headers");
+    let mut cursor = Cursor::new(&payload[..]);
+    let count = decode::<VarInt>(&mut cursor, "headers").0;
+    let mut hashes = vec![genesis];
+    for _ in 0..count {
+        let header: BlockHeader = decode(&mut cursor, "header");
+        // Each header is followed by an empty transaction count
+        decode::<VarInt>(&mut cursor, "header");
+        if Some(&header.prev_blockhash) != hashes.last() || !header.has_valid_pow() {
+            fail(format!(
+                "header {} does not extend the chain",
+                header.block_hash()
+            ));
+        }
+        hashes.push(header.block_hash());
+    }
+    hashes.truncate(MAX_FILTERS);
+    hashes
+}
+
+/// Fetches the basic filters of `blocks`, which start at the genesis block,
+/// checking each against the filter hash the peer commits to in `cfheaders`
+fn fetch_filters(peer: &mut Peer, blocks: &[BlockHash]) -> FilterBatch {
+    let stop = *blocks.last().expect("the genesis block is always there");
+    let mut request = serialize(&BASIC_FILTER);
+    request.extend(serialize(&0u32));
+    request.extend(serialize(&stop));
+
+    peer.send_raw("getcfheaders", request.clone());
+    let payload = peer.receive_raw("cfheaders");
+    let mut cursor = Cursor::new(&payload[..]);
+    decode::<u8>(&mut cursor, "cfheaders");
+    let stop_hash: BlockHash = decode(&mut cursor, "cfheaders");
+    decode::<sha256d::Hash>(&mut cursor, "cfheaders");
+    let count = decode::<VarInt>(&mut cursor, "cfheaders").0;
+    if stop_hash != stop || count != blocks.len() as u64 {
+        fail("cfheaders do not cover the requested blocks".to_owned());
+    }
+    let filter_hashes: Vec<sha256d::Hash> = (0..count)
+        .map(|_| decode(&mut cursor, "cfheaders"))
+        .collect();
+
+    peer.send_raw("getcfilters", request);
+    let mut batch = FilterBatch::new();
+    for (height, (block_hash, filter_hash)) in blocks.iter().zip(filter_hashes).enumerate() {
+        let payload = peer.receive_raw("cfilter");
+        let mut cursor = Cursor::new(&payload[..]);
+        decode::<u8>(&mut cursor, "cfilter");
+        if decode::<BlockHash>(&mut cursor, "cfilter") != *block_hash {
+            fail(format!("expected the filter of block {}", block_hash));
+        }
+        let content: Vec<u8> = decode(&mut cursor, "cfilter");
+        if sha256d::Hash::hash(&content) != filter_hash {
+            fail(format!(
+                "the filter of block {} does not match its hash",
+                block_hash
+            ));
+        }
+        batch.push(height as u32, *block_hash, BlockFilter::new(content));
+    }
+    batch
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 4 {
+        fail(
+            "usage: compact_filter_peer <network> <host:port> <script hex> [<proxy host:port>]"
+                .to_owned(),
+        );
+    }
+    let network: Network = args[1]
+        .parse()
+        .unwrap_or_else(|_| fail(format!("unknown network {}", args[1])));
+    let target = parse_target(&args[2]);
+    let script = Vec::<u8>::from_hex(&args[3])
+        .map(Script::from)
+        .unwrap_or_else(|_| fail(format!("invalid script {}", args[3])));
+    let config = Config {
+        network,
+        client: Some(("compact_filter_peer".to_owned(), "0.1".to_owned())),
+        proxy: args.get(4).map(|proxy| {
+            proxy
+                .parse()
+                .unwrap_or_else(|_| fail(format!("invalid proxy {}", proxy)))
+        }),
+        ..Default::default()
+    };
+    config
+        .validate()
+        .unwrap_or_else(|e| fail(format!("invalid config: {}", e)));
+
+    let stream = config
+        .dialer()
+        .dial(&target)
+        .unwrap_or_else(|e| fail(format!("cannot connect to {:?}: {}", target, e)));
+    let mut peer = Peer { stream, config };
+    let mut nonces = NonceManager::new(DEFAULT_NONCE_CAPACITY);
+    let version = peer.handshake(&mut nonces);
+
+    println!("user agent: {}", version.user_agent);
+    println!("protocol version: {}", version.version);
+    println!("height: {}", version.start_height);
+    println!("services: {}", version.services);
+    let serves_filters = version.services.has(ServiceFlags::COMPACT_FILTERS);
+    println!("serves compact filters: {}", serves_filters);
+
+    let nonce = nonces.generate();
+    peer.send(NetworkMessage::Ping(nonce));
+    loop {
+        if let NetworkMessage::Pong(n) = peer.receive() {
+            if n == nonce {
+                println!("ping answered");
+                break;
+            }
+        }
+    }
+
+    if serves_filters {
+        let blocks = fetch_block_hashes(&mut peer, network);
+        let filters = fetch_filters(&mut peer, &blocks);
+        println!("filters checked: {}", filters.len());
+        let scripts: ScriptSet = iter::once(script).collect();
+        for block_hash in filters.match_scripts(&scripts) {
+            println!("matching block: {}", block_hash);
+        }
+    }
+    let _ = peer.stream.shutdown(Shutdown::Both);
+}
@@ -0,0 +1,146 @@
+//! Finalizes a signed PSBT and prints the network transaction.
+//!
+//! Usage: `cargo run --example finalize_psbt -- <file>`, where the file
+//! holds a PSBT in binary or as hex. The PSBT is listed with its fee, every
+//! input is finalized from the signatures it holds, and the transaction is
+//! printed as hex, ready to be broadcast.
+//!
+//! Inputs spending P2PKH, P2WPKH, P2SH-P2WPKH and taproot outputs can be
+//! finalized. This library cannot sign, so the PSBT has to be signed by a
+//! wallet first.
+
+extern crate bitcoin;
+
+use std::env;
+use std::fs::File;
+use std::io::Read;
+use std::process;
+
+use bitcoin::blockdata::script::Builder;
+use bitcoin::consensus::encode::{deserialize, serialize};
+use bitcoin::hashes::hex::{FromHex, ToHex};
+use bitcoin::util::psbt::{Input, PartiallySignedTransaction};
+use bitcoin::{Script, TxOut};
+
+fn fail(msg: String) -> ! {
+    eprintln!("error: {}", msg);
+    process::exit(1);
+}
+
+/// Reads a PSBT file, which may hold hex rather than binary
+fn read_psbt(path: &str) -> PartiallySignedTransaction {
+    let mut data = vec![];
+    File::open(path)
+        .and_then(|mut file| file.read_to_end(&mut data))
+        .unwrap_or_else(|e| fail(format!("cannot read {}: {}", path, e)));
+    if let Ok(hex) = String::from_utf8(data.clone()) {
+        if let Ok(bytes) = Vec::<u8>::from_hex(hex.trim()) {
+            data = bytes;
+        }
+    }
+    deserialize(&data).unwrap_or_else(|e| fail(format!("invalid PSBT: {}", e)))
+}
+
+/// The output spent by input `index`
+fn spent_utxo(psbt: &PartiallySignedTransaction, index: usize) -> Option<TxOut> {
+    let input = &psbt.inputs[index];
+    if let Some(ref utxo) = input.witness_utxo {
+        return Some(utxo.clone());
+    }
+    let vout = psbt.global.unsigned_tx.input[index].previous_output.vout as usize;
+    input
+        .non_witness_utxo
+        .as_ref()
+        .and_then(|tx| tx.output.get(vout).cloned())
+}
+
+/// The scriptSig and witness spending `spent` with the single signature
+/// `input` holds
+fn single_sig_spend(input: &Input, spent: &Script) -> Option<(Script, Vec<Vec<u8>>)> {
+    let (pubkey, sig) = input.partial_sigs.iter().next()?;
+    if spent.is_p2pkh() {
+        let script_sig = Builder::new()
+            .push_slice(sig)
+            .push_slice(pubkey)
+            .into_script();
+        Some((script_sig, vec![]))
+    } else if spent.is_v0_p2wpkh() {
+        Some((Script::new(), vec![sig.clone(), pubkey.clone()]))
+    } else if spent.is_p2sh() {
+        let redeem_script = input.redeem_script.as_ref()?;
+        if !redeem_script.is_v0_p2wpkh() {
+            return None;
+        }
+        let script_sig = Builder::new()
+            .push_slice(redeem_script.as_bytes())
+            .into_script();
+        Some((script_sig, vec![sig.clone(), pubkey.clone()]))
+    } else {
+        None
+    }
+}
+
+fn finalize(psbt: &mut PartiallySignedTransaction, index: usize) -> Result<(), String> {
+    if psbt.inputs[index].is_finalized() {
+        return Ok(());
+    }
+    let spent = spent_utxo(psbt, index)
+        .ok_or_else(|| format!("input {} has no UTXO", index))?
+        .script_pubkey;
+    if spent.is_v1_p2tr() {
+        if let Some(sig) = psbt.inputs[index].tap_key_sig.clone() {
+            return psbt
+                .finalize_input(index, Script::new(), vec![sig])
+                .map_err(|e| e.to_string());
+        }
+        return psbt
+            .finalize_tap_script_path(index)
+            .map_err(|e| e.to_string());
+    }
+    let (script_sig, witness) = single_sig_spend(&psbt.inputs[index], &spent)
+        .ok_or_else(|| format!("input {} is not signed, or of an unsupported type", index))?;
+    psbt.finalize_input(index, script_sig, witness)
+        .map_err(|e| e.to_string())
+}
+
+fn main() {
+    let path = match env::args().nth(1) {
+        Some(path) => path,
+        None => fail("usage: finalize_psbt <file>".to_owned()),
+    };
+    let mut psbt = read_psbt(&path);
+
+    let summary = psbt.summary();
+    for (index, input) in summary.inputs.iter().enumerate() {
+        let value = match input.value {
+            Some(value) => value.to_string(),
+            None => "unknown amount".to_owned(),
+        };
+        eprintln!(
+            "input {}: {} ({}), {} signature(s)",
+            index, input.previous_output, value, input.signatures
+        );
+    }
+    for (index, output) in summary.outputs.iter().enumerate() {
+        eprintln!(
+            "output {}: {} to {}",
+            index,
+            output.value,
+            output.script_pubkey.asm()
+        );
+    }
+    match psbt.fee() {
+        Ok(fee) => eprintln!("fee: {}", fee),
+        Err(e) => eprintln!("fee: unknown ({})", e),
+    }
+
+    for index in 0..psbt.inputs.len() {
+        if let Err(e) = finalize(&mut psbt, index) {
+            fail(e);
+        }
+    }
+    let tx = psbt
+        .extract_tx_checked()
+        .unwrap_or_else(|e| fail(e.to_string()));
+    println!("{}", serialize(&tx).to_hex());
+}
@@ -0,0 +1,344 @@
+//! Python Bindings
+//!
+//! Exposes transactions, PSBTs, addresses and amounts to Python through
+//! pyo3. Build and install into the current environment with
+//! `maturin develop --release` from this directory.
+//!
+//! Decoders borrow the `bytes` they are given rather than copying them, so
+//! that large dumps of transactions can be fed straight from a file or a
+//! dataframe column. Failures are raised as `BitcoinError`, whose `code`
+//! attribute is the stable `ErrorCode` of the library error.
+
+use std::fmt;
+use std::str::FromStr;
+
+use pyo3::basic::CompareOp;
+use pyo3::create_exception;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+use bitcoin::consensus::encode::{deserialize, serialize};
+use bitcoin::network::constants::Network;
+use bitcoin::util::address;
+use bitcoin::util::amount::Denomination;
+use bitcoin::util::psbt::PartiallySignedTransaction;
+use bitcoin::{ErrorCode, Script};
+
+create_exception!(bitcoin_py, BitcoinError, PyValueError);
+
+/// A `BitcoinError` carrying the code of a library error
+fn py_error<E: fmt::Display + ErrorCode>(e: E) -> PyErr {
+    Python::with_gil(|py| {
+        let err = BitcoinError::new_err(e.to_string());
+        // Setting an attribute of a fresh exception cannot fail
+        let _ = err.value(py).setattr("code", e.code());
+        err
+    })
+}
+
+/// A transaction
+#[pyclass(name = "Transaction", module = "bitcoin_py")]
+#[derive(Clone)]
+pub struct PyTransaction(bitcoin::Transaction);
+
+#[pymethods]
+impl PyTransaction {
+    /// Decodes a transaction from its consensus encoding
+    #[staticmethod]
+    fn from_bytes(data: &[u8]) -> PyResult<Self> {
+        deserialize(data).map(PyTransaction).map_err(py_error)
+    }
+
+    /// The consensus encoding of the transaction
+    fn to_bytes<'p>(&self, py: Python<'p>) -> &'p PyBytes {
+        PyBytes::new(py, &serialize(&self.0))
+    }
+
+    fn __bytes__<'p>(&self, py: Python<'p>) -> &'p PyBytes {
+        self.to_bytes(py)
+    }
+
+    /// The txid, in hex
+    #[getter]
+    fn txid(&self) -> String {
+        self.0.txid().to_string()
+    }
+
+    /// The wtxid, in hex
+    #[getter]
+    fn wtxid(&self) -> String {
+        self.0.wtxid().to_string()
+    }
+
+    /// The version
+    #[getter]
+    fn version(&self) -> u32 {
+        self.0.version
+    }
+
+    /// The lock time
+    #[getter]
+    fn lock_time(&self) -> u32 {
+        self.0.lock_time
+    }
+
+    /// The weight, in weight units
+    #[getter]
+    fn weight(&self) -> usize {
+        self.0.get_weight()
+    }
+
+    /// The virtual size, in vbytes
+    #[getter]
+    fn vsize(&self) -> usize {
+        self.0.get_vsize()
+    }
+
+    /// The number of inputs
+    #[getter]
+    fn input_count(&self) -> usize {
+        self.0.input.len()
+    }
+
+    /// The number of outputs
+    #[getter]
+    fn output_count(&self) -> usize {
+        self.0.output.len()
+    }
+
+    /// The outpoints spent by the inputs, as "txid:vout" strings
+    #[getter]
+    fn outpoints(&self) -> Vec<String> {
+        self.0
+            .input
+            .iter()
+            .map(|txin| txin.previous_output.to_string())
+            .collect()
+    }
+
+    /// The values of the outputs, in satoshis
+    #[getter]
+    fn output_values(&self) -> Vec<u64> {
+        self.0.output.iter().map(|txout| txout.value).collect()
+    }
+
+    /// The scripts of the outputs
+    #[getter]
+    fn output_scripts<'p>(&self, py: Python<'p>) -> Vec<&'p PyBytes> {
+        self.0
+            .output
+            .iter()
+            .map(|txout| PyBytes::new(py, txout.script_pubkey.as_bytes()))
+            .collect()
+    }
+
+    /// Whether the transaction is a coinbase
+    fn is_coin_base(&self) -> bool {
+        self.0.is_coin_base()
+    }
+
+    fn __eq__(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Transaction({})", self.0.txid())
+    }
+}
+
+/// A PSBT
+#[pyclass(name = "Psbt", module = "bitcoin_py")]
+#[derive(Clone)]
+pub struct PyPsbt(PartiallySignedTransaction);
+
+#[pymethods]
+impl PyPsbt {
+    /// Decodes a PSBT from its binary encoding, checking that the UTXOs of
+    /// its inputs are consistent with its transaction
+    #[staticmethod]
+    fn from_bytes(data: &[u8]) -> PyResult<Self> {
+        PartiallySignedTransaction::deserialize_strict(data)
+            .map(PyPsbt)
+            .map_err(py_error)
+    }
+
+    /// The binary encoding of the PSBT
+    fn to_bytes<'p>(&self, py: Python<'p>) -> &'p PyBytes {
+        PyBytes::new(py, &serialize(&self.0))
+    }
+
+    fn __bytes__<'p>(&self, py: Python<'p>) -> &'p PyBytes {
+        self.to_bytes(py)
+    }
+
+    /// The unsigned transaction
+    #[getter]
+    fn unsigned_tx(&self) -> PyTransaction {
+        PyTransaction(self.0.global.unsigned_tx.clone())
+    }
+
+    /// The number of inputs
+    #[getter]
+    fn input_count(&self) -> usize {
+        self.0.inputs.len()
+    }
+
+    /// The number of outputs
+    #[getter]
+    fn output_count(&self) -> usize {
+        self.0.outputs.len()
+    }
+
+    /// Whether the input at `index` exists and has been finalized
+    fn is_finalized(&self, index: usize) -> bool {
+        self.0
+            .inputs
+            .get(index)
+            .map_or(false, |input| input.is_finalized())
+    }
+
+    /// The fee paid
+    fn fee(&self) -> PyResult<PyAmount> {
+        self.0.fee().map(PyAmount).map_err(py_error)
+    }
+
+    /// The network transaction, once every input has been finalized
+    fn extract_tx(&self) -> PyResult<PyTransaction> {
+        self.0
+            .clone()
+            .extract_tx_checked()
+            .map(PyTransaction)
+            .map_err(py_error)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Psbt({})", self.0.global.unsigned_tx.txid())
+    }
+}
+
+/// An address
+#[pyclass(name = "Address", module = "bitcoin_py")]
+#[derive(Clone)]
+pub struct PyAddress(address::Address);
+
+#[pymethods]
+impl PyAddress {
+    /// Parses an address
+    #[new]
+    fn new(s: &str) -> PyResult<Self> {
+        address::Address::from_str(s)
+            .map(PyAddress)
+            .map_err(py_error)
+    }
+
+    /// The address paying to a script, on a network named as in
+    /// "bitcoin", "testnet" or "regtest", if the script has an address
+    #[staticmethod]
+    fn from_script(script: &[u8], network: &str) -> PyResult<Option<Self>> {
+        let network =
+            Network::from_str(network).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let script = Script::from(script.to_vec());
+        Ok(address::Address::from_script(&script, network).map(PyAddress))
+    }
+
+    /// The script paying to the address
+    #[getter]
+    fn script_pubkey<'p>(&self, py: Python<'p>) -> &'p PyBytes {
+        PyBytes::new(py, self.0.script_pubkey().as_bytes())
+    }
+
+    /// The network of the address
+    #[getter]
+    fn network(&self) -> String {
+        self.0.network.to_string()
+    }
+
+    /// The type of the address, such as "p2wpkh", if it is standard
+    #[getter]
+    fn address_type(&self) -> Option<String> {
+        self.0.address_type().map(|t| t.to_string())
+    }
+
+    fn __eq__(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+
+    fn __str__(&self) -> String {
+        self.0.to_string()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Address('{}')", self.0)
+    }
+}
+
+/// An amount of bitcoin
+#[pyclass(name = "Amount", module = "bitcoin_py")]
+#[derive(Copy, Clone)]
+pub struct PyAmount(bitcoin::Amount);
+
+#[pymethods]
+impl PyAmount {
+    /// An amount of satoshis
+    #[new]
+    fn new(sat: u64) -> Self {
+        PyAmount(bitcoin::Amount::from_sat(sat))
+    }
+
+    /// Parses an amount with its denomination, such as "0.1 BTC"
+    #[staticmethod]
+    fn parse(s: &str) -> PyResult<Self> {
+        bitcoin::Amount::from_str(s)
+            .map(PyAmount)
+            .map_err(py_error)
+    }
+
+    /// The amount in satoshis
+    #[getter]
+    fn sat(&self) -> u64 {
+        self.0.as_sat()
+    }
+
+    /// The amount in bitcoin, as a float
+    #[getter]
+    fn btc(&self) -> f64 {
+        self.0.as_btc()
+    }
+
+    /// Formats the amount in a denomination, such as "BTC"
+    fn to_string_in(&self, denomination: &str) -> PyResult<String> {
+        let denomination = Denomination::from_str(denomination).map_err(py_error)?;
+        Ok(self.0.to_string_with_denomination(denomination))
+    }
+
+    fn __int__(&self) -> u64 {
+        self.0.as_sat()
+    }
+
+    fn __hash__(&self) -> u64 {
+        self.0.as_sat()
+    }
+
+    fn __richcmp__(&self, other: &Self, op: CompareOp) -> bool {
+        op.matches(self.0.cmp(&other.0))
+    }
+
+    fn __str__(&self) -> String {
+        self.0.to_string()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Amount({})", self.0.as_sat())
+    }
+}
+
+#[pymodule]
+fn bitcoin_py(py: Python, m: &PyModule) -> PyResult<()> {
+    m.add("BitcoinError", py.get_type::<BitcoinError>())?;
+    m.add_class::<PyTransaction>()?;
+    m.add_class::<PyPsbt>()?;
+    m.add_class::<PyAddress>()?;
+    m.add_class::<PyAmount>()?;
+    Ok(())
+}
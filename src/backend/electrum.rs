@@ -0,0 +1,340 @@
+//! Electrum Client
+//!
+//! The Electrum protocol is JSON-RPC, one message per line, over TCP or
+//! TLS. `ElectrumClient::connect` connects over plain TCP. For TLS, the
+//! application opens the stream with the TLS library of its choice and
+//! passes it to `ElectrumClient::new`, as any stream which reads and
+//! writes will do.
+//!
+//! Notifications the server pushes, such as new tips after a
+//! `blockchain.headers.subscribe`, are skipped.
+
+use std::convert::TryFrom;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+
+use backend::json::{self, Value};
+use backend::{check_txid, Backend, Error, Utxo};
+use blockdata::block::BlockHeader;
+use blockdata::script::Script;
+use blockdata::transaction::{OutPoint, Transaction, TxOut};
+use consensus::encode::deserialize_hex;
+use hash_types::{BlockHash, Txid};
+use hashes::hex::{FromHex, ToHex};
+use hashes::{sha256, Hash};
+
+/// The version of the protocol spoken
+pub const PROTOCOL_VERSION: &str = "1.4";
+
+/// The longest response line read, beyond which the server is assumed
+/// to be misbehaving
+pub const MAX_RESPONSE_SIZE: u64 = 32 * 1024 * 1024;
+
+/// A client of an Electrum server
+#[derive(Debug)]
+pub struct ElectrumClient<S: Read + Write> {
+    stream: BufReader<S>,
+    next_id: u64,
+    negotiated: bool,
+}
+
+impl ElectrumClient<TcpStream> {
+    /// Connects to a server over plain TCP
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> io::Result<ElectrumClient<TcpStream>> {
+        Ok(ElectrumClient::new(TcpStream::connect(addr)?))
+    }
+}
+
+impl<S: Read + Write> ElectrumClient<S> {
+    /// Creates a client talking over `stream`. The protocol version is
+    /// negotiated with the first request.
+    pub fn new(stream: S) -> ElectrumClient<S> {
+        ElectrumClient {
+            stream: BufReader::new(stream),
+            next_id: 0,
+            negotiated: false,
+        }
+    }
+
+    /// Calls `method` with `params`, a JSON array
+    fn call(&mut self, method: &str, params: &str) -> Result<Value, Error> {
+        if !self.negotiated {
+            self.negotiated = true;
+            let version = format!("[\"learn-bitcoin-rs\", \"{}\"]", PROTOCOL_VERSION);
+            self.call("server.version", &version)?;
+        }
+
+        let id = self.next_id;
+        self.next_id += 1;
+        let request = format!(
+            "{{\"jsonrpc\":\"2.0\",\"id\":{},\"method\":\"{}\",\"params\":{}}}\n",
+            id, method, params
+        );
+        let stream = self.stream.get_mut();
+        stream.write_all(request.as_bytes())?;
+        stream.flush()?;
+
+        loop {
+            let mut line = String::new();
+            let read = (&mut self.stream)
+                .take(MAX_RESPONSE_SIZE)
+                .read_line(&mut line)?;
+            if read == 0 {
+                return Err(Error::Io(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "connection closed",
+                )));
+            }
+            if !line.ends_with('\n') {
+                return Err(Error::Json("response too long"));
+            }
+            let response = json::parse(&line)?;
+            // Notifications have no id
+            if response.get("id").and_then(Value::as_u64) != Some(id) {
+                continue;
+            }
+            return match response.get("error") {
+                None | Some(&Value::Null) => match response.get("result") {
+                    Some(result) => Ok(result.clone()),
+                    None => Err(Error::Json("response without a result")),
+                },
+                Some(error) => Err(Error::Server(
+                    error
+                        .get("message")
+                        .and_then(Value::as_str)
+                        .unwrap_or("unknown error")
+                        .to_owned(),
+                )),
+            };
+        }
+    }
+}
+
+impl<S: Read + Write> Backend for ElectrumClient<S> {
+    fn tip(&mut self) -> Result<(u32, BlockHash), Error> {
+        let tip = self.call("blockchain.headers.subscribe", "[]")?;
+        let header: BlockHeader = deserialize_hex(json_str(tip.get("hex"))?)?;
+        Ok((json_u32(tip.get("height"))?, header.block_hash()))
+    }
+
+    fn header(&mut self, height: u32) -> Result<BlockHeader, Error> {
+        let header = self.call("blockchain.block.header", &format!("[{}]", height))?;
+        Ok(deserialize_hex(json_str(Some(&header))?)?)
+    }
+
+    fn transaction(&mut self, txid: &Txid) -> Result<Transaction, Error> {
+        let tx = self.call("blockchain.transaction.get", &format!("[\"{}\"]", txid))?;
+        check_txid(deserialize_hex(json_str(Some(&tx))?)?, txid)
+    }
+
+    fn utxos(&mut self, script_pubkey: &Script) -> Result<Vec<Utxo>, Error> {
+        let params = format!("[\"{}\"]", script_hash(script_pubkey));
+        let utxos = self.call("blockchain.scripthash.listunspent", &params)?;
+        let utxos = utxos.as_array().ok_or(Error::Json("expected an array"))?;
+        utxos
+            .iter()
+            .map(|utxo| {
+                let txid = Txid::from_hex(json_str(utxo.get("tx_hash"))?)
+                    .map_err(|_| Error::Json("invalid txid"))?;
+                // Unconfirmed outputs are at height 0, or -1 if they spend
+                // other unconfirmed outputs
+                let height = utxo
+                    .get("height")
+                    .and_then(Value::as_i64)
+                    .ok_or(Error::Json("expected a height"))?;
+                Ok(Utxo {
+                    outpoint: OutPoint {
+                        txid,
+                        vout: json_u32(utxo.get("tx_pos"))?,
+                    },
+                    txout: TxOut {
+                        value: utxo
+                            .get("value")
+                            .and_then(Value::as_u64)
+                            .ok_or(Error::Json("expected a value"))?,
+                        script_pubkey: script_pubkey.clone(),
+                    },
+                    height: u32::try_from(height).ok().filter(|&h| h > 0),
+                })
+            })
+            .collect()
+    }
+}
+
+/// The script hash Electrum indexes outputs by: the SHA256 of the script,
+/// in reverse byte order
+pub fn script_hash(script_pubkey: &Script) -> String {
+    let mut hash = sha256::Hash::hash(script_pubkey.as_bytes()).into_inner();
+    hash.reverse();
+    hash.to_hex()
+}
+
+fn json_str(value: Option<&Value>) -> Result<&str, Error> {
+    value
+        .and_then(Value::as_str)
+        .ok_or(Error::Json("expected a string"))
+}
+
+fn json_u32(value: Option<&Value>) -> Result<u32, Error> {
+    value
+        .and_then(Value::as_u64)
+        .and_then(|n| u32::try_from(n).ok())
+        .ok_or(Error::Json("expected a 32-bit number"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    use consensus::encode::serialize_hex;
+    use testutil::{InputKind, OutputKind, TxFixture};
+
+    fn fixture(seed: u64) -> Transaction {
+        TxFixture::new(seed)
+            .inputs(1, InputKind::P2wpkh)
+            .outputs(2, OutputKind::P2wpkh)
+            .build()
+    }
+
+    /// A server giving canned responses
+    struct Server {
+        responses: Cursor<Vec<u8>>,
+        requests: Vec<u8>,
+    }
+
+    impl Read for Server {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.responses.read(buf)
+        }
+    }
+
+    impl Write for Server {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.requests.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// A response to request `id`, with `body` its result or error
+    fn response(id: u64, body: &str) -> String {
+        format!("{{\"jsonrpc\": \"2.0\", \"id\": {}, {}}}\n", id, body)
+    }
+
+    /// A client whose server answers the version negotiation and then
+    /// sends `lines`
+    fn client(lines: &[String]) -> ElectrumClient<Server> {
+        let mut responses = response(0, "\"result\": [\"electrs\", \"1.4\"]");
+        for line in lines {
+            responses += line;
+        }
+        ElectrumClient::new(Server {
+            responses: Cursor::new(responses.into_bytes()),
+            requests: vec![],
+        })
+    }
+
+    fn requests(client: &ElectrumClient<Server>) -> Vec<String> {
+        String::from_utf8(client.stream.get_ref().requests.clone())
+            .unwrap()
+            .lines()
+            .map(|line| line.to_owned())
+            .collect()
+    }
+
+    #[test]
+    fn backend() {
+        let header_hex = "010000000000000000000000000000000000000000000000000000000000000000000000\
+                          3ba3edfd7a7b12b27ac72c3e67768f617fc81bc3888a51323a9fb8aa4b1e5e4a29ab5f49\
+                          ffff001d1dac2b7c";
+        let tx = fixture(1);
+        let script = tx.output[0].script_pubkey.clone();
+        let mut client = client(&[
+            response(
+                1,
+                &format!("\"result\": {{\"hex\": \"{}\", \"height\": 0}}", header_hex),
+            ),
+            // A notification, which is skipped
+            "{\"jsonrpc\": \"2.0\", \"method\": \"blockchain.headers.subscribe\", \"params\": []}\n"
+                .to_owned(),
+            response(2, "\"result\": \"00\""),
+            response(3, &format!("\"result\": \"{}\"", header_hex)),
+            response(4, &format!("\"result\": \"{}\"", serialize_hex(&tx))),
+            response(
+                5,
+                &format!(
+                    "\"result\": [{{\"tx_hash\": \"{}\", \"tx_pos\": 0, \"height\": 100, \"value\": 5000}}, \
+                     {{\"tx_hash\": \"{}\", \"tx_pos\": 1, \"height\": 0, \"value\": 7}}]",
+                    tx.txid(),
+                    tx.txid()
+                ),
+            ),
+        ]);
+
+        let (height, hash) = client.tip().unwrap();
+        assert_eq!(height, 0);
+        assert_eq!(
+            hash.to_string(),
+            "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f"
+        );
+        assert!(client.header(0).is_err());
+        assert_eq!(client.header(0).unwrap().block_hash(), hash);
+        assert_eq!(client.transaction(&tx.txid()).unwrap(), tx);
+        let utxos = client.utxos(&script).unwrap();
+        assert_eq!(utxos.len(), 2);
+        assert_eq!(utxos[0].outpoint, OutPoint::new(tx.txid(), 0));
+        assert_eq!(utxos[0].txout.value, 5000);
+        assert_eq!(utxos[0].txout.script_pubkey, script);
+        assert_eq!(utxos[0].height, Some(100));
+        assert_eq!(utxos[1].height, None);
+
+        let requests = requests(&client);
+        assert_eq!(requests.len(), 6);
+        assert!(requests[0].contains("\"method\":\"server.version\""));
+        assert_eq!(
+            requests[5],
+            format!(
+                "{{\"jsonrpc\":\"2.0\",\"id\":5,\"method\":\"blockchain.scripthash.listunspent\",\"params\":[\"{}\"]}}",
+                script_hash(&script)
+            )
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let tx = fixture(1);
+        let other = fixture(2);
+        let mut client = client(&[
+            response(1, "\"error\": {\"code\": 2, \"message\": \"daemon error\"}"),
+            response(2, &format!("\"result\": \"{}\"", serialize_hex(&other))),
+            response(3, "\"result\": 5"),
+        ]);
+        match client.transaction(&tx.txid()) {
+            Err(Error::Server(ref s)) => assert_eq!(s, "daemon error"),
+            r => panic!("unexpected result: {:?}", r),
+        }
+        match client.transaction(&tx.txid()) {
+            Err(Error::WrongTransaction(txid)) => assert_eq!(txid, tx.txid()),
+            r => panic!("unexpected result: {:?}", r),
+        }
+        assert!(client.header(1).is_err());
+        // The server hung up
+        assert!(client.header(2).is_err());
+    }
+
+    #[test]
+    fn script_hashes() {
+        // From the Electrum protocol documentation
+        let script = Script::from(
+            Vec::<u8>::from_hex("76a91462e907b15cbf27d5425399ebf6f0fb50ebb88f1888ac").unwrap(),
+        );
+        assert_eq!(
+            script_hash(&script),
+            "8b01df4e368ea28f8dc0423bcf7a4923e3a12d307c875e47a0cfbf90b5c39161"
+        );
+    }
+}
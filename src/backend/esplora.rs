@@ -0,0 +1,215 @@
+//! Esplora Client
+//!
+//! Esplora serves the chain over HTTP, with most resources as plain text
+//! or raw bytes and the rest as JSON. This library has no HTTP client, so
+//! requests go through an `HttpClient`, which the application implements
+//! with the HTTP and TLS libraries of its choice.
+
+use std::convert::TryFrom;
+use std::io;
+use std::str;
+
+use backend::json::{self, Value};
+use backend::{check_txid, Backend, Error, Utxo};
+use blockdata::block::BlockHeader;
+use blockdata::script::Script;
+use blockdata::transaction::{OutPoint, Transaction, TxOut};
+use consensus::encode::{deserialize, deserialize_hex};
+use hash_types::{BlockHash, Txid};
+use hashes::hex::ToHex;
+use hashes::{sha256, Hash};
+
+/// Makes HTTP requests
+pub trait HttpClient {
+    /// The body of the response to a GET of `url`, failing unless the
+    /// status is 200
+    fn get(&mut self, url: &str) -> io::Result<Vec<u8>>;
+}
+
+/// A client of an Esplora server
+#[derive(Debug)]
+pub struct EsploraClient<C> {
+    base_url: String,
+    http: C,
+}
+
+impl<C: HttpClient> EsploraClient<C> {
+    /// Creates a client of the API at `base_url`, such as
+    /// `https://blockstream.info/api`
+    pub fn new(base_url: &str, http: C) -> EsploraClient<C> {
+        EsploraClient {
+            base_url: base_url.trim_end_matches('/').to_owned(),
+            http,
+        }
+    }
+
+    fn get(&mut self, path: &str) -> Result<Vec<u8>, Error> {
+        Ok(self.http.get(&format!("{}{}", self.base_url, path))?)
+    }
+
+    fn get_text(&mut self, path: &str) -> Result<String, Error> {
+        let body = self.get(path)?;
+        match str::from_utf8(&body) {
+            Ok(s) => Ok(s.trim().to_owned()),
+            Err(_) => Err(Error::Json("expected text")),
+        }
+    }
+
+    /// The hash of the best chain block at `height`
+    pub fn block_hash(&mut self, height: u32) -> Result<BlockHash, Error> {
+        let hash = self.get_text(&format!("/block-height/{}", height))?;
+        BlockHash::from_rpc_hex(&hash).map_err(|_| Error::Json("invalid block hash"))
+    }
+}
+
+impl<C: HttpClient> Backend for EsploraClient<C> {
+    fn tip(&mut self) -> Result<(u32, BlockHash), Error> {
+        // Asking for the hash at the tip height, rather than for the tip
+        // hash, keeps the two consistent if a block arrives in between
+        let height = self
+            .get_text("/blocks/tip/height")?
+            .parse()
+            .map_err(|_| Error::Json("invalid height"))?;
+        Ok((height, self.block_hash(height)?))
+    }
+
+    fn header(&mut self, height: u32) -> Result<BlockHeader, Error> {
+        let hash = self.block_hash(height)?;
+        let header = self.get_text(&format!("/block/{}/header", hash))?;
+        Ok(deserialize_hex(&header)?)
+    }
+
+    fn transaction(&mut self, txid: &Txid) -> Result<Transaction, Error> {
+        let tx = self.get(&format!("/tx/{}/raw", txid))?;
+        check_txid(deserialize(&tx)?, txid)
+    }
+
+    fn utxos(&mut self, script_pubkey: &Script) -> Result<Vec<Utxo>, Error> {
+        let hash = sha256::Hash::hash(script_pubkey.as_bytes());
+        let utxos = self.get_text(&format!("/scripthash/{}/utxo", hash.to_hex()))?;
+        let utxos = json::parse(&utxos)?;
+        let utxos = utxos.as_array().ok_or(Error::Json("expected an array"))?;
+        utxos
+            .iter()
+            .map(|utxo| {
+                let txid = utxo
+                    .get("txid")
+                    .and_then(Value::as_str)
+                    .and_then(|s| Txid::from_rpc_hex(s).ok())
+                    .ok_or(Error::Json("expected a txid"))?;
+                let vout = utxo
+                    .get("vout")
+                    .and_then(Value::as_u64)
+                    .and_then(|n| u32::try_from(n).ok())
+                    .ok_or(Error::Json("expected an output index"))?;
+                let value = utxo
+                    .get("value")
+                    .and_then(Value::as_u64)
+                    .ok_or(Error::Json("expected a value"))?;
+                let status = utxo.get("status");
+                let height = match status
+                    .and_then(|s| s.get("confirmed"))
+                    .and_then(Value::as_bool)
+                {
+                    Some(true) => Some(
+                        status
+                            .and_then(|s| s.get("block_height"))
+                            .and_then(Value::as_u64)
+                            .and_then(|n| u32::try_from(n).ok())
+                            .ok_or(Error::Json("expected a height"))?,
+                    ),
+                    Some(false) => None,
+                    None => return Err(Error::Json("expected a status")),
+                };
+                Ok(Utxo {
+                    outpoint: OutPoint::new(txid, vout),
+                    txout: TxOut {
+                        value,
+                        script_pubkey: script_pubkey.clone(),
+                    },
+                    height,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    use consensus::encode::serialize;
+    use testutil::{InputKind, OutputKind, TxFixture};
+
+    fn fixture(seed: u64) -> Transaction {
+        TxFixture::new(seed)
+            .inputs(1, InputKind::P2wpkh)
+            .outputs(2, OutputKind::P2wpkh)
+            .build()
+    }
+
+    impl HttpClient for HashMap<String, Vec<u8>> {
+        fn get(&mut self, url: &str) -> io::Result<Vec<u8>> {
+            HashMap::get(self, url)
+                .cloned()
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "404"))
+        }
+    }
+
+    #[test]
+    fn backend() {
+        let genesis = "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f";
+        let tx = fixture(1);
+        let other = fixture(2);
+        let script = tx.output[0].script_pubkey.clone();
+        let script_hash = sha256::Hash::hash(script.as_bytes());
+
+        let mut server = HashMap::new();
+        let mut add = |path: String, body: Vec<u8>| {
+            server.insert(format!("https://esplora.example/api{}", path), body);
+        };
+        add("/blocks/tip/height".to_owned(), b"0".to_vec());
+        add("/block-height/0".to_owned(), genesis.as_bytes().to_vec());
+        add(
+            format!("/block/{}/header", genesis),
+            b"0100000000000000000000000000000000000000000000000000000000000000000000003ba3edfd7a7b12b27ac72c3e67768f617fc81bc3888a51323a9fb8aa4b1e5e4a29ab5f49ffff001d1dac2b7c\n".to_vec(),
+        );
+        add(format!("/tx/{}/raw", tx.txid()), serialize(&tx));
+        add(format!("/tx/{}/raw", other.txid()), serialize(&tx));
+        add(
+            format!("/scripthash/{}/utxo", script_hash),
+            format!(
+                "[{{\"txid\": \"{}\", \"vout\": 0, \"status\": {{\"confirmed\": true, \"block_height\": 100, \
+                 \"block_hash\": \"{}\", \"block_time\": 1231006505}}, \"value\": 5000}}, \
+                 {{\"txid\": \"{}\", \"vout\": 1, \"status\": {{\"confirmed\": false}}, \"value\": 7}}]",
+                tx.txid(),
+                genesis,
+                tx.txid()
+            )
+            .into_bytes(),
+        );
+
+        let mut client = EsploraClient::new("https://esplora.example/api/", server);
+        let (height, hash) = client.tip().unwrap();
+        assert_eq!((height, hash.to_string()), (0, genesis.to_owned()));
+        assert_eq!(client.header(0).unwrap().block_hash(), hash);
+        assert!(client.header(1).is_err());
+        assert_eq!(client.transaction(&tx.txid()).unwrap(), tx);
+        match client.transaction(&other.txid()) {
+            Err(Error::WrongTransaction(txid)) => assert_eq!(txid, other.txid()),
+            r => panic!("unexpected result: {:?}", r),
+        }
+
+        let utxos = client.utxos(&script).unwrap();
+        assert_eq!(utxos.len(), 2);
+        assert_eq!(utxos[0].outpoint, OutPoint::new(tx.txid(), 0));
+        assert_eq!(utxos[0].txout.value, 5000);
+        assert_eq!(utxos[0].txout.script_pubkey, script);
+        assert_eq!(utxos[0].height, Some(100));
+        assert_eq!(utxos[1].outpoint.vout, 1);
+        assert_eq!(utxos[1].height, None);
+        assert!(client.utxos(&Script::new()).is_err());
+    }
+}
@@ -0,0 +1,302 @@
+//! JSON
+//!
+//! Just enough JSON for the responses of the backends, so that they do
+//! not need `serde`. Numbers are kept as their text, to be read as the
+//! integers the servers send rather than through a float.
+
+use backend::Error;
+
+/// How deeply arrays and objects may nest
+const MAX_DEPTH: usize = 32;
+
+/// A JSON value
+#[derive(Clone, PartialEq, Debug)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// The member `key` of an object
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match *self {
+            Value::Object(ref members) => members.iter().find(|m| m.0 == key).map(|m| &m.1),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Value::String(ref s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            Value::Number(ref n) => n.parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Value::Number(ref n) => n.parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Value::Bool(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match *self {
+            Value::Array(ref values) => Some(values),
+            _ => None,
+        }
+    }
+}
+
+/// Parses a JSON document
+pub fn parse(s: &str) -> Result<Value, Error> {
+    let mut parser = Parser {
+        s: s.as_bytes(),
+        pos: 0,
+    };
+    let value = parser.value(0)?;
+    parser.whitespace();
+    if parser.pos != parser.s.len() {
+        return Err(Error::Json("trailing characters"));
+    }
+    Ok(value)
+}
+
+struct Parser<'a> {
+    s: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn whitespace(&mut self) {
+        while let Some(&b' ') | Some(&b'\t') | Some(&b'\n') | Some(&b'\r') = self.s.get(self.pos) {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Result<u8, Error> {
+        self.whitespace();
+        self.s
+            .get(self.pos)
+            .cloned()
+            .ok_or(Error::Json("unexpected end"))
+    }
+
+    fn expect(&mut self, c: u8) -> Result<(), Error> {
+        if self.peek()? != c {
+            return Err(Error::Json("unexpected character"));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn literal(&mut self, word: &'static [u8], value: Value) -> Result<Value, Error> {
+        if !self.s[self.pos..].starts_with(word) {
+            return Err(Error::Json("unexpected character"));
+        }
+        self.pos += word.len();
+        Ok(value)
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value, Error> {
+        if depth > MAX_DEPTH {
+            return Err(Error::Json("nested too deeply"));
+        }
+        match self.peek()? {
+            b'n' => self.literal(b"null", Value::Null),
+            b't' => self.literal(b"true", Value::Bool(true)),
+            b'f' => self.literal(b"false", Value::Bool(false)),
+            b'"' => Ok(Value::String(self.string()?)),
+            b'[' => {
+                self.pos += 1;
+                let mut values = vec![];
+                if self.peek()? == b']' {
+                    self.pos += 1;
+                    return Ok(Value::Array(values));
+                }
+                loop {
+                    values.push(self.value(depth + 1)?);
+                    match self.peek()? {
+                        b',' => self.pos += 1,
+                        b']' => {
+                            self.pos += 1;
+                            return Ok(Value::Array(values));
+                        }
+                        _ => return Err(Error::Json("unexpected character")),
+                    }
+                }
+            }
+            b'{' => {
+                self.pos += 1;
+                let mut members = vec![];
+                if self.peek()? == b'}' {
+                    self.pos += 1;
+                    return Ok(Value::Object(members));
+                }
+                loop {
+                    if self.peek()? != b'"' {
+                        return Err(Error::Json("unexpected character"));
+                    }
+                    let key = self.string()?;
+                    self.expect(b':')?;
+                    members.push((key, self.value(depth + 1)?));
+                    match self.peek()? {
+                        b',' => self.pos += 1,
+                        b'}' => {
+                            self.pos += 1;
+                            return Ok(Value::Object(members));
+                        }
+                        _ => return Err(Error::Json("unexpected character")),
+                    }
+                }
+            }
+            b'-' | b'0'...b'9' => {
+                let start = self.pos;
+                self.pos += 1;
+                while let Some(&b'0'...b'9') | Some(&b'.') | Some(&b'e') | Some(&b'E')
+                | Some(&b'+') | Some(&b'-') = self.s.get(self.pos)
+                {
+                    self.pos += 1;
+                }
+                let n = String::from_utf8_lossy(&self.s[start..self.pos]).into_owned();
+                Ok(Value::Number(n))
+            }
+            _ => Err(Error::Json("unexpected character")),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, Error> {
+        self.expect(b'"')?;
+        let mut ret = vec![];
+        loop {
+            let c = *self.s.get(self.pos).ok_or(Error::Json("unexpected end"))?;
+            self.pos += 1;
+            match c {
+                b'"' => break,
+                b'\\' => {
+                    let c = *self.s.get(self.pos).ok_or(Error::Json("unexpected end"))?;
+                    self.pos += 1;
+                    let unescaped = match c {
+                        b'"' | b'\\' | b'/' => c as char,
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => self.unicode_escape()?,
+                        _ => return Err(Error::Json("invalid escape")),
+                    };
+                    let mut buf = [0; 4];
+                    ret.extend_from_slice(unescaped.encode_utf8(&mut buf).as_bytes());
+                }
+                0...0x1f => return Err(Error::Json("control character in string")),
+                _ => ret.push(c),
+            }
+        }
+        String::from_utf8(ret).map_err(|_| Error::Json("invalid UTF-8"))
+    }
+
+    /// The character of a `\u` escape, whose `\u` has been read
+    fn unicode_escape(&mut self) -> Result<char, Error> {
+        let high = self.hex4()?;
+        let code = if (0xd800..0xdc00).contains(&high) {
+            if !self.s[self.pos..].starts_with(b"\\u") {
+                return Err(Error::Json("unpaired surrogate"));
+            }
+            self.pos += 2;
+            let low = self.hex4()?;
+            if !(0xdc00..0xe000).contains(&low) {
+                return Err(Error::Json("unpaired surrogate"));
+            }
+            0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+        } else {
+            high
+        };
+        ::std::char::from_u32(code).ok_or(Error::Json("unpaired surrogate"))
+    }
+
+    fn hex4(&mut self) -> Result<u32, Error> {
+        let digits = self
+            .s
+            .get(self.pos..self.pos + 4)
+            .ok_or(Error::Json("unexpected end"))?;
+        self.pos += 4;
+        let mut ret = 0;
+        for &d in digits {
+            let d = (d as char)
+                .to_digit(16)
+                .ok_or(Error::Json("invalid escape"))?;
+            ret = ret << 4 | d;
+        }
+        Ok(ret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values() {
+        let json = parse(
+            r#" {"a": [1, -2, 3.5e2, true, false, null], "b": {"c": "d\"\u00e9\ud83d\ude00\n"}, "e": []} "#,
+        )
+        .unwrap();
+        let a = json.get("a").unwrap().as_array().unwrap();
+        assert_eq!(a[0].as_u64(), Some(1));
+        assert_eq!(a[1].as_i64(), Some(-2));
+        assert_eq!(a[1].as_u64(), None);
+        assert_eq!(a[2], Value::Number("3.5e2".to_owned()));
+        assert_eq!(a[3].as_bool(), Some(true));
+        assert_eq!(a[5], Value::Null);
+        let c = json.get("b").unwrap().get("c").unwrap();
+        assert_eq!(c.as_str(), Some("d\"é😀\n"));
+        assert_eq!(json.get("e").unwrap().as_array(), Some(&[][..]));
+        assert_eq!(json.get("f"), None);
+        assert_eq!(
+            parse("2100000000000000").unwrap().as_u64(),
+            Some(2_100_000_000_000_000)
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        for s in &[
+            "",
+            "[",
+            "[1,]",
+            "{\"a\" 1}",
+            "{1: 2}",
+            "nul",
+            "\"a",
+            "\"\\x\"",
+            "\"\\ud800\"",
+            "\"\n\"",
+            "[] []",
+            "\"\\u12\"",
+        ] {
+            assert!(parse(s).is_err(), "{:?} parsed", s);
+        }
+        let deep = "[".repeat(100) + &"]".repeat(100);
+        assert!(parse(&deep).is_err());
+        let shallow = "[".repeat(10) + &"]".repeat(10);
+        assert!(parse(&shallow).is_ok());
+    }
+}
@@ -0,0 +1,141 @@
+//! Wallet Backends
+//!
+//! A wallet does not have to follow the chain over the peer-to-peer
+//! network: it can ask a server indexing the chain, speaking either the
+//! Electrum protocol or Esplora's HTTP API, for the headers, transactions
+//! and unspent outputs it cares about. Both clients here implement
+//! `Backend`, so that a wallet can be written against either, and give
+//! back this library's types.
+//!
+//! A server is trusted with what it serves. Transactions are checked
+//! against the txid asked for, but headers and unspent outputs are not
+//! checked against the chain.
+
+use std::{error, fmt, io};
+
+use blockdata::block::BlockHeader;
+use blockdata::script::Script;
+use blockdata::transaction::{OutPoint, Transaction, TxOut};
+use consensus::encode;
+use error::{ErrorCode, ErrorKind};
+use hash_types::{BlockHash, Txid};
+
+pub mod electrum;
+pub mod esplora;
+mod json;
+
+pub use self::electrum::ElectrumClient;
+pub use self::esplora::{EsploraClient, HttpClient};
+
+/// A backend error
+#[derive(Debug)]
+pub enum Error {
+    /// An I/O error talking to the server
+    Io(io::Error),
+    /// A header or transaction did not decode
+    Encode(encode::Error),
+    /// A response was not the JSON expected
+    Json(&'static str),
+    /// The server returned an error
+    Server(String),
+    /// The server gave a transaction other than the one asked for
+    WrongTransaction(Txid),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref e) => write!(f, "I/O error: {}", e),
+            Error::Encode(ref e) => write!(f, "decoding error: {}", e),
+            Error::Json(s) => write!(f, "invalid response: {}", s),
+            Error::Server(ref s) => write!(f, "server error: {}", s),
+            Error::WrongTransaction(ref txid) => {
+                write!(f, "server gave the wrong transaction for {}", txid)
+            }
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::Io(ref e) => Some(e),
+            Error::Encode(ref e) => Some(e),
+            Error::Json(..) | Error::Server(..) | Error::WrongTransaction(..) => None,
+        }
+    }
+
+    fn description(&self) -> &str {
+        match *self {
+            Error::Io(..) => "I/O error",
+            Error::Encode(..) => "decoding error",
+            Error::Json(..) => "invalid response",
+            Error::Server(..) => "server error",
+            Error::WrongTransaction(..) => "server gave the wrong transaction",
+        }
+    }
+}
+
+impl ErrorCode for Error {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Backend
+    }
+
+    fn number(&self) -> u32 {
+        match *self {
+            Error::Io(..) => 1,
+            Error::Encode(..) => 2,
+            Error::Json(..) => 3,
+            Error::Server(..) => 4,
+            Error::WrongTransaction(..) => 5,
+        }
+    }
+}
+
+#[doc(hidden)]
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+#[doc(hidden)]
+impl From<encode::Error> for Error {
+    fn from(e: encode::Error) -> Error {
+        Error::Encode(e)
+    }
+}
+
+/// An unspent output of a script
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Utxo {
+    /// The output
+    pub outpoint: OutPoint,
+    /// Its value and script
+    pub txout: TxOut,
+    /// The height of the block confirming it, if it is confirmed
+    pub height: Option<u32>,
+}
+
+/// A server which indexes the chain
+pub trait Backend {
+    /// The height and hash of the tip of the best chain
+    fn tip(&mut self) -> Result<(u32, BlockHash), Error>;
+
+    /// The header of the best chain block at `height`
+    fn header(&mut self, height: u32) -> Result<BlockHeader, Error>;
+
+    /// A transaction, confirmed or in the mempool
+    fn transaction(&mut self, txid: &Txid) -> Result<Transaction, Error>;
+
+    /// The unspent outputs of `script_pubkey`, including unconfirmed ones
+    fn utxos(&mut self, script_pubkey: &Script) -> Result<Vec<Utxo>, Error>;
+}
+
+/// Checks that a transaction from a server is the one asked for
+fn check_txid(tx: Transaction, txid: &Txid) -> Result<Transaction, Error> {
+    if tx.txid() != *txid {
+        return Err(Error::WrongTransaction(*txid));
+    }
+    Ok(tx)
+}
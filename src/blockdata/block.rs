@@ -0,0 +1,152 @@
+//! Bitcoin Block
+//!
+//! A block is a bundle of transactions with a proof-of-work attached,
+//! which commits to an earlier block to form the blockchain. This
+//! module describes the block header, which is all that is needed to
+//! follow the chain with the most work.
+
+use hashes::Hash;
+
+use consensus::encode::Encodable;
+use hash_types::{BlockHash, TxMerkleNode};
+use util::uint::Uint256;
+
+/// A block header, which contains all the block's information except
+/// the actual transactions
+#[derive(Copy, PartialEq, Eq, Clone, Debug)]
+pub struct BlockHeader {
+    /// The protocol version. Should always be 1.
+    pub version: i32,
+    /// Reference to the previous block in the chain
+    pub prev_blockhash: BlockHash,
+    /// The root hash of the merkle tree of transactions in the block
+    pub merkle_root: TxMerkleNode,
+    /// The timestamp of the block, as claimed by the miner
+    pub time: u32,
+    /// The target value below which the blockhash must lie, encoded as a
+    /// a float (with well-defined rounding, of course)
+    pub bits: u32,
+    /// The nonce, selected to obtain a low enough blockhash
+    pub nonce: u32,
+}
+
+impl_consensus_encoding!(
+    BlockHeader,
+    version,
+    prev_blockhash,
+    merkle_root,
+    time,
+    bits,
+    nonce
+);
+impl_consensus_from_hex!(BlockHeader);
+
+impl BlockHeader {
+    /// Return the block hash.
+    pub fn block_hash(&self) -> BlockHash {
+        let mut engine = BlockHash::engine();
+        self.consensus_encode(&mut engine)
+            .expect("engines don't error");
+        BlockHash::from_engine(engine)
+    }
+
+    /// Computes the target [0, T] that a blockhash must land in to be valid
+    pub fn target(&self) -> Uint256 {
+        // This is a floating-point "compact" encoding originally used by
+        // OpenSSL, which satoshi put into consensus code, so we're stuck
+        // with it. The exponent needs to have 3 subtracted from it, hence
+        // this goofy decoding code:
+        let (mant, expt) = {
+            let unshifted_expt = self.bits >> 24;
+            if unshifted_expt <= 3 {
+                (
+                    (self.bits & 0xFFFFFF) >> (8 * (3 - unshifted_expt as usize)),
+                    0,
+                )
+            } else {
+                (self.bits & 0xFFFFFF, 8 * ((self.bits >> 24) - 3))
+            }
+        };
+
+        // The mantissa is signed but may not be negative
+        if mant > 0x7FFFFF {
+            Default::default()
+        } else {
+            Uint256::from_u64(mant as u64) << (expt as usize)
+        }
+    }
+
+    /// Returns the total work of the block
+    pub fn work(&self) -> Uint256 {
+        // 2**256 / (target + 1) == ~target / (target+1) + 1    (eqn shamelessly stolen from bitcoind)
+        let target = self.target();
+        let (divisor, overflow) = target.overflowing_add(Uint256::from_u64(1));
+        if overflow || target.is_zero() {
+            return Uint256::zero();
+        }
+        !target / divisor + Uint256::from_u64(1)
+    }
+
+    /// Checks that the block hash is at most the target encoded in `bits`.
+    ///
+    /// Whether `bits` itself is the difficulty the network requires at this
+    /// point in the chain is not checked.
+    pub fn has_valid_pow(&self) -> bool {
+        let target = self.target();
+        !target.is_zero() && Uint256::from_le_bytes(self.block_hash().into_inner()) <= target
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use consensus::encode::{deserialize, serialize};
+    use hashes::hex::{FromHex, ToHex};
+
+    #[test]
+    fn block_header_test() {
+        // The genesis block header
+        let raw = Vec::from_hex("0100000000000000000000000000000000000000000000000000000000000000000000003ba3edfd7a7b12b27ac72c3e67768f617fc81bc3888a51323a9fb8aa4b1e5e4a29ab5f49ffff001d1dac2b7c").unwrap();
+        let header: BlockHeader = deserialize(&raw).unwrap();
+
+        assert_eq!(header.version, 1);
+        assert_eq!(header.prev_blockhash, Default::default());
+        assert_eq!(header.time, 1231006505);
+        assert_eq!(header.bits, 0x1d00ffff);
+        assert_eq!(header.nonce, 2083236893);
+        assert_eq!(
+            header.block_hash().to_hex(),
+            "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f"
+        );
+        assert_eq!(serialize(&header), raw);
+        assert_eq!(BlockHeader::from_hex(&raw.to_hex()).unwrap(), header);
+
+        assert_eq!(header.target(), Uint256::from_u64(0xffff) << 208);
+        assert_eq!(header.work(), Uint256::from_u64(0x100010001));
+        assert!(header.has_valid_pow());
+
+        let mut bad = header;
+        bad.nonce += 1;
+        assert!(!bad.has_valid_pow());
+    }
+
+    #[test]
+    fn compact_target_test() {
+        let mut header: BlockHeader = deserialize(&[0; 80]).unwrap();
+        header.bits = 0x207fffff;
+        assert_eq!(header.target(), Uint256::from_u64(0x7fffff) << 232);
+        assert_eq!(header.work(), Uint256::from_u64(2));
+
+        header.bits = 0x03123456;
+        assert_eq!(header.target(), Uint256::from_u64(0x123456));
+        header.bits = 0x02123456;
+        assert_eq!(header.target(), Uint256::from_u64(0x1234));
+
+        // Negative and zero targets are invalid and carry no work
+        header.bits = 0x04923456;
+        assert_eq!(header.target(), Uint256::zero());
+        assert_eq!(header.work(), Uint256::zero());
+        assert!(!header.has_valid_pow());
+    }
+}
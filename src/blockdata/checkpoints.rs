@@ -0,0 +1,269 @@
+//! Checkpoints
+//!
+//! Block hashes at known heights, which a `HeaderChain` can be rooted at
+//! instead of the genesis block, skipping the download of every header
+//! below it, and which headers at those heights have to match.
+//!
+//! With the `embedded-checkpoints` feature `Checkpoints::mainnet` gives a
+//! list shipped with the library: the checkpoints Bitcoin Core used to
+//! carry, then a block roughly every 100,000 along with the halvings and
+//! the segwit and taproot activations. They are only as trustworthy as the
+//! copy of the library they came with, so users with a node of their own
+//! can build a `Checkpoints` from hashes it reports instead.
+//!
+//! Only hashes are shipped. The chainwork of a checkpoint is optional: a
+//! chain rooted at a checkpoint without it counts work from the checkpoint,
+//! which is enough to pick the best of its forks but not to compare it with
+//! a chain rooted elsewhere.
+
+use std::collections::BTreeMap;
+
+use hash_types::BlockHash;
+use util::uint::Uint256;
+
+/// A block hash trusted to be in the best chain at its height
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Checkpoint {
+    /// Height of the block
+    pub height: u32,
+    /// Hash of the block
+    pub hash: BlockHash,
+    /// Total work of the chain ending at the block, including the genesis
+    /// block, if known
+    pub chainwork: Option<Uint256>,
+}
+
+/// A set of checkpoints, at most one per height
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Checkpoints {
+    by_height: BTreeMap<u32, Checkpoint>,
+}
+
+impl Checkpoints {
+    /// Creates an empty set
+    pub fn new() -> Checkpoints {
+        Checkpoints::default()
+    }
+
+    /// Adds a checkpoint, returning the one it replaced at the same height
+    pub fn insert(&mut self, checkpoint: Checkpoint) -> Option<Checkpoint> {
+        self.by_height.insert(checkpoint.height, checkpoint)
+    }
+
+    /// The checkpoint at `height`
+    pub fn get(&self, height: u32) -> Option<&Checkpoint> {
+        self.by_height.get(&height)
+    }
+
+    /// The highest checkpoint
+    pub fn last(&self) -> Option<&Checkpoint> {
+        self.by_height.values().next_back()
+    }
+
+    /// The highest checkpoint at or below `height`
+    pub fn last_at_or_below(&self, height: u32) -> Option<&Checkpoint> {
+        self.by_height.range(..=height).next_back().map(|(_, c)| c)
+    }
+
+    /// The checkpoints, lowest first
+    pub fn iter(&self) -> impl Iterator<Item = &Checkpoint> {
+        self.by_height.values()
+    }
+
+    /// The number of checkpoints
+    pub fn len(&self) -> usize {
+        self.by_height.len()
+    }
+
+    /// Whether there are no checkpoints
+    pub fn is_empty(&self) -> bool {
+        self.by_height.is_empty()
+    }
+
+    /// Bitcoin mainnet checkpoints shipped with the library
+    #[cfg(feature = "embedded-checkpoints")]
+    pub fn mainnet() -> Checkpoints {
+        use hashes::hex::FromHex;
+
+        MAINNET
+            .iter()
+            .map(|&(height, hash)| Checkpoint {
+                height,
+                hash: BlockHash::from_hex(hash).expect("valid embedded hash"),
+                chainwork: None,
+            })
+            .collect()
+    }
+}
+
+impl ::std::iter::FromIterator<Checkpoint> for Checkpoints {
+    /// Collects checkpoints, later ones replacing earlier ones at the same
+    /// height
+    fn from_iter<I: IntoIterator<Item = Checkpoint>>(iter: I) -> Checkpoints {
+        let mut ret = Checkpoints::new();
+        for checkpoint in iter {
+            ret.insert(checkpoint);
+        }
+        ret
+    }
+}
+
+/// Heights and hashes of the embedded mainnet checkpoints
+#[cfg(feature = "embedded-checkpoints")]
+const MAINNET: &[(u32, &str)] = &[
+    (
+        11111,
+        "0000000069e244f73d78e8fd29ba2fd2ed618bd6fa2ee92559f542fdb26e7c1d",
+    ),
+    (
+        33333,
+        "000000002dd5588a74784eaa7ab0507a18ad16a236e7b1ce69f00d7ddfb5d0a6",
+    ),
+    (
+        74000,
+        "0000000000573993a3c9e41ce34471c079dcf5f52a0e824a81e7f953b8661a20",
+    ),
+    (
+        100000,
+        "000000000003ba27aa200b1cecaad478d2b00432346c3f1f3986da1afd33e506",
+    ),
+    (
+        105000,
+        "00000000000291ce28027faea320c8d2b054b2e0fe44a773f3eefb151d6bdc97",
+    ),
+    (
+        134444,
+        "00000000000005b12ffd4cd315cd34ffd4a594f430ac814c91184a0d42d2b0fe",
+    ),
+    (
+        168000,
+        "000000000000099e61ea72015e79632f216fe6cb33d7899acb35b75c8303b763",
+    ),
+    (
+        193000,
+        "000000000000059f452a5f7340de6682a977387c17010ff6e6c3bd83ca8b1317",
+    ),
+    (
+        210000,
+        "000000000000048b95347e83192f69cf0366076336c639f9b7228e9ba171342e",
+    ),
+    (
+        216116,
+        "00000000000001b4f4b433e81ee46494af945cf96014816a4e2370f11b23df4e",
+    ),
+    (
+        225430,
+        "00000000000001c108384350f74090433e7fcf79a606b8e797f065b130575932",
+    ),
+    (
+        250000,
+        "000000000000003887df1f29024b06fc2200b55f8af8f35453d7be294df2d214",
+    ),
+    (
+        279000,
+        "0000000000000001ae8c72a0b0c301f67e3afca10e819efa9041e458e9bd7e40",
+    ),
+    (
+        295000,
+        "00000000000000004d9b4ef50f0f9d686fd69db2e03af35a100370c64632a983",
+    ),
+    (
+        300000,
+        "000000000000000082ccf8f1557c5d40b21edabb18d2d691cfbf87118bac7254",
+    ),
+    (
+        400000,
+        "000000000000000004ec466ce4732fe6f1ed1cddc2ed4b328fff5224276e3f6f",
+    ),
+    (
+        420000,
+        "000000000000000002cce816c0ab2c5c269cb081896b7dcb34b8422d6b74ffa1",
+    ),
+    (
+        481824,
+        "0000000000000000001c8018d9cb3b742ef25114f27563e3fc4a1902167f9893",
+    ),
+    (
+        500000,
+        "00000000000000000024fb37364cbf81fd49cc2d51c09c75c35433c3a1945d04",
+    ),
+    (
+        600000,
+        "00000000000000000007316856900e76b4f7a9139cfbfba89842c8d196cd5f91",
+    ),
+    (
+        630000,
+        "000000000000000000024bead8df69990852c202db0e0097c1a12ea637d7e96d",
+    ),
+    (
+        700000,
+        "0000000000000000000590fc0f3eba193a278534220b2b37e9849e1a770ca959",
+    ),
+    (
+        709632,
+        "0000000000000000000687bca986194dc2c1f949318629b44bb54ec0a94d8244",
+    ),
+    (
+        800000,
+        "00000000000000000002a7c4c1e48d76c5a37902165a270156b7a8d72728a054",
+    ),
+    (
+        840000,
+        "0000000000000000000320283a032748cef8227873ff4872689bf23f1cda83a5",
+    ),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use hashes::Hash;
+
+    fn checkpoint(height: u32) -> Checkpoint {
+        Checkpoint {
+            height,
+            hash: BlockHash::hash(&height.to_le_bytes()),
+            chainwork: None,
+        }
+    }
+
+    #[test]
+    fn lookups() {
+        let mut checkpoints: Checkpoints = vec![checkpoint(200), checkpoint(100), checkpoint(300)]
+            .into_iter()
+            .collect();
+        assert_eq!(checkpoints.len(), 3);
+        assert_eq!(checkpoints.get(100), Some(&checkpoint(100)));
+        assert_eq!(checkpoints.get(150), None);
+        assert_eq!(checkpoints.last(), Some(&checkpoint(300)));
+        assert_eq!(checkpoints.last_at_or_below(299), Some(&checkpoint(200)));
+        assert_eq!(checkpoints.last_at_or_below(200), Some(&checkpoint(200)));
+        assert_eq!(checkpoints.last_at_or_below(99), None);
+        let heights: Vec<u32> = checkpoints.iter().map(|c| c.height).collect();
+        assert_eq!(heights, vec![100, 200, 300]);
+
+        // A user's checkpoint replaces the one at its height
+        let mut own = checkpoint(200);
+        own.chainwork = Some(Uint256::from_u64(1000));
+        assert_eq!(checkpoints.insert(own), Some(checkpoint(200)));
+        assert_eq!(checkpoints.get(200), Some(&own));
+        assert_eq!(checkpoints.len(), 3);
+        assert!(Checkpoints::new().is_empty());
+    }
+
+    #[cfg(feature = "embedded-checkpoints")]
+    #[test]
+    fn mainnet() {
+        use hashes::hex::FromHex;
+
+        let checkpoints = Checkpoints::mainnet();
+        assert_eq!(checkpoints.len(), MAINNET.len());
+        let halving = checkpoints.get(210000).unwrap();
+        assert_eq!(
+            halving.hash,
+            BlockHash::from_hex("000000000000048b95347e83192f69cf0366076336c639f9b7228e9ba171342e")
+                .unwrap()
+        );
+        assert!(checkpoints.last().unwrap().height >= 840000);
+    }
+}
@@ -0,0 +1,532 @@
+//! Bitcoin Core Notifications
+//!
+//! An application can leave consensus to a Bitcoin Core node it trusts
+//! and still follow the chain with this library's types. `CoreFeed` adds
+//! the blocks Core reports to a `HeaderChain` through a `ChainNotifier`,
+//! so subscribers get the same `ChainEvent`s as for headers from peers.
+//!
+//! Core announces new blocks and transactions over ZMQ, on the `hashblock`,
+//! `hashtx`, `rawblock` and `rawtx` topics. This library has no ZMQ
+//! binding, so the application receives the messages itself and passes
+//! their frames to `CoreFeed::handle_zmq`. Anything else it needs from
+//! Core, such as the headers of blocks whose notifications were missed, is
+//! fetched through a `BlockSource`, which the application implements over
+//! Core's RPC. Without ZMQ, calling `CoreFeed::poll` now and then follows
+//! the chain through RPC alone.
+
+use std::{error, fmt, io};
+
+use blockdata::block::BlockHeader;
+use blockdata::events::ChainNotifier;
+use blockdata::headerchain::{self, HeaderChain, HeaderStatus};
+use blockdata::transaction::Transaction;
+use consensus::encode::{self, deserialize};
+use error::{ErrorCode, ErrorKind};
+use hash_types::{BlockHash, Txid};
+use hashes::Hash;
+use util::endian;
+
+/// A Core notification error
+#[derive(Debug)]
+pub enum Error {
+    /// The block source failed
+    Source(io::Error),
+    /// A notification did not decode
+    Encode(encode::Error),
+    /// The header chain rejected a header from Core
+    HeaderChain(headerchain::Error),
+    /// The block source gave a header other than the one asked for
+    WrongHeader(BlockHash),
+    /// A ZMQ message was not a topic, a body and a sequence number
+    InvalidMessage(&'static str),
+    /// A ZMQ topic other than those of blocks and transactions
+    UnknownTopic(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Source(ref e) => write!(f, "block source error: {}", e),
+            Error::Encode(ref e) => write!(f, "notification error: {}", e),
+            Error::HeaderChain(ref e) => write!(f, "header chain error: {}", e),
+            Error::WrongHeader(ref h) => write!(f, "block source gave the wrong header for {}", h),
+            Error::InvalidMessage(s) => write!(f, "invalid ZMQ message: {}", s),
+            Error::UnknownTopic(ref s) => write!(f, "unknown ZMQ topic: {}", s),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::Source(ref e) => Some(e),
+            Error::Encode(ref e) => Some(e),
+            Error::HeaderChain(ref e) => Some(e),
+            Error::WrongHeader(..) | Error::InvalidMessage(..) | Error::UnknownTopic(..) => None,
+        }
+    }
+
+    fn description(&self) -> &str {
+        match *self {
+            Error::Source(..) => "block source error",
+            Error::Encode(..) => "notification error",
+            Error::HeaderChain(..) => "header chain error",
+            Error::WrongHeader(..) => "block source gave the wrong header",
+            Error::InvalidMessage(..) => "invalid ZMQ message",
+            Error::UnknownTopic(..) => "unknown ZMQ topic",
+        }
+    }
+}
+
+impl ErrorCode for Error {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::CoreNotify
+    }
+
+    fn number(&self) -> u32 {
+        match *self {
+            Error::Source(..) => 1,
+            Error::Encode(..) => 2,
+            Error::HeaderChain(..) => 3,
+            Error::WrongHeader(..) => 4,
+            Error::InvalidMessage(..) => 5,
+            Error::UnknownTopic(..) => 6,
+        }
+    }
+}
+
+#[doc(hidden)]
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Source(e)
+    }
+}
+
+#[doc(hidden)]
+impl From<encode::Error> for Error {
+    fn from(e: encode::Error) -> Error {
+        Error::Encode(e)
+    }
+}
+
+#[doc(hidden)]
+impl From<headerchain::Error> for Error {
+    fn from(e: headerchain::Error) -> Error {
+        Error::HeaderChain(e)
+    }
+}
+
+/// Where Core's blocks are fetched from, usually its RPC
+pub trait BlockSource {
+    /// The hash of the tip of the best chain, as from `getbestblockhash`
+    fn best_block_hash(&mut self) -> io::Result<BlockHash>;
+
+    /// The header of a block, as from `getblockheader` with `verbose`
+    /// false
+    fn block_header(&mut self, hash: &BlockHash) -> io::Result<BlockHeader>;
+
+    /// The txids of a block, in block order, as from `getblock` with
+    /// verbosity 1
+    fn block_txids(&mut self, hash: &BlockHash) -> io::Result<Vec<Txid>>;
+}
+
+/// A ZMQ topic Core publishes on
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum ZmqTopic {
+    /// The hash of each block connected
+    HashBlock,
+    /// The txid of each transaction accepted or in a block connected
+    HashTx,
+    /// Each block connected
+    RawBlock,
+    /// Each transaction accepted or in a block connected
+    RawTx,
+}
+
+impl ZmqTopic {
+    /// The name of the topic, to subscribe to
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ZmqTopic::HashBlock => "hashblock",
+            ZmqTopic::HashTx => "hashtx",
+            ZmqTopic::RawBlock => "rawblock",
+            ZmqTopic::RawTx => "rawtx",
+        }
+    }
+
+    /// The topic with the name `name`
+    pub fn from_name(name: &[u8]) -> Option<ZmqTopic> {
+        match name {
+            b"hashblock" => Some(ZmqTopic::HashBlock),
+            b"hashtx" => Some(ZmqTopic::HashTx),
+            b"rawblock" => Some(ZmqTopic::RawBlock),
+            b"rawtx" => Some(ZmqTopic::RawTx),
+            _ => None,
+        }
+    }
+}
+
+/// A ZMQ notification from Core
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ZmqMessage {
+    /// The topic the message was published on
+    pub topic: ZmqTopic,
+    /// A hash, in the byte order Core displays it in, or a serialized
+    /// block or transaction
+    pub body: Vec<u8>,
+    /// The number of messages published on the topic before this one
+    pub sequence: u32,
+}
+
+impl ZmqMessage {
+    /// Reads a message from its three frames: the topic, the body and the
+    /// little-endian sequence number
+    pub fn from_frames<T: AsRef<[u8]>>(frames: &[T]) -> Result<ZmqMessage, Error> {
+        if frames.len() != 3 {
+            return Err(Error::InvalidMessage("expected three frames"));
+        }
+        let name = frames[0].as_ref();
+        let topic = match ZmqTopic::from_name(name) {
+            Some(topic) => topic,
+            None => {
+                return Err(Error::UnknownTopic(
+                    String::from_utf8_lossy(name).into_owned(),
+                ))
+            }
+        };
+        let sequence = frames[2].as_ref();
+        if sequence.len() != 4 {
+            return Err(Error::InvalidMessage("sequence number is not four bytes"));
+        }
+        Ok(ZmqMessage {
+            topic,
+            body: frames[1].as_ref().to_vec(),
+            sequence: endian::slice_to_u32_le(sequence),
+        })
+    }
+}
+
+/// What a ZMQ message was about
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Notification {
+    /// A block, whose changes to the best chain were sent to the
+    /// notifier's subscribers
+    Block(BlockHash),
+    /// A transaction, from `rawtx`
+    Tx(Transaction),
+    /// The txid of a transaction, from `hashtx`
+    Txid(Txid),
+}
+
+/// A block as Core serializes it
+struct RawBlock {
+    header: BlockHeader,
+    txdata: Vec<Transaction>,
+}
+impl_consensus_encoding!(RawBlock, header, txdata);
+
+/// Follows Core's best chain, adding its blocks to a `HeaderChain`
+#[derive(Debug)]
+pub struct CoreFeed<S> {
+    source: S,
+}
+
+impl<S: BlockSource> CoreFeed<S> {
+    /// Creates a feed which fetches blocks from `source`
+    pub fn new(source: S) -> CoreFeed<S> {
+        CoreFeed { source }
+    }
+
+    /// The block source
+    pub fn source(&mut self) -> &mut S {
+        &mut self.source
+    }
+
+    /// Catches `chain` up with Core's best chain, returning the number of
+    /// blocks added
+    pub fn poll(
+        &mut self,
+        notifier: &mut ChainNotifier,
+        chain: &mut HeaderChain,
+    ) -> Result<usize, Error> {
+        let tip = self.source.best_block_hash()?;
+        self.sync_to(notifier, chain, tip)
+    }
+
+    /// Handles a ZMQ message. Block notifications are added to `chain`,
+    /// along with any blocks before them which `chain` is missing because
+    /// their notifications were lost, while transaction notifications are
+    /// left to the caller.
+    pub fn handle_zmq(
+        &mut self,
+        notifier: &mut ChainNotifier,
+        chain: &mut HeaderChain,
+        message: &ZmqMessage,
+    ) -> Result<Notification, Error> {
+        match message.topic {
+            ZmqTopic::HashBlock => {
+                let hash = BlockHash::from_inner(reversed_hash(&message.body)?);
+                self.sync_to(notifier, chain, hash)?;
+                Ok(Notification::Block(hash))
+            }
+            ZmqTopic::HashTx => Ok(Notification::Txid(Txid::from_inner(reversed_hash(
+                &message.body,
+            )?))),
+            ZmqTopic::RawBlock => {
+                let block: RawBlock = deserialize(&message.body)?;
+                let hash = block.header.block_hash();
+                self.sync_to(notifier, chain, block.header.prev_blockhash)?;
+                if notifier.add_header(chain, block.header)? != HeaderStatus::AlreadyKnown {
+                    let txids = block.txdata.iter().map(Transaction::txid).collect();
+                    notifier.add_block_txids(chain, hash, txids);
+                }
+                Ok(Notification::Block(hash))
+            }
+            ZmqTopic::RawTx => Ok(Notification::Tx(deserialize(&message.body)?)),
+        }
+    }
+
+    /// Adds the block `tip` and those before it which `chain` is missing,
+    /// returning how many were added
+    fn sync_to(
+        &mut self,
+        notifier: &mut ChainNotifier,
+        chain: &mut HeaderChain,
+        tip: BlockHash,
+    ) -> Result<usize, Error> {
+        let mut headers = vec![];
+        let mut hash = tip;
+        while !chain.contains(&hash) {
+            let header = self.source.block_header(&hash)?;
+            if header.block_hash() != hash {
+                return Err(Error::WrongHeader(hash));
+            }
+            hash = header.prev_blockhash;
+            headers.push(header);
+        }
+        for header in headers.iter().rev() {
+            notifier.add_header(chain, *header)?;
+            let hash = header.block_hash();
+            let txids = self.source.block_txids(&hash)?;
+            notifier.add_block_txids(chain, hash, txids);
+        }
+        Ok(headers.len())
+    }
+}
+
+/// A hash from a ZMQ body, which Core sends in display order
+fn reversed_hash(body: &[u8]) -> Result<[u8; 32], Error> {
+    if body.len() != 32 {
+        return Err(Error::InvalidMessage("hash is not 32 bytes"));
+    }
+    let mut hash = [0; 32];
+    for (i, byte) in body.iter().rev().enumerate() {
+        hash[i] = *byte;
+    }
+    Ok(hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+    use std::sync::mpsc;
+
+    use blockdata::events::ChainEvent;
+    use consensus::encode::serialize;
+    use testutil::{mine_header, TxFixture};
+
+    /// A node's blocks, by hash
+    #[derive(Default)]
+    struct Node {
+        tip: BlockHash,
+        blocks: HashMap<BlockHash, (BlockHeader, Vec<Transaction>)>,
+        requests: usize,
+    }
+
+    impl Node {
+        fn mine(&mut self, prev: BlockHash, tag: u32) -> BlockHeader {
+            let header = mine_header(prev, 1, tag);
+            let txdata = TxFixture::new(tag as u64).block_txdata(tag, 2);
+            self.blocks.insert(header.block_hash(), (header, txdata));
+            self.tip = header.block_hash();
+            header
+        }
+
+        fn zmq(&self, topic: &str, hash: BlockHash) -> ZmqMessage {
+            let body = match topic {
+                "rawblock" => {
+                    let (header, ref txdata) = self.blocks[&hash];
+                    serialize(&RawBlock {
+                        header,
+                        txdata: txdata.clone(),
+                    })
+                }
+                _ => hash.into_inner().iter().rev().cloned().collect(),
+            };
+            ZmqMessage::from_frames(&[topic.as_bytes(), &body, &[0, 0, 0, 0]]).unwrap()
+        }
+    }
+
+    impl BlockSource for Node {
+        fn best_block_hash(&mut self) -> io::Result<BlockHash> {
+            Ok(self.tip)
+        }
+
+        fn block_header(&mut self, hash: &BlockHash) -> io::Result<BlockHeader> {
+            self.requests += 1;
+            match self.blocks.get(hash) {
+                Some(block) => Ok(block.0),
+                None => Err(io::Error::new(io::ErrorKind::NotFound, "unknown block")),
+            }
+        }
+
+        fn block_txids(&mut self, hash: &BlockHash) -> io::Result<Vec<Txid>> {
+            self.requests += 1;
+            match self.blocks.get(hash) {
+                Some(block) => Ok(block.1.iter().map(Transaction::txid).collect()),
+                None => Err(io::Error::new(io::ErrorKind::NotFound, "unknown block")),
+            }
+        }
+    }
+
+    fn connected(receiver: &mpsc::Receiver<ChainEvent>) -> Vec<BlockHash> {
+        receiver
+            .try_iter()
+            .filter_map(|event| match event {
+                ChainEvent::Connected { header, .. } => Some(header.block_hash()),
+                ChainEvent::Disconnected(hash) => Some(hash),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn poll() {
+        let mut node = Node::default();
+        let root = node.mine(BlockHash::default(), 0);
+        let a1 = node.mine(root.block_hash(), 1);
+        let a2 = node.mine(a1.block_hash(), 2);
+
+        let mut chain = HeaderChain::new(root);
+        let mut notifier = ChainNotifier::new();
+        let receiver = notifier.subscribe();
+        let mut feed = CoreFeed::new(node);
+        assert_eq!(feed.poll(&mut notifier, &mut chain).unwrap(), 2);
+        assert_eq!(feed.poll(&mut notifier, &mut chain).unwrap(), 0);
+        assert_eq!(chain.tip(), a2.block_hash());
+        let events: Vec<ChainEvent> = receiver.try_iter().collect();
+        assert_eq!(events.len(), 2 * (1 + 3));
+
+        // A reorg
+        let b2 = feed.source().mine(a1.block_hash(), 12);
+        let b3 = feed.source().mine(b2.block_hash(), 13);
+        assert_eq!(feed.poll(&mut notifier, &mut chain).unwrap(), 2);
+        assert_eq!(
+            connected(&receiver),
+            vec![a2.block_hash(), b2.block_hash(), b3.block_hash()]
+        );
+
+        // A source which lies about a header
+        let wrong = mine_header(b3.block_hash(), 1, 14);
+        let hash = wrong.block_hash();
+        feed.source().blocks.insert(hash, (b3, vec![]));
+        feed.source().tip = hash;
+        match feed.poll(&mut notifier, &mut chain) {
+            Err(Error::WrongHeader(h)) => assert_eq!(h, hash),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn zmq() {
+        let mut node = Node::default();
+        let root = node.mine(BlockHash::default(), 0);
+        let a1 = node.mine(root.block_hash(), 1);
+        let a2 = node.mine(a1.block_hash(), 2);
+        let a3 = node.mine(a2.block_hash(), 3);
+
+        let mut chain = HeaderChain::new(root);
+        let mut notifier = ChainNotifier::new();
+        let receiver = notifier.subscribe();
+        let mut feed = CoreFeed::new(node);
+
+        // A raw block needs nothing from the source
+        let message = feed.source().zmq("rawblock", a1.block_hash());
+        assert_eq!(
+            feed.handle_zmq(&mut notifier, &mut chain, &message)
+                .unwrap(),
+            Notification::Block(a1.block_hash())
+        );
+        assert_eq!(feed.source().requests, 0);
+        let events: Vec<ChainEvent> = receiver.try_iter().collect();
+        assert_eq!(events.len(), 1 + 3);
+        match events[1] {
+            ChainEvent::TxConfirmed { txid, height, .. } => {
+                assert_eq!(txid, feed.source().blocks[&a1.block_hash()].1[0].txid());
+                assert_eq!(height, 1);
+            }
+            ref e => panic!("unexpected event: {:?}", e),
+        }
+
+        // A missed notification is caught up on
+        let message = feed.source().zmq("rawblock", a3.block_hash());
+        feed.handle_zmq(&mut notifier, &mut chain, &message)
+            .unwrap();
+        assert_eq!(feed.source().requests, 2);
+        assert_eq!(connected(&receiver), vec![a2.block_hash(), a3.block_hash()]);
+
+        let a4 = feed.source().mine(a3.block_hash(), 4);
+        let message = feed.source().zmq("hashblock", a4.block_hash());
+        feed.handle_zmq(&mut notifier, &mut chain, &message)
+            .unwrap();
+        assert_eq!(chain.tip(), a4.block_hash());
+
+        // Transactions are passed on
+        let tx = TxFixture::new(9).build();
+        let message =
+            ZmqMessage::from_frames(&[&b"rawtx"[..], &serialize(&tx), &[1, 0, 0, 0]]).unwrap();
+        assert_eq!(message.sequence, 1);
+        assert_eq!(
+            feed.handle_zmq(&mut notifier, &mut chain, &message)
+                .unwrap(),
+            Notification::Tx(tx.clone())
+        );
+        let body: Vec<u8> = tx.txid().into_inner().iter().rev().cloned().collect();
+        let message = ZmqMessage::from_frames(&[&b"hashtx"[..], &body, &[2, 0, 0, 0]]).unwrap();
+        assert_eq!(
+            feed.handle_zmq(&mut notifier, &mut chain, &message)
+                .unwrap(),
+            Notification::Txid(tx.txid())
+        );
+    }
+
+    #[test]
+    fn invalid_messages() {
+        let seq = [0u8; 4];
+        match ZmqMessage::from_frames(&[&b"sequence"[..], &[], &seq]) {
+            Err(Error::UnknownTopic(ref s)) => assert_eq!(s, "sequence"),
+            r => panic!("unexpected result: {:?}", r),
+        }
+        assert!(ZmqMessage::from_frames(&[&b"rawtx"[..], &[]]).is_err());
+        assert!(ZmqMessage::from_frames(&[&b"rawtx"[..], &[], &seq[..3]]).is_err());
+
+        let mut node = Node::default();
+        let root = node.mine(BlockHash::default(), 0);
+        let mut chain = HeaderChain::new(root);
+        let mut notifier = ChainNotifier::new();
+        let mut feed = CoreFeed::new(node);
+        let message = ZmqMessage::from_frames(&[&b"hashblock"[..], &[0; 31], &seq]).unwrap();
+        assert_eq!(
+            feed.handle_zmq(&mut notifier, &mut chain, &message)
+                .unwrap_err()
+                .code(),
+            25005
+        );
+        let message = ZmqMessage::from_frames(&[&b"rawtx"[..], &[0; 4], &seq]).unwrap();
+        assert!(feed
+            .handle_zmq(&mut notifier, &mut chain, &message)
+            .is_err());
+    }
+}
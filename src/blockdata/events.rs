@@ -0,0 +1,333 @@
+//! Chain Events
+//!
+//! A wallet built on the header chain has to follow it through reorgs:
+//! transactions it saw confirmed can lose their confirmation when their
+//! block leaves the best chain, and be confirmed again in another. Instead
+//! of polling the chain for such changes, a wallet can subscribe to a
+//! `ChainNotifier`, which adds headers to a `HeaderChain` and tells every
+//! subscriber, in order, which blocks and transactions were connected and
+//! disconnected.
+//!
+//! Headers usually arrive before their blocks, so the transactions of a
+//! block are given separately, as their txids, and are reported confirmed
+//! once both the block is in the best chain and its txids are known.
+//!
+//! On a reorg, the blocks leaving the best chain are disconnected tip
+//! first, each after unconfirming its transactions in reverse order, and
+//! then the blocks of the new best chain are connected, each before
+//! confirming its transactions. Replaying the events in order therefore
+//! keeps a wallet in step with the best chain.
+
+use std::collections::HashMap;
+use std::sync::mpsc;
+
+use blockdata::block::BlockHeader;
+use blockdata::headerchain::{Error, HeaderChain, HeaderStatus};
+use hash_types::{BlockHash, Txid};
+use util::index::TxLocation;
+
+/// A change to the best chain
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum ChainEvent {
+    /// A block joined the best chain
+    Connected {
+        /// The header of the block
+        header: BlockHeader,
+        /// The height of the block
+        height: u32,
+    },
+    /// A block left the best chain in a reorg
+    Disconnected(BlockHash),
+    /// A transaction was confirmed in a block of the best chain
+    TxConfirmed {
+        /// The txid of the transaction
+        txid: Txid,
+        /// Where the transaction is confirmed
+        location: TxLocation,
+        /// The height of the block it is confirmed in
+        height: u32,
+    },
+    /// The block confirming a transaction left the best chain
+    TxUnconfirmed(Txid),
+}
+
+/// Adds headers to a `HeaderChain` and sends the resulting `ChainEvent`s
+/// to its subscribers
+#[derive(Debug, Default)]
+pub struct ChainNotifier {
+    subscribers: Vec<mpsc::Sender<ChainEvent>>,
+    /// The txids of the blocks given, in block order
+    block_txids: HashMap<BlockHash, Vec<Txid>>,
+}
+
+impl ChainNotifier {
+    /// Creates a notifier with no subscribers
+    pub fn new() -> ChainNotifier {
+        ChainNotifier::default()
+    }
+
+    /// Subscribes to the events from now on. A subscription ends when its
+    /// receiver is dropped.
+    pub fn subscribe(&mut self) -> mpsc::Receiver<ChainEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.push(sender);
+        receiver
+    }
+
+    /// The number of subscriptions, counting those whose receiver was
+    /// dropped since the last event
+    pub fn subscriber_count(&self) -> usize {
+        self.subscribers.len()
+    }
+
+    /// Adds a header to `chain`, sending the events for the blocks it
+    /// connects and disconnects
+    pub fn add_header(
+        &mut self,
+        chain: &mut HeaderChain,
+        header: BlockHeader,
+    ) -> Result<HeaderStatus, Error> {
+        let status = chain.add_header(header)?;
+        match status {
+            HeaderStatus::AlreadyKnown | HeaderStatus::Fork => {}
+            HeaderStatus::Extended => self.connect(chain, chain.height()),
+            HeaderStatus::Reorg {
+                fork_height,
+                ref disconnected,
+            } => {
+                for hash in disconnected {
+                    self.disconnect(*hash);
+                }
+                for height in fork_height + 1..chain.height() + 1 {
+                    self.connect(chain, height);
+                }
+                // Forget the transactions of blocks the chain has pruned
+                self.block_txids.retain(|hash, _| chain.contains(hash));
+            }
+        }
+        Ok(status)
+    }
+
+    /// Gives the txids of a block, in block order, confirming them if the
+    /// block is in the best chain. The txids are kept for as long as the
+    /// block is in `chain`, so that they can be unconfirmed and confirmed
+    /// again through reorgs, and ignored if its header is not in `chain`.
+    pub fn add_block_txids(&mut self, chain: &HeaderChain, block: BlockHash, txids: Vec<Txid>) {
+        if !chain.contains(&block) || self.block_txids.contains_key(&block) {
+            return;
+        }
+        self.block_txids.insert(block, txids);
+        if chain.is_in_best_chain(&block) {
+            let height = chain
+                .get(&block)
+                .expect("best chain headers are stored")
+                .height;
+            self.confirm(block, height);
+        }
+    }
+
+    /// Connects the best-chain block at `height`
+    fn connect(&mut self, chain: &HeaderChain, height: u32) {
+        let hash = chain
+            .hash_at_height(height)
+            .expect("height is in the best chain");
+        let header = chain
+            .get(&hash)
+            .expect("best chain headers are stored")
+            .header;
+        self.send(ChainEvent::Connected { header, height });
+        self.confirm(hash, height);
+    }
+
+    fn confirm(&mut self, block: BlockHash, height: u32) {
+        let events: Vec<ChainEvent> = match self.block_txids.get(&block) {
+            Some(txids) => txids
+                .iter()
+                .enumerate()
+                .map(|(position, txid)| ChainEvent::TxConfirmed {
+                    txid: *txid,
+                    location: TxLocation {
+                        block,
+                        position: position as u32,
+                    },
+                    height,
+                })
+                .collect(),
+            None => return,
+        };
+        for event in events {
+            self.send(event);
+        }
+    }
+
+    fn disconnect(&mut self, block: BlockHash) {
+        let events: Vec<ChainEvent> = match self.block_txids.get(&block) {
+            Some(txids) => txids
+                .iter()
+                .rev()
+                .map(|txid| ChainEvent::TxUnconfirmed(*txid))
+                .collect(),
+            None => vec![],
+        };
+        for event in events {
+            self.send(event);
+        }
+        self.send(ChainEvent::Disconnected(block));
+    }
+
+    /// Sends an event to every subscriber, dropping the subscriptions
+    /// whose receiver is gone
+    fn send(&mut self, event: ChainEvent) {
+        self.subscribers
+            .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use hashes::Hash;
+    use testutil::mine_header;
+
+    fn txid(n: u8) -> Txid {
+        Txid::hash(&[n])
+    }
+
+    fn events(receiver: &mpsc::Receiver<ChainEvent>) -> Vec<ChainEvent> {
+        receiver.try_iter().collect()
+    }
+
+    #[test]
+    fn extend_and_reorg() {
+        let root = mine_header(BlockHash::default(), 1, 0);
+        let mut chain = HeaderChain::new(root);
+        let mut notifier = ChainNotifier::new();
+        let receiver = notifier.subscribe();
+
+        // Headers first, then the txids of the blocks
+        let a1 = mine_header(root.block_hash(), 1, 1);
+        let a2 = mine_header(a1.block_hash(), 1, 2);
+        notifier.add_header(&mut chain, a1).unwrap();
+        notifier.add_header(&mut chain, a2).unwrap();
+        notifier.add_block_txids(&chain, a1.block_hash(), vec![txid(1), txid(2)]);
+        assert_eq!(
+            events(&receiver),
+            vec![
+                ChainEvent::Connected {
+                    header: a1,
+                    height: 1
+                },
+                ChainEvent::Connected {
+                    header: a2,
+                    height: 2
+                },
+                ChainEvent::TxConfirmed {
+                    txid: txid(1),
+                    location: TxLocation {
+                        block: a1.block_hash(),
+                        position: 0
+                    },
+                    height: 1,
+                },
+                ChainEvent::TxConfirmed {
+                    txid: txid(2),
+                    location: TxLocation {
+                        block: a1.block_hash(),
+                        position: 1
+                    },
+                    height: 1,
+                },
+            ]
+        );
+
+        // A fork, whose block txids are known before it takes over
+        let b1 = mine_header(root.block_hash(), 1, 11);
+        let b2 = mine_header(b1.block_hash(), 1, 12);
+        let b3 = mine_header(b2.block_hash(), 1, 13);
+        notifier.add_header(&mut chain, b1).unwrap();
+        notifier.add_header(&mut chain, b2).unwrap();
+        notifier.add_block_txids(&chain, b1.block_hash(), vec![txid(2)]);
+        assert_eq!(events(&receiver), vec![]);
+
+        notifier.add_header(&mut chain, b3).unwrap();
+        assert_eq!(
+            events(&receiver),
+            vec![
+                ChainEvent::Disconnected(a2.block_hash()),
+                ChainEvent::TxUnconfirmed(txid(2)),
+                ChainEvent::TxUnconfirmed(txid(1)),
+                ChainEvent::Disconnected(a1.block_hash()),
+                ChainEvent::Connected {
+                    header: b1,
+                    height: 1
+                },
+                ChainEvent::TxConfirmed {
+                    txid: txid(2),
+                    location: TxLocation {
+                        block: b1.block_hash(),
+                        position: 0
+                    },
+                    height: 1,
+                },
+                ChainEvent::Connected {
+                    header: b2,
+                    height: 2
+                },
+                ChainEvent::Connected {
+                    header: b3,
+                    height: 3
+                },
+            ]
+        );
+
+        // Known headers and txids, and txids of unknown blocks, change
+        // nothing
+        notifier.add_header(&mut chain, b3).unwrap();
+        notifier.add_block_txids(&chain, b1.block_hash(), vec![txid(3)]);
+        let b4 = mine_header(b3.block_hash(), 1, 14);
+        notifier.add_block_txids(&chain, b4.block_hash(), vec![txid(4)]);
+        assert_eq!(events(&receiver), vec![]);
+        notifier.add_header(&mut chain, b4).unwrap();
+        assert_eq!(
+            events(&receiver),
+            vec![ChainEvent::Connected {
+                header: b4,
+                height: 4
+            }]
+        );
+    }
+
+    #[test]
+    fn subscriptions() {
+        let root = mine_header(BlockHash::default(), 1, 0);
+        let mut chain = HeaderChain::new(root);
+        let mut notifier = ChainNotifier::new();
+        let first = notifier.subscribe();
+        let second = notifier.subscribe();
+        assert_eq!(notifier.subscriber_count(), 2);
+
+        let a1 = mine_header(root.block_hash(), 1, 1);
+        notifier.add_header(&mut chain, a1).unwrap();
+        assert_eq!(events(&first), events(&second));
+
+        // A dropped receiver ends its subscription
+        drop(first);
+        let a2 = mine_header(a1.block_hash(), 1, 2);
+        notifier.add_header(&mut chain, a2).unwrap();
+        assert_eq!(notifier.subscriber_count(), 1);
+        assert_eq!(
+            events(&second),
+            vec![ChainEvent::Connected {
+                header: a2,
+                height: 2
+            }]
+        );
+
+        // Errors leave the chain and the subscribers alone
+        assert!(notifier
+            .add_header(&mut chain, mine_header(BlockHash::default(), 1, 5))
+            .is_err());
+        assert_eq!(events(&second), vec![]);
+    }
+}
@@ -0,0 +1,417 @@
+//! Transaction Explanations
+//!
+//! A breakdown of a transaction into the parts its size and weight come
+//! from, for learning how transactions are laid out and for debugging real
+//! ones. `Transaction::explain` breaks down a transaction on its own, and
+//! `Transaction::explain_spending` also classifies its inputs and works out
+//! its fee from the outputs they spend.
+//!
+//! The sighash types are read from what looks like a signature: a DER
+//! encoded ECDSA signature in the `script_sig` or witness, or the 64 or 65
+//! byte Schnorr signature of a taproot key path spend. Other data may
+//! happen to look like a signature, and signatures in taproot script paths
+//! are only found when the spent output is known to be taproot.
+
+use std::fmt;
+
+use blockdata::script::{Instruction, Script};
+use blockdata::transaction::{
+    InputType, LockTime, OutPoint, Sequence, Transaction, TxOut, Version,
+};
+use consensus::encode::VarInt;
+use hash_types::{Txid, Wtxid};
+use util::address::AddressType;
+use util::amount::Amount;
+use util::fee_rate::FeeRate;
+use util::weight::Weight;
+
+/// A breakdown of a transaction, as returned by `Transaction::explain`
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TxExplanation {
+    /// The transaction id
+    pub txid: Txid,
+    /// The transaction id committing to the witnesses
+    pub wtxid: Wtxid,
+    /// The version
+    pub version: Version,
+    /// The lock time
+    pub lock_time: LockTime,
+    /// The weight of the version, the lock time and the input and output
+    /// counts
+    pub header_weight: Weight,
+    /// The weight of the segwit marker and flag, present if any input has a
+    /// witness
+    pub marker_weight: Weight,
+    /// The inputs
+    pub inputs: Vec<InputExplanation>,
+    /// The outputs
+    pub outputs: Vec<OutputExplanation>,
+    /// The fee, if the outputs spent are known
+    pub fee: Option<Amount>,
+}
+
+/// A breakdown of a transaction input
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct InputExplanation {
+    /// The output spent
+    pub previous_output: OutPoint,
+    /// The sequence number
+    pub sequence: Sequence,
+    /// The type of the output spent, if it is known
+    pub input_type: Option<InputType>,
+    /// The raw sighash byte of each signature found, `0x00` for the taproot
+    /// default
+    pub sighash_types: Vec<u8>,
+    /// The size of the input without its witness
+    pub base_size: usize,
+    /// The size of its witness, which is one byte for the empty stack of an
+    /// input without a witness in a segwit transaction
+    pub witness_size: usize,
+}
+
+impl InputExplanation {
+    /// The weight of the input, counting its witness
+    pub fn weight(&self) -> Weight {
+        Weight::from_wu((4 * self.base_size + self.witness_size) as u64)
+    }
+}
+
+/// A breakdown of a transaction output
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct OutputExplanation {
+    /// The value
+    pub value: Amount,
+    /// The address type of the script, if it has one
+    pub address_type: Option<AddressType>,
+    /// Whether the script is an `OP_RETURN` output
+    pub is_op_return: bool,
+    /// The size of the output
+    pub size: usize,
+}
+
+impl OutputExplanation {
+    /// The weight of the output
+    pub fn weight(&self) -> Weight {
+        Weight::from_wu(4 * self.size as u64)
+    }
+}
+
+impl TxExplanation {
+    /// The weight of the transaction, the sum of the weights of its parts
+    pub fn weight(&self) -> Weight {
+        let inputs: u64 = self.inputs.iter().map(|i| i.weight().to_wu()).sum();
+        let outputs: u64 = self.outputs.iter().map(|o| o.weight().to_wu()).sum();
+        Weight::from_wu(self.header_weight.to_wu() + self.marker_weight.to_wu() + inputs + outputs)
+    }
+
+    /// The size of the transaction without its witnesses
+    pub fn base_size(&self) -> usize {
+        let inputs: usize = self.inputs.iter().map(|i| i.base_size).sum();
+        let outputs: usize = self.outputs.iter().map(|o| o.size).sum();
+        self.header_weight.to_wu() as usize / 4 + inputs + outputs
+    }
+
+    /// The size of the transaction with its witnesses
+    pub fn total_size(&self) -> usize {
+        let witnesses: usize = self.inputs.iter().map(|i| i.witness_size).sum();
+        self.base_size() + self.marker_weight.to_wu() as usize + witnesses
+    }
+
+    /// The fee rate, if the fee is known
+    pub fn fee_rate(&self) -> Option<FeeRate> {
+        self.fee.map(|fee| fee / self.weight())
+    }
+}
+
+impl Transaction {
+    /// Breaks the transaction down into the parts its weight comes from.
+    /// The types of the inputs and the fee are unknown, as they depend on
+    /// the outputs spent; see `explain_spending`.
+    pub fn explain(&self) -> TxExplanation {
+        self.explain_spending(&[])
+    }
+
+    /// Breaks the transaction down like `explain`, given the outputs its
+    /// inputs spend in order. Inputs without a spent output are left
+    /// unclassified, and the fee is only worked out if all of them have
+    /// one.
+    pub fn explain_spending(&self, spent: &[TxOut]) -> TxExplanation {
+        let is_segwit = self.input.iter().any(|i| !i.witness.is_empty());
+        let inputs = self
+            .input
+            .iter()
+            .enumerate()
+            .map(|(i, input)| {
+                let input_type = spent.get(i).map(|o| input.input_type(&o.script_pubkey));
+                let mut sighash_types = vec![];
+                ecdsa_sighash_types(&input.script_sig, &mut sighash_types);
+                let stack = input.taproot_stack();
+                for elem in stack {
+                    if is_ecdsa_signature(elem) {
+                        sighash_types.push(elem[elem.len() - 1]);
+                    }
+                }
+                let is_key_path = input.script_sig.is_empty() && stack.len() == 1;
+                let schnorr = match input_type {
+                    Some(InputType::P2tr) if stack.len() > 1 => &stack[..stack.len() - 2],
+                    None | Some(InputType::P2tr) if is_key_path => stack,
+                    _ => &[],
+                };
+                for elem in schnorr {
+                    match elem.len() {
+                        64 => sighash_types.push(0x00),
+                        65 => sighash_types.push(elem[64]),
+                        _ => {}
+                    }
+                }
+                let witness_size = match input.witness_size() {
+                    0 if is_segwit => 1,
+                    size => size,
+                };
+                InputExplanation {
+                    previous_output: input.previous_output,
+                    sequence: Sequence(input.sequence),
+                    input_type,
+                    sighash_types,
+                    base_size: input.base_size(),
+                    witness_size,
+                }
+            })
+            .collect();
+        let outputs = self
+            .output
+            .iter()
+            .map(|output| {
+                let script_len = output.script_pubkey.len();
+                OutputExplanation {
+                    value: Amount::from_sat(output.value),
+                    address_type: output.script_pubkey.address_type(),
+                    is_op_return: output.script_pubkey.is_op_return(),
+                    size: 8 + VarInt(script_len as u64).len() + script_len,
+                }
+            })
+            .collect();
+
+        let header_size =
+            4 + VarInt(self.input.len() as u64).len() + VarInt(self.output.len() as u64).len() + 4;
+        let fee = if spent.len() >= self.input.len() {
+            let spent: u64 = spent[..self.input.len()].iter().map(|o| o.value).sum();
+            let created: u64 = self.output.iter().map(|o| o.value).sum();
+            spent.checked_sub(created).map(Amount::from_sat)
+        } else {
+            None
+        };
+        TxExplanation {
+            txid: self.txid(),
+            wtxid: self.wtxid(),
+            version: self.tx_version(),
+            lock_time: self.tx_lock_time(),
+            header_weight: Weight::from_wu(4 * header_size as u64),
+            marker_weight: Weight::from_wu(if is_segwit { 2 } else { 0 }),
+            inputs,
+            outputs,
+            fee,
+        }
+    }
+}
+
+/// Whether `elem` is a DER encoded ECDSA signature followed by a sighash
+/// byte
+fn is_ecdsa_signature(elem: &[u8]) -> bool {
+    elem.len() >= 9 && elem.len() <= 73 && elem[0] == 0x30 && elem[1] as usize == elem.len() - 3
+}
+
+/// Adds the sighash bytes of the ECDSA signatures pushed by `script_sig`
+fn ecdsa_sighash_types(script_sig: &Script, sighash_types: &mut Vec<u8>) {
+    for instruction in script_sig.instructions() {
+        match instruction {
+            Ok(Instruction::PushBytes(data)) if is_ecdsa_signature(data) => {
+                sighash_types.push(data[data.len() - 1])
+            }
+            Ok(_) => {}
+            Err(_) => break,
+        }
+    }
+}
+
+/// The name of a sighash byte
+fn sighash_name(sighash_type: u8) -> &'static str {
+    match sighash_type {
+        0x00 => "DEFAULT",
+        0x01 => "ALL",
+        0x02 => "NONE",
+        0x03 => "SINGLE",
+        0x81 => "ALL|ANYONECANPAY",
+        0x82 => "NONE|ANYONECANPAY",
+        0x83 => "SINGLE|ANYONECANPAY",
+        _ => "non-standard",
+    }
+}
+
+impl fmt::Display for TxExplanation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let weight = self.weight();
+        writeln!(f, "transaction {}", self.txid)?;
+        if self.marker_weight.to_wu() > 0 {
+            writeln!(f, "  wtxid {}", self.wtxid)?;
+        }
+        writeln!(
+            f,
+            "  version {}, lock time {}",
+            self.version, self.lock_time
+        )?;
+        writeln!(
+            f,
+            "  size {} bytes ({} without witnesses), weight {}, {} vbytes",
+            self.total_size(),
+            self.base_size(),
+            weight,
+            weight.to_vbytes_ceil()
+        )?;
+        writeln!(f, "  header: {}", self.header_weight)?;
+        if self.marker_weight.to_wu() > 0 {
+            writeln!(f, "  segwit marker and flag: {}", self.marker_weight)?;
+        }
+        for (i, input) in self.inputs.iter().enumerate() {
+            write!(f, "  input {}: {}", i, input.previous_output)?;
+            if let Some(input_type) = input.input_type {
+                write!(f, " ({:?})", input_type)?;
+            }
+            writeln!(f, ", sequence {:#010x}", input.sequence.0)?;
+            write!(
+                f,
+                "    {} bytes + {} witness bytes = {}",
+                input.base_size,
+                input.witness_size,
+                input.weight()
+            )?;
+            if !input.sighash_types.is_empty() {
+                let names: Vec<_> = input
+                    .sighash_types
+                    .iter()
+                    .map(|&t| sighash_name(t))
+                    .collect();
+                write!(f, ", signed {}", names.join(", "))?;
+            }
+            writeln!(f)?;
+        }
+        for (i, output) in self.outputs.iter().enumerate() {
+            write!(f, "  output {}: {}", i, output.value)?;
+            match output.address_type {
+                Some(address_type) => write!(f, " to {}", address_type)?,
+                None if output.is_op_return => write!(f, " to OP_RETURN")?,
+                None => write!(f, " to a non-standard script")?,
+            }
+            writeln!(f, ", {} bytes = {}", output.size, output.weight())?;
+        }
+        match self.fee {
+            Some(fee) => writeln!(
+                f,
+                "  fee {} at {:.1} sat/vB",
+                fee,
+                fee.as_sat() as f64 / weight.to_wu() as f64 * 4.0
+            ),
+            None => writeln!(f, "  fee unknown"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use blockdata::transaction::{TxIn, TAPROOT_ANNEX_PREFIX};
+    use consensus::encode::{deserialize, serialize};
+    use hashes::hex::FromHex;
+    use testutil::{InputKind, OutputKind, TxFixture};
+
+    #[test]
+    fn legacy() {
+        // A P2PKH spend
+        let tx: Transaction = deserialize(&Vec::<u8>::from_hex("0100000001a15d57094aa7a21a28cb20b59aab8fc7d1149a3bdbcddba9c622e4f5f6a99ece010000006c493046022100f93bb0e7d8db7bd46e40132d1f8242026e045f03a0efe71bbb8e3f475e970d790221009337cd7f1f929f00cc6ff01f03729b069a7c21b59b1736ddfee5db5946c5da8c0121033b9b137ee87d5a812d6f506efdd37f0affa7ffc310711c06c7f3e097c9447c52ffffffff0100e1f505000000001976a9140389035a9225b3839e2bbf32d826a1e222031fd888ac00000000").unwrap()).unwrap();
+        let explanation = tx.explain();
+        assert_eq!(explanation.weight().to_wu(), tx.get_weight() as u64);
+        assert_eq!(explanation.base_size(), serialize(&tx).len());
+        assert_eq!(explanation.total_size(), serialize(&tx).len());
+        assert_eq!(explanation.marker_weight.to_wu(), 0);
+        assert_eq!(explanation.header_weight.to_wu(), 40);
+        assert_eq!(explanation.inputs[0].input_type, None);
+        assert_eq!(explanation.inputs[0].sighash_types, vec![0x01]);
+        assert_eq!(explanation.inputs[0].witness_size, 0);
+        assert_eq!(
+            explanation.outputs[0].address_type,
+            Some(AddressType::P2pkh)
+        );
+        assert_eq!(explanation.outputs[0].size, 34);
+        assert_eq!(explanation.fee, None);
+        assert_eq!(explanation.fee_rate(), None);
+
+        let spent = TxOut {
+            value: 100_010_000,
+            script_pubkey: Script::from(
+                Vec::<u8>::from_hex("76a9140389035a9225b3839e2bbf32d826a1e222031fd888ac").unwrap(),
+            ),
+        };
+        let explanation = tx.explain_spending(&[spent]);
+        assert_eq!(explanation.inputs[0].input_type, Some(InputType::P2pkh));
+        assert_eq!(explanation.fee, Some(Amount::from_sat(10_000)));
+        let display = explanation.to_string();
+        assert!(display.contains("(P2pkh)"), "{}", display);
+        assert!(display.contains("signed ALL"), "{}", display);
+        assert!(display.contains("to p2pkh"), "{}", display);
+        assert!(display.contains("at 51.8 sat/vB"), "{}", display);
+        assert!(!display.contains("wtxid"), "{}", display);
+    }
+
+    #[test]
+    fn segwit() {
+        let mut tx = TxFixture::new(1)
+            .inputs(2, InputKind::P2wpkh)
+            .outputs(2, OutputKind::P2wpkh)
+            .build();
+        // A legacy input without a witness, and a taproot key path spend
+        // with an annex
+        tx.input.push(TxIn::default());
+        tx.input.push(TxIn {
+            witness: vec![vec![1; 65], vec![TAPROOT_ANNEX_PREFIX, 0]],
+            ..TxIn::default()
+        });
+        tx.input[3].witness[0][64] = 0x83;
+        tx.output.push(TxOut {
+            value: 0,
+            script_pubkey: Script::from(vec![0x6a, 0x01, 0x00]),
+        });
+
+        let explanation = tx.explain();
+        assert_eq!(explanation.weight().to_wu(), tx.get_weight() as u64);
+        assert_eq!(explanation.total_size(), serialize(&tx).len());
+        assert_eq!(explanation.marker_weight.to_wu(), 2);
+        assert_eq!(explanation.inputs[2].witness_size, 1);
+        assert!(explanation.inputs[2].sighash_types.is_empty());
+        assert_eq!(explanation.inputs[3].sighash_types, vec![0x83]);
+        assert!(explanation.outputs[2].is_op_return);
+        assert_eq!(explanation.outputs[2].address_type, None);
+        assert!(explanation.outputs[0].address_type.is_some());
+
+        // Two spent outputs are not enough for a fee
+        let spent = vec![tx.output[0].clone(); 2];
+        let explanation = tx.explain_spending(&spent);
+        assert_eq!(explanation.fee, None);
+        assert_eq!(explanation.inputs[0].input_type, Some(InputType::P2wpkh));
+        assert_eq!(explanation.inputs[2].input_type, None);
+        let display = explanation.to_string();
+        assert!(display.contains("wtxid"), "{}", display);
+        assert!(
+            display.contains("segwit marker and flag: 2 wu"),
+            "{}",
+            display
+        );
+        assert!(
+            display.contains("signed SINGLE|ANYONECANPAY"),
+            "{}",
+            display
+        );
+        assert!(display.contains("to OP_RETURN"), "{}", display);
+        assert!(display.contains("fee unknown"), "{}", display);
+    }
+}
@@ -0,0 +1,261 @@
+//! Fixed-Size Scripts
+//!
+//! A `Script` is allocated on the heap, so it cannot be built at compile
+//! time. The standard output scripts all have a size known in advance,
+//! and `FixedScript` holds one in an array, with `const fn` constructors
+//! for each template. Firmware can then bake its scripts into a `static`
+//! and compare or serialize them without allocating.
+//!
+//! The constructors take the raw hash or key bytes, as the hash types
+//! cannot be built in a `const fn`.
+//!
+//! ```
+//! use bitcoin::blockdata::fixedscript::{FixedScript, P2wpkhScript};
+//!
+//! static CHANGE: P2wpkhScript = FixedScript::new_v0_wpkh([0x11; 20]);
+//! static MEMO: FixedScript<7> = FixedScript::new_op_return(b"hello");
+//!
+//! assert!(CHANGE.to_script().is_v0_p2wpkh());
+//! assert!(MEMO.to_script().is_op_return());
+//! ```
+
+use std::fmt;
+
+use blockdata::opcodes::all::*;
+use blockdata::script::Script;
+
+/// A script of a size known at compile time
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FixedScript<const N: usize>([u8; N]);
+
+/// A P2PKH script pubkey
+pub type P2pkhScript = FixedScript<25>;
+/// A P2SH script pubkey
+pub type P2shScript = FixedScript<23>;
+/// A P2WPKH script pubkey
+pub type P2wpkhScript = FixedScript<22>;
+/// A P2WSH script pubkey
+pub type P2wshScript = FixedScript<34>;
+/// A P2TR script pubkey
+pub type P2trScript = FixedScript<34>;
+
+/// Copies `src` into `dst` from `offset` on, in a `const fn`
+const fn copy_into<const N: usize>(mut dst: [u8; N], offset: usize, src: &[u8]) -> [u8; N] {
+    let mut i = 0;
+    while i < src.len() {
+        dst[offset + i] = src[i];
+        i += 1;
+    }
+    dst
+}
+
+impl<const N: usize> FixedScript<N> {
+    /// Wraps the bytes of a script
+    pub const fn from_bytes(bytes: [u8; N]) -> FixedScript<N> {
+        FixedScript(bytes)
+    }
+
+    /// Creates an `OP_RETURN` script pushing `data`. Fails to compile in a
+    /// `const`, or panics, unless the script is `N` bytes long, which is 1
+    /// byte more than `data` for up to 75 bytes and 2 bytes more for up to
+    /// 255 bytes.
+    pub const fn new_op_return(data: &[u8]) -> FixedScript<N> {
+        let prefix_len = if data.len() <= 75 {
+            2
+        } else if data.len() <= 255 {
+            3
+        } else {
+            panic!("OP_RETURN data too long")
+        };
+        if prefix_len + data.len() != N {
+            panic!("OP_RETURN script of the wrong size");
+        }
+        let mut ret = [0; N];
+        ret[0] = OP_RETURN.into_u8();
+        if prefix_len == 3 {
+            ret[1] = OP_PUSHDATA1.into_u8();
+        }
+        ret[prefix_len - 1] = data.len() as u8;
+        FixedScript(copy_into(ret, prefix_len, data))
+    }
+
+    /// The bytes of the script
+    pub const fn as_array(&self) -> &[u8; N] {
+        &self.0
+    }
+
+    /// The bytes of the script
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// The length in bytes of the script
+    pub const fn len(&self) -> usize {
+        N
+    }
+
+    /// Whether the script is the empty script
+    pub const fn is_empty(&self) -> bool {
+        N == 0
+    }
+
+    /// Copies the script into a `Script`
+    pub fn to_script(&self) -> Script {
+        Script::from(self.0.to_vec())
+    }
+}
+
+impl FixedScript<25> {
+    /// Creates a P2PKH script pubkey
+    pub const fn new_p2pkh(pubkey_hash: [u8; 20]) -> P2pkhScript {
+        let mut ret = [0; 25];
+        ret[0] = OP_DUP.into_u8();
+        ret[1] = OP_HASH160.into_u8();
+        ret[2] = OP_PUSHBYTES_20.into_u8();
+        ret[23] = OP_EQUALVERIFY.into_u8();
+        ret[24] = OP_CHECKSIG.into_u8();
+        FixedScript(copy_into(ret, 3, &pubkey_hash))
+    }
+}
+
+impl FixedScript<23> {
+    /// Creates a P2SH script pubkey
+    pub const fn new_p2sh(script_hash: [u8; 20]) -> P2shScript {
+        let mut ret = [0; 23];
+        ret[0] = OP_HASH160.into_u8();
+        ret[1] = OP_PUSHBYTES_20.into_u8();
+        ret[22] = OP_EQUAL.into_u8();
+        FixedScript(copy_into(ret, 2, &script_hash))
+    }
+}
+
+impl FixedScript<22> {
+    /// Creates a P2WPKH script pubkey
+    pub const fn new_v0_wpkh(pubkey_hash: [u8; 20]) -> P2wpkhScript {
+        let mut ret = [0; 22];
+        ret[0] = OP_PUSHBYTES_0.into_u8();
+        ret[1] = OP_PUSHBYTES_20.into_u8();
+        FixedScript(copy_into(ret, 2, &pubkey_hash))
+    }
+}
+
+impl FixedScript<34> {
+    /// Creates a P2WSH script pubkey
+    pub const fn new_v0_wsh(script_hash: [u8; 32]) -> P2wshScript {
+        let mut ret = [0; 34];
+        ret[0] = OP_PUSHBYTES_0.into_u8();
+        ret[1] = OP_PUSHBYTES_32.into_u8();
+        FixedScript(copy_into(ret, 2, &script_hash))
+    }
+
+    /// Creates a P2TR script pubkey paying to the x-only `output_key`, which
+    /// is already tweaked
+    pub const fn new_v1_p2tr(output_key: [u8; 32]) -> P2trScript {
+        let mut ret = [0; 34];
+        ret[0] = OP_PUSHNUM_1.into_u8();
+        ret[1] = OP_PUSHBYTES_32.into_u8();
+        FixedScript(copy_into(ret, 2, &output_key))
+    }
+}
+
+impl<const N: usize> AsRef<[u8]> for FixedScript<N> {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl<const N: usize> From<FixedScript<N>> for Script {
+    fn from(script: FixedScript<N>) -> Script {
+        script.to_script()
+    }
+}
+
+impl<const N: usize> PartialEq<Script> for FixedScript<N> {
+    fn eq(&self, other: &Script) -> bool {
+        self.as_bytes() == other.as_bytes()
+    }
+}
+
+impl<const N: usize> PartialEq<FixedScript<N>> for Script {
+    fn eq(&self, other: &FixedScript<N>) -> bool {
+        self.as_bytes() == other.as_bytes()
+    }
+}
+
+impl<const N: usize> fmt::Debug for FixedScript<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("FixedScript(")?;
+        for &ch in self.0.iter() {
+            write!(f, "{:02x}", ch)?;
+        }
+        f.write_str(")")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use hash_types::{PubkeyHash, ScriptHash, WPubkeyHash, WScriptHash};
+    use hashes::Hash;
+
+    const HASH20: [u8; 20] = [0xab; 20];
+    const HASH32: [u8; 32] = [0xcd; 32];
+
+    static P2PKH: P2pkhScript = FixedScript::new_p2pkh(HASH20);
+    static P2SH: P2shScript = FixedScript::new_p2sh(HASH20);
+    static P2WPKH: P2wpkhScript = FixedScript::new_v0_wpkh(HASH20);
+    static P2WSH: P2wshScript = FixedScript::new_v0_wsh(HASH32);
+    static P2TR: P2trScript = FixedScript::new_v1_p2tr(HASH32);
+
+    #[test]
+    fn templates() {
+        let hash20 = &HASH20[..];
+        let hash32 = &HASH32[..];
+        assert_eq!(
+            P2PKH,
+            Script::new_p2pkh(&PubkeyHash::from_slice(hash20).unwrap())
+        );
+        assert_eq!(
+            P2SH,
+            Script::new_p2sh(&ScriptHash::from_slice(hash20).unwrap())
+        );
+        assert_eq!(
+            P2WPKH,
+            Script::new_v0_wpkh(&WPubkeyHash::from_slice(hash20).unwrap())
+        );
+        assert_eq!(
+            P2WSH,
+            Script::new_v0_wsh(&WScriptHash::from_slice(hash32).unwrap())
+        );
+        assert!(P2TR.to_script().is_v1_p2tr());
+        assert_eq!(&P2TR.as_bytes()[2..], hash32);
+        assert_eq!(P2PKH.len(), 25);
+        assert!(!P2PKH.is_empty());
+        assert_eq!(Script::from(P2WPKH), P2WPKH);
+        assert_eq!(
+            format!("{:?}", P2WPKH),
+            "FixedScript(0014abababababababababababababababababababab)"
+        );
+    }
+
+    #[test]
+    fn op_return() {
+        const EMPTY: FixedScript<2> = FixedScript::new_op_return(&[]);
+        const SHORT: FixedScript<7> = FixedScript::new_op_return(b"hello");
+        const LONG: FixedScript<83> = FixedScript::new_op_return(&[7; 80]);
+        assert_eq!(EMPTY.as_array(), &[0x6a, 0x00]);
+        assert_eq!(SHORT.as_bytes(), b"\x6a\x05hello");
+        assert_eq!(&LONG.as_bytes()[..3], &[0x6a, 0x4c, 80]);
+        for script in &[EMPTY.to_script(), SHORT.to_script(), LONG.to_script()] {
+            assert!(script.is_op_return());
+            assert_eq!(script.instructions().count(), 2);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn op_return_wrong_size() {
+        FixedScript::<8>::new_op_return(b"hello");
+    }
+}
@@ -0,0 +1,567 @@
+//! Header Chain
+//!
+//! An in-memory tree of block headers rooted at a starting header, which
+//! tracks every fork tip with its cumulative work and follows the one with
+//! the most work. Of two tips with equal work the one seen first is kept,
+//! as Bitcoin Core does.
+//!
+//! During a long sync stale forks can pile up, so a prune depth can be set:
+//! any fork whose tip falls more than that many blocks below the best tip
+//! is dropped. Headers building on a pruned fork are then rejected as
+//! having an unknown parent.
+//!
+//! A chain can also be rooted at a checkpoint rather than the genesis
+//! block, and be given checkpoints that the headers at their heights have
+//! to match. Heights are then counted from the genesis block, so they are
+//! the same as the network's.
+
+use std::collections::{HashMap, HashSet};
+use std::{error, fmt};
+
+use blockdata::block::BlockHeader;
+use blockdata::checkpoints::{Checkpoint, Checkpoints};
+use error::{ErrorCode, ErrorKind};
+use hash_types::BlockHash;
+use util::uint::Uint256;
+
+/// A header chain error
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Error {
+    /// The header's parent is not in the chain, either because it has
+    /// not been seen or because its fork was pruned
+    UnknownPrevBlock(BlockHash),
+    /// The header hash is above the target encoded in the header
+    BadProofOfWork(BlockHash),
+    /// The header is at the height of a checkpoint but does not have its
+    /// hash
+    CheckpointMismatch(u32, BlockHash),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::UnknownPrevBlock(ref h) => write!(f, "unknown previous block: {}", h),
+            Error::BadProofOfWork(ref h) => write!(f, "bad proof of work: {}", h),
+            Error::CheckpointMismatch(height, ref h) => {
+                write!(
+                    f,
+                    "block {} does not match checkpoint at height {}",
+                    h, height
+                )
+            }
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn cause(&self) -> Option<&error::Error> {
+        None
+    }
+
+    fn description(&self) -> &str {
+        match *self {
+            Error::UnknownPrevBlock(..) => "unknown previous block",
+            Error::BadProofOfWork(..) => "bad proof of work",
+            Error::CheckpointMismatch(..) => "checkpoint mismatch",
+        }
+    }
+}
+
+impl ErrorCode for Error {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::HeaderChain
+    }
+
+    fn number(&self) -> u32 {
+        match *self {
+            Error::UnknownPrevBlock(..) => 1,
+            Error::BadProofOfWork(..) => 2,
+            Error::CheckpointMismatch(..) => 3,
+        }
+    }
+}
+
+/// What adding a header did to the chain
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum HeaderStatus {
+    /// The header was already in the chain
+    AlreadyKnown,
+    /// The header was stored on a fork with no more work than the best chain
+    Fork,
+    /// The header extends the best chain
+    Extended,
+    /// The header's fork overtook the best chain
+    Reorg {
+        /// Height of the last block shared by the old and new best chains
+        fork_height: u32,
+        /// Hashes of the blocks no longer in the best chain, tip first
+        disconnected: Vec<BlockHash>,
+    },
+}
+
+/// A header stored in a [`HeaderChain`](struct.HeaderChain.html)
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct HeaderNode {
+    /// The header itself
+    pub header: BlockHeader,
+    /// Height of the header, counting from the genesis block if the chain
+    /// was rooted at a checkpoint and from the root otherwise
+    pub height: u32,
+    /// Total work of the chain ending at this header, including the root
+    /// and, if the root is a checkpoint with known chainwork, every block
+    /// below it
+    pub chainwork: Uint256,
+    /// Number of stored headers building on this one
+    children: u32,
+}
+
+/// A tree of headers following the branch with the most cumulative work
+#[derive(Clone, Debug)]
+pub struct HeaderChain {
+    nodes: HashMap<BlockHash, HeaderNode>,
+    /// Headers without children, including the best tip
+    tips: HashSet<BlockHash>,
+    /// The best chain, indexed by height above the root
+    best: Vec<BlockHash>,
+    /// Height of the root
+    root_height: u32,
+    prune_depth: Option<u32>,
+    checkpoints: Checkpoints,
+}
+
+impl HeaderChain {
+    /// Create a chain rooted at `root`, which is taken to be valid and to
+    /// be at height 0
+    pub fn new(root: BlockHeader) -> HeaderChain {
+        HeaderChain::with_root(root, 0, root.work())
+    }
+
+    /// Create a chain rooted at `root`, the block of `checkpoint`, at the
+    /// checkpoint's height. The checkpoint is kept, along with any given
+    /// later through `with_checkpoints`.
+    pub fn from_checkpoint(
+        root: BlockHeader,
+        checkpoint: &Checkpoint,
+    ) -> Result<HeaderChain, Error> {
+        let hash = root.block_hash();
+        if hash != checkpoint.hash {
+            return Err(Error::CheckpointMismatch(checkpoint.height, hash));
+        }
+        let chainwork = checkpoint.chainwork.unwrap_or_else(|| root.work());
+        let mut chain = HeaderChain::with_root(root, checkpoint.height, chainwork);
+        chain.checkpoints.insert(*checkpoint);
+        Ok(chain)
+    }
+
+    fn with_root(root: BlockHeader, height: u32, chainwork: Uint256) -> HeaderChain {
+        let hash = root.block_hash();
+        let mut nodes = HashMap::new();
+        nodes.insert(
+            hash,
+            HeaderNode {
+                header: root,
+                height,
+                chainwork,
+                children: 0,
+            },
+        );
+        let mut tips = HashSet::new();
+        tips.insert(hash);
+        HeaderChain {
+            nodes,
+            tips,
+            best: vec![hash],
+            root_height: height,
+            prune_depth: None,
+            checkpoints: Checkpoints::new(),
+        }
+    }
+
+    /// Require headers at the heights of `checkpoints` to match them, on
+    /// top of the checkpoint the chain was rooted at, if any. Headers
+    /// already stored are not checked.
+    pub fn with_checkpoints(mut self, checkpoints: Checkpoints) -> HeaderChain {
+        for checkpoint in checkpoints.iter() {
+            self.checkpoints.insert(*checkpoint);
+        }
+        self
+    }
+
+    /// The checkpoints headers have to match
+    pub fn checkpoints(&self) -> &Checkpoints {
+        &self.checkpoints
+    }
+
+    /// Prune forks whose tip is more than `depth` blocks below the best tip
+    pub fn with_prune_depth(mut self, depth: u32) -> HeaderChain {
+        self.prune_depth = Some(depth);
+        self.prune();
+        self
+    }
+
+    /// The prune depth, if pruning is enabled
+    pub fn prune_depth(&self) -> Option<u32> {
+        self.prune_depth
+    }
+
+    /// Add a header whose parent is already in the chain, switching to its
+    /// fork if that now has the most work
+    pub fn add_header(&mut self, header: BlockHeader) -> Result<HeaderStatus, Error> {
+        let hash = header.block_hash();
+        if self.nodes.contains_key(&hash) {
+            return Ok(HeaderStatus::AlreadyKnown);
+        }
+        let (height, chainwork) = match self.nodes.get(&header.prev_blockhash) {
+            Some(parent) => (parent.height + 1, parent.chainwork + header.work()),
+            None => return Err(Error::UnknownPrevBlock(header.prev_blockhash)),
+        };
+        if !header.has_valid_pow() {
+            return Err(Error::BadProofOfWork(hash));
+        }
+        if let Some(checkpoint) = self.checkpoints.get(height) {
+            if checkpoint.hash != hash {
+                return Err(Error::CheckpointMismatch(height, hash));
+            }
+        }
+
+        self.nodes
+            .get_mut(&header.prev_blockhash)
+            .expect("parent checked above")
+            .children += 1;
+        self.nodes.insert(
+            hash,
+            HeaderNode {
+                header,
+                height,
+                chainwork,
+                children: 0,
+            },
+        );
+        self.tips.remove(&header.prev_blockhash);
+        self.tips.insert(hash);
+
+        let status = if chainwork <= self.tip_node().chainwork {
+            HeaderStatus::Fork
+        } else if header.prev_blockhash == self.tip() {
+            self.best.push(hash);
+            HeaderStatus::Extended
+        } else {
+            self.reorg(hash)
+        };
+        self.prune();
+        Ok(status)
+    }
+
+    /// Make the fork ending in `new_tip` the best chain
+    fn reorg(&mut self, new_tip: BlockHash) -> HeaderStatus {
+        let mut connect = vec![];
+        let mut cursor = new_tip;
+        while !self.is_in_best_chain(&cursor) {
+            connect.push(cursor);
+            cursor = self.nodes[&cursor].header.prev_blockhash;
+        }
+        let fork_height = self.nodes[&cursor].height;
+        let mut disconnected = self
+            .best
+            .split_off((fork_height - self.root_height) as usize + 1);
+        disconnected.reverse();
+        self.best.extend(connect.into_iter().rev());
+        HeaderStatus::Reorg {
+            fork_height,
+            disconnected,
+        }
+    }
+
+    /// Drop the headers of forks that have fallen too far behind
+    fn prune(&mut self) {
+        let depth = match self.prune_depth {
+            Some(depth) => depth,
+            None => return,
+        };
+        let best_height = self.height();
+        let stale: Vec<BlockHash> = self
+            .tips
+            .iter()
+            .filter(|tip| (self.nodes[*tip].height as u64) + (depth as u64) < best_height as u64)
+            .cloned()
+            .collect();
+        for tip in stale {
+            self.tips.remove(&tip);
+            let mut cursor = tip;
+            while !self.is_in_best_chain(&cursor) {
+                let node = self.nodes.remove(&cursor).expect("fork headers are stored");
+                cursor = node.header.prev_blockhash;
+                let parent = self
+                    .nodes
+                    .get_mut(&cursor)
+                    .expect("fork headers are stored");
+                parent.children -= 1;
+                if parent.children > 0 {
+                    break;
+                }
+            }
+        }
+    }
+
+    fn tip_node(&self) -> &HeaderNode {
+        &self.nodes[&self.tip()]
+    }
+
+    /// Hash of the best tip
+    pub fn tip(&self) -> BlockHash {
+        *self
+            .best
+            .last()
+            .expect("the root is always in the best chain")
+    }
+
+    /// Height of the best tip
+    pub fn height(&self) -> u32 {
+        self.root_height + self.best.len() as u32 - 1
+    }
+
+    /// Height of the root
+    pub fn root_height(&self) -> u32 {
+        self.root_height
+    }
+
+    /// Cumulative work of the best chain
+    pub fn chainwork(&self) -> Uint256 {
+        self.tip_node().chainwork
+    }
+
+    /// Hash of the best-chain header at `height`
+    pub fn hash_at_height(&self, height: u32) -> Option<BlockHash> {
+        let index = height.checked_sub(self.root_height)?;
+        self.best.get(index as usize).cloned()
+    }
+
+    /// Whether the header with hash `hash` is in the best chain
+    pub fn is_in_best_chain(&self, hash: &BlockHash) -> bool {
+        match self.nodes.get(hash) {
+            Some(node) => self.best.get((node.height - self.root_height) as usize) == Some(hash),
+            None => false,
+        }
+    }
+
+    /// Look up any stored header, on the best chain or a fork
+    pub fn get(&self, hash: &BlockHash) -> Option<&HeaderNode> {
+        self.nodes.get(hash)
+    }
+
+    /// Whether the header with hash `hash` is stored
+    pub fn contains(&self, hash: &BlockHash) -> bool {
+        self.nodes.contains_key(hash)
+    }
+
+    /// Number of stored headers, on the best chain and on forks
+    pub fn header_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Hashes of all stored headers with no children, the best tip among them
+    pub fn tips(&self) -> Vec<BlockHash> {
+        self.tips.iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use hashes::Hash;
+    use testutil::mine_header;
+
+    /// Mine `n` headers on top of `prev`, returning their hashes
+    fn extend(chain: &mut HeaderChain, mut prev: BlockHash, n: u32, tag: u32) -> Vec<BlockHash> {
+        let mut ret = vec![];
+        for i in 0..n {
+            let header = mine_header(prev, 1, tag * 1000 + i);
+            chain.add_header(header).unwrap();
+            prev = header.block_hash();
+            ret.push(prev);
+        }
+        ret
+    }
+
+    fn root() -> BlockHeader {
+        mine_header(Default::default(), 1, 0)
+    }
+
+    #[test]
+    fn extend_and_fork() {
+        let root = root();
+        let mut chain = HeaderChain::new(root);
+        let main = extend(&mut chain, root.block_hash(), 3, 1);
+        assert_eq!(chain.height(), 3);
+        assert_eq!(chain.tip(), main[2]);
+        assert_eq!(chain.chainwork(), Uint256::from_u64(8));
+        assert_eq!(chain.hash_at_height(0), Some(root.block_hash()));
+        assert_eq!(chain.hash_at_height(2), Some(main[1]));
+
+        // Already known
+        let header = chain.get(&main[0]).unwrap().header;
+        assert_eq!(chain.add_header(header), Ok(HeaderStatus::AlreadyKnown));
+
+        // A fork with equal work does not replace the tip seen first
+        let fork = extend(&mut chain, main[0], 2, 2);
+        assert_eq!(chain.tip(), main[2]);
+        assert!(!chain.is_in_best_chain(&fork[1]));
+        assert_eq!(chain.tips().len(), 2);
+        assert_eq!(chain.header_count(), 6);
+
+        // One more block makes it the best chain
+        let header = mine_header(fork[1], 1, 3);
+        assert_eq!(
+            chain.add_header(header),
+            Ok(HeaderStatus::Reorg {
+                fork_height: 1,
+                disconnected: vec![main[2], main[1]],
+            })
+        );
+        assert_eq!(chain.tip(), header.block_hash());
+        assert_eq!(chain.height(), 4);
+        assert!(chain.is_in_best_chain(&fork[0]));
+        assert!(chain.is_in_best_chain(&main[0]));
+        assert!(!chain.is_in_best_chain(&main[1]));
+        assert_eq!(chain.hash_at_height(2), Some(fork[0]));
+
+        // Extending the old chain is now a fork
+        assert_eq!(
+            chain.add_header(mine_header(main[2], 1, 4)),
+            Ok(HeaderStatus::Fork)
+        );
+        assert_eq!(
+            chain.add_header(mine_header(header.block_hash(), 1, 5)),
+            Ok(HeaderStatus::Extended)
+        );
+    }
+
+    #[test]
+    fn rejected_headers() {
+        let root = root();
+        let mut chain = HeaderChain::new(root);
+
+        let orphan = mine_header(BlockHash::hash(&[1]), 1, 1);
+        assert_eq!(
+            chain.add_header(orphan),
+            Err(Error::UnknownPrevBlock(orphan.prev_blockhash))
+        );
+
+        let mut bad = mine_header(root.block_hash(), 1, 2);
+        while bad.has_valid_pow() {
+            bad.nonce += 1;
+        }
+        assert_eq!(
+            chain.add_header(bad),
+            Err(Error::BadProofOfWork(bad.block_hash()))
+        );
+        assert_eq!(chain.header_count(), 1);
+    }
+
+    #[test]
+    fn checkpoints() {
+        let root = root();
+        let checkpoint = Checkpoint {
+            height: 1000,
+            hash: root.block_hash(),
+            chainwork: Some(Uint256::from_u64(5000)),
+        };
+        let wrong = mine_header(Default::default(), 1, 1);
+        assert_eq!(
+            HeaderChain::from_checkpoint(wrong, &checkpoint).err(),
+            Some(Error::CheckpointMismatch(1000, wrong.block_hash()))
+        );
+
+        let mut chain = HeaderChain::from_checkpoint(root, &checkpoint).unwrap();
+        assert_eq!(chain.root_height(), 1000);
+        assert_eq!(chain.height(), 1000);
+        assert_eq!(chain.chainwork(), Uint256::from_u64(5000));
+        let main = extend(&mut chain, root.block_hash(), 2, 1);
+        assert_eq!(chain.height(), 1002);
+        assert_eq!(chain.get(&main[1]).unwrap().height, 1002);
+        assert_eq!(chain.chainwork(), Uint256::from_u64(5004));
+        assert_eq!(chain.hash_at_height(1001), Some(main[0]));
+        assert_eq!(chain.hash_at_height(999), None);
+
+        // Reorgs work above a checkpointed root
+        let fork = extend(&mut chain, root.block_hash(), 2, 2);
+        let header = mine_header(fork[1], 1, 3);
+        assert_eq!(
+            chain.add_header(header),
+            Ok(HeaderStatus::Reorg {
+                fork_height: 1000,
+                disconnected: vec![main[1], main[0]],
+            })
+        );
+        assert_eq!(chain.hash_at_height(1001), Some(fork[0]));
+
+        // A header at a checkpointed height must match, even on a fork
+        let expected = mine_header(header.block_hash(), 1, 4);
+        let mut chain = chain.with_checkpoints(
+            vec![Checkpoint {
+                height: 1004,
+                hash: expected.block_hash(),
+                chainwork: None,
+            }]
+            .into_iter()
+            .collect(),
+        );
+        assert_eq!(chain.checkpoints().len(), 2);
+        let other = mine_header(header.block_hash(), 1, 5);
+        assert_eq!(
+            chain.add_header(other),
+            Err(Error::CheckpointMismatch(1004, other.block_hash()))
+        );
+        let stale = mine_header(main[1], 1, 6);
+        assert_eq!(chain.add_header(stale), Ok(HeaderStatus::Fork));
+        let other = mine_header(stale.block_hash(), 1, 7);
+        assert_eq!(
+            chain.add_header(other),
+            Err(Error::CheckpointMismatch(1004, other.block_hash()))
+        );
+        assert_eq!(chain.add_header(expected), Ok(HeaderStatus::Extended));
+        assert_eq!(chain.height(), 1004);
+    }
+
+    #[test]
+    fn prune_stale_forks() {
+        let root = root();
+        let mut chain = HeaderChain::new(root).with_prune_depth(2);
+        let main = extend(&mut chain, root.block_hash(), 2, 1);
+        let fork = extend(&mut chain, main[0], 1, 2);
+        let nested = extend(&mut chain, main[0], 2, 3);
+        let branch = extend(&mut chain, nested[0], 1, 4);
+        // nested overtook main
+        assert_eq!(chain.tip(), nested[1]);
+        assert_eq!(chain.header_count(), 7);
+
+        // At height 4 the fork and old main (tips at height 2) are still in range
+        let tail = extend(&mut chain, nested[1], 1, 5);
+        assert_eq!(chain.header_count(), 8);
+        assert!(chain.contains(&fork[0]));
+
+        // At height 5 they are dropped, but the branch at height 3 is not
+        extend(&mut chain, tail[0], 1, 6);
+        assert!(!chain.contains(&fork[0]));
+        assert!(!chain.contains(&main[1]));
+        assert!(chain.contains(&main[0]));
+        assert!(chain.contains(&branch[0]));
+        assert_eq!(chain.header_count(), 7);
+        assert_eq!(chain.tips().len(), 2);
+
+        // Headers on a pruned fork are orphans
+        assert_eq!(
+            chain.add_header(mine_header(fork[0], 1, 7)),
+            Err(Error::UnknownPrevBlock(fork[0]))
+        );
+
+        // Without pruning nothing is dropped
+        let mut chain = HeaderChain::new(root);
+        let main = extend(&mut chain, root.block_hash(), 1, 1);
+        extend(&mut chain, root.block_hash(), 1, 2);
+        extend(&mut chain, main[0], 10, 3);
+        assert_eq!(chain.prune_depth(), None);
+        assert_eq!(chain.header_count(), 13);
+    }
+}
@@ -3,4 +3,16 @@
 //! This module defines structures and functions for storing blocks and
 //! transactions which make up the Bitcoin system.
 
+pub mod block;
+pub mod checkpoints;
+pub mod corenotify;
+pub mod events;
+pub mod explain;
+pub mod fixedscript;
+pub mod headerchain;
 pub mod opcodes;
+pub mod script;
+pub mod transaction;
+pub mod undo;
+pub mod utxo;
+pub mod verify;
@@ -12,6 +12,7 @@
 use serde;
 
 use std::fmt;
+use std::str::FromStr;
 
 /// A script opcode
 #[derive(Copy, Clone, PartialEq, Eq)]
@@ -674,12 +675,41 @@ impl All {
         Class::Ordinary(Ordinary::try_from_all(*self).unwrap())
     }
 
+    /// Converts from u8. Every byte is an opcode, if only one which fails
+    /// the script.
+    #[inline]
+    pub const fn from_u8(b: u8) -> All {
+        All { code: b }
+    }
+
     /// Converts to u8
     #[inline]
-    pub fn into_u8(&self) -> u8 {
+    pub const fn into_u8(&self) -> u8 {
         self.code
     }
 
+    /// The group this opcode is listed in, by what it operates on
+    pub fn category(&self) -> Category {
+        match self.code {
+            0x00...0x60 => Category::Push,
+            0x61...0x6a => Category::Control,
+            0x6b...0x7d => Category::Stack,
+            0x7e...0x82 => Category::Splice,
+            0x83...0x8a => Category::Bitwise,
+            0x8b...0xa5 => Category::Arithmetic,
+            0xa6...0xaf | 0xba => Category::Crypto,
+            0xb0...0xb9 => Category::Expansion,
+            _ => Category::Invalid,
+        }
+    }
+
+    /// Whether this opcode was disabled for being unsafe, so that it fails
+    /// the script even in an unexecuted branch. `OP_VERIF` and `OP_VERNOTIF`
+    /// fail the same way but are reserved rather than disabled.
+    pub fn is_disabled(&self) -> bool {
+        self.is_illegal_op() && *self != all::OP_VERIF && *self != all::OP_VERNOTIF
+    }
+
     /// Indicates whether this opcode fits in the IllegalOp class
     fn is_illegal_op(&self) -> bool {
         *self == all::OP_VERIF
@@ -729,8 +759,27 @@ impl From<u8> for All {
     }
 }
 
+impl From<All> for u8 {
+    #[inline]
+    fn from(op: All) -> u8 {
+        op.code
+    }
+}
+
 display_from_debug!(All);
 
+/// Parses an opcode as it is displayed, such as `OP_CHECKSIG`
+impl FromStr for All {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<All, ()> {
+        (0..=0xff_u8)
+            .map(All::from)
+            .find(|op| op.to_string() == s)
+            .ok_or(())
+    }
+}
+
 #[cfg(feature = "serde")]
 impl serde::Serialize for All {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
@@ -750,6 +799,54 @@ pub static OP_NOP2: All = all::OP_CLTV;
 /// previously called OP_NOP3
 pub static OP_NOP3: All = all::OP_CSV;
 
+#[cfg(feature = "experimental-opcodes")]
+pub mod experimental {
+    //! Proposed opcodes, for prototyping scripts which use them.
+    //!
+    //! **None of these are consensus rules.** Each proposal redefines an
+    //! existing opcode, and until it activates that opcode keeps its current
+    //! meaning on the network: scripts relying on the new one can be spent by
+    //! anyone, or not at all. Parsing, `classify` and formatting keep
+    //! following consensus, so these opcodes show up under their current
+    //! names.
+    use super::{all, All};
+
+    /// Fail unless the spending transaction matches the 32-byte template
+    /// hash on top of the stack, per BIP119. Redefines `OP_NOP4`.
+    pub const OP_CHECKTEMPLATEVERIFY: All = all::OP_NOP4;
+    /// Synonym for OP_CHECKTEMPLATEVERIFY
+    pub const OP_CTV: All = OP_CHECKTEMPLATEVERIFY;
+    /// Concatenate the top two stack items, per BIP347, in tapscript only.
+    /// Re-enables the disabled `OP_CAT`.
+    pub const OP_CAT: All = all::OP_CAT;
+}
+
+/// The groups of the opcode table, following Bitcoin Core's
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Category {
+    /// Push data or a number, or `OP_RESERVED` among them
+    Push,
+    /// Flow control, with `OP_VER`, `OP_VERIF` and `OP_VERNOTIF`
+    Control,
+    /// Stack manipulation
+    Stack,
+    /// Byte string operations, all disabled but `OP_SIZE`
+    Splice,
+    /// Bitwise logic and equality, with `OP_RESERVED1` and `OP_RESERVED2`
+    Bitwise,
+    /// Arithmetic and numeric comparison
+    Arithmetic,
+    /// Hashes and signature checks, with `OP_CHECKSIGADD` of tapscript
+    Crypto,
+    /// The NOPs reserved for soft forks, two of which are now the lock time
+    /// checks
+    Expansion,
+    /// Bytes which are not opcodes
+    Invalid,
+}
+
+display_from_debug!(Category);
+
 /// Broad categories of opcodes grouped by those with similar behavior
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub enum Class {
@@ -846,10 +943,24 @@ mod tests {
             let s2 = format!("{:?}", all::$op);
             assert_eq!(s1, s2);
             assert_eq!(s1, stringify!($op));
+            assert_eq!(s1.parse::<All>(), Ok(all::$op));
             assert!($unique.insert(s1));
         };
     }
 
+    #[test]
+    #[cfg(feature = "experimental-opcodes")]
+    fn experimental_opcodes() {
+        assert_eq!(experimental::OP_CHECKTEMPLATEVERIFY.into_u8(), 0xb3);
+        assert_eq!(experimental::OP_CTV, experimental::OP_CHECKTEMPLATEVERIFY);
+        assert_eq!(experimental::OP_CAT.into_u8(), 0x7e);
+
+        // Consensus meanings are unchanged
+        assert_eq!(experimental::OP_CTV.classify(), Class::NoOp);
+        assert_eq!(experimental::OP_CAT.classify(), Class::IllegalOp);
+        assert_eq!(format!("{:?}", experimental::OP_CTV), "OP_NOP4");
+    }
+
     #[test]
     fn str_roundtrip() {
         let mut unique = HashSet::new();
@@ -1181,4 +1292,42 @@ mod tests {
             C::PushBytes(all::OP_PUSHBYTES_75.code as u32)
         );
     }
+
+    #[test]
+    fn categories() {
+        assert_eq!(all::OP_PUSHBYTES_0.category(), Category::Push);
+        assert_eq!(all::OP_PUSHDATA4.category(), Category::Push);
+        assert_eq!(all::OP_PUSHNUM_16.category(), Category::Push);
+        assert_eq!(all::OP_NOP.category(), Category::Control);
+        assert_eq!(all::OP_RETURN.category(), Category::Control);
+        assert_eq!(all::OP_TOALTSTACK.category(), Category::Stack);
+        assert_eq!(all::OP_TUCK.category(), Category::Stack);
+        assert_eq!(all::OP_SIZE.category(), Category::Splice);
+        assert_eq!(all::OP_EQUALVERIFY.category(), Category::Bitwise);
+        assert_eq!(all::OP_WITHIN.category(), Category::Arithmetic);
+        assert_eq!(all::OP_CHECKMULTISIGVERIFY.category(), Category::Crypto);
+        assert_eq!(all::OP_RETURN_186.category(), Category::Crypto);
+        assert_eq!(all::OP_CSV.category(), Category::Expansion);
+        assert_eq!(all::OP_RETURN_187.category(), Category::Invalid);
+        assert_eq!(all::OP_RETURN_255.category(), Category::Invalid);
+
+        let disabled: Vec<_> = (0..=0xff_u8)
+            .map(All::from_u8)
+            .filter(All::is_disabled)
+            .collect();
+        assert_eq!(disabled.len(), 15);
+        assert!(disabled.contains(&all::OP_CAT));
+        assert!(disabled.contains(&all::OP_RSHIFT));
+        assert!(!all::OP_VERIF.is_disabled());
+        assert!(!all::OP_SIZE.is_disabled());
+        for op in disabled {
+            assert_eq!(op.classify(), Class::IllegalOp);
+        }
+
+        assert_eq!(u8::from(all::OP_CHECKSIG), 0xac);
+        assert_eq!(All::from_u8(0xac), all::OP_CHECKSIG);
+        assert_eq!("OP_CHECKSIG".parse::<All>(), Ok(all::OP_CHECKSIG));
+        assert_eq!("CHECKSIG".parse::<All>(), Err(()));
+        assert_eq!("OP_RETURN_186".parse::<All>(), Ok(all::OP_RETURN_186));
+    }
 }
@@ -0,0 +1,1459 @@
+//! Script
+//!
+//! Scripts define Bitcoin's digital signature scheme: a signature is formed
+//! from a script (the second half of which is defined by a coin to be spent,
+//! and the first half provided by the spending transaction), and is valid
+//! iff the script leaves `TRUE` on the stack after being evaluated.
+//! Bitcoin's script is a stack-based assembly language similar in spirit to
+//! Forth.
+//!
+//! This module provides the structures and functions needed to support scripts.
+
+use std::convert::TryFrom;
+use std::default::Default;
+use std::{error, fmt, io};
+
+use hashes::hex::{self, FromHex};
+use hashes::Hash;
+
+use blockdata::opcodes;
+use consensus::encode::{self, Decodable, Encodable, VarInt, WriteExt};
+use error::{ErrorCode, ErrorKind};
+use hash_types::{PubkeyHash, ScriptHash, TapLeafHash, WPubkeyHash, WScriptHash};
+use network::constants::Network;
+use util::address::{Address, AddressType, WitnessVersion};
+
+/// A Bitcoin script
+#[derive(Clone, Default, PartialOrd, Ord, PartialEq, Eq, Hash)]
+pub struct Script(Box<[u8]>);
+
+impl AsRef<[u8]> for Script {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Script {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Script(")?;
+        self.fmt_asm(f)?;
+        f.write_str(")")
+    }
+}
+
+display_from_debug!(Script);
+
+impl fmt::LowerHex for Script {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for &ch in self.0.iter() {
+            write!(f, "{:02x}", ch)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::UpperHex for Script {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for &ch in self.0.iter() {
+            write!(f, "{:02X}", ch)?;
+        }
+        Ok(())
+    }
+}
+
+impl hex::FromHex for Script {
+    fn from_byte_iter<I>(iter: I) -> Result<Self, hex::Error>
+    where
+        I: Iterator<Item = Result<u8, hex::Error>> + ExactSizeIterator + DoubleEndedIterator,
+    {
+        Vec::from_byte_iter(iter).map(|v| Script(v.into_boxed_slice()))
+    }
+}
+
+impl ::std::str::FromStr for Script {
+    type Err = hex::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        hex::FromHex::from_hex(s)
+    }
+}
+
+/// Displays a script as assembly annotated with the address it pays to,
+/// as returned by `Script::fmt_asm_with_network`
+#[derive(Copy, Clone, Debug)]
+pub struct AsmWithNetwork<'a> {
+    script: &'a Script,
+    network: Network,
+}
+
+impl<'a> fmt::Display for AsmWithNetwork<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.script.fmt_asm(f)?;
+        if let Some(address) = Address::from_script(self.script, self.network) {
+            write!(f, " <address: {}>", address)
+        } else if self.script.is_p2pk() {
+            let pubkey = &self.script.0[1..self.script.len() - 1];
+            write!(f, " <pubkey hash: {}>", PubkeyHash::hash(pubkey))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// An object which can be used to construct a script piece by piece
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Builder(Vec<u8>, Option<opcodes::All>);
+
+display_from_debug!(Builder);
+
+/// The largest element that may be pushed onto the stack
+pub const MAX_SCRIPT_ELEMENT_SIZE: usize = 520;
+
+/// Data which fits in a stack element, of at most `MAX_SCRIPT_ELEMENT_SIZE`
+/// bytes. Pushing more fails the script whether or not the push is
+/// executed.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct PushBytes<'a>(&'a [u8]);
+
+impl<'a> PushBytes<'a> {
+    /// The empty push
+    pub fn empty() -> PushBytes<'static> {
+        PushBytes(&[])
+    }
+
+    /// The data pushed
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.0
+    }
+
+    /// The length of the data
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Whether there is no data
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for PushBytes<'a> {
+    type Error = Error;
+
+    fn try_from(data: &'a [u8]) -> Result<PushBytes<'a>, Error> {
+        if data.len() > MAX_SCRIPT_ELEMENT_SIZE {
+            return Err(Error::PushTooLarge(data.len()));
+        }
+        Ok(PushBytes(data))
+    }
+}
+
+impl<'a> AsRef<[u8]> for PushBytes<'a> {
+    fn as_ref(&self) -> &[u8] {
+        self.0
+    }
+}
+
+/// Ways that a script might fail. Not everything is split up as
+/// much as it could be; patches welcome if more detailed errors
+/// would help you.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum Error {
+    /// Something did a non-minimal push; for more information see
+    /// `https://github.com/bitcoin/bips/blob/master/bip-0062.mediawiki#Push_operators`
+    NonMinimalPush,
+    /// Some opcode expected a parameter, but it was missing or truncated
+    EarlyEndOfScript,
+    /// Tried to read an array off the stack as a number when it was more than 4 bytes
+    NumericOverflow,
+    /// Tried to push more than `MAX_SCRIPT_ELEMENT_SIZE` bytes
+    PushTooLarge(usize),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::PushTooLarge(len) => write!(
+                f,
+                "push of {} bytes exceeds the {}-byte limit",
+                len, MAX_SCRIPT_ELEMENT_SIZE
+            ),
+            _ => f.write_str(error::Error::description(self)),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn cause(&self) -> Option<&error::Error> {
+        None
+    }
+
+    fn description(&self) -> &'static str {
+        match *self {
+            Error::NonMinimalPush => "non-minimal datapush",
+            Error::EarlyEndOfScript => "unexpected end of script",
+            Error::NumericOverflow => "numeric overflow (number on stack larger than 4 bytes)",
+            Error::PushTooLarge(..) => "push too large",
+        }
+    }
+}
+
+impl ErrorCode for Error {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Script
+    }
+
+    fn number(&self) -> u32 {
+        match *self {
+            Error::NonMinimalPush => 1,
+            Error::EarlyEndOfScript => 2,
+            Error::NumericOverflow => 3,
+            Error::PushTooLarge(..) => 4,
+        }
+    }
+}
+
+/// An error parsing the assembly of a script
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum ParseAsmError {
+    /// A word is not an opcode, a number or hex data
+    UnknownWord(String),
+    /// A push opcode is not followed by hex data of the length it pushes
+    BadPushData(String),
+    /// A number is outside the range a script number can hold
+    NumberOutOfRange(String),
+}
+
+impl fmt::Display for ParseAsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseAsmError::UnknownWord(ref w) => write!(f, "unknown word in script asm: {}", w),
+            ParseAsmError::BadPushData(ref w) => write!(f, "bad data for {} in script asm", w),
+            ParseAsmError::NumberOutOfRange(ref w) => {
+                write!(f, "number out of range in script asm: {}", w)
+            }
+        }
+    }
+}
+
+impl error::Error for ParseAsmError {
+    fn cause(&self) -> Option<&error::Error> {
+        None
+    }
+
+    fn description(&self) -> &'static str {
+        match *self {
+            ParseAsmError::UnknownWord(..) => "unknown word in script asm",
+            ParseAsmError::BadPushData(..) => "bad push data in script asm",
+            ParseAsmError::NumberOutOfRange(..) => "number out of range in script asm",
+        }
+    }
+}
+
+impl ErrorCode for ParseAsmError {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::ScriptAsm
+    }
+
+    fn number(&self) -> u32 {
+        match *self {
+            ParseAsmError::UnknownWord(..) => 1,
+            ParseAsmError::BadPushData(..) => 2,
+            ParseAsmError::NumberOutOfRange(..) => 3,
+        }
+    }
+}
+
+/// Look up an opcode by the name `fmt_asm` gives it, with or without the
+/// `OP_` prefix, or by one of the names Bitcoin Core uses
+fn opcode_from_name(name: &str) -> Option<opcodes::All> {
+    let name = if name.starts_with("OP_") {
+        name.to_owned()
+    } else {
+        format!("OP_{}", name)
+    };
+    let code = match &name[..] {
+        "OP_0" | "OP_FALSE" => 0x00,
+        "OP_TRUE" => opcodes::OP_TRUE.into_u8(),
+        "OP_1NEGATE" => opcodes::all::OP_PUSHNUM_NEG1.into_u8(),
+        "OP_CHECKLOCKTIMEVERIFY" => opcodes::all::OP_CLTV.into_u8(),
+        "OP_CHECKSEQUENCEVERIFY" => opcodes::all::OP_CSV.into_u8(),
+        "OP_CHECKSIGADD" => opcodes::all::OP_RETURN_186.into_u8(),
+        _ => {
+            if name.len() > 3 && name[3..].bytes().all(|b| b.is_ascii_digit()) {
+                match name[3..].parse::<u8>() {
+                    Ok(n) if n >= 1 && n <= 16 => return Some(opcodes::All::from(0x50 + n)),
+                    _ => return None,
+                }
+            }
+            return name.parse().ok();
+        }
+    };
+    Some(opcodes::All::from(code))
+}
+
+/// Helper to encode an integer in script format
+fn build_scriptint(n: i64) -> Vec<u8> {
+    if n == 0 {
+        return vec![];
+    }
+
+    let neg = n < 0;
+
+    let mut abs = if neg { -n } else { n } as usize;
+    let mut v = vec![];
+    while abs > 0xFF {
+        v.push((abs & 0xFF) as u8);
+        abs >>= 8;
+    }
+    // If the number's value causes the sign bit to be set, we need an extra
+    // byte to get the correct value and correct sign bit
+    if abs & 0x80 != 0 {
+        v.push(abs as u8);
+        v.push(if neg { 0x80u8 } else { 0u8 });
+    }
+    // Otherwise we just set the sign bit ourselves
+    else {
+        abs |= if neg { 0x80 } else { 0 };
+        v.push(abs as u8);
+    }
+    v
+}
+
+/// Helper to decode an integer in script format
+/// Notice that this fails on overflow: the result is the same as in
+/// bitcoind, that only 4-byte signed-magnitude values may be read as
+/// numbers. They can be added or subtracted (and a long time ago,
+/// multiplied and divided), and this may result in numbers which
+/// can't be written out in 4 bytes or less. This is ok! The number
+/// just can't be read as a number again.
+/// This is a bit crazy and subtle, but it makes sense: you can load
+/// 32-bit numbers and do anything with them, which back when mult/div
+/// was allowed, could result in up to a 64-bit number. We don't want
+/// overflow since that's surprising --- and we don't want numbers that
+/// don't fit in 64 bits (for efficiency on modern processors) so we
+/// simply say, anything in excess of 32 bits is no longer a number.
+/// This is basically a ranged type implementation.
+pub fn read_scriptint(v: &[u8]) -> Result<i64, Error> {
+    let len = v.len();
+    if len == 0 {
+        return Ok(0);
+    }
+    if len > 4 {
+        return Err(Error::NumericOverflow);
+    }
+
+    let (mut ret, sh) = v
+        .iter()
+        .fold((0, 0), |(acc, sh), n| (acc + ((*n as i64) << sh), sh + 8));
+    if v[len - 1] & 0x80 != 0 {
+        ret &= (1 << (sh - 1)) - 1;
+        ret = -ret;
+    }
+    Ok(ret)
+}
+
+/// This is like "`read_scriptint` then map 0 to false and everything
+/// else as true", except that the overflow rules don't apply.
+#[inline]
+pub fn read_scriptbool(v: &[u8]) -> bool {
+    !(v.len() == 0
+        || ((v[v.len() - 1] == 0 || v[v.len() - 1] == 0x80)
+            && v.iter().rev().skip(1).all(|&w| w == 0)))
+}
+
+/// Read a script-encoded unsigned integer
+pub fn read_uint(data: &[u8], size: usize) -> Result<usize, Error> {
+    if data.len() < size {
+        Err(Error::EarlyEndOfScript)
+    } else {
+        let mut ret = 0;
+        for (i, item) in data.iter().take(size).enumerate() {
+            ret += (*item as usize) << (i * 8);
+        }
+        Ok(ret)
+    }
+}
+
+impl Script {
+    /// Creates a new empty script
+    pub fn new() -> Script {
+        Script(vec![].into_boxed_slice())
+    }
+
+    /// The length in bytes of the script
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Whether the script is the empty script
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns the script data
+    pub fn as_bytes(&self) -> &[u8] {
+        &*self.0
+    }
+
+    /// Returns a copy of the script data
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.0.clone().into_vec()
+    }
+
+    /// Convert the script into a byte vector
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.0.into_vec()
+    }
+
+    /// Generates P2PKH-type of scriptPubkey
+    pub fn new_p2pkh(pubkey_hash: &PubkeyHash) -> Script {
+        Builder::new()
+            .push_opcode(opcodes::all::OP_DUP)
+            .push_opcode(opcodes::all::OP_HASH160)
+            .push_slice(&pubkey_hash[..])
+            .push_opcode(opcodes::all::OP_EQUALVERIFY)
+            .push_opcode(opcodes::all::OP_CHECKSIG)
+            .into_script()
+    }
+
+    /// Generates P2SH-type of scriptPubkey with a given hash of the redeem script
+    pub fn new_p2sh(script_hash: &ScriptHash) -> Script {
+        Builder::new()
+            .push_opcode(opcodes::all::OP_HASH160)
+            .push_slice(&script_hash[..])
+            .push_opcode(opcodes::all::OP_EQUAL)
+            .into_script()
+    }
+
+    /// Generates P2WPKH-type of scriptPubkey
+    pub fn new_v0_wpkh(pubkey_hash: &WPubkeyHash) -> Script {
+        Builder::new()
+            .push_int(0)
+            .push_slice(&pubkey_hash[..])
+            .into_script()
+    }
+
+    /// Generates P2WSH-type of scriptPubkey with a given hash of the redeem script
+    pub fn new_v0_wsh(script_hash: &WScriptHash) -> Script {
+        Builder::new()
+            .push_int(0)
+            .push_slice(&script_hash[..])
+            .into_script()
+    }
+
+    /// Returns the hash160 of the script, as committed to by a P2SH output
+    pub fn script_hash(&self) -> ScriptHash {
+        ScriptHash::from_script(self)
+    }
+
+    /// Returns the sha256 of the script, as committed to by a P2WSH output
+    pub fn wscript_hash(&self) -> WScriptHash {
+        WScriptHash::from_script(self)
+    }
+
+    /// Returns the hash of the script as a taproot leaf of the given leaf
+    /// version
+    pub fn tapleaf_hash(&self, leaf_version: u8) -> TapLeafHash {
+        TapLeafHash::from_script(self, leaf_version)
+    }
+
+    /// Compute the P2SH output corresponding to this redeem script
+    pub fn to_p2sh(&self) -> Script {
+        Script::new_p2sh(&self.script_hash())
+    }
+
+    /// Compute the P2WSH output corresponding to this witness script
+    pub fn to_v0_p2wsh(&self) -> Script {
+        Script::new_v0_wsh(&self.wscript_hash())
+    }
+
+    /// The type of address this script pubkey pays to, or `None` if it has
+    /// no standard address
+    pub fn address_type(&self) -> Option<AddressType> {
+        if self.is_p2pkh() {
+            Some(AddressType::P2pkh)
+        } else if self.is_p2sh() {
+            Some(AddressType::P2sh)
+        } else if self.is_v0_p2wpkh() {
+            Some(AddressType::P2wpkh)
+        } else if self.is_v0_p2wsh() {
+            Some(AddressType::P2wsh)
+        } else if self.is_v1_p2tr() {
+            Some(AddressType::P2tr)
+        } else {
+            None
+        }
+    }
+
+    /// Checks whether a script pubkey is a p2sh output
+    #[inline]
+    pub fn is_p2sh(&self) -> bool {
+        self.0.len() == 23
+            && self.0[0] == opcodes::all::OP_HASH160.into_u8()
+            && self.0[1] == opcodes::all::OP_PUSHBYTES_20.into_u8()
+            && self.0[22] == opcodes::all::OP_EQUAL.into_u8()
+    }
+
+    /// Checks whether a script pubkey is a p2pkh output
+    #[inline]
+    pub fn is_p2pkh(&self) -> bool {
+        self.0.len() == 25
+            && self.0[0] == opcodes::all::OP_DUP.into_u8()
+            && self.0[1] == opcodes::all::OP_HASH160.into_u8()
+            && self.0[2] == opcodes::all::OP_PUSHBYTES_20.into_u8()
+            && self.0[23] == opcodes::all::OP_EQUALVERIFY.into_u8()
+            && self.0[24] == opcodes::all::OP_CHECKSIG.into_u8()
+    }
+
+    /// Checks whether a script pubkey is a p2pk output
+    #[inline]
+    pub fn is_p2pk(&self) -> bool {
+        (self.0.len() == 67
+            && self.0[0] == opcodes::all::OP_PUSHBYTES_65.into_u8()
+            && self.0[66] == opcodes::all::OP_CHECKSIG.into_u8())
+            || (self.0.len() == 35
+                && self.0[0] == opcodes::all::OP_PUSHBYTES_33.into_u8()
+                && self.0[34] == opcodes::all::OP_CHECKSIG.into_u8())
+    }
+
+    /// Checks whether a script pubkey is a Segregated Witness (segwit) program.
+    #[inline]
+    pub fn is_witness_program(&self) -> bool {
+        // A scriptPubKey (or redeemScript as defined in BIP16/P2SH) that consists of a 1-byte
+        // push opcode (for 0 to 16) followed by a data push between 2 and 40 bytes gets a new
+        // special meaning. The value of the first push is called the "version byte". The
+        // following byte vector pushed is called the "witness program".
+        let min_vernum: u8 = opcodes::all::OP_PUSHNUM_1.into_u8();
+        let max_vernum: u8 = opcodes::all::OP_PUSHNUM_16.into_u8();
+        self.0.len() >= 4
+            && self.0.len() <= 42
+            // Version 0 or PUSHNUM_1-PUSHNUM_16
+            && (self.0[0] == 0 || self.0[0] >= min_vernum && self.0[0] <= max_vernum)
+            // Second byte push opcode 2-40 bytes
+            && self.0[1] >= opcodes::all::OP_PUSHBYTES_2.into_u8()
+            && self.0[1] <= opcodes::all::OP_PUSHBYTES_40.into_u8()
+            // Check that the rest of the script has the correct size
+            && self.0.len() - 2 == self.0[1] as usize
+    }
+
+    /// The version of the witness program, if the script is one
+    pub fn witness_version(&self) -> Option<WitnessVersion> {
+        if self.is_witness_program() {
+            WitnessVersion::from_opcode(opcodes::All::from(self.0[0])).ok()
+        } else {
+            None
+        }
+    }
+
+    /// Checks whether a script pubkey is a p2wsh output
+    #[inline]
+    pub fn is_v0_p2wsh(&self) -> bool {
+        self.0.len() == 34
+            && self.0[0] == opcodes::all::OP_PUSHBYTES_0.into_u8()
+            && self.0[1] == opcodes::all::OP_PUSHBYTES_32.into_u8()
+    }
+
+    /// Checks whether a script pubkey is a p2wpkh output
+    #[inline]
+    pub fn is_v0_p2wpkh(&self) -> bool {
+        self.0.len() == 22
+            && self.0[0] == opcodes::all::OP_PUSHBYTES_0.into_u8()
+            && self.0[1] == opcodes::all::OP_PUSHBYTES_20.into_u8()
+    }
+
+    /// Checks whether a script pubkey is a p2tr output
+    #[inline]
+    pub fn is_v1_p2tr(&self) -> bool {
+        self.0.len() == 34
+            && self.0[0] == opcodes::all::OP_PUSHNUM_1.into_u8()
+            && self.0[1] == opcodes::all::OP_PUSHBYTES_32.into_u8()
+    }
+
+    /// Check if this is an OP_RETURN output
+    pub fn is_op_return(&self) -> bool {
+        !self.0.is_empty() && (opcodes::All::from(self.0[0]) == opcodes::all::OP_RETURN)
+    }
+
+    /// Whether a script can be proven to have no satisfying input
+    pub fn is_provably_unspendable(&self) -> bool {
+        !self.0.is_empty()
+            && (opcodes::All::from(self.0[0]).classify() == opcodes::Class::ReturnOp
+                || opcodes::All::from(self.0[0]).classify() == opcodes::Class::IllegalOp)
+    }
+
+    /// Iterate over the script in the form of `Instruction`s, which are an enum covering
+    /// opcodes, datapushes and errors. At most one error will be returned and then the
+    /// iterator will end. To instead iterate over the script as sequence of bytes, treat
+    /// it as a slice using `script[..]` or convert it to a vector using `into_bytes()`.
+    pub fn instructions(&self) -> Instructions<'_> {
+        Instructions {
+            data: &self.0[..],
+            enforce_minimal: false,
+        }
+    }
+
+    /// Iterate over the script in the form of `Instruction`s while enforcing
+    /// minimal pushes.
+    pub fn instructions_minimal(&self) -> Instructions<'_> {
+        Instructions {
+            data: &self.0[..],
+            enforce_minimal: true,
+        }
+    }
+
+    /// Write the assembly decoding of the script to the formatter.
+    pub fn fmt_asm(&self, f: &mut fmt::Write) -> fmt::Result {
+        let mut index = 0;
+        while index < self.0.len() {
+            let opcode = opcodes::All::from(self.0[index]);
+            if index > 0 {
+                f.write_str(" ")?;
+            }
+            index += 1;
+
+            // Write the opcode
+            if opcode == opcodes::all::OP_PUSHBYTES_0 {
+                f.write_str("OP_0")?;
+            } else {
+                write!(f, "{:?}", opcode)?;
+            }
+
+            let data_len = if let opcodes::Class::PushBytes(n) = opcode.classify() {
+                n as usize
+            } else {
+                let push_len = match opcode {
+                    opcodes::all::OP_PUSHDATA1 => 1,
+                    opcodes::all::OP_PUSHDATA2 => 2,
+                    opcodes::all::OP_PUSHDATA4 => 4,
+                    _ => 0,
+                };
+                if push_len > 0 {
+                    match read_uint(&self.0[index..], push_len) {
+                        Ok(n) => {
+                            index += push_len;
+                            n
+                        }
+                        Err(_) => {
+                            f.write_str(" <unexpected end>")?;
+                            break;
+                        }
+                    }
+                } else {
+                    0
+                }
+            };
+
+            // Write any pushdata
+            if data_len > 0 {
+                f.write_str(" ")?;
+                if index + data_len <= self.0.len() {
+                    for ch in &self.0[index..index + data_len] {
+                        write!(f, "{:02x}", ch)?;
+                    }
+                    index += data_len;
+                } else {
+                    f.write_str("<push past end>")?;
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the assembly decoding of the script.
+    pub fn asm(&self) -> String {
+        let mut buf = String::new();
+        self.fmt_asm(&mut buf).unwrap();
+        buf
+    }
+
+    /// Displays the assembly decoding of the script followed by the
+    /// address it pays to on `network`, or the hash of the public key of a
+    /// p2pk script, for reading scripts in logs and debugging output.
+    ///
+    /// For example a p2pkh script shows as
+    /// `OP_DUP OP_HASH160 OP_PUSHBYTES_20 .. OP_EQUALVERIFY OP_CHECKSIG
+    /// <address: 1Ab..>`. Scripts which are neither print as `asm` does.
+    pub fn fmt_asm_with_network(&self, network: Network) -> AsmWithNetwork<'_> {
+        AsmWithNetwork {
+            script: self,
+            network,
+        }
+    }
+
+    /// Parse the assembly of a script, as written by `asm` or by Bitcoin
+    /// Core.
+    ///
+    /// Words are separated by whitespace and may be
+    /// * an opcode name, with or without the `OP_` prefix. A push opcode
+    ///   such as `OP_PUSHBYTES_20` or `OP_PUSHDATA1` is followed by the hex
+    ///   data it pushes, if any, which is encoded exactly as given;
+    /// * a decimal number, pushed as by `Builder::push_int`;
+    /// * hex data, pushed with the smallest push opcode;
+    /// * `0x` followed by hex bytes, which are inserted into the script as
+    ///   they are.
+    pub fn from_asm(asm: &str) -> Result<Script, ParseAsmError> {
+        let mut builder = Builder::new();
+        let mut words = asm.split_whitespace().peekable();
+        while let Some(word) = words.next() {
+            let is_number = {
+                let digits = word.strip_prefix('-').unwrap_or(word);
+                !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit())
+            };
+            if is_number {
+                match word.parse::<i64>() {
+                    Ok(n) if (-0xffffffff..=0xffffffff).contains(&n) => {
+                        builder = builder.push_int(n)
+                    }
+                    _ => return Err(ParseAsmError::NumberOutOfRange(word.to_owned())),
+                }
+            } else if let Some(hex) = word.strip_prefix("0x") {
+                match Vec::<u8>::from_hex(hex) {
+                    Ok(ref bytes) if !bytes.is_empty() => {
+                        builder.0.extend_from_slice(bytes);
+                        builder.1 = None;
+                    }
+                    _ => return Err(ParseAsmError::UnknownWord(word.to_owned())),
+                }
+            } else if let Some(op) = opcode_from_name(word) {
+                let push_len = match op.classify() {
+                    opcodes::Class::PushBytes(n) if n > 0 => Some(n as usize),
+                    _ => match op {
+                        opcodes::all::OP_PUSHDATA1
+                        | opcodes::all::OP_PUSHDATA2
+                        | opcodes::all::OP_PUSHDATA4 => None,
+                        _ => {
+                            builder = builder.push_opcode(op);
+                            continue;
+                        }
+                    },
+                };
+                // `asm` writes nothing after a push of no data
+                let data = match words.peek().map(|w| Vec::<u8>::from_hex(w)) {
+                    Some(Ok(data)) => {
+                        words.next();
+                        data
+                    }
+                    _ => vec![],
+                };
+                let len_bytes = match op {
+                    opcodes::all::OP_PUSHDATA1 if data.len() <= 0xff => 1,
+                    opcodes::all::OP_PUSHDATA2 if data.len() <= 0xffff => 2,
+                    opcodes::all::OP_PUSHDATA4 if data.len() as u64 <= 0xffffffff => 4,
+                    _ if push_len == Some(data.len()) => 0,
+                    _ => return Err(ParseAsmError::BadPushData(word.to_owned())),
+                };
+                builder.0.push(op.into_u8());
+                for i in 0..len_bytes {
+                    builder.0.push((data.len() >> (8 * i)) as u8);
+                }
+                builder.0.extend_from_slice(&data);
+                builder.1 = None;
+            } else {
+                match Vec::<u8>::from_hex(word) {
+                    Ok(data) => builder = builder.push_slice(&data),
+                    Err(_) => return Err(ParseAsmError::UnknownWord(word.to_owned())),
+                }
+            }
+        }
+        Ok(builder.into_script())
+    }
+}
+
+/// Creates a new script from an existing vector
+impl From<Vec<u8>> for Script {
+    fn from(v: Vec<u8>) -> Script {
+        Script(v.into_boxed_slice())
+    }
+}
+
+impl_index_newtype!(Script, u8);
+
+/// A "parsed opcode" which allows iterating over a Script in a more sensible way
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Instruction<'a> {
+    /// Push a bunch of data
+    PushBytes(&'a [u8]),
+    /// Some non-push opcode
+    Op(opcodes::All),
+}
+
+/// Iterator over a script returning parsed opcodes
+pub struct Instructions<'a> {
+    data: &'a [u8],
+    enforce_minimal: bool,
+}
+
+impl<'a> Instructions<'a> {
+    /// Yield a push of `len` bytes which begin `offset` bytes into the
+    /// remaining data, killing the iterator if there aren't enough bytes.
+    fn take_push(&mut self, offset: usize, len: usize) -> Option<Result<Instruction<'a>, Error>> {
+        if self.data.len() < offset + len {
+            // Kill iterator so that it does not return an infinite stream of errors
+            self.data = &[];
+            return Some(Err(Error::EarlyEndOfScript));
+        }
+        let ret = Some(Ok(Instruction::PushBytes(&self.data[offset..offset + len])));
+        self.data = &self.data[offset + len..];
+        ret
+    }
+
+    /// Yield a `OP_PUSHDATAn` push whose length is encoded in `size` bytes,
+    /// which must be at least `min_len` when minimality is enforced.
+    fn take_pushdata(
+        &mut self,
+        size: usize,
+        min_len: usize,
+    ) -> Option<Result<Instruction<'a>, Error>> {
+        let n = match read_uint(&self.data[1..], size) {
+            Ok(n) => n,
+            Err(e) => {
+                self.data = &[];
+                return Some(Err(e));
+            }
+        };
+        if self.enforce_minimal && n < min_len {
+            self.data = &[];
+            return Some(Err(Error::NonMinimalPush));
+        }
+        self.take_push(size + 1, n)
+    }
+}
+
+impl<'a> Iterator for Instructions<'a> {
+    type Item = Result<Instruction<'a>, Error>;
+
+    fn next(&mut self) -> Option<Result<Instruction<'a>, Error>> {
+        if self.data.is_empty() {
+            return None;
+        }
+
+        match opcodes::All::from(self.data[0]).classify() {
+            opcodes::Class::PushBytes(n) => {
+                let n = n as usize;
+                if self.enforce_minimal
+                    && n == 1
+                    && self.data.len() > 1
+                    && (self.data[1] == 0x81 || (self.data[1] > 0 && self.data[1] <= 16))
+                {
+                    self.data = &[];
+                    return Some(Err(Error::NonMinimalPush));
+                }
+                self.take_push(1, n)
+            }
+            opcodes::Class::Ordinary(opcodes::Ordinary::OP_PUSHDATA1) => self.take_pushdata(1, 76),
+            opcodes::Class::Ordinary(opcodes::Ordinary::OP_PUSHDATA2) => {
+                self.take_pushdata(2, 0x100)
+            }
+            opcodes::Class::Ordinary(opcodes::Ordinary::OP_PUSHDATA4) => {
+                self.take_pushdata(4, 0x10000)
+            }
+            // Everything else we can push right through
+            _ => {
+                let ret = Some(Ok(Instruction::Op(opcodes::All::from(self.data[0]))));
+                self.data = &self.data[1..];
+                ret
+            }
+        }
+    }
+}
+
+impl Builder {
+    /// Creates a new empty script
+    pub fn new() -> Self {
+        Builder(vec![], None)
+    }
+
+    /// The length in bytes of the script
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Whether the script is the empty script
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Adds instructions to push an integer onto the stack. Integers are
+    /// encoded as little-endian signed-magnitude numbers, but there are
+    /// dedicated opcodes to push some small integers.
+    pub fn push_int(self, data: i64) -> Builder {
+        // We can special-case -1, 1-16
+        if data == -1 || (data >= 1 && data <= 16) {
+            let opcode = opcodes::All::from((data - 1 + opcodes::OP_TRUE.into_u8() as i64) as u8);
+            self.push_opcode(opcode)
+        }
+        // We can also special-case zero
+        else if data == 0 {
+            self.push_opcode(opcodes::OP_FALSE)
+        }
+        // Otherwise encode it as data
+        else {
+            self.push_scriptint(data)
+        }
+    }
+
+    /// Adds instructions to push an integer onto the stack, using the explicit
+    /// encoding regardless of the availability of dedicated opcodes.
+    pub fn push_scriptint(self, data: i64) -> Builder {
+        self.push_slice(&build_scriptint(data))
+    }
+
+    /// Adds instructions to push data onto the stack, which is known to fit
+    /// in a stack element
+    pub fn push_bytes(self, data: PushBytes) -> Builder {
+        self.push_slice(data.as_bytes())
+    }
+
+    /// Adds instructions to push data onto the stack, failing if it does not
+    /// fit in a stack element
+    pub fn try_push_slice(self, data: &[u8]) -> Result<Builder, Error> {
+        Ok(self.push_bytes(PushBytes::try_from(data)?))
+    }
+
+    /// Adds instructions to push some arbitrary data onto the stack.
+    ///
+    /// The size of the data is not checked, so pushing more than
+    /// `MAX_SCRIPT_ELEMENT_SIZE` bytes gives a script which always fails,
+    /// which is only of use in outputs which are never spent; see
+    /// `push_bytes` and `try_push_slice`.
+    pub fn push_slice(mut self, data: &[u8]) -> Builder {
+        // Start with a PUSH opcode
+        match data.len() as u64 {
+            n if n < opcodes::Ordinary::OP_PUSHDATA1 as u64 => {
+                self.0.push(n as u8);
+            }
+            n if n < 0x100 => {
+                self.0.push(opcodes::Ordinary::OP_PUSHDATA1.into_u8());
+                self.0.push(n as u8);
+            }
+            n if n < 0x10000 => {
+                self.0.push(opcodes::Ordinary::OP_PUSHDATA2.into_u8());
+                self.0.push((n % 0x100) as u8);
+                self.0.push((n / 0x100) as u8);
+            }
+            n if n < 0x100000000 => {
+                self.0.push(opcodes::Ordinary::OP_PUSHDATA4.into_u8());
+                self.0.push((n % 0x100) as u8);
+                self.0.push(((n / 0x100) % 0x100) as u8);
+                self.0.push(((n / 0x10000) % 0x100) as u8);
+                self.0.push((n / 0x1000000) as u8);
+            }
+            _ => panic!("tried to put a 4bn+ sized object into a script!"),
+        }
+        // Then push the raw bytes
+        self.0.extend(data.iter().cloned());
+        self.1 = None;
+        self
+    }
+
+    /// Adds a single opcode to the script
+    pub fn push_opcode(mut self, data: opcodes::All) -> Builder {
+        self.0.push(data.into_u8());
+        self.1 = Some(data);
+        self
+    }
+
+    /// Adds an `OP_VERIFY` to the script, unless the most-recently-added
+    /// opcode has an alternate `VERIFY` form, in which case that opcode
+    /// is replaced. e.g. `OP_CHECKSIG` will become `OP_CHECKSIGVERIFY`.
+    pub fn push_verify(mut self) -> Builder {
+        match self.1 {
+            Some(opcodes::all::OP_EQUAL) => {
+                self.0.pop();
+                self.push_opcode(opcodes::all::OP_EQUALVERIFY)
+            }
+            Some(opcodes::all::OP_NUMEQUAL) => {
+                self.0.pop();
+                self.push_opcode(opcodes::all::OP_NUMEQUALVERIFY)
+            }
+            Some(opcodes::all::OP_CHECKSIG) => {
+                self.0.pop();
+                self.push_opcode(opcodes::all::OP_CHECKSIGVERIFY)
+            }
+            Some(opcodes::all::OP_CHECKMULTISIG) => {
+                self.0.pop();
+                self.push_opcode(opcodes::all::OP_CHECKMULTISIGVERIFY)
+            }
+            _ => self.push_opcode(opcodes::all::OP_VERIFY),
+        }
+    }
+
+    /// Adds a BIP119 `OP_CHECKTEMPLATEVERIFY` of `template_hash`, leaving
+    /// the hash on the stack as the opcode requires.
+    ///
+    /// This is not a consensus rule; see `opcodes::experimental`.
+    #[cfg(feature = "experimental-opcodes")]
+    pub fn push_ctv(self, template_hash: &[u8; 32]) -> Builder {
+        self.push_slice(template_hash)
+            .push_opcode(opcodes::experimental::OP_CHECKTEMPLATEVERIFY)
+    }
+
+    /// Converts the `Builder` into an unmodifiable `Script`
+    pub fn into_script(self) -> Script {
+        Script(self.0.into_boxed_slice())
+    }
+}
+
+impl_index_newtype!(Builder, u8);
+
+impl Default for Builder {
+    fn default() -> Builder {
+        Builder::new()
+    }
+}
+
+/// Creates a new script from an existing vector
+impl From<Vec<u8>> for Builder {
+    fn from(v: Vec<u8>) -> Builder {
+        let script = Script(v.into_boxed_slice());
+        let last_op = match script.instructions().last() {
+            Some(Ok(Instruction::Op(op))) => Some(op),
+            _ => None,
+        };
+        Builder(script.into_bytes(), last_op)
+    }
+}
+
+impl Encodable for Script {
+    #[inline]
+    fn consensus_encode<S: io::Write>(&self, mut s: S) -> Result<usize, encode::Error> {
+        let vi_len = VarInt(self.0.len() as u64).consensus_encode(&mut s)?;
+        s.emit_slice(&self.0)?;
+        Ok(vi_len + self.0.len())
+    }
+
+    #[inline]
+    fn consensus_encoded_len(&self) -> usize {
+        VarInt(self.0.len() as u64).len() + self.0.len()
+    }
+}
+
+impl Decodable for Script {
+    #[inline]
+    fn consensus_decode<D: io::Read>(d: D) -> Result<Self, encode::Error> {
+        let bytes: Vec<u8> = Decodable::consensus_decode(d)?;
+        Ok(Script(bytes.into_boxed_slice()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::build_scriptint;
+    use super::*;
+
+    use blockdata::opcodes;
+    use consensus::encode::{deserialize, serialize};
+    use hashes::hex::FromHex;
+
+    #[test]
+    fn script() {
+        let mut comp = vec![];
+        let mut script = Builder::new();
+        assert_eq!(&script[..], &comp[..]);
+
+        // small ints
+        script = script.push_int(1);
+        comp.push(81u8);
+        assert_eq!(&script[..], &comp[..]);
+        script = script.push_int(0);
+        comp.push(0u8);
+        assert_eq!(&script[..], &comp[..]);
+        script = script.push_int(4);
+        comp.push(84u8);
+        assert_eq!(&script[..], &comp[..]);
+        script = script.push_int(-1);
+        comp.push(79u8);
+        assert_eq!(&script[..], &comp[..]);
+        // forced scriptint
+        script = script.push_scriptint(4);
+        comp.extend([1u8, 4].iter().cloned());
+        assert_eq!(&script[..], &comp[..]);
+        // big ints
+        script = script.push_int(17);
+        comp.extend([1u8, 17].iter().cloned());
+        assert_eq!(&script[..], &comp[..]);
+        script = script.push_int(10000);
+        comp.extend([2u8, 16, 39].iter().cloned());
+        assert_eq!(&script[..], &comp[..]);
+        // notice the sign bit set here, hence the extra zero/128 at the end
+        script = script.push_int(10000000);
+        comp.extend([4u8, 128, 150, 152, 0].iter().cloned());
+        assert_eq!(&script[..], &comp[..]);
+        script = script.push_int(-10000000);
+        comp.extend([4u8, 128, 150, 152, 128].iter().cloned());
+        assert_eq!(&script[..], &comp[..]);
+
+        // data
+        script = script.push_slice("NRA4VR".as_bytes());
+        comp.extend([6u8, 78, 82, 65, 52, 86, 82].iter().cloned());
+        assert_eq!(&script[..], &comp[..]);
+
+        // opcodes
+        script = script.push_opcode(opcodes::all::OP_CHECKSIG);
+        comp.push(0xACu8);
+        assert_eq!(&script[..], &comp[..]);
+        script = script.push_opcode(opcodes::all::OP_CHECKSIG);
+        comp.push(0xACu8);
+        assert_eq!(&script[..], &comp[..]);
+    }
+
+    #[test]
+    fn push_bytes() {
+        let max = [7u8; MAX_SCRIPT_ELEMENT_SIZE + 1];
+        let push = PushBytes::try_from(&max[..MAX_SCRIPT_ELEMENT_SIZE]).unwrap();
+        assert_eq!(push.len(), 520);
+        let script = Builder::new().push_bytes(push).into_script();
+        assert_eq!(&script[..4], &[0x4d, 0x08, 0x02, 7]);
+        assert_eq!(script.len(), 3 + 520);
+        assert_eq!(
+            Builder::new().try_push_slice(&max[..]),
+            Err(Error::PushTooLarge(521))
+        );
+        assert_eq!(
+            Error::PushTooLarge(521).to_string(),
+            "push of 521 bytes exceeds the 520-byte limit"
+        );
+        assert_eq!(
+            Builder::new().try_push_slice(b"abc").unwrap().into_script(),
+            Builder::new().push_slice(b"abc").into_script()
+        );
+        assert!(PushBytes::empty().is_empty());
+        assert_eq!(
+            Builder::new().push_bytes(PushBytes::empty()).into_script()[..],
+            [0x00]
+        );
+    }
+
+    #[test]
+    fn script_builder_verify() {
+        let simple = Builder::new().push_verify().into_script();
+        assert_eq!(format!("{:x}", simple), "69");
+        let simple2 = Builder::from(vec![]).push_verify().into_script();
+        assert_eq!(format!("{:x}", simple2), "69");
+
+        let nonverify = Builder::new().push_verify().push_verify().into_script();
+        assert_eq!(format!("{:x}", nonverify), "6969");
+        let nonverify2 = Builder::from(vec![0x69]).push_verify().into_script();
+        assert_eq!(format!("{:x}", nonverify2), "6969");
+
+        let equal = Builder::new()
+            .push_opcode(opcodes::all::OP_EQUAL)
+            .push_verify()
+            .into_script();
+        assert_eq!(format!("{:x}", equal), "88");
+        let equal2 = Builder::from(vec![0x87]).push_verify().into_script();
+        assert_eq!(format!("{:x}", equal2), "88");
+
+        let checksig = Builder::new()
+            .push_opcode(opcodes::all::OP_CHECKSIG)
+            .push_verify()
+            .into_script();
+        assert_eq!(format!("{:x}", checksig), "ad");
+    }
+
+    #[test]
+    #[cfg(feature = "experimental-opcodes")]
+    fn script_builder_experimental() {
+        let ctv = Builder::new().push_ctv(&[0x11; 32]).into_script();
+        assert_eq!(ctv.len(), 34);
+        assert_eq!(&ctv[..2], &[0x20, 0x11]);
+        assert_eq!(ctv[33], 0xb3);
+
+        let cat = Builder::new()
+            .push_slice(b"ab")
+            .push_slice(b"cd")
+            .push_opcode(opcodes::experimental::OP_CAT)
+            .into_script();
+        assert_eq!(format!("{:x}", cat), "0261620263647e");
+    }
+
+    #[test]
+    fn script_serialize() {
+        let hex_script =
+            Vec::<u8>::from_hex("6c493046022100f93bb0e7d8db7bd46e40132d1f8242026e045f03a0efe71bbb8e3f475e970d790221009337cd7f1f929f00cc6ff01f03729b069a7c21b59b1736ddfee5db5946c5da8c0121033b9b137ee87d5a812d6f506efdd37f0affa7ffc310711c06c7f3e097c9447c52").unwrap();
+        let script: Result<Script, _> = deserialize(&hex_script);
+        assert!(script.is_ok());
+        assert_eq!(serialize(&script.unwrap()), hex_script);
+    }
+
+    #[test]
+    fn scriptint_round_trip() {
+        assert_eq!(build_scriptint(-1), vec![0x81]);
+        assert_eq!(build_scriptint(255), vec![255, 0]);
+        assert_eq!(build_scriptint(256), vec![0, 1]);
+        assert_eq!(build_scriptint(257), vec![1, 1]);
+        assert_eq!(build_scriptint(511), vec![255, 1]);
+        for &i in [
+            10,
+            100,
+            255,
+            256,
+            1000,
+            10000,
+            25000,
+            200000,
+            5000000,
+            1000000000,
+            (1 << 31) - 1,
+            -((1 << 31) - 1),
+        ]
+        .iter()
+        {
+            assert_eq!(Ok(i), read_scriptint(&build_scriptint(i)));
+            assert_eq!(Ok(-i), read_scriptint(&build_scriptint(-i)));
+        }
+        assert!(read_scriptint(&build_scriptint(1 << 31)).is_err());
+        assert!(read_scriptint(&build_scriptint(-(1 << 31))).is_err());
+    }
+
+    #[test]
+    fn script_hashes() {
+        let script = Script::from(Vec::<u8>::from_hex("410446ef0102d1ec5240f0d061a4246c1bdef63fc3dbab7733052fbbf0ecd8f41fc26bf049ebb4f9527f374280259e7cfa99c48b0e3f39c51347a19a5819651503a5ac").unwrap());
+        assert!(script.is_p2pk());
+        assert!(!script.is_p2pkh());
+        assert!(!script.is_witness_program());
+    }
+
+    #[test]
+    fn script_asm() {
+        let p2pkh = Script::from(
+            Vec::<u8>::from_hex("76a9140389035a9225b3839e2bbf32d826a1e222031fd888ac").unwrap(),
+        );
+        assert!(p2pkh.is_p2pkh());
+        assert_eq!(
+            p2pkh.asm(),
+            "OP_DUP OP_HASH160 OP_PUSHBYTES_20 0389035a9225b3839e2bbf32d826a1e222031fd8 OP_EQUALVERIFY OP_CHECKSIG"
+        );
+        assert_eq!(
+            format!(
+                "{:?}",
+                Script::from(vec![0x00, 0x4c, 0x01, 0x07, 0x4e, 0x01])
+            ),
+            "Script(OP_0 OP_PUSHDATA1 07 OP_PUSHDATA4 <unexpected end>)"
+        );
+        assert_eq!(
+            Script::from(vec![0x02, 0x01]).asm(),
+            "OP_PUSHBYTES_2 <push past end>"
+        );
+    }
+
+    #[test]
+    fn script_asm_with_network() {
+        let p2pkh = hex_script!("76a9140389035a9225b3839e2bbf32d826a1e222031fd888ac");
+        assert_eq!(
+            p2pkh.fmt_asm_with_network(Network::Bitcoin).to_string(),
+            format!(
+                "{} <address: 1KhAQw5BU3i4G8BDGpw8YqUKRLuW3mQdh>",
+                p2pkh.asm()
+            )
+        );
+        assert_eq!(
+            p2pkh.fmt_asm_with_network(Network::Testnet).to_string(),
+            format!(
+                "{} <address: mfqeTU23zVUxqNbnvqoJxU3oBQwcTvNCRs>",
+                p2pkh.asm()
+            )
+        );
+        let p2wpkh = hex_script!("0014751e76e8199196d454941c45d1b3a323f1433bd6");
+        assert_eq!(
+            p2wpkh.fmt_asm_with_network(Network::Bitcoin).to_string(),
+            "OP_0 OP_PUSHBYTES_20 751e76e8199196d454941c45d1b3a323f1433bd6 \
+             <address: bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4>"
+        );
+        let p2pk =
+            hex_script!("210279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798ac");
+        assert_eq!(
+            p2pk.fmt_asm_with_network(Network::Bitcoin).to_string(),
+            format!(
+                "{} <pubkey hash: 751e76e8199196d454941c45d1b3a323f1433bd6>",
+                p2pk.asm()
+            )
+        );
+        let op_return = hex_script!("6a0102");
+        assert_eq!(
+            op_return.fmt_asm_with_network(Network::Bitcoin).to_string(),
+            op_return.asm()
+        );
+    }
+
+    #[test]
+    fn script_from_asm() {
+        // What `asm` writes parses back to the same script
+        for hex in &[
+            "76a9140389035a9225b3839e2bbf32d826a1e222031fd888ac",
+            "004c0107",
+            "4c00",
+            "4d0100ff",
+            "0014751e76e8199196d454941c45d1b3a323f1433bd6",
+            "6a0b68656c6c6f20776f726c64",
+            "5221038282263212c609d9ea2a6e3e172de238d8c39cabd5ac1ca10646e23fd5f5150852ae",
+            "b1b2ba4f5060bb",
+        ] {
+            let script = Script::from(Vec::<u8>::from_hex(hex).unwrap());
+            assert_eq!(Script::from_asm(&script.asm()), Ok(script));
+        }
+
+        // Core-style asm, with pushes inferred
+        let p2pkh = Script::from_asm(
+            "OP_DUP OP_HASH160 0389035a9225b3839e2bbf32d826a1e222031fd8 OP_EQUALVERIFY OP_CHECKSIG",
+        )
+        .unwrap();
+        assert_eq!(
+            format!("{:x}", p2pkh),
+            "76a9140389035a9225b3839e2bbf32d826a1e222031fd888ac"
+        );
+        assert_eq!(
+            Script::from_asm("2 OP_CHECKLOCKTIMEVERIFY DROP 0 -1 1000 16 17").unwrap(),
+            Builder::new()
+                .push_int(2)
+                .push_opcode(opcodes::all::OP_CLTV)
+                .push_opcode(opcodes::all::OP_DROP)
+                .push_int(0)
+                .push_int(-1)
+                .push_int(1000)
+                .push_int(16)
+                .push_int(17)
+                .into_script()
+        );
+        assert_eq!(
+            Script::from_asm("OP_TRUE OP_2 OP_FALSE OP_1NEGATE").unwrap(),
+            Script::from(vec![0x51, 0x52, 0x00, 0x4f])
+        );
+        assert_eq!(
+            Script::from_asm(&vec!["ab"; 76].concat())
+                .unwrap()
+                .as_bytes()[..2],
+            [0x4c, 76]
+        );
+        assert_eq!(
+            Script::from_asm("  0x4c01 ab\tOP_NOP  ").unwrap(),
+            Script::from(vec![0x4c, 0x01, 0x01, 0xab, 0x61])
+        );
+        assert_eq!(
+            Script::from_asm("0x4c01ab\tOP_NOP").unwrap(),
+            Script::from(vec![0x4c, 0x01, 0xab, 0x61])
+        );
+        assert_eq!(Script::from_asm("").unwrap(), Script::new());
+
+        // Errors
+        assert_eq!(
+            Script::from_asm("OP_FOO"),
+            Err(ParseAsmError::UnknownWord("OP_FOO".to_owned()))
+        );
+        assert_eq!(
+            Script::from_asm("abc"),
+            Err(ParseAsmError::UnknownWord("abc".to_owned()))
+        );
+        assert_eq!(
+            Script::from_asm("OP_PUSHBYTES_2 ab"),
+            Err(ParseAsmError::BadPushData("OP_PUSHBYTES_2".to_owned()))
+        );
+        assert_eq!(
+            Script::from_asm("OP_PUSHBYTES_1 OP_DUP"),
+            Err(ParseAsmError::BadPushData("OP_PUSHBYTES_1".to_owned()))
+        );
+        assert_eq!(
+            Script::from_asm("4294967296"),
+            Err(ParseAsmError::NumberOutOfRange("4294967296".to_owned()))
+        );
+    }
+
+    #[test]
+    fn script_instructions() {
+        let script = Script::from(vec![0x00, 0x01, 0x07, 0x4c, 0x02, 0xab, 0xcd, 0x87]);
+        let instructions: Vec<_> = script.instructions().collect();
+        assert_eq!(
+            instructions,
+            vec![
+                Ok(Instruction::PushBytes(&[])),
+                Ok(Instruction::PushBytes(&[0x07])),
+                Ok(Instruction::PushBytes(&[0xab, 0xcd])),
+                Ok(Instruction::Op(opcodes::all::OP_EQUAL)),
+            ]
+        );
+
+        let minimal: Vec<_> = script.instructions_minimal().collect();
+        assert_eq!(minimal[1], Err(Error::NonMinimalPush));
+        assert_eq!(minimal.len(), 2);
+
+        let truncated = Script::from(vec![0x4d, 0x01]);
+        let instructions: Vec<_> = truncated.instructions().collect();
+        assert_eq!(instructions, vec![Err(Error::EarlyEndOfScript)]);
+    }
+
+    #[test]
+    fn script_templates() {
+        let pubkey =
+            Vec::from_hex("0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798")
+                .unwrap();
+
+        let p2pkh = Script::new_p2pkh(&PubkeyHash::from_pubkey(&pubkey));
+        assert!(p2pkh.is_p2pkh());
+        assert_eq!(
+            p2pkh,
+            hex_script!("76a914751e76e8199196d454941c45d1b3a323f1433bd688ac")
+        );
+
+        let p2wpkh = Script::new_v0_wpkh(&WPubkeyHash::from_pubkey(&pubkey).unwrap());
+        assert!(p2wpkh.is_v0_p2wpkh());
+        assert_eq!(
+            p2wpkh,
+            hex_script!("0014751e76e8199196d454941c45d1b3a323f1433bd6")
+        );
+
+        let p2sh = Script::new().to_p2sh();
+        assert!(p2sh.is_p2sh());
+        assert_eq!(
+            p2sh,
+            hex_script!("a914b472a266d0bd89c13706a4132ccfb16f7c3b9fcb87")
+        );
+        assert_eq!(p2sh, Script::new_p2sh(&Script::new().script_hash()));
+
+        let p2wsh = Script::new().to_v0_p2wsh();
+        assert!(p2wsh.is_v0_p2wsh());
+        assert_eq!(
+            p2wsh,
+            hex_script!("0020e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855")
+        );
+    }
+
+    #[test]
+    fn provably_unspendable_test() {
+        // p2pk
+        assert_eq!(hex_script!("410446ef0102d1ec5240f0d061a4246c1bdef63fc3dbab7733052fbbf0ecd8f41fc26bf049ebb4f9527f374280259e7cfa99c48b0e3f39c51347a19a5819651503a5ac").is_provably_unspendable(), false);
+        // p2pkhash
+        assert_eq!(
+            hex_script!("76a914ee61d57ab51b9d212335b1dba62794ac20d2bcf988ac")
+                .is_provably_unspendable(),
+            false
+        );
+        assert_eq!(
+            hex_script!("6aa9149eb21980dc9d413d8eac27314938b9da920ee53e87")
+                .is_provably_unspendable(),
+            true
+        );
+        assert!(hex_script!("6aa9149eb21980dc9d413d8eac27314938b9da920ee53e87").is_op_return());
+    }
+
+    #[test]
+    fn witness_program_test() {
+        assert!(hex_script!("0014ee61d57ab51b9d212335b1dba62794ac20d2bcf9").is_v0_p2wpkh());
+        assert!(hex_script!(
+            "0020d5c0b43fc5f1ecd9a6b1bca5e8d2f3a6a9c1e8d6d8b1e1b3c4e5d6f7a8b9c0d1"
+        )
+        .is_v0_p2wsh());
+        assert!(hex_script!("0014ee61d57ab51b9d212335b1dba62794ac20d2bcf9").is_witness_program());
+        assert!(!hex_script!("0015ee61d57ab51b9d212335b1dba62794ac20d2bcf9").is_witness_program());
+
+        assert_eq!(
+            hex_script!("0014ee61d57ab51b9d212335b1dba62794ac20d2bcf9").witness_version(),
+            Some(WitnessVersion::V0)
+        );
+        assert_eq!(
+            hex_script!("6002751e").witness_version(),
+            Some(WitnessVersion::V16)
+        );
+        assert_eq!(hex_script!("4f02751e").witness_version(), None);
+    }
+}
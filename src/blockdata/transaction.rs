@@ -0,0 +1,2250 @@
+//! Bitcoin Transaction
+//!
+//! A transaction describes a transfer of money. It consumes previously-unspent
+//! transaction outputs and produces new ones, satisfying the condition to spend
+//! the old outputs (typically a digital signature with a specific key must be
+//! provided) and defining the condition to spend the new ones. The use of digital
+//! signatures ensures that coins cannot be spent by unauthorized parties.
+//!
+//! This module provides the structures and functions needed to support transactions.
+
+use std::convert::TryFrom;
+use std::default::Default;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::{error, fmt, io, num};
+
+use hashes::hex::{self, FromHex};
+use hashes::{Hash, HashEngine};
+
+use blockdata::opcodes;
+use blockdata::script::{self, Builder, Instruction, Script};
+use consensus::encode::{self, Decodable, Encodable, VarInt};
+use error::{ErrorCode, ErrorKind};
+use hash_types::{Txid, WitnessCommitment, WitnessMerkleNode, Wtxid};
+use policy::{Policy, PolicyViolation};
+use util::rng::{DefaultRng, Rng};
+
+/// Number of blocks a coinbase output must wait before it can be spent
+pub const COINBASE_MATURITY: u32 = 100;
+
+/// The witness reserved value of BIP141, the one element of a coinbase
+/// input's witness
+pub const WITNESS_RESERVED_VALUE: [u8; 32] = [0; 32];
+
+/// The first byte of a taproot annex, as defined by BIP341
+pub const TAPROOT_ANNEX_PREFIX: u8 = 0x50;
+
+/// The bytes starting the data of the output committing to a block's
+/// witnesses, as defined by BIP141
+const WITNESS_COMMITMENT_HEADER: [u8; 4] = [0xaa, 0x21, 0xa9, 0xed];
+
+/// A reference to a transaction output
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq, PartialOrd, Ord)]
+pub struct OutPoint {
+    /// The referenced transaction's txid
+    pub txid: Txid,
+    /// The index of the referenced output in its transaction's vout
+    pub vout: u32,
+}
+impl_consensus_encoding!(OutPoint, txid, vout);
+impl_consensus_from_hex!(OutPoint);
+
+impl OutPoint {
+    /// Create a new [OutPoint].
+    #[inline]
+    pub fn new(txid: Txid, vout: u32) -> OutPoint {
+        OutPoint { txid, vout }
+    }
+
+    /// Creates a "null" `OutPoint`.
+    ///
+    /// This value is used for coinbase transactions because they don't have
+    /// any previous outputs.
+    #[inline]
+    pub fn null() -> OutPoint {
+        OutPoint {
+            txid: Default::default(),
+            vout: u32::max_value(),
+        }
+    }
+
+    /// Checks if an `OutPoint` is "null".
+    #[inline]
+    pub fn is_null(&self) -> bool {
+        *self == OutPoint::null()
+    }
+}
+
+impl Default for OutPoint {
+    fn default() -> Self {
+        OutPoint::null()
+    }
+}
+
+impl fmt::Display for OutPoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.txid, self.vout)
+    }
+}
+
+/// Parses the `txid:vout` form an `OutPoint` is displayed in. Only the
+/// form it is displayed in is accepted, so a vout with a sign or leading
+/// zeroes is rejected; the txid may be in either case of hex.
+impl FromStr for OutPoint {
+    type Err = ParseOutPointError;
+
+    fn from_str(s: &str) -> Result<OutPoint, ParseOutPointError> {
+        let colon = match s.find(':') {
+            Some(i) if s.rfind(':') == Some(i) => i,
+            _ => return Err(ParseOutPointError::Format),
+        };
+        let vout = &s[colon + 1..];
+        if vout.starts_with('+') || (vout.len() > 1 && vout.starts_with('0')) {
+            return Err(ParseOutPointError::VoutNotCanonical);
+        }
+        Ok(OutPoint {
+            txid: Txid::from_hex(&s[..colon]).map_err(ParseOutPointError::Txid)?,
+            vout: vout.parse().map_err(ParseOutPointError::Vout)?,
+        })
+    }
+}
+
+/// An error parsing an `OutPoint` from its `txid:vout` form
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseOutPointError {
+    /// The txid is not valid hex of 32 bytes
+    Txid(hex::Error),
+    /// The vout is not a 32-bit number
+    Vout(num::ParseIntError),
+    /// The string is not split in two by a single colon
+    Format,
+    /// The vout has a sign or leading zeroes
+    VoutNotCanonical,
+}
+
+impl fmt::Display for ParseOutPointError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseOutPointError::Txid(ref e) => write!(f, "invalid txid: {}", e),
+            ParseOutPointError::Vout(ref e) => write!(f, "invalid vout: {}", e),
+            ParseOutPointError::Format => f.write_str("outpoint not in txid:vout form"),
+            ParseOutPointError::VoutNotCanonical => {
+                f.write_str("vout has a sign or leading zeroes")
+            }
+        }
+    }
+}
+
+impl error::Error for ParseOutPointError {
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            ParseOutPointError::Txid(ref e) => Some(e),
+            ParseOutPointError::Vout(ref e) => Some(e),
+            ParseOutPointError::Format | ParseOutPointError::VoutNotCanonical => None,
+        }
+    }
+
+    fn description(&self) -> &str {
+        match *self {
+            ParseOutPointError::Txid(..) => "invalid txid",
+            ParseOutPointError::Vout(..) => "invalid vout",
+            ParseOutPointError::Format => "outpoint not in txid:vout form",
+            ParseOutPointError::VoutNotCanonical => "vout not canonical",
+        }
+    }
+}
+
+impl ErrorCode for ParseOutPointError {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::OutPoint
+    }
+
+    fn number(&self) -> u32 {
+        match *self {
+            ParseOutPointError::Txid(..) => 1,
+            ParseOutPointError::Vout(..) => 2,
+            ParseOutPointError::Format => 3,
+            ParseOutPointError::VoutNotCanonical => 4,
+        }
+    }
+}
+
+/// A transaction input, which defines old coins to be consumed
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub struct TxIn {
+    /// The reference to the previous output that is being used an an input
+    pub previous_output: OutPoint,
+    /// The script which pushes values on the stack which will cause
+    /// the referenced output's script to accept
+    pub script_sig: Script,
+    /// The sequence number, which suggests to miners which of two
+    /// conflicting transactions should be preferred, or 0xFFFFFFFF
+    /// to ignore this feature. This is generally never used since
+    /// the miner behaviour cannot be enforced.
+    pub sequence: u32,
+    /// Witness data: an array of byte-arrays.
+    /// Note that this field is *not* (de)serialized with the rest of the TxIn in
+    /// Encodable/Decodable, as it is (de)serialized at the end of the full
+    /// Transaction. It *is* (de)serialized with the rest of the TxIn in other
+    /// (de)serialization routines.
+    pub witness: Vec<Vec<u8>>,
+}
+
+impl Default for TxIn {
+    fn default() -> TxIn {
+        TxIn {
+            previous_output: OutPoint::default(),
+            script_sig: Script::new(),
+            sequence: u32::max_value(),
+            witness: Vec::new(),
+        }
+    }
+}
+
+impl TxIn {
+    /// The size of the input as serialized within a transaction, leaving
+    /// out its witness
+    pub fn base_size(&self) -> usize {
+        32 + 4 + 4 + VarInt(self.script_sig.len() as u64).len() + self.script_sig.len()
+    }
+
+    /// The serialized size of the witness, or 0 if there is none
+    pub fn witness_size(&self) -> usize {
+        if self.witness.is_empty() {
+            return 0;
+        }
+        self.witness
+            .iter()
+            .fold(VarInt(self.witness.len() as u64).len(), |size, elem| {
+                size + VarInt(elem.len() as u64).len() + elem.len()
+            })
+    }
+
+    /// The size of the input together with its witness
+    pub fn total_size(&self) -> usize {
+        self.base_size() + self.witness_size()
+    }
+
+    /// The weight the input contributes to its transaction, not counting
+    /// the segwit marker and flag
+    pub fn segwit_weight(&self) -> usize {
+        4 * self.base_size() + self.witness_size()
+    }
+
+    /// The annex of a taproot spend: the last witness element, if there
+    /// are at least two and it starts with `TAPROOT_ANNEX_PREFIX`.
+    ///
+    /// This only makes sense for inputs spending a taproot output; the
+    /// witness of other inputs may happen to look like it has one.
+    pub fn taproot_annex(&self) -> Option<&[u8]> {
+        match self.witness.last() {
+            Some(last) if self.witness.len() > 1 && last.first() == Some(&TAPROOT_ANNEX_PREFIX) => {
+                Some(last)
+            }
+            _ => None,
+        }
+    }
+
+    /// The witness of a taproot spend without its annex
+    pub(crate) fn taproot_stack(&self) -> &[Vec<u8>] {
+        match self.taproot_annex() {
+            Some(_) => &self.witness[..self.witness.len() - 1],
+            None => &self.witness,
+        }
+    }
+
+    /// The control block of a taproot script path spend: the last witness
+    /// element once the annex is left out, if there are at least two and it
+    /// has the length of a control block.
+    pub fn taproot_control_block(&self) -> Option<&[u8]> {
+        let stack = self.taproot_stack();
+        if stack.len() < 2 {
+            return None;
+        }
+        let control_block = &stack[stack.len() - 1];
+        let len = control_block.len();
+        if len >= 33 && len <= 33 + 32 * 128 && (len - 33) % 32 == 0 {
+            Some(control_block)
+        } else {
+            None
+        }
+    }
+
+    /// The script of a taproot script path spend, the witness element just
+    /// below the control block
+    pub fn tapscript(&self) -> Option<Script> {
+        self.taproot_control_block()?;
+        let stack = self.taproot_stack();
+        Some(Script::from(stack[stack.len() - 2].clone()))
+    }
+
+    /// Classifies the input by `spent`, the `script_pubkey` of the output it
+    /// spends, looking into the `script_sig` to tell nested segwit apart
+    pub fn input_type(&self, spent: &Script) -> InputType {
+        if spent.is_p2pk() {
+            InputType::P2pk
+        } else if spent.is_p2pkh() {
+            InputType::P2pkh
+        } else if spent.is_p2sh() {
+            // A nested witness program is the only push in the script_sig
+            let sig = self.script_sig.as_bytes();
+            if sig.is_empty() || sig[0] as usize != sig.len() - 1 {
+                return InputType::P2sh;
+            }
+            let redeem_script = Script::from(sig[1..].to_vec());
+            if redeem_script.is_v0_p2wpkh() {
+                InputType::P2shP2wpkh
+            } else if redeem_script.is_v0_p2wsh() {
+                InputType::P2shP2wsh
+            } else {
+                InputType::P2sh
+            }
+        } else if spent.is_v0_p2wpkh() {
+            InputType::P2wpkh
+        } else if spent.is_v0_p2wsh() {
+            InputType::P2wsh
+        } else if spent.is_v1_p2tr() {
+            InputType::P2tr
+        } else if spent.is_witness_program() {
+            InputType::WitnessUnknown
+        } else {
+            InputType::NonStandard
+        }
+    }
+}
+
+/// The kind of output a transaction input spends
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum InputType {
+    /// Pay to public key
+    P2pk,
+    /// Pay to public key hash
+    P2pkh,
+    /// Pay to script hash, other than nested segwit
+    P2sh,
+    /// Pay to witness public key hash nested in P2SH
+    P2shP2wpkh,
+    /// Pay to witness script hash nested in P2SH
+    P2shP2wsh,
+    /// Pay to witness public key hash
+    P2wpkh,
+    /// Pay to witness script hash
+    P2wsh,
+    /// Pay to taproot
+    P2tr,
+    /// A witness program of a version or length with no meaning yet
+    WitnessUnknown,
+    /// Anything else, such as bare multisig
+    NonStandard,
+}
+
+impl InputType {
+    /// The largest satisfaction of an output of this type, as used by
+    /// `Transaction::predict_weight`, or `None` if it depends on a script
+    /// other than the `script_pubkey`, as for P2SH and P2WSH.
+    ///
+    /// Signatures are assumed to be low-S, so at most 72 bytes with the
+    /// sighash byte, and P2PKH keys to be uncompressed unless known
+    /// otherwise. A taproot output is assumed to be spent through its key
+    /// path.
+    pub fn max_weight_prediction(self) -> Option<InputWeightPrediction> {
+        match self {
+            InputType::P2pk => Some(InputWeightPrediction::new(1 + 72, None)),
+            InputType::P2pkh => Some(InputWeightPrediction::P2PKH_UNCOMPRESSED_MAX),
+            InputType::P2shP2wpkh => Some(InputWeightPrediction::NESTED_P2WPKH_MAX),
+            InputType::P2wpkh => Some(InputWeightPrediction::P2WPKH_MAX),
+            InputType::P2tr => Some(InputWeightPrediction::P2TR_KEY_NON_DEFAULT_SIGHASH),
+            InputType::P2sh
+            | InputType::P2shP2wsh
+            | InputType::P2wsh
+            | InputType::WitnessUnknown
+            | InputType::NonStandard => None,
+        }
+    }
+
+    /// The largest weight the `script_sig` and witness satisfying an output
+    /// of this type add to the input, counting their length prefixes, so
+    /// that fees can be budgeted before there are signatures
+    pub fn max_satisfaction_weight(self) -> Option<usize> {
+        self.max_weight_prediction()
+            .map(|p| 4 * p.script_size + p.witness_size)
+    }
+
+    /// The largest number of bytes the `script_sig` and witness satisfying
+    /// an output of this type add to the input, counting their length
+    /// prefixes
+    pub fn max_satisfaction_size(self) -> Option<usize> {
+        self.max_weight_prediction()
+            .map(|p| p.script_size + p.witness_size)
+    }
+}
+
+/// A transaction input's sequence number.
+///
+/// Besides signalling replaceability, the sequence number encodes the
+/// relative timelock of an input as defined by BIP68: when bit 31 is unset,
+/// the low 16 bits give a number of blocks, or a number of 512-second
+/// intervals if bit 22 is set.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Sequence(pub u32);
+
+impl Sequence {
+    /// The maximum sequence number, which disables both the relative
+    /// timelock and the absolute `lock_time` of the transaction.
+    pub const MAX: Sequence = Sequence(0xFFFFFFFF);
+
+    /// Setting this bit disables the relative timelock.
+    const LOCK_TIME_DISABLE_FLAG_MASK: u32 = 0x80000000;
+    /// Setting this bit makes the relative timelock time-based.
+    const LOCK_TYPE_MASK: u32 = 0x00400000;
+    /// The bits holding the value of the relative timelock.
+    const LOCK_VALUE_MASK: u32 = 0x0000FFFF;
+    /// The granularity of time-based relative timelocks, in seconds.
+    const SECONDS_GRANULARITY: u32 = 512;
+
+    /// Creates a relative timelock of `height` blocks.
+    #[inline]
+    pub fn from_height(height: u16) -> Sequence {
+        Sequence(height as u32)
+    }
+
+    /// Creates a relative timelock of `seconds` seconds.
+    ///
+    /// BIP68 counts time in intervals of 512 seconds, so `seconds` must be a
+    /// multiple of 512 and no more than `0xFFFF` intervals.
+    pub fn from_seconds(seconds: u32) -> Result<Sequence, SequenceError> {
+        if seconds % Sequence::SECONDS_GRANULARITY != 0 {
+            return Err(SequenceError::NotMultipleOf512(seconds));
+        }
+        let intervals = seconds / Sequence::SECONDS_GRANULARITY;
+        if intervals > Sequence::LOCK_VALUE_MASK {
+            return Err(SequenceError::IntervalTooLarge(seconds));
+        }
+        Ok(Sequence(Sequence::LOCK_TYPE_MASK | intervals))
+    }
+
+    /// Whether this sequence number encodes a relative timelock.
+    #[inline]
+    pub fn is_relative_lock_time(&self) -> bool {
+        self.0 & Sequence::LOCK_TIME_DISABLE_FLAG_MASK == 0
+    }
+
+    /// Whether this sequence number encodes a relative timelock in blocks.
+    #[inline]
+    pub fn is_height_locked(&self) -> bool {
+        self.is_relative_lock_time() && self.0 & Sequence::LOCK_TYPE_MASK == 0
+    }
+
+    /// Whether this sequence number encodes a relative timelock in
+    /// 512-second intervals.
+    #[inline]
+    pub fn is_time_locked(&self) -> bool {
+        self.is_relative_lock_time() && self.0 & Sequence::LOCK_TYPE_MASK != 0
+    }
+
+    /// Creates a relative timelock of at least `duration`, rounded up to
+    /// the next multiple of 512 seconds.
+    pub fn from_duration_ceil(duration: Duration) -> Result<Sequence, SequenceError> {
+        let seconds = duration.as_secs() + if duration.subsec_nanos() > 0 { 1 } else { 0 };
+        let granularity = u64::from(Sequence::SECONDS_GRANULARITY);
+        let intervals = seconds.div_ceil(granularity);
+        if intervals > u64::from(Sequence::LOCK_VALUE_MASK) {
+            return Err(SequenceError::IntervalTooLarge(
+                u32::try_from(seconds).unwrap_or(0xFFFFFFFF),
+            ));
+        }
+        Ok(Sequence(Sequence::LOCK_TYPE_MASK | intervals as u32))
+    }
+
+    /// The number of blocks of a relative timelock in blocks.
+    #[inline]
+    pub fn height_lock(self) -> Option<u16> {
+        if self.is_height_locked() {
+            Some((self.0 & Sequence::LOCK_VALUE_MASK) as u16)
+        } else {
+            None
+        }
+    }
+
+    /// The duration of a relative timelock in 512-second intervals.
+    ///
+    /// It is counted in median time past (BIP113), from that of the block
+    /// before the one confirming the output spent to that of the block
+    /// before the one spending it, and not in wall clock time.
+    #[inline]
+    pub fn time_lock(self) -> Option<Duration> {
+        if self.is_time_locked() {
+            let intervals = u64::from(self.0 & Sequence::LOCK_VALUE_MASK);
+            Some(Duration::from_secs(
+                intervals * u64::from(Sequence::SECONDS_GRANULARITY),
+            ))
+        } else {
+            None
+        }
+    }
+
+    /// The duration of a relative timelock, estimating blocks to come every
+    /// `LockTime::BLOCK_INTERVAL`.
+    pub fn estimated_duration(self) -> Option<Duration> {
+        match self.height_lock() {
+            Some(blocks) => Some(LockTime::BLOCK_INTERVAL * u32::from(blocks)),
+            None => self.time_lock(),
+        }
+    }
+
+    /// The number of blocks of a relative timelock, estimating blocks to
+    /// come every `LockTime::BLOCK_INTERVAL`.
+    pub fn estimated_blocks(self) -> Option<u32> {
+        match self.height_lock() {
+            Some(blocks) => Some(u32::from(blocks)),
+            None => self.time_lock().map(|duration| {
+                let interval = LockTime::BLOCK_INTERVAL.as_secs();
+                duration.as_secs().div_ceil(interval) as u32
+            }),
+        }
+    }
+
+    /// Returns the value as it is stored in a `TxIn`.
+    #[inline]
+    pub fn to_consensus_u32(self) -> u32 {
+        self.0
+    }
+}
+
+impl Default for Sequence {
+    fn default() -> Sequence {
+        Sequence::MAX
+    }
+}
+
+impl From<Sequence> for u32 {
+    fn from(sequence: Sequence) -> u32 {
+        sequence.0
+    }
+}
+
+impl fmt::Display for Sequence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+/// An error in constructing a relative timelock `Sequence`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SequenceError {
+    /// The number of seconds is not a multiple of 512
+    NotMultipleOf512(u32),
+    /// The number of seconds exceeds what fits in 16 bits of 512-second intervals
+    IntervalTooLarge(u32),
+}
+
+impl fmt::Display for SequenceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SequenceError::NotMultipleOf512(s) => {
+                write!(f, "{} seconds is not a multiple of 512", s)
+            }
+            SequenceError::IntervalTooLarge(s) => {
+                write!(f, "{} seconds is too large for a relative timelock", s)
+            }
+        }
+    }
+}
+
+impl error::Error for SequenceError {
+    fn cause(&self) -> Option<&error::Error> {
+        None
+    }
+
+    fn description(&self) -> &str {
+        match *self {
+            SequenceError::NotMultipleOf512(..) => "seconds not a multiple of 512",
+            SequenceError::IntervalTooLarge(..) => "relative timelock too large",
+        }
+    }
+}
+
+impl ErrorCode for SequenceError {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Sequence
+    }
+
+    fn number(&self) -> u32 {
+        match *self {
+            SequenceError::NotMultipleOf512(..) => 1,
+            SequenceError::IntervalTooLarge(..) => 2,
+        }
+    }
+}
+
+/// A transaction's lock time.
+///
+/// Below `LockTime::THRESHOLD` the lock time is a block height, and a
+/// transaction may only be in blocks above it. From the threshold on it is
+/// a UNIX timestamp, which the median time past (BIP113) of the block
+/// before the one including the transaction must exceed. The median time
+/// past of a block is that of the 11 blocks ending with it, and lags the
+/// wall clock by about an hour, so a lock time set to a time is reached
+/// about an hour after that time. Either way the lock time only applies if
+/// an input has a sequence number other than `Sequence::MAX`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LockTime(pub u32);
+
+impl LockTime {
+    /// The lock time which does not lock the transaction.
+    pub const ZERO: LockTime = LockTime(0);
+
+    /// Lock times from this value on are UNIX timestamps, and below it
+    /// block heights.
+    pub const THRESHOLD: u32 = 500_000_000;
+
+    /// The time between blocks which estimates assume.
+    pub const BLOCK_INTERVAL: Duration = Duration::from_secs(600);
+
+    /// Creates a lock time which a transaction is final after the block at
+    /// `height`.
+    pub fn from_height(height: u32) -> Result<LockTime, LockTimeError> {
+        if height >= LockTime::THRESHOLD {
+            return Err(LockTimeError::InvalidHeight(height));
+        }
+        Ok(LockTime(height))
+    }
+
+    /// Creates a lock time which a transaction is final after, rounded
+    /// down to the second.
+    ///
+    /// It is compared to the median time past rather than to the time
+    /// blocks are mined, so a transaction with this lock time will confirm
+    /// about an hour after `time` at the earliest.
+    pub fn from_time(time: SystemTime) -> Result<LockTime, LockTimeError> {
+        let seconds = time
+            .duration_since(UNIX_EPOCH)
+            .map_err(|_| LockTimeError::InvalidTime(time))?
+            .as_secs();
+        match u32::try_from(seconds) {
+            Ok(seconds) if seconds >= LockTime::THRESHOLD => Ok(LockTime(seconds)),
+            _ => Err(LockTimeError::InvalidTime(time)),
+        }
+    }
+
+    /// Whether the lock time is a block height.
+    #[inline]
+    pub fn is_block_height(self) -> bool {
+        self.0 < LockTime::THRESHOLD
+    }
+
+    /// Whether the lock time is a UNIX timestamp.
+    #[inline]
+    pub fn is_block_time(self) -> bool {
+        !self.is_block_height()
+    }
+
+    /// The height, if the lock time is a block height.
+    #[inline]
+    pub fn height(self) -> Option<u32> {
+        if self.is_block_height() {
+            Some(self.0)
+        } else {
+            None
+        }
+    }
+
+    /// The time, if the lock time is a UNIX timestamp.
+    #[inline]
+    pub fn time(self) -> Option<SystemTime> {
+        if self.is_block_time() {
+            Some(UNIX_EPOCH + Duration::from_secs(u64::from(self.0)))
+        } else {
+            None
+        }
+    }
+
+    /// Whether a transaction with this lock time may be in the block at
+    /// `height`, given the median time past of the block before it.
+    pub fn is_final(self, height: u32, median_time_past: u32) -> bool {
+        if self.is_block_height() {
+            self.0 < height
+        } else {
+            self.0 < median_time_past
+        }
+    }
+
+    /// The height of the first block which may include a transaction with
+    /// this lock time, given the height and the median time past of the
+    /// tip. For a time, the median time past is assumed to advance by
+    /// `BLOCK_INTERVAL` a block.
+    pub fn estimated_height(self, tip_height: u32, median_time_past: u32) -> u32 {
+        let next = tip_height.saturating_add(1);
+        if self.is_block_height() {
+            return next.max(self.0.saturating_add(1));
+        }
+        if self.0 < median_time_past {
+            return next;
+        }
+        let blocks = (self.0 - median_time_past) as u64 / LockTime::BLOCK_INTERVAL.as_secs() + 1;
+        next.saturating_add(blocks as u32)
+    }
+
+    /// When the first block which may include a transaction with this lock
+    /// time is expected, given the height and the median time past of the
+    /// tip, assuming a block every `BLOCK_INTERVAL` from `now` on.
+    pub fn estimated_time(
+        self,
+        tip_height: u32,
+        median_time_past: u32,
+        now: SystemTime,
+    ) -> SystemTime {
+        let blocks = self.estimated_height(tip_height, median_time_past) - tip_height;
+        now + LockTime::BLOCK_INTERVAL * blocks
+    }
+
+    /// Returns the value as it is stored in a `Transaction`.
+    #[inline]
+    pub fn to_consensus_u32(self) -> u32 {
+        self.0
+    }
+}
+
+impl From<u32> for LockTime {
+    fn from(lock_time: u32) -> LockTime {
+        LockTime(lock_time)
+    }
+}
+
+impl From<LockTime> for u32 {
+    fn from(lock_time: LockTime) -> u32 {
+        lock_time.0
+    }
+}
+
+impl fmt::Display for LockTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+/// An error in constructing a `LockTime`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LockTimeError {
+    /// The height is not below `LockTime::THRESHOLD`
+    InvalidHeight(u32),
+    /// The time is not a UNIX timestamp from `LockTime::THRESHOLD` on
+    /// which fits in 32 bits
+    InvalidTime(SystemTime),
+}
+
+impl fmt::Display for LockTimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LockTimeError::InvalidHeight(h) => {
+                write!(f, "height {} is too large for a lock time", h)
+            }
+            LockTimeError::InvalidTime(t) => write!(f, "{:?} is out of range for a lock time", t),
+        }
+    }
+}
+
+impl error::Error for LockTimeError {
+    fn cause(&self) -> Option<&error::Error> {
+        None
+    }
+
+    fn description(&self) -> &str {
+        match *self {
+            LockTimeError::InvalidHeight(..) => "lock time height too large",
+            LockTimeError::InvalidTime(..) => "lock time out of range",
+        }
+    }
+}
+
+impl ErrorCode for LockTimeError {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::LockTime
+    }
+
+    fn number(&self) -> u32 {
+        match *self {
+            LockTimeError::InvalidHeight(..) => 1,
+            LockTimeError::InvalidTime(..) => 2,
+        }
+    }
+}
+
+/// A transaction's version number.
+///
+/// Consensus accepts any version, but relay policy only accepts the versions
+/// which have a meaning: 1, 2 which enables the BIP68 relative timelocks, and
+/// 3 which opts in to the topologically restricted until confirmation (TRUC)
+/// rules of BIP431.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Version(pub u32);
+
+impl Version {
+    /// The original transaction version
+    pub const ONE: Version = Version(1);
+    /// The version enabling BIP68 relative timelocks
+    pub const TWO: Version = Version(2);
+    /// The version opting in to the TRUC rules of BIP431
+    pub const THREE: Version = Version(3);
+
+    /// Whether transactions of this version are relayed by Bitcoin Core
+    #[inline]
+    pub fn is_standard(&self) -> bool {
+        *self >= Version::ONE && *self <= Version::THREE
+    }
+
+    /// Whether this version opts in to the TRUC rules
+    #[inline]
+    pub fn is_truc(&self) -> bool {
+        *self == Version::THREE
+    }
+
+    /// Whether the relative timelocks of BIP68 apply to the inputs of
+    /// transactions of this version.
+    ///
+    /// Versions are compared as signed numbers here, as they are in Bitcoin
+    /// Core.
+    #[inline]
+    pub fn enforces_relative_lock_time(&self) -> bool {
+        self.0 as i32 >= 2
+    }
+
+    /// Returns the value as it is stored in a `Transaction`.
+    #[inline]
+    pub fn to_consensus_u32(self) -> u32 {
+        self.0
+    }
+}
+
+impl From<u32> for Version {
+    fn from(version: u32) -> Version {
+        Version(version)
+    }
+}
+
+impl From<Version> for u32 {
+    fn from(version: Version) -> u32 {
+        version.0
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+/// A transaction output, which defines new coins to be created from old ones.
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub struct TxOut {
+    /// The value of the output, in satoshis
+    pub value: u64,
+    /// The script which must satisfy for the output to be spent
+    pub script_pubkey: Script,
+}
+impl_consensus_encoding!(TxOut, value, script_pubkey);
+impl_consensus_from_hex!(TxOut);
+
+// This is used as a "null txout" in consensus signing code
+impl Default for TxOut {
+    fn default() -> TxOut {
+        TxOut {
+            value: 0xffffffffffffffff,
+            script_pubkey: Script::new(),
+        }
+    }
+}
+
+/// A Bitcoin transaction, which describes an authenticated movement of coins.
+///
+/// If any inputs have nonempty witnesses, the entire transaction is serialized
+/// in the post-BIP141 Segwit format which includes a list of witnesses. If all
+/// inputs have empty witnesses, the transaction is serialized in the pre-BIP141
+/// format.
+///
+/// There is one major exception to this: to avoid deserialization ambiguity,
+/// if the transaction has no inputs, it is serialized in the BIP141 style. Be
+/// aware that this differs from the transaction format in contract-signing
+/// contexts, where a transaction with no inputs is serialized in the
+/// pre-BIP141 format.
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub struct Transaction {
+    /// The protocol version, is currently expected to be 1, 2 (BIP 68) or
+    /// 3 (BIP 431). See `Version`.
+    pub version: u32,
+    /// Block number before which this transaction is valid, or 0 for
+    /// valid immediately.
+    pub lock_time: u32,
+    /// List of inputs
+    pub input: Vec<TxIn>,
+    /// List of outputs
+    pub output: Vec<TxOut>,
+}
+
+impl Transaction {
+    /// Computes the txid. For non-segwit transactions this will be identical
+    /// to the output of `wtxid()`, but for segwit transactions,
+    /// this will give the correct txid (not including witnesses) while `wtxid`
+    /// will also hash witnesses.
+    pub fn txid(&self) -> Txid {
+        let mut enc = Txid::engine();
+        self.version.consensus_encode(&mut enc).unwrap();
+        self.input.consensus_encode(&mut enc).unwrap();
+        self.output.consensus_encode(&mut enc).unwrap();
+        self.lock_time.consensus_encode(&mut enc).unwrap();
+        Txid::from_engine(enc)
+    }
+
+    /// Computes SegWit-version of the transaction id (wtxid). For transaction with the witness
+    /// data this hash includes witness, for pre-witness transaction it is equal to the normal
+    /// value returned by txid() function.
+    pub fn wtxid(&self) -> Wtxid {
+        let mut enc = Wtxid::engine();
+        self.consensus_encode(&mut enc).unwrap();
+        Wtxid::from_engine(enc)
+    }
+
+    /// Gets the "weight" of this transaction, as defined by BIP141. For transactions with an empty
+    /// witness, this is simply the consensus-serialized size times 4. For transactions with a
+    /// witness, this is the non-witness consensus-serialized size multiplied by 3 plus the
+    /// with-witness consensus-serialized size.
+    #[inline]
+    pub fn get_weight(&self) -> usize {
+        let mut input_weight = 0;
+        let mut inputs_with_witnesses = 0;
+        for input in &self.input {
+            input_weight += input.segwit_weight();
+            if !input.witness.is_empty() {
+                inputs_with_witnesses += 1;
+            }
+        }
+        let output_lens = self.output.iter().map(|o| o.script_pubkey.len());
+        Transaction::weight_from_parts(
+            self.input.len(),
+            input_weight,
+            inputs_with_witnesses,
+            output_lens,
+        )
+    }
+
+    /// Gets the virtual size of this transaction, its weight divided by 4
+    /// and rounded up, as used for fee rates and relay policy.
+    #[inline]
+    pub fn get_vsize(&self) -> usize {
+        (self.get_weight() + 3) / 4
+    }
+
+    /// Gets the size of this transaction serialized without its witness,
+    /// which is what its txid commits to.
+    #[inline]
+    pub fn get_stripped_size(&self) -> usize {
+        let input_weight = self.input.iter().map(|i| 4 * i.base_size()).sum();
+        let output_lens = self.output.iter().map(|o| o.script_pubkey.len());
+        Transaction::weight_from_parts(self.input.len(), input_weight, 0, output_lens) / 4
+    }
+
+    /// Checks the transaction against the standardness rules of `policy`
+    /// which need neither its prevouts nor the mempool, returning every
+    /// violation found. An empty result does not guarantee the transaction
+    /// is relayed, but a non-empty one means it will not be.
+    pub fn check_standard(&self, policy: &Policy) -> Vec<PolicyViolation> {
+        policy.check(self)
+    }
+
+    /// The version of this transaction
+    #[inline]
+    pub fn tx_version(&self) -> Version {
+        Version(self.version)
+    }
+
+    /// The lock time of this transaction
+    #[inline]
+    pub fn tx_lock_time(&self) -> LockTime {
+        LockTime(self.lock_time)
+    }
+
+    /// Predicts the weight of a transaction before it is signed.
+    ///
+    /// `inputs` describes the shape of each input once it has been satisfied,
+    /// and `output_script_lens` yields the length of each output's
+    /// `script_pubkey`. The result matches what `get_weight` reports for the
+    /// signed transaction whenever the predictions are exact, and is an upper
+    /// bound when they are maximums, as are the `InputWeightPrediction`
+    /// constants.
+    pub fn predict_weight<I, O>(inputs: I, output_script_lens: O) -> usize
+    where
+        I: IntoIterator<Item = InputWeightPrediction>,
+        O: IntoIterator<Item = usize>,
+    {
+        let mut input_count = 0;
+        let mut input_weight = 0;
+        let mut inputs_with_witnesses = 0;
+        for prediction in inputs {
+            input_count += 1;
+            input_weight += prediction.weight();
+            if prediction.witness_size > 0 {
+                inputs_with_witnesses += 1;
+            }
+        }
+        Transaction::weight_from_parts(
+            input_count,
+            input_weight,
+            inputs_with_witnesses,
+            output_script_lens,
+        )
+    }
+
+    /// Combines the precomputed weight of all inputs with the size of the
+    /// rest of the transaction, adding the segwit marker, flag and empty
+    /// witnesses if any input carries a witness.
+    fn weight_from_parts<O>(
+        input_count: usize,
+        input_weight: usize,
+        inputs_with_witnesses: usize,
+        output_script_lens: O,
+    ) -> usize
+    where
+        O: IntoIterator<Item = usize>,
+    {
+        let mut output_count = 0;
+        let mut output_size = 0;
+        for script_len in output_script_lens {
+            output_count += 1;
+            output_size += 8 + VarInt(script_len as u64).len() + script_len;
+        }
+        let non_input_size = 4
+            + VarInt(input_count as u64).len()
+            + VarInt(output_count as u64).len()
+            + output_size
+            + 4;
+        if inputs_with_witnesses == 0 {
+            non_input_size * 4 + input_weight
+        } else {
+            non_input_size * 4 + input_weight + input_count - inputs_with_witnesses + 2
+        }
+    }
+
+    /// Is this a coin base transaction?
+    #[deprecated(note = "use is_coinbase instead")]
+    pub fn is_coin_base(&self) -> bool {
+        self.is_coinbase()
+    }
+
+    /// Whether this is a coinbase transaction, the first transaction of a
+    /// block, which spends no coins but creates the block reward
+    pub fn is_coinbase(&self) -> bool {
+        self.input.len() == 1 && self.input[0].previous_output.is_null()
+    }
+
+    /// Sorts the inputs and outputs in the deterministic order of BIP69, so
+    /// that their order reveals nothing about the wallet which built the
+    /// transaction.
+    ///
+    /// Inputs are ordered by the txid they spend, compared as displayed in
+    /// hex, then by output index. Outputs are ordered by value, then by
+    /// `script_pubkey` bytes. This changes the txid, so sort before signing.
+    pub fn sort_bip69(&mut self) {
+        self.input.sort_by(|a, b| {
+            let a_txid = a.previous_output.txid[..].iter().rev();
+            let b_txid = b.previous_output.txid[..].iter().rev();
+            a_txid
+                .cmp(b_txid)
+                .then(a.previous_output.vout.cmp(&b.previous_output.vout))
+        });
+        self.output.sort_by(|a, b| {
+            a.value
+                .cmp(&b.value)
+                .then_with(|| a.script_pubkey.as_bytes().cmp(b.script_pubkey.as_bytes()))
+        });
+    }
+
+    /// Shuffles the inputs and outputs into a random order. This changes
+    /// the txid, so shuffle before signing.
+    pub fn shuffle<R: Rng>(&mut self, mut rng: R) {
+        rng.shuffle(&mut self.input);
+        rng.shuffle(&mut self.output);
+    }
+
+    /// Creates a coinbase transaction for the block at `height`.
+    ///
+    /// The `script_sig` pushes the height, as BIP34 requires, followed by
+    /// `extra_nonce`. If `witness_root`, the merkle root of the block's
+    /// wtxids, is given then the input gets the witness reserved value as its
+    /// witness and an output committing to the root is appended to `outputs`.
+    ///
+    /// # Panics
+    ///
+    /// If `extra_nonce` makes the `script_sig` longer than the 100 bytes
+    /// consensus allows.
+    pub fn new_coinbase(
+        height: u32,
+        extra_nonce: &[u8],
+        mut outputs: Vec<TxOut>,
+        witness_root: Option<WitnessMerkleNode>,
+    ) -> Transaction {
+        let script_sig = Builder::new()
+            .push_int(height as i64)
+            .push_slice(extra_nonce)
+            .into_script();
+        assert!(
+            script_sig.len() <= 100,
+            "coinbase script_sig is longer than 100 bytes"
+        );
+
+        let mut witness = vec![];
+        if let Some(root) = witness_root {
+            witness.push(WITNESS_RESERVED_VALUE.to_vec());
+            let mut engine = WitnessCommitment::engine();
+            engine.input(&root[..]);
+            engine.input(&WITNESS_RESERVED_VALUE);
+            let commitment = WitnessCommitment::from_engine(engine);
+            let mut data = WITNESS_COMMITMENT_HEADER.to_vec();
+            data.extend_from_slice(&commitment[..]);
+            outputs.push(TxOut {
+                value: 0,
+                script_pubkey: Builder::new()
+                    .push_opcode(opcodes::all::OP_RETURN)
+                    .push_slice(&data)
+                    .into_script(),
+            });
+        }
+
+        Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig,
+                sequence: u32::max_value(),
+                witness,
+            }],
+            output: outputs,
+        }
+    }
+
+    /// The block height a coinbase transaction commits to under BIP34, read
+    /// from the start of its `script_sig`.
+    ///
+    /// Returns `None` if this is not a coinbase or its `script_sig` does not
+    /// start by pushing a non-negative number of at most 4 bytes.
+    pub fn coinbase_height(&self) -> Option<u32> {
+        if !self.is_coinbase() {
+            return None;
+        }
+        let height = match self.input[0].script_sig.instructions().next() {
+            Some(Ok(Instruction::PushBytes(bytes))) => script::read_scriptint(bytes).ok()?,
+            Some(Ok(Instruction::Op(op))) => {
+                let n = op.into_u8();
+                if n >= opcodes::all::OP_PUSHNUM_1.into_u8()
+                    && n <= opcodes::all::OP_PUSHNUM_16.into_u8()
+                {
+                    (n - opcodes::all::OP_PUSHNUM_1.into_u8() + 1) as i64
+                } else {
+                    return None;
+                }
+            }
+            _ => return None,
+        };
+        if height < 0 {
+            None
+        } else {
+            Some(height as u32)
+        }
+    }
+
+    /// The witness commitment of a coinbase transaction, from the last
+    /// output which carries one
+    pub fn witness_commitment(&self) -> Option<WitnessCommitment> {
+        if !self.is_coinbase() {
+            return None;
+        }
+        self.output.iter().rev().find_map(|output| {
+            let script = output.script_pubkey.as_bytes();
+            if script.len() >= 38
+                && script[0] == opcodes::all::OP_RETURN.into_u8()
+                && script[1] == opcodes::all::OP_PUSHBYTES_36.into_u8()
+                && script[2..6] == WITNESS_COMMITMENT_HEADER
+            {
+                Some(WitnessCommitment::from_slice(&script[6..38]).expect("32 bytes"))
+            } else {
+                None
+            }
+        })
+    }
+}
+
+/// Describes how large a transaction input will be once it is satisfied,
+/// for use with `Transaction::predict_weight`.
+///
+/// The sizes include the length prefixes of the script and of every witness
+/// element, so that the weight of an input can be computed without having
+/// its signatures at hand.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct InputWeightPrediction {
+    script_size: usize,
+    witness_size: usize,
+}
+
+impl InputWeightPrediction {
+    /// Input weight prediction corresponding to spending of P2WPKH output with
+    /// the largest possible DER-encoded signature.
+    ///
+    /// If the input in your transaction uses P2WPKH you can use this instead of
+    /// `InputWeightPrediction::new`. The signature is assumed to be at most 72
+    /// bytes including the sighash byte, which holds for low-S signatures.
+    pub const P2WPKH_MAX: Self = InputWeightPrediction {
+        script_size: 1,
+        witness_size: 108,
+    };
+
+    /// Input weight prediction corresponding to spending of a P2WPKH output
+    /// nested in P2SH with the largest possible DER-encoded signature.
+    pub const NESTED_P2WPKH_MAX: Self = InputWeightPrediction {
+        script_size: 24,
+        witness_size: 108,
+    };
+
+    /// Input weight prediction corresponding to spending of a P2PKH output
+    /// with the largest possible DER-encoded signature and a compressed
+    /// public key.
+    pub const P2PKH_COMPRESSED_MAX: Self = InputWeightPrediction {
+        script_size: 108,
+        witness_size: 0,
+    };
+
+    /// Input weight prediction corresponding to spending of a P2PKH output
+    /// with the largest possible DER-encoded signature and an uncompressed
+    /// public key.
+    pub const P2PKH_UNCOMPRESSED_MAX: Self = InputWeightPrediction {
+        script_size: 140,
+        witness_size: 0,
+    };
+
+    /// Input weight prediction corresponding to a taproot key-path spend
+    /// using the default sighash, which omits the sighash byte.
+    pub const P2TR_KEY_DEFAULT_SIGHASH: Self = InputWeightPrediction {
+        script_size: 1,
+        witness_size: 66,
+    };
+
+    /// Input weight prediction corresponding to a taproot key-path spend
+    /// using any sighash other than the default.
+    pub const P2TR_KEY_NON_DEFAULT_SIGHASH: Self = InputWeightPrediction {
+        script_size: 1,
+        witness_size: 67,
+    };
+
+    /// Computes the prediction for a single input from the length of its
+    /// `script_sig` and the lengths of its witness elements.
+    ///
+    /// An empty iterator of witness elements describes an input without a
+    /// witness.
+    pub fn new<T>(input_script_len: usize, witness_element_lengths: T) -> Self
+    where
+        T: IntoIterator<Item = usize>,
+    {
+        let mut witness_count = 0;
+        let mut witness_elements_size = 0;
+        for elem_len in witness_element_lengths {
+            witness_count += 1;
+            witness_elements_size += VarInt(elem_len as u64).len() + elem_len;
+        }
+        let witness_size = if witness_count > 0 {
+            VarInt(witness_count as u64).len() + witness_elements_size
+        } else {
+            0
+        };
+        let script_size = VarInt(input_script_len as u64).len() + input_script_len;
+
+        InputWeightPrediction {
+            script_size,
+            witness_size,
+        }
+    }
+
+    /// The weight this input contributes to its transaction, not counting
+    /// the segwit marker and flag.
+    pub fn weight(&self) -> usize {
+        4 * (32 + 4 + 4 + self.script_size) + self.witness_size
+    }
+}
+
+/// The order a `TxBuilder` puts the inputs and outputs of its transaction
+/// in
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum TxOrdering {
+    /// The order they were added in
+    AsAdded,
+    /// The deterministic order of BIP69, see `Transaction::sort_bip69`
+    Bip69,
+    /// A random order
+    Shuffle,
+}
+
+impl Default for TxOrdering {
+    fn default() -> TxOrdering {
+        TxOrdering::AsAdded
+    }
+}
+
+/// An object which can be used to construct a transaction piece by piece
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TxBuilder {
+    version: u32,
+    lock_time: u32,
+    input: Vec<TxIn>,
+    output: Vec<TxOut>,
+    ordering: TxOrdering,
+}
+
+impl TxBuilder {
+    /// Creates a builder for a version 2 transaction with no inputs, no
+    /// outputs and no absolute timelock
+    pub fn new() -> TxBuilder {
+        TxBuilder {
+            version: 2,
+            lock_time: 0,
+            input: vec![],
+            output: vec![],
+            ordering: TxOrdering::AsAdded,
+        }
+    }
+
+    /// Sets the transaction version
+    pub fn version(mut self, version: u32) -> TxBuilder {
+        self.version = version;
+        self
+    }
+
+    /// Sets the absolute timelock of the transaction
+    pub fn lock_time(mut self, lock_time: u32) -> TxBuilder {
+        self.lock_time = lock_time;
+        self
+    }
+
+    /// Sets the order of the inputs and outputs of the transaction, which
+    /// is the order they are added in by default
+    pub fn ordering(mut self, ordering: TxOrdering) -> TxBuilder {
+        self.ordering = ordering;
+        self
+    }
+
+    /// Adds an input spending `previous_output`, with an empty `script_sig`
+    /// and the maximum sequence number
+    pub fn add_input(mut self, previous_output: OutPoint) -> TxBuilder {
+        self.input.push(TxIn {
+            previous_output,
+            ..Default::default()
+        });
+        self
+    }
+
+    /// Adds an output paying `value` satoshis to `script_pubkey`
+    pub fn add_output(mut self, value: u64, script_pubkey: Script) -> TxBuilder {
+        self.output.push(TxOut {
+            value,
+            script_pubkey,
+        });
+        self
+    }
+
+    /// Sets the relative timelock of the input at index `input`, counting
+    /// inputs in the order they were added whatever the `TxOrdering`.
+    ///
+    /// BIP68 relative timelocks are only enforced for transactions of
+    /// version 2 or higher, so the version is raised to 2 if necessary.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `input` is not the index of an input already added, or if
+    /// `sequence` does not encode a relative timelock.
+    pub fn set_relative_timelock(mut self, input: usize, sequence: Sequence) -> TxBuilder {
+        assert!(
+            sequence.is_relative_lock_time(),
+            "sequence number does not encode a relative timelock"
+        );
+        self.input[input].sequence = sequence.to_consensus_u32();
+        if self.version < 2 {
+            self.version = 2;
+        }
+        self
+    }
+
+    /// Converts the `TxBuilder` into a `Transaction`, shuffling with a
+    /// `DefaultRng` if the ordering is `TxOrdering::Shuffle`
+    pub fn into_transaction(self) -> Transaction {
+        self.into_transaction_with_rng(DefaultRng::new())
+    }
+
+    /// Converts the `TxBuilder` into a `Transaction`, shuffling with `rng`
+    /// if the ordering is `TxOrdering::Shuffle`
+    pub fn into_transaction_with_rng<R: Rng>(self, rng: R) -> Transaction {
+        let mut tx = Transaction {
+            version: self.version,
+            lock_time: self.lock_time,
+            input: self.input,
+            output: self.output,
+        };
+        match self.ordering {
+            TxOrdering::AsAdded => {}
+            TxOrdering::Bip69 => tx.sort_bip69(),
+            TxOrdering::Shuffle => tx.shuffle(rng),
+        }
+        tx
+    }
+}
+
+impl Default for TxBuilder {
+    fn default() -> TxBuilder {
+        TxBuilder::new()
+    }
+}
+
+/// Hashtype of a transaction, encoded in the last byte of a signature
+/// Fixed values so they can be casted as integer types for encoding
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub enum SigHashType {
+    /// 0x1: Sign all outputs
+    All = 0x01,
+    /// 0x2: Sign no outputs --- anyone can choose the destination
+    None = 0x02,
+    /// 0x3: Sign the output whose index matches this input's index. If none exists,
+    /// sign the hash `0000000000000000000000000000000000000000000000000000000000000001`.
+    /// (This rule is probably an unintentional C++ism, but it's consensus so we have
+    /// to follow it.)
+    Single = 0x03,
+    /// 0x81: Sign all outputs but only this input
+    AllPlusAnyoneCanPay = 0x81,
+    /// 0x82: Sign no outputs and only this input
+    NonePlusAnyoneCanPay = 0x82,
+    /// 0x83: Sign one output and only this input (see `Single` for what "one output" means)
+    SinglePlusAnyoneCanPay = 0x83,
+}
+
+impl SigHashType {
+    /// Break the sighash flag into the "real" sighash flag and the ANYONECANPAY boolean
+    pub(crate) fn split_anyonecanpay_flag(self) -> (SigHashType, bool) {
+        match self {
+            SigHashType::All => (SigHashType::All, false),
+            SigHashType::None => (SigHashType::None, false),
+            SigHashType::Single => (SigHashType::Single, false),
+            SigHashType::AllPlusAnyoneCanPay => (SigHashType::All, true),
+            SigHashType::NonePlusAnyoneCanPay => (SigHashType::None, true),
+            SigHashType::SinglePlusAnyoneCanPay => (SigHashType::Single, true),
+        }
+    }
+
+    /// Reads a 4-byte uint32 as a sighash type
+    pub fn from_u32(n: u32) -> SigHashType {
+        match n & 0x9f {
+            // "real" sighashes
+            0x01 => SigHashType::All,
+            0x02 => SigHashType::None,
+            0x03 => SigHashType::Single,
+            0x81 => SigHashType::AllPlusAnyoneCanPay,
+            0x82 => SigHashType::NonePlusAnyoneCanPay,
+            0x83 => SigHashType::SinglePlusAnyoneCanPay,
+            // catchalls
+            x if x & 0x80 == 0x80 => SigHashType::AllPlusAnyoneCanPay,
+            _ => SigHashType::All,
+        }
+    }
+
+    /// Converts to a u32
+    pub fn as_u32(self) -> u32 {
+        self as u32
+    }
+}
+
+impl Encodable for TxIn {
+    fn consensus_encode<S: io::Write>(&self, mut s: S) -> Result<usize, encode::Error> {
+        let mut len = 0;
+        len += self.previous_output.consensus_encode(&mut s)?;
+        len += self.script_sig.consensus_encode(&mut s)?;
+        len += self.sequence.consensus_encode(s)?;
+        Ok(len)
+    }
+}
+
+impl Decodable for TxIn {
+    fn consensus_decode<D: io::Read>(mut d: D) -> Result<Self, encode::Error> {
+        Ok(TxIn {
+            previous_output: trace_field!("previous_output", Decodable::consensus_decode(&mut d)?),
+            script_sig: trace_field!("script_sig", Decodable::consensus_decode(&mut d)?),
+            sequence: trace_field!("sequence", Decodable::consensus_decode(d)?),
+            witness: vec![],
+        })
+    }
+}
+
+impl_consensus_from_hex!(TxIn);
+
+impl Encodable for Transaction {
+    fn consensus_encode<S: io::Write>(&self, mut s: S) -> Result<usize, encode::Error> {
+        let mut len = 0;
+        len += self.version.consensus_encode(&mut s)?;
+        let mut have_witness = self.input.is_empty();
+        for input in &self.input {
+            if !input.witness.is_empty() {
+                have_witness = true;
+                break;
+            }
+        }
+        if !have_witness {
+            len += self.input.consensus_encode(&mut s)?;
+            len += self.output.consensus_encode(&mut s)?;
+        } else {
+            len += 0u8.consensus_encode(&mut s)?;
+            len += 1u8.consensus_encode(&mut s)?;
+            len += self.input.consensus_encode(&mut s)?;
+            len += self.output.consensus_encode(&mut s)?;
+            for input in &self.input {
+                len += input.witness.consensus_encode(&mut s)?;
+            }
+        }
+        len += self.lock_time.consensus_encode(s)?;
+        Ok(len)
+    }
+}
+
+impl Decodable for Transaction {
+    fn consensus_decode<D: io::Read>(mut d: D) -> Result<Self, encode::Error> {
+        let version = trace_field!("version", u32::consensus_decode(&mut d)?);
+        let input = trace_field!("inputs", Vec::<TxIn>::consensus_decode(&mut d)?);
+        // segwit
+        if input.is_empty() {
+            trace_relabel!("marker");
+            let segwit_flag = trace_field!("flag", u8::consensus_decode(&mut d)?);
+            match segwit_flag {
+                // BIP144 input witnesses
+                1 => {
+                    let mut input = trace_field!("inputs", Vec::<TxIn>::consensus_decode(&mut d)?);
+                    let output = trace_field!("outputs", Vec::<TxOut>::consensus_decode(&mut d)?);
+                    trace_field!("witnesses", {
+                        for (i, txin) in input.iter_mut().enumerate() {
+                            txin.witness = trace_field!([i], Decodable::consensus_decode(&mut d)?);
+                        }
+                    });
+                    if !input.is_empty() && input.iter().all(|input| input.witness.is_empty()) {
+                        Err(encode::Error::ParseFailed(
+                            "witness flag set but no witnesses present",
+                        ))
+                    } else {
+                        Ok(Transaction {
+                            version,
+                            input,
+                            output,
+                            lock_time: trace_field!("lock_time", Decodable::consensus_decode(d)?),
+                        })
+                    }
+                }
+                // We don't support anything else
+                x => Err(encode::Error::UnsupportedSegwitFlag(x)),
+            }
+        // non-segwit
+        } else {
+            Ok(Transaction {
+                version,
+                input,
+                output: trace_field!("outputs", Decodable::consensus_decode(&mut d)?),
+                lock_time: trace_field!("lock_time", Decodable::consensus_decode(d)?),
+            })
+        }
+    }
+}
+
+impl_consensus_from_hex!(Transaction);
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        InputType, InputWeightPrediction, LockTime, LockTimeError, OutPoint, ParseOutPointError,
+        Sequence, SequenceError, Transaction, TxBuilder, TxIn, TxOrdering, TxOut, Version,
+        TAPROOT_ANNEX_PREFIX, WITNESS_RESERVED_VALUE,
+    };
+
+    use std::convert::TryFrom;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use blockdata::script::Script;
+    use consensus::encode::{self, deserialize, serialize, serialize_hex};
+    use hash_types::{Txid, WitnessMerkleNode};
+    use hashes::hex::FromHex;
+    use hashes::{Hash, HashEngine};
+    use util::rng::SeededRng;
+
+    const SOME_TX: &'static str = "0100000001a15d57094aa7a21a28cb20b59aab8fc7d1149a3bdbcddba9c622e4f5f6a99ece010000006c493046022100f93bb0e7d8db7bd46e40132d1f8242026e045f03a0efe71bbb8e3f475e970d790221009337cd7f1f929f00cc6ff01f03729b069a7c21b59b1736ddfee5db5946c5da8c0121033b9b137ee87d5a812d6f506efdd37f0affa7ffc310711c06c7f3e097c9447c52ffffffff0100e1f505000000001976a9140389035a9225b3839e2bbf32d826a1e222031fd888ac00000000";
+
+    fn tx_with(inputs: Vec<TxIn>, output_script_lens: &[usize]) -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: 0,
+            input: inputs,
+            output: output_script_lens
+                .iter()
+                .map(|&len| TxOut {
+                    value: 1000,
+                    script_pubkey: Script::from(vec![0; len]),
+                })
+                .collect(),
+        }
+    }
+
+    fn input_with(script_len: usize, witness: &[usize]) -> TxIn {
+        TxIn {
+            script_sig: Script::from(vec![0; script_len]),
+            witness: witness.iter().map(|&len| vec![0; len]).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn outpoint_null() {
+        let null = OutPoint::null();
+        assert!(null.is_null());
+        assert_eq!(OutPoint::default(), null);
+        assert!(!OutPoint::new(Txid::hash(&[]), 0).is_null());
+        assert_eq!(
+            OutPoint::new(Default::default(), 1).to_string(),
+            "0000000000000000000000000000000000000000000000000000000000000000:1"
+        );
+    }
+
+    #[test]
+    fn outpoint_fromstr() {
+        roundtrip_fromstr_display!(
+            OutPoint;
+            OutPoint::null(),
+            OutPoint::new(Txid::hash(&[]), 0),
+            OutPoint::new(Txid::hash(&[1]), 10),
+        );
+
+        let txid = "e567952fb6cc33857f392efa3a46c995a28f69cca4bb1b37e0204dab1ec7a389";
+        let outpoint = OutPoint::new(Txid::from_hex(txid).unwrap(), 1);
+        assert_eq!(format!("{}:1", txid).parse(), Ok(outpoint));
+        assert_eq!(format!("{}:1", txid.to_uppercase()).parse(), Ok(outpoint));
+
+        let p = |s: String| s.parse::<OutPoint>();
+        assert_eq!(p(txid.to_owned()), Err(ParseOutPointError::Format));
+        assert_eq!(p(format!("{}:1:1", txid)), Err(ParseOutPointError::Format));
+        assert_eq!(
+            p(format!("{}:01", txid)),
+            Err(ParseOutPointError::VoutNotCanonical)
+        );
+        assert_eq!(
+            p(format!("{}:+1", txid)),
+            Err(ParseOutPointError::VoutNotCanonical)
+        );
+        match p(format!("{}:", txid)) {
+            Err(ParseOutPointError::Vout(_)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        match p(format!("{}:4294967296", txid)) {
+            Err(ParseOutPointError::Vout(_)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        match p(format!("{}:1", &txid[2..])) {
+            Err(ParseOutPointError::Txid(_)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn transaction() {
+        let hex_tx = Vec::<u8>::from_hex(SOME_TX).unwrap();
+        let realtx: Transaction = deserialize(&hex_tx).unwrap();
+
+        assert_eq!(realtx.version, 1);
+        assert_eq!(realtx.input.len(), 1);
+        assert_eq!(
+            format!("{:x}", realtx.input[0].previous_output.txid),
+            "ce9ea9f6f5e422c6a9dbcddb3b9a14d1c78fab9ab520cb281aa2a74a09575da1"
+        );
+        assert_eq!(realtx.input[0].previous_output.vout, 1);
+        assert_eq!(realtx.output.len(), 1);
+        assert_eq!(realtx.lock_time, 0);
+        assert!(!realtx.is_coinbase());
+
+        assert_eq!(
+            format!("{:x}", realtx.txid()),
+            "a6eab3c14ab5272a58a5ba91505ba1a4b6d7a3a9fcbd187b6cd99a7b6d548cb7"
+        );
+        assert_eq!(
+            format!("{:x}", realtx.wtxid()),
+            "a6eab3c14ab5272a58a5ba91505ba1a4b6d7a3a9fcbd187b6cd99a7b6d548cb7"
+        );
+        assert_eq!(realtx.get_weight(), hex_tx.len() * 4);
+        assert_eq!(serialize(&realtx), hex_tx);
+    }
+
+    #[test]
+    fn from_hex() {
+        let hex_tx = Vec::<u8>::from_hex(SOME_TX).unwrap();
+        let tx = Transaction::from_hex(SOME_TX).unwrap();
+        assert_eq!(tx, deserialize(&hex_tx).unwrap());
+        assert_eq!(Transaction::try_from(&hex_tx[..]).unwrap(), tx);
+        assert_eq!(
+            TxIn::from_hex(&serialize_hex(&tx.input[0])).unwrap(),
+            tx.input[0]
+        );
+        assert_eq!(
+            TxOut::try_from(&serialize(&tx.output[0])[..]).unwrap(),
+            tx.output[0]
+        );
+        assert_eq!(
+            OutPoint::from_hex(&serialize_hex(&tx.input[0].previous_output)).unwrap(),
+            tx.input[0].previous_output
+        );
+
+        match Transaction::from_hex(&SOME_TX[1..]) {
+            Err(encode::Error::Hex(_)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        match Transaction::from_hex(&format!("{}00", SOME_TX)) {
+            Err(encode::Error::ParseFailed(_)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(Transaction::try_from(&hex_tx[..hex_tx.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn coinbase_height() {
+        let coinbase_with = |script: Script| Transaction {
+            version: 1,
+            lock_time: 0,
+            input: vec![TxIn {
+                script_sig: script,
+                ..Default::default()
+            }],
+            output: vec![],
+        };
+        // The first BIP34 coinbase, of block 227836
+        let tx = coinbase_with(hex_script!("03fc7903062f503253482f"));
+        assert!(tx.is_coinbase());
+        assert_eq!(tx.coinbase_height(), Some(227_836));
+
+        assert_eq!(coinbase_with(hex_script!("00")).coinbase_height(), Some(0));
+        assert_eq!(coinbase_with(hex_script!("5a")).coinbase_height(), Some(10));
+        assert_eq!(coinbase_with(hex_script!("0181")).coinbase_height(), None);
+        assert_eq!(coinbase_with(hex_script!("4f")).coinbase_height(), None);
+        assert_eq!(coinbase_with(hex_script!("03fc79")).coinbase_height(), None);
+        assert_eq!(coinbase_with(Script::new()).coinbase_height(), None);
+
+        let mut spend = coinbase_with(hex_script!("03fc7903"));
+        spend.input[0].previous_output = OutPoint::new(Txid::hash(&[]), 0);
+        assert!(!spend.is_coinbase());
+        assert_eq!(spend.coinbase_height(), None);
+    }
+
+    #[test]
+    fn new_coinbase() {
+        let reward = TxOut {
+            value: 625_000_000,
+            script_pubkey: hex_script!("0014751e76e8199196d454941c45d1b3a323f1433bd6"),
+        };
+        let tx = Transaction::new_coinbase(700_000, &[1, 2, 3, 4], vec![reward.clone()], None);
+        assert!(tx.is_coinbase());
+        assert_eq!(tx.input[0].script_sig, hex_script!("0360ae0a0401020304"));
+        assert_eq!(tx.coinbase_height(), Some(700_000));
+        assert!(tx.input[0].witness.is_empty());
+        assert_eq!(tx.output, vec![reward.clone()]);
+        assert_eq!(tx.witness_commitment(), None);
+
+        let root = WitnessMerkleNode::hash(&[0xab; 32]);
+        let tx = Transaction::new_coinbase(1, &[], vec![reward.clone()], Some(root));
+        assert_eq!(tx.coinbase_height(), Some(1));
+        assert_eq!(tx.input[0].witness, vec![WITNESS_RESERVED_VALUE.to_vec()]);
+        assert_eq!(tx.output.len(), 2);
+        assert_eq!(tx.output[0], reward);
+        assert_eq!(tx.output[1].value, 0);
+        assert_eq!(tx.output[1].script_pubkey.len(), 38);
+        assert_eq!(
+            &tx.output[1].script_pubkey[..6],
+            &[0x6a, 0x24, 0xaa, 0x21, 0xa9, 0xed]
+        );
+        let mut engine = WitnessMerkleNode::engine();
+        engine.input(&root[..]);
+        engine.input(&[0; 32]);
+        assert_eq!(
+            tx.witness_commitment().unwrap()[..],
+            WitnessMerkleNode::from_engine(engine)[..]
+        );
+        assert_eq!(deserialize::<Transaction>(&serialize(&tx)).unwrap(), tx);
+    }
+
+    #[test]
+    #[should_panic]
+    fn new_coinbase_oversized() {
+        Transaction::new_coinbase(1, &[0; 100], vec![], None);
+    }
+
+    #[test]
+    fn segwit_round_trip() {
+        let tx = tx_with(vec![input_with(0, &[72, 33]), input_with(107, &[])], &[22]);
+        let ser = serialize(&tx);
+        // marker and flag
+        assert_eq!(&ser[4..6], &[0, 1]);
+        let decoded: Transaction = deserialize(&ser).unwrap();
+        assert_eq!(decoded, tx);
+        assert_ne!(tx.txid().into_inner(), tx.wtxid().into_inner());
+
+        let mut stripped = tx.clone();
+        for input in stripped.input.iter_mut() {
+            input.witness.clear();
+        }
+        let base_size = serialize(&stripped).len();
+        assert_eq!(tx.get_weight(), base_size * 3 + ser.len());
+        assert_eq!(tx.get_stripped_size(), base_size);
+        assert_eq!(stripped.txid(), tx.txid());
+
+        // Flag set without any witness data
+        let legacy = serialize(&stripped);
+        let mut bad = legacy[..4].to_vec();
+        bad.extend(&[0, 1]);
+        bad.extend(&legacy[4..legacy.len() - 4]);
+        bad.extend(&[0, 0]);
+        bad.extend(&legacy[legacy.len() - 4..]);
+        assert!(deserialize::<Transaction>(&bad).is_err());
+    }
+
+    #[test]
+    fn txin_sizes() {
+        let legacy = input_with(107, &[]);
+        assert_eq!(legacy.base_size(), 148);
+        assert_eq!(legacy.witness_size(), 0);
+        assert_eq!(legacy.total_size(), 148);
+        assert_eq!(legacy.segwit_weight(), 592);
+
+        let p2wpkh = input_with(0, &[72, 33]);
+        assert_eq!(p2wpkh.base_size(), 41);
+        assert_eq!(p2wpkh.witness_size(), 108);
+        assert_eq!(p2wpkh.total_size(), 149);
+        assert_eq!(p2wpkh.segwit_weight(), 272);
+
+        let tx: Transaction = deserialize(&Vec::from_hex(SOME_TX).unwrap()).unwrap();
+        assert_eq!(tx.input[0].total_size(), serialize(&tx.input[0]).len());
+    }
+
+    #[test]
+    fn input_type_max_satisfaction() {
+        // The largest satisfactions, with 72-byte signatures and 33-byte
+        // keys, but 65-byte keys for P2PKH
+        let inputs = [
+            (InputType::P2pk, input_with(73, &[])),
+            (InputType::P2pkh, input_with(73 + 66, &[])),
+            (InputType::P2shP2wpkh, input_with(23, &[72, 33])),
+            (InputType::P2wpkh, input_with(0, &[72, 33])),
+            (InputType::P2tr, input_with(0, &[65])),
+        ];
+        // Leaving out the outpoint and sequence number
+        for &(input_type, ref input) in inputs.iter() {
+            assert_eq!(
+                input_type.max_satisfaction_weight(),
+                Some(input.segwit_weight() - 4 * 40)
+            );
+            assert_eq!(
+                input_type.max_satisfaction_size(),
+                Some(input.total_size() - 40)
+            );
+            let prediction = input_type.max_weight_prediction().unwrap();
+            assert_eq!(prediction.weight(), input.segwit_weight());
+        }
+        assert_eq!(InputType::P2wpkh.max_satisfaction_weight(), Some(4 + 108));
+        assert_eq!(InputType::P2wpkh.max_satisfaction_size(), Some(1 + 108));
+
+        for &input_type in [
+            InputType::P2sh,
+            InputType::P2shP2wsh,
+            InputType::P2wsh,
+            InputType::WitnessUnknown,
+            InputType::NonStandard,
+        ]
+        .iter()
+        {
+            assert_eq!(input_type.max_satisfaction_weight(), None);
+            assert_eq!(input_type.max_satisfaction_size(), None);
+        }
+    }
+
+    #[test]
+    fn txin_input_type() {
+        let key_hash = "751e76e8199196d454941c45d1b3a323f1433bd6";
+        let script_hash = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+        let spent = |hex: String| Script::from(Vec::from_hex(&hex).unwrap());
+        let input = TxIn::default();
+
+        let p2pk = spent(format!("21{}ac", "02".repeat(33)));
+        assert_eq!(input.input_type(&p2pk), InputType::P2pk);
+        let p2pkh = spent(format!("76a914{}88ac", key_hash));
+        assert_eq!(input.input_type(&p2pkh), InputType::P2pkh);
+        let p2wpkh = spent(format!("0014{}", key_hash));
+        assert_eq!(input.input_type(&p2wpkh), InputType::P2wpkh);
+        let p2wsh = spent(format!("0020{}", script_hash));
+        assert_eq!(input.input_type(&p2wsh), InputType::P2wsh);
+        let p2tr = spent(format!("5120{}", script_hash));
+        assert_eq!(input.input_type(&p2tr), InputType::P2tr);
+        let future = spent(format!("5220{}", script_hash));
+        assert_eq!(input.input_type(&future), InputType::WitnessUnknown);
+        let multisig = spent(format!("5121{}51ae", "02".repeat(33)));
+        assert_eq!(input.input_type(&multisig), InputType::NonStandard);
+
+        let p2sh = spent(format!("a914{}87", key_hash));
+        assert_eq!(input.input_type(&p2sh), InputType::P2sh);
+        let nested = |redeem: &str| TxIn {
+            script_sig: spent(format!("{:02x}{}", redeem.len() / 2, redeem)),
+            ..Default::default()
+        };
+        assert_eq!(
+            nested(&format!("0014{}", key_hash)).input_type(&p2sh),
+            InputType::P2shP2wpkh
+        );
+        assert_eq!(
+            nested(&format!("0020{}", script_hash)).input_type(&p2sh),
+            InputType::P2shP2wsh
+        );
+        assert_eq!(nested("51").input_type(&p2sh), InputType::P2sh);
+    }
+
+    #[test]
+    fn taproot_witness() {
+        let with_witness = |witness: Vec<Vec<u8>>| TxIn {
+            witness,
+            ..Default::default()
+        };
+        let signature = vec![1; 64];
+        let annex = vec![TAPROOT_ANNEX_PREFIX, 0xaa];
+        let script = vec![0x51];
+        let control_block = vec![0xc0; 33 + 32];
+
+        // Key path spends
+        let key_path = with_witness(vec![signature.clone()]);
+        assert_eq!(key_path.taproot_annex(), None);
+        assert_eq!(key_path.taproot_control_block(), None);
+        assert_eq!(key_path.tapscript(), None);
+        let key_path = with_witness(vec![signature.clone(), annex.clone()]);
+        assert_eq!(key_path.taproot_annex(), Some(&annex[..]));
+        assert_eq!(key_path.taproot_control_block(), None);
+        assert_eq!(key_path.tapscript(), None);
+        // A lone element is never an annex
+        assert_eq!(with_witness(vec![annex.clone()]).taproot_annex(), None);
+
+        // Script path spends
+        let script_path = with_witness(vec![
+            signature.clone(),
+            script.clone(),
+            control_block.clone(),
+            annex.clone(),
+        ]);
+        assert_eq!(script_path.taproot_annex(), Some(&annex[..]));
+        assert_eq!(
+            script_path.taproot_control_block(),
+            Some(&control_block[..])
+        );
+        assert_eq!(script_path.tapscript(), Some(Script::from(script.clone())));
+        let script_path = with_witness(vec![script.clone(), control_block.clone()]);
+        assert_eq!(script_path.taproot_annex(), None);
+        assert_eq!(script_path.tapscript(), Some(Script::from(script.clone())));
+
+        // A last element of the wrong length is no control block
+        let bad = with_witness(vec![script.clone(), vec![0xc0; 34]]);
+        assert_eq!(bad.taproot_control_block(), None);
+        assert_eq!(bad.tapscript(), None);
+    }
+
+    #[test]
+    fn predict_weight_exact() {
+        // P2PKH with a compressed key: 1-byte push + 72-byte signature,
+        // 1-byte push + 33-byte key.
+        let p2pkh = tx_with(vec![input_with(107, &[])], &[25]);
+        let predicted =
+            Transaction::predict_weight(vec![InputWeightPrediction::new(107, vec![])], vec![25]);
+        assert_eq!(predicted, p2pkh.get_weight());
+        assert_eq!(
+            Transaction::predict_weight(
+                vec![InputWeightPrediction::P2PKH_COMPRESSED_MAX],
+                vec![25]
+            ),
+            p2pkh.get_weight()
+        );
+
+        let p2wpkh = tx_with(vec![input_with(0, &[72, 33])], &[22, 34]);
+        assert_eq!(
+            Transaction::predict_weight(vec![InputWeightPrediction::P2WPKH_MAX], vec![22, 34]),
+            p2wpkh.get_weight()
+        );
+
+        let nested = tx_with(vec![input_with(23, &[72, 33])], &[23]);
+        assert_eq!(
+            Transaction::predict_weight(vec![InputWeightPrediction::NESTED_P2WPKH_MAX], vec![23]),
+            nested.get_weight()
+        );
+
+        let uncompressed = tx_with(vec![input_with(139, &[])], &[25]);
+        assert_eq!(
+            Transaction::predict_weight(
+                vec![InputWeightPrediction::P2PKH_UNCOMPRESSED_MAX],
+                vec![25]
+            ),
+            uncompressed.get_weight()
+        );
+
+        let taproot = tx_with(vec![input_with(0, &[64]), input_with(0, &[65])], &[34]);
+        assert_eq!(
+            Transaction::predict_weight(
+                vec![
+                    InputWeightPrediction::P2TR_KEY_DEFAULT_SIGHASH,
+                    InputWeightPrediction::P2TR_KEY_NON_DEFAULT_SIGHASH,
+                ],
+                vec![34]
+            ),
+            taproot.get_weight()
+        );
+    }
+
+    #[test]
+    fn predict_weight_mixed() {
+        // A legacy input next to a segwit one still pays for its empty witness
+        let mixed = tx_with(
+            vec![
+                input_with(107, &[]),
+                input_with(0, &[72, 33]),
+                input_with(0, &[]),
+            ],
+            &[25, 22],
+        );
+        let predicted = Transaction::predict_weight(
+            vec![
+                InputWeightPrediction::P2PKH_COMPRESSED_MAX,
+                InputWeightPrediction::P2WPKH_MAX,
+                InputWeightPrediction::new(0, vec![]),
+            ],
+            vec![25, 22],
+        );
+        assert_eq!(predicted, mixed.get_weight());
+
+        let custom = tx_with(vec![input_with(300, &[0, 71, 71, 300])], &[34]);
+        assert_eq!(
+            Transaction::predict_weight(
+                vec![InputWeightPrediction::new(300, vec![0, 71, 71, 300])],
+                vec![34]
+            ),
+            custom.get_weight()
+        );
+        assert_eq!(InputWeightPrediction::P2WPKH_MAX.weight(), 272);
+    }
+
+    #[test]
+    fn sequence_relative_timelock() {
+        let height = Sequence::from_height(144);
+        assert_eq!(height.to_consensus_u32(), 144);
+        assert!(height.is_relative_lock_time());
+        assert!(height.is_height_locked());
+        assert!(!height.is_time_locked());
+
+        let time = Sequence::from_seconds(512 * 10).unwrap();
+        assert_eq!(u32::from(time), 0x0040000a);
+        assert!(time.is_time_locked());
+        assert!(!time.is_height_locked());
+        assert_eq!(Sequence::from_seconds(512 * 0xFFFF).unwrap().0, 0x0040ffff);
+
+        assert_eq!(
+            Sequence::from_seconds(1000),
+            Err(SequenceError::NotMultipleOf512(1000))
+        );
+        assert_eq!(
+            Sequence::from_seconds(512 * 0x10000),
+            Err(SequenceError::IntervalTooLarge(512 * 0x10000))
+        );
+
+        assert_eq!(Sequence::default(), Sequence::MAX);
+        assert!(!Sequence::MAX.is_relative_lock_time());
+        assert!(!Sequence::MAX.is_height_locked());
+    }
+
+    #[test]
+    fn sequence_durations() {
+        let height = Sequence::from_height(144);
+        assert_eq!(height.height_lock(), Some(144));
+        assert_eq!(height.time_lock(), None);
+        assert_eq!(
+            height.estimated_duration(),
+            Some(Duration::from_secs(86400))
+        );
+        assert_eq!(height.estimated_blocks(), Some(144));
+
+        let time = Sequence::from_duration_ceil(Duration::from_secs(86400)).unwrap();
+        assert_eq!(time, Sequence::from_seconds(512 * 169).unwrap());
+        assert_eq!(time.height_lock(), None);
+        assert_eq!(time.time_lock(), Some(Duration::from_secs(512 * 169)));
+        assert_eq!(time.estimated_blocks(), Some(145));
+        assert_eq!(
+            Sequence::from_duration_ceil(Duration::from_millis(512_001)).unwrap(),
+            Sequence::from_seconds(1024).unwrap()
+        );
+        assert_eq!(
+            Sequence::from_duration_ceil(Duration::from_secs(512)).unwrap(),
+            Sequence::from_seconds(512).unwrap()
+        );
+        assert_eq!(
+            Sequence::from_duration_ceil(Duration::from_secs(512 * 0x10000)),
+            Err(SequenceError::IntervalTooLarge(512 * 0x10000))
+        );
+
+        assert_eq!(Sequence::MAX.height_lock(), None);
+        assert_eq!(Sequence::MAX.estimated_duration(), None);
+    }
+
+    #[test]
+    fn lock_time() {
+        let height = LockTime::from_height(800_000).unwrap();
+        assert!(height.is_block_height());
+        assert_eq!(height.height(), Some(800_000));
+        assert_eq!(height.time(), None);
+        assert!(!height.is_final(800_000, 2_000_000_000));
+        assert!(height.is_final(800_001, 0));
+        assert_eq!(height.estimated_height(799_990, 0), 800_001);
+        assert_eq!(height.estimated_height(900_000, 0), 900_001);
+        let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        assert_eq!(
+            height.estimated_time(799_990, 0, now),
+            now + Duration::from_secs(11 * 600)
+        );
+        assert_eq!(
+            LockTime::from_height(LockTime::THRESHOLD),
+            Err(LockTimeError::InvalidHeight(LockTime::THRESHOLD))
+        );
+
+        let at = UNIX_EPOCH + Duration::from_millis(1_700_000_000_500);
+        let time = LockTime::from_time(at).unwrap();
+        assert_eq!(time, LockTime(1_700_000_000));
+        assert!(time.is_block_time());
+        assert_eq!(time.height(), None);
+        assert_eq!(
+            time.time(),
+            Some(UNIX_EPOCH + Duration::from_secs(1_700_000_000))
+        );
+        assert!(!time.is_final(1, 1_700_000_000));
+        assert!(time.is_final(1, 1_700_000_001));
+        assert_eq!(time.estimated_height(100, 1_700_000_001), 101);
+        assert_eq!(time.estimated_height(100, 1_700_000_000), 102);
+        assert_eq!(time.estimated_height(100, 1_699_996_400), 108);
+
+        let early = UNIX_EPOCH + Duration::from_secs(u64::from(LockTime::THRESHOLD) - 1);
+        assert_eq!(
+            LockTime::from_time(early),
+            Err(LockTimeError::InvalidTime(early))
+        );
+        let late = UNIX_EPOCH + Duration::from_secs(1 << 32);
+        assert_eq!(
+            LockTime::from_time(late),
+            Err(LockTimeError::InvalidTime(late))
+        );
+        assert_eq!(u32::from(LockTime::ZERO), 0);
+        assert!(LockTime::ZERO.is_final(1, 0));
+
+        let tx: Transaction = deserialize(&Vec::<u8>::from_hex(SOME_TX).unwrap()).unwrap();
+        assert_eq!(tx.tx_lock_time(), LockTime::ZERO);
+    }
+
+    #[test]
+    fn version() {
+        assert!(!Version(0).is_standard());
+        assert!(Version::ONE.is_standard());
+        assert!(Version::TWO.is_standard());
+        assert!(Version::THREE.is_standard());
+        assert!(!Version(4).is_standard());
+        assert!(!Version(0xffffffff).is_standard());
+
+        assert!(Version::THREE.is_truc());
+        assert!(!Version::TWO.is_truc());
+
+        assert!(!Version::ONE.enforces_relative_lock_time());
+        assert!(Version::TWO.enforces_relative_lock_time());
+        assert!(Version(0x7fffffff).enforces_relative_lock_time());
+        assert!(!Version(0xffffffff).enforces_relative_lock_time());
+
+        let tx: Transaction = deserialize(&Vec::<u8>::from_hex(SOME_TX).unwrap()).unwrap();
+        assert_eq!(tx.tx_version(), Version::ONE);
+        assert_eq!(u32::from(Version::THREE), 3);
+        assert_eq!(Version::from(3), Version::THREE);
+        assert_eq!(tx.get_vsize(), 193);
+    }
+
+    #[test]
+    fn tx_builder_relative_timelock() {
+        let outpoint = OutPoint::new(Txid::hash(&[1]), 0);
+        let tx = TxBuilder::new()
+            .version(1)
+            .add_input(outpoint)
+            .add_input(OutPoint::new(Txid::hash(&[2]), 1))
+            .add_output(1000, Script::new())
+            .set_relative_timelock(1, Sequence::from_height(10))
+            .into_transaction();
+
+        assert_eq!(tx.version, 2);
+        assert_eq!(tx.lock_time, 0);
+        assert_eq!(tx.input[0].previous_output, outpoint);
+        assert_eq!(tx.input[0].sequence, 0xFFFFFFFF);
+        assert_eq!(tx.input[1].sequence, 10);
+        assert_eq!(tx.output[0].value, 1000);
+    }
+
+    #[test]
+    fn tx_builder_ordering() {
+        // Displayed as 01000..., stored as ...00001
+        let low_end = Txid::from_hex(&format!("01{}", "00".repeat(31))).unwrap();
+        // Displayed as ...00002, stored as 02000...
+        let low_start = Txid::from_hex(&format!("{}02", "00".repeat(31))).unwrap();
+        let p2 = Script::from(vec![2]);
+        let p1 = Script::from(vec![1]);
+        let builder = TxBuilder::new()
+            .add_input(OutPoint::new(low_end, 0))
+            .add_input(OutPoint::new(low_start, 1))
+            .add_input(OutPoint::new(low_start, 0))
+            .add_output(2000, Script::new())
+            .add_output(1000, p2.clone())
+            .add_output(1000, p1.clone())
+            .set_relative_timelock(0, Sequence::from_height(10));
+
+        let tx = builder.clone().into_transaction();
+        assert_eq!(tx.input[0].previous_output.txid, low_end);
+        assert_eq!(tx.output[0].value, 2000);
+
+        let tx = builder
+            .clone()
+            .ordering(TxOrdering::Bip69)
+            .into_transaction();
+        let outpoints: Vec<_> = tx.input.iter().map(|i| i.previous_output).collect();
+        assert_eq!(
+            outpoints,
+            vec![
+                OutPoint::new(low_start, 0),
+                OutPoint::new(low_start, 1),
+                OutPoint::new(low_end, 0),
+            ]
+        );
+        assert_eq!(tx.input[2].sequence, 10);
+        let outputs: Vec<_> = tx
+            .output
+            .iter()
+            .map(|o| (o.value, o.script_pubkey.clone()))
+            .collect();
+        assert_eq!(outputs, vec![(1000, p1), (1000, p2), (2000, Script::new())]);
+
+        // Shuffling is reproducible with a seeded rng, and only reorders
+        let shuffled = builder.clone().ordering(TxOrdering::Shuffle);
+        let mut tx = shuffled
+            .clone()
+            .into_transaction_with_rng(SeededRng::new(7));
+        assert_eq!(tx, shuffled.into_transaction_with_rng(SeededRng::new(7)));
+        tx.sort_bip69();
+        assert_eq!(tx, builder.ordering(TxOrdering::Bip69).into_transaction());
+    }
+
+    #[test]
+    #[should_panic]
+    fn tx_builder_relative_timelock_missing_input() {
+        TxBuilder::new().set_relative_timelock(0, Sequence::from_height(1));
+    }
+}
@@ -0,0 +1,489 @@
+//! Undo Data
+//!
+//! When a block is connected, the outputs its transactions spend are removed
+//! from the UTXO set. The undo data of a block records those outputs, so the
+//! block can be disconnected again during a reorganization.
+//!
+//! The serialization matches the undo files (`rev*.dat`) of Bitcoin Core,
+//! which store the spent outputs in a compressed form.
+
+use std::io;
+
+use blockdata::opcodes;
+use blockdata::script::{Builder, Script};
+use blockdata::transaction::{OutPoint, Transaction, TxOut, COINBASE_MATURITY};
+use consensus::encode::{self, Decodable, Encodable, ReadExt, WriteExt};
+
+/// Scripts longer than this can never be spent, and are stored as a bare
+/// `OP_RETURN` when read back.
+const MAX_SCRIPT_SIZE: u64 = 10_000;
+
+/// The number of special script encodings in the compressed form; the size
+/// of any other script is stored offset by this.
+const SPECIAL_SCRIPTS: u64 = 6;
+
+/// An unspent output together with the metadata the UTXO set keeps for it.
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub struct Coin {
+    /// The output itself
+    pub output: TxOut,
+    /// The height of the block containing the transaction which created
+    /// the output
+    pub height: u32,
+    /// Whether the output was created by a coinbase transaction
+    pub is_coinbase: bool,
+}
+
+impl Coin {
+    /// Whether the coin may be spent by a transaction in the block at
+    /// `spend_height`. Coinbase outputs have to wait `COINBASE_MATURITY`
+    /// blocks; all other outputs can be spent right away.
+    pub fn is_mature(&self, spend_height: u32) -> bool {
+        !self.is_coinbase || spend_height.saturating_sub(self.height) >= COINBASE_MATURITY
+    }
+}
+
+/// The outputs spent by a transaction, in the order of its inputs.
+#[derive(Clone, PartialEq, Eq, Debug, Hash, Default)]
+pub struct TxUndo {
+    /// The coin spent by each input
+    pub prevouts: Vec<Coin>,
+}
+
+/// The outputs spent by the transactions of a block, in block order. The
+/// coinbase transaction spends nothing and has no entry.
+#[derive(Clone, PartialEq, Eq, Debug, Hash, Default)]
+pub struct BlockUndo {
+    /// The undo data of every transaction but the coinbase
+    pub txs: Vec<TxUndo>,
+}
+
+impl TxUndo {
+    /// Build the undo data of `tx` while spending its inputs, taking the
+    /// coin each input spends out of the UTXO set through `spend`.
+    ///
+    /// Returns the first previous output `spend` has no coin for as an
+    /// error.
+    pub fn from_spent_coins<F>(tx: &Transaction, mut spend: F) -> Result<TxUndo, OutPoint>
+    where
+        F: FnMut(&OutPoint) -> Option<Coin>,
+    {
+        let mut prevouts = Vec::with_capacity(tx.input.len());
+        for input in tx.input.iter() {
+            match spend(&input.previous_output) {
+                Some(coin) => prevouts.push(coin),
+                None => return Err(input.previous_output),
+            }
+        }
+        Ok(TxUndo { prevouts })
+    }
+
+    /// The coins to put back into the UTXO set when disconnecting `tx`,
+    /// paired with the previous outputs they are restored at.
+    ///
+    /// # Panics
+    ///
+    /// If this is not the undo data of `tx`, i.e. it has a different number
+    /// of coins than `tx` has inputs.
+    pub fn restored_coins<'a>(&'a self, tx: &Transaction) -> Vec<(OutPoint, &'a Coin)> {
+        assert_eq!(
+            self.prevouts.len(),
+            tx.input.len(),
+            "undo data does not match the transaction"
+        );
+        tx.input
+            .iter()
+            .map(|input| input.previous_output)
+            .zip(self.prevouts.iter())
+            .collect()
+    }
+}
+
+/// Write `n` in the variable-length integer format of Bitcoin Core's
+/// on-disk data, which is distinct from the `VarInt` of the network
+/// protocol: big-endian base-128 digits, where each digit but the last has
+/// its high bit set and is one less than its value.
+fn write_disk_varint<S: io::Write>(mut s: S, mut n: u64) -> Result<usize, encode::Error> {
+    let mut tmp = [0u8; 10];
+    let mut len = 0;
+    loop {
+        tmp[len] = (n & 0x7f) as u8 | if len > 0 { 0x80 } else { 0x00 };
+        if n <= 0x7f {
+            break;
+        }
+        n = (n >> 7) - 1;
+        len += 1;
+    }
+    for i in (0..len + 1).rev() {
+        s.emit_u8(tmp[i])?;
+    }
+    Ok(len + 1)
+}
+
+/// Read a variable-length integer written by `write_disk_varint`.
+fn read_disk_varint<D: io::Read>(mut d: D) -> Result<u64, encode::Error> {
+    let mut n: u64 = 0;
+    loop {
+        let ch = d.read_u8()?;
+        if n > (u64::max_value() >> 7) {
+            return Err(encode::Error::ParseFailed("disk varint is too large"));
+        }
+        n = (n << 7) | (ch & 0x7f) as u64;
+        if ch & 0x80 == 0 {
+            return Ok(n);
+        }
+        if n == u64::max_value() {
+            return Err(encode::Error::ParseFailed("disk varint is too large"));
+        }
+        n += 1;
+    }
+}
+
+/// Compress an amount by removing trailing decimal zeros, which most
+/// amounts in practice have.
+fn compress_amount(mut n: u64) -> u64 {
+    if n == 0 {
+        return 0;
+    }
+    let mut e = 0;
+    while n % 10 == 0 && e < 9 {
+        n /= 10;
+        e += 1;
+    }
+    if e < 9 {
+        let d = n % 10;
+        n /= 10;
+        1 + (n * 9 + d - 1) * 10 + e
+    } else {
+        1 + (n - 1) * 10 + 9
+    }
+}
+
+/// Reverse `compress_amount`.
+fn decompress_amount(mut x: u64) -> u64 {
+    if x == 0 {
+        return 0;
+    }
+    x -= 1;
+    let mut e = x % 10;
+    x /= 10;
+    let mut n = if e < 9 {
+        let d = (x % 9) + 1;
+        x /= 9;
+        x.wrapping_mul(10).wrapping_add(d)
+    } else {
+        x.wrapping_add(1)
+    };
+    while e > 0 {
+        n = n.wrapping_mul(10);
+        e -= 1;
+    }
+    n
+}
+
+/// Write a script in compressed form: P2PKH, P2SH and compressed-key P2PK
+/// scripts are stored as a type byte and the hash or key, anything else as
+/// its size plus `SPECIAL_SCRIPTS` followed by the script.
+///
+/// Uncompressed-key P2PK scripts have special encodings too, but those
+/// need the key to be checked for validity, so they are written in full.
+/// Bitcoin Core reads either form.
+fn write_compressed_script<S: io::Write>(
+    mut s: S,
+    script: &Script,
+) -> Result<usize, encode::Error> {
+    let bytes = script.as_bytes();
+    if script.is_p2pkh() {
+        s.emit_u8(0x00)?;
+        s.emit_slice(&bytes[3..23])?;
+        Ok(21)
+    } else if script.is_p2sh() {
+        s.emit_u8(0x01)?;
+        s.emit_slice(&bytes[2..22])?;
+        Ok(21)
+    } else if bytes.len() == 35 && script.is_p2pk() && (bytes[1] == 0x02 || bytes[1] == 0x03) {
+        s.emit_u8(bytes[1])?;
+        s.emit_slice(&bytes[2..34])?;
+        Ok(33)
+    } else {
+        let len = write_disk_varint(&mut s, bytes.len() as u64 + SPECIAL_SCRIPTS)?;
+        s.emit_slice(bytes)?;
+        Ok(len + bytes.len())
+    }
+}
+
+/// Read a script written by `write_compressed_script`.
+fn read_compressed_script<D: io::Read>(mut d: D) -> Result<Script, encode::Error> {
+    let size = read_disk_varint(&mut d)?;
+    match size {
+        0x00 => {
+            let mut hash = [0u8; 20];
+            d.read_slice(&mut hash)?;
+            Ok(Builder::new()
+                .push_opcode(opcodes::all::OP_DUP)
+                .push_opcode(opcodes::all::OP_HASH160)
+                .push_slice(&hash)
+                .push_opcode(opcodes::all::OP_EQUALVERIFY)
+                .push_opcode(opcodes::all::OP_CHECKSIG)
+                .into_script())
+        }
+        0x01 => {
+            let mut hash = [0u8; 20];
+            d.read_slice(&mut hash)?;
+            Ok(Builder::new()
+                .push_opcode(opcodes::all::OP_HASH160)
+                .push_slice(&hash)
+                .push_opcode(opcodes::all::OP_EQUAL)
+                .into_script())
+        }
+        0x02 | 0x03 => {
+            let mut key = [0u8; 33];
+            key[0] = size as u8;
+            d.read_slice(&mut key[1..])?;
+            Ok(Builder::new()
+                .push_slice(&key)
+                .push_opcode(opcodes::all::OP_CHECKSIG)
+                .into_script())
+        }
+        0x04 | 0x05 => Err(encode::Error::ParseFailed(
+            "decompressing uncompressed public keys is not supported",
+        )),
+        _ => {
+            let len = size - SPECIAL_SCRIPTS;
+            if len > MAX_SCRIPT_SIZE {
+                // Unspendable anyway, so skip it and keep a short placeholder
+                if io::copy(
+                    &mut <&mut D as io::Read>::take(&mut d, len),
+                    &mut io::sink(),
+                )? != len
+                {
+                    return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+                }
+                return Ok(Builder::new()
+                    .push_opcode(opcodes::all::OP_RETURN)
+                    .into_script());
+            }
+            let mut bytes = vec![0u8; len as usize];
+            d.read_slice(&mut bytes)?;
+            Ok(Script::from(bytes))
+        }
+    }
+}
+
+impl Encodable for Coin {
+    fn consensus_encode<S: io::Write>(&self, mut s: S) -> Result<usize, encode::Error> {
+        let code = (self.height as u64) * 2 + self.is_coinbase as u64;
+        let mut len = write_disk_varint(&mut s, code)?;
+        if self.height > 0 {
+            // Transaction versions were stored here once; only a dummy is
+            // left for compatibility.
+            len += write_disk_varint(&mut s, 0)?;
+        }
+        len += write_disk_varint(&mut s, compress_amount(self.output.value))?;
+        len += write_compressed_script(&mut s, &self.output.script_pubkey)?;
+        Ok(len)
+    }
+}
+
+impl Decodable for Coin {
+    fn consensus_decode<D: io::Read>(mut d: D) -> Result<Self, encode::Error> {
+        let code = read_disk_varint(&mut d)?;
+        if code >> 1 > u32::max_value() as u64 {
+            return Err(encode::Error::ParseFailed("coin height out of range"));
+        }
+        let height = (code >> 1) as u32;
+        if height > 0 {
+            read_disk_varint(&mut d)?;
+        }
+        let value = decompress_amount(read_disk_varint(&mut d)?);
+        let script_pubkey = read_compressed_script(&mut d)?;
+        Ok(Coin {
+            output: TxOut {
+                value,
+                script_pubkey,
+            },
+            height,
+            is_coinbase: code & 1 == 1,
+        })
+    }
+}
+
+impl_consensus_encoding!(TxUndo, prevouts);
+impl_consensus_encoding!(BlockUndo, txs);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use blockdata::transaction::TxIn;
+    use consensus::encode::{deserialize, serialize};
+    use hash_types::Txid;
+    use hashes::hex::FromHex;
+    use hashes::Hash;
+
+    #[test]
+    fn disk_varint() {
+        let vectors: [(u64, &str); 10] = [
+            (0, "00"),
+            (0x7f, "7f"),
+            (0x80, "8000"),
+            (0x1234, "a334"),
+            (0xffff, "82fe7f"),
+            (0x123456, "c7e756"),
+            (0x80123456, "86ffc7e756"),
+            (0xffffffff, "8efefefe7f"),
+            (0x7fffffffffffffff, "fefefefefefefefe7f"),
+            (0xffffffffffffffff, "80fefefefefefefefe7f"),
+        ];
+        for &(n, hex) in vectors.iter() {
+            let bytes = Vec::from_hex(hex).unwrap();
+            let mut out = vec![];
+            assert_eq!(write_disk_varint(&mut out, n).unwrap(), bytes.len());
+            assert_eq!(out, bytes);
+            assert_eq!(read_disk_varint(&bytes[..]).unwrap(), n);
+        }
+        // One past u64::MAX
+        assert!(read_disk_varint(&Vec::from_hex("80fefefefefefefeff00").unwrap()[..]).is_err());
+        assert!(read_disk_varint(&[0x80][..]).is_err());
+    }
+
+    #[test]
+    fn amount_compression() {
+        let coin = 100_000_000;
+        let vectors = [
+            (0, 0),
+            (1, 1),
+            (1_000_000, 7),
+            (coin, 9),
+            (50 * coin, 50),
+            (21_000_000 * coin, 21_000_000),
+        ];
+        for &(amount, compressed) in vectors.iter() {
+            assert_eq!(compress_amount(amount), compressed);
+            assert_eq!(decompress_amount(compressed), amount);
+        }
+        for amount in (0..100_000).chain((0..1000).map(|i| i * 1_000_000 + 1)) {
+            assert_eq!(decompress_amount(compress_amount(amount)), amount);
+        }
+    }
+
+    fn coin(script: Script, value: u64, height: u32, is_coinbase: bool) -> Coin {
+        Coin {
+            output: TxOut {
+                value,
+                script_pubkey: script,
+            },
+            height,
+            is_coinbase,
+        }
+    }
+
+    #[test]
+    fn coin_serialization() {
+        let p2pkh = coin(
+            hex_script!("76a914751e76e8199196d454941c45d1b3a323f1433bd688ac"),
+            50 * 100_000_000,
+            1,
+            true,
+        );
+        // height 1 and coinbase, dummy version, 50 BTC, P2PKH
+        let ser = serialize(&p2pkh);
+        assert_eq!(
+            ser,
+            Vec::from_hex("03003200751e76e8199196d454941c45d1b3a323f1433bd6").unwrap()
+        );
+        assert_eq!(deserialize::<Coin>(&ser).unwrap(), p2pkh);
+
+        let scripts = [
+            hex_script!("a914b472a266d0bd89c13706a4132ccfb16f7c3b9fcb87"),
+            hex_script!("210279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798ac"),
+            hex_script!("0014751e76e8199196d454941c45d1b3a323f1433bd6"),
+            Script::new(),
+        ];
+        for (i, script) in scripts.iter().enumerate() {
+            let c = coin(script.clone(), 1234 * i as u64, 500_000 + i as u32, false);
+            assert_eq!(deserialize::<Coin>(&serialize(&c)).unwrap(), c);
+        }
+        // Height 0 has no dummy version
+        let c = coin(Script::new(), 0, 0, false);
+        assert_eq!(serialize(&c), vec![0x00, 0x00, 0x06]);
+        assert_eq!(deserialize::<Coin>(&[0x00, 0x00, 0x06]).unwrap(), c);
+
+        // Uncompressed public keys cannot be decompressed here
+        let mut uncompressed = vec![0x00, 0x00, 0x04];
+        uncompressed.extend_from_slice(&[0x11; 32]);
+        assert!(deserialize::<Coin>(&uncompressed).is_err());
+
+        // Oversized scripts are replaced by OP_RETURN
+        let mut oversized = vec![0x00, 0x00];
+        write_disk_varint(&mut oversized, MAX_SCRIPT_SIZE + 1 + SPECIAL_SCRIPTS).unwrap();
+        oversized.extend_from_slice(&[0x51; 10_001]);
+        assert_eq!(
+            deserialize::<Coin>(&oversized)
+                .unwrap()
+                .output
+                .script_pubkey,
+            hex_script!("6a")
+        );
+    }
+
+    #[test]
+    fn coin_maturity() {
+        let reward = coin(Script::new(), 50, 1000, true);
+        assert!(!reward.is_mature(1000));
+        assert!(!reward.is_mature(1099));
+        assert!(reward.is_mature(1100));
+        assert!(!reward.is_mature(0));
+
+        let regular = coin(Script::new(), 50, 1000, false);
+        assert!(regular.is_mature(1000));
+    }
+
+    #[test]
+    fn tx_undo() {
+        let spent = |vout: u32| OutPoint::new(Txid::hash(&[1]), vout);
+        let tx = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![
+                TxIn {
+                    previous_output: spent(0),
+                    ..Default::default()
+                },
+                TxIn {
+                    previous_output: spent(1),
+                    ..Default::default()
+                },
+            ],
+            output: vec![],
+        };
+        let coins = [
+            coin(Script::new().to_p2sh(), 1000, 10, false),
+            coin(Script::new().to_v0_p2wsh(), 2000, 11, true),
+        ];
+
+        let undo =
+            TxUndo::from_spent_coins(&tx, |prevout| coins.get(prevout.vout as usize).cloned())
+                .unwrap();
+        assert_eq!(undo.prevouts, coins.to_vec());
+        assert_eq!(
+            undo.restored_coins(&tx),
+            vec![(spent(0), &coins[0]), (spent(1), &coins[1])]
+        );
+        assert_eq!(
+            TxUndo::from_spent_coins(&tx, |prevout| if prevout.vout == 0 {
+                Some(coins[0].clone())
+            } else {
+                None
+            }),
+            Err(spent(1))
+        );
+
+        let block_undo = BlockUndo {
+            txs: vec![undo.clone(), TxUndo::default()],
+        };
+        let ser = serialize(&block_undo);
+        assert_eq!(ser[0], 2);
+        assert_eq!(deserialize::<BlockUndo>(&ser).unwrap(), block_undo);
+    }
+}
@@ -0,0 +1,339 @@
+//! UTXO Set
+//!
+//! The set of unspent transaction outputs, which is all the state needed to
+//! validate new blocks. Building it means validating every block since
+//! genesis, so in the spirit of Bitcoin Core's assumeutxo the set can
+//! instead be loaded from a snapshot taken at some block. The snapshot is
+//! only accepted if it hashes to a value known in advance, so that it need
+//! not come from a trusted source.
+//!
+//! A snapshot is the hash of the block it was taken at, the number of coins
+//! as a `u64`, and every coin as its outpoint followed by the coin in the
+//! format of the undo data. The hash committed to is computed as Bitcoin
+//! Core's `hash_serialized`: the double SHA256 of every coin, in outpoint
+//! order, as its outpoint, its height times two plus one for a coinbase
+//! output as a `u32`, and its output.
+
+use std::collections::hash_map::{self, HashMap};
+use std::{error, fmt, io};
+
+use blockdata::transaction::OutPoint;
+use blockdata::undo::Coin;
+use consensus::encode::{self, Decodable, Encodable};
+use error::{ErrorCode, ErrorKind};
+use hash_types::{BlockHash, UtxoSetHash};
+use hashes::{sha256d, Hash};
+
+/// The most coins preallocated for when loading a snapshot, which may claim
+/// any number
+const MAX_PREALLOCATED_COINS: u64 = 1 << 20;
+
+/// An error loading a UTXO snapshot
+#[derive(Debug)]
+pub enum Error {
+    /// The snapshot could not be read or decoded
+    Encode(encode::Error),
+    /// The snapshot was taken at a different block than expected
+    WrongBaseBlock(BlockHash),
+    /// The snapshot hashes to a different value than expected
+    HashMismatch(UtxoSetHash),
+    /// The snapshot contains the coin at this outpoint twice
+    DuplicateCoin(OutPoint),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Encode(ref e) => fmt::Display::fmt(e, f),
+            Error::WrongBaseBlock(ref h) => write!(f, "snapshot taken at unexpected block {}", h),
+            Error::HashMismatch(ref h) => write!(f, "unexpected snapshot hash {}", h),
+            Error::DuplicateCoin(ref o) => write!(f, "duplicate coin in snapshot: {:?}", o),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::Encode(ref e) => Some(e),
+            _ => None,
+        }
+    }
+
+    fn description(&self) -> &str {
+        match *self {
+            Error::Encode(ref e) => e.description(),
+            Error::WrongBaseBlock(..) => "snapshot taken at unexpected block",
+            Error::HashMismatch(..) => "unexpected snapshot hash",
+            Error::DuplicateCoin(..) => "duplicate coin in snapshot",
+        }
+    }
+}
+
+impl ErrorCode for Error {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Utxo
+    }
+
+    fn number(&self) -> u32 {
+        match *self {
+            Error::Encode(..) => 1,
+            Error::WrongBaseBlock(..) => 2,
+            Error::HashMismatch(..) => 3,
+            Error::DuplicateCoin(..) => 4,
+        }
+    }
+}
+
+#[doc(hidden)]
+impl From<encode::Error> for Error {
+    fn from(e: encode::Error) -> Error {
+        Error::Encode(e)
+    }
+}
+
+/// What a UTXO snapshot is expected to be, as known ahead of loading it
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct SnapshotCommitment {
+    /// The block the snapshot was taken at
+    pub base_block: BlockHash,
+    /// The hash of the UTXO set as of that block
+    pub hash: UtxoSetHash,
+}
+
+/// The set of unspent transaction outputs
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct UtxoSet {
+    coins: HashMap<OutPoint, Coin>,
+}
+
+impl UtxoSet {
+    /// Creates an empty UTXO set
+    pub fn new() -> UtxoSet {
+        UtxoSet::default()
+    }
+
+    /// Adds a coin, returning the coin it replaced at the same outpoint
+    pub fn add(&mut self, outpoint: OutPoint, coin: Coin) -> Option<Coin> {
+        self.coins.insert(outpoint, coin)
+    }
+
+    /// Removes and returns the coin at `outpoint`, if it is unspent
+    pub fn spend(&mut self, outpoint: &OutPoint) -> Option<Coin> {
+        self.coins.remove(outpoint)
+    }
+
+    /// The coin at `outpoint`, if it is unspent
+    pub fn get(&self, outpoint: &OutPoint) -> Option<&Coin> {
+        self.coins.get(outpoint)
+    }
+
+    /// Whether the output at `outpoint` is unspent
+    pub fn contains(&self, outpoint: &OutPoint) -> bool {
+        self.coins.contains_key(outpoint)
+    }
+
+    /// The number of unspent outputs
+    pub fn len(&self) -> usize {
+        self.coins.len()
+    }
+
+    /// Whether there are no unspent outputs
+    pub fn is_empty(&self) -> bool {
+        self.coins.is_empty()
+    }
+
+    /// Iterates over the unspent outputs, in no particular order
+    pub fn iter<'a>(&'a self) -> hash_map::Iter<'a, OutPoint, Coin> {
+        self.coins.iter()
+    }
+
+    /// The coins in outpoint order
+    fn sorted(&self) -> Vec<(&OutPoint, &Coin)> {
+        let mut coins: Vec<_> = self.coins.iter().collect();
+        coins.sort_by_key(|&(outpoint, _)| *outpoint);
+        coins
+    }
+
+    /// The hash of the set which snapshots are committed to
+    pub fn hash_serialized(&self) -> UtxoSetHash {
+        let mut engine = sha256d::Hash::engine();
+        for (outpoint, coin) in self.sorted() {
+            outpoint
+                .consensus_encode(&mut engine)
+                .expect("engines don't error");
+            let code = coin.height * 2 + coin.is_coinbase as u32;
+            code.consensus_encode(&mut engine)
+                .expect("engines don't error");
+            coin.output
+                .consensus_encode(&mut engine)
+                .expect("engines don't error");
+        }
+        UtxoSetHash::from_engine(engine)
+    }
+
+    /// Writes a snapshot of the set, taken at `base_block`
+    pub fn dump_snapshot<W: io::Write>(
+        &self,
+        mut writer: W,
+        base_block: BlockHash,
+    ) -> Result<usize, encode::Error> {
+        let mut len = base_block.consensus_encode(&mut writer)?;
+        len += (self.coins.len() as u64).consensus_encode(&mut writer)?;
+        for (outpoint, coin) in self.sorted() {
+            len += outpoint.consensus_encode(&mut writer)?;
+            len += coin.consensus_encode(&mut writer)?;
+        }
+        Ok(len)
+    }
+
+    /// Loads a snapshot, checking that it was taken at the expected block
+    /// and hashes to the expected value
+    pub fn load_snapshot<R: io::Read>(
+        mut reader: R,
+        expected: &SnapshotCommitment,
+    ) -> Result<UtxoSet, Error> {
+        let base_block = BlockHash::consensus_decode(&mut reader)?;
+        if base_block != expected.base_block {
+            return Err(Error::WrongBaseBlock(base_block));
+        }
+        let count = u64::consensus_decode(&mut reader)?;
+        let mut set = UtxoSet {
+            coins: HashMap::with_capacity(count.min(MAX_PREALLOCATED_COINS) as usize),
+        };
+        for _ in 0..count {
+            let outpoint = OutPoint::consensus_decode(&mut reader)?;
+            let coin = Coin::consensus_decode(&mut reader)?;
+            if set.add(outpoint, coin).is_some() {
+                return Err(Error::DuplicateCoin(outpoint));
+            }
+        }
+        let hash = set.hash_serialized();
+        if hash != expected.hash {
+            return Err(Error::HashMismatch(hash));
+        }
+        Ok(set)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use blockdata::script::Script;
+    use blockdata::transaction::TxOut;
+    use hash_types::Txid;
+    use hashes::hex::FromHex;
+
+    fn coin(value: u64, height: u32, is_coinbase: bool) -> Coin {
+        Coin {
+            output: TxOut {
+                value,
+                script_pubkey: Script::from(
+                    Vec::<u8>::from_hex("76a914000000000000000000000000000000000000000088ac")
+                        .unwrap(),
+                ),
+            },
+            height,
+            is_coinbase,
+        }
+    }
+
+    fn set() -> UtxoSet {
+        let mut set = UtxoSet::new();
+        set.add(
+            OutPoint::new(Txid::hash(&[1]), 0),
+            coin(50_0000_0000, 1, true),
+        );
+        set.add(OutPoint::new(Txid::hash(&[2]), 3), coin(1000, 200, false));
+        set.add(OutPoint::new(Txid::hash(&[2]), 1), coin(2500, 200, false));
+        set
+    }
+
+    #[test]
+    fn coins() {
+        let mut set = set();
+        assert_eq!(set.len(), 3);
+        let outpoint = OutPoint::new(Txid::hash(&[2]), 1);
+        assert!(set.contains(&outpoint));
+        assert_eq!(set.get(&outpoint), Some(&coin(2500, 200, false)));
+        assert_eq!(set.spend(&outpoint), Some(coin(2500, 200, false)));
+        assert_eq!(set.spend(&outpoint), None);
+        assert_eq!(set.iter().count(), 2);
+        assert_eq!(UtxoSet::new().hash_serialized(), UtxoSetHash::hash(&[]));
+    }
+
+    #[test]
+    fn snapshot_roundtrip() {
+        let set = set();
+        let base_block = BlockHash::hash(&[9]);
+        let mut snapshot = vec![];
+        let len = set.dump_snapshot(&mut snapshot, base_block).unwrap();
+        assert_eq!(len, snapshot.len());
+
+        let commitment = SnapshotCommitment {
+            base_block,
+            hash: set.hash_serialized(),
+        };
+        let loaded = UtxoSet::load_snapshot(&snapshot[..], &commitment).unwrap();
+        assert_eq!(loaded, set);
+
+        // Insertion order does not change the hash
+        let mut reordered = UtxoSet::new();
+        for (outpoint, coin) in set.sorted().into_iter().rev() {
+            reordered.add(*outpoint, coin.clone());
+        }
+        assert_eq!(reordered.hash_serialized(), commitment.hash);
+    }
+
+    #[test]
+    fn snapshot_rejected() {
+        let set = set();
+        let base_block = BlockHash::hash(&[9]);
+        let mut snapshot = vec![];
+        set.dump_snapshot(&mut snapshot, base_block).unwrap();
+        let commitment = SnapshotCommitment {
+            base_block,
+            hash: set.hash_serialized(),
+        };
+
+        let other_block = SnapshotCommitment {
+            base_block: BlockHash::hash(&[8]),
+            ..commitment
+        };
+        match UtxoSet::load_snapshot(&snapshot[..], &other_block) {
+            Err(Error::WrongBaseBlock(h)) => assert_eq!(h, base_block),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let mut tampered = set.clone();
+        tampered.add(OutPoint::new(Txid::hash(&[2]), 3), coin(1001, 200, false));
+        let mut tampered_snapshot = vec![];
+        tampered
+            .dump_snapshot(&mut tampered_snapshot, base_block)
+            .unwrap();
+        match UtxoSet::load_snapshot(&tampered_snapshot[..], &commitment) {
+            Err(Error::HashMismatch(h)) => assert_eq!(h, tampered.hash_serialized()),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        match UtxoSet::load_snapshot(&snapshot[..snapshot.len() - 1], &commitment) {
+            Err(Error::Encode(_)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        // The same coin twice
+        let mut duplicate = vec![];
+        base_block.consensus_encode(&mut duplicate).unwrap();
+        2u64.consensus_encode(&mut duplicate).unwrap();
+        let outpoint = OutPoint::new(Txid::hash(&[1]), 0);
+        for _ in 0..2 {
+            outpoint.consensus_encode(&mut duplicate).unwrap();
+            coin(1, 1, false).consensus_encode(&mut duplicate).unwrap();
+        }
+        match UtxoSet::load_snapshot(&duplicate[..], &commitment) {
+            Err(Error::DuplicateCoin(o)) => assert_eq!(o, outpoint),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
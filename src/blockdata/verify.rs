@@ -0,0 +1,500 @@
+//! Signature Verification
+//!
+//! A check of the signatures of a transaction which stops short of
+//! interpreting scripts. `Transaction::verify_signatures` recognizes the
+//! inputs whose signature and public key are in a fixed place, works out
+//! the signature hash of each the way its output type requires, and has a
+//! cryptography backend check the signatures; taproot key path signatures
+//! are checked as one batch.
+//!
+//! Inputs spending P2SH, P2WSH or bare multisig outputs, and taproot script
+//! path spends, are reported as unsupported rather than guessed at. An
+//! input which passes may still fail validation for other reasons, such as
+//! a timelock.
+
+use std::{error, fmt};
+
+use hashes::Hash;
+
+use blockdata::script::{Instruction, Script};
+use blockdata::transaction::{InputType, SigHashType, Transaction, TxIn, TxOut};
+use error::{ErrorCode, ErrorKind};
+use hash_types::{PubkeyHash, ScriptHash};
+use util::crypto::{EcdsaVerifier, SchnorrItem, SchnorrVerifier};
+use util::sighash::{self, Prevouts, SigHashCache};
+
+/// Why the signature of an input was not found valid
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The output spent by the input was not given
+    MissingPrevout,
+    /// The input spends an output of a type whose signatures can only be
+    /// found by interpreting its script, or through a taproot script path
+    Unsupported(InputType),
+    /// The input does not hold a signature and public key where its
+    /// output type puts them
+    MalformedWitness,
+    /// The public key does not hash to the one the spent output commits to
+    PubkeyMismatch,
+    /// The redeem script does not hash to the one the spent output commits
+    /// to
+    RedeemScriptMismatch,
+    /// The signature uses a sighash type the segwit signature hash cannot
+    /// be computed for here
+    UnsupportedSighashType(u8),
+    /// The signature hash could not be computed
+    Sighash(sighash::Error),
+    /// The signature is not valid
+    InvalidSignature,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Unsupported(input_type) => {
+                write!(f, "cannot verify signatures of {:?} inputs", input_type)
+            }
+            Error::UnsupportedSighashType(t) => {
+                write!(f, "unsupported sighash type {:#x}", t)
+            }
+            Error::Sighash(ref e) => write!(f, "cannot compute signature hash: {}", e),
+            Error::MissingPrevout
+            | Error::MalformedWitness
+            | Error::PubkeyMismatch
+            | Error::RedeemScriptMismatch
+            | Error::InvalidSignature => f.write_str(error::Error::description(self)),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::Sighash(ref e) => Some(e),
+            _ => None,
+        }
+    }
+
+    fn description(&self) -> &str {
+        match *self {
+            Error::MissingPrevout => "spent output not given",
+            Error::Unsupported(..) => "unsupported input type",
+            Error::MalformedWitness => "no signature and public key where expected",
+            Error::PubkeyMismatch => "public key does not match the spent output",
+            Error::RedeemScriptMismatch => "redeem script does not match the spent output",
+            Error::UnsupportedSighashType(..) => "unsupported sighash type",
+            Error::Sighash(..) => "cannot compute signature hash",
+            Error::InvalidSignature => "invalid signature",
+        }
+    }
+}
+
+impl ErrorCode for Error {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Verify
+    }
+
+    fn number(&self) -> u32 {
+        match *self {
+            Error::MissingPrevout => 1,
+            Error::Unsupported(..) => 2,
+            Error::MalformedWitness => 3,
+            Error::PubkeyMismatch => 4,
+            Error::UnsupportedSighashType(..) => 5,
+            Error::Sighash(..) => 6,
+            Error::InvalidSignature => 7,
+            Error::RedeemScriptMismatch => 8,
+        }
+    }
+}
+
+#[doc(hidden)]
+impl From<sighash::Error> for Error {
+    fn from(e: sighash::Error) -> Error {
+        Error::Sighash(e)
+    }
+}
+
+/// A taproot key path signature waiting for the batch
+struct PendingSchnorr {
+    index: usize,
+    msg: [u8; 32],
+    pubkey: [u8; 32],
+    sig: [u8; 64],
+}
+
+impl Transaction {
+    /// Checks the signature of every input of the transaction, given the
+    /// outputs they spend in order, returning one result per input.
+    ///
+    /// P2PK, P2PKH, P2WPKH, nested P2WPKH and taproot key path spends are
+    /// checked; see the module documentation for the rest. `verifier` is
+    /// the cryptography backend, which also decides how strict to be about
+    /// the encoding of signatures and keys.
+    pub fn verify_signatures<V>(&self, spent: &[TxOut], verifier: &V) -> Vec<Result<(), Error>>
+    where
+        V: EcdsaVerifier + SchnorrVerifier + ?Sized,
+    {
+        let mut cache = SigHashCache::new(self);
+        let prevouts = Prevouts::from_spent_outputs(spent);
+        let mut pending = vec![];
+        let mut results: Vec<Result<(), Error>> = self
+            .input
+            .iter()
+            .enumerate()
+            .map(|(index, input)| {
+                let spent = spent.get(index).ok_or(Error::MissingPrevout)?;
+                let spk = spent.script_pubkey.as_bytes();
+                match input.input_type(&spent.script_pubkey) {
+                    InputType::P2pk => {
+                        let pushes = pushes(&input.script_sig)?;
+                        if pushes.len() != 1 {
+                            return Err(Error::MalformedWitness);
+                        }
+                        let pubkey = &spk[1..spk.len() - 1];
+                        verify_legacy(
+                            &cache,
+                            index,
+                            &spent.script_pubkey,
+                            pushes[0],
+                            pubkey,
+                            verifier,
+                        )
+                    }
+                    InputType::P2pkh => {
+                        let pushes = pushes(&input.script_sig)?;
+                        if pushes.len() != 2 {
+                            return Err(Error::MalformedWitness);
+                        }
+                        check_pubkey_hash(pushes[1], &spk[3..23])?;
+                        verify_legacy(
+                            &cache,
+                            index,
+                            &spent.script_pubkey,
+                            pushes[0],
+                            pushes[1],
+                            verifier,
+                        )
+                    }
+                    InputType::P2wpkh => {
+                        verify_p2wpkh(&mut cache, index, input, &spk[2..], spent.value, verifier)
+                    }
+                    InputType::P2shP2wpkh => {
+                        // The redeem script is the program, pushed on its own
+                        let redeem_script = &input.script_sig.as_bytes()[1..];
+                        if ScriptHash::hash(redeem_script)[..] != spk[2..22] {
+                            return Err(Error::RedeemScriptMismatch);
+                        }
+                        let program = &redeem_script[2..];
+                        verify_p2wpkh(&mut cache, index, input, program, spent.value, verifier)
+                    }
+                    InputType::P2tr => {
+                        let stack = input.taproot_stack();
+                        if stack.len() != 1 {
+                            return Err(Error::Unsupported(InputType::P2tr));
+                        }
+                        let sig = &stack[0];
+                        let sighash_type = match sig.len() {
+                            64 => 0x00,
+                            65 if sig[64] != 0x00 => sig[64],
+                            _ => return Err(Error::MalformedWitness),
+                        };
+                        let msg = cache.taproot_witness_signature_hash(
+                            index,
+                            &prevouts,
+                            spent,
+                            sighash_type,
+                        )?;
+                        let mut item = PendingSchnorr {
+                            index,
+                            msg: msg.into_inner(),
+                            pubkey: [0; 32],
+                            sig: [0; 64],
+                        };
+                        item.pubkey.copy_from_slice(&spk[2..]);
+                        item.sig.copy_from_slice(&sig[..64]);
+                        pending.push(item);
+                        Ok(())
+                    }
+                    other => Err(Error::Unsupported(other)),
+                }
+            })
+            .collect();
+
+        // A failed batch only says that some signature is invalid, so then
+        // they are checked one by one to tell which
+        let items: Vec<SchnorrItem> = pending
+            .iter()
+            .map(|p| (&p.msg, &p.pubkey, &p.sig))
+            .collect();
+        if !verifier.verify_schnorr_batch(&items) {
+            for p in pending.iter() {
+                if !verifier.verify_schnorr(&p.msg, &p.pubkey, &p.sig) {
+                    results[p.index] = Err(Error::InvalidSignature);
+                }
+            }
+        }
+        results
+    }
+}
+
+/// The data pushed by a `script_sig` made of pushes only
+fn pushes(script_sig: &Script) -> Result<Vec<&[u8]>, Error> {
+    script_sig
+        .instructions()
+        .map(|instruction| match instruction {
+            Ok(Instruction::PushBytes(data)) => Ok(data),
+            _ => Err(Error::MalformedWitness),
+        })
+        .collect()
+}
+
+/// Checks that `pubkey` hashes to the 20-byte `hash` of the spent output
+fn check_pubkey_hash(pubkey: &[u8], hash: &[u8]) -> Result<(), Error> {
+    if &PubkeyHash::hash(pubkey)[..] == hash {
+        Ok(())
+    } else {
+        Err(Error::PubkeyMismatch)
+    }
+}
+
+/// Splits an ECDSA signature into its DER encoding and sighash byte
+fn split_sighash(sig: &[u8]) -> Result<(&[u8], u8), Error> {
+    match sig.split_last() {
+        Some((&sighash_type, der)) if !der.is_empty() => Ok((der, sighash_type)),
+        _ => Err(Error::MalformedWitness),
+    }
+}
+
+/// Checks the ECDSA signature of an input spending a non-segwit output
+fn verify_legacy<V: EcdsaVerifier + ?Sized>(
+    cache: &SigHashCache,
+    index: usize,
+    script_code: &Script,
+    sig: &[u8],
+    pubkey: &[u8],
+    verifier: &V,
+) -> Result<(), Error> {
+    let (der, sighash_type) = split_sighash(sig)?;
+    let msg = cache.legacy_signature_hash(index, script_code, sighash_type as u32)?;
+    if verifier.verify_ecdsa(&msg.into_inner(), pubkey, der) {
+        Ok(())
+    } else {
+        Err(Error::InvalidSignature)
+    }
+}
+
+/// Checks the ECDSA signature of an input spending a P2WPKH output, on its
+/// own or nested in P2SH, whose witness program is `program`
+fn verify_p2wpkh<V: EcdsaVerifier + ?Sized>(
+    cache: &mut SigHashCache,
+    index: usize,
+    input: &TxIn,
+    program: &[u8],
+    value: u64,
+    verifier: &V,
+) -> Result<(), Error> {
+    if input.witness.len() != 2 {
+        return Err(Error::MalformedWitness);
+    }
+    let (sig, pubkey) = (&input.witness[0], &input.witness[1]);
+    check_pubkey_hash(pubkey, program)?;
+    let (der, sighash_type) = split_sighash(sig)?;
+    // The segwit signature hash commits to the sighash type as given, which
+    // `SigHashType` can only hold for the standard ones
+    let standard = SigHashType::from_u32(sighash_type as u32);
+    if standard.as_u32() != sighash_type as u32 {
+        return Err(Error::UnsupportedSighashType(sighash_type));
+    }
+    let script_code = Script::new_p2pkh(&PubkeyHash::from_slice(program).unwrap());
+    let msg = cache.segwit_signature_hash(index, &script_code, value, standard)?;
+    if verifier.verify_ecdsa(&msg.into_inner(), pubkey, der) {
+        Ok(())
+    } else {
+        Err(Error::InvalidSignature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use blockdata::opcodes;
+    use blockdata::script::Builder;
+    use blockdata::transaction::OutPoint;
+    use hash_types::{Txid, WPubkeyHash, WScriptHash};
+
+    /// Accepts an ECDSA signature which is the message itself, and a
+    /// Schnorr signature which is the message followed by the public key
+    struct Echo;
+
+    impl EcdsaVerifier for Echo {
+        fn verify_ecdsa(&self, msg: &[u8; 32], _: &[u8], sig: &[u8]) -> bool {
+            sig == &msg[..]
+        }
+    }
+
+    impl SchnorrVerifier for Echo {
+        fn verify_schnorr(&self, msg: &[u8; 32], pubkey: &[u8; 32], sig: &[u8; 64]) -> bool {
+            &sig[..32] == &msg[..] && &sig[32..] == &pubkey[..]
+        }
+    }
+
+    #[test]
+    fn verify_signatures() {
+        let pubkey = vec![2; 33];
+        let output_key = [7; 32];
+        let wpkh = Script::new_v0_wpkh(&WPubkeyHash::hash(&pubkey));
+        let p2tr = Builder::new()
+            .push_int(1)
+            .push_slice(&output_key)
+            .into_script();
+        let spent: Vec<TxOut> = vec![
+            Builder::new()
+                .push_slice(&pubkey)
+                .push_opcode(opcodes::all::OP_CHECKSIG)
+                .into_script(),
+            Script::new_p2pkh(&PubkeyHash::hash(&pubkey)),
+            wpkh.clone(),
+            Script::new_p2sh(&ScriptHash::hash(wpkh.as_bytes())),
+            p2tr.clone(),
+            p2tr.clone(),
+            Script::new_v0_wsh(&WScriptHash::hash(&[0x51])),
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(i, script_pubkey)| TxOut {
+            value: 1000 * (i as u64 + 1),
+            script_pubkey,
+        })
+        .collect();
+        let mut tx = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: (0..spent.len())
+                .map(|i| TxIn {
+                    previous_output: OutPoint::new(Txid::hash(&[i as u8]), 0),
+                    ..Default::default()
+                })
+                .collect(),
+            output: vec![TxOut {
+                value: 20000,
+                script_pubkey: wpkh.clone(),
+            }],
+        };
+        // The taproot signature hashes commit to the annex
+        tx.input[4].witness = vec![vec![0; 64], vec![0x50, 1]];
+        tx.input[5].witness = vec![vec![0; 64]];
+
+        let prevouts = Prevouts::from_spent_outputs(&spent);
+        let script_code = Script::new_p2pkh(&PubkeyHash::hash(&pubkey));
+        let (legacy, segwit, taproot) = {
+            let mut cache = SigHashCache::new(&tx);
+            let legacy: Vec<Vec<u8>> = (0..2)
+                .map(|i| {
+                    let msg = cache
+                        .legacy_signature_hash(i, &spent[i].script_pubkey, 0x01)
+                        .unwrap();
+                    msg[..].to_vec()
+                })
+                .collect();
+            let segwit: Vec<Vec<u8>> = (2..4)
+                .map(|i| {
+                    let msg = cache
+                        .segwit_signature_hash(i, &script_code, spent[i].value, SigHashType::All)
+                        .unwrap();
+                    msg[..].to_vec()
+                })
+                .collect();
+            let taproot = cache
+                .taproot_witness_signature_hash(4, &prevouts, &spent[4], 0x00)
+                .unwrap();
+            (legacy, segwit, taproot[..].to_vec())
+        };
+        let sig = |msg: &[u8]| {
+            let mut sig = msg.to_vec();
+            sig.push(0x01);
+            sig
+        };
+        tx.input[0].script_sig = Builder::new().push_slice(&sig(&legacy[0])).into_script();
+        tx.input[1].script_sig = Builder::new()
+            .push_slice(&sig(&legacy[1]))
+            .push_slice(&pubkey)
+            .into_script();
+        tx.input[2].witness = vec![sig(&segwit[0]), pubkey.clone()];
+        tx.input[3].script_sig = Builder::new().push_slice(wpkh.as_bytes()).into_script();
+        tx.input[3].witness = vec![sig(&segwit[1]), pubkey.clone()];
+        tx.input[4].witness[0] = [&taproot[..], &output_key[..]].concat();
+        // A signature for another input
+        tx.input[5].witness[0] = tx.input[4].witness[0].clone();
+
+        assert_eq!(
+            tx.verify_signatures(&spent, &Echo),
+            vec![
+                Ok(()),
+                Ok(()),
+                Ok(()),
+                Ok(()),
+                Ok(()),
+                Err(Error::InvalidSignature),
+                Err(Error::Unsupported(InputType::P2wsh)),
+            ]
+        );
+
+        // Every input is checked, whatever the others hold
+        tx.input[1].script_sig = Builder::new()
+            .push_slice(&sig(&legacy[1]))
+            .push_slice(&[3; 33])
+            .into_script();
+        tx.input[2].witness[0][0] ^= 1;
+        tx.input[3].witness.pop();
+        let results = tx.verify_signatures(&spent[..6], &Echo);
+        assert_eq!(results[0], Ok(()));
+        assert_eq!(results[1], Err(Error::PubkeyMismatch));
+        assert_eq!(results[2], Err(Error::InvalidSignature));
+        assert_eq!(results[3], Err(Error::MalformedWitness));
+        assert_eq!(
+            results[4],
+            Err(Error::Sighash(sighash::Error::PrevoutsSize {
+                prevouts_size: 6,
+                inputs_size: 7
+            }))
+        );
+        assert_eq!(results[6], Err(Error::MissingPrevout));
+    }
+
+    #[test]
+    fn nested_redeem_script_mismatch() {
+        let pubkey = vec![2; 33];
+        let wpkh = Script::new_v0_wpkh(&WPubkeyHash::hash(&pubkey));
+        let spent = vec![TxOut {
+            value: 1000,
+            script_pubkey: Script::new_p2sh(&ScriptHash::hash(b"not this redeem script")),
+        }];
+        let mut tx = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid::hash(&[0]), 0),
+                script_sig: Builder::new().push_slice(wpkh.as_bytes()).into_script(),
+                ..Default::default()
+            }],
+            output: vec![],
+        };
+        let script_code = Script::new_p2pkh(&PubkeyHash::hash(&pubkey));
+        let mut sig = SigHashCache::new(&tx)
+            .segwit_signature_hash(0, &script_code, 1000, SigHashType::All)
+            .unwrap()[..]
+            .to_vec();
+        sig.push(0x01);
+        tx.input[0].witness = vec![sig, pubkey];
+        assert_eq!(
+            tx.input[0].input_type(&spent[0].script_pubkey),
+            InputType::P2shP2wpkh
+        );
+        assert_eq!(
+            tx.verify_signatures(&spent, &Echo),
+            vec![Err(Error::RedeemScriptMismatch)]
+        );
+    }
+}
@@ -13,31 +13,42 @@
 //! opcode decode, hashes are big-endian, numbers are typically big-
 //! endian decimals, etc.)
 
-use hashes::hex::ToHex;
-use std::io::{Cursor, Read, Write};
+use hashes::hex::{self, FromHex, ToHex};
+use std::io::{Read, Write};
 use std::borrow::Cow;
-use std::{error, fmt, io, mem, u32};
-use hashes::{sha256d, Hash};
+use std::collections::{BTreeMap, HashMap};
+use std::hash;
+use std::fs;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+use std::path::Path;
+use std::{cmp, error, fmt, io, mem, u32};
+use hashes::{hash160, sha256, sha256d, Hash};
 // use hash_types::{BlockHash, FilterHash, TxMerkleNode};
 
 
+use blockdata::transaction::{Transaction, TxIn, TxOut};
+use blockdata::undo::{Coin, TxUndo};
+use error::{ErrorCode, ErrorKind};
+use hash_types::Txid;
 use network::address::Address;
+use network::constants::Magic;
+use network::message_blockdata::Inventory;
 use util::endian;
+use util::psbt;
 
 /// Encoding error
 #[derive(Debug)]
 pub enum Error {
     /// An I/O error
     Io(io::Error),
-    // TODO FULLY IMPLEMENT this:
-    //    /// PBST-related error
-    //    Psbt(psbt::Error),
+    /// PSBT-related error
+    Psbt(psbt::Error),
     /// Network magic was not expected
     UnexpectedNetworkMagic {
         /// The expected network magic
-        expected: u32,
+        expected: Magic,
         /// The unexpected network magic
-        actual: u32,
+        actual: Magic,
     },
     /// Tried to allocate an oversized vector
     OversizedVectorAllocation {
@@ -56,7 +67,7 @@ pub enum Error {
     /// VarInt was encoded in a non-minimal way
     NonMinimalVarInt,
     /// Network magic was unknown
-    UnknownNetworkMagic(u32),
+    UnknownNetworkMagic(Magic),
     /// Parsing failed
     ParseFailed(&'static str),
     /// Unspported Segwit flag
@@ -65,14 +76,24 @@ pub enum Error {
     UnrecognizedNetworkCommand(String),
     /// Invalid inventory type
     UnknownInventoryType(u32),
+    /// A network message declared a payload larger than its command allows
+    MessageTooLarge {
+        /// The command of the message
+        command: String,
+        /// The declared payload size
+        size: usize,
+        /// The largest payload allowed for the command
+        max: usize,
+    },
+    /// Invalid hex
+    Hex(hex::Error),
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             Error::Io(ref e) => write!(f, "I/I error: {}", e),
-            // TODO IMPLEMENT THIS
-            //            Error::Psbt(ref e) => write!(f, "PSBT: {}", e),
+            Error::Psbt(ref e) => write!(f, "PSBT: {}", e),
             Error::UnexpectedNetworkMagic {
                 expected: ref e,
                 actual: ref a,
@@ -104,6 +125,16 @@ impl fmt::Display for Error {
                 write!(f, "unrecognized network command: {}", nwcmd)
             }
             Error::UnknownInventoryType(ref tp) => write!(f, "unknown inventory type: {}", tp),
+            Error::MessageTooLarge {
+                ref command,
+                size,
+                max,
+            } => write!(
+                f,
+                "{} message of {} bytes exceeds the limit of {} bytes",
+                command, size, max
+            ),
+            Error::Hex(ref e) => write!(f, "hex: {}", e),
         }
     }
 }
@@ -112,8 +143,8 @@ impl error::Error for Error {
     fn cause(&self) -> Option<&error::Error> {
         match *self {
             Error::Io(ref e) => Some(e),
-            // TODO IMPLEMENT THIS:
-            //            Error::Psbt(ref e) => Some(e),
+            Error::Psbt(ref e) => Some(e),
+            Error::Hex(ref e) => Some(e),
             // Use XOR to return `None` for a cause if any of these types are triggered:
             Error::UnexpectedNetworkMagic { .. }
             | Error::OversizedVectorAllocation { .. }
@@ -123,7 +154,8 @@ impl error::Error for Error {
             | Error::ParseFailed(..)
             | Error::UnsupportedSegwitFlag(..)
             | Error::UnrecognizedNetworkCommand(..)
-            | Error::UnknownInventoryType(..) => None,
+            | Error::UnknownInventoryType(..)
+            | Error::MessageTooLarge { .. } => None,
         }
     }
 
@@ -132,6 +164,30 @@ impl error::Error for Error {
     }
 }
 
+impl ErrorCode for Error {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Encode
+    }
+
+    fn number(&self) -> u32 {
+        match *self {
+            Error::Io(..) => 1,
+            Error::Psbt(..) => 2,
+            Error::UnexpectedNetworkMagic { .. } => 3,
+            Error::OversizedVectorAllocation { .. } => 4,
+            Error::InvalidChecksum { .. } => 5,
+            Error::NonMinimalVarInt => 6,
+            Error::UnknownNetworkMagic(..) => 7,
+            Error::ParseFailed(..) => 8,
+            Error::UnsupportedSegwitFlag(..) => 9,
+            Error::UnrecognizedNetworkCommand(..) => 10,
+            Error::UnknownInventoryType(..) => 11,
+            Error::MessageTooLarge { .. } => 12,
+            Error::Hex(..) => 13,
+        }
+    }
+}
+
 #[doc(hidden)]
 #[doc(hidden)]
 impl From<io::Error> for Error {
@@ -140,19 +196,46 @@ impl From<io::Error> for Error {
     }
 }
 
-// TODO IMPLEMENT THIS:
-//#[doc(hidden)]
-//impl From<osbt::Error> for Error {
-//    fn from(error: psbt::Error) -> Self {
-//        Error::Psbt(error)
-//    }
-//}
+#[doc(hidden)]
+impl From<psbt::Error> for Error {
+    fn from(error: psbt::Error) -> Self {
+        Error::Psbt(error)
+    }
+}
+
+#[doc(hidden)]
+impl From<hex::Error> for Error {
+    fn from(error: hex::Error) -> Self {
+        Error::Hex(error)
+    }
+}
 
 /// Encode an object into a vector
 pub fn serialize<T: Encodable + ?Sized>(data: &T) -> Vec<u8> {
-    let mut encoder = Cursor::new(vec![]);
+    let mut encoder = Vec::new();
+    data.consensus_encode(&mut encoder).unwrap();
+    encoder
+}
+
+/// Encode an object into a hex string
+pub fn serialize_hex<T: Encodable + ?Sized>(data: &T) -> String {
+    serialize(data).to_hex()
+}
+
+/// Encode an object onto the end of an existing vector, returning the
+/// number of bytes written. Useful for reusing a single buffer across
+/// many objects.
+pub fn serialize_into<T: Encodable + ?Sized>(buf: &mut Vec<u8>, data: &T) -> usize {
+    data.consensus_encode(buf).unwrap()
+}
+
+/// Encode an object into a vector which is allocated up-front with the
+/// exact encoded length of the object, avoiding reallocations when
+/// serializing large objects.
+pub fn serialize_with_capacity_hint<T: Encodable + ?Sized>(data: &T) -> Vec<u8> {
+    let mut encoder = Vec::with_capacity(data.consensus_encoded_len());
     data.consensus_encode(&mut encoder).unwrap();
-    encoder.into_inner()
+    encoder
 }
 
 /// Deserialize an object from a vector, will error if said deserialization
@@ -170,16 +253,33 @@ pub fn deserialize<'a, T: Decodable>(data: &'a [u8]) -> Result<T, Error> {
     }
 }
 
+/// Deserialize an object from a hex string, will error if said
+/// deserialization does not consume all of the data
+pub fn deserialize_hex<T: Decodable>(hex: &str) -> Result<T, Error> {
+    deserialize(&Vec::<u8>::from_hex(hex)?)
+}
+
 /// Deserializes an object from a vector and will not throw an error
 /// if the entire vector is not consumed
 pub fn deserialize_partial<'a, T: Decodable>(data: &'a [u8]) -> Result<(T, usize), Error> {
-    let mut decoder = Cursor::new(data);
+    let mut decoder = data;
+    #[cfg(feature = "decode-trace")]
+    let rv = Decodable::consensus_decode(::consensus::trace::Reader(&mut decoder))?;
+    #[cfg(not(feature = "decode-trace"))]
     let rv = Decodable::consensus_decode(&mut decoder)?;
-    let consumed = decoder.position() as usize;
+    let consumed = data.len() - decoder.len();
 
     Ok((rv, consumed))
 }
 
+/// Deserialize an object from the whole of a file, which is read into
+/// memory in one go rather than through a buffered reader. Fails like
+/// `deserialize` if the file holds more than the object.
+pub fn from_file<T: Decodable, P: AsRef<Path>>(path: P) -> Result<T, Error> {
+    let data = fs::read(path)?;
+    deserialize(&data)
+}
+
 /// Extensions of `Write` to encode data as per Bitcoin consensus
 pub trait WriteExt {
     /// Output a 64-bit uint
@@ -314,12 +414,37 @@ impl<R: Read> ReadExt for R {
 /// Maximum size, in bytes, of a vector we are allowed to decode
 pub const MAX_VEC_SIZE: usize = 4_000_000;
 
+/// Maximum size, in bytes, preallocated for a vector being decoded. Length
+/// prefixes come from untrusted data, so past this a vector only grows as
+/// its contents are actually read.
+const MAX_VEC_PREALLOC: usize = 64 * 1024;
+
+/// Read exactly `len` bytes, allocating only as they arrive
+pub(crate) fn read_bytes<D: io::Read>(mut d: D, len: usize) -> Result<Vec<u8>, Error> {
+    let mut ret = Vec::with_capacity(cmp::min(len, MAX_VEC_PREALLOC));
+    let read = <&mut D as io::Read>::take(&mut d, len as u64)
+        .read_to_end(&mut ret)
+        .map_err(Error::Io)?;
+    if read != len {
+        return Err(Error::Io(io::Error::from(io::ErrorKind::UnexpectedEof)));
+    }
+    Ok(ret)
+}
+
 /// Data which can be encoded in a consensus-consistent way
 pub trait Encodable {
     /// Encode an object with a well-defined format, should only ever
     /// error if the underlying `Write` errors. Returns the number of
     /// bytes written on success
     fn consensus_encode<W: io::Write>(&self, e: W) -> Result<usize, Error>;
+
+    /// The number of bytes `consensus_encode` will write for this object.
+    /// The default implementation encodes into a sink and counts the
+    /// bytes; types which know their length cheaply should override it.
+    fn consensus_encoded_len(&self) -> usize {
+        self.consensus_encode(io::sink())
+            .expect("writing to a sink never fails")
+    }
 }
 
 /// Data which can be decoded in a consensus-consistent way
@@ -368,9 +493,9 @@ impl VarInt {
     #[inline]
     pub fn len(&self) -> usize {
         match self.0 {
-            0 ... 0xFC => 1,      // u8
-            0xFC ... 0xFFFF => 3, // u16
-            0xFD ... 0xFFFF => 5,
+            0...0xFC => 1,             // u8
+            0xFD...0xFFFF => 3,        // u16
+            0x10000...0xFFFFFFFF => 5, // u32
             _ => 9,
         }
     }
@@ -401,6 +526,11 @@ impl Encodable for VarInt {
             },
         }
     }
+
+    #[inline]
+    fn consensus_encoded_len(&self) -> usize {
+        self.len()
+    }
 }
 
 impl Decodable for VarInt {
@@ -460,6 +590,11 @@ impl Encodable for String {
         s.emit_slice(&b)?;
         Ok(vi_len + b.len())
     }
+
+    #[inline]
+    fn consensus_encoded_len(&self) -> usize {
+        VarInt(self.len() as u64).len() + self.len()
+    }
 }
 impl Decodable for String {
     fn consensus_decode<D: io::Read>(d: D) -> Result<String, Error> {
@@ -511,6 +646,7 @@ impl_array!(4);
 impl_array!(8);
 impl_array!(12);
 impl_array!(16);
+impl_array!(20);
 impl_array!(32);
 impl_array!(33);
 
@@ -535,6 +671,94 @@ impl Encodable for [u16; 8] {
     }
 }
 
+// Network addresses. An IPv4 address is its 4 bytes and an IPv6 address its
+// 16, as in the payloads of addrv2 (BIP155). A socket address is encoded as
+// in an addr message: an IPv6 address, with an IPv4 one mapped into it,
+// followed by the port in big-endian.
+impl Encodable for Ipv4Addr {
+    #[inline]
+    fn consensus_encode<S: io::Write>(&self, s: S) -> Result<usize, Error> {
+        self.octets().consensus_encode(s)
+    }
+}
+
+impl Decodable for Ipv4Addr {
+    #[inline]
+    fn consensus_decode<D: io::Read>(d: D) -> Result<Self, Error> {
+        <[u8; 4]>::consensus_decode(d).map(Ipv4Addr::from)
+    }
+}
+
+impl Encodable for Ipv6Addr {
+    #[inline]
+    fn consensus_encode<S: io::Write>(&self, s: S) -> Result<usize, Error> {
+        self.octets().consensus_encode(s)
+    }
+}
+
+impl Decodable for Ipv6Addr {
+    #[inline]
+    fn consensus_decode<D: io::Read>(d: D) -> Result<Self, Error> {
+        <[u8; 16]>::consensus_decode(d).map(Ipv6Addr::from)
+    }
+}
+
+impl Encodable for SocketAddrV6 {
+    #[inline]
+    fn consensus_encode<S: io::Write>(&self, mut s: S) -> Result<usize, Error> {
+        Ok(self.ip().consensus_encode(&mut s)? + self.port().to_be_bytes().consensus_encode(s)?)
+    }
+}
+
+impl Decodable for SocketAddrV6 {
+    #[inline]
+    fn consensus_decode<D: io::Read>(mut d: D) -> Result<Self, Error> {
+        let ip = Ipv6Addr::consensus_decode(&mut d)?;
+        let port = u16::from_be_bytes(Decodable::consensus_decode(d)?);
+        Ok(SocketAddrV6::new(ip, port, 0, 0))
+    }
+}
+
+impl Encodable for SocketAddrV4 {
+    #[inline]
+    fn consensus_encode<S: io::Write>(&self, s: S) -> Result<usize, Error> {
+        SocketAddrV6::new(self.ip().to_ipv6_mapped(), self.port(), 0, 0).consensus_encode(s)
+    }
+}
+
+impl Decodable for SocketAddrV4 {
+    /// Fails unless the address is an IPv4-mapped one
+    #[inline]
+    fn consensus_decode<D: io::Read>(d: D) -> Result<Self, Error> {
+        match SocketAddr::consensus_decode(d)? {
+            SocketAddr::V4(addr) => Ok(addr),
+            SocketAddr::V6(_) => Err(self::Error::ParseFailed("not an IPv4-mapped address")),
+        }
+    }
+}
+
+impl Encodable for SocketAddr {
+    #[inline]
+    fn consensus_encode<S: io::Write>(&self, s: S) -> Result<usize, Error> {
+        match *self {
+            SocketAddr::V4(ref addr) => addr.consensus_encode(s),
+            SocketAddr::V6(ref addr) => addr.consensus_encode(s),
+        }
+    }
+}
+
+impl Decodable for SocketAddr {
+    /// Decodes an IPv4-mapped address as IPv4, and any other as IPv6
+    #[inline]
+    fn consensus_decode<D: io::Read>(d: D) -> Result<Self, Error> {
+        let addr = SocketAddrV6::consensus_decode(d)?;
+        Ok(match addr.ip().to_ipv4_mapped() {
+            Some(ip) => SocketAddr::V4(SocketAddrV4::new(ip, addr.port())),
+            None => SocketAddr::V6(addr),
+        })
+    }
+}
+
 // Vectors
 macro_rules! impl_vec {
     ($type: ty) => {
@@ -556,16 +780,16 @@ macro_rules! impl_vec {
         impl Decodable for Vec<$type> {
             #[inline]
             fn consensus_decode<D: io::Read>(mut d: D) -> Result<Self, Error> {
-                let len = VarInt::consensus_decode(&mut d)?.0;
+                let len = trace_field!("count", VarInt::consensus_decode(&mut d)?).0;
                 let byte_size = (len as usize)
                                     .checked_mul(mem::size_of::<$type>())
                                     .ok_or(self::Error::ParseFailed("Invalid length"))?;
                 if byte_size > MAX_VEC_SIZE {
                     return Err(self::Error::OversizedVectorAllocation { requested: byte_size, max: MAX_VEC_SIZE })
                 }
-                let mut ret = Vec::with_capacity(len as usize);
-                for _ in 0..len {
-                    ret.push(Decodable::consensus_decode(&mut d)?);
+                let mut ret = Vec::with_capacity(cmp::min(len as usize, MAX_VEC_PREALLOC / mem::size_of::<$type>()));
+                for i in 0..len {
+                    ret.push(trace_field!([i as usize], Decodable::consensus_decode(&mut d)?));
                 }
                 Ok(ret)
             }
@@ -575,6 +799,14 @@ macro_rules! impl_vec {
 
 impl_vec!(Vec<u8>);
 impl_vec!(u64);
+impl_vec!((u32, Address));
+impl_vec!(TxIn);
+impl_vec!(TxOut);
+impl_vec!(Transaction);
+impl_vec!(Coin);
+impl_vec!(TxUndo);
+impl_vec!(Txid);
+impl_vec!(Inventory);
 
 impl Encodable for Vec<u8> {
     #[inline]
@@ -583,42 +815,322 @@ impl Encodable for Vec<u8> {
         s.emit_slice(&self)?;
         Ok(vi_len + self.len())
     }
-    
+
+    #[inline]
+    fn consensus_encoded_len(&self) -> usize {
+        VarInt(self.len() as u64).len() + self.len()
+    }
 }
 
 impl Decodable for Vec<u8> {
     fn consensus_decode<D: io::Read>(mut d: D) -> Result<Self, Error> {
-        let len = VarInt::consensus_decode(&mut d)?.0 as usize;
+        let len = trace_field!("length", VarInt::consensus_decode(&mut d)?).0 as usize;
         if len > MAX_VEC_SIZE {
             return Err(self::Error::OversizedVectorAllocation { requested: len, max: MAX_VEC_SIZE })
         }
-        let mut ret = Vec::with_capacity(len);
-        ret.resize(len, 0);
-        d.read_slice(&mut ret)?;
+        trace_field!("data", read_bytes(d, len))
+    }
+}
+
+/// Data framed by its length and a checksum, the first 4 bytes of its
+/// sha256d, so that a torn or corrupted frame is detected when decoded
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct CheckedData(pub Vec<u8>);
+
+fn sha2_checksum(data: &[u8]) -> [u8; 4] {
+    let mut checksum = [0; 4];
+    checksum.copy_from_slice(&sha256d::Hash::hash(data)[0..4]);
+    checksum
+}
+
+impl Encodable for CheckedData {
+    fn consensus_encode<S: io::Write>(&self, mut s: S) -> Result<usize, Error> {
+        (self.0.len() as u32).consensus_encode(&mut s)?;
+        sha2_checksum(&self.0).consensus_encode(&mut s)?;
+        s.emit_slice(&self.0)?;
+        Ok(8 + self.0.len())
+    }
+
+    fn consensus_encoded_len(&self) -> usize {
+        8 + self.0.len()
+    }
+}
+
+impl Decodable for CheckedData {
+    fn consensus_decode<D: io::Read>(mut d: D) -> Result<Self, Error> {
+        let len = u32::consensus_decode(&mut d)? as usize;
+        if len > MAX_VEC_SIZE {
+            return Err(self::Error::OversizedVectorAllocation { requested: len, max: MAX_VEC_SIZE })
+        }
+        let expected: [u8; 4] = Decodable::consensus_decode(&mut d)?;
+        let data = read_bytes(d, len)?;
+        let actual = sha2_checksum(&data);
+        if actual != expected {
+            return Err(self::Error::InvalidChecksum { expected, actual });
+        }
+        Ok(CheckedData(data))
+    }
+}
+
+// Tuples
+macro_rules! tuple_encode {
+    ($($x:ident),*) => {
+        impl <$($x: Encodable),*> Encodable for ($($x),*) {
+            #[inline]
+            #[allow(non_snake_case)]
+            fn consensus_encode<S: io::Write>(&self, mut s: S) -> Result<usize, Error> {
+                let &($(ref $x),*) = self;
+                let mut len = 0;
+                $(len += $x.consensus_encode(&mut s)?;)*
+                Ok(len)
+            }
+        }
+
+        impl<$($x: Decodable),*> Decodable for ($($x),*) {
+            #[inline]
+            #[allow(non_snake_case)]
+            fn consensus_decode<D: io::Read>(mut d: D) -> Result<Self, Error> {
+                Ok(($({let $x = Decodable::consensus_decode(&mut d)?; $x }),*))
+            }
+        }
+    };
+}
+
+tuple_encode!(T0, T1);
+
+// Maps, as a count followed by each key and its value, in key order so that
+// equal maps have one encoding
+impl<K: Encodable + Ord, V: Encodable> Encodable for BTreeMap<K, V> {
+    fn consensus_encode<S: io::Write>(&self, mut s: S) -> Result<usize, Error> {
+        let mut len = VarInt(self.len() as u64).consensus_encode(&mut s)?;
+        for (key, value) in self.iter() {
+            len += key.consensus_encode(&mut s)?;
+            len += value.consensus_encode(&mut s)?;
+        }
+        Ok(len)
+    }
+}
+
+impl<K: Decodable + Ord, V: Decodable> Decodable for BTreeMap<K, V> {
+    fn consensus_decode<D: io::Read>(mut d: D) -> Result<Self, Error> {
+        let len = VarInt::consensus_decode(&mut d)?.0;
+        let mut ret = BTreeMap::new();
+        for _ in 0..len {
+            let key = Decodable::consensus_decode(&mut d)?;
+            let value = Decodable::consensus_decode(&mut d)?;
+            if ret.insert(key, value).is_some() {
+                return Err(Error::ParseFailed("duplicate key in map"));
+            }
+        }
         Ok(ret)
     }
 }
 
-impl Encodable for sha256d::Hash {
-    fn consensus_encode<S: io::Write>(&self, s: S) -> Result<usize, Error> {
-        self.into_inner().consensus_encode(s)
+impl<K: Encodable + Ord + hash::Hash + Eq, V: Encodable> Encodable for HashMap<K, V> {
+    fn consensus_encode<S: io::Write>(&self, mut s: S) -> Result<usize, Error> {
+        let mut entries: Vec<(&K, &V)> = self.iter().collect();
+        entries.sort_by(|a, b| a.0.cmp(b.0));
+        let mut len = VarInt(entries.len() as u64).consensus_encode(&mut s)?;
+        for (key, value) in entries {
+            len += key.consensus_encode(&mut s)?;
+            len += value.consensus_encode(&mut s)?;
+        }
+        Ok(len)
     }
 }
 
-impl Decodable for sha256d::Hash {
-    fn consensus_decode<D: io::Read>(d: D) -> Result<Self, Error> {
-        Ok(Self::from_inner(<<Self as Hash>::Inner>::consensus_decode(d)?))
+impl<K: Decodable + hash::Hash + Eq, V: Decodable> Decodable for HashMap<K, V> {
+    fn consensus_decode<D: io::Read>(mut d: D) -> Result<Self, Error> {
+        let len = VarInt::consensus_decode(&mut d)?.0;
+        let mut ret = HashMap::new();
+        for _ in 0..len {
+            let key = Decodable::consensus_decode(&mut d)?;
+            let value = Decodable::consensus_decode(&mut d)?;
+            if ret.insert(key, value).is_some() {
+                return Err(Error::ParseFailed("duplicate key in map"));
+            }
+        }
+        Ok(ret)
     }
 }
 
+macro_rules! impl_hash_encodable {
+    ($hashtype:ty) => {
+        impl Encodable for $hashtype {
+            fn consensus_encode<S: io::Write>(&self, s: S) -> Result<usize, Error> {
+                self.into_inner().consensus_encode(s)
+            }
+        }
+
+        impl Decodable for $hashtype {
+            fn consensus_decode<D: io::Read>(d: D) -> Result<Self, Error> {
+                Ok(Self::from_inner(<<Self as Hash>::Inner>::consensus_decode(d)?))
+            }
+        }
+    };
+}
+impl_hash_encodable!(sha256d::Hash);
+impl_hash_encodable!(sha256::Hash);
+impl_hash_encodable!(hash160::Hash);
+
 // Tests
 #[cfg(test)]
 mod tests {
-    use super::serialize;
+    use super::{
+        deserialize, deserialize_hex, deserialize_partial, from_file, serialize, serialize_hex,
+        serialize_into, serialize_with_capacity_hint, CheckedData, Encodable, Error, VarInt,
+    };
+    use std::collections::{BTreeMap, HashMap};
 
     #[test]
     fn serialize_int_test() {
         assert_eq!(serialize(&false), vec![0u8]);
         assert_eq!(serialize(&true), vec![1u8]);
     }
+
+    #[test]
+    fn varint_len_test() {
+        for &n in [0u64, 0xFC, 0xFD, 0xFFFF, 0x10000, 0xFFFFFFFF, 0x100000000].iter() {
+            let vi = VarInt(n);
+            assert_eq!(vi.len(), serialize(&vi).len());
+            assert_eq!(vi.consensus_encoded_len(), serialize(&vi).len());
+        }
+    }
+
+    #[test]
+    fn from_file_test() {
+        use std::{env, fs, process};
+
+        let path = env::temp_dir().join(format!("from_file_test-{}", process::id()));
+        fs::write(&path, serialize(&vec![vec![1u8, 2], vec![3]])).unwrap();
+        assert_eq!(
+            from_file::<Vec<Vec<u8>>, _>(&path).unwrap(),
+            vec![vec![1u8, 2], vec![3]]
+        );
+        assert!(from_file::<u8, _>(&path).is_err());
+        fs::remove_file(&path).unwrap();
+        match from_file::<u8, _>(&path) {
+            Err(Error::Io(_)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn serialize_into_test() {
+        let mut buf = vec![0xFFu8];
+        assert_eq!(serialize_into(&mut buf, &0x0201u16), 2);
+        assert_eq!(serialize_into(&mut buf, &"ab".to_string()), 3);
+        assert_eq!(buf, vec![0xFF, 0x01, 0x02, 0x02, b'a', b'b']);
+    }
+
+    #[test]
+    fn serialize_with_capacity_hint_test() {
+        let data = vec![vec![1u8; 300], vec![2u8; 5]];
+        let encoded = serialize_with_capacity_hint(&data);
+        assert_eq!(encoded, serialize(&data));
+        assert_eq!(encoded.len(), data.consensus_encoded_len());
+        assert_eq!(encoded.capacity(), encoded.len());
+    }
+
+    #[test]
+    fn hex_test() {
+        assert_eq!(serialize_hex(&vec![0xabu8, 0xcd]), "02abcd");
+        assert_eq!(deserialize_hex::<Vec<u8>>("02ABcd").unwrap(), vec![0xab, 0xcd]);
+        match deserialize_hex::<Vec<u8>>("02abc") {
+            Err(Error::Hex(_)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        match deserialize_hex::<u8>("0102") {
+            Err(Error::ParseFailed(_)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn deserialize_partial_test() {
+        let (val, consumed) = deserialize_partial::<u16>(&[0x01, 0x02, 0x03]).unwrap();
+        assert_eq!(val, 0x0201);
+        assert_eq!(consumed, 2);
+    }
+
+    #[test]
+    fn map_test() {
+        let mut map = BTreeMap::new();
+        map.insert(2u8, vec![0xbbu8]);
+        map.insert(1u8, vec![]);
+        let encoded = serialize(&map);
+        assert_eq!(encoded, vec![0x02, 0x01, 0x00, 0x02, 0x01, 0xbb]);
+        assert_eq!(deserialize::<BTreeMap<u8, Vec<u8>>>(&encoded).unwrap(), map);
+
+        // A hash map encodes in key order too
+        let hash_map: HashMap<u8, Vec<u8>> = map.clone().into_iter().collect();
+        assert_eq!(serialize(&hash_map), encoded);
+        assert_eq!(deserialize::<HashMap<u8, Vec<u8>>>(&encoded).unwrap(), hash_map);
+
+        let duplicate = [0x02, 0x01, 0x00, 0x01, 0x01, 0xbb];
+        match deserialize::<BTreeMap<u8, Vec<u8>>>(&duplicate) {
+            Err(Error::ParseFailed(_)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        match deserialize::<HashMap<u8, Vec<u8>>>(&duplicate) {
+            Err(Error::ParseFailed(_)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(deserialize::<BTreeMap<u8, Vec<u8>>>(&[0x02, 0x01, 0x00]).is_err());
+    }
+
+    #[test]
+    fn net_addr_test() {
+        use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+
+        let ipv4 = Ipv4Addr::new(10, 0, 0, 1);
+        assert_eq!(serialize(&ipv4), vec![10, 0, 0, 1]);
+        assert_eq!(deserialize::<Ipv4Addr>(&[10, 0, 0, 1]).unwrap(), ipv4);
+        let ipv6 = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1);
+        let encoded = serialize(&ipv6);
+        assert_eq!(&encoded[..4], &[0x20, 0x01, 0x0d, 0xb8]);
+        assert_eq!(encoded.len(), 16);
+        assert_eq!(deserialize::<Ipv6Addr>(&encoded).unwrap(), ipv6);
+
+        // As in an addr message, with the port in big-endian
+        let v4 = SocketAddr::V4(SocketAddrV4::new(ipv4, 8333));
+        let encoded = serialize(&v4);
+        assert_eq!(
+            encoded,
+            vec![0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, 10, 0, 0, 1, 0x20, 0x8d]
+        );
+        assert_eq!(deserialize::<SocketAddr>(&encoded).unwrap(), v4);
+        assert_eq!(
+            deserialize::<SocketAddrV4>(&encoded).unwrap(),
+            SocketAddrV4::new(ipv4, 8333)
+        );
+        let v6 = SocketAddr::V6(SocketAddrV6::new(ipv6, 18333, 0, 0));
+        let encoded = serialize(&v6);
+        assert_eq!(encoded.len(), 18);
+        assert_eq!(deserialize::<SocketAddr>(&encoded).unwrap(), v6);
+        assert!(deserialize::<SocketAddrV4>(&encoded).is_err());
+        assert!(deserialize::<SocketAddr>(&encoded[..17]).is_err());
+    }
+
+    #[test]
+    fn checked_data_test() {
+        let data = CheckedData(vec![1, 2, 3, 4, 5]);
+        let encoded = serialize(&data);
+        // Length, the first bytes of sha256d(0102030405) and the data
+        assert_eq!(
+            encoded,
+            vec![5, 0, 0, 0, 0xa2, 0x6b, 0xaf, 0x5a, 1, 2, 3, 4, 5]
+        );
+        assert_eq!(encoded.len(), data.consensus_encoded_len());
+        assert_eq!(deserialize::<CheckedData>(&encoded).unwrap(), data);
+
+        let mut corrupted = encoded.clone();
+        corrupted[10] ^= 1;
+        match deserialize::<CheckedData>(&corrupted) {
+            Err(Error::InvalidChecksum { .. }) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(deserialize::<CheckedData>(&encoded[..12]).is_err());
+        assert!(deserialize::<CheckedData>(&[0xff, 0xff, 0xff, 0xff, 0, 0, 0, 0]).is_err());
+    }
 }
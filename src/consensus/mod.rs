@@ -4,6 +4,12 @@
 //! conform to Bitcoin consensus.
 
 pub mod encode;
+// Browsers have no threads to run a pipeline on
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub mod pipeline;
+#[cfg(feature = "decode-trace")]
+pub mod trace;
+pub mod versionbits;
 
 pub use self::encode::{deserialize, deserialize_partial, serialize};
 pub use self::encode::{Decodable, Encodable, ReadExt, WriteExt};
@@ -0,0 +1,293 @@
+//! Decoding Pipeline
+//!
+//! Scanning the whole chain means decoding and checking hundreds of
+//! gigabytes of blocks. Decoding an object and checking it on its own, such
+//! as verifying its scripts, can happen in any order and so can be spread
+//! over threads, but updates to shared state such as the UTXO set have to
+//! be applied one object at a time, in chain order.
+//!
+//! A `Pipeline` does the first part on a pool of worker threads and hands
+//! the objects back to the calling thread in their original order. Only a
+//! bounded number of objects are in flight at once, so that a slow
+//! consumer holds the reading back instead of letting decoded objects pile
+//! up in memory.
+
+use std::collections::BTreeMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::{error, fmt, thread};
+
+use consensus::encode::{self, deserialize};
+use error::{ErrorCode, ErrorKind};
+
+/// The default number of objects in flight per worker thread
+pub const DEFAULT_QUEUE_DEPTH_PER_THREAD: usize = 4;
+
+/// A failure of the object at the given index of the input
+#[derive(Debug)]
+pub enum Error<E> {
+    /// The object could not be decoded
+    Decode(usize, encode::Error),
+    /// The object was rejected by the check or while being committed
+    Rejected(usize, E),
+}
+
+impl<E: fmt::Display> fmt::Display for Error<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Decode(index, ref e) => write!(f, "object {}: {}", index, e),
+            Error::Rejected(index, ref e) => write!(f, "object {} rejected: {}", index, e),
+        }
+    }
+}
+
+impl<E: error::Error> error::Error for Error<E> {
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::Decode(_, ref e) => Some(e),
+            Error::Rejected(_, ref e) => Some(e),
+        }
+    }
+
+    fn description(&self) -> &str {
+        match *self {
+            Error::Decode(..) => "object could not be decoded",
+            Error::Rejected(..) => "object rejected",
+        }
+    }
+}
+
+impl<E> ErrorCode for Error<E> {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Pipeline
+    }
+
+    fn number(&self) -> u32 {
+        match *self {
+            Error::Decode(..) => 1,
+            Error::Rejected(..) => 2,
+        }
+    }
+}
+
+/// Decodes and checks objects on worker threads, committing them in order
+/// on the calling thread
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Pipeline {
+    threads: usize,
+    queue_depth: usize,
+}
+
+impl Pipeline {
+    /// Creates a pipeline with the given number of worker threads, keeping
+    /// `DEFAULT_QUEUE_DEPTH_PER_THREAD` objects in flight per thread
+    ///
+    /// # Panics
+    ///
+    /// If `threads` is 0.
+    pub fn new(threads: usize) -> Pipeline {
+        assert!(threads > 0, "a pipeline needs a worker thread");
+        Pipeline {
+            threads,
+            queue_depth: threads * DEFAULT_QUEUE_DEPTH_PER_THREAD,
+        }
+    }
+
+    /// Sets the most objects read but not yet committed
+    ///
+    /// # Panics
+    ///
+    /// If `depth` is 0.
+    pub fn with_queue_depth(mut self, depth: usize) -> Pipeline {
+        assert!(depth > 0, "a pipeline needs room for an object");
+        self.queue_depth = depth;
+        self
+    }
+
+    /// Decodes each item of `input` as a `T` and runs `check` on it on the
+    /// worker threads, then passes the objects to `commit` in input order.
+    ///
+    /// Stops at the first object in input order which fails to decode or
+    /// is rejected by `check` or `commit`; every object before it has been
+    /// committed.
+    ///
+    /// # Panics
+    ///
+    /// If `check` panics, the panic is passed on to the calling thread.
+    pub fn run<I, T, E, C, F>(&self, input: I, check: C, mut commit: F) -> Result<usize, Error<E>>
+    where
+        I: IntoIterator<Item = Vec<u8>>,
+        T: encode::Decodable + Send + 'static,
+        E: Send + 'static,
+        C: Fn(&T) -> Result<(), E> + Send + Sync + 'static,
+        F: FnMut(T) -> Result<(), E>,
+    {
+        let (job_tx, job_rx) = mpsc::channel::<(usize, Vec<u8>)>();
+        let (result_tx, result_rx) =
+            mpsc::channel::<(usize, thread::Result<Result<T, Error<E>>>)>();
+        let job_rx = Arc::new(Mutex::new(job_rx));
+        let check = Arc::new(check);
+
+        let workers: Vec<_> = (0..self.threads)
+            .map(|_| {
+                let job_rx = job_rx.clone();
+                let result_tx = result_tx.clone();
+                let check = check.clone();
+                thread::spawn(move || loop {
+                    let job = job_rx.lock().expect("no worker panics").recv();
+                    let (index, bytes) = match job {
+                        Ok(job) => job,
+                        Err(_) => return,
+                    };
+                    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                        deserialize::<T>(&bytes)
+                            .map_err(|e| Error::Decode(index, e))
+                            .and_then(|obj| match check(&obj) {
+                                Ok(()) => Ok(obj),
+                                Err(e) => Err(Error::Rejected(index, e)),
+                            })
+                    }));
+                    if result_tx.send((index, result)).is_err() {
+                        return;
+                    }
+                })
+            })
+            .collect();
+        drop(result_tx);
+
+        let mut input = input.into_iter();
+        let mut sent = 0;
+        let mut exhausted = false;
+        let mut committed = 0;
+        // Results which arrived ahead of an earlier object
+        let mut ready = BTreeMap::new();
+        let outcome = loop {
+            while !exhausted && sent - committed < self.queue_depth {
+                match input.next() {
+                    Some(bytes) => {
+                        job_tx
+                            .send((sent, bytes))
+                            .expect("workers outlive the jobs");
+                        sent += 1;
+                    }
+                    None => exhausted = true,
+                }
+            }
+            if committed == sent {
+                break Ok(committed);
+            }
+
+            let (index, result) = result_rx.recv().expect("a worker holds every job sent");
+            ready.insert(index, result.unwrap_or_else(|p| panic::resume_unwind(p)));
+            let mut failure = None;
+            while let Some(result) = ready.remove(&committed) {
+                match result.and_then(|obj| commit(obj).map_err(|e| Error::Rejected(committed, e)))
+                {
+                    Ok(()) => committed += 1,
+                    Err(e) => {
+                        failure = Some(e);
+                        break;
+                    }
+                }
+            }
+            if let Some(e) = failure {
+                break Err(e);
+            }
+        };
+
+        // Workers finish the jobs in flight and exit once the queue is
+        // closed; their results are discarded
+        drop(job_tx);
+        drop(result_rx);
+        for worker in workers {
+            let _ = worker.join();
+        }
+        outcome
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use consensus::encode::serialize;
+
+    fn items(n: u64) -> Vec<Vec<u8>> {
+        (0..n).map(|i| serialize(&i)).collect()
+    }
+
+    #[test]
+    fn commits_in_order() {
+        let mut seen = vec![];
+        let count = Pipeline::new(4)
+            .run(
+                items(100),
+                |_: &u64| Ok::<(), ()>(()),
+                |n| {
+                    seen.push(n);
+                    Ok(())
+                },
+            )
+            .unwrap();
+        assert_eq!(count, 100);
+        assert_eq!(seen, (0..100).collect::<Vec<u64>>());
+
+        let count = Pipeline::new(1)
+            .with_queue_depth(1)
+            .run(vec![], |_: &u64| Ok::<(), ()>(()), |_| Ok(()))
+            .unwrap();
+        assert_eq!(count, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "bad object")]
+    fn check_panics() {
+        let _ = Pipeline::new(2).run(
+            items(10),
+            |n: &u64| {
+                if *n == 3 {
+                    panic!("bad object")
+                } else {
+                    Ok::<(), ()>(())
+                }
+            },
+            |_| Ok(()),
+        );
+    }
+
+    #[test]
+    fn stops_at_first_failure() {
+        let mut seen = vec![];
+        let result = Pipeline::new(3).run(
+            items(50),
+            |n: &u64| if *n % 20 == 19 { Err(*n) } else { Ok(()) },
+            |n| {
+                seen.push(n);
+                Ok(())
+            },
+        );
+        match result {
+            Err(Error::Rejected(19, 19)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert_eq!(seen, (0..19).collect::<Vec<u64>>());
+
+        let mut input = items(10);
+        input[5] = vec![1, 2];
+        match Pipeline::new(2).run(input, |_: &u64| Ok::<(), ()>(()), |_| Ok(())) {
+            Err(Error::Decode(5, _)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let result = Pipeline::new(2).run(
+            items(10),
+            |_: &u64| Ok(()),
+            |n| if n == 7 { Err("spent twice") } else { Ok(()) },
+        );
+        match result {
+            Err(Error::Rejected(7, "spent twice")) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
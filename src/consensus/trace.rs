@@ -0,0 +1,416 @@
+//! Decoder Traces
+//!
+//! With the `decode-trace` feature, decoders record the fields they read,
+//! with their offsets, so that the layout of a transaction, block header or
+//! network message can be followed byte by byte. `trace` decodes an object
+//! like `deserialize` and returns the `Trace` of the fields read next to
+//! the result. A failed decoding is traced up to the field it failed in,
+//! which shows where the data went wrong.
+//!
+//! The `Display` of a trace is an annotated hexdump, one line per field
+//! with the fields it is made of indented below it:
+//!
+//! ```text
+//! 000000  01 00 00 00                                      version
+//! 000004                                                   inputs
+//! 000004  01                                                 count
+//! 000005                                                     [0]
+//! ```
+//!
+//! Decoders name their fields with the `trace_field!` macro, which is the
+//! bare decoding without the feature. Fields are only recorded inside
+//! `trace`, on the thread calling it.
+
+use std::cell::RefCell;
+use std::{cmp, fmt, io, mem};
+
+use consensus::encode::{self, Decodable};
+
+/// The number of bytes shown per line of a hexdump
+const BYTES_PER_LINE: usize = 16;
+
+/// A field read by a decoder
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Field {
+    /// The name of the field, or its index in brackets for the elements of
+    /// a vector
+    pub name: String,
+    /// The offset of its first byte
+    pub offset: usize,
+    /// The number of bytes it spans
+    pub len: usize,
+    /// The fields it is made of, in order
+    pub children: Vec<Field>,
+}
+
+impl Field {
+    fn new(name: String, offset: usize) -> Field {
+        Field {
+            name,
+            offset,
+            len: 0,
+            children: vec![],
+        }
+    }
+}
+
+/// The fields read while decoding an object
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Trace {
+    /// The bytes read
+    pub bytes: Vec<u8>,
+    /// The outermost fields, in order
+    pub fields: Vec<Field>,
+}
+
+impl Trace {
+    /// The bytes of `field`
+    pub fn field_bytes(&self, field: &Field) -> &[u8] {
+        let start = cmp::min(field.offset, self.bytes.len());
+        let end = cmp::min(field.offset + field.len, self.bytes.len());
+        &self.bytes[start..end]
+    }
+
+    /// The first field found by following `path`, the names of a field and
+    /// of the fields it is made of down to the one wanted
+    pub fn find(&self, path: &[&str]) -> Option<&Field> {
+        let (first, rest) = path.split_first()?;
+        let mut field = self.fields.iter().find(|f| f.name == *first)?;
+        for name in rest {
+            field = field.children.iter().find(|f| f.name == *name)?;
+        }
+        Some(field)
+    }
+
+    fn fmt_line(
+        &self,
+        f: &mut fmt::Formatter,
+        offset: usize,
+        bytes: &[u8],
+        label: &str,
+    ) -> fmt::Result {
+        let mut hex = String::with_capacity(3 * BYTES_PER_LINE);
+        for (i, byte) in bytes.iter().enumerate() {
+            if i > 0 {
+                hex.push(' ');
+            }
+            hex.push_str(&format!("{:02x}", byte));
+        }
+        let line = format!(
+            "{:06x}  {:width$}  {}",
+            offset,
+            hex,
+            label,
+            width = 3 * BYTES_PER_LINE - 1
+        );
+        writeln!(f, "{}", line.trim_end())
+    }
+
+    fn fmt_fields(
+        &self,
+        f: &mut fmt::Formatter,
+        fields: &[Field],
+        start: usize,
+        end: usize,
+        depth: usize,
+    ) -> fmt::Result {
+        let indent = "  ".repeat(depth);
+        let mut pos = start;
+        for field in fields {
+            // Bytes read by the parent outside of any field
+            if field.offset > pos {
+                self.fmt_bytes(f, pos, field.offset, &format!("{}?", indent))?;
+            }
+            let label = format!("{}{}", indent, field.name);
+            if field.children.is_empty() {
+                self.fmt_bytes(f, field.offset, field.offset + field.len, &label)?;
+            } else {
+                self.fmt_line(f, field.offset, &[], &label)?;
+                let field_end = field.offset + field.len;
+                self.fmt_fields(f, &field.children, field.offset, field_end, depth + 1)?;
+            }
+            pos = cmp::max(pos, field.offset + field.len);
+        }
+        if end > pos {
+            self.fmt_bytes(f, pos, end, &format!("{}?", indent))?;
+        }
+        Ok(())
+    }
+
+    /// Writes the bytes from `start` to `end`, labelling the first line
+    fn fmt_bytes(
+        &self,
+        f: &mut fmt::Formatter,
+        start: usize,
+        end: usize,
+        label: &str,
+    ) -> fmt::Result {
+        let end = cmp::min(end, self.bytes.len());
+        if start >= end {
+            return self.fmt_line(f, start, &[], label);
+        }
+        let mut offset = start;
+        for chunk in self.bytes[start..end].chunks(BYTES_PER_LINE) {
+            let label = if offset == start { label } else { "" };
+            self.fmt_line(f, offset, chunk, label)?;
+            offset += chunk.len();
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_fields(f, &self.fields, 0, self.bytes.len(), 0)
+    }
+}
+
+/// The trace being taken on a thread
+struct Recorder {
+    /// The number of bytes read
+    pos: usize,
+    /// The fields being read, outermost first, below a nameless root
+    open: Vec<Field>,
+}
+
+thread_local! {
+    static RECORDER: RefCell<Option<Recorder>> = const { RefCell::new(None) };
+}
+
+/// Decodes an object from `data` like `deserialize`, tracing the fields
+/// read
+pub fn trace<T: Decodable>(data: &[u8]) -> (Result<T, encode::Error>, Trace) {
+    let recorder = Recorder {
+        pos: 0,
+        open: vec![Field::new(String::new(), 0)],
+    };
+    let outer = RECORDER.with(|r| r.borrow_mut().replace(recorder));
+    let result = encode::deserialize(data);
+    let recorder = RECORDER
+        .with(|r| mem::replace(&mut *r.borrow_mut(), outer))
+        .expect("recorder set by trace");
+
+    // Every field is closed by now, even after an error
+    let root = recorder
+        .open
+        .into_iter()
+        .next()
+        .expect("root field is never closed");
+    let trace = Trace {
+        bytes: data[..cmp::min(recorder.pos, data.len())].to_vec(),
+        fields: root.children,
+    };
+    (result, trace)
+}
+
+/// Closes the innermost open field when dropped. See `trace_field!`
+#[must_use]
+pub struct FieldGuard {
+    /// Whether a field was opened
+    open: bool,
+    /// Where the field ends, if not at the last byte read
+    end: Option<usize>,
+}
+
+impl Drop for FieldGuard {
+    fn drop(&mut self) {
+        if !self.open {
+            return;
+        }
+        let end = self.end;
+        RECORDER.with(|r| {
+            if let Some(ref mut recorder) = *r.borrow_mut() {
+                if let Some(end) = end {
+                    recorder.pos = end;
+                }
+                let mut field = recorder.open.pop().expect("field opened by a guard");
+                field.len = recorder.pos - field.offset;
+                recorder
+                    .open
+                    .last_mut()
+                    .expect("root field is never closed")
+                    .children
+                    .push(field);
+            }
+        })
+    }
+}
+
+/// Opens a field starting `rewind` bytes back and ending `len` bytes after
+/// its start, or at the last byte read if `len` is `None`
+fn open<F: FnOnce() -> String>(name: F, rewind: usize, len: Option<usize>) -> FieldGuard {
+    RECORDER.with(|r| match *r.borrow_mut() {
+        Some(ref mut recorder) => {
+            recorder.pos -= cmp::min(rewind, recorder.pos);
+            let offset = recorder.pos;
+            recorder.open.push(Field::new(name(), offset));
+            FieldGuard {
+                open: true,
+                end: len.map(|len| offset + len),
+            }
+        }
+        None => FieldGuard {
+            open: false,
+            end: None,
+        },
+    })
+}
+
+/// Opens a field named `name`, closed when the guard is dropped
+pub fn enter(name: &str) -> FieldGuard {
+    open(|| name.to_owned(), 0, None)
+}
+
+/// Opens the field of the element at `index` of a vector
+pub fn enter_index(index: usize) -> FieldGuard {
+    open(|| format!("[{}]", index), 0, None)
+}
+
+/// Opens a field named `name` over the `len` bytes just read, for data
+/// read whole and then decoded from memory, such as the payload of a
+/// network message. The field ends after those bytes whatever the
+/// decoding reads.
+pub fn replay(name: &str, len: usize) -> FieldGuard {
+    open(|| name.to_owned(), len, Some(len))
+}
+
+/// Renames the last field closed within the open one, dropping the fields
+/// it is made of, for a field which turns out to be something else once
+/// read, such as the segwit marker read as the input count
+pub fn relabel_last(name: &str) {
+    RECORDER.with(|r| {
+        if let Some(ref mut recorder) = *r.borrow_mut() {
+            let parent = recorder
+                .open
+                .last_mut()
+                .expect("root field is never closed");
+            if let Some(field) = parent.children.last_mut() {
+                field.name = name.to_owned();
+                field.children.clear();
+            }
+        }
+    })
+}
+
+/// A reader which counts the bytes read through it towards the trace
+/// being taken, if any
+pub struct Reader<R>(pub R);
+
+impl<R: io::Read> io::Read for Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.0.read(buf)?;
+        RECORDER.with(|r| {
+            if let Some(ref mut recorder) = *r.borrow_mut() {
+                recorder.pos += n;
+            }
+        });
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use blockdata::block::BlockHeader;
+    use blockdata::transaction::Transaction;
+    use consensus::encode::serialize;
+    use hashes::hex::FromHex;
+    use network::message::RawNetworkMessage;
+
+    #[test]
+    fn transaction() {
+        // A segwit spend with one input and one output
+        let data = Vec::<u8>::from_hex("02000000000101595895ea20179de87052b4046dfe6fd515860505d6511a9004cf12a1f93cac7c0100000000ffffffff01deb807000000000017a9140f3444e271620c736808aa7b33e370bd87cb5a078702483045022100fb60dad8df4af2841adc0346638c16d0b8035f5e3f3753b88db122e70c79f9370220756e6633b17fd2710e626347d28d60b0a2d6cbb41de51740644b9fb3ba7751040121028fa937ca8cba2197a37c007176ed8941055d3bcb8627d085e94553e62f057dcc00000000").unwrap();
+        let (result, trace) = trace::<Transaction>(&data);
+        let tx = result.unwrap();
+        assert_eq!(trace.bytes, data);
+
+        let names: Vec<&str> = trace.fields.iter().map(|f| &f.name[..]).collect();
+        assert_eq!(
+            names,
+            vec![
+                "version",
+                "marker",
+                "flag",
+                "inputs",
+                "outputs",
+                "witnesses",
+                "lock_time"
+            ]
+        );
+        let version = trace.find(&["version"]).unwrap();
+        assert_eq!((version.offset, version.len), (0, 4));
+        assert_eq!(trace.field_bytes(version), &[2, 0, 0, 0]);
+        assert!(trace.find(&["marker"]).unwrap().children.is_empty());
+        let sequence = trace.find(&["inputs", "[0]", "sequence"]).unwrap();
+        assert_eq!(trace.field_bytes(sequence), &[0xff; 4]);
+        let witness = trace.find(&["witnesses", "[0]"]).unwrap();
+        assert_eq!(witness.len, tx.input[0].witness_size());
+        let lock_time = trace.find(&["lock_time"]).unwrap();
+        assert_eq!(lock_time.offset + lock_time.len, data.len());
+
+        let dump = trace.to_string();
+        assert!(dump.starts_with("000000  02 00 00 00"));
+        assert!(dump.lines().next().unwrap().ends_with("version"));
+        assert!(dump.contains("    script_pubkey\n"));
+        assert_eq!(
+            dump.lines().filter(|l| l.trim_end().ends_with('?')).count(),
+            0
+        );
+    }
+
+    #[test]
+    fn failure() {
+        // A header cut short in its nonce
+        let header = BlockHeader {
+            version: 1,
+            prev_blockhash: Default::default(),
+            merkle_root: Default::default(),
+            time: 1231006505,
+            bits: 0x1d00ffff,
+            nonce: 2083236893,
+        };
+        let mut data = serialize(&header);
+        data.truncate(78);
+        let (result, trace) = trace::<BlockHeader>(&data);
+        assert!(result.is_err());
+        let names: Vec<&str> = trace.fields.iter().map(|f| &f.name[..]).collect();
+        assert_eq!(
+            names,
+            vec![
+                "version",
+                "prev_blockhash",
+                "merkle_root",
+                "time",
+                "bits",
+                "nonce"
+            ]
+        );
+        assert_eq!(trace.find(&["bits"]).unwrap().offset, 72);
+        // The field it failed in spans what could be read of it
+        assert_eq!(trace.find(&["nonce"]).unwrap().len, 2);
+        assert_eq!(trace.bytes, data);
+    }
+
+    #[test]
+    fn message() {
+        let data =
+            Vec::<u8>::from_hex("f9beb4d970696e670000000000000000080000002467f11d6400000000000000")
+                .unwrap();
+        let (result, trace) = trace::<RawNetworkMessage>(&data);
+        assert!(result.is_ok());
+        let names: Vec<&str> = trace.fields.iter().map(|f| &f.name[..]).collect();
+        assert_eq!(
+            names,
+            vec!["magic", "command", "length", "checksum", "payload"]
+        );
+        let payload = trace.find(&["payload"]).unwrap();
+        assert_eq!((payload.offset, payload.len), (24, 8));
+        assert_eq!(trace.bytes, data);
+
+        // Decoding outside of `trace` records nothing
+        assert!(encode::deserialize::<RawNetworkMessage>(&data).is_ok());
+        RECORDER.with(|r| assert!(r.borrow().is_none()));
+    }
+}
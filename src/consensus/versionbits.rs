@@ -0,0 +1,358 @@
+//! Version Bits
+//!
+//! Soft forks deployed through
+//! https://github.com/bitcoin/bips/blob/master/bip-0009.mediawiki are
+//! activated by miners setting a bit of the block version. The chain is cut
+//! into periods of 2016 blocks, the retarget periods, and a deployment
+//! moves through its states only at their boundaries: it is `Started` once
+//! the median time past reaches its start time, `LockedIn` after a period
+//! in which enough blocks signal, and `Active` one period later, or from
+//! its minimum activation height if that is later. If it times out before
+//! locking in it has `Failed`.
+//!
+//! Heights are those of a `HeaderChain`, so its root has to be the genesis
+//! block for the periods to line up with the network's.
+
+use std::cmp;
+
+use blockdata::headerchain::HeaderChain;
+
+/// The top three bits of a version which signals version bits
+const TOP_BITS: u32 = 0x20000000;
+/// The mask of the top three bits
+const TOP_MASK: u32 = 0xe0000000;
+/// The number of blocks the median time past is taken over
+const MEDIAN_TIME_SPAN: u32 = 11;
+
+/// The state of a deployment, which applies to every block of a period
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
+pub enum ThresholdState {
+    /// The deployment has not started
+    Defined,
+    /// Blocks may signal for the deployment
+    Started,
+    /// Enough blocks signalled, and the deployment will activate
+    LockedIn,
+    /// The rules of the deployment are enforced
+    Active,
+    /// The deployment timed out without locking in
+    Failed,
+}
+
+impl ThresholdState {
+    /// The name Bitcoin Core gives the state
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ThresholdState::Defined => "defined",
+            ThresholdState::Started => "started",
+            ThresholdState::LockedIn => "locked_in",
+            ThresholdState::Active => "active",
+            ThresholdState::Failed => "failed",
+        }
+    }
+}
+
+/// A deployment through version bits
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
+pub struct Deployment {
+    /// The name of the deployment
+    pub name: &'static str,
+    /// The bit of the version blocks signal with, from 0 to 28
+    pub bit: u8,
+    /// The median time past from which blocks may signal, or one of
+    /// `ALWAYS_ACTIVE` and `NEVER_ACTIVE`
+    pub start_time: i64,
+    /// The median time past from which the deployment fails if it has not
+    /// locked in
+    pub timeout: i64,
+    /// The lowest height at which the deployment may activate
+    pub min_activation_height: u32,
+}
+
+impl Deployment {
+    /// A `start_time` for a deployment active from the genesis block
+    pub const ALWAYS_ACTIVE: i64 = -1;
+    /// A `start_time` for a deployment which never activates
+    pub const NEVER_ACTIVE: i64 = -2;
+    /// A `timeout` for a deployment which never times out
+    pub const NO_TIMEOUT: i64 = i64::MAX;
+
+    /// The Speedy Trial deployment of taproot on mainnet
+    pub const TAPROOT: Deployment = Deployment {
+        name: "taproot",
+        bit: 2,
+        start_time: 1_619_222_400,
+        timeout: 1_628_640_000,
+        min_activation_height: 709_632,
+    };
+
+    /// Whether a block of `version` signals for the deployment
+    pub fn is_signalled_by(&self, version: i32) -> bool {
+        let version = version as u32;
+        version & TOP_MASK == TOP_BITS && version & (1 << self.bit) != 0
+    }
+}
+
+/// What happened in one period of a deployment
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
+pub struct PeriodState {
+    /// The height of the first block of the period
+    pub start_height: u32,
+    /// The state of the deployment during the period
+    pub state: ThresholdState,
+    /// The number of blocks of the period in the chain, fewer than the
+    /// period for the last one
+    pub blocks: u32,
+    /// The number of those blocks signalling for the deployment
+    pub signalling: u32,
+}
+
+/// Tracks the states of version bits deployments along a chain
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct VersionBits {
+    period: u32,
+    threshold: u32,
+    deployments: Vec<Deployment>,
+}
+
+impl VersionBits {
+    /// Creates a tracker of periods of `period` blocks, in which
+    /// `threshold` blocks have to signal for a deployment to lock in
+    ///
+    /// # Panics
+    ///
+    /// If `period` is 0.
+    pub fn new(period: u32, threshold: u32) -> VersionBits {
+        assert!(period > 0, "version bits period must not be 0");
+        VersionBits {
+            period,
+            threshold,
+            deployments: vec![],
+        }
+    }
+
+    /// The parameters of mainnet, where 95% of a period has to signal
+    pub fn mainnet() -> VersionBits {
+        VersionBits::new(2016, 1916)
+    }
+
+    /// The parameters of testnet, where 75% of a period has to signal
+    pub fn testnet() -> VersionBits {
+        VersionBits::new(2016, 1512)
+    }
+
+    /// Tracks `deployment` too
+    pub fn with_deployment(mut self, deployment: Deployment) -> VersionBits {
+        self.deployments.push(deployment);
+        self
+    }
+
+    /// The deployments tracked
+    pub fn deployments(&self) -> &[Deployment] {
+        &self.deployments
+    }
+
+    /// The state of `deployment` and how many blocks signalled for it in
+    /// each period of the best chain of `chain`, up to the one the next
+    /// block will be in
+    pub fn periods(&self, chain: &HeaderChain, deployment: &Deployment) -> Vec<PeriodState> {
+        let header = |height: u32| {
+            let hash = chain
+                .hash_at_height(height)
+                .expect("height is in the best chain");
+            chain
+                .get(&hash)
+                .expect("best chain headers are stored")
+                .header
+        };
+        let median_time_past = |height: u32| {
+            let first = height.saturating_sub(MEDIAN_TIME_SPAN - 1);
+            let mut times: Vec<u32> = (first..=height).map(|h| header(h).time).collect();
+            times.sort_unstable();
+            i64::from(times[times.len() / 2])
+        };
+
+        let mut state = match deployment.start_time {
+            Deployment::ALWAYS_ACTIVE => ThresholdState::Active,
+            Deployment::NEVER_ACTIVE => ThresholdState::Failed,
+            _ => ThresholdState::Defined,
+        };
+        let next = chain.height() + 1;
+        let mut ret = vec![];
+        let mut start = 0;
+        loop {
+            let end = cmp::min(start + self.period, next);
+            let signalling = (start..end)
+                .filter(|&h| deployment.is_signalled_by(header(h).version))
+                .count() as u32;
+            ret.push(PeriodState {
+                start_height: start,
+                state,
+                blocks: end - start,
+                signalling,
+            });
+            if next < start + self.period {
+                return ret;
+            }
+
+            // The state of the next period follows from this one
+            let mtp = median_time_past(start + self.period - 1);
+            start += self.period;
+            state = match state {
+                ThresholdState::Defined if mtp >= deployment.start_time => ThresholdState::Started,
+                ThresholdState::Started if signalling >= self.threshold => ThresholdState::LockedIn,
+                ThresholdState::Started if mtp >= deployment.timeout => ThresholdState::Failed,
+                ThresholdState::LockedIn if start >= deployment.min_activation_height => {
+                    ThresholdState::Active
+                }
+                state => state,
+            };
+        }
+    }
+
+    /// The state of `deployment` for the next block on the best chain of
+    /// `chain`
+    pub fn state(&self, chain: &HeaderChain, deployment: &Deployment) -> ThresholdState {
+        self.periods(chain, deployment)
+            .last()
+            .expect("there is always a current period")
+            .state
+    }
+
+    /// The current period of each deployment tracked, with its state for
+    /// the next block and the signalling so far
+    pub fn current(&self, chain: &HeaderChain) -> Vec<(Deployment, PeriodState)> {
+        self.deployments
+            .iter()
+            .map(|d| {
+                let current = *self
+                    .periods(chain, d)
+                    .last()
+                    .expect("there is always a current period");
+                (*d, current)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use testutil::mine_header;
+
+    /// A chain of a genesis block and a block for each of `versions`, a
+    /// minute apart
+    fn chain(versions: &[i32]) -> HeaderChain {
+        let genesis = mine_header(Default::default(), 1, 1000);
+        let mut chain = HeaderChain::new(genesis);
+        let mut prev = genesis.block_hash();
+        for (i, &version) in versions.iter().enumerate() {
+            let header = mine_header(prev, version, 1000 + 60 * (i as u32 + 1));
+            chain.add_header(header).unwrap();
+            prev = header.block_hash();
+        }
+        chain
+    }
+
+    const DEPLOYMENT: Deployment = Deployment {
+        name: "test",
+        bit: 1,
+        start_time: 1300,
+        timeout: 3000,
+        min_activation_height: 0,
+    };
+
+    #[test]
+    fn signalling() {
+        assert!(DEPLOYMENT.is_signalled_by(0x20000002));
+        assert!(DEPLOYMENT.is_signalled_by(0x3fffffff));
+        assert!(!DEPLOYMENT.is_signalled_by(0x20000001));
+        assert!(!DEPLOYMENT.is_signalled_by(0x60000002));
+        assert!(!DEPLOYMENT.is_signalled_by(2));
+        assert!(Deployment::TAPROOT.is_signalled_by(0x20000004));
+    }
+
+    #[test]
+    fn activation() {
+        let signal = 0x20000002;
+        let other = 0x20000000;
+        // Periods of 5 blocks, 4 of which have to signal
+        let bits = VersionBits::new(5, 4).with_deployment(DEPLOYMENT);
+
+        let mut versions = vec![other; 9];
+        versions.extend_from_slice(&[signal, signal, signal, other, other]);
+        versions.extend_from_slice(&[signal, other, signal, signal, signal]);
+        let periods = bits.periods(&chain(&versions), &DEPLOYMENT);
+        let states: Vec<_> = periods.iter().map(|p| p.state).collect();
+        assert_eq!(
+            states,
+            vec![
+                ThresholdState::Defined,
+                ThresholdState::Defined,
+                ThresholdState::Started,
+                ThresholdState::Started,
+                ThresholdState::LockedIn,
+            ]
+        );
+        assert_eq!(periods[2].signalling, 3);
+        assert_eq!(periods[2].blocks, 5);
+        assert_eq!(periods[3].signalling, 4);
+        // The genesis block and 19 more fill four periods, and the next
+        // block starts a fifth
+        assert_eq!(periods[4].start_height, 20);
+        assert_eq!(periods[4].blocks, 0);
+
+        versions.push(other);
+        let chain = chain(&versions);
+        let periods = bits.periods(&chain, &DEPLOYMENT);
+        assert_eq!(periods.len(), 5);
+        assert_eq!(periods[4].state, ThresholdState::LockedIn);
+        assert_eq!(periods[4].blocks, 1);
+        assert_eq!(bits.state(&chain, &DEPLOYMENT), ThresholdState::LockedIn);
+
+        versions.extend_from_slice(&[other; 4]);
+        let chain_active = self::chain(&versions);
+        assert_eq!(
+            bits.state(&chain_active, &DEPLOYMENT),
+            ThresholdState::Active
+        );
+        let current = bits.current(&chain_active);
+        assert_eq!(current.len(), 1);
+        assert_eq!(current[0].1.start_height, 25);
+        assert_eq!(current[0].1.blocks, 0);
+
+        // Locked in, but not before its minimum activation height
+        let late = Deployment {
+            min_activation_height: 30,
+            ..DEPLOYMENT
+        };
+        assert_eq!(bits.state(&chain_active, &late), ThresholdState::LockedIn);
+    }
+
+    #[test]
+    fn timeout_and_special_start_times() {
+        let bits = VersionBits::new(5, 4);
+        let chain = chain(&[0x20000000; 40]);
+        let periods = bits.periods(&chain, &DEPLOYMENT);
+        let failed = periods
+            .iter()
+            .position(|p| p.state == ThresholdState::Failed)
+            .unwrap();
+        assert_eq!(periods[failed - 1].state, ThresholdState::Started);
+        // The median time past of block 39 is that of block 34, 3040
+        assert_eq!(periods[failed].start_height, 40);
+
+        let always = Deployment {
+            start_time: Deployment::ALWAYS_ACTIVE,
+            ..DEPLOYMENT
+        };
+        let never = Deployment {
+            start_time: Deployment::NEVER_ACTIVE,
+            ..DEPLOYMENT
+        };
+        assert_eq!(bits.state(&chain, &always), ThresholdState::Active);
+        assert_eq!(bits.state(&chain, &never), ThresholdState::Failed);
+        assert_eq!(ThresholdState::LockedIn.as_str(), "locked_in");
+    }
+}
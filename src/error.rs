@@ -0,0 +1,196 @@
+//! Error Codes
+//!
+//! Every error type of this library implements `ErrorCode`, which gives
+//! each error a stable number, so that code wrapping the library, such as
+//! an RPC server or a binding to another language, can tell failures apart
+//! without matching on their messages.
+//!
+//! A code is the code of the error's `ErrorKind` times 1000 plus the
+//! number of the variant within its type, counting from 1. Codes are
+//! never changed or reused: a new variant takes the next number of its
+//! type and a new error type the next kind.
+
+use std::fmt;
+
+/// Multiplies the code of an `ErrorKind` to make room for the numbers of
+/// the variants of its type
+pub const KIND_MULTIPLIER: u32 = 1000;
+
+/// The type of error a code belongs to
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum ErrorKind {
+    /// `consensus::encode::Error`
+    Encode = 1,
+    /// `blockdata::script::Error`
+    Script = 2,
+    /// `blockdata::script::ParseAsmError`
+    ScriptAsm = 3,
+    /// `blockdata::transaction::SequenceError`
+    Sequence = 4,
+    /// `blockdata::transaction::ParseOutPointError`
+    OutPoint = 5,
+    /// `blockdata::headerchain::Error`
+    HeaderChain = 6,
+    /// `blockdata::utxo::Error`
+    Utxo = 7,
+    /// `util::amount::ParseAmountError`
+    Amount = 8,
+    /// `util::base58::Error`
+    Base58 = 9,
+    /// `util::bech32::Error`
+    Bech32 = 10,
+    /// `util::address::Error`
+    Address = 11,
+    /// `util::bip32::Error`
+    Bip32 = 12,
+    /// `util::psbt::Error`
+    Psbt = 13,
+    /// `util::sighash::Error`
+    Sighash = 14,
+    /// `util::core_json::Error`
+    CoreJson = 15,
+    /// `network::Error`
+    Network = 16,
+    /// `network::config::Error`
+    NetworkConfig = 17,
+    /// `network::message_network::UserAgentError`
+    UserAgent = 18,
+    /// `network::message::CommandStringError`
+    CommandString = 19,
+    /// `policy::Error`
+    Policy = 20,
+    /// `consensus::pipeline::Error`
+    Pipeline = 21,
+    /// `util::psbt::payjoin::Error`
+    Payjoin = 22,
+    /// `util::silentpayments::Error`
+    SilentPayments = 23,
+    /// `util::crypto_container::Error`
+    CryptoContainer = 24,
+    /// `blockdata::corenotify::Error`
+    CoreNotify = 25,
+    /// `backend::Error`
+    Backend = 26,
+    /// `blockdata::transaction::LockTimeError`
+    LockTime = 27,
+    /// `network::constants::ParseServiceFlagsError`
+    ServiceFlags = 28,
+    /// `util::fee_rate::ParseFeeRateError`
+    FeeRate = 29,
+    /// `blockdata::verify::Error`
+    Verify = 30,
+}
+
+/// Every kind, in code order
+const KINDS: [ErrorKind; 30] = [
+    ErrorKind::Encode,
+    ErrorKind::Script,
+    ErrorKind::ScriptAsm,
+    ErrorKind::Sequence,
+    ErrorKind::OutPoint,
+    ErrorKind::HeaderChain,
+    ErrorKind::Utxo,
+    ErrorKind::Amount,
+    ErrorKind::Base58,
+    ErrorKind::Bech32,
+    ErrorKind::Address,
+    ErrorKind::Bip32,
+    ErrorKind::Psbt,
+    ErrorKind::Sighash,
+    ErrorKind::CoreJson,
+    ErrorKind::Network,
+    ErrorKind::NetworkConfig,
+    ErrorKind::UserAgent,
+    ErrorKind::CommandString,
+    ErrorKind::Policy,
+    ErrorKind::Pipeline,
+    ErrorKind::Payjoin,
+    ErrorKind::SilentPayments,
+    ErrorKind::CryptoContainer,
+    ErrorKind::CoreNotify,
+    ErrorKind::Backend,
+    ErrorKind::LockTime,
+    ErrorKind::ServiceFlags,
+    ErrorKind::FeeRate,
+    ErrorKind::Verify,
+];
+
+impl ErrorKind {
+    /// The stable code of this kind
+    pub fn code(self) -> u32 {
+        self as u32
+    }
+
+    /// The kind with the given code
+    pub fn from_code(code: u32) -> Option<ErrorKind> {
+        KINDS.iter().cloned().find(|k| k.code() == code)
+    }
+
+    /// The kind an error code belongs to
+    pub fn of_error_code(code: u32) -> Option<ErrorKind> {
+        ErrorKind::from_code(code / KIND_MULTIPLIER)
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+/// An error with a stable code
+pub trait ErrorCode {
+    /// The type of error this is
+    fn kind(&self) -> ErrorKind;
+
+    /// The number of this error within its kind, counting from 1
+    fn number(&self) -> u32;
+
+    /// The stable code of this error
+    fn code(&self) -> u32 {
+        self.kind().code() * KIND_MULTIPLIER + self.number()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io;
+
+    use blockdata::script;
+    use consensus::encode;
+    use network::message_network::UserAgentError;
+    use util::{address, amount, base58, psbt};
+
+    #[test]
+    fn kinds() {
+        for (i, kind) in KINDS.iter().enumerate() {
+            assert_eq!(kind.code(), i as u32 + 1);
+            assert_eq!(ErrorKind::from_code(kind.code()), Some(*kind));
+        }
+        assert_eq!(ErrorKind::from_code(0), None);
+        assert_eq!(ErrorKind::from_code(KINDS.len() as u32 + 1), None);
+        assert_eq!(ErrorKind::of_error_code(11_002), Some(ErrorKind::Address));
+        assert_eq!(ErrorKind::Bip32.to_string(), "Bip32");
+    }
+
+    #[test]
+    fn codes() {
+        // Codes are part of the API, so these must never change
+        let io = encode::Error::Io(io::Error::new(io::ErrorKind::Other, "io"));
+        assert_eq!(io.code(), 1_001);
+        assert_eq!(encode::Error::NonMinimalVarInt.code(), 1_006);
+        assert_eq!(script::Error::NumericOverflow.code(), 2_003);
+        assert_eq!(amount::ParseAmountError::TooPrecise.code(), 8_003);
+        assert_eq!(base58::Error::TooShort(3).code(), 9_004);
+        assert_eq!(address::Error::UnknownPrefix.code(), 11_009);
+        assert_eq!(psbt::Error::FeeOutOfRange.code(), 13_020);
+        assert_eq!(UserAgentError::TooLong.code(), 18_003);
+
+        // A wrapped error keeps the code of the wrapper
+        let wrapped = address::Error::Base58(base58::Error::TooShort(3));
+        assert_eq!(wrapped.kind(), ErrorKind::Address);
+        assert_eq!(wrapped.number(), 1);
+    }
+}
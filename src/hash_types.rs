@@ -0,0 +1,356 @@
+//! Hash types
+//!
+//! This module defines types for hashes used throughout the library. These
+//! types are needed in order to avoid mixing data of the same hash format
+//! (like SHA256d) but of different meaning (transaction id, block hash etc).
+//!
+//! Txids and block hashes are shown by Bitcoin Core's RPC and by block
+//! explorers with their bytes reversed. `rpc_hex` displays them that way
+//! straight into the formatter, and `from_rpc_hex` parses them back, both
+//! without allocating, as code serving explorers converts a great many of
+//! them.
+
+use blockdata::script::Script;
+use consensus::encode::{Decodable, Encodable, Error};
+use hashes::hex;
+use hashes::{hash160, sha256, sha256d};
+use hashes::{Hash, HashEngine};
+use std::{fmt, io, str};
+
+macro_rules! impl_hashencode {
+    ($hashtype:ident) => {
+        impl Encodable for $hashtype {
+            fn consensus_encode<S: io::Write>(&self, s: S) -> Result<usize, Error> {
+                self.0.consensus_encode(s)
+            }
+        }
+
+        impl Decodable for $hashtype {
+            fn consensus_decode<D: io::Read>(d: D) -> Result<Self, Error> {
+                Ok(Self::from_inner(
+                    <<$hashtype as Hash>::Inner>::consensus_decode(d)?,
+                ))
+            }
+        }
+    };
+}
+
+hash_newtype!(
+    Txid,
+    sha256d::Hash,
+    32,
+    doc = "A bitcoin transaction hash/transaction ID."
+);
+hash_newtype!(
+    Wtxid,
+    sha256d::Hash,
+    32,
+    doc = "A bitcoin witness transaction ID."
+);
+hash_newtype!(BlockHash, sha256d::Hash, 32, doc = "A bitcoin block hash.");
+hash_newtype!(
+    SigHash,
+    sha256d::Hash,
+    32,
+    doc = "Hash of the transaction according to the signature algorithm"
+);
+hash_newtype!(
+    TapSighashHash,
+    sha256::Hash,
+    32,
+    doc = "Taproot-tagged hash of the transaction according to the BIP341 signature algorithm"
+);
+hash_newtype!(
+    TapLeafHash,
+    sha256::Hash,
+    32,
+    doc = "Taproot-tagged hash of a script leaf of a taproot output's script tree"
+);
+hash_newtype!(
+    TapBranchHash,
+    sha256::Hash,
+    32,
+    doc = "Taproot-tagged hash of a branch of a taproot output's script tree"
+);
+
+hash_newtype!(
+    TxMerkleNode,
+    sha256d::Hash,
+    32,
+    doc = "A hash of the Merkle tree branch or root for transactions"
+);
+
+hash_newtype!(
+    WitnessMerkleNode,
+    sha256d::Hash,
+    32,
+    doc = "A hash corresponding to the Merkle tree root for witness data."
+);
+hash_newtype!(
+    WitnessCommitment,
+    sha256d::Hash,
+    32,
+    doc = "A hash corresponding to the witness structure commitment in the coinbase transaction."
+);
+
+hash_newtype!(
+    UtxoSetHash,
+    sha256d::Hash,
+    32,
+    doc = "A hash of the UTXO set, which assumeutxo snapshots commit to."
+);
+
+hash_newtype!(
+    PubkeyHash,
+    hash160::Hash,
+    20,
+    doc = "A hash of a public key."
+);
+hash_newtype!(
+    ScriptHash,
+    hash160::Hash,
+    20,
+    doc = "A hash of Bitcoin Script bytecode."
+);
+hash_newtype!(
+    WPubkeyHash,
+    hash160::Hash,
+    20,
+    doc = "SegWit version of a public key hash."
+);
+hash_newtype!(
+    WScriptHash,
+    sha256::Hash,
+    32,
+    doc = "SegWit version of a Bitcoin Script bytecode hash."
+);
+
+/// Adds `rpc_hex` and `from_rpc_hex` to a 32-byte hash type
+macro_rules! impl_rpc_hex {
+    ($hashtype:ident) => {
+        impl $hashtype {
+            /// Displays the hash in the reversed byte order of Bitcoin
+            /// Core's RPC, without allocating
+            pub fn rpc_hex(&self) -> RpcHex<'_> {
+                RpcHex(&self[..])
+            }
+
+            /// Parses a hash in the reversed byte order of Bitcoin Core's
+            /// RPC, in lower or upper case, without allocating
+            pub fn from_rpc_hex(s: &str) -> Result<$hashtype, hex::Error> {
+                let mut bytes = [0; 32];
+                decode_rpc_hex(s, &mut bytes)?;
+                Ok($hashtype::from_inner(bytes))
+            }
+        }
+    };
+}
+
+/// Displays a 32-byte hash in reverse byte order. Lower case is used unless
+/// formatted with `{:X}`, and width and alignment are honored.
+#[derive(Copy, Clone, Debug)]
+pub struct RpcHex<'a>(&'a [u8]);
+
+impl<'a> RpcHex<'a> {
+    fn write(&self, f: &mut fmt::Formatter, alphabet: &[u8; 16]) -> fmt::Result {
+        let mut buf = [0u8; 64];
+        for (i, byte) in self.0.iter().rev().enumerate() {
+            buf[2 * i] = alphabet[(byte >> 4) as usize];
+            buf[2 * i + 1] = alphabet[(byte & 0x0f) as usize];
+        }
+        f.pad(str::from_utf8(&buf[..2 * self.0.len()]).expect("hex is ascii"))
+    }
+}
+
+impl<'a> fmt::Display for RpcHex<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::LowerHex::fmt(self, f)
+    }
+}
+
+impl<'a> fmt::LowerHex for RpcHex<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write(f, b"0123456789abcdef")
+    }
+}
+
+impl<'a> fmt::UpperHex for RpcHex<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write(f, b"0123456789ABCDEF")
+    }
+}
+
+/// Decodes 64 hex digits into `bytes`, last byte first
+fn decode_rpc_hex(s: &str, bytes: &mut [u8; 32]) -> Result<(), hex::Error> {
+    if s.len() != 64 {
+        return Err(hex::Error::InvalidLength(64, s.len()));
+    }
+    let digit = |c: u8| match c {
+        b'0'...b'9' => Ok(c - b'0'),
+        b'a'...b'f' => Ok(c - b'a' + 10),
+        b'A'...b'F' => Ok(c - b'A' + 10),
+        _ => Err(hex::Error::InvalidChar(c)),
+    };
+    for (byte, pair) in bytes.iter_mut().rev().zip(s.as_bytes().chunks(2)) {
+        *byte = (digit(pair[0])? << 4) | digit(pair[1])?;
+    }
+    Ok(())
+}
+
+impl_rpc_hex!(Txid);
+impl_rpc_hex!(Wtxid);
+impl_rpc_hex!(BlockHash);
+
+impl_hashencode!(Txid);
+impl_hashencode!(Wtxid);
+impl_hashencode!(BlockHash);
+impl_hashencode!(TxMerkleNode);
+impl_hashencode!(WitnessMerkleNode);
+impl_hashencode!(WitnessCommitment);
+impl_hashencode!(UtxoSetHash);
+impl_hashencode!(PubkeyHash);
+impl_hashencode!(ScriptHash);
+impl_hashencode!(WPubkeyHash);
+impl_hashencode!(WScriptHash);
+
+impl PubkeyHash {
+    /// Hashes a serialized public key, compressed or uncompressed.
+    pub fn from_pubkey(pubkey: &[u8]) -> PubkeyHash {
+        PubkeyHash::hash(pubkey)
+    }
+}
+
+impl WPubkeyHash {
+    /// Hashes a serialized public key. Returns `None` unless the key is
+    /// compressed, since segwit outputs do not allow uncompressed keys.
+    pub fn from_pubkey(pubkey: &[u8]) -> Option<WPubkeyHash> {
+        if pubkey.len() == 33 && (pubkey[0] == 0x02 || pubkey[0] == 0x03) {
+            Some(WPubkeyHash::hash(pubkey))
+        } else {
+            None
+        }
+    }
+}
+
+impl ScriptHash {
+    /// Hashes a script, as committed to by a P2SH output.
+    pub fn from_script(script: &Script) -> ScriptHash {
+        ScriptHash::hash(script.as_bytes())
+    }
+}
+
+impl WScriptHash {
+    /// Hashes a script, as committed to by a P2WSH output.
+    pub fn from_script(script: &Script) -> WScriptHash {
+        WScriptHash::hash(script.as_bytes())
+    }
+}
+
+impl TapLeafHash {
+    /// Hashes a script leaf of the given leaf version, as committed to by
+    /// the script tree of a taproot output.
+    pub fn from_script(script: &Script, leaf_version: u8) -> TapLeafHash {
+        let tag = sha256::Hash::hash(b"TapLeaf");
+        let mut engine = TapLeafHash::engine();
+        engine.input(&tag[..]);
+        engine.input(&tag[..]);
+        engine.input(&[leaf_version]);
+        script
+            .consensus_encode(&mut engine)
+            .expect("engines don't error");
+        TapLeafHash::from_engine(engine)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use consensus::encode::{deserialize, serialize};
+    use hashes::hex::{FromHex, ToHex};
+
+    #[test]
+    fn key_hashes() {
+        let compressed =
+            Vec::from_hex("0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798")
+                .unwrap();
+        let expected = Vec::from_hex("751e76e8199196d454941c45d1b3a323f1433bd6").unwrap();
+        assert_eq!(&PubkeyHash::from_pubkey(&compressed)[..], &expected[..]);
+        assert_eq!(
+            &WPubkeyHash::from_pubkey(&compressed).unwrap()[..],
+            &expected[..]
+        );
+
+        let mut uncompressed = vec![0x04];
+        uncompressed.extend_from_slice(&[1; 64]);
+        assert_eq!(WPubkeyHash::from_pubkey(&uncompressed), None);
+        assert_eq!(WPubkeyHash::from_pubkey(&compressed[1..]), None);
+        assert_eq!(
+            PubkeyHash::from_pubkey(&uncompressed),
+            PubkeyHash::hash(&uncompressed)
+        );
+    }
+
+    #[test]
+    fn rpc_hex() {
+        // The genesis block
+        let genesis = "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f";
+        let hash = BlockHash::from_rpc_hex(genesis).unwrap();
+        assert_eq!(hash, BlockHash::from_hex(genesis).unwrap());
+        assert_eq!(hash.rpc_hex().to_string(), genesis);
+        assert_eq!(hash.rpc_hex().to_string(), hash.to_hex());
+        assert_eq!(format!("{:x}", hash.rpc_hex()), genesis);
+        assert_eq!(format!("{:X}", hash.rpc_hex()), genesis.to_uppercase());
+        assert_eq!(
+            format!("{:>66}|{:.8}", hash.rpc_hex(), hash.rpc_hex()),
+            format!("  {}|00000000", genesis)
+        );
+        assert_eq!(BlockHash::from_rpc_hex(&genesis.to_uppercase()), Ok(hash));
+
+        let txid = Txid::hash(&[1, 2, 3]);
+        assert_eq!(Txid::from_rpc_hex(&txid.rpc_hex().to_string()), Ok(txid));
+        assert_eq!(txid.rpc_hex().to_string(), txid.to_string());
+        let wtxid = Wtxid::hash(&[1, 2, 3]);
+        assert_eq!(Wtxid::from_rpc_hex(&wtxid.rpc_hex().to_string()), Ok(wtxid));
+
+        assert_eq!(
+            Txid::from_rpc_hex(&genesis[2..]),
+            Err(hex::Error::InvalidLength(64, 62))
+        );
+        assert_eq!(
+            Txid::from_rpc_hex(&genesis.replace('d', "g")),
+            Err(hex::Error::InvalidChar(b'g'))
+        );
+        // Multi-byte characters are not digits
+        let mut odd = "é".to_owned();
+        odd.push_str(&genesis[2..]);
+        assert_eq!(Txid::from_rpc_hex(&odd), Err(hex::Error::InvalidChar(0xc3)));
+    }
+
+    #[test]
+    fn script_hashes() {
+        let empty = Script::new();
+        assert_eq!(
+            &ScriptHash::from_script(&empty)[..],
+            &Vec::from_hex("b472a266d0bd89c13706a4132ccfb16f7c3b9fcb").unwrap()[..]
+        );
+        let wsh = WScriptHash::from_script(&empty);
+        assert_eq!(
+            &wsh[..],
+            &Vec::from_hex("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855")
+                .unwrap()[..]
+        );
+        assert_eq!(deserialize::<WScriptHash>(&serialize(&wsh)).unwrap(), wsh);
+
+        // From the BIP341 test vectors
+        let leaf = Script::from(
+            Vec::from_hex("20b617298552a72ade070667e86ca63b8f5789a9fe8731ef91202a91c9f3459007ac")
+                .unwrap(),
+        );
+        assert_eq!(
+            &TapLeafHash::from_script(&leaf, 0xc0)[..],
+            &Vec::from_hex("c525714a7f49c28aedbbba78c005931a81c234b2f6c99a73e4d06082adc8bf2b")
+                .unwrap()[..]
+        );
+    }
+}
@@ -20,13 +20,73 @@ macro_rules! impl_consensus_encoding {
                     mut d: D
                 ) -> Result<$thing, ::consensus::encode::Error> {
                     Ok($thing {
-                        $($field: ::consensus::Decodable::consensus_decode(&mut d)?),+
+                        $($field: trace_field!(
+                            stringify!($field),
+                            ::consensus::Decodable::consensus_decode(&mut d)?
+                        )),+
                     })
                 }
             }
         )
 }
 
+/// Implements `from_hex` and `TryFrom<&[u8]>` for a type with a consensus
+/// encoding, both of which fail unless all of the data is consumed
+macro_rules! impl_consensus_from_hex {
+    ($thing:ident) => {
+        impl $thing {
+            /// Deserializes from the hex of the consensus encoding
+            pub fn from_hex(hex: &str) -> Result<$thing, ::consensus::encode::Error> {
+                ::consensus::encode::deserialize_hex(hex)
+            }
+        }
+
+        impl<'a> ::std::convert::TryFrom<&'a [u8]> for $thing {
+            type Error = ::consensus::encode::Error;
+
+            fn try_from(data: &'a [u8]) -> Result<$thing, ::consensus::encode::Error> {
+                ::consensus::encode::deserialize(data)
+            }
+        }
+    };
+}
+
+/// Decodes a field named `$name`, or the element at index `$i` of a vector
+/// with `[$i]`, recording it in the trace being taken with the
+/// `decode-trace` feature. Without the feature this is the bare decoding.
+macro_rules! trace_field {
+    ([$i:expr], $decode:expr) => {{
+        #[cfg(feature = "decode-trace")]
+        let _field = ::consensus::trace::enter_index($i);
+        #[cfg(not(feature = "decode-trace"))]
+        let _ = $i;
+        $decode
+    }};
+    ($name:expr, $decode:expr) => {{
+        #[cfg(feature = "decode-trace")]
+        let _field = ::consensus::trace::enter($name);
+        $decode
+    }};
+}
+
+/// Renames the field just decoded in the trace being taken, if any
+macro_rules! trace_relabel {
+    ($name:expr) => {
+        #[cfg(feature = "decode-trace")]
+        ::consensus::trace::relabel_last($name);
+    };
+}
+
+/// Decodes data already read whole, recording it in the trace being taken
+/// as a field named `$name` over the `$len` bytes just read
+macro_rules! trace_replay {
+    ($name:expr, $len:expr, $decode:expr) => {{
+        #[cfg(feature = "decode-trace")]
+        let _field = ::consensus::trace::replay($name, $len);
+        $decode
+    }};
+}
+
 macro_rules! display_from_debug {
     ($thing:ident) => {
         impl fmt::Display for $thing {
@@ -80,15 +140,17 @@ macro_rules! user_enum {
             }
         }
 
-         #[cfg(feature = "serde")]
+        #[cfg(feature = "serde")]
         impl<'de> $crate::serde::Deserialize<'de> for $name {
             #[inline]
             fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
             where
                 D: $crate::serde::Deserializer<'de>,
             {
-                // TODO implement this:
-                unimplemented!();
+                let s = <String as $crate::serde::Deserialize>::deserialize(deserializer)?;
+                s.parse().map_err(|_| {
+                    <D::Error as $crate::serde::de::Error>::unknown_variant(&s, &[$($txt),*])
+                })
             }
         }
 
@@ -98,9 +160,82 @@ macro_rules! user_enum {
             where
                 S: ::serde::Serializer,
             {
-                // TODO implement this:
-                unimplemented!();
+                serializer.serialize_str(&self.to_string())
             }
         }
     );
 }
+
+macro_rules! impl_index_newtype {
+    ($thing:ident, $ty:ty) => {
+        impl ::std::ops::Index<usize> for $thing {
+            type Output = $ty;
+
+            #[inline]
+            fn index(&self, index: usize) -> &$ty {
+                &self.0[index]
+            }
+        }
+
+        impl ::std::ops::Index<::std::ops::Range<usize>> for $thing {
+            type Output = [$ty];
+
+            #[inline]
+            fn index(&self, index: ::std::ops::Range<usize>) -> &[$ty] {
+                &self.0[index]
+            }
+        }
+
+        impl ::std::ops::Index<::std::ops::RangeTo<usize>> for $thing {
+            type Output = [$ty];
+
+            #[inline]
+            fn index(&self, index: ::std::ops::RangeTo<usize>) -> &[$ty] {
+                &self.0[index]
+            }
+        }
+
+        impl ::std::ops::Index<::std::ops::RangeFrom<usize>> for $thing {
+            type Output = [$ty];
+
+            #[inline]
+            fn index(&self, index: ::std::ops::RangeFrom<usize>) -> &[$ty] {
+                &self.0[index]
+            }
+        }
+
+        impl ::std::ops::Index<::std::ops::RangeFull> for $thing {
+            type Output = [$ty];
+
+            #[inline]
+            fn index(&self, _: ::std::ops::RangeFull) -> &[$ty] {
+                &self.0[..]
+            }
+        }
+    };
+}
+
+/// Asserts that each value, of a type implementing both `Display` and
+/// `FromStr`, parses back from its display to the same value, which in turn
+/// displays the same. Strings which parse but display differently are not
+/// canonical and are tested separately.
+#[cfg(test)]
+macro_rules! roundtrip_fromstr_display {
+    ($ty:ty; $($value:expr),+ $(,)*) => ({
+        $({
+            let value: $ty = $value;
+            let s = value.to_string();
+            let parsed = match s.parse::<$ty>() {
+                Ok(parsed) => parsed,
+                Err(e) => panic!("{:?} does not parse back: {:?}", s, e),
+            };
+            assert_eq!(parsed, value, "{:?} parses back to another value", s);
+            assert_eq!(parsed.to_string(), s);
+        })+
+    });
+}
+
+#[cfg(test)]
+macro_rules! hex_script (($s:expr) => (
+    <$crate::blockdata::script::Script as ::std::str::FromStr>::from_str($s).unwrap()
+));
@@ -27,10 +27,19 @@
 #![allow(bare_trait_objects)]
 #![allow(ellipsis_inclusive_range_patterns)]
 
+#[macro_use]
 pub extern crate bitcoin_hashes as hashes;
 
 #[cfg(feature = "serde")]
 extern crate serde;
+#[cfg(feature = "arbitrary")]
+extern crate arbitrary;
+#[cfg(feature = "zeroize")]
+extern crate zeroize;
+#[cfg(feature = "crypto-container")]
+extern crate chacha20poly1305;
+#[cfg(feature = "crypto-container")]
+extern crate scrypt;
 #[cfg(all(test, feature = "serde"))]
 #[macro_use]
 extern crate serde_derive; // for 1.22.0 compat
@@ -43,10 +52,24 @@ extern crate test;
 
 #[macro_use]
 pub mod internal_macros;
+pub mod backend;
 pub mod blockdata;
 pub mod consensus;
+pub mod error;
+pub mod hash_types;
 pub mod network;
+pub mod policy;
+pub mod storage;
+#[cfg(any(test, feature = "testutil"))]
+pub mod testutil;
 pub mod util;
 
+pub use blockdata::script::Script;
+pub use blockdata::transaction::Transaction;
+pub use blockdata::transaction::TxIn;
+pub use blockdata::transaction::TxOut;
+pub use blockdata::transaction::OutPoint;
+pub use error::{ErrorCode, ErrorKind};
+pub use hash_types::*;
 pub use util::amount::Amount;
 pub use util::amount::SignedAmount;
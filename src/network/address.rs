@@ -47,9 +47,7 @@ impl Address {
         if addr[0..3] == ONION {
             return Err(io::Error::from(io::ErrorKind::AddrNotAvailable));
         }
-        let ipv6 = Ipv6Addr::new(
-            addr[0], addr[1], addr[2], addr[3], addr[4], addr[5], addr[6], addr[7],
-        );
+        let ipv6 = Ipv6Addr::from(*addr);
         if let Some(ipv4) = ipv6.to_ipv4() {
             Ok(SocketAddr::V4(SocketAddrV4::new(ipv4, self.port)))
         } else {
@@ -58,36 +56,24 @@ impl Address {
     }
 }
 
-fn addr_to_be(addr: [u16; 8]) -> [u16; 8] {
-    [
-        addr[0].to_be(),
-        addr[1].to_be(),
-        addr[2].to_be(),
-        addr[3].to_be(),
-        addr[4].to_be(),
-        addr[5].to_be(),
-        addr[6].to_be(),
-        addr[7].to_be(),
-    ]
-}
-
 impl Encodable for Address {
     #[inline]
     fn consensus_encode<S: io::Write>(&self, mut s: S) -> Result<usize, encode::Error> {
-        let len = self.services.consensus_encode(&mut s)?
-            + addr_to_be(self.address).consensus_encode(&mut s)?
-            + self.port.to_be().consensus_encode(s)?;
-        Ok(len)
+        // Any address, Tor ones included, is carried as IPv6
+        let socket = SocketAddrV6::new(Ipv6Addr::from(self.address), self.port, 0, 0);
+        Ok(self.services.consensus_encode(&mut s)? + socket.consensus_encode(s)?)
     }
 }
 
 impl Decodable for Address {
     #[inline]
     fn consensus_decode<D: io::Read>(mut d: D) -> Result<Self, encode::Error> {
+        let services = Decodable::consensus_decode(&mut d)?;
+        let socket = SocketAddrV6::consensus_decode(d)?;
         Ok(Address {
-            services: Decodable::consensus_decode(&mut d)?,
-            address: addr_to_be(Decodable::consensus_decode(&mut d)?),
-            port: u16::from_be(Decodable::consensus_decode(d)?),
+            services,
+            address: socket.ip().segments(),
+            port: socket.port(),
         })
     }
 }
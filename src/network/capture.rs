@@ -0,0 +1,274 @@
+//! Message capture
+//!
+//! Records every message sent to and received from a peer, with the time it
+//! was seen, to a capture file, and reads them back. A capture attached to a
+//! bug report lets the exchange with the peer be replayed deterministically
+//! by feeding the received messages back into the code that handled them.
+//!
+//! A capture file is the 8 bytes `CAPTURE_MAGIC` followed by one record per
+//! message: the time in microseconds since the Unix epoch as a little-endian
+//! `u64`, a direction byte, 0 for sent and 1 for received, and the message
+//! as it went over the wire.
+
+use std::io;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use consensus::encode::{self, Decodable, Encodable};
+use network::message::RawNetworkMessage;
+
+/// The bytes a capture file starts with, which include the format version
+pub const CAPTURE_MAGIC: [u8; 8] = *b"btccap\x00\x01";
+
+/// Whether a message was sent to or received from the peer
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Direction {
+    /// We sent the message
+    Sent,
+    /// The peer sent the message
+    Received,
+}
+
+/// A message in a capture
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct CaptureRecord {
+    /// When the message was seen, in microseconds since the Unix epoch
+    pub timestamp: u64,
+    /// Whether it was sent or received
+    pub direction: Direction,
+    /// The message
+    pub message: RawNetworkMessage,
+}
+
+impl Encodable for CaptureRecord {
+    fn consensus_encode<S: io::Write>(&self, mut s: S) -> Result<usize, encode::Error> {
+        let direction: u8 = match self.direction {
+            Direction::Sent => 0,
+            Direction::Received => 1,
+        };
+        let mut len = self.timestamp.consensus_encode(&mut s)?;
+        len += direction.consensus_encode(&mut s)?;
+        len += self.message.consensus_encode(&mut s)?;
+        Ok(len)
+    }
+}
+
+impl Decodable for CaptureRecord {
+    fn consensus_decode<D: io::Read>(mut d: D) -> Result<Self, encode::Error> {
+        let timestamp = Decodable::consensus_decode(&mut d)?;
+        let direction = match u8::consensus_decode(&mut d)? {
+            0 => Direction::Sent,
+            1 => Direction::Received,
+            _ => return Err(encode::Error::ParseFailed("invalid capture direction")),
+        };
+        Ok(CaptureRecord {
+            timestamp,
+            direction,
+            message: Decodable::consensus_decode(&mut d)?,
+        })
+    }
+}
+
+/// Writes messages to a capture file
+#[derive(Debug)]
+pub struct CaptureWriter<W: io::Write> {
+    writer: W,
+}
+
+impl<W: io::Write> CaptureWriter<W> {
+    /// Starts a capture, writing the file header to `writer`
+    pub fn new(mut writer: W) -> Result<CaptureWriter<W>, encode::Error> {
+        writer.write_all(&CAPTURE_MAGIC)?;
+        Ok(CaptureWriter { writer })
+    }
+
+    /// Records a message, timestamped with the current time
+    pub fn record(
+        &mut self,
+        direction: Direction,
+        message: &RawNetworkMessage,
+    ) -> Result<(), encode::Error> {
+        let timestamp = match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(now) => now.as_secs() * 1_000_000 + now.subsec_micros() as u64,
+            Err(_) => 0,
+        };
+        self.record_at(timestamp, direction, message)
+    }
+
+    /// Records a message with the given timestamp, in microseconds since the
+    /// Unix epoch
+    pub fn record_at(
+        &mut self,
+        timestamp: u64,
+        direction: Direction,
+        message: &RawNetworkMessage,
+    ) -> Result<(), encode::Error> {
+        // Write the record in one go, so that it can't be interleaved with
+        // another when the writer is shared
+        let mut buf = Vec::new();
+        CaptureRecord {
+            timestamp,
+            direction,
+            message: message.clone(),
+        }
+        .consensus_encode(&mut buf)?;
+        self.writer.write_all(&buf)?;
+        Ok(())
+    }
+
+    /// Flushes the underlying writer
+    pub fn flush(&mut self) -> Result<(), encode::Error> {
+        Ok(self.writer.flush()?)
+    }
+
+    /// Returns the underlying writer
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Reads the messages of a capture file back, in the order they were
+/// recorded
+///
+/// Replaying a capture is iterating over it and passing each received
+/// message to the code under test, which can be checked against the sent
+/// messages that follow.
+#[derive(Debug)]
+pub struct CaptureReader<R: io::Read> {
+    reader: R,
+    done: bool,
+}
+
+impl<R: io::Read> CaptureReader<R> {
+    /// Opens a capture, checking the file header
+    pub fn new(mut reader: R) -> Result<CaptureReader<R>, encode::Error> {
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if magic != CAPTURE_MAGIC {
+            return Err(encode::Error::ParseFailed("not a capture file"));
+        }
+        Ok(CaptureReader {
+            reader,
+            done: false,
+        })
+    }
+
+    /// Reads the next record, or `None` at the end of the capture
+    pub fn read_record(&mut self) -> Result<Option<CaptureRecord>, encode::Error> {
+        if self.done {
+            return Ok(None);
+        }
+        // A capture cut short ends within a record, which is an error, while
+        // a complete one ends between records
+        let mut first = [0; 1];
+        loop {
+            match self.reader.read(&mut first) {
+                Ok(0) => {
+                    self.done = true;
+                    return Ok(None);
+                }
+                Ok(_) => break,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        let record = CaptureRecord::consensus_decode(io::Read::chain(&first[..], &mut self.reader));
+        if record.is_err() {
+            self.done = true;
+        }
+        record.map(Some)
+    }
+}
+
+impl<R: io::Read> Iterator for CaptureReader<R> {
+    type Item = Result<CaptureRecord, encode::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.read_record() {
+            Ok(Some(record)) => Some(Ok(record)),
+            Ok(None) => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use network::constants::Magic;
+    use network::message::NetworkMessage;
+
+    fn message(payload: NetworkMessage) -> RawNetworkMessage {
+        RawNetworkMessage {
+            magic: Magic::BITCOIN,
+            payload,
+        }
+    }
+
+    #[test]
+    fn roundtrip() {
+        let mut writer = CaptureWriter::new(Vec::new()).unwrap();
+        writer
+            .record_at(1, Direction::Sent, &message(NetworkMessage::Verack))
+            .unwrap();
+        writer
+            .record_at(2, Direction::Received, &message(NetworkMessage::Ping(42)))
+            .unwrap();
+        writer
+            .record(Direction::Sent, &message(NetworkMessage::Pong(42)))
+            .unwrap();
+        let capture = writer.into_inner();
+
+        let records = CaptureReader::new(&capture[..])
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(records.len(), 3);
+        assert_eq!(
+            records[0],
+            CaptureRecord {
+                timestamp: 1,
+                direction: Direction::Sent,
+                message: message(NetworkMessage::Verack),
+            }
+        );
+        assert_eq!(records[1].direction, Direction::Received);
+        assert_eq!(records[1].message, message(NetworkMessage::Ping(42)));
+        assert!(records[2].timestamp > 2);
+        assert_eq!(records[2].message, message(NetworkMessage::Pong(42)));
+
+        // Replay the received messages through a handler
+        let replies: Vec<_> = records
+            .iter()
+            .filter(|r| r.direction == Direction::Received)
+            .filter_map(|r| match r.message.payload {
+                NetworkMessage::Ping(nonce) => Some(message(NetworkMessage::Pong(nonce))),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(replies, vec![records[2].message.clone()]);
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(CaptureReader::new(&b"btccap\x00\x02"[..]).is_err());
+        assert!(CaptureReader::new(&b"btc"[..]).is_err());
+        assert_eq!(CaptureReader::new(&CAPTURE_MAGIC[..]).unwrap().count(), 0);
+
+        let mut writer = CaptureWriter::new(Vec::new()).unwrap();
+        writer
+            .record_at(1, Direction::Sent, &message(NetworkMessage::Verack))
+            .unwrap();
+        let mut capture = writer.into_inner();
+        capture.pop();
+        let mut reader = CaptureReader::new(&capture[..]).unwrap();
+        assert!(reader.next().unwrap().is_err());
+        assert!(reader.next().is_none());
+
+        let mut bad_direction = CAPTURE_MAGIC.to_vec();
+        bad_direction.extend_from_slice(&[0; 8]);
+        bad_direction.push(2);
+        let mut reader = CaptureReader::new(&bad_direction[..]).unwrap();
+        assert!(reader.next().unwrap().is_err());
+    }
+}
@@ -0,0 +1,287 @@
+//! Compressed Headers
+//!
+//! An experimental encoding of a batch of block headers which leaves out
+//! what a client can tell from the headers before, so that header sync
+//! takes less bandwidth. It is not part of the peer-to-peer protocol, and
+//! is only available with the `experimental-header-compression` feature.
+//!
+//! Each header starts with a flags byte, and of its fields only the merkle
+//! root and the nonce are always written:
+//!
+//! * the version is written in full if the low three bits are 0, and
+//!   otherwise is the one of the seven most recently used distinct
+//!   versions those bits give the position of, 1 being the most recent
+//! * the previous block hash is written if bit 3 is set, and otherwise is
+//!   the hash of the header before
+//! * the time is written in full if bit 4 is set, and otherwise as a signed
+//!   16-bit offset from the time of the header before
+//! * the target is written if bit 5 is set, and otherwise is the target of
+//!   the header before
+//!
+//! The other bits are reserved and must not be set. A batch of contiguous
+//! headers with few distinct versions takes about 39 bytes per header,
+//! rather than 80.
+
+use std::{cmp, io};
+
+use blockdata::block::BlockHeader;
+use consensus::encode::{self, Decodable, Encodable, VarInt};
+
+/// How many distinct versions are remembered
+const VERSION_WINDOW: usize = 7;
+const VERSION_MASK: u8 = 0x07;
+const PREV_BLOCKHASH: u8 = 0x08;
+const FULL_TIME: u8 = 0x10;
+const BITS: u8 = 0x20;
+const RESERVED: u8 = 0xc0;
+/// The flags the first header, which has no header before it, must have
+const FIRST: u8 = PREV_BLOCKHASH | FULL_TIME | BITS;
+
+/// The largest number of headers a `headers` message carries, which
+/// bounds the allocation made before decoding
+const MAX_HEADERS: usize = 2000;
+
+/// A batch of block headers which encodes compressed and decodes back to
+/// the full headers, ready for `HeaderChain` or `HeaderCrossCheck`
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct CompressedHeaders(pub Vec<BlockHeader>);
+
+/// The headers most recently encoded or decoded, which later headers are
+/// compressed against
+#[derive(Default)]
+struct Context {
+    prev: Option<BlockHeader>,
+    versions: Vec<i32>,
+}
+
+impl Context {
+    /// The position of `version` among the recent versions, counting from
+    /// 1, and makes it the most recent
+    fn use_version(&mut self, version: i32) -> Option<u8> {
+        let pos = self.versions.iter().position(|&v| v == version);
+        match pos {
+            Some(pos) => self.versions.remove(pos),
+            None => version,
+        };
+        self.versions.insert(0, version);
+        self.versions.truncate(VERSION_WINDOW);
+        pos.map(|pos| pos as u8 + 1)
+    }
+}
+
+impl Encodable for CompressedHeaders {
+    fn consensus_encode<S: io::Write>(&self, mut s: S) -> Result<usize, encode::Error> {
+        let mut len = VarInt(self.0.len() as u64).consensus_encode(&mut s)?;
+        let mut context = Context::default();
+        for header in &self.0 {
+            let mut flags = context.use_version(header.version).unwrap_or(0);
+            let time_offset = match context.prev {
+                Some(prev) => {
+                    if header.prev_blockhash != prev.block_hash() {
+                        flags |= PREV_BLOCKHASH;
+                    }
+                    if header.bits != prev.bits {
+                        flags |= BITS;
+                    }
+                    let offset = header.time as i64 - prev.time as i64;
+                    if offset as i16 as i64 != offset {
+                        flags |= FULL_TIME;
+                    }
+                    offset as i16
+                }
+                None => {
+                    flags |= FIRST;
+                    0
+                }
+            };
+
+            len += flags.consensus_encode(&mut s)?;
+            if flags & VERSION_MASK == 0 {
+                len += header.version.consensus_encode(&mut s)?;
+            }
+            if flags & PREV_BLOCKHASH != 0 {
+                len += header.prev_blockhash.consensus_encode(&mut s)?;
+            }
+            len += header.merkle_root.consensus_encode(&mut s)?;
+            if flags & FULL_TIME != 0 {
+                len += header.time.consensus_encode(&mut s)?;
+            } else {
+                len += time_offset.consensus_encode(&mut s)?;
+            }
+            if flags & BITS != 0 {
+                len += header.bits.consensus_encode(&mut s)?;
+            }
+            len += header.nonce.consensus_encode(&mut s)?;
+            context.prev = Some(*header);
+        }
+        Ok(len)
+    }
+}
+
+impl Decodable for CompressedHeaders {
+    fn consensus_decode<D: io::Read>(mut d: D) -> Result<Self, encode::Error> {
+        let count = VarInt::consensus_decode(&mut d)?.0 as usize;
+        let mut headers = Vec::with_capacity(cmp::min(count, MAX_HEADERS));
+        let mut context = Context::default();
+        for _ in 0..count {
+            let flags = u8::consensus_decode(&mut d)?;
+            if flags & RESERVED != 0 {
+                return Err(encode::Error::ParseFailed(
+                    "reserved compressed header flags set",
+                ));
+            }
+            let prev = match context.prev {
+                Some(prev) => prev,
+                None if flags & FIRST == FIRST => BlockHeader {
+                    version: 0,
+                    prev_blockhash: Default::default(),
+                    merkle_root: Default::default(),
+                    time: 0,
+                    bits: 0,
+                    nonce: 0,
+                },
+                None => {
+                    return Err(encode::Error::ParseFailed(
+                        "first compressed header refers to a previous header",
+                    ))
+                }
+            };
+
+            let version = match (flags & VERSION_MASK) as usize {
+                0 => i32::consensus_decode(&mut d)?,
+                pos if pos <= context.versions.len() => context.versions[pos - 1],
+                _ => {
+                    return Err(encode::Error::ParseFailed(
+                        "compressed header refers to an unknown version",
+                    ))
+                }
+            };
+            context.use_version(version);
+            let prev_blockhash = if flags & PREV_BLOCKHASH != 0 {
+                Decodable::consensus_decode(&mut d)?
+            } else {
+                prev.block_hash()
+            };
+            let merkle_root = Decodable::consensus_decode(&mut d)?;
+            let time = if flags & FULL_TIME != 0 {
+                u32::consensus_decode(&mut d)?
+            } else {
+                (prev.time as i64 + i16::consensus_decode(&mut d)? as i64) as u32
+            };
+            let bits = if flags & BITS != 0 {
+                u32::consensus_decode(&mut d)?
+            } else {
+                prev.bits
+            };
+            let header = BlockHeader {
+                version,
+                prev_blockhash,
+                merkle_root,
+                time,
+                bits,
+                nonce: Decodable::consensus_decode(&mut d)?,
+            };
+            headers.push(header);
+            context.prev = Some(header);
+        }
+        Ok(CompressedHeaders(headers))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use consensus::encode::{deserialize, serialize};
+    use hash_types::{BlockHash, TxMerkleNode};
+    use hashes::Hash;
+
+    /// `n` contiguous headers after `prev`, ten minutes apart, cycling
+    /// through `versions`
+    fn chain(prev: BlockHash, n: u32, versions: &[i32]) -> Vec<BlockHeader> {
+        let mut ret: Vec<BlockHeader> = vec![];
+        for i in 0..n {
+            let header = BlockHeader {
+                version: versions[i as usize % versions.len()],
+                prev_blockhash: ret.last().map_or(prev, |h| h.block_hash()),
+                merkle_root: TxMerkleNode::hash(&i.to_le_bytes()),
+                time: 1_600_000_000 + 600 * i,
+                bits: 0x1d00ffff,
+                nonce: i.wrapping_mul(0x9e37_79b9),
+            };
+            ret.push(header);
+        }
+        ret
+    }
+
+    fn roundtrip(headers: &[BlockHeader]) -> usize {
+        let compressed = CompressedHeaders(headers.to_vec());
+        let encoded = serialize(&compressed);
+        assert_eq!(
+            deserialize::<CompressedHeaders>(&encoded).unwrap(),
+            compressed
+        );
+        encoded.len()
+    }
+
+    #[test]
+    fn compression() {
+        assert_eq!(roundtrip(&[]), 1);
+
+        // A full first header and 39 bytes for each after it
+        let headers = chain(BlockHash::hash(&[1]), 2000, &[0x2000_0000]);
+        let len = roundtrip(&headers);
+        assert_eq!(len, 3 + 81 + 1999 * 39);
+        assert!(len * 2 < headers.len() * 80);
+
+        // Version rolling within the window costs nothing
+        let versions: Vec<i32> = (0..7).map(|i| 0x2000_0000 | i << 13).collect();
+        let len = roundtrip(&chain(BlockHash::default(), 100, &versions));
+        assert_eq!(len, 1 + 81 + 6 * 4 + 99 * 39);
+        let versions: Vec<i32> = (0..8).map(|i| 0x2000_0000 | i << 13).collect();
+        let len = roundtrip(&chain(BlockHash::default(), 100, &versions));
+        assert_eq!(len, 1 + 81 + 99 * 43);
+    }
+
+    #[test]
+    fn irregular_headers() {
+        let mut headers = chain(BlockHash::default(), 10, &[1, 2]);
+        // A gap, a retarget and times far apart or going backwards
+        headers[5].bits = 0x1c0ffff0;
+        headers[6].time = headers[5].time + 40_000;
+        headers[7].time = headers[6].time - 30_000;
+        headers[8].time = 0;
+        headers[3].prev_blockhash = BlockHash::hash(&[3]);
+        for i in 4..headers.len() {
+            headers[i].prev_blockhash = headers[i - 1].block_hash();
+        }
+
+        // The second version, the gap, the target and its return, and the
+        // times after the jump forward and either side of the jump back
+        let extra = 4 + 32 + 2 * 4 + 3 * 2;
+        assert_eq!(roundtrip(&headers), 1 + 81 + 9 * 39 + extra);
+    }
+
+    #[test]
+    fn invalid() {
+        let headers = chain(BlockHash::default(), 2, &[1]);
+        let encoded = serialize(&CompressedHeaders(headers));
+
+        let mut reserved = encoded.clone();
+        reserved[1] |= 0x40;
+        assert!(deserialize::<CompressedHeaders>(&reserved).is_err());
+
+        // The first header cannot leave anything out
+        let mut first = encoded.clone();
+        first[1] &= !PREV_BLOCKHASH;
+        assert!(deserialize::<CompressedHeaders>(&first).is_err());
+
+        // Only one version has been seen
+        let mut version = encoded.clone();
+        version[82] = 2;
+        assert!(deserialize::<CompressedHeaders>(&version).is_err());
+
+        assert!(deserialize::<CompressedHeaders>(&encoded[..encoded.len() - 1]).is_err());
+        assert!(deserialize::<CompressedHeaders>(&[0xfd, 0xff, 0xff]).is_err());
+    }
+}
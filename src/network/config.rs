@@ -0,0 +1,359 @@
+//! Node configuration
+//!
+//! The settings every application embedding the networking code needs:
+//! which network to join, how to present itself to peers, how many
+//! connections to keep, whether to reach peers through a proxy and where to
+//! keep its data. With the `serde` feature a `Config` can be loaded from a
+//! settings file, and is checked with `validate` as it is.
+//!
+//! A `Config` builds the pieces the networking code is set up with, such as
+//! the `RoutingDialer` connections are opened with and the `UserAgent`
+//! announced in version messages.
+
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::{error, fmt};
+
+use error::{ErrorCode, ErrorKind};
+use network::constants::{Magic, Network, ServiceFlags};
+use network::dialer::{DirectDialer, RoutingDialer, Socks5Dialer};
+use network::message_network::{UserAgent, UserAgentError};
+
+/// The number of outbound connections kept by default
+pub const DEFAULT_MAX_OUTBOUND: usize = 8;
+
+/// The most connections, outbound and inbound together, a `Config` may ask
+/// for, as in Bitcoin Core
+pub const MAX_CONNECTIONS: usize = 125;
+
+/// An invalid setting in a `Config`
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum Error {
+    /// No outbound connections are allowed, so nothing can be synced
+    NoOutboundConnections,
+    /// More than `MAX_CONNECTIONS` connections are allowed in total
+    TooManyConnections(usize),
+    /// The client name or version makes an invalid user agent
+    UserAgent(UserAgentError),
+    /// A proxy address has an unspecified IP address or port 0
+    InvalidProxy(SocketAddr),
+    /// The data directory is an empty path
+    EmptyDataDir,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::TooManyConnections(n) => write!(f, "too many connections: {}", n),
+            Error::UserAgent(ref e) => fmt::Display::fmt(e, f),
+            Error::InvalidProxy(ref addr) => write!(f, "invalid proxy address: {}", addr),
+            Error::NoOutboundConnections | Error::EmptyDataDir => {
+                f.write_str(error::Error::description(self))
+            }
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::UserAgent(ref e) => Some(e),
+            _ => None,
+        }
+    }
+
+    fn description(&self) -> &str {
+        match *self {
+            Error::NoOutboundConnections => "no outbound connections allowed",
+            Error::TooManyConnections(..) => "too many connections",
+            Error::UserAgent(ref e) => e.description(),
+            Error::InvalidProxy(..) => "invalid proxy address",
+            Error::EmptyDataDir => "empty data directory",
+        }
+    }
+}
+
+impl ErrorCode for Error {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::NetworkConfig
+    }
+
+    fn number(&self) -> u32 {
+        match *self {
+            Error::NoOutboundConnections => 1,
+            Error::TooManyConnections(..) => 2,
+            Error::UserAgent(..) => 3,
+            Error::InvalidProxy(..) => 4,
+            Error::EmptyDataDir => 5,
+        }
+    }
+}
+
+#[doc(hidden)]
+impl From<UserAgentError> for Error {
+    fn from(e: UserAgentError) -> Error {
+        Error::UserAgent(e)
+    }
+}
+
+/// Settings for the networking code
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Config {
+    /// The network to join
+    pub network: Network,
+    /// The name and version of the application, announced in the user agent
+    /// after this library's
+    pub client: Option<(String, String)>,
+    /// The services announced to peers
+    pub services: ServiceFlags,
+    /// The most outbound connections to keep
+    pub max_outbound: usize,
+    /// The most inbound connections to accept
+    pub max_inbound: usize,
+    /// A SOCKS5 proxy to reach every peer through
+    pub proxy: Option<SocketAddr>,
+    /// A SOCKS5 proxy to reach `.onion` peers through, such as Tor's, when
+    /// other peers are dialed directly
+    pub onion_proxy: Option<SocketAddr>,
+    /// Where to keep data such as headers and known addresses, if anywhere
+    pub data_dir: Option<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            network: Network::Bitcoin,
+            client: None,
+            services: ServiceFlags::NONE,
+            max_outbound: DEFAULT_MAX_OUTBOUND,
+            max_inbound: 0,
+            proxy: None,
+            onion_proxy: None,
+            data_dir: None,
+        }
+    }
+}
+
+impl Config {
+    /// Checks that the settings make sense together
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.max_outbound == 0 {
+            return Err(Error::NoOutboundConnections);
+        }
+        let connections = self.max_outbound.saturating_add(self.max_inbound);
+        if connections > MAX_CONNECTIONS {
+            return Err(Error::TooManyConnections(connections));
+        }
+        self.user_agent()?;
+        for proxy in self.proxy.iter().chain(self.onion_proxy.iter()) {
+            if proxy.ip().is_unspecified() || proxy.port() == 0 {
+                return Err(Error::InvalidProxy(*proxy));
+            }
+        }
+        if let Some(ref data_dir) = self.data_dir {
+            if data_dir.as_os_str().is_empty() {
+                return Err(Error::EmptyDataDir);
+            }
+        }
+        Ok(())
+    }
+
+    /// The magic bytes of the network
+    pub fn magic(&self) -> Magic {
+        self.network.magic()
+    }
+
+    /// The user agent to announce, naming the client after this library
+    pub fn user_agent(&self) -> Result<UserAgent, UserAgentError> {
+        match self.client {
+            Some((ref name, ref version)) => UserAgent::new().push(name, version),
+            None => Ok(UserAgent::new()),
+        }
+    }
+
+    /// A dialer reaching peers through the configured proxies
+    pub fn dialer(&self) -> RoutingDialer {
+        match (self.proxy, self.onion_proxy) {
+            (Some(proxy), onion_proxy) => RoutingDialer::new(Socks5Dialer::new(proxy))
+                .with_onion(Socks5Dialer::new(onion_proxy.unwrap_or(proxy))),
+            (None, Some(onion_proxy)) => RoutingDialer::new(DirectDialer::default())
+                .with_onion(Socks5Dialer::new(onion_proxy)),
+            (None, None) => RoutingDialer::new(DirectDialer::default()),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+mod serde_impl {
+    use std::fmt;
+
+    use serde::de::{self, MapAccess, Visitor};
+    use serde::ser::SerializeStruct;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::Config;
+
+    const FIELDS: &[&str] = &[
+        "network",
+        "client",
+        "services",
+        "max_outbound",
+        "max_inbound",
+        "proxy",
+        "onion_proxy",
+        "data_dir",
+    ];
+
+    impl Serialize for Config {
+        fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+            let mut st = s.serialize_struct("Config", FIELDS.len())?;
+            st.serialize_field("network", &self.network)?;
+            st.serialize_field("client", &self.client)?;
+            st.serialize_field("services", &self.services.as_u64())?;
+            st.serialize_field("max_outbound", &self.max_outbound)?;
+            st.serialize_field("max_inbound", &self.max_inbound)?;
+            st.serialize_field("proxy", &self.proxy)?;
+            st.serialize_field("onion_proxy", &self.onion_proxy)?;
+            st.serialize_field("data_dir", &self.data_dir)?;
+            st.end()
+        }
+    }
+
+    /// Reads a config, where missing settings take their default value and
+    /// unknown ones are an error, so that typos are not silently ignored
+    impl<'de> Deserialize<'de> for Config {
+        fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Config, D::Error> {
+            struct ConfigVisitor;
+
+            impl<'de> Visitor<'de> for ConfigVisitor {
+                type Value = Config;
+
+                fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                    f.write_str("a network config")
+                }
+
+                fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Config, A::Error> {
+                    let mut config = Config::default();
+                    while let Some(key) = map.next_key::<String>()? {
+                        match key.as_str() {
+                            "network" => config.network = map.next_value()?,
+                            "client" => config.client = map.next_value()?,
+                            "services" => config.services = map.next_value()?,
+                            "max_outbound" => config.max_outbound = map.next_value()?,
+                            "max_inbound" => config.max_inbound = map.next_value()?,
+                            "proxy" => config.proxy = map.next_value()?,
+                            "onion_proxy" => config.onion_proxy = map.next_value()?,
+                            "data_dir" => config.data_dir = map.next_value()?,
+                            _ => return Err(de::Error::unknown_field(&key, FIELDS)),
+                        }
+                    }
+                    config.validate().map_err(de::Error::custom)?;
+                    Ok(config)
+                }
+            }
+
+            d.deserialize_struct("Config", FIELDS, ConfigVisitor)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use network::dialer::Target;
+
+    #[test]
+    fn validate() {
+        let config = Config::default();
+        assert_eq!(config.validate(), Ok(()));
+        assert_eq!(config.magic(), Magic::BITCOIN);
+        assert_eq!(config.user_agent().unwrap(), UserAgent::new());
+
+        let mut config = Config {
+            network: Network::Testnet,
+            client: Some(("MyWallet".to_owned(), "2.0".to_owned())),
+            max_inbound: 117,
+            data_dir: Some(PathBuf::from("/var/lib/wallet")),
+            ..Default::default()
+        };
+        assert_eq!(config.validate(), Ok(()));
+        assert!(config
+            .user_agent()
+            .unwrap()
+            .to_string()
+            .ends_with("/MyWallet:2.0/"));
+
+        config.max_inbound = 118;
+        assert_eq!(config.validate(), Err(Error::TooManyConnections(126)));
+        config.max_inbound = 0;
+        config.max_outbound = 0;
+        assert_eq!(config.validate(), Err(Error::NoOutboundConnections));
+        config.max_outbound = 1;
+        config.client = Some(("My/Wallet".to_owned(), "2.0".to_owned()));
+        assert_eq!(
+            config.validate(),
+            Err(Error::UserAgent(UserAgentError::InvalidChar('/')))
+        );
+        config.client = None;
+        config.onion_proxy = Some("0.0.0.0:9050".parse().unwrap());
+        assert_eq!(
+            config.validate(),
+            Err(Error::InvalidProxy("0.0.0.0:9050".parse().unwrap()))
+        );
+        config.onion_proxy = None;
+        config.data_dir = Some(PathBuf::new());
+        assert_eq!(config.validate(), Err(Error::EmptyDataDir));
+    }
+
+    #[test]
+    fn dialer() {
+        let onion = Target::Host("example.onion".to_owned(), 8333);
+        let tor: SocketAddr = "127.0.0.1:9050".parse().unwrap();
+
+        assert!(!Config::default().dialer().can_dial(&onion));
+        let config = Config {
+            onion_proxy: Some(tor),
+            ..Default::default()
+        };
+        assert!(config.dialer().can_dial(&onion));
+        let config = Config {
+            proxy: Some(tor),
+            ..Default::default()
+        };
+        assert!(config.dialer().can_dial(&onion));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_roundtrip() {
+        use serde_json;
+
+        let config = Config {
+            network: Network::Regtest,
+            client: Some(("MyWallet".to_owned(), "2.0".to_owned())),
+            services: ServiceFlags::WITNESS,
+            proxy: Some("127.0.0.1:9050".parse().unwrap()),
+            data_dir: Some(PathBuf::from("/var/lib/wallet")),
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&config).unwrap();
+        assert_eq!(serde_json::from_str::<Config>(&json).unwrap(), config);
+
+        let partial: Config = serde_json::from_str(r#"{"network": "testnet"}"#).unwrap();
+        assert_eq!(
+            partial,
+            Config {
+                network: Network::Testnet,
+                ..Default::default()
+            }
+        );
+        let symbolic: Config = serde_json::from_str(r#"{"services": "NETWORK|WITNESS"}"#).unwrap();
+        assert_eq!(
+            symbolic.services,
+            ServiceFlags::NETWORK | ServiceFlags::WITNESS
+        );
+        assert!(serde_json::from_str::<Config>(r#"{"netwrok": "testnet"}"#).is_err());
+        assert!(serde_json::from_str::<Config>(r#"{"max_outbound": 0}"#).is_err());
+    }
+}
@@ -3,11 +3,11 @@
 //! This module provides various constants relating to the Bitcoin network
 //! protocol, such as protocol versioning and magic header bytes.
 //!
-//! The [`Network`][1] type implements the [`Decodable`][2] and
-//! [`Encodable`][3] traits and encodes the magic bytes of the
-//! given network.
+//! The [`Magic`][1] type implements the [`Decodable`][2] and
+//! [`Encodable`][3] traits and encodes the magic bytes of a
+//! network.
 //!
-//! [1]: enum.Network.html
+//! [1]: struct.Magic.html
 //! [2]: ../../consensus/encode/trait.Decodable.html
 //! [3]: ../../consensus/encode/trait.Encodable.html
 //!
@@ -23,9 +23,14 @@
 //! assert_eq!(&bytes[..], &[0xF9, 0xBE, 0xB4, 0xD9]);
 //! ```
 
-use std::{fmt, io, ops};
+use std::str::FromStr;
+use std::{error, fmt, io, ops};
 
 use consensus::encode::{self, Decodable, Encodable};
+use error::{ErrorCode, ErrorKind};
+use hashes::hex::{self, FromHex, ToHex};
+use network::params::NetworkParams;
+use util::endian;
 
 /// Version of the protocol as appearing in network message
 pub const PROTOCOL_VERSION: u32 = 70001;
@@ -44,45 +49,153 @@ user_enum! {
 }
 
 impl Network {
+    /// Every network
+    pub const ALL: [Network; 3] = [Network::Bitcoin, Network::Testnet, Network::Regtest];
+
     /// Creates a `Network` from the magic bytes.
     ///
     /// # Examples
     ///
     /// ```rust
-    /// use bitcoin::network::constants::Network;
+    /// use bitcoin::network::constants::{Magic, Network};
     ///
-    /// assert_eq!(Some(Network::Bitcoin), Network::from_magic(0xD9B4BEF9));
-    /// assert_eq!(None, Network::from_magic(0xFFFFFFFF));
+    /// assert_eq!(Some(Network::Bitcoin), Network::from_magic(Magic::from(0xD9B4BEF9)));
+    /// assert_eq!(None, Network::from_magic(Magic::from(0xFFFFFFFF)));
     /// ```
-    pub fn from_magic(magic: u32) -> Option<Network> {
+    pub fn from_magic(magic: Magic) -> Option<Network> {
         // Note: any new entries here must be added to `magic` below
         match magic {
-            0xD9B4BEF9 => Some(Network::Bitcoin),
-            0x0709110B => Some(Network::Testnet),
-            0xDAB5BFFA => Some(Network::Regtest),
+            Magic::BITCOIN => Some(Network::Bitcoin),
+            Magic::TESTNET => Some(Network::Testnet),
+            Magic::REGTEST => Some(Network::Regtest),
             _ => None,
         }
     }
 
-    /// Return the network magic bytes, which should be encoded little-endian
+    /// Return the network magic bytes, which should be encoded
     /// at the start of every message
     ///
     /// # Examples
     ///
     /// ```rust
-    /// use bitcoin::network::constants::Network;
+    /// use bitcoin::network::constants::{Magic, Network};
     ///
     /// let network = Network::Bitcoin;
-    /// assert_eq!(network.magic(), 0xD9B4BEF9);
+    /// assert_eq!(network.magic(), Magic::from(0xD9B4BEF9));
+    /// assert_eq!(network.magic().to_string(), "f9beb4d9");
     /// ```
-    pub fn magic(&self) -> u32 {
-        // Note: any new entries here must be added to `magic` below
+    pub fn magic(&self) -> Magic {
+        // Note: any new entries here must be added to `from_magic` above
         match *self {
-            Network::Bitcoin => 0xD9B4BEF9,
-            Network::Testnet => 0x0709110B,
-            Network::Regtest => 0xDAB5BFFA,
+            Network::Bitcoin => Magic::BITCOIN,
+            Network::Testnet => Magic::TESTNET,
+            Network::Regtest => Magic::REGTEST,
+        }
+    }
+
+    /// The address prefixes and other parameters of the network
+    pub fn params(self) -> &'static NetworkParams {
+        match self {
+            Network::Bitcoin => &NetworkParams::BITCOIN,
+            Network::Testnet => &NetworkParams::TESTNET,
+            Network::Regtest => &NetworkParams::REGTEST,
         }
     }
+
+    /// The network with the parameters `params`, if they are those of one
+    pub fn from_params(params: &NetworkParams) -> Option<Network> {
+        Network::ALL.iter().cloned().find(|n| n.params() == params)
+    }
+}
+
+/// The magic bytes at the start of every network message, identifying the
+/// chain the message belongs to.
+///
+/// The networking code works in terms of `Magic` rather than [`Network`], so
+/// forks and private chains can use it by picking their own magic bytes.
+/// Magic bytes display as hex in the order they appear on the wire.
+///
+/// [`Network`]: enum.Network.html
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Magic([u8; 4]);
+
+impl Magic {
+    /// Bitcoin mainnet magic bytes.
+    pub const BITCOIN: Magic = Magic([0xF9, 0xBE, 0xB4, 0xD9]);
+    /// Bitcoin testnet magic bytes.
+    pub const TESTNET: Magic = Magic([0x0B, 0x11, 0x09, 0x07]);
+    /// Bitcoin regtest magic bytes.
+    pub const REGTEST: Magic = Magic([0xFA, 0xBF, 0xB5, 0xDA]);
+
+    /// Creates magic from the bytes as they appear on the wire.
+    pub fn from_bytes(bytes: [u8; 4]) -> Magic {
+        Magic(bytes)
+    }
+
+    /// Returns the bytes as they appear on the wire.
+    pub fn to_bytes(self) -> [u8; 4] {
+        self.0
+    }
+}
+
+impl From<Network> for Magic {
+    fn from(network: Network) -> Magic {
+        network.magic()
+    }
+}
+
+/// Converts from the little-endian `u32` form used by Bitcoin Core's
+/// chain parameters, e.g. `0xD9B4BEF9` for mainnet.
+impl From<u32> for Magic {
+    fn from(magic: u32) -> Magic {
+        Magic(endian::u32_to_array_le(magic))
+    }
+}
+
+impl From<Magic> for u32 {
+    fn from(magic: Magic) -> u32 {
+        endian::slice_to_u32_le(&magic.0)
+    }
+}
+
+impl fmt::Display for Magic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0.to_hex())
+    }
+}
+
+impl fmt::Debug for Magic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Magic({})", self)
+    }
+}
+
+impl FromStr for Magic {
+    type Err = hex::Error;
+
+    fn from_str(s: &str) -> Result<Magic, hex::Error> {
+        let bytes = Vec::<u8>::from_hex(s)?;
+        if bytes.len() != 4 {
+            return Err(hex::Error::InvalidLength(8, s.len()));
+        }
+        let mut ret = [0; 4];
+        ret.copy_from_slice(&bytes);
+        Ok(Magic(ret))
+    }
+}
+
+impl Encodable for Magic {
+    #[inline]
+    fn consensus_encode<S: io::Write>(&self, s: S) -> Result<usize, encode::Error> {
+        self.0.consensus_encode(s)
+    }
+}
+
+impl Decodable for Magic {
+    #[inline]
+    fn consensus_decode<D: io::Read>(d: D) -> Result<Self, encode::Error> {
+        Ok(Magic(Decodable::consensus_decode(d)?))
+    }
 }
 
 /// Flags to indicate which network services a ndoe supports.
@@ -128,8 +241,70 @@ impl ServiceFlags {
     pub fn as_u64(&self) -> u64 {
         self.0
     }
+
+    /// The known flags which are set, in bit order
+    pub fn iter(&self) -> impl Iterator<Item = ServiceFlags> {
+        let flags = *self;
+        NAMED_FLAGS
+            .iter()
+            .map(|&(flag, _)| flag)
+            .filter(move |&flag| flags.has(flag))
+    }
+
+    /// The flags which are set but not known
+    pub fn unknown(&self) -> ServiceFlags {
+        let known = NAMED_FLAGS.iter().fold(0, |acc, &(flag, _)| acc | flag.0);
+        ServiceFlags(self.0 & !known)
+    }
+
+    /// The name of each known flag which is set, as Bitcoin Core names
+    /// them, followed by the unknown flags in hex if any. Each name is
+    /// parsed back by [FromStr].
+    pub fn names(&self) -> Vec<String> {
+        let mut ret: Vec<String> = NAMED_FLAGS
+            .iter()
+            .filter(|&&(flag, _)| self.has(flag))
+            .map(|&(_, name)| name.to_owned())
+            .collect();
+        let unknown = self.unknown();
+        if unknown != ServiceFlags::NONE {
+            ret.push(format!("0x{:x}", unknown));
+        }
+        ret
+    }
+
+    /// The flags a single name from [ServiceFlags::names] stands for,
+    /// or a Bitcoin Core `UNKNOWN[2^n]` name
+    fn from_name(name: &str) -> Option<ServiceFlags> {
+        if name == "NONE" {
+            return Some(ServiceFlags::NONE);
+        }
+        if let Some(&(flag, _)) = NAMED_FLAGS.iter().find(|&&(_, n)| n == name) {
+            return Some(flag);
+        }
+        if let Some(hex) = name.strip_prefix("0x") {
+            return u64::from_str_radix(hex, 16).ok().map(ServiceFlags);
+        }
+        if let Some(bit) = name
+            .strip_prefix("UNKNOWN[2^")
+            .and_then(|rest| rest.strip_suffix(']'))
+        {
+            return 1_u64.checked_shl(bit.parse().ok()?).map(ServiceFlags);
+        }
+        None
+    }
 }
 
+/// The known flags and their names, in bit order
+const NAMED_FLAGS: [(ServiceFlags, &str); 6] = [
+    (ServiceFlags::NETWORK, "NETWORK"),
+    (ServiceFlags::GETUTXO, "GETUTXO"),
+    (ServiceFlags::BLOOM, "BLOOM"),
+    (ServiceFlags::WITNESS, "WITNESS"),
+    (ServiceFlags::COMPACT_FILTERS, "COMPACT_FILTERS"),
+    (ServiceFlags::NETWORK_LIMITED, "NETWORK_LIMITED"),
+];
+
 impl fmt::LowerHex for ServiceFlags {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         fmt::LowerHex::fmt(&self.0, f)
@@ -147,36 +322,69 @@ impl fmt::Display for ServiceFlags {
         if *self == ServiceFlags::NONE {
             return write!(f, "ServiceFlags(NONE)");
         }
+        write!(f, "ServiceFlags({})", self.names().join("|"))
+    }
+}
 
-        let mut flags: ServiceFlags = self.clone();
-        let mut first: bool = true;
-        macro_rules! write_flag {
-            ($f:ident) => {
-                if flags.has(ServiceFlags::$f) {
-                    if !first {
-                        write!(f, "|")?;
-                    }
-                    first = false;
-                    write!(f, stringify!($f))?;
-                    flags.remove(ServiceFlags::$f);
-                }
-            };
+/// Parses flag names separated by '|', like "NETWORK|WITNESS", optionally
+/// wrapped in "ServiceFlags(..)" as they are displayed. A name is one of
+/// [ServiceFlags::names], "NONE" or Bitcoin Core's "UNKNOWN[2^n]".
+impl FromStr for ServiceFlags {
+    type Err = ParseServiceFlagsError;
+
+    fn from_str(s: &str) -> Result<ServiceFlags, ParseServiceFlagsError> {
+        let s = s.trim();
+        let s = s
+            .strip_prefix("ServiceFlags(")
+            .and_then(|rest| rest.strip_suffix(')'))
+            .unwrap_or(s);
+        let mut flags = ServiceFlags::NONE;
+        for name in s.split('|').map(str::trim) {
+            flags |= ServiceFlags::from_name(name)
+                .ok_or_else(|| ParseServiceFlagsError::UnknownFlag(name.to_owned()))?;
         }
-        write!(f, "ServiceFlags(")?;
-        write_flag!(NETWORK);
-        write_flag!(GETUTXO);
-        write_flag!(BLOOM);
-        write_flag!(WITNESS);
-        write_flag!(COMPACT_FILTERS);
-        write_flag!(NETWORK_LIMITED);
-        // If there are unknown flags left, we append them in hex.
-        if flags != ServiceFlags::NONE {
-            if !first {
-                write!(f, "|")?;
+        Ok(flags)
+    }
+}
+
+/// An error in parsing [ServiceFlags] from their names
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseServiceFlagsError {
+    /// A name is not that of a service flag
+    UnknownFlag(String),
+}
+
+impl fmt::Display for ParseServiceFlagsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseServiceFlagsError::UnknownFlag(ref name) => {
+                write!(f, "unknown service flag: {:?}", name)
             }
-            write!(f, "0x{:x}", flags)?;
         }
-        write!(f, ")")
+    }
+}
+
+impl error::Error for ParseServiceFlagsError {
+    fn cause(&self) -> Option<&error::Error> {
+        None
+    }
+
+    fn description(&self) -> &str {
+        match *self {
+            ParseServiceFlagsError::UnknownFlag(..) => "unknown service flag",
+        }
+    }
+}
+
+impl ErrorCode for ParseServiceFlagsError {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::ServiceFlags
+    }
+
+    fn number(&self) -> u32 {
+        match *self {
+            ParseServiceFlagsError::UnknownFlag(..) => 1,
+        }
     }
 }
 
@@ -234,10 +442,92 @@ impl Decodable for ServiceFlags {
     }
 }
 
+#[cfg(feature = "serde")]
+mod serde_impl {
+    use std::fmt;
+
+    use serde::de::{self, SeqAccess, Visitor};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::ServiceFlags;
+
+    /// Serializes as the integer, like on the wire
+    impl Serialize for ServiceFlags {
+        fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+            s.serialize_u64(self.0)
+        }
+    }
+
+    /// Reads the integer, a string like "NETWORK|WITNESS" or an array of
+    /// names, so that either serialization reads back
+    impl<'de> Deserialize<'de> for ServiceFlags {
+        fn deserialize<D: Deserializer<'de>>(d: D) -> Result<ServiceFlags, D::Error> {
+            struct FlagsVisitor;
+
+            impl<'de> Visitor<'de> for FlagsVisitor {
+                type Value = ServiceFlags;
+
+                fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                    f.write_str("service flags as an integer, a string or an array of names")
+                }
+
+                fn visit_u64<E: de::Error>(self, v: u64) -> Result<ServiceFlags, E> {
+                    Ok(ServiceFlags(v))
+                }
+
+                fn visit_str<E: de::Error>(self, v: &str) -> Result<ServiceFlags, E> {
+                    v.parse().map_err(E::custom)
+                }
+
+                fn visit_seq<A: SeqAccess<'de>>(
+                    self,
+                    mut seq: A,
+                ) -> Result<ServiceFlags, A::Error> {
+                    let mut flags = ServiceFlags::NONE;
+                    while let Some(name) = seq.next_element::<String>()? {
+                        flags |= name.parse().map_err(de::Error::custom)?;
+                    }
+                    Ok(flags)
+                }
+            }
+
+            d.deserialize_any(FlagsVisitor)
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+pub mod serde {
+    //! Serialize [ServiceFlags] as an array of names, like the
+    //! `localservicesnames` of Bitcoin Core, instead of an integer. Use with
+    //! `#[serde(with = "bitcoin::network::constants::serde::as_names")]`.
+    //!
+    //! [ServiceFlags]: ../struct.ServiceFlags.html
+
+    pub mod as_names {
+        //! Serialize and deserialize [ServiceFlags] as an array of names.
+
+        // methods are implementation of a standardized serde-specific signature
+        #![allow(missing_docs)]
+
+        use network::constants::ServiceFlags;
+        use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+        pub fn serialize<S: Serializer>(flags: &ServiceFlags, s: S) -> Result<S::Ok, S::Error> {
+            flags.names().serialize(s)
+        }
+
+        pub fn deserialize<'d, D: Deserializer<'d>>(d: D) -> Result<ServiceFlags, D::Error> {
+            ServiceFlags::deserialize(d)
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{Network, ServiceFlags};
+    use super::{Magic, Network, ParseServiceFlagsError, ServiceFlags};
     use consensus::encode::{deserialize, serialize};
+    use hashes::hex;
 
     #[test]
     fn serialize_test() {
@@ -267,6 +557,37 @@ mod tests {
         );
     }
 
+    #[test]
+    fn magic_test() {
+        for network in [Network::Bitcoin, Network::Testnet, Network::Regtest].iter() {
+            assert_eq!(Network::from_magic(network.magic()), Some(*network));
+            assert_eq!(Magic::from(*network), network.magic());
+        }
+
+        assert_eq!(Magic::from(0xD9B4BEF9), Magic::BITCOIN);
+        assert_eq!(u32::from(Magic::TESTNET), 0x0709110B);
+        assert_eq!(Magic::REGTEST.to_string(), "fabfb5da");
+        assert_eq!(format!("{:?}", Magic::BITCOIN), "Magic(f9beb4d9)");
+        assert_eq!("0B110907".parse::<Magic>(), Ok(Magic::TESTNET));
+
+        // A chain the `Network` enum does not know about
+        let signet: Magic = "0a03cf40".parse().unwrap();
+        assert_eq!(signet.to_bytes(), [0x0a, 0x03, 0xcf, 0x40]);
+        assert_eq!(Magic::from_bytes(signet.to_bytes()), signet);
+        assert_eq!(Network::from_magic(signet), None);
+        assert_eq!(serialize(&signet), &[0x0a, 0x03, 0xcf, 0x40]);
+        assert_eq!(
+            deserialize::<Magic>(&[0x0a, 0x03, 0xcf, 0x40]).ok(),
+            Some(signet)
+        );
+
+        assert_eq!(
+            "0a03cf".parse::<Magic>(),
+            Err(hex::Error::InvalidLength(8, 6))
+        );
+        assert!("0a03cf4g".parse::<Magic>().is_err());
+    }
+
     #[test]
     fn string_test() {
         assert_eq!(Network::Bitcoin.to_string(), "bitcoin");
@@ -277,6 +598,7 @@ mod tests {
         assert_eq!("testnet".parse::<Network>().unwrap(), Network::Testnet);
         assert_eq!("regtest".parse::<Network>().unwrap(), Network::Regtest);
         assert!("fakenet".parse::<Network>().is_err());
+        roundtrip_fromstr_display!(Network; Network::Bitcoin, Network::Testnet, Network::Regtest);
     }
 
     #[test]
@@ -324,4 +646,75 @@ mod tests {
             flag.to_string()
         );
     }
+
+    #[test]
+    fn service_flags_names() {
+        let flags = ServiceFlags::NETWORK | ServiceFlags::WITNESS | 0x100_0000.into();
+        assert_eq!(
+            flags.iter().collect::<Vec<_>>(),
+            vec![ServiceFlags::NETWORK, ServiceFlags::WITNESS]
+        );
+        assert_eq!(flags.unknown(), ServiceFlags::from(0x100_0000));
+        assert_eq!(flags.names(), vec!["NETWORK", "WITNESS", "0x1000000"]);
+        assert_eq!(ServiceFlags::NONE.iter().count(), 0);
+        assert!(ServiceFlags::NONE.names().is_empty());
+
+        assert_eq!(
+            "NETWORK|WITNESS".parse(),
+            Ok(ServiceFlags::NETWORK | ServiceFlags::WITNESS)
+        );
+        assert_eq!(
+            " WITNESS | NETWORK_LIMITED ".parse(),
+            Ok(ServiceFlags::WITNESS | ServiceFlags::NETWORK_LIMITED)
+        );
+        assert_eq!(
+            "NETWORK|UNKNOWN[2^24]".parse(),
+            Ok(ServiceFlags::NETWORK | 0x100_0000.into())
+        );
+        assert_eq!("NONE".parse(), Ok(ServiceFlags::NONE));
+        assert_eq!(flags.names().join("|").parse(), Ok(flags));
+        assert_eq!(
+            "NETWORK|witness".parse::<ServiceFlags>(),
+            Err(ParseServiceFlagsError::UnknownFlag("witness".to_owned()))
+        );
+        assert!("".parse::<ServiceFlags>().is_err());
+        assert!("NETWORK||WITNESS".parse::<ServiceFlags>().is_err());
+        assert!("UNKNOWN[2^64]".parse::<ServiceFlags>().is_err());
+        assert!("0xg".parse::<ServiceFlags>().is_err());
+
+        roundtrip_fromstr_display!(
+            ServiceFlags;
+            ServiceFlags::NONE,
+            ServiceFlags::WITNESS,
+            flags,
+            ServiceFlags::from(u64::MAX),
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn service_flags_serde() {
+        use serde_json;
+
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct T {
+            flags: ServiceFlags,
+            #[serde(with = "::network::constants::serde::as_names")]
+            names: ServiceFlags,
+        }
+
+        let t = T {
+            flags: ServiceFlags::NETWORK | ServiceFlags::WITNESS,
+            names: ServiceFlags::NETWORK | ServiceFlags::WITNESS | 0x100_0000.into(),
+        };
+        let json = serde_json::to_string(&t).unwrap();
+        assert_eq!(
+            json,
+            r#"{"flags":9,"names":["NETWORK","WITNESS","0x1000000"]}"#
+        );
+        assert_eq!(serde_json::from_str::<T>(&json).unwrap(), t);
+        let symbolic = r#"{"flags":"NETWORK|WITNESS","names":16777225}"#;
+        assert_eq!(serde_json::from_str::<T>(symbolic).unwrap(), t);
+        assert!(serde_json::from_str::<T>(r#"{"flags":["NETWRK"],"names":0}"#).is_err());
+    }
 }
@@ -0,0 +1,326 @@
+//! Dialing peers
+//!
+//! This module defines how outgoing connections to peers are opened. A
+//! `Dialer` turns a `Target` into a connected `TcpStream`, either directly
+//! or through a SOCKS5 proxy such as Tor, and a `RoutingDialer` picks
+//! between them so that `.onion` peers go through Tor while clearnet peers
+//! are dialed directly from the same process.
+
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use network::Error;
+
+/// A peer to connect to
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Target {
+    /// An IP address and port
+    Socket(SocketAddr),
+    /// A host name and port, which is resolved by the dialer. For `.onion`
+    /// hosts that has to be a proxy.
+    Host(String, u16),
+}
+
+impl Target {
+    /// Whether the target is a Tor hidden service
+    pub fn is_onion(&self) -> bool {
+        match *self {
+            Target::Socket(_) => false,
+            Target::Host(ref host, _) => host.to_ascii_lowercase().ends_with(".onion"),
+        }
+    }
+}
+
+impl From<SocketAddr> for Target {
+    fn from(addr: SocketAddr) -> Target {
+        Target::Socket(addr)
+    }
+}
+
+/// Opens connections to peers
+pub trait Dialer {
+    /// Connect to `target`
+    fn dial(&self, target: &Target) -> Result<TcpStream, Error>;
+}
+
+/// Connect to `addr`, within `timeout` if one is given
+fn connect(addr: &SocketAddr, timeout: Option<Duration>) -> io::Result<TcpStream> {
+    match timeout {
+        Some(timeout) => TcpStream::connect_timeout(addr, timeout),
+        None => TcpStream::connect(addr),
+    }
+}
+
+/// Dials peers directly over TCP
+#[derive(Clone, Debug, Default)]
+pub struct DirectDialer {
+    /// Give up on a connection attempt after this long
+    pub timeout: Option<Duration>,
+}
+
+impl Dialer for DirectDialer {
+    fn dial(&self, target: &Target) -> Result<TcpStream, Error> {
+        match *target {
+            Target::Socket(ref addr) => Ok(connect(addr, self.timeout)?),
+            Target::Host(..) if target.is_onion() => Err(Error::UnroutableTarget),
+            Target::Host(ref host, port) => {
+                let mut last_err = io::Error::new(io::ErrorKind::NotFound, "host did not resolve");
+                for addr in (host.as_str(), port).to_socket_addrs()? {
+                    match connect(&addr, self.timeout) {
+                        Ok(stream) => return Ok(stream),
+                        Err(e) => last_err = e,
+                    }
+                }
+                Err(Error::Io(last_err))
+            }
+        }
+    }
+}
+
+/// Dials peers through a SOCKS5 proxy, such as the one Tor provides.
+///
+/// Host names are passed to the proxy unresolved, so no DNS lookups leak
+/// out around it. Only proxies which require no authentication are
+/// supported.
+#[derive(Clone, Debug)]
+pub struct Socks5Dialer {
+    /// Address of the proxy
+    pub proxy: SocketAddr,
+    /// Give up on connecting to the proxy after this long
+    pub timeout: Option<Duration>,
+}
+
+impl Socks5Dialer {
+    /// Create a dialer using the proxy at `proxy`
+    pub fn new(proxy: SocketAddr) -> Socks5Dialer {
+        Socks5Dialer {
+            proxy,
+            timeout: None,
+        }
+    }
+}
+
+impl Dialer for Socks5Dialer {
+    fn dial(&self, target: &Target) -> Result<TcpStream, Error> {
+        let mut stream = connect(&self.proxy, self.timeout)?;
+        socks5_connect(&mut stream, target)?;
+        Ok(stream)
+    }
+}
+
+/// Perform a SOCKS5 handshake, per RFC 1928, asking the proxy on the
+/// other end of `stream` to connect to `target`
+fn socks5_connect<S: Read + Write>(stream: &mut S, target: &Target) -> Result<(), Error> {
+    // Version 5, one method: no authentication
+    stream.write_all(&[0x05, 0x01, 0x00])?;
+    let mut reply = [0u8; 2];
+    stream.read_exact(&mut reply)?;
+    if reply != [0x05, 0x00] {
+        return Err(Error::Socks5Protocol("no acceptable authentication method"));
+    }
+
+    let mut request = vec![0x05, 0x01, 0x00];
+    let port = match *target {
+        Target::Socket(SocketAddr::V4(ref addr)) => {
+            request.push(0x01);
+            request.extend_from_slice(&addr.ip().octets());
+            addr.port()
+        }
+        Target::Socket(SocketAddr::V6(ref addr)) => {
+            request.push(0x04);
+            request.extend_from_slice(&addr.ip().octets());
+            addr.port()
+        }
+        Target::Host(ref host, port) => {
+            if host.len() > 255 {
+                return Err(Error::Socks5Protocol("host name too long"));
+            }
+            request.push(0x03);
+            request.push(host.len() as u8);
+            request.extend_from_slice(host.as_bytes());
+            port
+        }
+    };
+    request.push((port >> 8) as u8);
+    request.push(port as u8);
+    stream.write_all(&request)?;
+
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply)?;
+    if reply[0] != 0x05 {
+        return Err(Error::Socks5Protocol("unexpected version in reply"));
+    }
+    if reply[1] != 0x00 {
+        return Err(Error::Socks5Refused(reply[1]));
+    }
+    // The address the proxy bound, which we have no use for
+    let addr_len = match reply[3] {
+        0x01 => 4,
+        0x04 => 16,
+        0x03 => {
+            let mut len = [0u8; 1];
+            stream.read_exact(&mut len)?;
+            len[0] as usize
+        }
+        _ => return Err(Error::Socks5Protocol("unknown address type in reply")),
+    };
+    let mut bound = vec![0u8; addr_len + 2];
+    stream.read_exact(&mut bound)?;
+    Ok(())
+}
+
+/// Routes `.onion` targets to one dialer and everything else to another.
+///
+/// `RoutingDialer::new(DirectDialer::default())` on its own refuses
+/// `.onion` targets; adding a `Socks5Dialer` for Tor with `with_onion`
+/// lets one connection pool reach both networks.
+pub struct RoutingDialer {
+    clearnet: Box<Dialer>,
+    onion: Option<Box<Dialer>>,
+}
+
+impl RoutingDialer {
+    /// Create a dialer sending all clearnet targets to `clearnet`
+    pub fn new<D: Dialer + 'static>(clearnet: D) -> RoutingDialer {
+        RoutingDialer {
+            clearnet: Box::new(clearnet),
+            onion: None,
+        }
+    }
+
+    /// Send `.onion` targets to `onion`
+    pub fn with_onion<D: Dialer + 'static>(mut self, onion: D) -> RoutingDialer {
+        self.onion = Some(Box::new(onion));
+        self
+    }
+
+    /// Whether `target` would be routed anywhere
+    pub fn can_dial(&self, target: &Target) -> bool {
+        !target.is_onion() || self.onion.is_some()
+    }
+}
+
+impl Dialer for RoutingDialer {
+    fn dial(&self, target: &Target) -> Result<TcpStream, Error> {
+        if !target.is_onion() {
+            return self.clearnet.dial(target);
+        }
+        match self.onion {
+            Some(ref onion) => onion.dial(target),
+            None => Err(Error::UnroutableTarget),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+    use std::net::{Ipv4Addr, TcpListener};
+    use std::thread;
+
+    /// A stream replaying canned proxy replies and recording requests
+    struct MockStream {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl MockStream {
+        fn new(replies: Vec<u8>) -> MockStream {
+            MockStream {
+                input: Cursor::new(replies),
+                output: vec![],
+            }
+        }
+    }
+
+    impl Read for MockStream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for MockStream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn socks5_handshake() {
+        let onion = Target::Host("expyuzz4wqqyqhjn.onion".to_string(), 8333);
+        let mut stream = MockStream::new(vec![
+            0x05, 0x00, // no authentication
+            0x05, 0x00, 0x00, 0x01, 127, 0, 0, 1, 0x20, 0x8d, // succeeded
+        ]);
+        socks5_connect(&mut stream, &onion).unwrap();
+        let mut expected = vec![0x05, 0x01, 0x00, 0x05, 0x01, 0x00, 0x03, 22];
+        expected.extend_from_slice(b"expyuzz4wqqyqhjn.onion");
+        expected.extend_from_slice(&[0x20, 0x8d]);
+        assert_eq!(stream.output, expected);
+
+        let ip = Target::from(SocketAddr::new(Ipv4Addr::new(10, 1, 2, 3).into(), 18333));
+        let mut stream = MockStream::new(vec![
+            0x05, 0x00, // no authentication
+            0x05, 0x00, 0x00, 0x03, 3, b'a', b'b', b'c', 0x00, 0x00, // succeeded
+        ]);
+        socks5_connect(&mut stream, &ip).unwrap();
+        assert_eq!(
+            &stream.output[3..],
+            &[0x05, 0x01, 0x00, 0x01, 10, 1, 2, 3, 0x47, 0x9d]
+        );
+
+        // Authentication required
+        let mut stream = MockStream::new(vec![0x05, 0xff]);
+        match socks5_connect(&mut stream, &onion) {
+            Err(Error::Socks5Protocol(_)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        // Host unreachable
+        let mut stream = MockStream::new(vec![0x05, 0x00, 0x05, 0x04, 0x00, 0x01]);
+        match socks5_connect(&mut stream, &onion) {
+            Err(Error::Socks5Refused(0x04)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        // Cut off
+        let mut stream = MockStream::new(vec![0x05, 0x00, 0x05, 0x00, 0x00, 0x01, 127]);
+        match socks5_connect(&mut stream, &onion) {
+            Err(Error::Io(_)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn routing() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let accept = thread::spawn(move || listener.accept().map(|(_, peer)| peer));
+
+        let onion = Target::Host("expyuzz4wqqyqhjn.ONION".to_string(), 8333);
+        assert!(onion.is_onion());
+        assert!(!Target::Host("seed.bitcoin.sipa.be".to_string(), 8333).is_onion());
+
+        let dialer = RoutingDialer::new(DirectDialer::default());
+        assert!(!dialer.can_dial(&onion));
+        match dialer.dial(&onion) {
+            Err(Error::UnroutableTarget) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let stream = dialer.dial(&Target::from(addr)).unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), addr);
+        assert_eq!(
+            accept.join().unwrap().unwrap(),
+            stream.local_addr().unwrap()
+        );
+
+        let dialer = dialer.with_onion(Socks5Dialer::new(addr));
+        assert!(dialer.can_dial(&onion));
+    }
+}
@@ -0,0 +1,271 @@
+//! Peer discovery
+//!
+//! Beyond DNS seeds, nodes learn about each other from `addr` messages,
+//! sent either unsolicited or in response to a `getaddr`. This module
+//! tracks that exchange with a single peer: it sends `getaddr` once,
+//! limits how many addresses the peer may feed us with a token bucket, as
+//! Bitcoin Core does, and passes the accepted addresses on to an
+//! `AddressBook`.
+
+use std::time::Instant;
+
+use network::address::Address;
+use network::message::{NetworkMessage, MAX_ADDR_TO_SEND};
+use network::Error;
+
+/// Rate at which a peer earns permission to send us another address
+pub const MAX_ADDR_RATE_PER_SECOND: f64 = 0.1;
+
+/// Most addresses a peer may save up permission to send, apart from those
+/// allowed in response to our `getaddr`
+pub const MAX_ADDR_PROCESSING_TOKEN_BUCKET: f64 = MAX_ADDR_TO_SEND as f64;
+
+/// Somewhere to keep the addresses of peers we learn about
+pub trait AddressBook {
+    /// Remember `addr`, last seen at unix time `time`
+    fn add(&mut self, time: u32, addr: Address);
+}
+
+impl AddressBook for Vec<(u32, Address)> {
+    fn add(&mut self, time: u32, addr: Address) {
+        self.push((time, addr));
+    }
+}
+
+/// A token bucket, filling at a constant rate up to a maximum
+#[derive(Clone, Debug)]
+pub struct TokenBucket {
+    tokens: f64,
+    rate: f64,
+    max: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    /// Creates a bucket holding one token, gaining `rate` tokens per second
+    /// up to `max`
+    pub fn new(rate: f64, max: f64, now: Instant) -> TokenBucket {
+        TokenBucket {
+            tokens: 1.0,
+            rate,
+            max,
+            last: now,
+        }
+    }
+
+    /// Adds the tokens earned since the last refill. A bucket already
+    /// holding more than `max`, thanks to `add`, is left as it is.
+    pub fn refill(&mut self, now: Instant) {
+        if now <= self.last {
+            return;
+        }
+        let elapsed = now.duration_since(self.last);
+        self.last = now;
+        if self.tokens < self.max {
+            let secs = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 * 1e-9;
+            self.tokens = (self.tokens + secs * self.rate).min(self.max);
+        }
+    }
+
+    /// Adds `n` tokens, which may take the bucket beyond its maximum
+    pub fn add(&mut self, n: f64) {
+        self.tokens += n;
+    }
+
+    /// Takes a token if one is available
+    pub fn try_take(&mut self) -> bool {
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// The number of tokens in the bucket
+    pub fn tokens(&self) -> f64 {
+        self.tokens
+    }
+}
+
+/// What happened to the addresses in an `addr` message
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct AddrStats {
+    /// Addresses passed on to the address book
+    pub accepted: usize,
+    /// Addresses dropped because the peer sent too many too quickly
+    pub rate_limited: usize,
+}
+
+/// The address exchange with a single peer
+#[derive(Clone, Debug)]
+pub struct PeerDiscovery {
+    bucket: TokenBucket,
+    getaddr_sent: bool,
+    awaiting_response: bool,
+}
+
+impl PeerDiscovery {
+    /// Starts tracking a newly connected peer
+    pub fn new(now: Instant) -> PeerDiscovery {
+        PeerDiscovery {
+            bucket: TokenBucket::new(
+                MAX_ADDR_RATE_PER_SECOND,
+                MAX_ADDR_PROCESSING_TOKEN_BUCKET,
+                now,
+            ),
+            getaddr_sent: false,
+            awaiting_response: false,
+        }
+    }
+
+    /// Returns the `getaddr` message to send the peer, the first time only.
+    ///
+    /// Sending it allows the peer a full `addr` message beyond its usual
+    /// rate.
+    pub fn getaddr(&mut self) -> Option<NetworkMessage> {
+        if self.getaddr_sent {
+            return None;
+        }
+        self.getaddr_sent = true;
+        self.awaiting_response = true;
+        self.bucket.add(MAX_ADDR_TO_SEND as f64);
+        Some(NetworkMessage::GetAddr)
+    }
+
+    /// Whether we sent `getaddr` and the response may not be complete. A
+    /// response is taken to be complete once an `addr` message arrives with
+    /// fewer than `MAX_ADDR_TO_SEND` entries.
+    pub fn awaiting_response(&self) -> bool {
+        self.awaiting_response
+    }
+
+    /// Processes the addresses of an `addr` message, passing as many as the
+    /// rate limit allows to `book`
+    pub fn handle_addr(
+        &mut self,
+        addrs: &[(u32, Address)],
+        now: Instant,
+        book: &mut AddressBook,
+    ) -> Result<AddrStats, Error> {
+        if addrs.len() > MAX_ADDR_TO_SEND {
+            return Err(Error::TooManyAddresses(addrs.len()));
+        }
+        if addrs.len() < MAX_ADDR_TO_SEND {
+            self.awaiting_response = false;
+        }
+        self.bucket.refill(now);
+        let mut stats = AddrStats::default();
+        for &(time, ref addr) in addrs.iter() {
+            if self.bucket.try_take() {
+                book.add(time, addr.clone());
+                stats.accepted += 1;
+            } else {
+                stats.rate_limited += 1;
+            }
+        }
+        Ok(stats)
+    }
+
+    /// The number of addresses the peer may currently send us
+    pub fn allowance(&self) -> usize {
+        self.bucket.tokens() as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::{Ipv4Addr, SocketAddr};
+    use std::time::Duration;
+
+    use network::constants::ServiceFlags;
+
+    fn addrs(n: usize) -> Vec<(u32, Address)> {
+        (0..n)
+            .map(|i| {
+                let ip = Ipv4Addr::new(10, (i >> 16) as u8, (i >> 8) as u8, i as u8);
+                let addr = Address::new(&SocketAddr::new(ip.into(), 8333), ServiceFlags::NETWORK);
+                (1_600_000_000 + i as u32, addr)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn token_bucket() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(0.5, 2.0, start);
+        assert!(bucket.try_take());
+        assert!(!bucket.try_take());
+
+        bucket.refill(start + Duration::from_secs(1));
+        assert!(!bucket.try_take());
+        bucket.refill(start + Duration::from_secs(2));
+        assert!(bucket.try_take());
+
+        // Capped at the maximum
+        bucket.refill(start + Duration::from_secs(100));
+        assert_eq!(bucket.tokens(), 2.0);
+
+        // Unless added to directly, which is kept
+        bucket.add(10.0);
+        bucket.refill(start + Duration::from_secs(200));
+        assert_eq!(bucket.tokens(), 12.0);
+
+        // Time going backwards changes nothing
+        bucket.refill(start);
+        assert_eq!(bucket.tokens(), 12.0);
+    }
+
+    #[test]
+    fn getaddr_response() {
+        let start = Instant::now();
+        let mut peer = PeerDiscovery::new(start);
+        let mut book = vec![];
+        assert_eq!(peer.allowance(), 1);
+
+        assert_eq!(peer.getaddr(), Some(NetworkMessage::GetAddr));
+        assert_eq!(peer.getaddr(), None);
+        assert!(peer.awaiting_response());
+        assert_eq!(peer.allowance(), 1001);
+
+        // A full response, and another one, part of which is rate limited
+        let stats = peer.handle_addr(&addrs(1000), start, &mut book).unwrap();
+        assert_eq!(
+            stats,
+            AddrStats {
+                accepted: 1000,
+                rate_limited: 0
+            }
+        );
+        assert!(peer.awaiting_response());
+        let stats = peer.handle_addr(&addrs(10), start, &mut book).unwrap();
+        assert_eq!(
+            stats,
+            AddrStats {
+                accepted: 1,
+                rate_limited: 9
+            }
+        );
+        assert!(!peer.awaiting_response());
+        assert_eq!(book.len(), 1001);
+        assert_eq!(book[..1000], addrs(1000)[..]);
+
+        // Unsolicited addresses trickle in at the base rate
+        let later = start + Duration::from_secs(100);
+        let stats = peer.handle_addr(&addrs(20), later, &mut book).unwrap();
+        assert_eq!(
+            stats,
+            AddrStats {
+                accepted: 10,
+                rate_limited: 10
+            }
+        );
+
+        match peer.handle_addr(&addrs(1001), later, &mut book) {
+            Err(Error::TooManyAddresses(1001)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,357 @@
+//! Batched getdata requests
+//!
+//! A `getdata` message may ask for at most `MAX_INV_SZ` items, and a peer
+//! sent too many requests at once may fall behind or disconnect. A
+//! `GetDataQueue` takes any number of items, such as the 50000 transactions
+//! of a mempool sync, and hands out `getdata` messages of a batch of them at
+//! a time, keeping no more than a window of items in flight. The peer's
+//! `tx`, `block` and `notfound` messages are passed back to the queue, which
+//! hands them out again in the order the items were requested.
+//!
+//! The queue does no I/O: sending its messages to a peer and passing it
+//! the ones received is left to the caller, as with `PeerDiscovery`.
+
+use std::cmp;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use hash_types::BlockHash;
+use hashes::Hash;
+use network::message::{NetworkMessage, MAX_INV_SZ};
+use network::message_blockdata::Inventory;
+
+/// Items asked for per `getdata` message by default, as Bitcoin Core
+/// batches its own
+pub const DEFAULT_BATCH_SIZE: usize = 1000;
+
+/// Items in flight at once by default
+pub const DEFAULT_WINDOW: usize = 5000;
+
+/// What a peer answered for a requested item
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Response {
+    /// The peer sent the item, in the message
+    Found(Inventory, NetworkMessage),
+    /// The peer does not have the item
+    NotFound(Inventory),
+}
+
+impl Response {
+    /// The item the response is for
+    pub fn inventory(&self) -> Inventory {
+        match *self {
+            Response::Found(inv, _) | Response::NotFound(inv) => inv,
+        }
+    }
+}
+
+/// The items requested from a single peer
+#[derive(Clone, Debug)]
+pub struct GetDataQueue {
+    batch_size: usize,
+    window: usize,
+    /// Items not yet asked for
+    pending: VecDeque<Inventory>,
+    /// Items asked for and not answered
+    in_flight: HashSet<Inventory>,
+    /// Every item whose response has not been taken, in request order
+    order: VecDeque<Inventory>,
+    /// The same items, for lookup
+    queued: HashSet<Inventory>,
+    responses: HashMap<Inventory, Response>,
+}
+
+impl GetDataQueue {
+    /// An empty queue with the default batch size and window
+    pub fn new() -> GetDataQueue {
+        GetDataQueue {
+            batch_size: DEFAULT_BATCH_SIZE,
+            window: DEFAULT_WINDOW,
+            pending: VecDeque::new(),
+            in_flight: HashSet::new(),
+            order: VecDeque::new(),
+            queued: HashSet::new(),
+            responses: HashMap::new(),
+        }
+    }
+
+    /// Asks for up to `batch_size` items per message, at most `MAX_INV_SZ`
+    pub fn with_batch_size(mut self, batch_size: usize) -> GetDataQueue {
+        self.batch_size = batch_size.clamp(1, MAX_INV_SZ);
+        self
+    }
+
+    /// Keeps up to `window` items in flight, at least one
+    pub fn with_window(mut self, window: usize) -> GetDataQueue {
+        self.window = cmp::max(window, 1);
+        self
+    }
+
+    /// Queues `items` to be requested. Items already queued and
+    /// `Inventory::Error` entries are skipped.
+    pub fn request_many<I: IntoIterator<Item = Inventory>>(&mut self, items: I) {
+        for inv in items {
+            if inv == Inventory::Error || !self.queued.insert(inv) {
+                continue;
+            }
+            self.pending.push_back(inv);
+            self.order.push_back(inv);
+        }
+    }
+
+    /// The next `getdata` message to send, if any items are left to request
+    /// and the window has room for them
+    pub fn next_message(&mut self) -> Option<NetworkMessage> {
+        let room = self.window.saturating_sub(self.in_flight.len());
+        let n = cmp::min(cmp::min(self.batch_size, room), self.pending.len());
+        if n == 0 {
+            return None;
+        }
+        let batch: Vec<Inventory> = self.pending.drain(..n).collect();
+        self.in_flight.extend(batch.iter().cloned());
+        Some(NetworkMessage::GetData(batch))
+    }
+
+    /// Takes the message if it answers an item in flight, returning it back
+    /// otherwise. `tx` messages answer requests by txid or wtxid, `block`
+    /// messages, which are passed through undecoded, answer requests by
+    /// block hash, and `notfound` messages answer those they list.
+    pub fn handle_message(&mut self, msg: NetworkMessage) -> Option<NetworkMessage> {
+        let inv = match msg {
+            NetworkMessage::Tx(ref tx) => {
+                let txid = tx.txid();
+                [
+                    Inventory::Transaction(txid),
+                    Inventory::WitnessTransaction(txid),
+                    Inventory::WTx(tx.wtxid()),
+                ]
+                .iter()
+                .find(|inv| self.in_flight.contains(inv))
+                .cloned()
+            }
+            NetworkMessage::Unknown {
+                ref command,
+                ref payload,
+            } if command.as_ref() == "block" && payload.len() >= 80 => {
+                let hash = BlockHash::hash(&payload[..80]);
+                [Inventory::Block(hash), Inventory::WitnessBlock(hash)]
+                    .iter()
+                    .find(|inv| self.in_flight.contains(inv))
+                    .cloned()
+            }
+            NetworkMessage::NotFound(ref invs) => {
+                let mut found = false;
+                for inv in invs.iter() {
+                    if self.in_flight.remove(inv) {
+                        self.responses.insert(*inv, Response::NotFound(*inv));
+                        found = true;
+                    }
+                }
+                return if found { None } else { Some(msg) };
+            }
+            _ => None,
+        };
+        match inv {
+            Some(inv) => {
+                self.in_flight.remove(&inv);
+                self.responses.insert(inv, Response::Found(inv, msg));
+                None
+            }
+            None => Some(msg),
+        }
+    }
+
+    /// The response for the earliest requested item not yet taken, once
+    /// it has arrived
+    pub fn next_response(&mut self) -> Option<Response> {
+        let response = self.responses.remove(self.order.front()?)?;
+        self.order.pop_front();
+        self.queued.remove(&response.inventory());
+        Some(response)
+    }
+
+    /// Puts the items in flight back in front of the queue, to be asked for
+    /// again, such as from another peer once this one timed out. Returns
+    /// how many there were.
+    pub fn requeue_in_flight(&mut self) -> usize {
+        let in_flight = &self.in_flight;
+        let requeued: Vec<Inventory> = self
+            .order
+            .iter()
+            .filter(|inv| in_flight.contains(inv))
+            .cloned()
+            .collect();
+        for inv in requeued.iter().rev() {
+            self.pending.push_front(*inv);
+        }
+        self.in_flight.clear();
+        requeued.len()
+    }
+
+    /// The number of items not yet asked for
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// The number of items asked for and not answered
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.len()
+    }
+
+    /// Whether every item was answered and its response taken
+    pub fn is_done(&self) -> bool {
+        self.order.is_empty()
+    }
+}
+
+impl Default for GetDataQueue {
+    fn default() -> GetDataQueue {
+        GetDataQueue::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use blockdata::block::BlockHeader;
+    use blockdata::transaction::Transaction;
+    use consensus::encode::serialize;
+    use network::message::CommandString;
+
+    fn tx(lock_time: u32) -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time,
+            input: vec![],
+            output: vec![],
+        }
+    }
+
+    fn getdata(msg: Option<NetworkMessage>) -> Vec<Inventory> {
+        match msg {
+            Some(NetworkMessage::GetData(invs)) => invs,
+            other => panic!("unexpected message: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn batches() {
+        let txs: Vec<Transaction> = (0..2500).map(tx).collect();
+        let invs: Vec<Inventory> = txs
+            .iter()
+            .map(|t| Inventory::Transaction(t.txid()))
+            .collect();
+        let mut queue = GetDataQueue::new().with_window(2000);
+        queue.request_many(invs.iter().cloned());
+        // Already queued
+        queue.request_many(vec![invs[0], Inventory::Error]);
+        assert_eq!(queue.pending(), 2500);
+
+        assert_eq!(getdata(queue.next_message()), &invs[..1000]);
+        assert_eq!(getdata(queue.next_message()), &invs[1000..2000]);
+        assert_eq!(queue.next_message(), None);
+        assert_eq!(queue.in_flight(), 2000);
+
+        // Answered out of order, and handed out in order
+        assert_eq!(
+            queue.handle_message(NetworkMessage::Tx(txs[1].clone())),
+            None
+        );
+        assert_eq!(queue.next_response(), None);
+        assert_eq!(
+            queue.handle_message(NetworkMessage::NotFound(vec![invs[0], invs[2000]])),
+            None
+        );
+        assert_eq!(queue.next_response(), Some(Response::NotFound(invs[0])));
+        assert_eq!(
+            queue.next_response(),
+            Some(Response::Found(invs[1], NetworkMessage::Tx(txs[1].clone())))
+        );
+        assert_eq!(queue.next_response(), None);
+
+        // Two answers make room for two more items
+        assert_eq!(getdata(queue.next_message()), &invs[2000..2002]);
+        // Unrelated messages are handed back
+        let unrelated = NetworkMessage::Tx(tx(5000));
+        assert_eq!(queue.handle_message(unrelated.clone()), Some(unrelated));
+        assert_eq!(
+            queue.handle_message(NetworkMessage::Ping(1)),
+            Some(NetworkMessage::Ping(1))
+        );
+        let notfound = NetworkMessage::NotFound(vec![Inventory::WTx(Default::default())]);
+        assert_eq!(queue.handle_message(notfound.clone()), Some(notfound));
+
+        for t in txs[2..2002].iter() {
+            assert_eq!(queue.handle_message(NetworkMessage::Tx(t.clone())), None);
+        }
+        assert_eq!(queue.in_flight(), 0);
+        assert_eq!(getdata(queue.next_message()), &invs[2002..]);
+        for t in txs[2002..].iter() {
+            assert_eq!(queue.handle_message(NetworkMessage::Tx(t.clone())), None);
+        }
+        for inv in invs[2..].iter() {
+            assert_eq!(queue.next_response().unwrap().inventory(), *inv);
+        }
+        assert!(queue.is_done());
+        assert_eq!(queue.next_message(), None);
+    }
+
+    #[test]
+    fn witness_and_blocks() {
+        let t = tx(1);
+        let header = BlockHeader {
+            version: 1,
+            prev_blockhash: Default::default(),
+            merkle_root: Default::default(),
+            time: 1231006505,
+            bits: 0x1d00ffff,
+            nonce: 0,
+        };
+        let hash = header.block_hash();
+        let mut queue = GetDataQueue::new().with_batch_size(0);
+        queue.request_many(vec![
+            Inventory::WTx(t.wtxid()),
+            Inventory::WitnessBlock(hash),
+        ]);
+        assert_eq!(getdata(queue.next_message()).len(), 1);
+        assert_eq!(getdata(queue.next_message()).len(), 1);
+
+        let block_msg = NetworkMessage::Unknown {
+            command: CommandString::try_from_str("block").unwrap(),
+            // The header, followed by an empty transaction list
+            payload: [serialize(&header), vec![0]].concat(),
+        };
+        assert_eq!(queue.handle_message(block_msg.clone()), None);
+        assert_eq!(queue.handle_message(NetworkMessage::Tx(t.clone())), None);
+        assert_eq!(
+            queue.next_response(),
+            Some(Response::Found(
+                Inventory::WTx(t.wtxid()),
+                NetworkMessage::Tx(t)
+            ))
+        );
+        assert_eq!(
+            queue.next_response(),
+            Some(Response::Found(Inventory::WitnessBlock(hash), block_msg))
+        );
+        assert!(queue.is_done());
+    }
+
+    #[test]
+    fn requeue() {
+        let invs: Vec<Inventory> = (0..5)
+            .map(|i| Inventory::Transaction(tx(i).txid()))
+            .collect();
+        let mut queue = GetDataQueue::new().with_batch_size(2).with_window(4);
+        queue.request_many(invs.iter().cloned());
+        assert_eq!(getdata(queue.next_message()), &invs[..2]);
+        assert_eq!(getdata(queue.next_message()), &invs[2..4]);
+        queue.handle_message(NetworkMessage::Tx(tx(2)));
+
+        assert_eq!(queue.requeue_in_flight(), 3);
+        assert_eq!(queue.in_flight(), 0);
+        assert_eq!(queue.pending(), 4);
+        assert_eq!(getdata(queue.next_message()), &invs[..2]);
+        assert_eq!(getdata(queue.next_message()), vec![invs[3], invs[4]]);
+    }
+}
@@ -0,0 +1,249 @@
+//! Cross-checked header sync
+//!
+//! A client which takes its headers from a single peer follows whatever
+//! chain that peer serves, and a client surrounded by attacker peers can be
+//! kept on a chain of their choosing. To make this harder, each round of
+//! headers can be requested from several peers and cross-checked before any
+//! of it is committed to the `HeaderChain`.
+//!
+//! Of the batches received, the one ending with the most cumulative work is
+//! followed, as the consensus rules require, but only as far as at least a
+//! set number of peers agree with it. Peers whose batch forks away from it
+//! are reported as divergent, and peers whose batch does not even connect or
+//! has bad proof of work as invalid, so that the caller can disconnect them.
+
+use blockdata::block::BlockHeader;
+use blockdata::headerchain::{Error, HeaderChain};
+use hash_types::BlockHash;
+use util::uint::Uint256;
+
+/// The outcome of a round of cross-checked header sync
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct CrossCheckOutcome<P> {
+    /// Hashes of the headers committed to the chain, in order
+    pub committed: Vec<BlockHash>,
+    /// Peers whose headers fork away from the chain that was followed
+    pub divergent: Vec<P>,
+    /// Peers whose headers were rejected by the header chain
+    pub invalid: Vec<(P, Error)>,
+}
+
+/// Collects the headers served by several peers for one round of sync and
+/// commits those that enough of them agree on
+#[derive(Clone, Debug)]
+pub struct HeaderCrossCheck<P> {
+    peers: usize,
+    min_agreement: usize,
+    responses: Vec<(P, Vec<BlockHeader>)>,
+}
+
+impl<P: Clone + PartialEq> HeaderCrossCheck<P> {
+    /// Cross-checks the responses of `peers` peers per round, committing
+    /// headers served by at least `min_agreement` of them
+    pub fn new(peers: usize, min_agreement: usize) -> HeaderCrossCheck<P> {
+        assert!(
+            min_agreement >= 1 && min_agreement <= peers,
+            "agreement must be between 1 and the number of peers"
+        );
+        HeaderCrossCheck {
+            peers,
+            min_agreement,
+            responses: Vec::with_capacity(peers),
+        }
+    }
+
+    /// Records the `headers` message of `peer`, replacing any earlier
+    /// response of the same peer this round
+    pub fn add_response(&mut self, peer: P, headers: Vec<BlockHeader>) {
+        self.responses.retain(|response| response.0 != peer);
+        self.responses.push((peer, headers));
+    }
+
+    /// Whether enough peers have responded for the round to be committed
+    pub fn is_ready(&self) -> bool {
+        self.responses.len() >= self.peers
+    }
+
+    /// Cross-checks the responses of this round, adds the agreed headers to
+    /// `chain` and starts a new round
+    ///
+    /// This can be called before `is_ready`, for example once the other
+    /// peers have timed out, but then fewer than `min_agreement` peers may
+    /// have responded and nothing is committed.
+    pub fn commit(&mut self, chain: &mut HeaderChain) -> CrossCheckOutcome<P> {
+        let mut outcome = CrossCheckOutcome {
+            committed: vec![],
+            divergent: vec![],
+            invalid: vec![],
+        };
+
+        // Check that each batch connects and compute the work it ends with
+        let mut valid: Vec<(P, Vec<BlockHeader>, Uint256)> = vec![];
+        for (peer, headers) in self.responses.drain(..) {
+            match batch_work(chain, &headers) {
+                Ok(work) => valid.push((peer, headers, work)),
+                Err(e) => outcome.invalid.push((peer, e)),
+            }
+        }
+
+        // Follow the batch with the most work, the first one seen on ties
+        let mut best: Option<&(P, Vec<BlockHeader>, Uint256)> = None;
+        for response in &valid {
+            let better = match best {
+                Some(b) => response.2 > b.2,
+                None => true,
+            };
+            if better {
+                best = Some(response);
+            }
+        }
+        let best = match best {
+            Some(b) => b.1.clone(),
+            None => return outcome,
+        };
+
+        // Count, for each peer, how far its batch agrees with the best one
+        let mut agreed: Vec<usize> = vec![];
+        for (peer, headers, _) in valid {
+            let matching = headers
+                .iter()
+                .zip(best.iter())
+                .take_while(|&(a, b)| a == b)
+                .count();
+            if matching < headers.len() {
+                outcome.divergent.push(peer);
+            }
+            agreed.push(matching);
+        }
+        agreed.sort_by(|a, b| b.cmp(a));
+        let commit = agreed.get(self.min_agreement - 1).cloned().unwrap_or(0);
+
+        for header in &best[..commit] {
+            chain
+                .add_header(*header)
+                .expect("headers checked to connect with valid proof of work");
+            outcome.committed.push(header.block_hash());
+        }
+        outcome
+    }
+}
+
+/// The chainwork at the end of `headers` once added to `chain`, checking
+/// that each connects to the one before and has valid proof of work
+fn batch_work(chain: &HeaderChain, headers: &[BlockHeader]) -> Result<Uint256, Error> {
+    let first = match headers.first() {
+        Some(first) => first,
+        None => return Ok(chain.chainwork()),
+    };
+    let mut work = match chain.get(&first.prev_blockhash) {
+        Some(node) => node.chainwork,
+        None => return Err(Error::UnknownPrevBlock(first.prev_blockhash)),
+    };
+    let mut prev = first.prev_blockhash;
+    for header in headers {
+        if header.prev_blockhash != prev {
+            return Err(Error::UnknownPrevBlock(header.prev_blockhash));
+        }
+        prev = header.block_hash();
+        if !header.has_valid_pow() {
+            return Err(Error::BadProofOfWork(prev));
+        }
+        work = work + header.work();
+    }
+    Ok(work)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use hashes::Hash;
+    use testutil::mine_header;
+
+    /// `n` headers on top of `prev`
+    fn batch(mut prev: BlockHash, n: u32, tag: u32) -> Vec<BlockHeader> {
+        let mut ret = vec![];
+        for i in 0..n {
+            let header = mine_header(prev, 1, tag * 1000 + i);
+            prev = header.block_hash();
+            ret.push(header);
+        }
+        ret
+    }
+
+    fn hashes(headers: &[BlockHeader]) -> Vec<BlockHash> {
+        headers.iter().map(|h| h.block_hash()).collect()
+    }
+
+    #[test]
+    fn agreeing_peers() {
+        let root = mine_header(Default::default(), 1, 0);
+        let mut chain = HeaderChain::new(root);
+        let main = batch(root.block_hash(), 4, 1);
+
+        let mut sync = HeaderCrossCheck::new(3, 2);
+        sync.add_response(1, main.clone());
+        sync.add_response(2, main[..3].to_vec());
+        assert!(!sync.is_ready());
+        // A peer answering twice only counts once
+        sync.add_response(2, main[..2].to_vec());
+        assert!(!sync.is_ready());
+        sync.add_response(3, vec![]);
+        assert!(sync.is_ready());
+
+        // Only the two headers served by two peers are committed, and a
+        // lagging peer is not divergent
+        let outcome = sync.commit(&mut chain);
+        assert_eq!(outcome.committed, hashes(&main[..2]));
+        assert!(outcome.divergent.is_empty());
+        assert!(outcome.invalid.is_empty());
+        assert_eq!(chain.tip(), main[1].block_hash());
+        assert!(!sync.is_ready());
+
+        // Nothing is committed without enough responses
+        sync.add_response(1, main[2..].to_vec());
+        assert!(sync.commit(&mut chain).committed.is_empty());
+        assert_eq!(chain.height(), 2);
+    }
+
+    #[test]
+    fn divergent_and_invalid_peers() {
+        let root = mine_header(Default::default(), 1, 0);
+        let mut chain = HeaderChain::new(root);
+        let main = batch(root.block_hash(), 3, 1);
+        let fork = batch(main[0].block_hash(), 1, 2);
+        let orphan = batch(BlockHash::hash(&[1]), 2, 3);
+        let mut bad = main.clone();
+        while bad[2].has_valid_pow() {
+            bad[2].nonce += 1;
+        }
+
+        let mut sync = HeaderCrossCheck::new(4, 2);
+        let mut forked = main[..1].to_vec();
+        forked.extend(fork);
+        sync.add_response("fork", forked);
+        sync.add_response("main", main.clone());
+        sync.add_response("orphan", orphan.clone());
+        sync.add_response("bad", bad.clone());
+
+        let outcome = sync.commit(&mut chain);
+        // The fork agrees with the most-work chain on the first header only
+        assert_eq!(outcome.committed, hashes(&main[..1]));
+        assert_eq!(outcome.divergent, vec!["fork"]);
+        assert_eq!(
+            outcome.invalid,
+            vec![
+                ("orphan", Error::UnknownPrevBlock(orphan[0].prev_blockhash)),
+                ("bad", Error::BadProofOfWork(bad[2].block_hash())),
+            ]
+        );
+        assert_eq!(chain.height(), 1);
+
+        // With a single peer trusted, the most-work batch is followed
+        let mut chain = HeaderChain::new(root);
+        let mut sync = HeaderCrossCheck::new(2, 1);
+        sync.add_response("short", main[..1].to_vec());
+        sync.add_response("long", main.clone());
+        assert_eq!(sync.commit(&mut chain).committed, hashes(&main));
+    }
+}
@@ -6,12 +6,20 @@
 //! many primitives.
 
 use std::borrow::Cow;
-use std::{io, iter, mem, fmt};
+use std::collections::HashMap;
+use std::{error, io, iter, fmt};
+use blockdata::transaction::Transaction;
 use consensus::{encode, serialize};
-use consensus::encode::{Decodable, Encodable};
+use consensus::encode::{deserialize, Decodable, Encodable, WriteExt, MAX_VEC_SIZE};
+use error::{ErrorCode, ErrorKind};
+use hashes::{sha256d, Hash};
+use network::address::Address;
+use network::constants::Magic;
+use network::message_blockdata::Inventory;
+use network::message_network::{self, MAX_USER_AGENT_LENGTH};
 
 /// Serializer for a command string
-#[derive(PartialEq, Eq, Clone, Debug)]
+#[derive(PartialEq, Eq, Clone, Debug, Hash)]
 pub struct CommandString(Cow<'static, str>);
 
 impl fmt::Display for CommandString {
@@ -20,15 +28,26 @@ impl fmt::Display for CommandString {
     }
 }
 
-impl From<&'static str> for CommandString {
-    fn from(f: &'static str) -> Self {
-        CommandString(f.into())
+impl CommandString {
+    /// Convert from various string types into a [CommandString].
+    ///
+    /// Returns an error if the string is longer than 12 characters or
+    /// contains anything other than printable ASCII.
+    pub fn try_from_str<S: Into<Cow<'static, str>>>(
+        s: S,
+    ) -> Result<CommandString, CommandStringError> {
+        let cow = s.into();
+        if cow.len() > 12 || !cow.bytes().all(|b| b >= 0x20 && b <= 0x7e) {
+            return Err(CommandStringError { cow });
+        }
+        Ok(CommandString(cow))
     }
-}
 
-impl From<String> for CommandString {
-    fn from(f: String) -> Self {
-        CommandString(f.into())
+    /// Creates a [CommandString] for one of our own commands, which are
+    /// known to be valid.
+    fn from_static(s: &'static str) -> CommandString {
+        debug_assert!(CommandString::try_from_str(s).is_ok());
+        CommandString(Cow::Borrowed(s))
     }
 }
 
@@ -46,9 +65,6 @@ impl Encodable for CommandString {
     ) -> Result<usize, encode::Error> {
         let mut rawbytes = [0u8; 12];
         let strbytes = self.0.as_bytes();
-        if strbytes.len() > 12 {
-            return Err(encode::Error::UnrecognizedNetworkCommand(self.0.clone().into_owned()));
-        }
         for x in 0..strbytes.len() {
             rawbytes[x] = strbytes[x];
         }
@@ -60,11 +76,845 @@ impl Decodable for CommandString {
     #[inline]
     fn consensus_decode<D: io::Read>(d: D) -> Result<Self, encode::Error> {
         let rawbytes: [u8; 12] = Decodable::consensus_decode(d)?;
-        let rv = iter::FromIterator::from_iter(
-            rawbytes
-            .iter()
-            .filter_map(|&u| if u > 0 { Some(u as char) } else { None })
+        let len = rawbytes.iter().position(|&u| u == 0).unwrap_or(12);
+        // The command must be padded with nulls only
+        if rawbytes[len..].iter().any(|&u| u != 0) {
+            return Err(encode::Error::ParseFailed("command string has non-null padding"));
+        }
+        let rv: String = iter::FromIterator::from_iter(rawbytes[..len].iter().map(|&u| u as char));
+        CommandString::try_from_str(rv)
+            .map_err(|_| encode::Error::ParseFailed("command string is not printable ASCII"))
+    }
+}
+
+/// Error returned when a command string is invalid.
+///
+/// This is currently returned for command strings longer than 12 characters
+/// or containing characters other than printable ASCII.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct CommandStringError {
+    cow: Cow<'static, str>,
+}
+
+impl fmt::Display for CommandStringError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "the command string '{}' is not at most 12 printable ASCII characters",
+            self.cow.escape_default()
+        )
+    }
+}
+
+impl error::Error for CommandStringError {
+    fn description(&self) -> &str {
+        "invalid command string"
+    }
+}
+
+impl ErrorCode for CommandStringError {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::CommandString
+    }
+
+    fn number(&self) -> u32 {
+        1
+    }
+}
+
+/// The most addresses an `addr` message may carry.
+pub const MAX_ADDR_TO_SEND: usize = 1000;
+
+/// The largest payload of a message whose command has no tighter limit,
+/// Bitcoin Core's `MAX_PROTOCOL_MESSAGE_LENGTH`.
+pub const MAX_PAYLOAD_SIZE: usize = MAX_VEC_SIZE;
+
+/// The most headers a `headers` message may carry.
+pub const MAX_HEADERS_RESULTS: usize = 2000;
+
+/// The most entries an `inv`, `getdata` or `notfound` message may carry.
+pub const MAX_INV_SZ: usize = 50_000;
+
+/// The most block hashes in the locator of a `getblocks` or `getheaders`
+/// message.
+pub const MAX_LOCATOR_SZ: usize = 101;
+
+/// The longest reason Bitcoin Core puts in a `reject` message.
+pub const MAX_REJECT_MESSAGE_LENGTH: usize = 111;
+
+/// The largest payload allowed for a message with `command` by default,
+/// checked before the payload is read. The limits are those which follow
+/// from the most a valid message of each kind can hold.
+pub fn default_max_payload_size(command: &str) -> usize {
+    // A varint prefix of up to 65535 items
+    const VARINT: usize = 3;
+    match command {
+        "verack" | "getaddr" | "sendheaders" | "mempool" | "filterclear" => 0,
+        "ping" | "pong" | "feefilter" => 8,
+        // Version, services, timestamp, two addresses, nonce, user agent,
+        // start height and relay
+        "version" => 4 + 8 + 8 + 26 + 26 + 8 + VARINT + MAX_USER_AGENT_LENGTH + 4 + 1,
+        // A timestamp and an address each
+        "addr" => VARINT + MAX_ADDR_TO_SEND * 30,
+        // Command, code, reason and hash
+        "reject" => 1 + 12 + 1 + 1 + MAX_REJECT_MESSAGE_LENGTH + 32,
+        // A header and an empty transaction count each
+        "headers" => VARINT + MAX_HEADERS_RESULTS * 81,
+        // A type and a hash each
+        "inv" | "getdata" | "notfound" => VARINT + MAX_INV_SZ * 36,
+        // Version, locator and stop hash
+        "getblocks" | "getheaders" => 4 + 1 + MAX_LOCATOR_SZ * 32 + 32,
+        _ => MAX_PAYLOAD_SIZE,
+    }
+}
+
+/// A Network message payload. Proper documentation is available on at
+/// [Bitcoin Wiki: Protocol Specification](https://en.bitcoin.it/wiki/Protocol_specification)
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum NetworkMessage {
+    /// `version`
+    Version(message_network::VersionMessage),
+    /// `verack`
+    Verack,
+    /// `addr`
+    Addr(Vec<(u32, Address)>),
+    /// `getaddr`
+    GetAddr,
+    /// `sendheaders`
+    SendHeaders,
+    /// `inv`
+    Inv(Vec<Inventory>),
+    /// `getdata`
+    GetData(Vec<Inventory>),
+    /// `notfound`
+    NotFound(Vec<Inventory>),
+    /// `ping`
+    Ping(u64),
+    /// `pong`
+    Pong(u64),
+    /// `reject`
+    Reject(message_network::Reject),
+    /// `tx`
+    Tx(Transaction),
+    /// A message whose command is not decoded, kept as raw bytes so it can
+    /// be relayed unchanged.
+    Unknown {
+        /// The command of the message.
+        command: CommandString,
+        /// The undecoded payload of the message.
+        payload: Vec<u8>,
+    },
+}
+
+/// The commands of the messages which have their own `NetworkMessage` variant.
+const BUILTIN_COMMANDS: [&'static str; 12] = [
+    "version",
+    "verack",
+    "addr",
+    "getaddr",
+    "sendheaders",
+    "inv",
+    "getdata",
+    "notfound",
+    "ping",
+    "pong",
+    "reject",
+    "tx",
+];
+
+impl NetworkMessage {
+    /// Return the message command. This is useful for debug outputs.
+    pub fn cmd(&self) -> &str {
+        match *self {
+            NetworkMessage::Version(_) => "version",
+            NetworkMessage::Verack => "verack",
+            NetworkMessage::Addr(_) => "addr",
+            NetworkMessage::GetAddr => "getaddr",
+            NetworkMessage::SendHeaders => "sendheaders",
+            NetworkMessage::Inv(_) => "inv",
+            NetworkMessage::GetData(_) => "getdata",
+            NetworkMessage::NotFound(_) => "notfound",
+            NetworkMessage::Ping(_) => "ping",
+            NetworkMessage::Pong(_) => "pong",
+            NetworkMessage::Reject(_) => "reject",
+            NetworkMessage::Tx(_) => "tx",
+            NetworkMessage::Unknown { ref command, .. } => command.as_ref(),
+        }
+    }
+
+    /// Return the CommandString for the message command.
+    pub fn command(&self) -> CommandString {
+        match *self {
+            NetworkMessage::Unknown { ref command, .. } => command.clone(),
+            _ => CommandString(Cow::Owned(self.cmd().to_owned())),
+        }
+    }
+
+    /// Serializes the payload of the message, without any header.
+    pub fn serialize_payload(&self) -> Vec<u8> {
+        match *self {
+            NetworkMessage::Version(ref dat) => serialize(dat),
+            NetworkMessage::Addr(ref dat) => serialize(dat),
+            NetworkMessage::Inv(ref dat)
+            | NetworkMessage::GetData(ref dat)
+            | NetworkMessage::NotFound(ref dat) => serialize(dat),
+            NetworkMessage::Ping(ref dat) | NetworkMessage::Pong(ref dat) => serialize(dat),
+            NetworkMessage::Reject(ref dat) => serialize(dat),
+            NetworkMessage::Tx(ref dat) => serialize(dat),
+            NetworkMessage::Unknown { ref payload, .. } => payload.clone(),
+            NetworkMessage::Verack | NetworkMessage::GetAddr | NetworkMessage::SendHeaders => {
+                vec![]
+            }
+        }
+    }
+}
+
+/// Decodes the payload of a message with one of the `BUILTIN_COMMANDS`,
+/// anything else is passed through as `NetworkMessage::Unknown`.
+fn decode_builtin(
+    command: &CommandString,
+    payload: Vec<u8>,
+) -> Result<NetworkMessage, encode::Error> {
+    Ok(match command.as_ref() {
+        "version" => NetworkMessage::Version(deserialize(&payload)?),
+        "verack" => NetworkMessage::Verack,
+        "addr" => {
+            let addrs: Vec<(u32, Address)> = deserialize(&payload)?;
+            if addrs.len() > MAX_ADDR_TO_SEND {
+                return Err(encode::Error::ParseFailed("too many addresses in addr message"));
+            }
+            NetworkMessage::Addr(addrs)
+        }
+        "getaddr" => NetworkMessage::GetAddr,
+        "sendheaders" => NetworkMessage::SendHeaders,
+        "inv" | "getdata" | "notfound" => {
+            let invs: Vec<Inventory> = deserialize(&payload)?;
+            if invs.len() > MAX_INV_SZ {
+                return Err(encode::Error::ParseFailed("too many inventory entries"));
+            }
+            match command.as_ref() {
+                "inv" => NetworkMessage::Inv(invs),
+                "getdata" => NetworkMessage::GetData(invs),
+                _ => NetworkMessage::NotFound(invs),
+            }
+        }
+        "ping" => NetworkMessage::Ping(deserialize(&payload)?),
+        "pong" => NetworkMessage::Pong(deserialize(&payload)?),
+        "reject" => NetworkMessage::Reject(deserialize(&payload)?),
+        "tx" => NetworkMessage::Tx(deserialize(&payload)?),
+        _ => NetworkMessage::Unknown {
+            command: command.clone(),
+            payload,
+        },
+    })
+}
+
+/// A function decoding the payload of a message with the given command.
+pub type PayloadDecoder = fn(&CommandString, Vec<u8>) -> Result<NetworkMessage, encode::Error>;
+
+/// The set of commands whose payloads are decoded when reading a
+/// `RawNetworkMessage`.
+///
+/// Messages with a command which is not registered are passed through as
+/// `NetworkMessage::Unknown`. The default registry decodes every message
+/// with its own `NetworkMessage` variant; extension messages can be checked
+/// by registering a decoder for their command.
+#[derive(Clone)]
+pub struct CommandRegistry {
+    decoders: HashMap<CommandString, PayloadDecoder>,
+    max_payload_sizes: HashMap<CommandString, usize>,
+}
+
+impl CommandRegistry {
+    /// Creates a registry with no commands, under which every message is
+    /// passed through undecoded.
+    pub fn empty() -> CommandRegistry {
+        CommandRegistry {
+            decoders: HashMap::new(),
+            max_payload_sizes: HashMap::new(),
+        }
+    }
+
+    /// Registers a decoder for `command`, returning the decoder it replaces.
+    pub fn register(
+        &mut self,
+        command: CommandString,
+        decoder: PayloadDecoder,
+    ) -> Option<PayloadDecoder> {
+        self.decoders.insert(command, decoder)
+    }
+
+    /// Unregisters `command` so its messages are passed through undecoded,
+    /// returning its decoder.
+    pub fn unregister(&mut self, command: &CommandString) -> Option<PayloadDecoder> {
+        self.decoders.remove(command)
+    }
+
+    /// Whether messages with `command` are decoded.
+    pub fn is_registered(&self, command: &CommandString) -> bool {
+        self.decoders.contains_key(command)
+    }
+
+    /// Sets the largest payload allowed for messages with `command`,
+    /// replacing the `default_max_payload_size`, as an extension message
+    /// registered here may need.
+    pub fn set_max_payload_size(&mut self, command: CommandString, max: usize) {
+        self.max_payload_sizes.insert(command, max);
+    }
+
+    /// The largest payload allowed for messages with `command`.
+    pub fn max_payload_size(&self, command: &CommandString) -> usize {
+        match self.max_payload_sizes.get(command) {
+            Some(&max) => max,
+            None => default_max_payload_size(command.as_ref()),
+        }
+    }
+
+    /// Decodes the payload of a message with `command`.
+    pub fn decode_payload(
+        &self,
+        command: CommandString,
+        payload: Vec<u8>,
+    ) -> Result<NetworkMessage, encode::Error> {
+        match self.decoders.get(&command) {
+            Some(decoder) => decoder(&command, payload),
+            None => Ok(NetworkMessage::Unknown { command, payload }),
+        }
+    }
+}
+
+impl Default for CommandRegistry {
+    fn default() -> CommandRegistry {
+        let mut registry = CommandRegistry::empty();
+        for command in BUILTIN_COMMANDS.iter() {
+            registry.register(CommandString::from_static(*command), decode_builtin);
+        }
+        registry
+    }
+}
+
+impl fmt::Debug for CommandRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set().entries(self.decoders.keys()).finish()
+    }
+}
+
+/// A Network message
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RawNetworkMessage {
+    /// Magic bytes to identify the network these messages are meant for
+    pub magic: Magic,
+    /// The actual message data
+    pub payload: NetworkMessage,
+}
+
+impl RawNetworkMessage {
+    /// Return the message command. This is useful for debug outputs.
+    pub fn cmd(&self) -> &str {
+        self.payload.cmd()
+    }
+
+    /// Return the CommandString for the message command.
+    pub fn command(&self) -> CommandString {
+        self.payload.command()
+    }
+
+    /// Decodes a message, decoding its payload only if its command is in
+    /// `registry`, and within the payload size limits of `registry`.
+    pub fn consensus_decode_with<D: io::Read>(
+        mut d: D,
+        registry: &CommandRegistry,
+    ) -> Result<Self, encode::Error> {
+        let (magic, command, payload) = read_message(&mut d, |c| registry.max_payload_size(c))?;
+        Ok(RawNetworkMessage {
+            magic,
+            payload: registry.decode_payload(command, payload)?,
+        })
+    }
+}
+
+fn checksum(data: &[u8]) -> [u8; 4] {
+    let mut checksum = [0; 4];
+    checksum.copy_from_slice(&sha256d::Hash::hash(data)[0..4]);
+    checksum
+}
+
+/// Reads the header of a message and its payload, checking the payload
+/// length against the limit for its command before reading it, and the
+/// payload against the checksum in the header.
+fn read_message<D, F>(
+    mut d: D,
+    max_payload_size: F,
+) -> Result<(Magic, CommandString, Vec<u8>), encode::Error>
+where
+    D: io::Read,
+    F: Fn(&CommandString) -> usize,
+{
+    let magic = trace_field!("magic", Decodable::consensus_decode(&mut d)?);
+    let command = trace_field!("command", Decodable::consensus_decode(&mut d)?);
+    let len = trace_field!("length", u32::consensus_decode(&mut d)?) as usize;
+    let expected: [u8; 4] = trace_field!("checksum", Decodable::consensus_decode(&mut d)?);
+    let max = max_payload_size(&command);
+    if len > max {
+        return Err(encode::Error::MessageTooLarge {
+            command: command.to_string(),
+            size: len,
+            max,
+        });
+    }
+    let payload = encode::read_bytes(&mut d, len)?;
+
+    let actual = checksum(&payload);
+    if actual != expected {
+        return Err(encode::Error::InvalidChecksum { expected, actual });
+    }
+    Ok((magic, command, payload))
+}
+
+impl Encodable for RawNetworkMessage {
+    fn consensus_encode<S: io::Write>(&self, mut s: S) -> Result<usize, encode::Error> {
+        let payload = self.payload.serialize_payload();
+        let mut len = self.magic.consensus_encode(&mut s)?;
+        len += self.command().consensus_encode(&mut s)?;
+        len += (payload.len() as u32).consensus_encode(&mut s)?;
+        len += checksum(&payload).consensus_encode(&mut s)?;
+        s.emit_slice(&payload)?;
+        Ok(len + payload.len())
+    }
+}
+
+impl Decodable for RawNetworkMessage {
+    fn consensus_decode<D: io::Read>(mut d: D) -> Result<Self, encode::Error> {
+        let (magic, command, payload) =
+            read_message(&mut d, |c| default_max_payload_size(c.as_ref()))?;
+        let payload = trace_replay!("payload", payload.len(), decode_builtin(&command, payload)?);
+        Ok(RawNetworkMessage { magic, payload })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        default_max_payload_size, CommandRegistry, CommandString, NetworkMessage,
+        RawNetworkMessage, MAX_PAYLOAD_SIZE,
+    };
+
+    use std::borrow::Cow;
+    use std::net::{Ipv4Addr, SocketAddr};
+
+    use blockdata::script::Script;
+    use blockdata::transaction::{OutPoint, Transaction, TxIn, TxOut};
+    use consensus::encode::{self, deserialize, serialize, MAX_VEC_SIZE};
+    use error::ErrorCode;
+    use hashes::hex::FromHex;
+    use hashes::{sha256d, Hash};
+    use network::address::Address;
+    use network::constants::{Magic, Network, ServiceFlags};
+    use network::message_blockdata::Inventory;
+    use network::message_network::{Reject, RejectReason, UserAgent, VersionMessage};
+
+    #[test]
+    fn command_string_test() {
+        assert_eq!(
+            CommandString::try_from_str("getaddr").unwrap().as_ref(),
+            "getaddr"
+        );
+        assert!(CommandString::try_from_str("").is_ok());
+        assert!(CommandString::try_from_str("twelve-chars").is_ok());
+        assert!(CommandString::try_from_str("thirteenchars").is_err());
+        assert!(CommandString::try_from_str("tab\there").is_err());
+        assert!(CommandString::try_from_str(String::from("caf\u{e9}")).is_err());
+        assert_eq!(
+            CommandString::try_from_str("bad\0")
+                .unwrap_err()
+                .to_string(),
+            "the command string 'bad\\u{0}' is not at most 12 printable ASCII characters"
+        );
+
+        let cs = CommandString::try_from_str("ping").unwrap();
+        let bytes = Vec::from_hex("70696e670000000000000000").unwrap();
+        assert_eq!(serialize(&cs), bytes);
+        assert_eq!(deserialize::<CommandString>(&bytes).unwrap(), cs);
+
+        // Data after the padding
+        assert!(
+            deserialize::<CommandString>(&Vec::from_hex("70696e670000000000000001").unwrap())
+                .is_err()
+        );
+        // Non-printable command
+        assert!(
+            deserialize::<CommandString>(&Vec::from_hex("70691e670000000000000000").unwrap())
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn serialize_verack_test() {
+        let msg = RawNetworkMessage {
+            magic: Network::Bitcoin.magic(),
+            payload: NetworkMessage::Verack,
+        };
+        let bytes = Vec::from_hex("f9beb4d976657261636b000000000000000000005df6e0e2").unwrap();
+        assert_eq!(serialize(&msg), bytes);
+        assert_eq!(deserialize::<RawNetworkMessage>(&bytes).unwrap(), msg);
+        assert_eq!(msg.cmd(), "verack");
+    }
+
+    #[test]
+    fn serialize_ping_test() {
+        let msg = RawNetworkMessage {
+            magic: Network::Bitcoin.magic(),
+            payload: NetworkMessage::Ping(100),
+        };
+        let bytes =
+            Vec::from_hex("f9beb4d970696e670000000000000000080000002467f11d6400000000000000")
+                .unwrap();
+        assert_eq!(serialize(&msg), bytes);
+        assert_eq!(deserialize::<RawNetworkMessage>(&bytes).unwrap(), msg);
+
+        let mut bad_checksum = bytes.clone();
+        bad_checksum[20] ^= 1;
+        match deserialize::<RawNetworkMessage>(&bad_checksum) {
+            Err(encode::Error::InvalidChecksum { .. }) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn unknown_command_roundtrip_test() {
+        // An extension message on a chain `Network` does not know about
+        let bytes =
+            Vec::from_hex("0a03cf40736f6d65657874000000000004000000281dd50fdeadbeef").unwrap();
+        let msg: RawNetworkMessage = deserialize(&bytes).unwrap();
+        assert_eq!(msg.magic, Magic::from_bytes([0x0a, 0x03, 0xcf, 0x40]));
+        assert_eq!(msg.cmd(), "someext");
+        assert_eq!(
+            msg.payload,
+            NetworkMessage::Unknown {
+                command: CommandString::try_from_str("someext").unwrap(),
+                payload: vec![0xde, 0xad, 0xbe, 0xef],
+            }
+        );
+        assert_eq!(serialize(&msg), bytes);
+    }
+
+    #[test]
+    fn command_registry_test() {
+        fn decode_someext(
+            _: &CommandString,
+            payload: Vec<u8>,
+        ) -> Result<NetworkMessage, encode::Error> {
+            if payload.len() != 4 {
+                return Err(encode::Error::ParseFailed(
+                    "someext payload must be 4 bytes",
+                ));
+            }
+            Ok(NetworkMessage::Unknown {
+                command: CommandString::try_from_str("someext").unwrap(),
+                payload,
+            })
+        }
+
+        let ping =
+            Vec::from_hex("f9beb4d970696e670000000000000000080000002467f11d6400000000000000")
+                .unwrap();
+        let someext =
+            Vec::from_hex("f9beb4d9736f6d65657874000000000004000000281dd50fdeadbeef").unwrap();
+
+        let mut registry = CommandRegistry::default();
+        assert!(registry.is_registered(&CommandString::try_from_str("ping").unwrap()));
+        assert!(!registry.is_registered(&CommandString::try_from_str("someext").unwrap()));
+
+        assert!(registry
+            .register(
+                CommandString::try_from_str("someext").unwrap(),
+                decode_someext
+            )
+            .is_none());
+        assert!(RawNetworkMessage::consensus_decode_with(&someext[..], &registry).is_ok());
+        let mut truncated = someext.clone();
+        truncated.pop();
+        truncated[16] = 3;
+        truncated[20..24].copy_from_slice(&super::checksum(&[0xde, 0xad, 0xbe]));
+        assert!(RawNetworkMessage::consensus_decode_with(&truncated[..], &registry).is_err());
+
+        assert!(registry
+            .unregister(&CommandString::try_from_str("ping").unwrap())
+            .is_some());
+        let msg = RawNetworkMessage::consensus_decode_with(&ping[..], &registry).unwrap();
+        assert_eq!(
+            msg.payload,
+            NetworkMessage::Unknown {
+                command: CommandString::try_from_str("ping").unwrap(),
+                payload: vec![100, 0, 0, 0, 0, 0, 0, 0],
+            }
+        );
+        assert_eq!(serialize(&msg), ping);
+
+        let msg =
+            RawNetworkMessage::consensus_decode_with(&ping[..], &CommandRegistry::empty()).unwrap();
+        assert_eq!(msg.cmd(), "ping");
+        assert_eq!(
+            msg.payload.serialize_payload(),
+            vec![100, 0, 0, 0, 0, 0, 0, 0]
+        );
+    }
+
+    /// One message of every type, to be mangled by the malformed message
+    /// tests below.
+    fn message_corpus() -> Vec<RawNetworkMessage> {
+        let addr = Address::new(
+            &SocketAddr::new(Ipv4Addr::new(10, 0, 0, 1).into(), 8333),
+            ServiceFlags::NETWORK,
+        );
+        let tx = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Default::default(), 1),
+                script_sig: Script::new(),
+                sequence: 0xfffffffd,
+                witness: vec![vec![0x30; 71], vec![0x02; 33]],
+            }],
+            output: vec![TxOut {
+                value: 50_000,
+                script_pubkey: hex_script!("0014751e76e8199196d454941c45d1b3a323f1433bd6"),
+            }],
+        };
+        let payloads = vec![
+            NetworkMessage::Version(VersionMessage::new(
+                ServiceFlags::NETWORK,
+                1_600_000_000,
+                addr.clone(),
+                addr.clone(),
+                0x0123456789abcdef,
+                UserAgent::new(),
+                650_000,
+            )),
+            NetworkMessage::Verack,
+            NetworkMessage::Addr(vec![(1_600_000_000, addr); 2]),
+            NetworkMessage::GetAddr,
+            NetworkMessage::SendHeaders,
+            NetworkMessage::Inv(vec![
+                Inventory::Transaction(tx.txid()),
+                Inventory::Block(Default::default()),
+            ]),
+            NetworkMessage::GetData(vec![Inventory::WTx(tx.wtxid())]),
+            NetworkMessage::NotFound(vec![Inventory::WitnessTransaction(tx.txid())]),
+            NetworkMessage::Ping(100),
+            NetworkMessage::Pong(100),
+            NetworkMessage::Reject(Reject {
+                message: CommandString::try_from_str("tx").unwrap(),
+                ccode: RejectReason::Dust,
+                reason: Cow::Borrowed("dust"),
+                hash: sha256d::Hash::hash(&[1, 2, 3]),
+            }),
+            NetworkMessage::Tx(tx),
+            NetworkMessage::Unknown {
+                command: CommandString::try_from_str("someext").unwrap(),
+                payload: vec![0xde, 0xad, 0xbe, 0xef],
+            },
+        ];
+        payloads
+            .into_iter()
+            .map(|payload| RawNetworkMessage {
+                magic: Network::Bitcoin.magic(),
+                payload,
+            })
+            .collect()
+    }
+
+    /// Rewrites the length and checksum in the header of a serialized
+    /// message to match its payload, so that mangled payloads make it past
+    /// the header checks and into the payload decoders.
+    fn fix_header(bytes: &mut Vec<u8>) {
+        let len = bytes.len() as u32 - 24;
+        let checksum = super::checksum(&bytes[24..]);
+        bytes[16..20].copy_from_slice(&serialize(&len));
+        bytes[20..24].copy_from_slice(&checksum);
+    }
+
+    #[test]
+    fn truncated_messages_test() {
+        for msg in message_corpus() {
+            let bytes = serialize(&msg);
+            for len in 0..bytes.len() {
+                assert!(
+                    deserialize::<RawNetworkMessage>(&bytes[..len]).is_err(),
+                    "{} truncated to {} bytes",
+                    msg.cmd(),
+                    len
+                );
+                if len < 24 || msg.cmd() == "someext" {
+                    continue;
+                }
+                let mut truncated = bytes[..len].to_vec();
+                fix_header(&mut truncated);
+                assert!(
+                    deserialize::<RawNetworkMessage>(&truncated).is_err(),
+                    "{} payload truncated to {} bytes",
+                    msg.cmd(),
+                    len - 24
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn oversized_messages_test() {
+        fn raw_message(command: &str, payload: &[u8]) -> Vec<u8> {
+            let mut bytes = serialize(&Network::Bitcoin.magic());
+            bytes.extend(serialize(&CommandString::try_from_str(command.to_owned()).unwrap()));
+            bytes.extend(&[0; 8]);
+            bytes.extend(payload);
+            fix_header(&mut bytes);
+            bytes
+        }
+
+        // Payload length beyond the limit
+        let mut bytes = raw_message("someext", &[0; 8]);
+        bytes[16..20].copy_from_slice(&serialize(&(MAX_VEC_SIZE as u32 + 1)));
+        match deserialize::<RawNetworkMessage>(&bytes) {
+            Err(encode::Error::MessageTooLarge { ref command, size, max }) => {
+                assert_eq!(command, "someext");
+                assert_eq!(size, MAX_VEC_SIZE + 1);
+                assert_eq!(max, MAX_VEC_SIZE);
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        // A payload length within the limit, but far more than was sent
+        bytes[16..20].copy_from_slice(&serialize(&(MAX_VEC_SIZE as u32)));
+        match deserialize::<RawNetworkMessage>(&bytes) {
+            Err(encode::Error::Io(_)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let oversized = [
+            // A million inputs
+            "02000000fe40420f00",
+            // 65535 inputs, which are not there
+            "02000000fdffff",
+            // A script_sig of 3999999 bytes, which is not there
+            "02000000010000000000000000000000000000000000000000000000000000000000000000ffffffffff3f0900",
+            // An input count beyond usize
+            "02000000ffffffffffffffffff",
+        ];
+        for payload in oversized.iter() {
+            let bytes = raw_message("tx", &Vec::from_hex(payload).unwrap());
+            assert!(deserialize::<RawNetworkMessage>(&bytes).is_err(), "{}", payload);
+        }
+
+        // More addresses than an addr message may carry, which is too large
+        // for one, or fails to parse if the limit is raised
+        let mut payload = vec![0xfd, 0xe9, 0x03];
+        payload.extend(vec![0; 1001 * 30]);
+        match deserialize::<RawNetworkMessage>(&raw_message("addr", &payload)) {
+            Err(encode::Error::MessageTooLarge { .. }) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        let mut registry = CommandRegistry::default();
+        let addr = CommandString::try_from_str("addr").unwrap();
+        registry.set_max_payload_size(addr, MAX_VEC_SIZE);
+        let bytes = raw_message("addr", &payload);
+        match RawNetworkMessage::consensus_decode_with(&bytes[..], &registry) {
+            Err(encode::Error::ParseFailed(_)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        payload[1] = 0xe8;
+        payload.truncate(3 + 1000 * 30);
+        assert!(deserialize::<RawNetworkMessage>(&raw_message("addr", &payload)).is_ok());
+
+        // A user agent of 3999999 bytes, which is not there
+        let mut version = serialize(&message_corpus()[0]);
+        let ua_offset = 24 + 4 + 8 + 8 + 26 + 26 + 8;
+        assert_eq!(version[ua_offset] as usize, UserAgent::new().to_string().len());
+        version.splice(ua_offset..ua_offset + 1, vec![0xfe, 0xff, 0x08, 0x3d, 0x00]);
+        fix_header(&mut version);
+        match deserialize::<RawNetworkMessage>(&version) {
+            Err(encode::Error::Io(_)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn payload_size_limits_test() {
+        // Every message of the corpus fits the limit for its command
+        for msg in message_corpus() {
+            let len = msg.payload.serialize_payload().len();
+            assert!(len <= default_max_payload_size(msg.cmd()), "{}", msg.cmd());
+        }
+        assert_eq!(default_max_payload_size("verack"), 0);
+        assert_eq!(default_max_payload_size("addr"), 30_003);
+        assert_eq!(default_max_payload_size("headers"), 162_003);
+        assert_eq!(default_max_payload_size("block"), MAX_PAYLOAD_SIZE);
+
+        // A ping with a payload beyond its eight bytes is rejected before
+        // any of it is read
+        let mut bytes = serialize(&RawNetworkMessage {
+            magic: Network::Bitcoin.magic(),
+            payload: NetworkMessage::Ping(100),
+        });
+        bytes[16..20].copy_from_slice(&serialize(&9u32));
+        let err = deserialize::<RawNetworkMessage>(&bytes).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "ping message of 9 bytes exceeds the limit of 8 bytes"
         );
-        Ok(CommandString(rv))
+        assert_eq!(err.code(), 1_012);
+
+        // Registries can set their own limits
+        let ping = CommandString::try_from_str("ping").unwrap();
+        let mut registry = CommandRegistry::default();
+        assert_eq!(registry.max_payload_size(&ping), 8);
+        registry.set_max_payload_size(ping.clone(), 0);
+        assert_eq!(registry.max_payload_size(&ping), 0);
+        bytes[16..20].copy_from_slice(&serialize(&8u32));
+        assert!(deserialize::<RawNetworkMessage>(&bytes).is_ok());
+        match RawNetworkMessage::consensus_decode_with(&bytes[..], &registry) {
+            Err(encode::Error::MessageTooLarge { size: 8, max: 0, .. }) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn bit_flipped_messages_test() {
+        for msg in message_corpus() {
+            let bytes = serialize(&msg);
+            for bit in 0..bytes.len() * 8 {
+                let mut flipped = bytes.clone();
+                flipped[bit / 8] ^= 1 << (bit % 8);
+                let result = deserialize::<RawNetworkMessage>(&flipped);
+                match bit / 8 {
+                    // Magic, which is not checked here
+                    0...3 => assert!(result.is_ok()),
+                    // Command, which may still be a valid but different one
+                    4...15 => {
+                        if let Ok(decoded) = result {
+                            assert_ne!(decoded.cmd(), msg.cmd());
+                            match decoded.payload {
+                                NetworkMessage::Unknown { .. } => {}
+                                other => panic!("unexpected payload: {:?}", other),
+                            }
+                        }
+                    }
+                    // Length and checksum
+                    16...23 => assert!(result.is_err()),
+                    // Payload, which no longer matches the checksum
+                    _ => match result {
+                        Err(encode::Error::InvalidChecksum { .. }) => {}
+                        other => panic!("unexpected result: {:?}", other),
+                    },
+                }
+
+                // Past the checksum, the payload decoders must cope with
+                // anything without panicking
+                if bit >= 24 * 8 {
+                    fix_header(&mut flipped);
+                    if let Ok(decoded) = deserialize::<RawNetworkMessage>(&flipped) {
+                        assert_eq!(decoded.cmd(), msg.cmd());
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,143 @@
+//! Blockdata network messages
+//!
+//! This module describes network messages which are used for passing
+//! Bitcoin data (blocks and transactions) around.
+
+use std::io;
+
+use consensus::encode::{self, Decodable, Encodable};
+use hash_types::{BlockHash, Txid, Wtxid};
+use hashes::sha256d;
+
+/// An entry of an `inv`, `getdata` or `notfound` message
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Hash)]
+pub enum Inventory {
+    /// Error --- these inventories can be ignored
+    Error,
+    /// Transaction
+    Transaction(Txid),
+    /// Block
+    Block(BlockHash),
+    /// A block as a `merkleblock` message, per BIP37
+    FilteredBlock(BlockHash),
+    /// A block as a `cmpctblock` message, per BIP152
+    CompactBlock(BlockHash),
+    /// Transaction by its witness txid, per BIP339
+    WTx(Wtxid),
+    /// Witness transaction, per BIP144
+    WitnessTransaction(Txid),
+    /// Witness block, per BIP144
+    WitnessBlock(BlockHash),
+}
+
+impl Inventory {
+    /// The flag set on the types of inventories asking for witness data
+    pub const WITNESS_FLAG: u32 = 1 << 30;
+
+    /// The type of the inventory, as encoded
+    pub fn inv_type(&self) -> u32 {
+        match *self {
+            Inventory::Error => 0,
+            Inventory::Transaction(..) => 1,
+            Inventory::Block(..) => 2,
+            Inventory::FilteredBlock(..) => 3,
+            Inventory::CompactBlock(..) => 4,
+            Inventory::WTx(..) => 5,
+            Inventory::WitnessTransaction(..) => Inventory::WITNESS_FLAG | 1,
+            Inventory::WitnessBlock(..) => Inventory::WITNESS_FLAG | 2,
+        }
+    }
+
+    /// The hash of the inventory, all zeroes for `Inventory::Error`
+    pub fn hash(&self) -> sha256d::Hash {
+        match *self {
+            Inventory::Error => sha256d::Hash::default(),
+            Inventory::Transaction(ref txid) | Inventory::WitnessTransaction(ref txid) => {
+                txid.as_hash()
+            }
+            Inventory::Block(ref hash)
+            | Inventory::FilteredBlock(ref hash)
+            | Inventory::CompactBlock(ref hash)
+            | Inventory::WitnessBlock(ref hash) => hash.as_hash(),
+            Inventory::WTx(ref wtxid) => wtxid.as_hash(),
+        }
+    }
+}
+
+impl Encodable for Inventory {
+    #[inline]
+    fn consensus_encode<S: io::Write>(&self, mut s: S) -> Result<usize, encode::Error> {
+        let len = self.inv_type().consensus_encode(&mut s)?;
+        Ok(len + self.hash().consensus_encode(&mut s)?)
+    }
+}
+
+impl Decodable for Inventory {
+    #[inline]
+    fn consensus_decode<D: io::Read>(mut d: D) -> Result<Self, encode::Error> {
+        let inv_type = u32::consensus_decode(&mut d)?;
+        let hash = sha256d::Hash::consensus_decode(&mut d)?;
+        Ok(match inv_type {
+            0 => Inventory::Error,
+            1 => Inventory::Transaction(Txid::from_hash(hash)),
+            2 => Inventory::Block(BlockHash::from_hash(hash)),
+            3 => Inventory::FilteredBlock(BlockHash::from_hash(hash)),
+            4 => Inventory::CompactBlock(BlockHash::from_hash(hash)),
+            5 => Inventory::WTx(Wtxid::from_hash(hash)),
+            t if t == Inventory::WITNESS_FLAG | 1 => {
+                Inventory::WitnessTransaction(Txid::from_hash(hash))
+            }
+            t if t == Inventory::WITNESS_FLAG | 2 => {
+                Inventory::WitnessBlock(BlockHash::from_hash(hash))
+            }
+            t => return Err(encode::Error::UnknownInventoryType(t)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use consensus::encode::{deserialize, serialize};
+    use hashes::hex::FromHex;
+    use hashes::Hash;
+
+    #[test]
+    fn inventory() {
+        let hash = sha256d::Hash::hash(b"inventory");
+        let invs = [
+            Inventory::Error,
+            Inventory::Transaction(Txid::from_hash(hash)),
+            Inventory::Block(BlockHash::from_hash(hash)),
+            Inventory::FilteredBlock(BlockHash::from_hash(hash)),
+            Inventory::CompactBlock(BlockHash::from_hash(hash)),
+            Inventory::WTx(Wtxid::from_hash(hash)),
+            Inventory::WitnessTransaction(Txid::from_hash(hash)),
+            Inventory::WitnessBlock(BlockHash::from_hash(hash)),
+        ];
+        for inv in invs.iter().skip(1) {
+            let bytes = serialize(inv);
+            assert_eq!(bytes.len(), 36);
+            assert_eq!(&bytes[4..], &hash[..]);
+            assert_eq!(deserialize::<Inventory>(&bytes).unwrap(), *inv);
+        }
+        assert_eq!(serialize(&Inventory::Error), vec![0; 36]);
+        assert_eq!(
+            deserialize::<Inventory>(&[0; 36]).unwrap(),
+            Inventory::Error
+        );
+
+        let bytes = serialize(&invs[6]);
+        assert_eq!(&bytes[..4], &[0x01, 0x00, 0x00, 0x40]);
+        let list = serialize(&invs.to_vec());
+        assert_eq!(list[0], 8);
+        assert_eq!(deserialize::<Vec<Inventory>>(&list).unwrap(), invs.to_vec());
+
+        let unknown = Vec::from_hex(&format!("06000000{}", "00".repeat(32))).unwrap();
+        match deserialize::<Inventory>(&unknown) {
+            Err(encode::Error::UnknownInventoryType(6)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
@@ -4,10 +4,11 @@
 //! their capabilities
 
 use std::borrow::Cow;
-use std::io;
+use std::{error, fmt, io};
 
 use consensus::encode;
 use consensus::{Decodable, Encodable, ReadExt};
+use error::{ErrorCode, ErrorKind};
 use hashes::sha256d;
 use network::address::Address;
 use network::constants::{self, ServiceFlags};
@@ -48,7 +49,7 @@ impl VersionMessage {
         receiver: Address,
         sender: Address,
         nonce: u64,
-        user_agent: String,
+        user_agent: UserAgent,
         start_height: i32,
     ) -> VersionMessage {
         VersionMessage {
@@ -58,7 +59,7 @@ impl VersionMessage {
             receiver,
             sender,
             nonce,
-            user_agent,
+            user_agent: user_agent.to_string(),
             start_height,
             relay: false,
         }
@@ -78,6 +79,142 @@ impl_consensus_encoding!(
     relay
 );
 
+/// Maximum length of a user agent, as enforced by Bitcoin Core
+pub const MAX_USER_AGENT_LENGTH: usize = 256;
+
+/// An error building a `UserAgent`
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum UserAgentError {
+    /// A name or version was empty
+    Empty,
+    /// A name, version or comment contained a character reserved by BIP14,
+    /// or one which is not printable ASCII
+    InvalidChar(char),
+    /// The user agent would be longer than `MAX_USER_AGENT_LENGTH`
+    TooLong,
+}
+
+impl fmt::Display for UserAgentError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            UserAgentError::InvalidChar(c) => write!(f, "invalid user agent character {:?}", c),
+            UserAgentError::Empty | UserAgentError::TooLong => {
+                f.write_str(error::Error::description(self))
+            }
+        }
+    }
+}
+
+impl error::Error for UserAgentError {
+    fn cause(&self) -> Option<&error::Error> {
+        None
+    }
+
+    fn description(&self) -> &str {
+        match *self {
+            UserAgentError::Empty => "empty user agent name or version",
+            UserAgentError::InvalidChar(..) => "invalid user agent character",
+            UserAgentError::TooLong => "user agent too long",
+        }
+    }
+}
+
+impl ErrorCode for UserAgentError {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::UserAgent
+    }
+
+    fn number(&self) -> u32 {
+        match *self {
+            UserAgentError::Empty => 1,
+            UserAgentError::InvalidChar(..) => 2,
+            UserAgentError::TooLong => 3,
+        }
+    }
+}
+
+/// A user agent in the format of BIP14, such as
+/// `/learn-bitcoin-rs:0.1.0/MyWallet:2.0(Linux; beta)/`.
+///
+/// It always starts with this library's name and version, and each client
+/// embedding it adds itself after the ones it is built on.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct UserAgent {
+    /// Name, version and comments of each client
+    clients: Vec<(String, String, Vec<String>)>,
+}
+
+impl UserAgent {
+    /// Creates a user agent naming only this library
+    pub fn new() -> UserAgent {
+        UserAgent {
+            clients: vec![(
+                env!("CARGO_PKG_NAME").to_owned(),
+                env!("CARGO_PKG_VERSION").to_owned(),
+                vec![],
+            )],
+        }
+    }
+
+    /// Adds a client built on top of the ones already named
+    pub fn push(mut self, name: &str, version: &str) -> Result<UserAgent, UserAgentError> {
+        if name.is_empty() || version.is_empty() {
+            return Err(UserAgentError::Empty);
+        }
+        check_user_agent_chars(name, "/:()")?;
+        check_user_agent_chars(version, "/:()")?;
+        self.clients.push((name.to_owned(), version.to_owned(), vec![]));
+        self.check_length()
+    }
+
+    /// Adds a comment, such as a platform, to the last client added
+    pub fn comment(mut self, comment: &str) -> Result<UserAgent, UserAgentError> {
+        check_user_agent_chars(comment, "/:();")?;
+        self.clients
+            .last_mut()
+            .expect("a user agent always names this library")
+            .2
+            .push(comment.to_owned());
+        self.check_length()
+    }
+
+    fn check_length(self) -> Result<UserAgent, UserAgentError> {
+        if self.to_string().len() > MAX_USER_AGENT_LENGTH {
+            Err(UserAgentError::TooLong)
+        } else {
+            Ok(self)
+        }
+    }
+}
+
+impl Default for UserAgent {
+    fn default() -> UserAgent {
+        UserAgent::new()
+    }
+}
+
+impl fmt::Display for UserAgent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("/")?;
+        for &(ref name, ref version, ref comments) in self.clients.iter() {
+            write!(f, "{}:{}", name, version)?;
+            if !comments.is_empty() {
+                write!(f, "({})", comments.join("; "))?;
+            }
+            f.write_str("/")?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks that `s` is printable ASCII without any of `reserved`
+fn check_user_agent_chars(s: &str, reserved: &str) -> Result<(), UserAgentError> {
+    match s.chars().find(|&c| c < ' ' || c > '~' || reserved.contains(c)) {
+        Some(c) => Err(UserAgentError::InvalidChar(c)),
+        None => Ok(()),
+    }
+}
+
 #[derive(PartialEq, Eq, Clone, Copy, Debug)]
 /// message rejection reason as a code
 pub enum RejectReason {
@@ -139,7 +276,7 @@ impl_consensus_encoding!(Reject, message, ccode, reason, hash);
 
 #[cfg(test)]
 mod tests {
-    use super::VersionMessage;
+    use super::{UserAgent, UserAgentError, VersionMessage};
 
     use hashes::hex::{FromHex, ToHex};
     use network::constants::ServiceFlags;
@@ -165,4 +302,40 @@ mod tests {
 
         assert_eq!(serialize(&real_decode), from_sat);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn user_agent_test() {
+        let base = format!("/learn-bitcoin-rs:{}/", env!("CARGO_PKG_VERSION"));
+        assert_eq!(UserAgent::new().to_string(), base);
+
+        let ua = UserAgent::new()
+            .push("MyWallet", "2.0")
+            .unwrap()
+            .comment("Linux")
+            .unwrap()
+            .comment("beta build")
+            .unwrap()
+            .push("Plugin", "0.1-rc1")
+            .unwrap();
+        assert_eq!(
+            ua.to_string(),
+            format!("{}MyWallet:2.0(Linux; beta build)/Plugin:0.1-rc1/", base)
+        );
+
+        let err = |c| Err(UserAgentError::InvalidChar(c));
+        assert_eq!(UserAgent::new().push("", "1.0"), Err(UserAgentError::Empty));
+        assert_eq!(UserAgent::new().push("My/Wallet", "1.0"), err('/'));
+        assert_eq!(UserAgent::new().push("MyWallet", "1:0"), err(':'));
+        assert_eq!(UserAgent::new().push("MyWallet", "1.0\n"), err('\n'));
+        assert_eq!(UserAgent::new().push("Caf\u{e9}", "1.0"), err('\u{e9}'));
+        assert_eq!(UserAgent::new().comment("a; b"), err(';'));
+        assert_eq!(UserAgent::new().comment("(nested)"), err('('));
+
+        let long = "x".repeat(200);
+        assert!(UserAgent::new().push(&long, "1").is_ok());
+        assert_eq!(
+            UserAgent::new().push(&long, &long),
+            Err(UserAgentError::TooLong)
+        );
+    }
+}
@@ -7,9 +7,23 @@ use std::error;
 use std::fmt;
 use std::io;
 
+use error::{ErrorCode, ErrorKind};
+
 pub mod address;
+pub mod capture;
+#[cfg(feature = "experimental-header-compression")]
+pub mod compressedheaders;
+pub mod config;
 pub mod constants;
+pub mod dialer;
+pub mod discovery;
+pub mod getdata;
+pub mod headersync;
+pub mod message_blockdata;
 pub mod message_network;
+pub mod nonce;
+pub mod params;
+pub mod trusted;
 pub use self::address::Address;
 pub mod message;
 
@@ -22,15 +36,39 @@ pub enum Error {
     SocketMutexPoisoned,
     /// Not connected to peer
     SocketNotConnectedToPeer,
+    /// No dialer can reach the target, such as a `.onion` host without a proxy
+    UnroutableTarget,
+    /// The SOCKS5 proxy did not follow the protocol, or asked for
+    /// authentication
+    Socks5Protocol(&'static str),
+    /// The SOCKS5 proxy could not connect to the target, with the reply code
+    Socks5Refused(u8),
+    /// The peer sent back the nonce of one of our own version messages, so
+    /// we connected to ourselves
+    SelfConnection,
+    /// An `addr` message carried more than `MAX_ADDR_TO_SEND` addresses
+    TooManyAddresses(usize),
+    /// The peer is not one connections are restricted to
+    UntrustedPeer(dialer::Target),
+    /// A trusted peer did not announce these of its expected services
+    MissingServices(constants::ServiceFlags),
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             Error::Io(ref e) => fmt::Display::fmt(e, f),
-            Error::SocketMutexPoisoned | Error::SocketNotConnectedToPeer => {
-                f.write_str(error::Error::description(self))
+            Error::Socks5Protocol(s) => write!(f, "SOCKS5 protocol error: {}", s),
+            Error::TooManyAddresses(n) => write!(f, "too many addresses in addr message: {}", n),
+            Error::UntrustedPeer(ref target) => write!(f, "untrusted peer: {:?}", target),
+            Error::MissingServices(services) => write!(f, "peer is missing services: {}", services),
+            Error::Socks5Refused(code) => {
+                write!(f, "SOCKS5 proxy refused connection: code {}", code)
             }
+            Error::SocketMutexPoisoned
+            | Error::SocketNotConnectedToPeer
+            | Error::UnroutableTarget
+            | Error::SelfConnection => f.write_str(error::Error::description(self)),
         }
     }
 }
@@ -48,13 +86,49 @@ impl error::Error for Error {
             Error::Io(ref e) => e.description(),
             Error::SocketMutexPoisoned => "socket mutex was poisoned",
             Error::SocketNotConnectedToPeer => "not connected to peer",
+            Error::UnroutableTarget => "no dialer can reach the target",
+            Error::Socks5Protocol(..) => "SOCKS5 protocol error",
+            Error::Socks5Refused(..) => "SOCKS5 proxy refused connection",
+            Error::SelfConnection => "connected to ourselves",
+            Error::TooManyAddresses(..) => "too many addresses in addr message",
+            Error::UntrustedPeer(..) => "untrusted peer",
+            Error::MissingServices(..) => "peer is missing services",
         }
     }
 
     fn cause(&self) -> Option<&error::Error> {
         match *self {
             Error::Io(ref e) => Some(e),
-            Error::SocketMutexPoisoned | Error::SocketNotConnectedToPeer => None,
+            Error::SocketMutexPoisoned
+            | Error::SocketNotConnectedToPeer
+            | Error::UnroutableTarget
+            | Error::Socks5Protocol(..)
+            | Error::Socks5Refused(..)
+            | Error::SelfConnection
+            | Error::TooManyAddresses(..)
+            | Error::UntrustedPeer(..)
+            | Error::MissingServices(..) => None,
+        }
+    }
+}
+
+impl ErrorCode for Error {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Network
+    }
+
+    fn number(&self) -> u32 {
+        match *self {
+            Error::Io(..) => 1,
+            Error::SocketMutexPoisoned => 2,
+            Error::SocketNotConnectedToPeer => 3,
+            Error::UnroutableTarget => 4,
+            Error::Socks5Protocol(..) => 5,
+            Error::Socks5Refused(..) => 6,
+            Error::SelfConnection => 7,
+            Error::TooManyAddresses(..) => 8,
+            Error::UntrustedPeer(..) => 9,
+            Error::MissingServices(..) => 10,
         }
     }
 }
@@ -0,0 +1,189 @@
+//! Version nonces
+//!
+//! Every `version` message carries a random nonce. By remembering the
+//! nonces of the `version` messages we sent, an incoming `version` which
+//! carries one of them reveals that we have connected to ourselves, and
+//! the connection should be dropped.
+
+use std::collections::{HashSet, VecDeque};
+
+use network::address::Address;
+use network::constants::ServiceFlags;
+use network::message_network::{UserAgent, VersionMessage};
+use network::Error;
+use util::rng::{DefaultRng, Rng};
+
+/// Number of nonces remembered by `NonceManager::default()`, comfortably
+/// more than the number of connections a node keeps open at once
+pub const DEFAULT_NONCE_CAPACITY: usize = 1024;
+
+/// Hands out nonces for `version` messages and recognizes them when they
+/// come back
+#[derive(Clone, Debug)]
+pub struct NonceManager<R = DefaultRng> {
+    /// Nonces handed out, oldest first
+    recent: VecDeque<u64>,
+    /// The same nonces, for lookup
+    known: HashSet<u64>,
+    capacity: usize,
+    rng: R,
+}
+
+impl NonceManager {
+    /// Creates a manager which remembers the last `capacity` nonces
+    pub fn new(capacity: usize) -> NonceManager {
+        NonceManager::with_rng(capacity, DefaultRng::new())
+    }
+}
+
+impl<R: Rng> NonceManager<R> {
+    /// Creates a manager which remembers the last `capacity` nonces and
+    /// draws them from `rng`
+    pub fn with_rng(capacity: usize, rng: R) -> NonceManager<R> {
+        NonceManager {
+            recent: VecDeque::with_capacity(capacity),
+            known: HashSet::with_capacity(capacity),
+            capacity,
+            rng,
+        }
+    }
+
+    /// Generates a fresh nonce and remembers it
+    pub fn generate(&mut self) -> u64 {
+        let nonce = loop {
+            let nonce = self.rng.next_u64();
+            if nonce != 0 && !self.known.contains(&nonce) {
+                break nonce;
+            }
+        };
+        if self.capacity == 0 {
+            return nonce;
+        }
+        if self.recent.len() == self.capacity {
+            let oldest = self.recent.pop_front().expect("capacity is non-zero");
+            self.known.remove(&oldest);
+        }
+        self.recent.push_back(nonce);
+        self.known.insert(nonce);
+        nonce
+    }
+
+    /// Makes a new version message with a fresh nonce
+    pub fn version_message(
+        &mut self,
+        services: ServiceFlags,
+        timestamp: i64,
+        receiver: Address,
+        sender: Address,
+        user_agent: UserAgent,
+        start_height: i32,
+    ) -> VersionMessage {
+        let nonce = self.generate();
+        VersionMessage::new(
+            services,
+            timestamp,
+            receiver,
+            sender,
+            nonce,
+            user_agent,
+            start_height,
+        )
+    }
+
+    /// Whether `nonce` is one we handed out recently
+    pub fn is_own(&self, nonce: u64) -> bool {
+        self.known.contains(&nonce)
+    }
+
+    /// Checks a version message received from a peer, returning
+    /// `Error::SelfConnection` if it is one of ours, in which case the
+    /// connection should be closed
+    pub fn check_incoming(&self, version: &VersionMessage) -> Result<(), Error> {
+        if self.is_own(version.nonce) {
+            Err(Error::SelfConnection)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Forgets `nonce`, such as once its connection is closed
+    pub fn forget(&mut self, nonce: u64) {
+        if self.known.remove(&nonce) {
+            self.recent.retain(|&n| n != nonce);
+        }
+    }
+}
+
+impl Default for NonceManager {
+    fn default() -> NonceManager {
+        NonceManager::new(DEFAULT_NONCE_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::{Ipv4Addr, SocketAddr};
+
+    use util::rng::SeededRng;
+
+    #[test]
+    fn nonce_manager() {
+        let mut manager = NonceManager::new(3);
+        let nonces: Vec<u64> = (0..4).map(|_| manager.generate()).collect();
+        assert!(nonces.iter().all(|&n| n != 0));
+        assert_eq!(nonces.iter().collect::<HashSet<_>>().len(), 4);
+
+        // Only the last three are remembered
+        assert!(!manager.is_own(nonces[0]));
+        assert!(nonces[1..].iter().all(|&n| manager.is_own(n)));
+
+        manager.forget(nonces[2]);
+        assert!(!manager.is_own(nonces[2]));
+        assert!(manager.is_own(nonces[1]));
+        let next = manager.generate();
+        assert!(manager.is_own(nonces[1]) && manager.is_own(next));
+
+        // Without capacity nothing is remembered
+        let mut manager = NonceManager::new(0);
+        let nonce = manager.generate();
+        assert!(!manager.is_own(nonce));
+    }
+
+    #[test]
+    fn seeded_rng() {
+        let mut a = NonceManager::with_rng(1, SeededRng::new(3));
+        let mut b = NonceManager::with_rng(1, SeededRng::new(3));
+        assert_eq!(a.generate(), b.generate());
+        let mut rng = SeededRng::new(3);
+        rng.next_u64();
+        assert_eq!(a.generate(), rng.next_u64());
+    }
+
+    #[test]
+    fn self_connection() {
+        let addr = Address::new(
+            &SocketAddr::new(Ipv4Addr::new(127, 0, 0, 1).into(), 8333),
+            ServiceFlags::NONE,
+        );
+        let mut manager = NonceManager::default();
+        let ours = manager.version_message(
+            ServiceFlags::NONE,
+            0,
+            addr.clone(),
+            addr,
+            UserAgent::new(),
+            0,
+        );
+        assert!(manager.is_own(ours.nonce));
+
+        let mut theirs = ours.clone();
+        theirs.nonce = ours.nonce.wrapping_add(1);
+        assert!(manager.check_incoming(&theirs).is_ok());
+        match manager.check_incoming(&ours) {
+            Err(Error::SelfConnection) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
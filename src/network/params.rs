@@ -0,0 +1,179 @@
+//! Network Parameters
+//!
+//! The prefixes which tell the addresses of one chain from those of
+//! another, along with the chain's network magic. Addresses are encoded and
+//! parsed through `NetworkParams` rather than by matching on [`Network`],
+//! so forks and private chains can use them with their own prefixes: a
+//! `NetworkRegistry` holds the parameters of Bitcoin's networks by default,
+//! and more can be registered at runtime.
+//!
+//! [`Network`]: ../constants/enum.Network.html
+
+use network::constants::{Magic, Network};
+
+/// The parameters of a chain
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct NetworkParams {
+    /// The magic bytes starting every network message
+    pub magic: Magic,
+    /// The base58 version byte of P2PKH addresses
+    pub p2pkh_prefix: u8,
+    /// The base58 version byte of P2SH addresses
+    pub p2sh_prefix: u8,
+    /// The bech32 human-readable part of segwit addresses
+    pub bech32_hrp: &'static str,
+    /// The bech32 human-readable part of BIP352 silent payment addresses
+    pub silent_payment_hrp: &'static str,
+}
+
+impl NetworkParams {
+    /// Bitcoin mainnet
+    pub const BITCOIN: NetworkParams = NetworkParams {
+        magic: Magic::BITCOIN,
+        p2pkh_prefix: 0,
+        p2sh_prefix: 5,
+        bech32_hrp: "bc",
+        silent_payment_hrp: "sp",
+    };
+
+    /// Bitcoin's testnet
+    pub const TESTNET: NetworkParams = NetworkParams {
+        magic: Magic::TESTNET,
+        p2pkh_prefix: 111,
+        p2sh_prefix: 196,
+        bech32_hrp: "tb",
+        silent_payment_hrp: "tsp",
+    };
+
+    /// Bitcoin's regtest, which shares the base58 prefixes of testnet
+    pub const REGTEST: NetworkParams = NetworkParams {
+        magic: Magic::REGTEST,
+        p2pkh_prefix: 111,
+        p2sh_prefix: 196,
+        bech32_hrp: "bcrt",
+        silent_payment_hrp: "sprt",
+    };
+}
+
+/// The chains whose addresses can be parsed.
+///
+/// Chains may share prefixes, as testnet and regtest share their base58
+/// ones. Lookups return the chain registered first, so a regtest base58
+/// address is found as a testnet one.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct NetworkRegistry {
+    params: Vec<NetworkParams>,
+}
+
+impl Default for NetworkRegistry {
+    /// A registry of mainnet, testnet and regtest, in that order
+    fn default() -> NetworkRegistry {
+        NetworkRegistry {
+            params: Network::ALL
+                .iter()
+                .map(|network| *network.params())
+                .collect(),
+        }
+    }
+}
+
+impl NetworkRegistry {
+    /// Creates a registry without any chains
+    pub fn new() -> NetworkRegistry {
+        NetworkRegistry { params: vec![] }
+    }
+
+    /// Adds a chain, which loses to chains registered before it where
+    /// their prefixes are the same
+    pub fn register(&mut self, params: NetworkParams) {
+        self.params.push(params);
+    }
+
+    /// The registered chains, in the order they were registered
+    pub fn iter(&self) -> ::std::slice::Iter<'_, NetworkParams> {
+        self.params.iter()
+    }
+
+    /// The chain with the network magic `magic`
+    pub fn by_magic(&self, magic: Magic) -> Option<&NetworkParams> {
+        self.params.iter().find(|p| p.magic == magic)
+    }
+
+    /// The chain whose segwit addresses have the human-readable part
+    /// `hrp`, in lower or upper case
+    pub fn by_bech32_hrp(&self, hrp: &str) -> Option<&NetworkParams> {
+        self.params.iter().find(|p| same_hrp(p.bech32_hrp, hrp))
+    }
+
+    /// The chain whose silent payment addresses have the human-readable
+    /// part `hrp`, in lower or upper case
+    pub fn by_silent_payment_hrp(&self, hrp: &str) -> Option<&NetworkParams> {
+        self.params
+            .iter()
+            .find(|p| same_hrp(p.silent_payment_hrp, hrp))
+    }
+
+    /// The chain with a P2PKH or P2SH version byte of `prefix`
+    pub fn by_base58_prefix(&self, prefix: u8) -> Option<&NetworkParams> {
+        self.params
+            .iter()
+            .find(|p| p.p2pkh_prefix == prefix || p.p2sh_prefix == prefix)
+    }
+}
+
+/// Whether `hrp` is `expected` in lower or upper case, but not mixed case,
+/// which bech32 does not allow
+fn same_hrp(expected: &str, hrp: &str) -> bool {
+    hrp == expected || hrp == expected.to_uppercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registry() {
+        let mut registry = NetworkRegistry::default();
+        assert_eq!(registry.iter().count(), 3);
+        assert_eq!(registry.by_bech32_hrp("bc"), Some(&NetworkParams::BITCOIN));
+        assert_eq!(
+            registry.by_bech32_hrp("BCRT"),
+            Some(&NetworkParams::REGTEST)
+        );
+        assert_eq!(registry.by_bech32_hrp("Bc"), None);
+        assert_eq!(
+            registry.by_silent_payment_hrp("tsp"),
+            Some(&NetworkParams::TESTNET)
+        );
+        assert_eq!(registry.by_base58_prefix(5), Some(&NetworkParams::BITCOIN));
+        assert_eq!(
+            registry.by_base58_prefix(111),
+            Some(&NetworkParams::TESTNET)
+        );
+        assert_eq!(
+            registry.by_magic(Magic::REGTEST),
+            Some(&NetworkParams::REGTEST)
+        );
+        assert_eq!(registry.by_base58_prefix(48), None);
+
+        let litecoin = NetworkParams {
+            magic: Magic::from(0xdbb6c0fb),
+            p2pkh_prefix: 48,
+            p2sh_prefix: 50,
+            bech32_hrp: "ltc",
+            silent_payment_hrp: "ltcsp",
+        };
+        registry.register(litecoin);
+        assert_eq!(registry.by_base58_prefix(48), Some(&litecoin));
+        assert_eq!(registry.by_bech32_hrp("ltc"), Some(&litecoin));
+
+        // Earlier chains win
+        let shadow = NetworkParams {
+            bech32_hrp: "bc",
+            ..litecoin
+        };
+        registry.register(shadow);
+        assert_eq!(registry.by_bech32_hrp("bc"), Some(&NetworkParams::BITCOIN));
+        assert!(NetworkRegistry::new().by_bech32_hrp("bc").is_none());
+    }
+}
@@ -0,0 +1,196 @@
+//! Trusted peers
+//!
+//! A wallet backed by the user's own nodes should not talk to anyone else.
+//! `TrustedPeers` lists those nodes with the services each is expected to
+//! offer: a `TrustedDialer` refuses to open connections to anything not on
+//! the list, and `check_version` fails the handshake of a listed node whose
+//! `version` message lacks the expected services, such as a pruned node
+//! where an archive node was configured.
+//!
+//! Addresses are all the peers are identified by, so a connection should
+//! reach them over a network the user trusts, such as a LAN, a VPN or Tor
+//! hidden services, which authenticate the host through the `.onion`
+//! address itself. BIP324 does not authenticate peers, so there is no
+//! identity key to pin.
+
+use std::net::TcpStream;
+
+use network::constants::ServiceFlags;
+use network::dialer::{Dialer, Target};
+use network::message_network::VersionMessage;
+use network::Error;
+
+/// A node connections are allowed to
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct TrustedPeer {
+    /// Where the node is reached
+    pub target: Target,
+    /// The services the node has to announce, among any others
+    pub services: ServiceFlags,
+}
+
+impl TrustedPeer {
+    /// A node at `target` which is expected to offer `services`
+    pub fn new<T: Into<Target>>(target: T, services: ServiceFlags) -> TrustedPeer {
+        TrustedPeer {
+            target: target.into(),
+            services,
+        }
+    }
+}
+
+/// The nodes connections are restricted to
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct TrustedPeers {
+    peers: Vec<TrustedPeer>,
+}
+
+impl TrustedPeers {
+    /// An empty list, which allows no connections
+    pub fn new() -> TrustedPeers {
+        TrustedPeers::default()
+    }
+
+    /// Allows connections to `peer` too, replacing any entry for its target
+    pub fn with_peer(mut self, peer: TrustedPeer) -> TrustedPeers {
+        self.insert(peer);
+        self
+    }
+
+    /// Allows connections to `peer`, replacing any entry for its target
+    pub fn insert(&mut self, peer: TrustedPeer) {
+        self.peers.retain(|p| p.target != peer.target);
+        self.peers.push(peer);
+    }
+
+    /// The entry for `target`, if it is trusted
+    pub fn get(&self, target: &Target) -> Option<&TrustedPeer> {
+        self.peers.iter().find(|p| p.target == *target)
+    }
+
+    /// Whether connections to `target` are allowed
+    pub fn is_trusted(&self, target: &Target) -> bool {
+        self.get(target).is_some()
+    }
+
+    /// The trusted nodes, in the order they were added
+    pub fn peers(&self) -> &[TrustedPeer] {
+        &self.peers
+    }
+
+    /// Checks the version message received from `target`, returning
+    /// `Error::UntrustedPeer` if it is not on the list and
+    /// `Error::MissingServices` with the services it lacks if it does not
+    /// announce those expected of it. Either way the connection should be
+    /// closed.
+    pub fn check_version(&self, target: &Target, version: &VersionMessage) -> Result<(), Error> {
+        let peer = self
+            .get(target)
+            .ok_or_else(|| Error::UntrustedPeer(target.clone()))?;
+        let missing = peer.services.as_u64() & !version.services.as_u64();
+        if missing != 0 {
+            return Err(Error::MissingServices(ServiceFlags::from(missing)));
+        }
+        Ok(())
+    }
+}
+
+/// Dials only trusted peers, through another dialer
+#[derive(Clone, Debug)]
+pub struct TrustedDialer<D> {
+    inner: D,
+    peers: TrustedPeers,
+}
+
+impl<D: Dialer> TrustedDialer<D> {
+    /// Create a dialer reaching `peers` through `inner`
+    pub fn new(inner: D, peers: TrustedPeers) -> TrustedDialer<D> {
+        TrustedDialer { inner, peers }
+    }
+
+    /// The peers this dialer reaches
+    pub fn peers(&self) -> &TrustedPeers {
+        &self.peers
+    }
+}
+
+impl<D: Dialer> Dialer for TrustedDialer<D> {
+    fn dial(&self, target: &Target) -> Result<TcpStream, Error> {
+        if !self.peers.is_trusted(target) {
+            return Err(Error::UntrustedPeer(target.clone()));
+        }
+        self.inner.dial(target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::{Ipv4Addr, SocketAddr, TcpListener};
+
+    use network::address::Address;
+    use network::dialer::DirectDialer;
+    use network::message_network::UserAgent;
+
+    fn version(services: ServiceFlags) -> VersionMessage {
+        let addr = Address::new(
+            &SocketAddr::new(Ipv4Addr::new(127, 0, 0, 1).into(), 8333),
+            ServiceFlags::NONE,
+        );
+        VersionMessage::new(services, 0, addr.clone(), addr, 1, UserAgent::new(), 0)
+    }
+
+    #[test]
+    fn check_version() {
+        let archive = Target::from(SocketAddr::new(Ipv4Addr::new(10, 0, 0, 2).into(), 8333));
+        let onion = Target::Host("expyuzz4wqqyqhjn.onion".to_string(), 8333);
+        let peers = TrustedPeers::new()
+            .with_peer(TrustedPeer::new(
+                archive.clone(),
+                ServiceFlags::NETWORK | ServiceFlags::WITNESS,
+            ))
+            .with_peer(TrustedPeer::new(onion.clone(), ServiceFlags::NONE));
+        assert_eq!(peers.peers().len(), 2);
+
+        let full = ServiceFlags::NETWORK | ServiceFlags::WITNESS | ServiceFlags::BLOOM;
+        assert!(peers.check_version(&archive, &version(full)).is_ok());
+        let pruned = ServiceFlags::NETWORK_LIMITED | ServiceFlags::WITNESS;
+        match peers.check_version(&archive, &version(pruned)) {
+            Err(Error::MissingServices(missing)) => assert_eq!(missing, ServiceFlags::NETWORK),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(peers.check_version(&onion, &version(pruned)).is_ok());
+
+        let other = Target::Host("seed.bitcoin.sipa.be".to_string(), 8333);
+        match peers.check_version(&other, &version(full)) {
+            Err(Error::UntrustedPeer(ref target)) if *target == other => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        // A later entry for the same target replaces the earlier one
+        let peers = peers.with_peer(TrustedPeer::new(archive.clone(), ServiceFlags::NONE));
+        assert_eq!(peers.peers().len(), 2);
+        assert!(peers.check_version(&archive, &version(pruned)).is_ok());
+    }
+
+    #[test]
+    fn dialer() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let untrusted = SocketAddr::new(addr.ip(), addr.port().wrapping_add(1));
+
+        let peers = TrustedPeers::new().with_peer(TrustedPeer::new(addr, ServiceFlags::NONE));
+        let dialer = TrustedDialer::new(DirectDialer::default(), peers);
+        let stream = dialer.dial(&Target::from(addr)).unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), addr);
+        match dialer.dial(&Target::from(untrusted)) {
+            Err(Error::UntrustedPeer(_)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        match TrustedDialer::new(DirectDialer::default(), TrustedPeers::new()).dial(&addr.into()) {
+            Err(Error::UntrustedPeer(_)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
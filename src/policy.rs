@@ -0,0 +1,742 @@
+//! Relay Policy
+//!
+//! Besides the consensus rules every block must follow, nodes apply
+//! standardness rules to the unconfirmed transactions they accept into their
+//! mempool and relay. These are not enforced by the network and may differ
+//! between nodes; the rules here follow Bitcoin Core 28.0.
+//!
+//! This includes the topologically restricted until confirmation (TRUC)
+//! rules of BIP431, which transactions opt in to with version 3. A TRUC
+//! transaction may only be part of a cluster of one parent and one child,
+//! both TRUC, and the child is kept small so that it can always be cheaply
+//! replaced to bump the fee of the parent.
+//!
+//! Outputs to witness programs of versions without spending rules yet are
+//! relayed, so that wallets can pay addresses of future versions, but spends
+//! of them are not, as a soft fork may later give them rules they break.
+//!
+//! `Transaction::check_standard` goes through every rule that can be
+//! checked on a transaction alone, without its prevouts or the mempool, and
+//! reports all the violations it finds rather than the first, which makes
+//! it a useful lint for a wallet before broadcasting. It also flags lock
+//! times and sequences which have no effect, as these are more likely to be
+//! mistakes than intended.
+
+use std::{error, fmt};
+
+use blockdata::opcodes;
+use blockdata::script::{Instruction, Script};
+use blockdata::transaction::{Sequence, Transaction, TxOut, Version};
+use consensus::encode::VarInt;
+use error::{ErrorCode, ErrorKind};
+use hash_types::Txid;
+use util::address::WitnessVersion;
+use util::amount::Amount;
+use util::fee_rate::FeeRate;
+
+/// The largest weight of a transaction which is relayed
+pub const MAX_STANDARD_TX_WEIGHT: usize = 400_000;
+
+/// The smallest size of a transaction without its witness which is relayed,
+/// so that it cannot be mistaken for an inner node of a merkle tree
+pub const MIN_STANDARD_TX_NONWITNESS_SIZE: usize = 65;
+
+/// The largest sigop cost of a transaction which is relayed
+pub const MAX_STANDARD_TX_SIGOPS_COST: usize = 16_000;
+
+/// The largest `script_sig` which is relayed
+pub const MAX_STANDARD_SCRIPTSIG_SIZE: usize = 1650;
+
+/// The largest `script_pubkey` of an `OP_RETURN` output which is relayed
+pub const MAX_OP_RETURN_RELAY: usize = 83;
+
+/// The most keys of a bare multisig output which is relayed
+pub const MAX_STANDARD_BARE_MULTISIG_KEYS: usize = 3;
+
+/// The largest virtual size of a TRUC transaction
+pub const TRUC_MAX_VSIZE: usize = 10_000;
+
+/// The largest virtual size of a TRUC transaction with an unconfirmed parent
+pub const TRUC_CHILD_MAX_VSIZE: usize = 1_000;
+
+/// The most unconfirmed transactions a TRUC transaction and its ancestors may
+/// add up to, counting itself
+pub const TRUC_ANCESTOR_LIMIT: usize = 2;
+
+/// The most unconfirmed transactions a TRUC transaction and its descendants
+/// may add up to, counting itself
+pub const TRUC_DESCENDANT_LIMIT: usize = 2;
+
+/// A relay policy violation
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Error {
+    /// The transaction version is not one relayed
+    NonStandardVersion(Version),
+    /// The transaction weight exceeds `MAX_STANDARD_TX_WEIGHT`
+    TxTooLarge(usize),
+    /// A TRUC transaction's virtual size exceeds `TRUC_MAX_VSIZE`
+    TrucTooLarge(usize),
+    /// A TRUC transaction with an unconfirmed parent has a virtual size
+    /// above `TRUC_CHILD_MAX_VSIZE`
+    TrucChildTooLarge(usize),
+    /// A TRUC transaction spends an unconfirmed transaction which is not TRUC
+    TrucSpendsNonTruc(Txid),
+    /// A transaction which is not TRUC spends an unconfirmed TRUC transaction
+    NonTrucSpendsTruc(Txid),
+    /// A TRUC transaction would have more than `TRUC_ANCESTOR_LIMIT`
+    /// unconfirmed ancestors, counting itself
+    TrucTooManyAncestors,
+    /// The unconfirmed TRUC parent with this txid already has a child
+    TrucTooManyDescendants(Txid),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::NonStandardVersion(v) => write!(f, "non-standard transaction version {}", v),
+            Error::TxTooLarge(w) => write!(f, "transaction weight {} too large", w),
+            Error::TrucTooLarge(vsize) => {
+                write!(f, "TRUC transaction virtual size {} too large", vsize)
+            }
+            Error::TrucChildTooLarge(vsize) => {
+                write!(f, "TRUC child transaction virtual size {} too large", vsize)
+            }
+            Error::TrucSpendsNonTruc(ref txid) => {
+                write!(f, "TRUC transaction spends non-TRUC transaction {}", txid)
+            }
+            Error::NonTrucSpendsTruc(ref txid) => {
+                write!(f, "non-TRUC transaction spends TRUC transaction {}", txid)
+            }
+            Error::TrucTooManyDescendants(ref txid) => {
+                write!(f, "TRUC transaction {} already has a child", txid)
+            }
+            Error::TrucTooManyAncestors => f.write_str(error::Error::description(self)),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn cause(&self) -> Option<&error::Error> {
+        None
+    }
+
+    fn description(&self) -> &str {
+        match *self {
+            Error::NonStandardVersion(..) => "non-standard transaction version",
+            Error::TxTooLarge(..) => "transaction too large",
+            Error::TrucTooLarge(..) => "TRUC transaction too large",
+            Error::TrucChildTooLarge(..) => "TRUC child transaction too large",
+            Error::TrucSpendsNonTruc(..) => "TRUC transaction spends non-TRUC transaction",
+            Error::NonTrucSpendsTruc(..) => "non-TRUC transaction spends TRUC transaction",
+            Error::TrucTooManyAncestors => "TRUC transaction has too many unconfirmed ancestors",
+            Error::TrucTooManyDescendants(..) => "TRUC transaction already has a child",
+        }
+    }
+}
+
+impl ErrorCode for Error {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Policy
+    }
+
+    fn number(&self) -> u32 {
+        match *self {
+            Error::NonStandardVersion(..) => 1,
+            Error::TxTooLarge(..) => 2,
+            Error::TrucTooLarge(..) => 3,
+            Error::TrucChildTooLarge(..) => 4,
+            Error::TrucSpendsNonTruc(..) => 5,
+            Error::NonTrucSpendsTruc(..) => 6,
+            Error::TrucTooManyAncestors => 7,
+            Error::TrucTooManyDescendants(..) => 8,
+        }
+    }
+}
+
+/// A violation of the standardness rules, or a lock time which has no
+/// effect, found by `Transaction::check_standard`
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum PolicyViolation {
+    /// The transaction version is not one relayed
+    NonStandardVersion(Version),
+    /// The transaction weight exceeds the policy's maximum
+    TxTooLarge(usize),
+    /// The size of the transaction without its witness is below
+    /// `MIN_STANDARD_TX_NONWITNESS_SIZE`
+    TxTooSmall(usize),
+    /// The sigop cost of the scripts of the transaction, counted without
+    /// the outputs they spend, exceeds the policy's maximum
+    TooManySigops(usize),
+    /// The `script_sig` of the input at this index exceeds
+    /// `MAX_STANDARD_SCRIPTSIG_SIZE`
+    ScriptSigTooLarge(usize),
+    /// The `script_sig` of the input at this index does more than push data
+    ScriptSigNotPushOnly(usize),
+    /// The output at this index has a `script_pubkey` of no standard type
+    NonStandardOutput(usize),
+    /// The output at this index is a bare multisig, which the policy does
+    /// not permit
+    BareMultisig(usize),
+    /// The output at this index is worth less than spending it would cost
+    /// at the policy's dust relay fee rate
+    Dust(usize),
+    /// The `OP_RETURN` output at this index exceeds the policy's maximum
+    /// size
+    OpReturnTooLarge(usize),
+    /// The transaction has more than one `OP_RETURN` output
+    MultipleOpReturn,
+    /// The lock time is set, but every input has the maximum sequence
+    /// number, which disables it
+    LockTimeIgnored,
+    /// The sequence of the input at this index sets a relative timelock,
+    /// which the transaction version does not enforce
+    RelativeLockTimeIgnored(usize),
+}
+
+impl fmt::Display for PolicyViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PolicyViolation::NonStandardVersion(v) => {
+                write!(f, "non-standard transaction version {}", v)
+            }
+            PolicyViolation::TxTooLarge(w) => write!(f, "transaction weight {} too large", w),
+            PolicyViolation::TxTooSmall(size) => {
+                write!(f, "transaction size {} without witness too small", size)
+            }
+            PolicyViolation::TooManySigops(cost) => {
+                write!(f, "transaction sigop cost {} too large", cost)
+            }
+            PolicyViolation::ScriptSigTooLarge(i) => write!(f, "input {} script_sig too large", i),
+            PolicyViolation::ScriptSigNotPushOnly(i) => {
+                write!(f, "input {} script_sig is not push only", i)
+            }
+            PolicyViolation::NonStandardOutput(i) => {
+                write!(f, "output {} has a non-standard script_pubkey", i)
+            }
+            PolicyViolation::BareMultisig(i) => write!(f, "output {} is a bare multisig", i),
+            PolicyViolation::Dust(i) => write!(f, "output {} is dust", i),
+            PolicyViolation::OpReturnTooLarge(i) => {
+                write!(f, "OP_RETURN output {} too large", i)
+            }
+            PolicyViolation::MultipleOpReturn => f.write_str("more than one OP_RETURN output"),
+            PolicyViolation::LockTimeIgnored => {
+                f.write_str("lock time disabled by the sequences of every input")
+            }
+            PolicyViolation::RelativeLockTimeIgnored(i) => write!(
+                f,
+                "input {} relative timelock not enforced for the transaction version",
+                i
+            ),
+        }
+    }
+}
+
+/// The limits `Transaction::check_standard` checks against, which default to
+/// those of Bitcoin Core
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Policy {
+    /// The largest weight of a transaction
+    pub max_tx_weight: usize,
+    /// The largest sigop cost of a transaction
+    pub max_sigops_cost: usize,
+    /// The fee rate outputs must be worth spending at
+    pub dust_relay_fee: FeeRate,
+    /// The largest `script_pubkey` of an `OP_RETURN` output
+    pub max_op_return_size: usize,
+    /// Whether bare multisig outputs are standard
+    pub permit_bare_multisig: bool,
+}
+
+impl Default for Policy {
+    fn default() -> Policy {
+        Policy {
+            max_tx_weight: MAX_STANDARD_TX_WEIGHT,
+            max_sigops_cost: MAX_STANDARD_TX_SIGOPS_COST,
+            dust_relay_fee: FeeRate::DUST_RELAY,
+            max_op_return_size: MAX_OP_RETURN_RELAY,
+            permit_bare_multisig: true,
+        }
+    }
+}
+
+impl Policy {
+    /// Checks `tx` against every rule, returning the violations in the
+    /// order the transaction is laid out
+    pub fn check(&self, tx: &Transaction) -> Vec<PolicyViolation> {
+        let mut ret = vec![];
+
+        let version = tx.tx_version();
+        if !version.is_standard() {
+            ret.push(PolicyViolation::NonStandardVersion(version));
+        }
+        let weight = tx.get_weight();
+        if weight > self.max_tx_weight {
+            ret.push(PolicyViolation::TxTooLarge(weight));
+        }
+        let stripped_size = tx.get_stripped_size();
+        if stripped_size < MIN_STANDARD_TX_NONWITNESS_SIZE {
+            ret.push(PolicyViolation::TxTooSmall(stripped_size));
+        }
+        let sigops_cost = legacy_sigops_cost(tx);
+        if sigops_cost > self.max_sigops_cost {
+            ret.push(PolicyViolation::TooManySigops(sigops_cost));
+        }
+
+        for (i, input) in tx.input.iter().enumerate() {
+            if input.script_sig.len() > MAX_STANDARD_SCRIPTSIG_SIZE {
+                ret.push(PolicyViolation::ScriptSigTooLarge(i));
+            }
+            if !is_push_only(&input.script_sig) {
+                ret.push(PolicyViolation::ScriptSigNotPushOnly(i));
+            }
+        }
+
+        let mut op_returns = 0;
+        for (i, output) in tx.output.iter().enumerate() {
+            let script = &output.script_pubkey;
+            if script.is_op_return() {
+                op_returns += 1;
+                if script.len() > self.max_op_return_size {
+                    ret.push(PolicyViolation::OpReturnTooLarge(i));
+                }
+                continue;
+            }
+            match multisig_keys(script) {
+                Some(n) if n > MAX_STANDARD_BARE_MULTISIG_KEYS => {
+                    ret.push(PolicyViolation::NonStandardOutput(i));
+                    continue;
+                }
+                Some(_) if !self.permit_bare_multisig => {
+                    ret.push(PolicyViolation::BareMultisig(i));
+                }
+                Some(_) => {}
+                None if !is_standard_script_pubkey(script) => {
+                    ret.push(PolicyViolation::NonStandardOutput(i));
+                    continue;
+                }
+                None => {}
+            }
+            if Amount::from_sat(output.value) < dust_threshold(output, self.dust_relay_fee) {
+                ret.push(PolicyViolation::Dust(i));
+            }
+        }
+        if op_returns > 1 {
+            ret.push(PolicyViolation::MultipleOpReturn);
+        }
+
+        if tx.lock_time != 0
+            && tx
+                .input
+                .iter()
+                .all(|i| Sequence(i.sequence) == Sequence::MAX)
+        {
+            ret.push(PolicyViolation::LockTimeIgnored);
+        }
+        if !version.enforces_relative_lock_time() {
+            for (i, input) in tx.input.iter().enumerate() {
+                let sequence = Sequence(input.sequence);
+                if sequence.estimated_blocks().unwrap_or(0) > 0 {
+                    ret.push(PolicyViolation::RelativeLockTimeIgnored(i));
+                }
+            }
+        }
+        ret
+    }
+}
+
+/// The smallest value `output` may have without being dust: what it would
+/// cost to spend it at `dust_relay_fee`, assuming a typical input for its
+/// type. Outputs which can never be spent, such as `OP_RETURN` ones, have no
+/// threshold.
+pub fn dust_threshold(output: &TxOut, dust_relay_fee: FeeRate) -> Amount {
+    let script = &output.script_pubkey;
+    if script.is_provably_unspendable() {
+        return Amount::ZERO;
+    }
+    let output_size = 8 + VarInt(script.len() as u64).len() + script.len();
+    // An outpoint, an empty script_sig and a sequence, plus either a
+    // signature and public key in the witness or a script_sig holding them
+    let input_size = if script.is_witness_program() {
+        32 + 4 + 1 + 107 / 4 + 4
+    } else {
+        32 + 4 + 1 + 107 + 4
+    };
+    dust_relay_fee
+        .fee_vb((output_size + input_size) as u64)
+        .expect("dust threshold overflow")
+}
+
+/// Whether `script_pubkey` is of one of the types Bitcoin Core relays, other
+/// than bare multisig and `OP_RETURN`
+fn is_standard_script_pubkey(script: &Script) -> bool {
+    script.is_p2pk() || script.is_p2pkh() || script.is_p2sh() || script.is_witness_program()
+}
+
+/// The number of keys of a bare multisig `script_pubkey`
+fn multisig_keys(script: &Script) -> Option<usize> {
+    let instructions: Vec<_> = script.instructions().collect::<Result<_, _>>().ok()?;
+    if instructions.len() < 4 {
+        return None;
+    }
+    let pushnum = |instruction: &Instruction| match *instruction {
+        Instruction::Op(op) => match op.classify() {
+            opcodes::Class::PushNum(n) if n >= 1 => Some(n as usize),
+            _ => None,
+        },
+        _ => None,
+    };
+    let last = instructions.len() - 1;
+    let m = pushnum(&instructions[0])?;
+    let n = pushnum(&instructions[last - 1])?;
+    let keys = &instructions[1..last - 1];
+    let valid = m <= n
+        && n == keys.len()
+        && instructions[last] == Instruction::Op(opcodes::all::OP_CHECKMULTISIG)
+        && keys.iter().all(|i| match *i {
+            Instruction::PushBytes(key) => key.len() == 33 || key.len() == 65,
+            _ => false,
+        });
+    if valid {
+        Some(n)
+    } else {
+        None
+    }
+}
+
+/// Whether `script` only pushes data, which Bitcoin Core requires of every
+/// `script_sig` it relays
+fn is_push_only(script: &Script) -> bool {
+    script.instructions().all(|instruction| match instruction {
+        Ok(Instruction::PushBytes(_)) => true,
+        Ok(Instruction::Op(op)) => op.into_u8() <= opcodes::all::OP_PUSHNUM_16.into_u8(),
+        Err(_) => false,
+    })
+}
+
+/// The sigop cost of the scripts of `tx` alone, counting a
+/// `CHECKMULTISIG` as 20 sigops as Bitcoin Core does without the outputs
+/// spent. The sigops of P2SH redeem scripts and witnesses are not counted.
+fn legacy_sigops_cost(tx: &Transaction) -> usize {
+    let scripts = tx
+        .input
+        .iter()
+        .map(|i| &i.script_sig)
+        .chain(tx.output.iter().map(|o| &o.script_pubkey));
+    let mut sigops = 0;
+    for script in scripts {
+        for instruction in script.instructions() {
+            match instruction {
+                Ok(Instruction::Op(opcodes::all::OP_CHECKSIG))
+                | Ok(Instruction::Op(opcodes::all::OP_CHECKSIGVERIFY)) => sigops += 1,
+                Ok(Instruction::Op(opcodes::all::OP_CHECKMULTISIG))
+                | Ok(Instruction::Op(opcodes::all::OP_CHECKMULTISIGVERIFY)) => sigops += 20,
+                Ok(_) => {}
+                Err(_) => break,
+            }
+        }
+    }
+    sigops * 4
+}
+
+/// An unconfirmed transaction spent by a transaction being checked, together
+/// with what the mempool knows about its relatives
+#[derive(Clone, Copy, Debug)]
+pub struct MempoolParent<'a> {
+    /// The parent transaction
+    pub tx: &'a Transaction,
+    /// The number of its unconfirmed ancestors, not counting itself
+    pub ancestors: usize,
+    /// The number of its unconfirmed children already in the mempool
+    pub children: usize,
+}
+
+/// Checks the version and weight of `tx` against the standardness rules
+pub fn check_standard(tx: &Transaction) -> Result<(), Error> {
+    let version = tx.tx_version();
+    if !version.is_standard() {
+        return Err(Error::NonStandardVersion(version));
+    }
+    let weight = tx.get_weight();
+    if weight > MAX_STANDARD_TX_WEIGHT {
+        return Err(Error::TxTooLarge(weight));
+    }
+    Ok(())
+}
+
+/// Whether `script_pubkey` is a witness program reserved for a future soft
+/// fork: one of version 2 to 16, or of version 1 other than a taproot
+/// output. Such outputs are standard, but spending them is not.
+pub fn is_upgradable_witness_program(script_pubkey: &Script) -> bool {
+    match script_pubkey.witness_version() {
+        Some(WitnessVersion::V0) | None => false,
+        Some(WitnessVersion::V1) => !script_pubkey.is_v1_p2tr(),
+        Some(_) => true,
+    }
+}
+
+/// Checks the TRUC rules for `tx`, given the distinct unconfirmed
+/// transactions it spends.
+///
+/// A TRUC parent which already has a child is reported as
+/// `Error::TrucTooManyDescendants`; Bitcoin Core may instead accept `tx` by
+/// evicting that child, if `tx` pays enough to replace it.
+pub fn check_truc(tx: &Transaction, parents: &[MempoolParent]) -> Result<(), Error> {
+    if !tx.tx_version().is_truc() {
+        return match parents.iter().find(|p| p.tx.tx_version().is_truc()) {
+            Some(parent) => Err(Error::NonTrucSpendsTruc(parent.tx.txid())),
+            None => Ok(()),
+        };
+    }
+
+    if let Some(parent) = parents.iter().find(|p| !p.tx.tx_version().is_truc()) {
+        return Err(Error::TrucSpendsNonTruc(parent.tx.txid()));
+    }
+    let ancestors = parents.iter().fold(1, |n, p| n + 1 + p.ancestors);
+    if ancestors > TRUC_ANCESTOR_LIMIT {
+        return Err(Error::TrucTooManyAncestors);
+    }
+    let vsize = tx.get_vsize();
+    if vsize > TRUC_MAX_VSIZE {
+        return Err(Error::TrucTooLarge(vsize));
+    }
+    if let Some(parent) = parents.first() {
+        if vsize > TRUC_CHILD_MAX_VSIZE {
+            return Err(Error::TrucChildTooLarge(vsize));
+        }
+        if 1 + parent.children + 1 > TRUC_DESCENDANT_LIMIT {
+            return Err(Error::TrucTooManyDescendants(parent.tx.txid()));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use blockdata::transaction::{OutPoint, TxIn, TxOut};
+    use hashes::Hash;
+
+    fn tx(version: u32, spends: &[Txid], output_script_len: usize) -> Transaction {
+        Transaction {
+            version,
+            lock_time: 0,
+            input: spends
+                .iter()
+                .map(|txid| TxIn {
+                    previous_output: OutPoint::new(*txid, 0),
+                    ..Default::default()
+                })
+                .collect(),
+            output: vec![TxOut {
+                value: 1000,
+                script_pubkey: Script::from(vec![0; output_script_len]),
+            }],
+        }
+    }
+
+    fn parent(tx: &Transaction) -> MempoolParent {
+        MempoolParent {
+            tx,
+            ancestors: 0,
+            children: 0,
+        }
+    }
+
+    #[test]
+    fn standard() {
+        let confirmed = Txid::hash(&[0]);
+        assert!(check_standard(&tx(1, &[confirmed], 22)).is_ok());
+        assert!(check_standard(&tx(3, &[confirmed], 22)).is_ok());
+        assert_eq!(
+            check_standard(&tx(4, &[confirmed], 22)),
+            Err(Error::NonStandardVersion(Version(4)))
+        );
+        match check_standard(&tx(2, &[confirmed], 100_000)) {
+            Err(Error::TxTooLarge(_)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn lint() {
+        let confirmed = Txid::hash(&[0]);
+        let policy = Policy::default();
+        let output = |value: u64, script: Vec<u8>| TxOut {
+            value,
+            script_pubkey: Script::from(script),
+        };
+        let p2wpkh = |value: u64| output(value, [&[0x00, 0x14][..], &[0; 20][..]].concat());
+
+        let mut good = tx(2, &[confirmed], 0);
+        good.output = vec![p2wpkh(1000)];
+        assert_eq!(good.check_standard(&policy), vec![]);
+        assert_eq!(
+            dust_threshold(&p2wpkh(0), policy.dust_relay_fee),
+            Amount::from_sat(294)
+        );
+        let p2pkh = [&[0x76, 0xa9, 0x14][..], &[0; 20][..], &[0x88, 0xac][..]].concat();
+        assert_eq!(
+            dust_threshold(&output(0, p2pkh), policy.dust_relay_fee),
+            Amount::from_sat(546)
+        );
+        assert_eq!(
+            dust_threshold(&output(0, vec![0x6a]), policy.dust_relay_fee),
+            Amount::ZERO
+        );
+
+        // Every violation is reported, in order
+        let key = [&[0x21][..], &[0x02; 33][..]].concat();
+        let mut multisig = vec![0x51];
+        for _ in 0..4 {
+            multisig.extend(&key);
+        }
+        multisig.extend(&[0x54, 0xae]);
+        let mut bad = good.clone();
+        bad.version = 4;
+        bad.lock_time = 100;
+        bad.input[0].script_sig = Script::from(vec![0x51, 0xac]);
+        bad.output = vec![
+            p2wpkh(293),
+            output(1000, vec![0xac]),
+            output(0, vec![0x6a, 0x01, 0x00]),
+            output(0, [&[0x6a, 0x4c, 82][..], &[0; 82][..]].concat()),
+            output(1000, multisig),
+        ];
+        assert_eq!(
+            bad.check_standard(&policy),
+            vec![
+                PolicyViolation::NonStandardVersion(Version(4)),
+                PolicyViolation::ScriptSigNotPushOnly(0),
+                PolicyViolation::Dust(0),
+                PolicyViolation::NonStandardOutput(1),
+                PolicyViolation::OpReturnTooLarge(3),
+                PolicyViolation::NonStandardOutput(4),
+                PolicyViolation::MultipleOpReturn,
+                PolicyViolation::LockTimeIgnored,
+            ]
+        );
+
+        // Bare multisig of up to three keys is standard unless disabled
+        let mut bare = good.clone();
+        bare.output = vec![output(
+            1000,
+            [&[0x51][..], &key[..], &[0x51, 0xae][..]].concat(),
+        )];
+        assert_eq!(bare.check_standard(&policy), vec![]);
+        let strict = Policy {
+            permit_bare_multisig: false,
+            ..policy
+        };
+        assert_eq!(
+            bare.check_standard(&strict),
+            vec![PolicyViolation::BareMultisig(0)]
+        );
+
+        // Size, sigops and relative timelocks
+        let mut small = good.clone();
+        small.output = vec![output(0, vec![0x6a])];
+        assert_eq!(
+            small.check_standard(&policy),
+            vec![PolicyViolation::TxTooSmall(61)]
+        );
+        let mut sigops = good.clone();
+        sigops.output = vec![output(1000, vec![0xae; 201])];
+        assert_eq!(
+            sigops.check_standard(&policy),
+            vec![
+                PolicyViolation::TooManySigops(16_080),
+                PolicyViolation::NonStandardOutput(0),
+            ]
+        );
+        let mut relative = good.clone();
+        relative.input[0].sequence = Sequence::from_height(10).to_consensus_u32();
+        assert_eq!(relative.check_standard(&policy), vec![]);
+        relative.version = 1;
+        assert_eq!(
+            relative.check_standard(&policy),
+            vec![PolicyViolation::RelativeLockTimeIgnored(0)]
+        );
+    }
+
+    #[test]
+    fn upgradable_witness_program() {
+        let program = |version: u8, len: usize| {
+            let mut script = vec![version, len as u8];
+            script.extend(vec![0; len]);
+            Script::from(script)
+        };
+        assert!(!is_upgradable_witness_program(&program(0x00, 20)));
+        assert!(!is_upgradable_witness_program(&program(0x00, 32)));
+        assert!(!is_upgradable_witness_program(&program(0x51, 32)));
+        assert!(is_upgradable_witness_program(&program(0x51, 20)));
+        assert!(is_upgradable_witness_program(&program(0x52, 32)));
+        assert!(is_upgradable_witness_program(&program(0x60, 2)));
+        assert!(!is_upgradable_witness_program(&program(0x61, 2)));
+        assert!(!is_upgradable_witness_program(&Script::new()));
+    }
+
+    #[test]
+    fn truc_topology() {
+        let confirmed = Txid::hash(&[0]);
+        let truc_parent = tx(3, &[confirmed], 22);
+        let legacy_parent = tx(2, &[confirmed], 22);
+
+        // No unconfirmed parents
+        assert!(check_truc(&truc_parent, &[]).is_ok());
+        assert!(check_truc(&legacy_parent, &[]).is_ok());
+
+        // TRUC spends TRUC, and nothing else mixes
+        let child = tx(3, &[truc_parent.txid()], 22);
+        assert!(check_truc(&child, &[parent(&truc_parent)]).is_ok());
+        assert_eq!(
+            check_truc(&child, &[parent(&legacy_parent)]),
+            Err(Error::TrucSpendsNonTruc(legacy_parent.txid()))
+        );
+        let legacy_child = tx(2, &[truc_parent.txid()], 22);
+        assert_eq!(
+            check_truc(&legacy_child, &[parent(&truc_parent)]),
+            Err(Error::NonTrucSpendsTruc(truc_parent.txid()))
+        );
+        let other_legacy_child = tx(2, &[legacy_parent.txid()], 22);
+        assert!(check_truc(&other_legacy_child, &[parent(&legacy_parent)]).is_ok());
+
+        // One parent, with no ancestors of its own, and one child
+        let other_parent = tx(3, &[Txid::hash(&[1])], 22);
+        assert_eq!(
+            check_truc(&child, &[parent(&truc_parent), parent(&other_parent)]),
+            Err(Error::TrucTooManyAncestors)
+        );
+        let mut grandchild = parent(&truc_parent);
+        grandchild.ancestors = 1;
+        assert_eq!(
+            check_truc(&child, &[grandchild]),
+            Err(Error::TrucTooManyAncestors)
+        );
+        let mut sibling = parent(&truc_parent);
+        sibling.children = 1;
+        assert_eq!(
+            check_truc(&child, &[sibling]),
+            Err(Error::TrucTooManyDescendants(truc_parent.txid()))
+        );
+    }
+
+    #[test]
+    fn truc_size() {
+        let confirmed = Txid::hash(&[0]);
+        let big = tx(3, &[confirmed], 9_900);
+        assert!(big.get_vsize() > TRUC_CHILD_MAX_VSIZE);
+        assert!(check_truc(&big, &[]).is_ok());
+        match check_truc(&tx(3, &[confirmed], 10_000), &[]) {
+            Err(Error::TrucTooLarge(_)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let truc_parent = tx(3, &[confirmed], 22);
+        let big_child = tx(3, &[truc_parent.txid()], 1_000);
+        match check_truc(&big_child, &[parent(&truc_parent)]) {
+            Err(Error::TrucChildTooLarge(vsize)) => assert_eq!(vsize, big_child.get_vsize()),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
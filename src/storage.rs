@@ -0,0 +1,438 @@
+//! Flat File Storage
+//!
+//! A `FlatFileStore` persists the headers a client syncs, and optionally
+//! the blocks it downloads, to append-only files, so that it can resume
+//! from where it stopped after a restart instead of downloading everything
+//! again.
+//!
+//! Each file is 8 magic bytes, which include the format version, followed
+//! by one record per header or block, framed as `CheckedData`: the length
+//! and a checksum of the record, then the record itself. A write torn by a
+//! crash leaves a frame which is short or fails its checksum, and opening
+//! the store discards everything from there on, keeping all the records
+//! written before it. The discarded bytes are zeroed, or truncated away
+//! for a store opened from a directory.
+//!
+//! Headers are kept in memory once read. Blocks are stored as serialized
+//! and only their position is kept in memory, in an index by block hash
+//! which is rebuilt when the store is opened.
+
+use std::cmp;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use blockdata::block::BlockHeader;
+use blockdata::headerchain::{self, HeaderChain};
+use consensus::encode::{self, deserialize, deserialize_partial, CheckedData, Encodable};
+use hash_types::BlockHash;
+
+/// The bytes a header file starts with
+pub const HEADERS_MAGIC: [u8; 8] = *b"btchdr\x00\x01";
+
+/// The bytes a block file starts with
+pub const BLOCKS_MAGIC: [u8; 8] = *b"btcblk\x00\x01";
+
+/// The name of the header file in a store directory
+pub const HEADERS_FILE_NAME: &str = "headers.dat";
+
+/// The name of the block file in a store directory
+pub const BLOCKS_FILE_NAME: &str = "blocks.dat";
+
+/// A file of records, and where its valid records end
+#[derive(Debug)]
+struct RecordFile<F> {
+    file: F,
+    end: u64,
+}
+
+impl<F: Read + Write + Seek> RecordFile<F> {
+    /// Opens a record file, writing `magic` if it is empty, and passes
+    /// each valid record and its position to `f`
+    fn open<C>(mut file: F, magic: [u8; 8], mut f: C) -> Result<RecordFile<F>, encode::Error>
+    where
+        C: FnMut(u64, Vec<u8>) -> Result<(), encode::Error>,
+    {
+        let len = file.seek(SeekFrom::End(0))?;
+        file.seek(SeekFrom::Start(0))?;
+        if len < magic.len() as u64 {
+            // Empty, or torn before the magic was written
+            file.write_all(&magic)?;
+            return Ok(RecordFile {
+                file,
+                end: magic.len() as u64,
+            });
+        }
+
+        let mut reader = io::BufReader::new(&mut file);
+        let mut found = [0; 8];
+        reader.read_exact(&mut found)?;
+        if found != magic {
+            return Err(encode::Error::ParseFailed("not a store file of this kind"));
+        }
+        let mut end = magic.len() as u64;
+        loop {
+            let record: CheckedData = match encode::Decodable::consensus_decode(&mut reader) {
+                Ok(record) => record,
+                Err(encode::Error::Io(ref e)) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(encode::Error::InvalidChecksum { .. })
+                | Err(encode::Error::OversizedVectorAllocation { .. }) => break,
+                Err(e) => return Err(e),
+            };
+            let frame_len = record.consensus_encoded_len() as u64;
+            f(end, record.0)?;
+            end += frame_len;
+        }
+
+        // Zero whatever follows, so that records after a corrupted one can
+        // never be read again once new records are appended over it: a
+        // zeroed frame fails its checksum
+        file.seek(SeekFrom::Start(end))?;
+        let zeros = [0; 4096];
+        let mut left = len - end;
+        while left > 0 {
+            let n = cmp::min(left, zeros.len() as u64) as usize;
+            file.write_all(&zeros[..n])?;
+            left -= n as u64;
+        }
+        Ok(RecordFile { file, end })
+    }
+
+    /// Appends a record, returning its position
+    fn append(&mut self, data: Vec<u8>) -> Result<u64, encode::Error> {
+        // Write the frame in one go, after the last valid record, which
+        // overwrites whatever a torn write left there
+        let frame = encode::serialize(&CheckedData(data));
+        let pos = self.end;
+        self.file.seek(SeekFrom::Start(pos))?;
+        self.file.write_all(&frame)?;
+        self.end += frame.len() as u64;
+        Ok(pos)
+    }
+
+    /// Reads the record at `pos`
+    fn read(&mut self, pos: u64) -> Result<Vec<u8>, encode::Error> {
+        self.file.seek(SeekFrom::Start(pos))?;
+        let record: CheckedData = encode::Decodable::consensus_decode(&mut self.file)?;
+        Ok(record.0)
+    }
+}
+
+/// Headers, and optionally blocks, persisted to append-only files
+#[derive(Debug)]
+pub struct FlatFileStore<F> {
+    headers_file: RecordFile<F>,
+    blocks_file: Option<RecordFile<F>>,
+    headers: Vec<BlockHeader>,
+    block_index: HashMap<BlockHash, u64>,
+}
+
+impl<F: Read + Write + Seek> FlatFileStore<F> {
+    /// Opens a store of headers only, reading the headers in `headers`
+    pub fn new(headers: F) -> Result<FlatFileStore<F>, encode::Error> {
+        FlatFileStore::open_files(headers, None)
+    }
+
+    /// Opens a store of headers and blocks, reading the headers in
+    /// `headers` and indexing the blocks in `blocks`
+    pub fn with_blocks(headers: F, blocks: F) -> Result<FlatFileStore<F>, encode::Error> {
+        FlatFileStore::open_files(headers, Some(blocks))
+    }
+
+    fn open_files(headers: F, blocks: Option<F>) -> Result<FlatFileStore<F>, encode::Error> {
+        let mut stored_headers = vec![];
+        let headers_file = RecordFile::open(headers, HEADERS_MAGIC, |_, data| {
+            stored_headers.push(deserialize(&data)?);
+            Ok(())
+        })?;
+
+        let mut block_index = HashMap::new();
+        let blocks_file = match blocks {
+            Some(blocks) => Some(RecordFile::open(blocks, BLOCKS_MAGIC, |pos, data| {
+                let (header, _) = deserialize_partial::<BlockHeader>(&data)?;
+                block_index.insert(header.block_hash(), pos);
+                Ok(())
+            })?),
+            None => None,
+        };
+
+        Ok(FlatFileStore {
+            headers_file,
+            blocks_file,
+            headers: stored_headers,
+            block_index,
+        })
+    }
+
+    /// Appends a header
+    ///
+    /// Headers are stored in the order they are appended, which must have
+    /// each header after its parent for `header_chain` to rebuild the chain.
+    pub fn append_header(&mut self, header: &BlockHeader) -> Result<(), encode::Error> {
+        self.headers_file.append(encode::serialize(header))?;
+        self.headers.push(*header);
+        Ok(())
+    }
+
+    /// The headers stored, in the order they were appended
+    pub fn headers(&self) -> &[BlockHeader] {
+        &self.headers
+    }
+
+    /// Rebuilds the header chain from the stored headers, rooted at the
+    /// first, or `None` if there are none
+    pub fn header_chain(&self) -> Result<Option<HeaderChain>, headerchain::Error> {
+        let mut headers = self.headers.iter();
+        let mut chain = match headers.next() {
+            Some(root) => HeaderChain::new(*root),
+            None => return Ok(None),
+        };
+        for header in headers {
+            chain.add_header(*header)?;
+        }
+        Ok(Some(chain))
+    }
+
+    /// Whether the store keeps blocks
+    pub fn stores_blocks(&self) -> bool {
+        self.blocks_file.is_some()
+    }
+
+    /// Appends a serialized block, returning whether it was stored: it is
+    /// not if it is stored already or if the store keeps headers only
+    pub fn append_block(&mut self, block: &[u8]) -> Result<bool, encode::Error> {
+        let (header, _) = deserialize_partial::<BlockHeader>(block)?;
+        let hash = header.block_hash();
+        let blocks_file = match self.blocks_file {
+            Some(ref mut blocks_file) => blocks_file,
+            None => return Ok(false),
+        };
+        if self.block_index.contains_key(&hash) {
+            return Ok(false);
+        }
+        let pos = blocks_file.append(block.to_vec())?;
+        self.block_index.insert(hash, pos);
+        Ok(true)
+    }
+
+    /// Whether the block with the given hash is stored
+    pub fn has_block(&self, hash: &BlockHash) -> bool {
+        self.block_index.contains_key(hash)
+    }
+
+    /// The number of blocks stored
+    pub fn block_count(&self) -> usize {
+        self.block_index.len()
+    }
+
+    /// Reads the serialized block with the given hash, if it is stored
+    pub fn get_block(&mut self, hash: &BlockHash) -> Result<Option<Vec<u8>>, encode::Error> {
+        let pos = match self.block_index.get(hash) {
+            Some(&pos) => pos,
+            None => return Ok(None),
+        };
+        match self.blocks_file {
+            Some(ref mut blocks_file) => blocks_file.read(pos).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Flushes the underlying files
+    pub fn flush(&mut self) -> Result<(), encode::Error> {
+        self.headers_file.file.flush()?;
+        if let Some(ref mut blocks_file) = self.blocks_file {
+            blocks_file.file.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Returns the underlying header and block files
+    pub fn into_inner(self) -> (F, Option<F>) {
+        (self.headers_file.file, self.blocks_file.map(|b| b.file))
+    }
+}
+
+impl FlatFileStore<File> {
+    /// Opens the store in directory `dir`, creating it if needed, with a
+    /// block file if `blocks` is set
+    ///
+    /// Anything a torn write left after the last valid record is truncated
+    /// away.
+    pub fn open<P: AsRef<Path>>(
+        dir: P,
+        blocks: bool,
+    ) -> Result<FlatFileStore<File>, encode::Error> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+        let open = |name| {
+            OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(dir.join(name))
+        };
+        let headers = open(HEADERS_FILE_NAME)?;
+        let blocks = if blocks {
+            Some(open(BLOCKS_FILE_NAME)?)
+        } else {
+            None
+        };
+
+        let store = FlatFileStore::open_files(headers, blocks)?;
+        store.headers_file.file.set_len(store.headers_file.end)?;
+        if let Some(ref blocks_file) = store.blocks_file {
+            blocks_file.file.set_len(blocks_file.end)?;
+        }
+        Ok(store)
+    }
+
+    /// Flushes the underlying files and waits until they are on disk
+    pub fn sync(&mut self) -> Result<(), encode::Error> {
+        self.flush()?;
+        self.headers_file.file.sync_data()?;
+        if let Some(ref blocks_file) = self.blocks_file {
+            blocks_file.file.sync_data()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::io::Cursor;
+
+    use testutil::mine_header;
+
+    fn headers(n: u32) -> Vec<BlockHeader> {
+        let mut ret: Vec<BlockHeader> = vec![];
+        for i in 0..n {
+            let prev = ret.last().map_or(BlockHash::default(), |h| h.block_hash());
+            ret.push(mine_header(prev, 1, i));
+        }
+        ret
+    }
+
+    /// A serialized block with `header` and some made up transaction data
+    fn block(header: &BlockHeader, len: usize) -> Vec<u8> {
+        let mut block = encode::serialize(header);
+        block.extend(vec![header.nonce as u8; len]);
+        block
+    }
+
+    type MemStore = FlatFileStore<Cursor<Vec<u8>>>;
+
+    fn reopen(store: MemStore) -> MemStore {
+        let (headers, blocks) = store.into_inner();
+        match blocks {
+            Some(blocks) => FlatFileStore::with_blocks(headers, blocks).unwrap(),
+            None => FlatFileStore::new(headers).unwrap(),
+        }
+    }
+
+    #[test]
+    fn persistence() {
+        let chain = headers(5);
+        let mut store = MemStore::with_blocks(Cursor::new(vec![]), Cursor::new(vec![])).unwrap();
+        assert!(store.headers().is_empty());
+        assert!(store.header_chain().unwrap().is_none());
+        for header in &chain {
+            store.append_header(header).unwrap();
+        }
+        assert!(store.append_block(&block(&chain[2], 100)).unwrap());
+        assert!(!store.append_block(&block(&chain[2], 100)).unwrap());
+        assert!(store.append_block(&block(&chain[3], 10)).unwrap());
+
+        let mut store = reopen(store);
+        assert_eq!(store.headers(), &chain[..]);
+        let rebuilt = store.header_chain().unwrap().unwrap();
+        assert_eq!(rebuilt.tip(), chain[4].block_hash());
+        assert_eq!(rebuilt.height(), 4);
+
+        assert_eq!(store.block_count(), 2);
+        assert!(store.has_block(&chain[3].block_hash()));
+        assert!(!store.has_block(&chain[4].block_hash()));
+        assert_eq!(
+            store.get_block(&chain[2].block_hash()).unwrap(),
+            Some(block(&chain[2], 100))
+        );
+        assert_eq!(store.get_block(&chain[4].block_hash()).unwrap(), None);
+
+        // A store of headers only ignores blocks
+        let mut store = MemStore::new(Cursor::new(vec![])).unwrap();
+        assert!(!store.stores_blocks());
+        assert!(!store.append_block(&block(&chain[0], 1)).unwrap());
+        assert!(store.append_block(&[0; 79]).is_err());
+    }
+
+    #[test]
+    fn torn_writes() {
+        let chain = headers(4);
+        let mut store = MemStore::new(Cursor::new(vec![])).unwrap();
+        for header in &chain[..3] {
+            store.append_header(header).unwrap();
+        }
+        let (file, _) = store.into_inner();
+        let full = file.into_inner();
+        assert_eq!(full.len(), 8 + 3 * (8 + 80));
+
+        // Every prefix of the file opens, keeping the whole records in it
+        for len in 0..full.len() {
+            let store = MemStore::new(Cursor::new(full[..len].to_vec())).unwrap();
+            let whole = len.saturating_sub(8) / 88;
+            assert_eq!(store.headers(), &chain[..whole], "length {}", len);
+        }
+
+        // A corrupted record is discarded with everything after it, and
+        // appending overwrites it
+        let mut corrupted = full.clone();
+        corrupted[8 + 88 + 20] ^= 1;
+        let mut store = MemStore::new(Cursor::new(corrupted)).unwrap();
+        assert_eq!(store.headers(), &chain[..1]);
+        store.append_header(&chain[3]).unwrap();
+        let store = reopen(store);
+        assert_eq!(store.headers(), &[chain[0], chain[3]]);
+
+        // Another kind of file is refused
+        let mut blocks = BLOCKS_MAGIC.to_vec();
+        blocks.extend(&full[8..]);
+        assert!(MemStore::new(Cursor::new(blocks)).is_err());
+    }
+
+    #[test]
+    fn files() {
+        let dir = env::temp_dir().join(format!("flat-file-store-{}", ::std::process::id()));
+        let chain = headers(3);
+        {
+            let mut store = FlatFileStore::open(&dir, true).unwrap();
+            for header in &chain {
+                store.append_header(header).unwrap();
+            }
+            store.append_block(&block(&chain[1], 50)).unwrap();
+            store.sync().unwrap();
+        }
+        // A torn write at the end of the header file
+        let mut headers = OpenOptions::new()
+            .append(true)
+            .open(dir.join(HEADERS_FILE_NAME))
+            .unwrap();
+        headers.write_all(&[80, 0, 0, 0, 1, 2]).unwrap();
+        drop(headers);
+
+        {
+            let mut store = FlatFileStore::open(&dir, true).unwrap();
+            assert_eq!(store.headers(), &chain[..]);
+            assert_eq!(
+                store.get_block(&chain[1].block_hash()).unwrap(),
+                Some(block(&chain[1], 50))
+            );
+        }
+        let len = fs::metadata(dir.join(HEADERS_FILE_NAME)).unwrap().len();
+        assert_eq!(len, 8 + 3 * 88);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
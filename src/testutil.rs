@@ -0,0 +1,399 @@
+//! Test Fixtures
+//!
+//! Deterministic transactions of a given shape, for tests and benchmarks
+//! which need realistic transactions but do not care about their contents.
+//! Everything a fixture makes up, such as the outpoints it spends, the
+//! hashes in its output scripts and its signatures, is drawn from a
+//! `SeededRng`, so the same fixture always builds the same transactions.
+//!
+//! The signatures are random bytes of the size of real ones: the
+//! transactions have the weight of signed ones but do not verify. Headers,
+//! on the other hand, are mined, as chains check their proof of work.
+//!
+//! The module is only built for the tests of this library and with the
+//! `testutil` feature, for the tests and benchmarks of other crates.
+
+use blockdata::block::BlockHeader;
+use blockdata::opcodes;
+use blockdata::script::{Builder, Script};
+use blockdata::transaction::{InputWeightPrediction, OutPoint, Transaction, TxIn, TxOut};
+use hash_types::{BlockHash, PubkeyHash, ScriptHash, TxMerkleNode, Txid, WPubkeyHash, WScriptHash};
+use hashes::Hash;
+use util::rng::{Rng, SeededRng};
+
+/// The type of output an input of a fixture spends
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum InputKind {
+    /// P2PKH with a compressed key
+    P2pkh,
+    /// P2WPKH
+    P2wpkh,
+    /// P2WPKH nested in P2SH
+    NestedP2wpkh,
+    /// Taproot key path with the default sighash
+    P2trKey,
+}
+
+impl InputKind {
+    /// The weight prediction of an input of this kind, which matches the
+    /// inputs a fixture builds
+    pub fn weight_prediction(self) -> InputWeightPrediction {
+        match self {
+            InputKind::P2pkh => InputWeightPrediction::P2PKH_COMPRESSED_MAX,
+            InputKind::P2wpkh => InputWeightPrediction::P2WPKH_MAX,
+            InputKind::NestedP2wpkh => InputWeightPrediction::NESTED_P2WPKH_MAX,
+            InputKind::P2trKey => InputWeightPrediction::P2TR_KEY_DEFAULT_SIGHASH,
+        }
+    }
+
+    /// Whether inputs of this kind have a witness
+    pub fn is_segwit(self) -> bool {
+        self != InputKind::P2pkh
+    }
+}
+
+/// The type of an output of a fixture
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum OutputKind {
+    /// P2PKH
+    P2pkh,
+    /// P2SH
+    P2sh,
+    /// P2WPKH
+    P2wpkh,
+    /// P2WSH
+    P2wsh,
+    /// Taproot
+    P2tr,
+    /// `OP_RETURN` pushing the given number of bytes
+    OpReturn(usize),
+    /// A script of random bytes of the given length
+    Raw(usize),
+}
+
+/// Builds deterministic transactions of a configurable shape
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TxFixture {
+    seed: u64,
+    version: u32,
+    lock_time: u32,
+    inputs: Vec<InputKind>,
+    outputs: Vec<OutputKind>,
+    output_value: Option<u64>,
+}
+
+impl TxFixture {
+    /// Creates a fixture for version 2 transactions with no inputs and no
+    /// outputs, which makes up everything from `seed`
+    pub fn new(seed: u64) -> TxFixture {
+        TxFixture {
+            seed,
+            version: 2,
+            lock_time: 0,
+            inputs: vec![],
+            outputs: vec![],
+            output_value: None,
+        }
+    }
+
+    /// Sets the transaction version
+    pub fn version(mut self, version: u32) -> TxFixture {
+        self.version = version;
+        self
+    }
+
+    /// Sets the absolute timelock
+    pub fn lock_time(mut self, lock_time: u32) -> TxFixture {
+        self.lock_time = lock_time;
+        self
+    }
+
+    /// Adds `n` inputs of `kind`
+    pub fn inputs(mut self, n: usize, kind: InputKind) -> TxFixture {
+        self.inputs.extend((0..n).map(|_| kind));
+        self
+    }
+
+    /// Adds `n` outputs of `kind`
+    pub fn outputs(mut self, n: usize, kind: OutputKind) -> TxFixture {
+        self.outputs.extend((0..n).map(|_| kind));
+        self
+    }
+
+    /// Makes every output, except `OP_RETURN` ones, pay `value` satoshis
+    /// instead of a random amount below one bitcoin
+    pub fn output_value(mut self, value: u64) -> TxFixture {
+        self.output_value = Some(value);
+        self
+    }
+
+    /// The weight the transactions of this fixture have
+    pub fn predicted_weight(&self) -> usize {
+        Transaction::predict_weight(
+            self.inputs.iter().map(|kind| kind.weight_prediction()),
+            self.outputs.iter().map(|&kind| output_script_len(kind)),
+        )
+    }
+
+    /// Builds the transaction of this fixture
+    pub fn build(&self) -> Transaction {
+        self.build_with(&mut SeededRng::new(self.seed))
+    }
+
+    /// Builds `n` transactions of this fixture's shape, which differ in
+    /// everything made up
+    pub fn build_many(&self, n: usize) -> Vec<Transaction> {
+        let mut rng = SeededRng::new(self.seed);
+        (0..n).map(|_| self.build_with(&mut rng)).collect()
+    }
+
+    /// The transactions of a block at `height`: a coinbase followed by `n`
+    /// transactions of this fixture's shape
+    pub fn block_txdata(&self, height: u32, n: usize) -> Vec<Transaction> {
+        let mut rng = SeededRng::new(self.seed);
+        let coinbase_output = TxOut {
+            value: (50 * 100_000_000) >> (height / 210_000).min(63),
+            script_pubkey: output_script(OutputKind::P2wpkh, &mut rng),
+        };
+        let mut ret = vec![Transaction::new_coinbase(
+            height,
+            &random_bytes(&mut rng, 8),
+            vec![coinbase_output],
+            None,
+        )];
+        ret.extend((0..n).map(|_| self.build_with(&mut rng)));
+        ret
+    }
+
+    fn build_with<R: Rng>(&self, rng: &mut R) -> Transaction {
+        let input = self
+            .inputs
+            .iter()
+            .map(|&kind| {
+                let mut txid = [0; 32];
+                rng.fill_bytes(&mut txid);
+                let previous_output =
+                    OutPoint::new(Txid::from_inner(txid), rng.next_below(4) as u32);
+                input(kind, previous_output, rng)
+            })
+            .collect();
+        let output = self
+            .outputs
+            .iter()
+            .map(|&kind| {
+                let value = match (kind, self.output_value) {
+                    (OutputKind::OpReturn(..), _) => 0,
+                    (_, Some(value)) => value,
+                    (_, None) => 1 + rng.next_below(100_000_000),
+                };
+                TxOut {
+                    value,
+                    script_pubkey: output_script(kind, rng),
+                }
+            })
+            .collect();
+        Transaction {
+            version: self.version,
+            lock_time: self.lock_time,
+            input,
+            output,
+        }
+    }
+}
+
+/// A header with the easiest possible target on top of `prev`. `time`
+/// also sets the merkle root, so that siblings mined at different times
+/// differ.
+pub fn mine_header(prev: BlockHash, version: i32, time: u32) -> BlockHeader {
+    let mut header = BlockHeader {
+        version,
+        prev_blockhash: prev,
+        merkle_root: TxMerkleNode::hash(&time.to_le_bytes()),
+        time,
+        bits: 0x207fffff,
+        nonce: 0,
+    };
+    while !header.has_valid_pow() {
+        header.nonce += 1;
+    }
+    header
+}
+
+fn random_bytes<R: Rng>(rng: &mut R, len: usize) -> Vec<u8> {
+    let mut ret = vec![0; len];
+    rng.fill_bytes(&mut ret);
+    ret
+}
+
+/// A DER signature of the largest size with the sighash byte appended
+fn signature<R: Rng>(rng: &mut R) -> Vec<u8> {
+    random_bytes(rng, 72)
+}
+
+/// A compressed public key
+fn pubkey<R: Rng>(rng: &mut R) -> Vec<u8> {
+    let mut ret = random_bytes(rng, 33);
+    ret[0] = 2 + (ret[0] & 1);
+    ret
+}
+
+fn input<R: Rng>(kind: InputKind, previous_output: OutPoint, rng: &mut R) -> TxIn {
+    let (script_sig, witness) = match kind {
+        InputKind::P2pkh => (
+            Builder::new()
+                .push_slice(&signature(rng))
+                .push_slice(&pubkey(rng))
+                .into_script(),
+            vec![],
+        ),
+        InputKind::P2wpkh => (Script::new(), vec![signature(rng), pubkey(rng)]),
+        InputKind::NestedP2wpkh => {
+            let mut program = vec![0, 20];
+            program.extend(random_bytes(rng, 20));
+            (
+                Builder::new().push_slice(&program).into_script(),
+                vec![signature(rng), pubkey(rng)],
+            )
+        }
+        InputKind::P2trKey => (Script::new(), vec![random_bytes(rng, 64)]),
+    };
+    TxIn {
+        previous_output,
+        script_sig,
+        sequence: 0xffff_fffd,
+        witness,
+    }
+}
+
+fn output_script_len(kind: OutputKind) -> usize {
+    match kind {
+        OutputKind::P2pkh => 25,
+        OutputKind::P2sh => 23,
+        OutputKind::P2wpkh => 22,
+        OutputKind::P2wsh | OutputKind::P2tr => 34,
+        OutputKind::OpReturn(len) => Builder::new()
+            .push_opcode(opcodes::all::OP_RETURN)
+            .push_slice(&vec![0; len])
+            .into_script()
+            .len(),
+        OutputKind::Raw(len) => len,
+    }
+}
+
+fn output_script<R: Rng>(kind: OutputKind, rng: &mut R) -> Script {
+    match kind {
+        OutputKind::P2pkh => {
+            Script::new_p2pkh(&PubkeyHash::from_slice(&random_bytes(rng, 20)).unwrap())
+        }
+        OutputKind::P2sh => {
+            Script::new_p2sh(&ScriptHash::from_slice(&random_bytes(rng, 20)).unwrap())
+        }
+        OutputKind::P2wpkh => {
+            Script::new_v0_wpkh(&WPubkeyHash::from_slice(&random_bytes(rng, 20)).unwrap())
+        }
+        OutputKind::P2wsh => {
+            Script::new_v0_wsh(&WScriptHash::from_slice(&random_bytes(rng, 32)).unwrap())
+        }
+        OutputKind::P2tr => Builder::new()
+            .push_int(1)
+            .push_slice(&random_bytes(rng, 32))
+            .into_script(),
+        OutputKind::OpReturn(len) => Builder::new()
+            .push_opcode(opcodes::all::OP_RETURN)
+            .push_slice(&random_bytes(rng, len))
+            .into_script(),
+        OutputKind::Raw(len) => Script::from(random_bytes(rng, len)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use consensus::encode::{deserialize, serialize};
+
+    const INPUTS: [InputKind; 4] = [
+        InputKind::P2pkh,
+        InputKind::P2wpkh,
+        InputKind::NestedP2wpkh,
+        InputKind::P2trKey,
+    ];
+
+    #[test]
+    fn deterministic() {
+        let fixture = TxFixture::new(7)
+            .inputs(2, InputKind::P2wpkh)
+            .outputs(2, OutputKind::P2tr);
+        assert_eq!(fixture.build(), fixture.build());
+        assert_eq!(fixture.build(), fixture.build_many(3)[0]);
+        assert_ne!(fixture.build_many(2)[0], fixture.build_many(2)[1]);
+        assert_ne!(fixture.build(), fixture.clone().lock_time(1).build());
+        assert_ne!(
+            fixture.build().txid(),
+            TxFixture::new(8)
+                .inputs(2, InputKind::P2wpkh)
+                .outputs(2, OutputKind::P2tr)
+                .build()
+                .txid()
+        );
+
+        let tx = fixture.clone().output_value(546).build();
+        assert_eq!(tx.input.len(), 2);
+        assert!(tx
+            .output
+            .iter()
+            .all(|out| out.value == 546 && out.script_pubkey.is_v1_p2tr()));
+    }
+
+    #[test]
+    fn shapes() {
+        let outputs = [
+            (OutputKind::P2pkh, Script::is_p2pkh as fn(&Script) -> bool),
+            (OutputKind::P2sh, Script::is_p2sh),
+            (OutputKind::P2wpkh, Script::is_v0_p2wpkh),
+            (OutputKind::P2wsh, Script::is_v0_p2wsh),
+            (OutputKind::P2tr, Script::is_v1_p2tr),
+            (OutputKind::OpReturn(80), Script::is_op_return),
+        ];
+        for &(kind, is_kind) in outputs.iter() {
+            let tx = TxFixture::new(1).outputs(1, kind).build();
+            assert!(is_kind(&tx.output[0].script_pubkey), "{:?}", kind);
+        }
+        let tx = TxFixture::new(1).outputs(1, OutputKind::Raw(1000)).build();
+        assert_eq!(tx.output[0].script_pubkey.len(), 1000);
+
+        // Every mix of input kinds has the weight it is predicted to have
+        for (i, &a) in INPUTS.iter().enumerate() {
+            for &b in INPUTS[i..].iter() {
+                let fixture = TxFixture::new(i as u64)
+                    .inputs(1, a)
+                    .inputs(2, b)
+                    .outputs(1, OutputKind::P2wpkh)
+                    .outputs(1, OutputKind::OpReturn(40));
+                let tx = fixture.build();
+                assert_eq!(
+                    tx.get_weight(),
+                    fixture.predicted_weight(),
+                    "{:?} {:?}",
+                    a,
+                    b
+                );
+                let has_witness = a.is_segwit() || b.is_segwit();
+                assert_eq!(tx.input.iter().any(|i| !i.witness.is_empty()), has_witness);
+                assert_eq!(deserialize::<Transaction>(&serialize(&tx)).unwrap(), tx);
+            }
+        }
+    }
+
+    #[test]
+    fn block_txdata() {
+        let txdata = TxFixture::new(3)
+            .inputs(1, InputKind::P2pkh)
+            .outputs(2, OutputKind::P2pkh)
+            .block_txdata(420_000, 5);
+        assert_eq!(txdata.len(), 6);
+        assert!(txdata[0].is_coinbase());
+        assert_eq!(txdata[0].coinbase_height(), Some(420_000));
+        assert_eq!(txdata[0].output[0].value, 1_250_000_000);
+        assert!(txdata[1..].iter().all(|tx| !tx.is_coinbase()));
+    }
+}
@@ -0,0 +1,927 @@
+//! Addresses
+//!
+//! Support for ordinary base58 Bitcoin addresses and segwit addresses,
+//! which are encoded with bech32 for witness version 0 and with bech32m for
+//! later versions.
+//!
+//! # Example
+//!
+//! ```rust
+//! use std::str::FromStr;
+//! use bitcoin::util::address::{Address, AddressType};
+//!
+//! let address = Address::from_str("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4").unwrap();
+//! assert_eq!(address.address_type(), Some(AddressType::P2wpkh));
+//! assert_eq!(address.script_pubkey().address_type(), Some(AddressType::P2wpkh));
+//! ```
+
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+use std::error;
+
+use hashes::Hash;
+
+use blockdata::opcodes;
+use blockdata::script;
+use error::{ErrorCode, ErrorKind};
+use hash_types::{PubkeyHash, ScriptHash};
+use network::constants::Network;
+use network::params::{NetworkParams, NetworkRegistry};
+use util::base58;
+use util::bech32::{self, u5, FromBase32, ToBase32, Variant};
+
+/// Address error.
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    /// Base58 encoding error
+    Base58(base58::Error),
+    /// Bech32 encoding error
+    Bech32(bech32::Error),
+    /// The bech32 payload was empty
+    EmptyBech32Payload,
+    /// Script version must be 0 to 16 inclusive
+    InvalidWitnessVersion(u8),
+    /// The opcode giving the witness version is not `OP_0` to `OP_16`
+    MalformedWitnessVersion,
+    /// The witness program must be between 2 and 40 bytes in length.
+    InvalidWitnessProgramLength(usize),
+    /// A v0 witness program must be either of length 20 or 32.
+    InvalidSegwitV0ProgramLength(usize),
+    /// The checksum variant does not match the witness version: bech32 for
+    /// version 0, bech32m for the others
+    InvalidBech32Variant,
+    /// The base58 version byte or bech32 human-readable part is unknown
+    UnknownPrefix,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            Error::Base58(ref e) => write!(f, "base58: {}", e),
+            Error::Bech32(ref e) => write!(f, "bech32: {}", e),
+            Error::InvalidWitnessVersion(v) => write!(f, "invalid witness script version: {}", v),
+            Error::InvalidWitnessProgramLength(l) => write!(
+                f,
+                "the witness program must be between 2 and 40 bytes in length: length={}",
+                l
+            ),
+            Error::InvalidSegwitV0ProgramLength(l) => write!(
+                f,
+                "a v0 witness program must be either of length 20 or 32 bytes: length={}",
+                l
+            ),
+            Error::EmptyBech32Payload
+            | Error::MalformedWitnessVersion
+            | Error::InvalidBech32Variant
+            | Error::UnknownPrefix => f.write_str(error::Error::description(self)),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::Base58(ref e) => Some(e),
+            Error::Bech32(ref e) => Some(e),
+            _ => None,
+        }
+    }
+
+    fn description(&self) -> &str {
+        match *self {
+            Error::Base58(..) => "base58 error",
+            Error::Bech32(..) => "bech32 error",
+            Error::EmptyBech32Payload => "the bech32 payload was empty",
+            Error::InvalidWitnessVersion(..) => "invalid witness script version",
+            Error::MalformedWitnessVersion => "witness version opcode is not a small number push",
+            Error::InvalidWitnessProgramLength(..) => "invalid witness program length",
+            Error::InvalidSegwitV0ProgramLength(..) => "invalid segwit v0 program length",
+            Error::InvalidBech32Variant => "invalid bech32 checksum variant for witness version",
+            Error::UnknownPrefix => "unknown address prefix",
+        }
+    }
+}
+
+impl ErrorCode for Error {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Address
+    }
+
+    fn number(&self) -> u32 {
+        match *self {
+            Error::Base58(..) => 1,
+            Error::Bech32(..) => 2,
+            Error::EmptyBech32Payload => 3,
+            Error::InvalidWitnessVersion(..) => 4,
+            Error::MalformedWitnessVersion => 5,
+            Error::InvalidWitnessProgramLength(..) => 6,
+            Error::InvalidSegwitV0ProgramLength(..) => 7,
+            Error::InvalidBech32Variant => 8,
+            Error::UnknownPrefix => 9,
+        }
+    }
+}
+
+#[doc(hidden)]
+impl From<base58::Error> for Error {
+    fn from(e: base58::Error) -> Error {
+        Error::Base58(e)
+    }
+}
+
+#[doc(hidden)]
+impl From<bech32::Error> for Error {
+    fn from(e: bech32::Error) -> Error {
+        Error::Bech32(e)
+    }
+}
+
+/// The different types of addresses.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub enum AddressType {
+    /// pay-to-pubkey-hash
+    P2pkh,
+    /// pay-to-script-hash
+    P2sh,
+    /// pay-to-witness-pubkey-hash
+    P2wpkh,
+    /// pay-to-witness-script-hash
+    P2wsh,
+    /// pay-to-taproot
+    P2tr,
+}
+
+impl fmt::Display for AddressType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            AddressType::P2pkh => "p2pkh",
+            AddressType::P2sh => "p2sh",
+            AddressType::P2wpkh => "p2wpkh",
+            AddressType::P2wsh => "p2wsh",
+            AddressType::P2tr => "p2tr",
+        })
+    }
+}
+
+impl FromStr for AddressType {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "p2pkh" => Ok(AddressType::P2pkh),
+            "p2sh" => Ok(AddressType::P2sh),
+            "p2wpkh" => Ok(AddressType::P2wpkh),
+            "p2wsh" => Ok(AddressType::P2wsh),
+            "p2tr" => Ok(AddressType::P2tr),
+            _ => Err(()),
+        }
+    }
+}
+
+/// The version of a witness program.
+///
+/// Only versions 0 and 1 have spending rules so far. Outputs of the other
+/// versions can be created and are relayed, as BIP173 and BIP350 require
+/// addresses of every version to be accepted, but until a soft fork gives
+/// a version its rules, spends of it are not relayed.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub enum WitnessVersion {
+    /// Version 0, of segwit (BIP141)
+    V0 = 0,
+    /// Version 1, of taproot (BIP341)
+    V1 = 1,
+    /// Version 2
+    V2 = 2,
+    /// Version 3
+    V3 = 3,
+    /// Version 4
+    V4 = 4,
+    /// Version 5
+    V5 = 5,
+    /// Version 6
+    V6 = 6,
+    /// Version 7
+    V7 = 7,
+    /// Version 8
+    V8 = 8,
+    /// Version 9
+    V9 = 9,
+    /// Version 10
+    V10 = 10,
+    /// Version 11
+    V11 = 11,
+    /// Version 12
+    V12 = 12,
+    /// Version 13
+    V13 = 13,
+    /// Version 14
+    V14 = 14,
+    /// Version 15
+    V15 = 15,
+    /// Version 16
+    V16 = 16,
+}
+
+impl WitnessVersion {
+    const ALL: [WitnessVersion; 17] = [
+        WitnessVersion::V0,
+        WitnessVersion::V1,
+        WitnessVersion::V2,
+        WitnessVersion::V3,
+        WitnessVersion::V4,
+        WitnessVersion::V5,
+        WitnessVersion::V6,
+        WitnessVersion::V7,
+        WitnessVersion::V8,
+        WitnessVersion::V9,
+        WitnessVersion::V10,
+        WitnessVersion::V11,
+        WitnessVersion::V12,
+        WitnessVersion::V13,
+        WitnessVersion::V14,
+        WitnessVersion::V15,
+        WitnessVersion::V16,
+    ];
+
+    /// The version with the given number
+    pub fn from_num(n: u8) -> Result<WitnessVersion, Error> {
+        WitnessVersion::ALL
+            .get(n as usize)
+            .cloned()
+            .ok_or(Error::InvalidWitnessVersion(n))
+    }
+
+    /// The version given by the first 5-bit value of a bech32 address
+    pub fn from_u5(value: u5) -> Result<WitnessVersion, Error> {
+        WitnessVersion::from_num(value.to_u8())
+    }
+
+    /// The version pushed by the first opcode of a witness program
+    pub fn from_opcode(opcode: opcodes::All) -> Result<WitnessVersion, Error> {
+        match opcode.into_u8() {
+            0 => Ok(WitnessVersion::V0),
+            code if code >= opcodes::all::OP_PUSHNUM_1.into_u8()
+                && code <= opcodes::all::OP_PUSHNUM_16.into_u8() =>
+            {
+                WitnessVersion::from_num(code - opcodes::all::OP_PUSHNUM_1.into_u8() + 1)
+            }
+            _ => Err(Error::MalformedWitnessVersion),
+        }
+    }
+
+    /// The number of the version
+    pub fn into_num(self) -> u8 {
+        self as u8
+    }
+
+    /// The 5-bit value of the version, which starts the data of a bech32
+    /// address
+    pub fn into_u5(self) -> u5 {
+        u5::try_from_u8(self.into_num()).expect("0..=16 fits in 5 bits")
+    }
+
+    /// The opcode pushing the version, which starts a witness program
+    pub fn into_opcode(self) -> opcodes::All {
+        match self {
+            WitnessVersion::V0 => opcodes::all::OP_PUSHBYTES_0,
+            v => opcodes::All::from(opcodes::all::OP_PUSHNUM_1.into_u8() + v.into_num() - 1),
+        }
+    }
+
+    /// The bech32 checksum variant of addresses of this version
+    pub fn bech32_variant(self) -> Variant {
+        match self {
+            WitnessVersion::V0 => Variant::Bech32,
+            _ => Variant::Bech32m,
+        }
+    }
+
+    /// Whether outputs of this version have spending rules, rather than
+    /// being reserved for future soft forks
+    pub fn is_known(self) -> bool {
+        self <= WitnessVersion::V1
+    }
+}
+
+impl fmt::Display for WitnessVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.into_num(), f)
+    }
+}
+
+/// The method used to produce an address
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Payload {
+    /// P2PKH address
+    PubkeyHash(PubkeyHash),
+    /// P2SH address
+    ScriptHash(ScriptHash),
+    /// Segwit address
+    WitnessProgram {
+        /// The witness program version
+        version: WitnessVersion,
+        /// The witness program
+        program: Vec<u8>,
+    },
+}
+
+impl Payload {
+    /// Get a [Payload] from an output script (scriptPubkey).
+    pub fn from_script(script: &script::Script) -> Option<Payload> {
+        Some(if script.is_p2pkh() {
+            Payload::PubkeyHash(PubkeyHash::from_slice(&script.as_bytes()[3..23]).unwrap())
+        } else if script.is_p2sh() {
+            Payload::ScriptHash(ScriptHash::from_slice(&script.as_bytes()[2..22]).unwrap())
+        } else if let Some(version) = script.witness_version() {
+            Payload::WitnessProgram {
+                version,
+                program: script.as_bytes()[2..].to_vec(),
+            }
+        } else {
+            return None;
+        })
+    }
+
+    /// Generates a script pubkey spending to this [Payload].
+    pub fn script_pubkey(&self) -> script::Script {
+        match *self {
+            Payload::PubkeyHash(ref hash) => script::Script::new_p2pkh(hash),
+            Payload::ScriptHash(ref hash) => script::Script::new_p2sh(hash),
+            Payload::WitnessProgram {
+                version: ver,
+                program: ref prog,
+            } => script::Builder::new()
+                .push_opcode(ver.into_opcode())
+                .push_slice(prog)
+                .into_script(),
+        }
+    }
+}
+
+/// A Bitcoin address
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct Address {
+    /// The type of the address
+    pub payload: Payload,
+    /// The network on which this address is usable
+    pub network: Network,
+}
+
+impl Address {
+    /// Creates a pay to (compressed) public key hash address from the hash
+    /// of a public key
+    pub fn p2pkh_from_hash(pubkey_hash: PubkeyHash, network: Network) -> Address {
+        Address {
+            network,
+            payload: Payload::PubkeyHash(pubkey_hash),
+        }
+    }
+
+    /// Creates a pay to script hash P2SH address from a script
+    /// This address type was introduced with BIP16 and is the popular type
+    /// to implement multi-sig these days.
+    pub fn p2sh(script: &script::Script, network: Network) -> Address {
+        Address {
+            network,
+            payload: Payload::ScriptHash(script.script_hash()),
+        }
+    }
+
+    /// Create a witness pay to script hash address
+    pub fn p2wsh(script: &script::Script, network: Network) -> Address {
+        Address {
+            network,
+            payload: Payload::WitnessProgram {
+                version: WitnessVersion::V0,
+                program: script.wscript_hash()[..].to_vec(),
+            },
+        }
+    }
+
+    /// Create a pay to script address that embeds a witness pay to script
+    /// hash address. This is a segwit address type that looks familiar (as
+    /// p2sh) to legacy clients
+    pub fn p2shwsh(script: &script::Script, network: Network) -> Address {
+        Address::p2sh(&script.to_v0_p2wsh(), network)
+    }
+
+    /// Get the address type of the address.
+    /// None if unknown or non-standard.
+    pub fn address_type(&self) -> Option<AddressType> {
+        match self.payload {
+            Payload::PubkeyHash(_) => Some(AddressType::P2pkh),
+            Payload::ScriptHash(_) => Some(AddressType::P2sh),
+            Payload::WitnessProgram {
+                version: ver,
+                program: ref prog,
+            } => {
+                // BIP-141 p2wpkh or p2wsh addresses.
+                match (ver, prog.len()) {
+                    (WitnessVersion::V0, 20) => Some(AddressType::P2wpkh),
+                    (WitnessVersion::V0, 32) => Some(AddressType::P2wsh),
+                    (WitnessVersion::V1, 32) => Some(AddressType::P2tr),
+                    _ => None,
+                }
+            }
+        }
+    }
+
+    /// Check whether or not the address is following Bitcoin
+    /// standardness rules.
+    ///
+    /// Segwit addresses with unassigned witness versions or non-standard
+    /// program sizes are considered non-standard.
+    pub fn is_standard(&self) -> bool {
+        self.address_type().is_some()
+    }
+
+    /// Get an [Address] from an output script (scriptPubkey).
+    pub fn from_script(script: &script::Script, network: Network) -> Option<Address> {
+        Some(Address {
+            payload: Payload::from_script(script)?,
+            network,
+        })
+    }
+
+    /// Generates a script pubkey spending to this address
+    pub fn script_pubkey(&self) -> script::Script {
+        self.payload.script_pubkey()
+    }
+
+    /// A BIP21 URI of the address for a QR code. Bech32 addresses are in
+    /// upper case, with the scheme, so that the QR code can use its denser
+    /// alphanumeric mode; base58 addresses are case-sensitive and are kept
+    /// as they are.
+    pub fn to_qr_uri(&self) -> String {
+        match self.payload {
+            Payload::WitnessProgram { .. } => format!("BITCOIN:{:#}", self),
+            Payload::PubkeyHash(..) | Payload::ScriptHash(..) => format!("bitcoin:{}", self),
+        }
+    }
+}
+
+/// Displays the address, in upper case for `{:#}` if it is bech32
+impl Display for Address {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        Display::fmt(&self.payload.with_params(self.network.params()), fmt)
+    }
+}
+
+impl ::std::fmt::Debug for Address {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+/// Displays a payload as an address of the chain with the given
+/// parameters, as returned by `Payload::with_params`. The alternate form,
+/// `{:#}`, displays bech32 addresses in upper case.
+#[derive(Copy, Clone, Debug)]
+pub struct AddressEncoding<'a> {
+    payload: &'a Payload,
+    params: &'a NetworkParams,
+}
+
+impl<'a> Display for AddressEncoding<'a> {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        match *self.payload {
+            Payload::PubkeyHash(ref hash) => {
+                let mut prefixed = [0; 21];
+                prefixed[0] = self.params.p2pkh_prefix;
+                prefixed[1..].copy_from_slice(&hash[..]);
+                fmt.write_str(&base58::check_encode_slice(&prefixed[..]))
+            }
+            Payload::ScriptHash(ref hash) => {
+                let mut prefixed = [0; 21];
+                prefixed[0] = self.params.p2sh_prefix;
+                prefixed[1..].copy_from_slice(&hash[..]);
+                fmt.write_str(&base58::check_encode_slice(&prefixed[..]))
+            }
+            Payload::WitnessProgram {
+                version: ver,
+                program: ref prog,
+            } => {
+                let mut data = vec![ver.into_u5()];
+                data.extend(prog.to_base32());
+                let encoded = if fmt.alternate() {
+                    bech32::encode_upper(self.params.bech32_hrp, &data, ver.bech32_variant())
+                } else {
+                    bech32::encode(self.params.bech32_hrp, &data, ver.bech32_variant())
+                };
+                let encoded = encoded.map_err(|_| fmt::Error)?;
+                fmt.write_str(&encoded)
+            }
+        }
+    }
+}
+
+/// Extract the bech32 prefix.
+/// Returns the same slice when no prefix is found.
+fn find_bech32_prefix(bech32: &str) -> &str {
+    // Split at the last occurrence of the separator character '1'.
+    match bech32.rfind('1') {
+        None => bech32,
+        Some(sep) => bech32.split_at(sep).0,
+    }
+}
+
+impl Payload {
+    /// Displays the payload as an address of the chain with `params`
+    pub fn with_params<'a>(&'a self, params: &'a NetworkParams) -> AddressEncoding<'a> {
+        AddressEncoding {
+            payload: self,
+            params,
+        }
+    }
+
+    /// Parses an address of any chain in `registry`, returning its payload
+    /// and the parameters of its chain. Bech32 addresses may also be in
+    /// upper case.
+    pub fn from_str_with_registry<'r>(
+        s: &str,
+        registry: &'r NetworkRegistry,
+    ) -> Result<(Payload, &'r NetworkParams), Error> {
+        // try bech32
+        if let Some(params) = registry.by_bech32_hrp(find_bech32_prefix(s)) {
+            // decode as bech32
+            let (_, payload, variant) = bech32::decode(s)?;
+            if payload.is_empty() {
+                return Err(Error::EmptyBech32Payload);
+            }
+
+            // Get the script version and program (converted from 5-bit to 8-bit)
+            let (version, program): (WitnessVersion, Vec<u8>) = {
+                let (v, p5) = payload.split_at(1);
+                (WitnessVersion::from_u5(v[0])?, FromBase32::from_base32(p5)?)
+            };
+
+            // Generic segwit checks.
+            if program.len() < 2 || program.len() > 40 {
+                return Err(Error::InvalidWitnessProgramLength(program.len()));
+            }
+
+            // Specific segwit v0 check.
+            if version == WitnessVersion::V0 && (program.len() != 20 && program.len() != 32) {
+                return Err(Error::InvalidSegwitV0ProgramLength(program.len()));
+            }
+
+            if variant != version.bech32_variant() {
+                return Err(Error::InvalidBech32Variant);
+            }
+
+            return Ok((Payload::WitnessProgram { version, program }, params));
+        }
+
+        // Base58
+        if s.len() > 50 {
+            return Err(Error::Base58(base58::Error::InvalidLength(
+                s.len() * 11 / 15,
+            )));
+        }
+        let data = base58::from_check(s)?;
+        if data.len() != 21 {
+            return Err(Error::Base58(base58::Error::InvalidLength(data.len())));
+        }
+
+        let params = registry
+            .by_base58_prefix(data[0])
+            .ok_or(Error::UnknownPrefix)?;
+        let payload = if data[0] == params.p2pkh_prefix {
+            Payload::PubkeyHash(PubkeyHash::from_slice(&data[1..]).unwrap())
+        } else {
+            Payload::ScriptHash(ScriptHash::from_slice(&data[1..]).unwrap())
+        };
+        Ok((payload, params))
+    }
+}
+
+/// Parses an address as it is displayed. Bech32 addresses may also be in
+/// upper case, and are displayed in lower case. Testnet and regtest share
+/// their base58 prefixes, so a regtest base58 address parses as a testnet
+/// one.
+impl FromStr for Address {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Address, Error> {
+        let registry = NetworkRegistry::default();
+        let (payload, params) = Payload::from_str_with_registry(s, &registry)?;
+        let network =
+            Network::from_params(params).expect("the default registry only has Bitcoin's networks");
+        Ok(Address { network, payload })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use blockdata::script::Script;
+    use hashes::hex::FromHex;
+    use network::constants::Magic;
+    use network::constants::Network::{Bitcoin, Testnet};
+
+    macro_rules! hex (($hex:expr) => (Vec::from_hex($hex).unwrap()));
+
+    fn roundtrips(addr: &Address) {
+        roundtrip_fromstr_display!(Address; addr.clone());
+        assert_eq!(
+            Address::from_script(&addr.script_pubkey(), addr.network).as_ref(),
+            Some(addr),
+            "script round-trip failed for {}",
+            addr,
+        );
+    }
+
+    #[test]
+    fn test_p2pkh_address_58() {
+        let addr = Address {
+            network: Bitcoin,
+            payload: Payload::PubkeyHash(
+                PubkeyHash::from_slice(&hex!("62e907b15cbf27d5425399ebf6f0fb50ebb88f18")).unwrap(),
+            ),
+        };
+
+        assert_eq!(
+            addr.script_pubkey(),
+            hex_script!("76a91462e907b15cbf27d5425399ebf6f0fb50ebb88f1888ac")
+        );
+        assert_eq!(&addr.to_string(), "1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa");
+        assert_eq!(addr.address_type(), Some(AddressType::P2pkh));
+        roundtrips(&addr);
+    }
+
+    #[test]
+    fn test_p2sh_address_58() {
+        let addr = Address {
+            network: Bitcoin,
+            payload: Payload::ScriptHash(
+                ScriptHash::from_slice(&hex!("162c5ea71c0b23f5b9022ef047c4a86470a5b070")).unwrap(),
+            ),
+        };
+
+        assert_eq!(
+            addr.script_pubkey(),
+            hex_script!("a914162c5ea71c0b23f5b9022ef047c4a86470a5b07087")
+        );
+        assert_eq!(&addr.to_string(), "33iFwdLuRpW1uK1RTRqsoi8rR4NpDzk66k");
+        assert_eq!(addr.address_type(), Some(AddressType::P2sh));
+        roundtrips(&addr);
+    }
+
+    #[test]
+    fn test_p2wsh() {
+        // stolen from Bitcoin transaction 5df912fda4becb1c29e928bec8d64d93e9ba8efa9b5b405bd683c86fd2c65667
+        let script = hex_script!("52210375e00eb72e29da82b89367947f29ef34afb75e8654f6ea368e0acdfd92976b7c2103a1b26313f430c4b15bb1fdce663207659d8cac749a0e53d70eff01874496feff2103c96d495bfdd5ba4145e3e046fee45e84a8a48ad05bd8dbb395c011a32cf9f88053ae");
+        let addr = Address::p2wsh(&script, Bitcoin);
+        assert_eq!(
+            &addr.to_string(),
+            "bc1qwqdg6squsna38e46795at95yu9atm8azzmyvckulcc7kytlcckxswvvzej"
+        );
+        assert_eq!(addr.address_type(), Some(AddressType::P2wsh));
+        roundtrips(&addr);
+    }
+
+    #[test]
+    fn test_p2shwsh() {
+        // stolen from Bitcoin transaction f9ee2be4df05041d0e0a35d7caa3157495ca4f93b233234c9967b6901dacf7a9
+        let script = hex_script!("522103e5529d8eaa3d559903adb2e881eb06c86ac2574ffa503c45f4e942e2a693b33e2102e5f10fcdcdbab211e0af6a481f5532536ec61a5fdbf7183770cf8680fe729d8152ae");
+        let addr = Address::p2shwsh(&script, Bitcoin);
+        assert_eq!(&addr.to_string(), "36EqgNnsWW94SreZgBWc1ANC6wpFZwirHr");
+        assert_eq!(addr.address_type(), Some(AddressType::P2sh));
+        roundtrips(&addr);
+    }
+
+    #[test]
+    fn test_bip173_350_vectors() {
+        let valid_vectors = [
+            (
+                "BC1QW508D6QEJXTDG4Y5R3ZARVARY0C5XW7KV8F3T4",
+                "0014751e76e8199196d454941c45d1b3a323f1433bd6",
+                Some(AddressType::P2wpkh),
+            ),
+            (
+                "tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3q0sl5k7",
+                "00201863143c14c5166804bd19203356da136c985678cd4d27a1b8c6329604903262",
+                Some(AddressType::P2wsh),
+            ),
+            (
+                "bc1pw508d6qejxtdg4y5r3zarvary0c5xw7kw508d6qejxtdg4y5r3zarvary0c5xw7kt5nd6y",
+                "5128751e76e8199196d454941c45d1b3a323f1433bd6751e76e8199196d454941c45d1b3a323f1433bd6",
+                None,
+            ),
+            ("BC1SW50QGDZ25J", "6002751e", None),
+            (
+                "bc1zw508d6qejxtdg4y5r3zarvaryvaxxpcs",
+                "5210751e76e8199196d454941c45d1b3a323",
+                None,
+            ),
+            (
+                "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqzk5jj0",
+                "512079be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+                Some(AddressType::P2tr),
+            ),
+        ];
+        for &(addr, script, ty) in valid_vectors.iter() {
+            let parsed = Address::from_str(addr).unwrap();
+            let script = Script::from(hex!(script));
+            assert_eq!(parsed.script_pubkey(), script);
+            assert_eq!(parsed.address_type(), ty);
+            assert_eq!(script.address_type(), ty);
+            assert_eq!(parsed.to_string(), addr.to_lowercase());
+            roundtrips(&parsed);
+        }
+
+        let invalid_vectors = [
+            // bech32 checksum on witness version 1
+            "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqh2y7hd",
+            // bech32m checksum on witness version 0
+            "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kemeawh",
+            // invalid program length for witness version 0
+            "BC1QR508D6QEJXTDG4Y5R3ZARVARYV98GJ9P",
+            // mixed case
+            "tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3q0sL5k7",
+            // empty data section
+            "bc1gmk9yu",
+        ];
+        for addr in invalid_vectors.iter() {
+            assert!(Address::from_str(addr).is_err(), "{} parsed", addr);
+        }
+    }
+
+    #[test]
+    fn test_witness_version() {
+        for n in 0..17 {
+            let version = WitnessVersion::from_num(n).unwrap();
+            assert_eq!(version.into_num(), n);
+            assert_eq!(WitnessVersion::from_u5(version.into_u5()), Ok(version));
+            assert_eq!(WitnessVersion::from_opcode(version.into_opcode()), Ok(version));
+            assert_eq!(version.is_known(), n < 2);
+        }
+        assert_eq!(
+            WitnessVersion::from_num(17),
+            Err(Error::InvalidWitnessVersion(17))
+        );
+        assert_eq!(
+            WitnessVersion::from_opcode(opcodes::all::OP_PUSHNUM_NEG1),
+            Err(Error::MalformedWitnessVersion)
+        );
+        assert_eq!(WitnessVersion::V16.to_string(), "16");
+
+        // A future version pays to a valid address, which is not standard
+        let addr = Address::from_str("BC1SW50QGDZ25J").unwrap();
+        assert_eq!(
+            addr.payload,
+            Payload::WitnessProgram {
+                version: WitnessVersion::V16,
+                program: hex!("751e"),
+            }
+        );
+        assert!(!addr.is_standard());
+    }
+
+    #[test]
+    fn test_address_type() {
+        let samples = [
+            ("1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa", AddressType::P2pkh),
+            ("33iFwdLuRpW1uK1RTRqsoi8rR4NpDzk66k", AddressType::P2sh),
+            (
+                "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4",
+                AddressType::P2wpkh,
+            ),
+            (
+                "bc1qwqdg6squsna38e46795at95yu9atm8azzmyvckulcc7kytlcckxswvvzej",
+                AddressType::P2wsh,
+            ),
+            (
+                "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqzk5jj0",
+                AddressType::P2tr,
+            ),
+        ];
+        for &(addr, ty) in samples.iter() {
+            let addr = Address::from_str(addr).unwrap();
+            assert_eq!(addr.network, Bitcoin);
+            assert_eq!(addr.address_type(), Some(ty));
+            assert_eq!(addr.script_pubkey().address_type(), Some(ty));
+            assert!(addr.is_standard());
+            assert_eq!(AddressType::from_str(&ty.to_string()), Ok(ty));
+        }
+
+        assert_eq!(hex_script!("6a").address_type(), None);
+        assert_eq!(Address::from_script(&hex_script!("6a"), Bitcoin), None);
+        assert_eq!(AddressType::from_str("p2pk"), Err(()));
+    }
+
+    #[test]
+    fn test_noncanonical_strings() {
+        let addr = "BC1QW508D6QEJXTDG4Y5R3ZARVARY0C5XW7KV8F3T4";
+        assert_eq!(
+            Address::from_str(addr).unwrap().to_string(),
+            addr.to_lowercase()
+        );
+
+        let hash = PubkeyHash::from_slice(&hex!("62e907b15cbf27d5425399ebf6f0fb50ebb88f18"));
+        let regtest = Address::p2pkh_from_hash(hash.unwrap(), Network::Regtest);
+        let parsed = Address::from_str(&regtest.to_string()).unwrap();
+        assert_eq!(parsed.network, Testnet);
+        assert_eq!(parsed.payload, regtest.payload);
+
+        roundtrip_fromstr_display!(
+            AddressType;
+            AddressType::P2pkh,
+            AddressType::P2sh,
+            AddressType::P2wpkh,
+            AddressType::P2wsh,
+            AddressType::P2tr,
+        );
+    }
+
+    #[test]
+    fn test_qr_uri() {
+        let lower = "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4";
+        let addr = Address::from_str(lower).unwrap();
+        assert_eq!(format!("{:#}", addr), lower.to_uppercase());
+        assert_eq!(addr.to_qr_uri(), format!("BITCOIN:{}", lower.to_uppercase()));
+        assert_eq!(Address::from_str(&format!("{:#}", addr)).unwrap(), addr);
+        assert_eq!(
+            Address::from_str("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kV8F3T4"),
+            Err(Error::Bech32(bech32::Error::MixedCase))
+        );
+
+        let taproot = "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqzk5jj0";
+        let addr = Address::from_str(&taproot.to_uppercase()).unwrap();
+        assert_eq!(addr.to_string(), taproot);
+        assert_eq!(addr.to_qr_uri(), format!("BITCOIN:{}", taproot.to_uppercase()));
+
+        let base58 = "1QJVDzdqb1VpbDK7uDeyVXy9mR27CJiyhY";
+        let addr = Address::from_str(base58).unwrap();
+        assert_eq!(format!("{:#}", addr), base58);
+        assert_eq!(addr.to_qr_uri(), format!("bitcoin:{}", base58));
+    }
+
+    #[test]
+    fn test_testnet_base58() {
+        let addr = Address::p2pkh_from_hash(
+            PubkeyHash::from_slice(&hex!("62e907b15cbf27d5425399ebf6f0fb50ebb88f18")).unwrap(),
+            Testnet,
+        );
+        let s = addr.to_string();
+        assert!(s.starts_with('m') || s.starts_with('n'));
+        roundtrips(&addr);
+        match Address::from_str("1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNb") {
+            Err(Error::Base58(base58::Error::BadChecksum(..))) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_custom_chain() {
+        let litecoin = NetworkParams {
+            magic: Magic::from(0xdbb6c0fb),
+            p2pkh_prefix: 48,
+            p2sh_prefix: 50,
+            bech32_hrp: "ltc",
+            silent_payment_hrp: "ltcsp",
+        };
+        let mut registry = NetworkRegistry::default();
+        registry.register(litecoin);
+
+        let hash =
+            PubkeyHash::from_slice(&hex!("62e907b15cbf27d5425399ebf6f0fb50ebb88f18")).unwrap();
+        let payloads = [
+            Payload::PubkeyHash(hash),
+            Payload::ScriptHash(ScriptHash::from_slice(&hash[..]).unwrap()),
+            Payload::WitnessProgram {
+                version: WitnessVersion::V0,
+                program: hash[..].to_vec(),
+            },
+        ];
+        for payload in payloads.iter() {
+            let encoded = payload.with_params(&litecoin).to_string();
+            let (parsed, params) = Payload::from_str_with_registry(&encoded, &registry).unwrap();
+            assert_eq!(&parsed, payload);
+            assert_eq!(params, &litecoin);
+            assert!(Address::from_str(&encoded).is_err());
+        }
+        assert!(Payload::PubkeyHash(hash)
+            .with_params(&litecoin)
+            .to_string()
+            .starts_with('L'));
+        assert!(payloads[2]
+            .with_params(&litecoin)
+            .to_string()
+            .starts_with("ltc1"));
+
+        // Bitcoin's addresses display as before
+        for &network in Network::ALL.iter() {
+            for payload in payloads.iter() {
+                let addr = Address {
+                    network,
+                    payload: payload.clone(),
+                };
+                assert_eq!(
+                    addr.to_string(),
+                    payload.with_params(network.params()).to_string()
+                );
+            }
+        }
+    }
+}
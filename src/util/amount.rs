@@ -20,6 +20,8 @@ use std::fmt::{self, Write};
 use std::ops;
 use std::str::FromStr;
 
+use error::{ErrorCode, ErrorKind};
+
 /// A set of denominations in which amounts can be expressed.
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
 pub enum Denomination {
@@ -38,6 +40,20 @@ pub enum Denomination {
 }
 
 impl Denomination {
+    /// The name a denomination is displayed as, which is also the name
+    /// [FromStr] should be given. `Satoshi` is parsed from "sat" too, but
+    /// always displayed as "satoshi".
+    pub fn as_canonical_str(self) -> &'static str {
+        match self {
+            Denomination::Bitcoin => "BTC",
+            Denomination::MilliBitcoin => "mBTC",
+            Denomination::MicroBitcoin => "uBTC",
+            Denomination::Bit => "bits",
+            Denomination::Satoshi => "satoshi",
+            Denomination::MilliSatoshi => "msat",
+        }
+    }
+
     /// The number of decimal places more than a satoshi.
     fn precision(self) -> i32 {
         match self {
@@ -53,17 +69,12 @@ impl Denomination {
 
 impl fmt::Display for Denomination {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.write_str(match *self {
-            Denomination::Bitcoin => "BTC",
-            Denomination::MilliBitcoin => "mBTC",
-            Denomination::MicroBitcoin => "uBTC",
-            Denomination::Bit => "bits",
-            Denomination::Satoshi => "satoshi",
-            Denomination::MilliSatoshi => "msat",
-        })
+        f.write_str(self.as_canonical_str())
     }
 }
 
+/// Parses the names denominations are displayed as, and "sat" for
+/// `Satoshi`.
 impl FromStr for Denomination {
     type Err = ParseAmountError;
 
@@ -81,6 +92,35 @@ impl FromStr for Denomination {
     }
 }
 
+/// The policy used for amount strings which may lack a denomination
+/// suffix.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum ParseMode {
+    /// A denomination suffix is mandatory, like with [FromStr].
+    RequireDenomination,
+    /// Amounts without a denomination suffix are taken to be in satoshi.
+    AssumeSat,
+    /// Amounts without a denomination suffix are taken to be in BTC.
+    AssumeBtc,
+}
+
+impl ParseMode {
+    /// The denomination assumed when none is given, if any.
+    fn default_denomination(self) -> Option<Denomination> {
+        match self {
+            ParseMode::RequireDenomination => None,
+            ParseMode::AssumeSat => Some(Denomination::Satoshi),
+            ParseMode::AssumeBtc => Some(Denomination::Bitcoin),
+        }
+    }
+}
+
+impl default::Default for ParseMode {
+    fn default() -> Self {
+        ParseMode::RequireDenomination
+    }
+}
+
 /// An error during amount parsing.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ParseAmountError {
@@ -129,6 +169,90 @@ impl error::Error for ParseAmountError {
     }
 }
 
+impl ErrorCode for ParseAmountError {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Amount
+    }
+
+    fn number(&self) -> u32 {
+        match *self {
+            ParseAmountError::Negative => 1,
+            ParseAmountError::TooBig => 2,
+            ParseAmountError::TooPrecise => 3,
+            ParseAmountError::InvalidFormat => 4,
+            ParseAmountError::InputTooLarge => 5,
+            ParseAmountError::InvalidCharacter(..) => 6,
+            ParseAmountError::UnknownDenomination(..) => 7,
+        }
+    }
+}
+
+/// Split an amount string into its value and denomination, applying
+/// the given [ParseMode] when the denomination suffix is missing.
+fn split_denomination(s: &str, mode: ParseMode) -> Result<(&str, Denomination), ParseAmountError> {
+    let mut split = s.splitn(3, " ");
+    let amt_str = split.next().unwrap();
+    let denom_str = split.next();
+    if split.next().is_some() {
+        return Err(ParseAmountError::InvalidFormat);
+    }
+
+    let denom = match denom_str {
+        Some(denom_str) => denom_str.parse()?,
+        None => mode
+            .default_denomination()
+            .ok_or(ParseAmountError::InvalidFormat)?,
+    };
+    Ok((amt_str, denom))
+}
+
+/// Split an amount string with grouped digits into its value, with the
+/// separators removed, and its denomination, which is mandatory.
+fn split_grouped(s: &str, separator: char) -> Result<(String, Denomination), ParseAmountError> {
+    let split = s.rfind(' ').ok_or(ParseAmountError::InvalidFormat)?;
+    let denom = s[split + 1..].parse()?;
+    Ok((ungroup_digits(&s[..split], separator)?, denom))
+}
+
+/// Remove the separators from the integer part of a decimal string. They
+/// may only stand between two digits, and never after the decimal point.
+fn ungroup_digits(s: &str, separator: char) -> Result<String, ParseAmountError> {
+    if separator.is_ascii_digit() || separator == '.' || separator == '-' {
+        return Err(ParseAmountError::InvalidFormat);
+    }
+    let int_len = s.find('.').unwrap_or(s.len());
+    let mut ret = String::with_capacity(s.len());
+    let mut prev = None;
+    for (i, c) in s.char_indices() {
+        if c == separator {
+            let next = s[i + c.len_utf8()..].chars().next();
+            let is_digit = |c: Option<char>| c.is_some_and(|c| c.is_ascii_digit());
+            if i > int_len || !is_digit(prev) || !is_digit(next) {
+                return Err(ParseAmountError::InvalidFormat);
+            }
+        } else {
+            ret.push(c);
+        }
+        prev = Some(c);
+    }
+    Ok(ret)
+}
+
+/// Insert the separator between every three digits of the integer part of
+/// a decimal string without a sign.
+fn group_digits(s: &str, separator: char) -> String {
+    let int_len = s.find('.').unwrap_or(s.len());
+    let mut ret = String::with_capacity(s.len() + int_len / 3 * separator.len_utf8());
+    for (i, c) in s[..int_len].chars().enumerate() {
+        if i > 0 && (int_len - i) % 3 == 0 {
+            ret.push(separator);
+        }
+        ret.push(c);
+    }
+    ret.push_str(&s[int_len..]);
+    ret
+}
+
 fn is_too_precise(s: &str, precision: usize) -> bool {
     s.contains(".") || precision >= s.len() || s.chars().rev().take(precision).any(|d| d != '0')
 }
@@ -179,6 +303,14 @@ fn parse_signed_to_satoshi(
     for c in s.chars() {
         match c {
             '0'...'9' => {
+                // Increment the decimal digit counter if past decimal. This
+                // comes first so that an excess decimal is reported as such
+                // even if the digits would overflow.
+                decimals = match decimals {
+                    None => None,
+                    Some(d) if d < max_decimals => Some(d + 1),
+                    _ => return Err(ParseAmountError::TooPrecise),
+                };
                 // Do `value = 10 * value + digit`, catching overflows.
                 match 10_u64.checked_mul(value) {
                     None => return Err(ParseAmountError::TooBig),
@@ -187,12 +319,6 @@ fn parse_signed_to_satoshi(
                         Some(val) => value = val,
                     },
                 }
-                // Increment the decimal digit counter if past decimal.
-                decimals = match decimals {
-                    None => None,
-                    Some(d) if d < max_decimals => Some(d + 1),
-                    _ => return Err(ParseAmountError::TooPrecise),
-                };
             }
             '.' => match decimals {
                 None => decimals = Some(0),
@@ -217,42 +343,101 @@ fn parse_signed_to_satoshi(
 
 /// Format the given satoshi amount in the given denomination.
 ///
+/// With a `precision`, exactly that many decimal places are written, rounding
+/// half up or padding with zeroes as needed. Otherwise as many are written as
+/// the denomination has.
+///
 /// Does not include the denomination.
 fn fmt_satoshi_in(
     satoshi: u64,
     negative: bool,
     f: &mut fmt::Write,
     denom: Denomination,
+    precision: Option<usize>,
 ) -> fmt::Result {
     if negative {
         f.write_str("-")?;
     }
 
-    if denom.precision() > 0 {
-        // add zeroes in the end
-        let width = denom.precision() as usize;
-        write!(f, "{}{:0width$}", satoshi, 0, width = width)?;
-    } else if denom.precision() < 0 {
-        // need to inject a comma in the number
-        let nb_decimals = denom.precision().abs() as usize;
-        let real = format!("{:0width$}", satoshi, width = nb_decimals);
-        if real.len() == nb_decimals {
-            write!(f, "0.{}", &real[real.len() - nb_decimals..])?;
-        } else {
-            write!(
-                f,
-                "{}.{}",
-                &real[0..(real.len() - nb_decimals)],
-                &real[real.len() - nb_decimals..]
-            )?;
-        }
+    // The value is `value` with `decimals` decimal places, followed by
+    // `trailing_zeroes` zeroes before the decimal point.
+    let mut value = satoshi;
+    let (mut decimals, trailing_zeroes) = if denom.precision() < 0 {
+        (denom.precision().abs() as usize, 0)
     } else {
-        // denom.precision() == 0
-        write!(f, "{}", satoshi)?;
+        (0, denom.precision() as usize)
+    };
+    if let Some(precision) = precision {
+        if precision < decimals {
+            let unit = 10_u64.pow((decimals - precision) as u32);
+            let rem = value % unit;
+            value /= unit;
+            if rem >= unit - rem {
+                value += 1;
+            }
+            decimals = precision;
+        }
+    }
+
+    let real = format!("{:0width$}", value, width = decimals + 1);
+    let (int, frac) = real.split_at(real.len() - decimals);
+    write!(f, "{}{:0<width$}", int, "", width = trailing_zeroes)?;
+    let frac_width = precision.unwrap_or(decimals);
+    if frac_width > 0 {
+        write!(f, ".{:0<width$}", frac, width = frac_width)?;
     }
     Ok(())
 }
 
+/// A helper to [fmt::Display] an amount in a given denomination, optionally
+/// followed by the denomination, returned by [Amount::display_in] and
+/// [SignedAmount::display_in].
+///
+/// The formatter precision, if any, sets the number of decimal places. The
+/// width, fill, alignment and the `+` and `0` flags apply to the whole, as
+/// they do for integers: `format!("{:>12.2}", amount)` right-aligns the
+/// value and its denomination in twelve columns.
+#[derive(Copy, Clone, Debug)]
+pub struct DisplayInDenomination {
+    satoshi: u64,
+    negative: bool,
+    denom: Denomination,
+    show_denomination: bool,
+    separator: Option<char>,
+}
+
+impl DisplayInDenomination {
+    /// Also write the denomination after the value.
+    pub fn show_denomination(mut self) -> DisplayInDenomination {
+        self.show_denomination = true;
+        self
+    }
+
+    /// Separate every three digits before the decimal point with
+    /// `separator`, which should not be a digit, '.' or '-' for the amount
+    /// to be parsed back.
+    pub fn group_digits(mut self, separator: char) -> DisplayInDenomination {
+        self.separator = Some(separator);
+        self
+    }
+}
+
+impl fmt::Display for DisplayInDenomination {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // The sign is left to `pad_integral`, which puts it before any zero
+        // padding
+        let mut buf = String::new();
+        fmt_satoshi_in(self.satoshi, false, &mut buf, self.denom, f.precision())?;
+        if let Some(separator) = self.separator {
+            buf = group_digits(&buf, separator);
+        }
+        if self.show_denomination {
+            write!(buf, " {}", self.denom)?;
+        }
+        f.pad_integral(!self.negative, "", &buf)
+    }
+}
+
 /// Amount
 ///
 /// The [Amount] type can be used to express Bitcoin amounts that supports
@@ -280,6 +465,8 @@ impl Amount {
     pub const ONE_SAT: Amount = Amount(1);
     /// Exactly one bitcoin.
     pub const ONE_BTC: Amount = Amount(100_000_000);
+    /// The maximum number of satoshis that will ever exist, 21 million bitcoin.
+    pub const MAX_MONEY: Amount = Amount(21_000_000 * 100_000_000);
 
     /// Create an [Amount] with satoshi precision and the given number of satoshis.
     pub fn from_sat(satoshi: u64) -> Amount {
@@ -326,14 +513,30 @@ impl Amount {
     /// If you want to parse only the amount without the denomination,
     /// use [from_str_in].
     pub fn from_str_with_denomination(s: &str) -> Result<Amount, ParseAmountError> {
-        let mut split = s.splitn(3, " ");
-        let amt_str = split.next().unwrap();
-        let denom_str = split.next().ok_or(ParseAmountError::InvalidFormat)?;
-        if split.next().is_some() {
-            return Err(ParseAmountError::InvalidFormat);
-        }
+        Amount::from_str_with_mode(s, ParseMode::RequireDenomination)
+    }
+
+    /// Parses amounts with an optional denomination suffix. When the suffix
+    /// is missing, the given [ParseMode] decides which denomination is
+    /// assumed, or whether parsing fails.
+    pub fn from_str_with_mode(s: &str, mode: ParseMode) -> Result<Amount, ParseAmountError> {
+        let (amt_str, denom) = split_denomination(s, mode)?;
+        Amount::from_str_in(amt_str, denom)
+    }
 
-        Ok(Amount::from_str_in(amt_str, denom_str.parse()?)?)
+    /// Parses amounts with denomination suffix whose digits before the
+    /// decimal point may be grouped with `separator`, like they are produced
+    /// with [Amount::display_grouped]. The separator may only stand between
+    /// two digits, but the groups may be of any size.
+    pub fn from_str_grouped(s: &str, separator: char) -> Result<Amount, ParseAmountError> {
+        let (amt_str, denom) = split_grouped(s, separator)?;
+        Amount::from_str_in(&amt_str, denom)
+    }
+
+    /// Parse a plain decimal string of satoshis, without any denomination
+    /// suffix, as is common in CLI arguments and environment variables.
+    pub fn from_dec_str_sats(s: &str) -> Result<Amount, ParseAmountError> {
+        Amount::from_str_in(s, Denomination::Satoshi)
     }
 
     /// Express this [Amount] as a floating-point value in the given denomination.
@@ -357,6 +560,8 @@ impl Amount {
     /// Can return error if the amount is too big, too precise or negative.
     ///
     /// Please be aware of the risk of using floating-point numbers.
+    /// Prefer [Amount::from_decimal_in] where the value is available in
+    /// decimal form.
     pub fn from_float_in(value: f64, denom: Denomination) -> Result<Amount, ParseAmountError> {
         if value < 0.0 {
             return Err(ParseAmountError::Negative);
@@ -366,11 +571,89 @@ impl Amount {
         Amount::from_str_in(&value.to_string(), denom)
     }
 
+    /// Create an [Amount] of `mantissa * 10^exponent` bitcoin, without going
+    /// through floating-point numbers.
+    ///
+    /// Equivalent to `from_decimal_in(mantissa, exponent, Denomination::Bitcoin)`.
+    pub fn from_decimal(mantissa: u64, exponent: i8) -> Result<Amount, ParseAmountError> {
+        Amount::from_decimal_in(mantissa, exponent, Denomination::Bitcoin)
+    }
+
+    /// Create an [Amount] of `mantissa * 10^exponent` in the given
+    /// denomination.
+    /// Can return error if the amount is too big or more precise than a satoshi.
+    pub fn from_decimal_in(
+        mantissa: u64,
+        exponent: i8,
+        denom: Denomination,
+    ) -> Result<Amount, ParseAmountError> {
+        let mut satoshi = mantissa;
+        let shift = exponent as i32 - denom.precision();
+        if satoshi != 0 {
+            for _ in 0..shift {
+                satoshi = satoshi.checked_mul(10).ok_or(ParseAmountError::TooBig)?;
+            }
+            for _ in shift..0 {
+                if satoshi % 10 != 0 {
+                    return Err(ParseAmountError::TooPrecise);
+                }
+                satoshi /= 10;
+            }
+        }
+        if satoshi > i64::max_value() as u64 {
+            return Err(ParseAmountError::TooBig);
+        }
+        Ok(Amount::from_sat(satoshi))
+    }
+
+    /// Express this [Amount] as `(mantissa, exponent)`, the value being
+    /// `mantissa * 10^exponent` in the given denomination, without going
+    /// through floating-point numbers.
+    ///
+    /// The mantissa is the smallest possible, so has no trailing zeroes, and
+    /// zero is `(0, 0)`.
+    pub fn to_decimal_in(&self, denom: Denomination) -> (u64, i8) {
+        let mut mantissa = self.as_sat();
+        if mantissa == 0 {
+            return (0, 0);
+        }
+        let mut exponent = denom.precision() as i8;
+        while mantissa % 10 == 0 {
+            mantissa /= 10;
+            exponent += 1;
+        }
+        (mantissa, exponent)
+    }
+
     /// Format the value of this [Amount] in the given denomination.
     ///
     /// Does not include the denomination.
     pub fn fmt_value_in(&self, f: &mut fmt::Write, denom: Denomination) -> fmt::Result {
-        fmt_satoshi_in(self.as_sat(), false, f, denom)
+        fmt_satoshi_in(self.as_sat(), false, f, denom, None)
+    }
+
+    /// Display this [Amount] in the given denomination, without the
+    /// denomination unless [DisplayInDenomination::show_denomination] is
+    /// called. Unlike [Amount::fmt_value_in], this honors the formatter
+    /// precision.
+    pub fn display_in(self, denom: Denomination) -> DisplayInDenomination {
+        DisplayInDenomination {
+            satoshi: self.as_sat(),
+            negative: false,
+            denom,
+            show_denomination: false,
+            separator: None,
+        }
+    }
+
+    /// Display this [Amount] in the given denomination, followed by the
+    /// denomination, with every three digits before the decimal point
+    /// separated by `separator`, like "1 234 567 satoshi". It is parsed
+    /// back by [Amount::from_str_grouped].
+    pub fn display_grouped(self, denom: Denomination, separator: char) -> DisplayInDenomination {
+        self.display_in(denom)
+            .show_denomination()
+            .group_digits(separator)
     }
 
     /// Get a string number of this [Amount] in the given denomination.
@@ -467,11 +750,16 @@ impl fmt::Debug for Amount {
 }
 
 // No one should depend on a binding contract for Display for this type.
-// Just using Bitcoin denominated string.
+// Just using Bitcoin denominated string, or satoshis with `{:#}`, honoring
+// the formatter flags as `DisplayInDenomination` does.
 impl fmt::Display for Amount {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        self.fmt_value_in(f, Denomination::Bitcoin)?;
-        write!(f, " {}", Denomination::Bitcoin)
+        let denom = if f.alternate() {
+            Denomination::Satoshi
+        } else {
+            Denomination::Bitcoin
+        };
+        fmt::Display::fmt(&self.display_in(denom).show_denomination(), f)
     }
 }
 
@@ -545,6 +833,9 @@ impl ops::DivAssign<u64> for Amount {
     }
 }
 
+/// Parses amounts as they are displayed, in any denomination. Amounts
+/// above `SignedAmount::max_value()` can be displayed but are rejected as
+/// too big, so that every parsed amount converts to a `SignedAmount`.
 impl FromStr for Amount {
     type Err = ParseAmountError;
 
@@ -553,6 +844,13 @@ impl FromStr for Amount {
     }
 }
 
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Amount {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(Amount::from_sat(u64::arbitrary(u)?))
+    }
+}
+
 /// SignedAmount
 ///
 /// The [SignedAmount] type can be used to express Bitcoin amounts that supports
@@ -623,14 +921,30 @@ impl SignedAmount {
     /// If you want to parse only the amount without the denomination,
     /// use [from_str_in].
     pub fn from_str_with_denomination(s: &str) -> Result<SignedAmount, ParseAmountError> {
-        let mut split = s.splitn(3, " ");
-        let amt_str = split.next().unwrap();
-        let denom_str = split.next().ok_or(ParseAmountError::InvalidFormat)?;
-        if split.next().is_some() {
-            return Err(ParseAmountError::InvalidFormat);
-        }
+        SignedAmount::from_str_with_mode(s, ParseMode::RequireDenomination)
+    }
 
-        Ok(SignedAmount::from_str_in(amt_str, denom_str.parse()?)?)
+    /// Parses amounts with an optional denomination suffix. When the suffix
+    /// is missing, the given [ParseMode] decides which denomination is
+    /// assumed, or whether parsing fails.
+    pub fn from_str_with_mode(s: &str, mode: ParseMode) -> Result<SignedAmount, ParseAmountError> {
+        let (amt_str, denom) = split_denomination(s, mode)?;
+        SignedAmount::from_str_in(amt_str, denom)
+    }
+
+    /// Parses amounts with denomination suffix whose digits before the
+    /// decimal point may be grouped with `separator`, like they are produced
+    /// with [SignedAmount::display_grouped]. The separator may only stand between
+    /// two digits, but the groups may be of any size.
+    pub fn from_str_grouped(s: &str, separator: char) -> Result<SignedAmount, ParseAmountError> {
+        let (amt_str, denom) = split_grouped(s, separator)?;
+        SignedAmount::from_str_in(&amt_str, denom)
+    }
+
+    /// Parse a plain decimal string of satoshis, without any denomination
+    /// suffix, as is common in CLI arguments and environment variables.
+    pub fn from_dec_str_sats(s: &str) -> Result<SignedAmount, ParseAmountError> {
+        SignedAmount::from_str_in(s, Denomination::Satoshi)
     }
 
     /// Express this [SignedAmount] as a floating-point value in the given denomination.
@@ -667,7 +981,37 @@ impl SignedAmount {
     ///
     /// Does not include the denomination.
     pub fn fmt_value_in(&self, f: &mut fmt::Write, denom: Denomination) -> fmt::Result {
-        fmt_satoshi_in(self.as_sat().abs() as u64, self.is_negative(), f, denom)
+        fmt_satoshi_in(
+            self.as_sat().abs() as u64,
+            self.is_negative(),
+            f,
+            denom,
+            None,
+        )
+    }
+
+    /// Display this [SignedAmount] in the given denomination, without the
+    /// denomination unless [DisplayInDenomination::show_denomination] is
+    /// called. Unlike [SignedAmount::fmt_value_in], this honors the formatter
+    /// precision.
+    pub fn display_in(self, denom: Denomination) -> DisplayInDenomination {
+        DisplayInDenomination {
+            satoshi: self.as_sat().abs() as u64,
+            negative: self.is_negative(),
+            denom,
+            show_denomination: false,
+            separator: None,
+        }
+    }
+
+    /// Display this [SignedAmount] in the given denomination, followed by the
+    /// denomination, with every three digits before the decimal point
+    /// separated by `separator`, like "1 234 567 satoshi". It is parsed
+    /// back by [SignedAmount::from_str_grouped].
+    pub fn display_grouped(self, denom: Denomination, separator: char) -> DisplayInDenomination {
+        self.display_in(denom)
+            .show_denomination()
+            .group_digits(separator)
     }
 
     /// Get a string number of this [SignedAmount] in the given denomination.
@@ -800,11 +1144,16 @@ impl fmt::Debug for SignedAmount {
 }
 
 // No one should depend on a binding contract for Display for this type.
-// Just using Bitcoin denominated string.
+// Just using Bitcoin denominated string, or satoshis with `{:#}`, honoring
+// the formatter flags as `DisplayInDenomination` does.
 impl fmt::Display for SignedAmount {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        self.fmt_value_in(f, Denomination::Bitcoin)?;
-        write!(f, " {}", Denomination::Bitcoin)
+        let denom = if f.alternate() {
+            Denomination::Satoshi
+        } else {
+            Denomination::Bitcoin
+        };
+        fmt::Display::fmt(&self.display_in(denom).show_denomination(), f)
     }
 }
 
@@ -889,6 +1238,13 @@ impl FromStr for SignedAmount {
     }
 }
 
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for SignedAmount {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(SignedAmount::from_sat(i64::arbitrary(u)?))
+    }
+}
+
 #[cfg(feature = "serde")]
 pub mod serde {
     // methods are implementation of a standardized serde-specific signature
@@ -1182,6 +1538,7 @@ mod tests {
         let more_than_max = format!("1{}", Amount::max_value());
         assert_eq!(p(&more_than_max, btc), Err(E::TooBig));
         assert_eq!(p("0.000000042", btc), Err(E::TooPrecise));
+        assert_eq!(p("184467440000.123456789", btc), Err(E::TooPrecise));
 
         assert_eq!(p("1", btc), Ok(Amount::from_sat(1_000_000_00)));
         assert_eq!(sp("-.5", btc), Ok(SignedAmount::from_sat(-500_000_00)));
@@ -1193,6 +1550,174 @@ mod tests {
         assert_eq!(p("12.000", Denomination::MilliSatoshi), Err(E::TooPrecise));
     }
 
+    /// The satoshi value of a decimal string of digits with at most one
+    /// dot in a denomination, worked out on the digits, or the errors
+    /// parsing it may give. A value above `u64::max_value()` is too big. A
+    /// value with a fraction of a satoshi is too precise, and so is one
+    /// with more decimals than the denomination has, even zeroes.
+    /// Denominations smaller than a satoshi take no decimals and need a
+    /// digit before the sub-satoshi zeroes.
+    fn parse_model(
+        int: &str,
+        frac: Option<&str>,
+        denom: Denomination,
+    ) -> Result<u64, Vec<ParseAmountError>> {
+        let mut digits = format!("{}{}", int, frac.unwrap_or(""));
+        // Decimal places of the string relative to a satoshi
+        let shift = -denom.precision() - frac.map(|f| f.len() as i32).unwrap_or(0);
+
+        let mut errors = vec![];
+        if shift >= 0 {
+            digits.extend((0..shift).map(|_| '0'));
+        } else {
+            let sub_satoshi = digits.len() - (-shift as usize).min(digits.len());
+            let sub_satoshi_digits = digits.split_off(sub_satoshi);
+            if sub_satoshi_digits.chars().any(|d| d != '0')
+                || frac
+                    .map(|f| f.len() as i32 > -denom.precision())
+                    .unwrap_or(false)
+                || (denom.precision() > 0 && (frac.is_some() || digits.is_empty()))
+            {
+                errors.push(ParseAmountError::TooPrecise);
+            }
+        }
+        let digits = digits.trim_left_matches('0');
+        let max = u64::max_value().to_string();
+        if digits.len() > max.len() || (digits.len() == max.len() && digits > &max[..]) {
+            errors.push(ParseAmountError::TooBig);
+        }
+        if errors.is_empty() {
+            Ok(if digits.is_empty() {
+                0
+            } else {
+                digits.parse().unwrap()
+            })
+        } else {
+            Err(errors)
+        }
+    }
+
+    #[test]
+    fn parsing_boundaries() {
+        let denoms = [
+            Denomination::Bitcoin,
+            Denomination::MilliBitcoin,
+            Denomination::MicroBitcoin,
+            Denomination::Bit,
+            Denomination::Satoshi,
+            Denomination::MilliSatoshi,
+        ];
+
+        // Every length of number up to and just past the limits
+        let mut ints = vec![String::new()];
+        for limit in &[
+            "18446744073709551615",
+            "18446744073709551616",
+            "9223372036854775807",
+            "9223372036854775808",
+            "99999999999999999999",
+            "10000000000000000000",
+        ] {
+            for len in 1..limit.len() + 1 {
+                ints.push(limit[..len].to_owned());
+                ints.push(format!("{}000", &limit[..len]));
+            }
+        }
+        for int in &["0", "00", "000", "0000", "1000", "1001"] {
+            ints.push((*int).to_owned());
+        }
+        // Every length of fraction up to and just past the most decimals
+        let mut fracs = vec![None, Some(String::new())];
+        for len in 1..11 {
+            fracs.push(Some("1234567890"[..len].to_owned()));
+            fracs.push(Some(format!("{:0>width$}", "1", width = len)));
+            fracs.push(Some(format!("{:0<width$}", "5", width = len)));
+            fracs.push(Some(format!("{:9<width$}", "", width = len)));
+        }
+
+        for &denom in &denoms {
+            for int in &ints {
+                for frac in &fracs {
+                    let frac = frac.as_ref().map(|f| &f[..]);
+                    if int.is_empty() && frac.unwrap_or("").is_empty() {
+                        continue;
+                    }
+                    let s = match frac {
+                        Some(frac) => format!("{}.{}", int, frac),
+                        None => int.clone(),
+                    };
+                    let expected = parse_model(int, frac, denom);
+                    for &negative in &[false, true] {
+                        let s = if negative {
+                            format!("-{}", s)
+                        } else {
+                            s.clone()
+                        };
+                        match (parse_signed_to_satoshi(&s, denom), &expected) {
+                            (Ok(got), &Ok(sat)) => {
+                                assert_eq!(got, (negative, sat), "{} {}", s, denom)
+                            }
+                            (Err(ref e), &Err(ref errors)) if errors.contains(e) => {}
+                            (got, expected) => {
+                                panic!("{} {}: parsed {:?}, expected {:?}", s, denom, got, expected)
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        // The amount types are bounded by the largest signed amount
+        let max = SignedAmount::max_value();
+        for &denom in &denoms {
+            let max_str = max.to_string_in(denom);
+            assert_eq!(SignedAmount::from_str_in(&max_str, denom), Ok(max));
+            assert_eq!(
+                Amount::from_str_in(&max_str, denom),
+                Ok(max.to_unsigned().unwrap())
+            );
+            let min = SignedAmount::from_sat(-max.as_sat());
+            let min_str = min.to_string_in(denom);
+            assert_eq!(SignedAmount::from_str_in(&min_str, denom), Ok(min));
+            assert_eq!(
+                Amount::from_str_in(&min_str, denom),
+                Err(ParseAmountError::Negative)
+            );
+
+            let past_max = Amount::from_sat(max.as_sat() as u64 + 1).to_string_in(denom);
+            assert_eq!(
+                SignedAmount::from_str_in(&past_max, denom),
+                Err(ParseAmountError::TooBig)
+            );
+            assert_eq!(
+                Amount::from_str_in(&past_max, denom),
+                Err(ParseAmountError::TooBig)
+            );
+            let past_min = format!("-{}", past_max);
+            assert_eq!(
+                SignedAmount::from_str_in(&past_min, denom),
+                Err(ParseAmountError::TooBig)
+            );
+
+            for &sat in &[
+                0,
+                1,
+                9,
+                10,
+                99,
+                100,
+                12_345_678,
+                100_000_000,
+                2_100_000_000_000_000,
+            ] {
+                for &amount in &[SignedAmount::from_sat(sat), SignedAmount::from_sat(-sat)] {
+                    let s = amount.to_string_in(denom);
+                    assert_eq!(SignedAmount::from_str_in(&s, denom), Ok(amount));
+                }
+            }
+        }
+    }
+
     #[test]
     fn to_string() {
         use super::Denomination as D;
@@ -1227,6 +1752,124 @@ mod tests {
         );
     }
 
+    #[test]
+    fn decimal() {
+        use super::Denomination as D;
+        use super::ParseAmountError as E;
+
+        assert_eq!(Amount::from_decimal(1, 0), Ok(Amount::ONE_BTC));
+        assert_eq!(Amount::from_decimal(1, -8), Ok(Amount::ONE_SAT));
+        assert_eq!(
+            Amount::from_decimal(12345, -3),
+            Ok(Amount::from_sat(1_234_500_000))
+        );
+        assert_eq!(Amount::from_decimal(21, 6), Ok(Amount::MAX_MONEY));
+        assert_eq!(Amount::from_decimal(0, 127), Ok(Amount::ZERO));
+        assert_eq!(Amount::from_decimal(1, -9), Err(E::TooPrecise));
+        assert_eq!(Amount::from_decimal(10, -9), Ok(Amount::ONE_SAT));
+        assert_eq!(Amount::from_decimal(1, 20), Err(E::TooBig));
+        assert_eq!(Amount::from_decimal(1, 11), Err(E::TooBig));
+        assert_eq!(Amount::from_decimal(1, -128), Err(E::TooPrecise));
+        assert_eq!(
+            Amount::from_decimal_in(1, 3, D::MilliSatoshi),
+            Ok(Amount::ONE_SAT)
+        );
+        assert_eq!(
+            Amount::from_decimal_in(1, 0, D::MilliSatoshi),
+            Err(E::TooPrecise)
+        );
+
+        assert_eq!(Amount::ONE_BTC.to_decimal_in(D::Bitcoin), (1, 0));
+        assert_eq!(Amount::ONE_SAT.to_decimal_in(D::Bitcoin), (1, -8));
+        assert_eq!(Amount::MAX_MONEY.to_decimal_in(D::Bitcoin), (21, 6));
+        assert_eq!(Amount::ONE_SAT.to_decimal_in(D::MilliSatoshi), (1, 3));
+        assert_eq!(
+            Amount::from_sat(1_234_500_000).to_decimal_in(D::Bit),
+            (12_345, 3)
+        );
+        assert_eq!(Amount::ZERO.to_decimal_in(D::Bitcoin), (0, 0));
+
+        for &sat in &[1, 10, 123_456_789, 2_100_000_000_000_000] {
+            for &denom in &[
+                D::Bitcoin,
+                D::MilliBitcoin,
+                D::Bit,
+                D::Satoshi,
+                D::MilliSatoshi,
+            ] {
+                let amt = Amount::from_sat(sat);
+                let (m, e) = amt.to_decimal_in(denom);
+                assert_eq!(Amount::from_decimal_in(m, e, denom), Ok(amt));
+            }
+        }
+    }
+
+    #[test]
+    fn display_precision() {
+        use super::Denomination as D;
+
+        let amt = Amount::from_sat(123_456_789);
+        assert_eq!(format!("{}", amt), "1.23456789 BTC");
+        assert_eq!(format!("{:.2}", amt), "1.23 BTC");
+        assert_eq!(format!("{:.4}", amt), "1.2346 BTC");
+        assert_eq!(format!("{:.0}", amt), "1 BTC");
+        assert_eq!(format!("{:.10}", amt), "1.2345678900 BTC");
+        assert_eq!(format!("{:.1}", Amount::from_sat(99_999_999)), "1.0 BTC");
+        assert_eq!(
+            format!("{:.3}", SignedAmount::from_sat(-150_000)),
+            "-0.002 BTC"
+        );
+
+        assert_eq!(format!("{}", amt.display_in(D::Satoshi)), "123456789");
+        assert_eq!(
+            format!("{}", amt.display_in(D::Satoshi).show_denomination()),
+            "123456789 satoshi"
+        );
+        assert_eq!(format!("{:.2}", amt.display_in(D::Satoshi)), "123456789.00");
+        assert_eq!(format!("{:.1}", amt.display_in(D::MilliBitcoin)), "1234.6");
+        assert_eq!(
+            format!("{}", Amount::ONE_SAT.display_in(D::MilliSatoshi)),
+            "1000"
+        );
+        assert_eq!(
+            format!("{}", SignedAmount::from_sat(-42).display_in(D::Bit)),
+            "-0.42"
+        );
+    }
+
+    #[test]
+    fn display_flags() {
+        use super::Denomination as D;
+
+        let amt = Amount::from_sat(123_456_789);
+        let neg = SignedAmount::from_sat(-150_000);
+        assert_eq!(format!("{:16}", amt), "  1.23456789 BTC");
+        assert_eq!(format!("{:<16}|", amt), "1.23456789 BTC  |");
+        assert_eq!(format!("{:*^18}", amt), "**1.23456789 BTC**");
+        assert_eq!(format!("{:>10.2}", amt), "  1.23 BTC");
+        assert_eq!(format!("{:+.1}", amt), "+1.2 BTC");
+        // Zero padding goes after the sign
+        assert_eq!(format!("{:012.3}", neg), "-000.002 BTC");
+        assert_eq!(format!("{:16}", neg), " -0.00150000 BTC");
+        assert_eq!(format!("{:4}", amt), "1.23456789 BTC");
+
+        // The alternate form is in satoshis
+        assert_eq!(format!("{:#}", amt), "123456789 satoshi");
+        assert_eq!(format!("{:#}", neg), "-150000 satoshi");
+        assert_eq!(format!("{:>#20}", amt), "   123456789 satoshi");
+        assert_eq!(format!("{:#.2}", Amount::ONE_SAT), "1.00 satoshi");
+
+        assert_eq!(format!("{:>6}", amt.display_in(D::Satoshi)), "123456789");
+        assert_eq!(
+            format!("{:>12}", amt.display_in(D::Satoshi)),
+            "   123456789"
+        );
+        assert_eq!(
+            format!("{:<8.1}|", SignedAmount::from_sat(-42).display_in(D::Bit)),
+            "-0.4    |"
+        );
+    }
+
     #[test]
     fn from_str() {
         use super::ParseAmountError as E;
@@ -1265,6 +1908,109 @@ mod tests {
         assert_eq!(sp("-100 bits"), Ok(SignedAmount::from_sat(-10_000)));
     }
 
+    #[test]
+    fn from_str_with_mode() {
+        use super::ParseAmountError as E;
+        use super::ParseMode as M;
+        let p = Amount::from_str_with_mode;
+        let sp = SignedAmount::from_str_with_mode;
+
+        assert_eq!(p("5", M::RequireDenomination), Err(E::InvalidFormat));
+        assert_eq!(p("5", M::AssumeSat), Ok(Amount::from_sat(5)));
+        assert_eq!(p("5", M::AssumeBtc), Ok(Amount::from_sat(5_000_000_00)));
+        assert_eq!(
+            sp("-0.5", M::AssumeBtc),
+            Ok(SignedAmount::from_sat(-50_000_000))
+        );
+        assert_eq!(p("0.5", M::AssumeSat), Err(E::TooPrecise));
+
+        // An explicit denomination always wins over the assumed one.
+        assert_eq!(p("5 bits", M::AssumeBtc), Ok(Amount::from_sat(500)));
+        assert_eq!(
+            p("5 bits", M::RequireDenomination),
+            Ok(Amount::from_sat(500))
+        );
+        assert_eq!(p("5 5 BTC", M::AssumeSat), Err(E::InvalidFormat));
+
+        assert_eq!(Amount::from_dec_str_sats("21"), Ok(Amount::from_sat(21)));
+        assert_eq!(
+            Amount::from_dec_str_sats("21 sat"),
+            Err(E::InvalidCharacter(' '))
+        );
+        assert_eq!(Amount::from_dec_str_sats("-21"), Err(E::Negative));
+        assert_eq!(
+            SignedAmount::from_dec_str_sats("-21"),
+            Ok(SignedAmount::from_sat(-21))
+        );
+    }
+
+    #[test]
+    fn grouped() {
+        use super::Denomination as D;
+        use super::ParseAmountError as E;
+
+        let amt = Amount::from_sat(1_234_567);
+        assert_eq!(
+            amt.display_grouped(D::Satoshi, ' ').to_string(),
+            "1 234 567 satoshi"
+        );
+        assert_eq!(
+            amt.display_grouped(D::MilliSatoshi, ',').to_string(),
+            "1,234,567,000 msat"
+        );
+        assert_eq!(
+            amt.display_grouped(D::Bitcoin, ' ').to_string(),
+            "0.01234567 BTC"
+        );
+        assert_eq!(
+            Amount::from_sat(123_456_789_000)
+                .display_grouped(D::Bitcoin, '\'')
+                .to_string(),
+            "1'234.56789000 BTC"
+        );
+        assert_eq!(
+            format!("{:.1}", Amount::from_sat(999).display_grouped(D::Bit, ' ')),
+            "10.0 bits"
+        );
+        assert_eq!(
+            format!("{}", amt.display_in(D::Satoshi).group_digits('_')),
+            "1_234_567"
+        );
+        let neg = SignedAmount::from_sat(-123_456);
+        assert_eq!(
+            neg.display_grouped(D::Satoshi, ' ').to_string(),
+            "-123 456 satoshi"
+        );
+
+        for &sep in &[' ', ',', '\'', '\u{202f}'] {
+            for &denom in &[D::Bitcoin, D::Satoshi, D::MilliSatoshi] {
+                let s = amt.display_grouped(denom, sep).to_string();
+                assert_eq!(Amount::from_str_grouped(&s, sep), Ok(amt));
+                let s = neg.display_grouped(denom, sep).to_string();
+                assert_eq!(SignedAmount::from_str_grouped(&s, sep), Ok(neg));
+            }
+        }
+        assert_eq!(Amount::from_str_grouped("1 234 567 sat", ' '), Ok(amt));
+        assert_eq!(Amount::from_str_grouped("12,34,567 sat", ','), Ok(amt));
+        assert_eq!(Amount::from_str_grouped("1234567 sat", ','), Ok(amt));
+
+        let err = |s| Amount::from_str_grouped(s, ' ').unwrap_err();
+        assert_eq!(err("1 234 567"), E::UnknownDenomination("567".to_owned()));
+        assert_eq!(err("1  234 sat"), E::InvalidFormat);
+        assert_eq!(err(" 1234 sat"), E::InvalidFormat);
+        assert_eq!(err("1234  sat"), E::InvalidFormat);
+        assert_eq!(err("1.234 5 BTC"), E::InvalidFormat);
+        assert_eq!(err("-1 234 sat"), E::Negative);
+        assert_eq!(
+            Amount::from_str_grouped("1.234 BTC", '.'),
+            Err(E::InvalidFormat)
+        );
+        assert_eq!(
+            Amount::from_str_grouped("1,2x4 sat", ','),
+            Err(E::InvalidCharacter('x'))
+        );
+    }
+
     #[test]
     fn to_string_with_denomination_from_str_roundtrip() {
         use super::Denomination as D;
@@ -1279,6 +2025,47 @@ mod tests {
         assert_eq!(Amount::from_str(&denom(&amt, D::MilliSatoshi)), Ok(amt));
     }
 
+    #[test]
+    fn fromstr_display_roundtrip() {
+        use super::Denomination as D;
+
+        roundtrip_fromstr_display!(
+            Denomination;
+            D::Bitcoin,
+            D::MilliBitcoin,
+            D::MicroBitcoin,
+            D::Bit,
+            D::Satoshi,
+            D::MilliSatoshi,
+        );
+        for &denom in &[D::Bitcoin, D::Satoshi, D::MilliSatoshi] {
+            assert_eq!(denom.to_string(), denom.as_canonical_str());
+        }
+        // The one alias
+        assert_eq!(D::from_str("sat").unwrap().to_string(), "satoshi");
+
+        roundtrip_fromstr_display!(
+            Amount;
+            Amount::ZERO,
+            Amount::ONE_SAT,
+            Amount::ONE_BTC,
+            Amount::from_sat(2_100_000_000_000_000),
+            Amount::from_sat(SignedAmount::max_value().as_sat() as u64),
+        );
+        assert_eq!(
+            Amount::from_str(&Amount::max_value().to_string()),
+            Err(ParseAmountError::TooBig)
+        );
+        roundtrip_fromstr_display!(
+            SignedAmount;
+            SignedAmount::ZERO,
+            SignedAmount::from_sat(-1),
+            SignedAmount::ONE_BTC,
+            SignedAmount::max_value(),
+            SignedAmount::from_sat(-SignedAmount::max_value().as_sat()),
+        );
+    }
+
     #[cfg(feature = "serde")]
     #[test]
     fn serde_as_sat() {
@@ -0,0 +1,250 @@
+//! Base58 Encoding
+//!
+//! Encoding and decoding of base58 and of base58check, base58 with a 4-byte
+//! checksum appended, as used by legacy addresses and extended keys.
+
+use std::{error, fmt};
+
+use hashes::{sha256d, Hash};
+
+use error::{ErrorCode, ErrorKind};
+
+/// The characters of the base58 alphabet, indexed by their value.
+const BASE58_CHARS: &[u8] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+/// The value of each ASCII character, or -1 if it is not in `BASE58_CHARS`.
+const BASE58_DIGITS: [i8; 128] = [
+    -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1,
+    -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1,
+    -1, 0, 1, 2, 3, 4, 5, 6, 7, 8, -1, -1, -1, -1, -1, -1, -1, 9, 10, 11, 12, 13, 14, 15, 16, -1,
+    17, 18, 19, 20, 21, -1, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31, 32, -1, -1, -1, -1, -1, -1, 33,
+    34, 35, 36, 37, 38, 39, 40, 41, 42, 43, -1, 44, 45, 46, 47, 48, 49, 50, 51, 52, 53, 54, 55, 56,
+    57, -1, -1, -1, -1, -1,
+];
+
+/// A base58 error
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Error {
+    /// A character is not in the base58 alphabet
+    BadByte(u8),
+    /// The checksum, second, does not match the one computed, first
+    BadChecksum(u32, u32),
+    /// The decoded data has a length not valid for its use
+    InvalidLength(usize),
+    /// The data is too short to hold a checksum
+    TooShort(usize),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::BadByte(b) => write!(f, "invalid base58 character 0x{:x}", b),
+            Error::BadChecksum(exp, actual) => write!(
+                f,
+                "base58ck checksum 0x{:x} does not match expected 0x{:x}",
+                actual, exp
+            ),
+            Error::InvalidLength(ell) => write!(f, "length {} invalid for this base58 type", ell),
+            Error::TooShort(ell) => write!(
+                f,
+                "base58ck data not even long enough for a checksum: {}",
+                ell
+            ),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn cause(&self) -> Option<&error::Error> {
+        None
+    }
+
+    fn description(&self) -> &str {
+        match *self {
+            Error::BadByte(..) => "invalid base58 character",
+            Error::BadChecksum(..) => "base58ck checksum mismatch",
+            Error::InvalidLength(..) => "invalid length for base58 type",
+            Error::TooShort(..) => "base58ck data too short",
+        }
+    }
+}
+
+impl ErrorCode for Error {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Base58
+    }
+
+    fn number(&self) -> u32 {
+        match *self {
+            Error::BadByte(..) => 1,
+            Error::BadChecksum(..) => 2,
+            Error::InvalidLength(..) => 3,
+            Error::TooShort(..) => 4,
+        }
+    }
+}
+
+/// Decode base58-encoded string into a byte vector
+pub fn from(data: &str) -> Result<Vec<u8>, Error> {
+    // 11/15 is just over log_256(58)
+    let mut scratch = vec![0u8; 1 + data.len() * 11 / 15];
+    // Build in base 256
+    for d58 in data.bytes() {
+        // Compute "X = X * 58 + next_digit" in base 256
+        if d58 as usize >= BASE58_DIGITS.len() || BASE58_DIGITS[d58 as usize] < 0 {
+            return Err(Error::BadByte(d58));
+        }
+        let mut carry = BASE58_DIGITS[d58 as usize] as u32;
+        for d256 in scratch.iter_mut().rev() {
+            carry += *d256 as u32 * 58;
+            *d256 = carry as u8;
+            carry /= 256;
+        }
+        assert_eq!(carry, 0);
+    }
+
+    // Copy leading zeroes directly
+    let mut ret: Vec<u8> = data
+        .bytes()
+        .take_while(|&x| x == BASE58_CHARS[0])
+        .map(|_| 0)
+        .collect();
+    // Copy rest of string
+    ret.extend(scratch.into_iter().skip_while(|&x| x == 0));
+    Ok(ret)
+}
+
+/// Decode a base58check-encoded string
+pub fn from_check(data: &str) -> Result<Vec<u8>, Error> {
+    let mut ret: Vec<u8> = from(data)?;
+    if ret.len() < 4 {
+        return Err(Error::TooShort(ret.len()));
+    }
+    let ck_start = ret.len() - 4;
+    let expected = checksum(&ret[..ck_start]);
+    let actual = read_u32_le(&ret[ck_start..]);
+    if expected != actual {
+        return Err(Error::BadChecksum(expected, actual));
+    }
+
+    ret.truncate(ck_start);
+    Ok(ret)
+}
+
+/// Directly encode a slice as base58
+pub fn encode_slice(data: &[u8]) -> String {
+    // 7/5 is just over log_58(256)
+    let mut scratch = vec![0u8; 1 + data.len() * 7 / 5];
+    // Build in base 58
+    for &d256 in data {
+        // Compute "X = X * 256 + next_digit" in base 58
+        let mut carry = d256 as u32;
+        for d58 in scratch.iter_mut().rev() {
+            carry += (*d58 as u32) << 8;
+            *d58 = (carry % 58) as u8;
+            carry /= 58;
+        }
+        assert_eq!(carry, 0);
+    }
+
+    // Copy leading zeroes directly
+    let mut ret: String = data
+        .iter()
+        .take_while(|&&x| x == 0)
+        .map(|_| BASE58_CHARS[0] as char)
+        .collect();
+    // Copy rest of string
+    ret.extend(
+        scratch
+            .into_iter()
+            .skip_while(|&x| x == 0)
+            .map(|x| BASE58_CHARS[x as usize] as char),
+    );
+    ret
+}
+
+/// Obtain a string with the base58check encoding of a slice
+/// (Tack the first 4 256-digits of the object's Bitcoin hash onto the end.)
+pub fn check_encode_slice(data: &[u8]) -> String {
+    let mut with_checksum = data.to_vec();
+    let ck = checksum(data);
+    with_checksum.extend_from_slice(&[
+        ck as u8,
+        (ck >> 8) as u8,
+        (ck >> 16) as u8,
+        (ck >> 24) as u8,
+    ]);
+    encode_slice(&with_checksum)
+}
+
+/// The first 4 bytes of the double SHA256 of `data`, as a little-endian
+/// number
+fn checksum(data: &[u8]) -> u32 {
+    read_u32_le(&sha256d::Hash::hash(data)[0..4])
+}
+
+fn read_u32_le(data: &[u8]) -> u32 {
+    data[0] as u32 | (data[1] as u32) << 8 | (data[2] as u32) << 16 | (data[3] as u32) << 24
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hashes::hex::FromHex;
+
+    #[test]
+    fn test_base58_encode() {
+        // Basics
+        assert_eq!(&encode_slice(&[0][..]), "1");
+        assert_eq!(&encode_slice(&[1][..]), "2");
+        assert_eq!(&encode_slice(&[58][..]), "21");
+        assert_eq!(&encode_slice(&[13, 36][..]), "211");
+
+        // Leading zeroes
+        assert_eq!(&encode_slice(&[0, 13, 36][..]), "1211");
+        assert_eq!(&encode_slice(&[0, 0, 0, 0, 13, 36][..]), "1111211");
+
+        // Addresses
+        let addr = Vec::from_hex("00f8917303bfa8ef24f292e8fa1419b20460ba064d").unwrap();
+        assert_eq!(
+            &check_encode_slice(&addr[..]),
+            "1PfJpZsjreyVrqeoAfabrRwwjQyoSQMmHH"
+        );
+    }
+
+    #[test]
+    fn test_base58_decode() {
+        // Basics
+        assert_eq!(from("1").ok(), Some(vec![0u8]));
+        assert_eq!(from("2").ok(), Some(vec![1u8]));
+        assert_eq!(from("21").ok(), Some(vec![58u8]));
+        assert_eq!(from("211").ok(), Some(vec![13u8, 36]));
+
+        // Leading zeroes
+        assert_eq!(from("1211").ok(), Some(vec![0u8, 13, 36]));
+        assert_eq!(from("111211").ok(), Some(vec![0u8, 0, 0, 13, 36]));
+
+        // Addresses
+        assert_eq!(
+            from_check("1PfJpZsjreyVrqeoAfabrRwwjQyoSQMmHH").ok(),
+            Some(Vec::from_hex("00f8917303bfa8ef24f292e8fa1419b20460ba064d").unwrap())
+        );
+
+        // Invalid characters and checksums
+        assert_eq!(from("1OIl"), Err(Error::BadByte(b'O')));
+        assert_eq!(from("é"), Err(Error::BadByte(0xc3)));
+        match from_check("1PfJpZsjreyVrqeoAfabrRwwjQyoSQMmHJ") {
+            Err(Error::BadChecksum(..)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert_eq!(from_check("11"), Err(Error::TooShort(2)));
+    }
+
+    #[test]
+    fn test_base58_roundtrip() {
+        let s = "xprv9wTYmMFdV23N2TdNG573QoEsfRrWKQgWeibmLntzniatZvR9BmLnvSxqu53Kw1UmYPxLgboyZQaXwTCg8MSY3H2EU4pWcQDnRnrVA1xe8fs";
+        let v: Vec<u8> = from_check(s).unwrap();
+        assert_eq!(check_encode_slice(&v[..]), s);
+        assert_eq!(from_check(&check_encode_slice(&v[..])).ok(), Some(v));
+    }
+}
@@ -0,0 +1,509 @@
+//! Bech32 Encoding
+//!
+//! Encoding and decoding of the bech32 and bech32m formats, as defined at
+//! https://github.com/bitcoin/bips/blob/master/bip-0173.mediawiki and
+//! https://github.com/bitcoin/bips/blob/master/bip-0350.mediawiki
+//!
+//! This is independent of addresses, so it can be used for any payload
+//! carried with a human-readable part. The 90 character limit of BIP173
+//! is specific to addresses and is not enforced here.
+
+use std::{error, fmt};
+
+use error::{ErrorCode, ErrorKind};
+
+/// The characters of the bech32 alphabet, indexed by their 5-bit value.
+const CHARSET: [char; 32] = [
+    'q', 'p', 'z', 'r', 'y', '9', 'x', '8', 'g', 'f', '2', 't', 'v', 'd', 'w', '0', 's', '3', 'j',
+    'n', '5', '4', 'k', 'h', 'c', 'e', '6', 'm', 'u', 'a', '7', 'l',
+];
+
+/// The 5-bit value of each ASCII character, or -1 if it is not in `CHARSET`.
+const CHARSET_REV: [i8; 128] = [
+    -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1,
+    -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1,
+    15, -1, 10, 17, 21, 20, 26, 30, 7, 5, -1, -1, -1, -1, -1, -1, -1, 29, -1, 24, 13, 25, 9, 8, 23,
+    -1, 18, 22, 31, 27, 19, -1, 1, 0, 3, 16, 11, 28, 12, 14, 6, 4, 2, -1, -1, -1, -1, -1, -1, 29,
+    -1, 24, 13, 25, 9, 8, 23, -1, 18, 22, 31, 27, 19, -1, 1, 0, 3, 16, 11, 28, 12, 14, 6, 4, 2, -1,
+    -1, -1, -1, -1,
+];
+
+/// Generator coefficients of the checksum polynomial
+const GEN: [u32; 5] = [0x3b6a57b2, 0x26508e6d, 0x1ea119fa, 0x3d4233dd, 0x2a1462b3];
+
+/// The separator between the human-readable part and the data
+const SEP: char = '1';
+
+/// The number of characters in a checksum
+const CHECKSUM_LENGTH: usize = 6;
+
+/// The checksum constant of the original bech32 format
+pub const BECH32_CONST: u32 = 1;
+/// The checksum constant of the bech32m format
+pub const BECH32M_CONST: u32 = 0x2bc830a3;
+
+/// A 5-bit unsigned integer, the unit of data in bech32.
+#[allow(non_camel_case_types)]
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+pub struct u5(u8);
+
+impl u5 {
+    /// Convert a `u8` to `u5` if it is in range, returning an error
+    /// otherwise.
+    pub fn try_from_u8(value: u8) -> Result<u5, Error> {
+        if value > 31 {
+            Err(Error::InvalidData(value))
+        } else {
+            Ok(u5(value))
+        }
+    }
+
+    /// Returns the value as a `u8`.
+    pub fn to_u8(self) -> u8 {
+        self.0
+    }
+
+    /// Returns the character encoding this value.
+    pub fn to_char(self) -> char {
+        CHARSET[self.0 as usize]
+    }
+
+    /// Returns the value encoded by a character of either case, if it is
+    /// in the bech32 alphabet.
+    pub fn from_char(c: char) -> Result<u5, Error> {
+        let value = if (c as u32) < 128 {
+            CHARSET_REV[c as usize]
+        } else {
+            -1
+        };
+        if value < 0 {
+            Err(Error::InvalidChar(c))
+        } else {
+            Ok(u5(value as u8))
+        }
+    }
+}
+
+impl From<u5> for u8 {
+    fn from(v: u5) -> u8 {
+        v.0
+    }
+}
+
+impl AsRef<u8> for u5 {
+    fn as_ref(&self) -> &u8 {
+        &self.0
+    }
+}
+
+/// The checksum variant of a bech32 string.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Variant {
+    /// The original bech32 of BIP173
+    Bech32,
+    /// The bech32m of BIP350
+    Bech32m,
+}
+
+impl Variant {
+    /// The constant the checksum of this variant is computed against.
+    pub fn constant(self) -> u32 {
+        match self {
+            Variant::Bech32 => BECH32_CONST,
+            Variant::Bech32m => BECH32M_CONST,
+        }
+    }
+
+    /// The variant whose checksum constant a polymod residue matches.
+    fn from_remainder(c: u32) -> Option<Variant> {
+        match c {
+            BECH32_CONST => Some(Variant::Bech32),
+            BECH32M_CONST => Some(Variant::Bech32m),
+            _ => None,
+        }
+    }
+}
+
+/// Conversion of bytes into base32.
+pub trait ToBase32 {
+    /// Convert to base32, padding the last group with zero bits.
+    fn to_base32(&self) -> Vec<u5>;
+}
+
+impl<T: ?Sized + AsRef<[u8]>> ToBase32 for T {
+    fn to_base32(&self) -> Vec<u5> {
+        convert_bits(self.as_ref(), 8, 5, true)
+            .expect("padded conversion from 8 to 5 bits cannot fail")
+            .into_iter()
+            .map(u5)
+            .collect()
+    }
+}
+
+/// Conversion of base32 into bytes.
+pub trait FromBase32: Sized {
+    /// Convert from base32, which must have no more than 4 bits of zero
+    /// padding.
+    fn from_base32(b32: &[u5]) -> Result<Self, Error>;
+}
+
+impl FromBase32 for Vec<u8> {
+    fn from_base32(b32: &[u5]) -> Result<Vec<u8>, Error> {
+        let data: Vec<u8> = b32.iter().map(|v| v.0).collect();
+        convert_bits(&data, 5, 8, false)
+    }
+}
+
+/// Regroup the `from`-bit values of `data` into `to`-bit values.
+///
+/// If `pad` is set the last group is padded with zero bits, otherwise any
+/// leftover bits must be zero and fewer than `from`.
+///
+/// # Panics
+///
+/// If `from` or `to` is 0 or greater than 8.
+pub fn convert_bits(data: &[u8], from: u32, to: u32, pad: bool) -> Result<Vec<u8>, Error> {
+    if from > 8 || to > 8 || from == 0 || to == 0 {
+        panic!("convert_bits `from` and `to` parameters must be in 1..=8");
+    }
+    let mut acc: u32 = 0;
+    let mut bits: u32 = 0;
+    let mut ret: Vec<u8> = Vec::with_capacity(data.len() * from as usize / to as usize + 1);
+    let maxv: u32 = (1 << to) - 1;
+    for value in data.iter() {
+        let v = *value as u32;
+        if (v >> from) != 0 {
+            return Err(Error::InvalidData(*value));
+        }
+        acc = (acc << from) | v;
+        bits += from;
+        while bits >= to {
+            bits -= to;
+            ret.push(((acc >> bits) & maxv) as u8);
+        }
+    }
+    if pad {
+        if bits > 0 {
+            ret.push(((acc << (to - bits)) & maxv) as u8);
+        }
+    } else if bits >= from || ((acc << (to - bits)) & maxv) != 0 {
+        return Err(Error::InvalidPadding);
+    }
+    Ok(ret)
+}
+
+fn polymod(values: &[u5]) -> u32 {
+    let mut chk: u32 = 1;
+    for v in values.iter() {
+        let b = (chk >> 25) as u8;
+        chk = (chk & 0x1ffffff) << 5 ^ (v.0 as u32);
+        for (i, gen) in GEN.iter().enumerate() {
+            if (b >> i) & 1 == 1 {
+                chk ^= gen;
+            }
+        }
+    }
+    chk
+}
+
+/// Expand the human-readable part into the values it contributes to the
+/// checksum.
+fn hrp_expand(hrp: &[u8]) -> Vec<u5> {
+    let mut v: Vec<u5> = Vec::with_capacity(hrp.len() * 2 + 1);
+    for b in hrp.iter() {
+        v.push(u5(*b >> 5));
+    }
+    v.push(u5(0));
+    for b in hrp.iter() {
+        v.push(u5(*b & 0x1f));
+    }
+    v
+}
+
+/// Compute the checksum of `data` under the lowercase `hrp`.
+fn create_checksum(hrp: &[u8], data: &[u5], variant: Variant) -> Vec<u5> {
+    let mut values = hrp_expand(hrp);
+    values.extend_from_slice(data);
+    values.extend_from_slice(&[u5(0); CHECKSUM_LENGTH]);
+    let plm = polymod(&values) ^ variant.constant();
+    (0..CHECKSUM_LENGTH)
+        .map(|i| u5(((plm >> (5 * (5 - i))) & 0x1f) as u8))
+        .collect()
+}
+
+/// Check the human-readable part and return it in lowercase.
+fn check_hrp(hrp: &str) -> Result<String, Error> {
+    if hrp.is_empty() || hrp.len() > 83 {
+        return Err(Error::InvalidLength);
+    }
+    let mut has_lower = false;
+    let mut has_upper = false;
+    for b in hrp.bytes() {
+        if b < 33 || b > 126 {
+            return Err(Error::InvalidChar(b as char));
+        }
+        has_lower |= b.is_ascii_lowercase();
+        has_upper |= b.is_ascii_uppercase();
+    }
+    if has_lower && has_upper {
+        return Err(Error::MixedCase);
+    }
+    Ok(hrp.to_ascii_lowercase())
+}
+
+/// Encode `data` with the human-readable part `hrp` and the checksum of
+/// `variant`. The result is always lowercase.
+pub fn encode(hrp: &str, data: &[u5], variant: Variant) -> Result<String, Error> {
+    encode_case(hrp, data, variant, false)
+}
+
+/// Encode `data` like `encode`, but in uppercase, which QR codes store
+/// more compactly in their alphanumeric mode.
+pub fn encode_upper(hrp: &str, data: &[u5], variant: Variant) -> Result<String, Error> {
+    encode_case(hrp, data, variant, true)
+}
+
+fn encode_case(hrp: &str, data: &[u5], variant: Variant, upper: bool) -> Result<String, Error> {
+    // The checksum is always over the lowercase human-readable part
+    let hrp = check_hrp(hrp)?;
+    let checksum = create_checksum(hrp.as_bytes(), data, variant);
+    let mut ret = String::with_capacity(hrp.len() + 1 + data.len() + CHECKSUM_LENGTH);
+    if upper {
+        ret.push_str(&hrp.to_ascii_uppercase());
+    } else {
+        ret.push_str(&hrp);
+    }
+    ret.push(SEP);
+    for v in data.iter().chain(checksum.iter()) {
+        let c = v.to_char();
+        ret.push(if upper { c.to_ascii_uppercase() } else { c });
+    }
+    Ok(ret)
+}
+
+/// Decode a bech32 or bech32m string into its lowercase human-readable
+/// part, its data without the checksum, and the variant of its checksum.
+pub fn decode(s: &str) -> Result<(String, Vec<u5>, Variant), Error> {
+    let sep = match s.rfind(SEP) {
+        Some(sep) => sep,
+        None => return Err(Error::MissingSeparator),
+    };
+    let (raw_hrp, raw_data) = (&s[..sep], &s[sep + 1..]);
+    if raw_data.len() < CHECKSUM_LENGTH {
+        return Err(Error::InvalidLength);
+    }
+    let hrp = check_hrp(raw_hrp)?;
+
+    let mut has_lower = raw_hrp.bytes().any(|b| b.is_ascii_lowercase());
+    let mut has_upper = raw_hrp.bytes().any(|b| b.is_ascii_uppercase());
+    let mut data = Vec::with_capacity(raw_data.len());
+    for c in raw_data.chars() {
+        data.push(u5::from_char(c)?);
+        has_lower |= c.is_ascii_lowercase();
+        has_upper |= c.is_ascii_uppercase();
+    }
+    if has_lower && has_upper {
+        return Err(Error::MixedCase);
+    }
+
+    let mut values = hrp_expand(hrp.as_bytes());
+    values.extend_from_slice(&data);
+    let variant = match Variant::from_remainder(polymod(&values)) {
+        Some(variant) => variant,
+        None => return Err(Error::InvalidChecksum),
+    };
+
+    let data_len = data.len() - CHECKSUM_LENGTH;
+    data.truncate(data_len);
+    Ok((hrp, data, variant))
+}
+
+/// A bech32 error
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Error {
+    /// The string has no separator between the human-readable part and the
+    /// data
+    MissingSeparator,
+    /// The checksum does not match either variant
+    InvalidChecksum,
+    /// The human-readable part or the data has an invalid length
+    InvalidLength,
+    /// A character is not valid in its position
+    InvalidChar(char),
+    /// A value is out of range for its bit width
+    InvalidData(u8),
+    /// The padding bits of base32 data are invalid
+    InvalidPadding,
+    /// The string mixes upper and lower case
+    MixedCase,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::InvalidChar(c) => write!(f, "invalid character: {:?}", c),
+            Error::InvalidData(v) => write!(f, "invalid data point: {}", v),
+            Error::MissingSeparator
+            | Error::InvalidChecksum
+            | Error::InvalidLength
+            | Error::InvalidPadding
+            | Error::MixedCase => f.write_str(error::Error::description(self)),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn cause(&self) -> Option<&error::Error> {
+        None
+    }
+
+    fn description(&self) -> &str {
+        match *self {
+            Error::MissingSeparator => "missing human-readable separator",
+            Error::InvalidChecksum => "invalid checksum",
+            Error::InvalidLength => "invalid length",
+            Error::InvalidChar(_) => "invalid character",
+            Error::InvalidData(_) => "invalid data point",
+            Error::InvalidPadding => "invalid padding",
+            Error::MixedCase => "mixed-case string",
+        }
+    }
+}
+
+impl ErrorCode for Error {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Bech32
+    }
+
+    fn number(&self) -> u32 {
+        match *self {
+            Error::MissingSeparator => 1,
+            Error::InvalidChecksum => 2,
+            Error::InvalidLength => 3,
+            Error::InvalidChar(..) => 4,
+            Error::InvalidData(..) => 5,
+            Error::InvalidPadding => 6,
+            Error::MixedCase => 7,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_strings() {
+        let bech32 = [
+            "A12UEL5L",
+            "a12uel5l",
+            "an83characterlonghumanreadablepartthatcontainsthenumber1andtheexcludedcharactersbio1tt5tgs",
+            "abcdef1qpzry9x8gf2tvdw0s3jn54khce6mua7lmqqqxw",
+            "split1checkupstagehandshakeupstreamerranterredcaperred2y9e3w",
+            "?1ezyfcl",
+        ];
+        let bech32m = [
+            "A1LQFN3A",
+            "a1lqfn3a",
+            "abcdef1l7aum6echk45nj3s0wdvt2fg8x9yrzpqzd3ryx",
+            "split1checkupstagehandshakeupstreamerranterredcaperredlc445v",
+            "?1v759aa",
+        ];
+        for (strings, variant) in [
+            (&bech32[..], Variant::Bech32),
+            (&bech32m[..], Variant::Bech32m),
+        ]
+        .iter()
+        {
+            for s in strings.iter() {
+                let (hrp, data, decoded_variant) = decode(s).unwrap();
+                assert_eq!(decoded_variant, *variant);
+                assert_eq!(encode(&hrp, &data, *variant).unwrap(), s.to_lowercase());
+                let upper = encode_upper(&hrp, &data, *variant).unwrap();
+                assert_eq!(upper, s.to_uppercase());
+                assert_eq!(decode(&upper).unwrap(), (hrp, data, decoded_variant));
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_strings() {
+        let pairs = [
+            (" 1nwldj5", Error::InvalidChar(' ')),
+            ("\x7f1axkwrx", Error::InvalidChar('\x7f')),
+            ("pzry9x0s0muk", Error::MissingSeparator),
+            ("1pzry9x0s0muk", Error::InvalidLength),
+            ("x1b4n0q5v", Error::InvalidChar('b')),
+            ("li1dgmt3", Error::InvalidLength),
+            ("de1lg7wt\u{ff}", Error::InvalidChar('\u{ff}')),
+            // checksum calculated with the uppercase form of the HRP
+            ("A1G7SGD8", Error::InvalidChecksum),
+            ("10a06t8", Error::InvalidLength),
+            ("1qzzfhee", Error::InvalidLength),
+            ("a12UEL5L", Error::MixedCase),
+            ("A12uel5l", Error::MixedCase),
+        ];
+        for &(s, err) in pairs.iter() {
+            assert_eq!(decode(s), Err(err), "{}", s);
+        }
+    }
+
+    #[test]
+    fn variants_differ() {
+        let data = [0u8, 1, 2].to_base32();
+        let bech32 = encode("test", &data, Variant::Bech32).unwrap();
+        let bech32m = encode("test", &data, Variant::Bech32m).unwrap();
+        assert_ne!(bech32, bech32m);
+        assert_eq!(decode(&bech32).unwrap().2, Variant::Bech32);
+        assert_eq!(decode(&bech32m).unwrap().2, Variant::Bech32m);
+        assert_eq!(encode("TEST", &data, Variant::Bech32).unwrap(), bech32);
+        assert_eq!(
+            encode("Test", &data, Variant::Bech32),
+            Err(Error::MixedCase)
+        );
+        assert_eq!(
+            encode("", &data, Variant::Bech32),
+            Err(Error::InvalidLength)
+        );
+    }
+
+    #[test]
+    fn base32_roundtrip() {
+        let bytes: Vec<u8> = (0..=255).collect();
+        for len in 0..40 {
+            let b32 = bytes[..len].to_base32();
+            assert_eq!(b32.len(), (len * 8 + 4) / 5);
+            assert_eq!(Vec::<u8>::from_base32(&b32).unwrap(), &bytes[..len]);
+        }
+
+        // Non-zero padding bits
+        let b32: Vec<u5> = [0u8, 1]
+            .iter()
+            .map(|v| u5::try_from_u8(*v).unwrap())
+            .collect();
+        assert_eq!(Vec::<u8>::from_base32(&b32), Err(Error::InvalidPadding));
+        // A whole 5-bit group of padding
+        let b32 = [u5(0); 2];
+        assert_eq!(
+            Vec::<u8>::from_base32(&b32[..1]),
+            Err(Error::InvalidPadding)
+        );
+        assert_eq!(
+            convert_bits(&[0xff], 5, 8, false),
+            Err(Error::InvalidData(0xff))
+        );
+    }
+
+    #[test]
+    fn u5_conversions() {
+        assert_eq!(u5::try_from_u8(31).unwrap().to_u8(), 31);
+        assert_eq!(u5::try_from_u8(32), Err(Error::InvalidData(32)));
+        for i in 0..32 {
+            let v = u5::try_from_u8(i).unwrap();
+            assert_eq!(u5::from_char(v.to_char()), Ok(v));
+            assert_eq!(u5::from_char(v.to_char().to_ascii_uppercase()), Ok(v));
+        }
+        assert_eq!(u5::from_char('1'), Err(Error::InvalidChar('1')));
+        assert_eq!(u8::from(u5::from_char('l').unwrap()), 31);
+    }
+}
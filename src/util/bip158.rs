@@ -0,0 +1,454 @@
+//! Compact Block Filters
+//!
+//! The basic block filters of
+//! https://github.com/bitcoin/bips/blob/master/bip-0158.mediawiki let a
+//! light wallet find the blocks paying to or spending from its scripts
+//! without telling a server which scripts are its own. A filter is a
+//! Golomb-coded set of the scripts a block creates and spends, hashed
+//! under a key taken from the block hash; it matches every script in the
+//! block, and about one in 784931 others by accident.
+//!
+//! A rescan checks thousands of filters against the same scripts, so
+//! `FilterBatch` spreads the filters over threads, and `RescanPlan` turns
+//! the blocks they match into batches of blocks to download.
+
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
+use std::io::Cursor;
+use std::iter::FromIterator;
+use std::thread;
+
+use blockdata::script::Script;
+use blockdata::transaction::Transaction;
+use consensus::encode::{self, Decodable, Encodable, VarInt};
+use hash_types::BlockHash;
+use network::message::MAX_INV_SZ;
+use util::golomb::{golomb_rice_decode, golomb_rice_encode, BitReader, BitWriter};
+use util::siphash::SipHasher24;
+
+/// The Golomb-Rice parameter of basic filters
+pub const P: u8 = 19;
+/// The inverse false positive rate of basic filters
+pub const M: u64 = 784_931;
+
+/// A basic block filter
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct BlockFilter {
+    /// The serialized filter: the number of elements and the Golomb-coded
+    /// set
+    pub content: Vec<u8>,
+}
+
+/// The key filter elements of a block are hashed under
+fn filter_key(block_hash: &BlockHash) -> [u8; 16] {
+    let mut key = [0; 16];
+    key.copy_from_slice(&block_hash[0..16]);
+    key
+}
+
+/// Hashes `element` into the range `[0, n * M)`
+fn hash_to_range(key: &[u8; 16], n: u64, element: &[u8]) -> u64 {
+    let mut hasher = SipHasher24::new_with_key(key);
+    hasher.write(element);
+    ((u128::from(hasher.finish()) * u128::from(n * M)) >> 64) as u64
+}
+
+impl BlockFilter {
+    /// Wraps a serialized filter, as received in a `cfilter` message
+    pub fn new(content: Vec<u8>) -> BlockFilter {
+        BlockFilter { content }
+    }
+
+    /// Builds the filter of a set of elements. Empty elements are left out.
+    pub fn from_elements<'a, I>(block_hash: &BlockHash, elements: I) -> BlockFilter
+    where
+        I: IntoIterator<Item = &'a [u8]>,
+    {
+        let elements: BTreeSet<&[u8]> = elements.into_iter().filter(|e| !e.is_empty()).collect();
+        let key = filter_key(block_hash);
+        let n = elements.len() as u64;
+        let mut hashes: Vec<u64> = elements.iter().map(|e| hash_to_range(&key, n, e)).collect();
+        hashes.sort_unstable();
+
+        let mut content = vec![];
+        VarInt(n).consensus_encode(&mut content).unwrap();
+        {
+            let mut writer = BitWriter::new(&mut content);
+            let mut last = 0;
+            for hash in hashes {
+                golomb_rice_encode(&mut writer, P, hash - last).unwrap();
+                last = hash;
+            }
+            writer.flush().unwrap();
+        }
+        BlockFilter { content }
+    }
+
+    /// Builds the basic filter of a block: the script pubkeys of its
+    /// outputs other than `OP_RETURN` outputs, and `spent`, the script
+    /// pubkeys of the outputs its inputs spend
+    pub fn new_basic<'a, I>(
+        block_hash: &BlockHash,
+        txdata: &'a [Transaction],
+        spent: I,
+    ) -> BlockFilter
+    where
+        I: IntoIterator<Item = &'a Script>,
+    {
+        let outputs = txdata
+            .iter()
+            .flat_map(|tx| tx.output.iter())
+            .map(|output| &output.script_pubkey)
+            .filter(|script| !script.is_op_return());
+        BlockFilter::from_elements(
+            block_hash,
+            outputs.chain(spent).map(|script| script.as_bytes()),
+        )
+    }
+
+    /// Whether the filter matches any of `scripts`. A match may be a false
+    /// positive; no match means none of the scripts are in the block.
+    pub fn match_any(
+        &self,
+        block_hash: &BlockHash,
+        scripts: &ScriptSet,
+    ) -> Result<bool, encode::Error> {
+        if scripts.is_empty() {
+            return Ok(false);
+        }
+        let mut cursor = Cursor::new(&self.content[..]);
+        let n = VarInt::consensus_decode(&mut cursor)?.0;
+        if n == 0 {
+            return Ok(false);
+        }
+        if u32::try_from(n).is_err() {
+            return Err(encode::Error::ParseFailed("too many filter elements"));
+        }
+
+        let key = filter_key(block_hash);
+        let mut queries: Vec<u64> = scripts
+            .scripts
+            .iter()
+            .map(|s| hash_to_range(&key, n, s.as_bytes()))
+            .collect();
+        queries.sort_unstable();
+
+        // Walk the set and the sorted queries together
+        let mut reader = BitReader::new(cursor);
+        let mut value = 0u64;
+        let mut queries = queries.into_iter().peekable();
+        for _ in 0..n {
+            value += golomb_rice_decode(&mut reader, P)?;
+            while let Some(&query) = queries.peek() {
+                if query < value {
+                    queries.next();
+                } else if query == value {
+                    return Ok(true);
+                } else {
+                    break;
+                }
+            }
+            if queries.peek().is_none() {
+                break;
+            }
+        }
+        Ok(false)
+    }
+}
+
+/// The scripts a rescan looks for
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct ScriptSet {
+    scripts: BTreeSet<Script>,
+}
+
+impl ScriptSet {
+    /// Creates an empty set
+    pub fn new() -> ScriptSet {
+        ScriptSet::default()
+    }
+
+    /// Adds a script, returning whether it was not already in the set.
+    /// Empty scripts are never in a filter and are not added.
+    pub fn insert(&mut self, script: Script) -> bool {
+        !script.is_empty() && self.scripts.insert(script)
+    }
+
+    /// Whether the set holds `script`
+    pub fn contains(&self, script: &Script) -> bool {
+        self.scripts.contains(script)
+    }
+
+    /// The number of scripts
+    pub fn len(&self) -> usize {
+        self.scripts.len()
+    }
+
+    /// Whether the set is empty
+    pub fn is_empty(&self) -> bool {
+        self.scripts.is_empty()
+    }
+}
+
+impl FromIterator<Script> for ScriptSet {
+    fn from_iter<I: IntoIterator<Item = Script>>(iter: I) -> ScriptSet {
+        let mut set = ScriptSet::new();
+        for script in iter {
+            set.insert(script);
+        }
+        set
+    }
+}
+
+/// The filters of a run of blocks, to be matched together
+#[derive(Clone, Debug)]
+pub struct FilterBatch {
+    filters: Vec<(u32, BlockHash, BlockFilter)>,
+    threads: usize,
+}
+
+impl FilterBatch {
+    /// Creates an empty batch, which matches on as many threads as the
+    /// machine has
+    pub fn new() -> FilterBatch {
+        FilterBatch {
+            filters: vec![],
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+        }
+    }
+
+    /// Sets the number of threads to match on, at least 1
+    pub fn threads(mut self, threads: usize) -> FilterBatch {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Adds the filter of the block at `height`
+    pub fn push(&mut self, height: u32, block_hash: BlockHash, filter: BlockFilter) {
+        self.filters.push((height, block_hash, filter));
+    }
+
+    /// The number of filters
+    pub fn len(&self) -> usize {
+        self.filters.len()
+    }
+
+    /// Whether the batch is empty
+    pub fn is_empty(&self) -> bool {
+        self.filters.is_empty()
+    }
+
+    /// The heights and hashes of the blocks whose filters match any of
+    /// `scripts`, in the order the filters were added. A filter which does
+    /// not decode counts as a match, as a needless download is cheaper
+    /// than a missed payment.
+    fn matches(&self, scripts: &ScriptSet) -> Vec<(u32, BlockHash)> {
+        let check = |filters: &[(u32, BlockHash, BlockFilter)]| -> Vec<(u32, BlockHash)> {
+            filters
+                .iter()
+                .filter(|f| f.2.match_any(&f.1, scripts).unwrap_or(true))
+                .map(|f| (f.0, f.1))
+                .collect()
+        };
+        if self.threads == 1 || self.filters.len() < 2 {
+            return check(&self.filters);
+        }
+        let chunk_size = self.filters.len().div_ceil(self.threads);
+        thread::scope(|scope| {
+            let handles: Vec<_> = self
+                .filters
+                .chunks(chunk_size)
+                .map(|chunk| scope.spawn(move || check(chunk)))
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().expect("filter matching panicked"))
+                .collect()
+        })
+    }
+
+    /// The hashes of the blocks whose filters match any of `scripts`, in
+    /// the order the filters were added
+    pub fn match_scripts(&self, scripts: &ScriptSet) -> Vec<BlockHash> {
+        self.matches(scripts).into_iter().map(|m| m.1).collect()
+    }
+
+    /// Plans the downloads of the blocks whose filters match any of
+    /// `scripts`, at most `batch_size` blocks a request
+    pub fn plan_rescan(&self, scripts: &ScriptSet, batch_size: usize) -> RescanPlan {
+        RescanPlan::new(self.matches(scripts), batch_size)
+    }
+}
+
+impl Default for FilterBatch {
+    fn default() -> FilterBatch {
+        FilterBatch::new()
+    }
+}
+
+/// The blocks a rescan has to download, in chain order and split into
+/// requests
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct RescanPlan {
+    blocks: Vec<(u32, BlockHash)>,
+    batch_size: usize,
+}
+
+impl RescanPlan {
+    /// Plans the downloads of blocks given by height and hash, at most
+    /// `batch_size` a request and never more than a `getdata` message
+    /// holds. Duplicates are dropped.
+    pub fn new(mut blocks: Vec<(u32, BlockHash)>, batch_size: usize) -> RescanPlan {
+        blocks.sort();
+        blocks.dedup();
+        RescanPlan {
+            blocks,
+            batch_size: batch_size.clamp(1, MAX_INV_SZ),
+        }
+    }
+
+    /// The heights and hashes of the blocks to download, in chain order
+    pub fn blocks(&self) -> &[(u32, BlockHash)] {
+        &self.blocks
+    }
+
+    /// The hashes of the blocks to ask for in each request
+    pub fn requests(&self) -> Vec<Vec<BlockHash>> {
+        self.blocks
+            .chunks(self.batch_size)
+            .map(|chunk| chunk.iter().map(|b| b.1).collect())
+            .collect()
+    }
+
+    /// The number of blocks to download
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Whether there is nothing to download
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    /// Drops the blocks up to and including `height` once they have been
+    /// processed, so that an interrupted rescan picks up where it stopped
+    pub fn complete_through(&mut self, height: u32) {
+        self.blocks.retain(|b| b.0 > height);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use hashes::hex::FromHex;
+    use hashes::Hash;
+    use testutil::{InputKind, OutputKind, TxFixture};
+
+    fn script(hex: &str) -> Script {
+        Script::from(Vec::<u8>::from_hex(hex).unwrap())
+    }
+
+    #[test]
+    fn genesis_filter() {
+        // From the BIP158 test vectors: the testnet genesis block
+        let block_hash =
+            BlockHash::from_hex("000000000933ea01ad0ee984209779baaec3ced90fa3f408719526f8d77f4943")
+                .unwrap();
+        let coinbase = script(
+            "4104678afdb0fe5548271967f1a67130b7105cd6a828e03909a67962e0ea1f61deb649f6bc3f4cef38c4f\
+             35504e51ec112de5c384df7ba0b8d578a4c702b6bf11d5fac",
+        );
+        let filter = BlockFilter::from_elements(&block_hash, vec![coinbase.as_bytes()]);
+        assert_eq!(filter.content, Vec::<u8>::from_hex("019dfca8").unwrap());
+
+        let mut scripts = ScriptSet::new();
+        assert!(!filter.match_any(&block_hash, &scripts).unwrap());
+        assert!(scripts.insert(script("0014aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa")));
+        assert!(!filter.match_any(&block_hash, &scripts).unwrap());
+        assert!(scripts.insert(coinbase.clone()));
+        assert!(!scripts.insert(coinbase));
+        assert!(!scripts.insert(Script::new()));
+        assert!(filter.match_any(&block_hash, &scripts).unwrap());
+
+        let empty = BlockFilter::from_elements(&block_hash, vec![]);
+        assert_eq!(empty.content, vec![0]);
+        assert!(!empty.match_any(&block_hash, &scripts).unwrap());
+        assert!(BlockFilter::new(vec![])
+            .match_any(&block_hash, &scripts)
+            .is_err());
+        assert!(BlockFilter::new(vec![5])
+            .match_any(&block_hash, &scripts)
+            .is_err());
+    }
+
+    #[test]
+    fn basic_filters() {
+        let block_hash =
+            BlockHash::from_hex("00000000000000000002b8d4d1b2a0a3c5e93b2fd8e7f6f1c2e0d3a4b5c6d7e8")
+                .unwrap();
+        let mut tx = TxFixture::new(1)
+            .inputs(1, InputKind::P2wpkh)
+            .outputs(20, OutputKind::P2wpkh)
+            .build();
+        tx.output[0].script_pubkey = script("6a0568656c6c6f");
+        let spent = script("76a91462e907b15cbf27d5425399ebf6f0fb50ebb88f1888ac");
+        let txdata = vec![tx];
+        let filter = BlockFilter::new_basic(&block_hash, &txdata, vec![&spent]);
+
+        for output in &txdata[0].output[1..] {
+            let set: ScriptSet = vec![output.script_pubkey.clone()].into_iter().collect();
+            assert!(filter.match_any(&block_hash, &set).unwrap());
+        }
+        let set: ScriptSet = vec![spent].into_iter().collect();
+        assert!(filter.match_any(&block_hash, &set).unwrap());
+        let set: ScriptSet = vec![txdata[0].output[0].script_pubkey.clone()]
+            .into_iter()
+            .collect();
+        assert!(!filter.match_any(&block_hash, &set).unwrap());
+    }
+
+    #[test]
+    fn batch_and_plan() {
+        let wallet = script("0014bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb");
+        let other = script("0014cccccccccccccccccccccccccccccccccccccccc");
+        let hash = |height: u32| {
+            let mut bytes = [0u8; 32];
+            bytes[..4].copy_from_slice(&height.to_le_bytes());
+            BlockHash::from_slice(&bytes).unwrap()
+        };
+        let mut batch = FilterBatch::new().threads(3);
+        for height in 0..50 {
+            let element = if height % 7 == 3 { &wallet } else { &other };
+            let filter = BlockFilter::from_elements(&hash(height), vec![element.as_bytes()]);
+            batch.push(height, hash(height), filter);
+        }
+        // A corrupt filter is downloaded to be safe
+        batch.push(50, hash(50), BlockFilter::new(vec![0xff]));
+        assert_eq!(batch.len(), 51);
+
+        let scripts: ScriptSet = vec![wallet].into_iter().collect();
+        let expected: Vec<u32> = vec![3, 10, 17, 24, 31, 38, 45, 50];
+        let matched = batch.match_scripts(&scripts);
+        assert_eq!(
+            matched,
+            expected.iter().map(|&h| hash(h)).collect::<Vec<_>>()
+        );
+        assert_eq!(batch.clone().threads(1).match_scripts(&scripts), matched);
+        assert!(batch.match_scripts(&ScriptSet::new()).is_empty());
+
+        let mut plan = batch.plan_rescan(&scripts, 3);
+        assert_eq!(plan.len(), 8);
+        let requests = plan.requests();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[0], vec![hash(3), hash(10), hash(17)]);
+        assert_eq!(requests[2], vec![hash(45), hash(50)]);
+        plan.complete_through(17);
+        assert_eq!(plan.blocks()[0], (24, hash(24)));
+        plan.complete_through(50);
+        assert!(plan.is_empty());
+
+        let plan = RescanPlan::new(vec![(2, hash(2)), (1, hash(1)), (2, hash(2))], 0);
+        assert_eq!(plan.requests(), vec![vec![hash(1)], vec![hash(2)]]);
+    }
+}
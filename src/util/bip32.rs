@@ -0,0 +1,547 @@
+//! BIP32 Implementation
+//!
+//! Implementation of BIP32 hierarchical deterministic wallet derivation
+//! paths, as defined at https://github.com/bitcoin/bips/blob/master/bip-0032.mediawiki
+
+use std::str::FromStr;
+use std::{error, fmt};
+
+use hashes::hex::FromHex;
+
+use error::{ErrorCode, ErrorKind};
+
+/// A child number for a derived key
+#[derive(Copy, Clone, PartialEq, Eq, Debug, PartialOrd, Ord, Hash)]
+pub enum ChildNumber {
+    /// Non-hardened key
+    Normal {
+        /// Key index, within [0, 2^31 - 1]
+        index: u32,
+    },
+    /// Hardened key
+    Hardened {
+        /// Key index, within [0, 2^31 - 1]
+        index: u32,
+    },
+}
+
+impl ChildNumber {
+    /// Create a [`Normal`] from an index, returns an error if the index is not within
+    /// [0, 2^31 - 1].
+    ///
+    /// [`Normal`]: #variant.Normal
+    pub fn from_normal_idx(index: u32) -> Result<Self, Error> {
+        if index & (1 << 31) == 0 {
+            Ok(ChildNumber::Normal { index })
+        } else {
+            Err(Error::InvalidChildNumber(index))
+        }
+    }
+
+    /// Create a [`Hardened`] from an index, returns an error if the index is not within
+    /// [0, 2^31 - 1].
+    ///
+    /// [`Hardened`]: #variant.Hardened
+    pub fn from_hardened_idx(index: u32) -> Result<Self, Error> {
+        if index & (1 << 31) == 0 {
+            Ok(ChildNumber::Hardened { index })
+        } else {
+            Err(Error::InvalidChildNumber(index))
+        }
+    }
+
+    /// Returns `true` if the child number is a [`Normal`] value.
+    ///
+    /// [`Normal`]: #variant.Normal
+    pub fn is_normal(&self) -> bool {
+        !self.is_hardened()
+    }
+
+    /// Returns `true` if the child number is a [`Hardened`] value.
+    ///
+    /// [`Hardened`]: #variant.Hardened
+    pub fn is_hardened(&self) -> bool {
+        match *self {
+            ChildNumber::Hardened { .. } => true,
+            ChildNumber::Normal { .. } => false,
+        }
+    }
+}
+
+impl From<u32> for ChildNumber {
+    fn from(number: u32) -> Self {
+        if number & (1 << 31) != 0 {
+            ChildNumber::Hardened {
+                index: number ^ (1 << 31),
+            }
+        } else {
+            ChildNumber::Normal { index: number }
+        }
+    }
+}
+
+impl From<ChildNumber> for u32 {
+    fn from(cnum: ChildNumber) -> Self {
+        match cnum {
+            ChildNumber::Normal { index } => index,
+            ChildNumber::Hardened { index } => index | (1 << 31),
+        }
+    }
+}
+
+impl fmt::Display for ChildNumber {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ChildNumber::Hardened { index } => write!(f, "{}'", index),
+            ChildNumber::Normal { index } => write!(f, "{}", index),
+        }
+    }
+}
+
+/// Parses a child number as it is displayed, with hardened ones marked by
+/// `'`. `h` is accepted in place of `'`, but a child number is always
+/// displayed with `'`.
+impl FromStr for ChildNumber {
+    type Err = Error;
+
+    fn from_str(inp: &str) -> Result<ChildNumber, Error> {
+        let is_hardened = inp.chars().last().map_or(false, |l| l == '\'' || l == 'h');
+        Ok(if is_hardened {
+            ChildNumber::from_hardened_idx(
+                inp[0..inp.len() - 1]
+                    .parse()
+                    .map_err(|_| Error::InvalidChildNumberFormat)?,
+            )?
+        } else {
+            ChildNumber::from_normal_idx(inp.parse().map_err(|_| Error::InvalidChildNumberFormat)?)?
+        })
+    }
+}
+
+/// A BIP-32 derivation path.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct DerivationPath(Vec<ChildNumber>);
+impl_index_newtype!(DerivationPath, ChildNumber);
+
+impl From<Vec<ChildNumber>> for DerivationPath {
+    fn from(numbers: Vec<ChildNumber>) -> Self {
+        DerivationPath(numbers)
+    }
+}
+
+impl Into<Vec<ChildNumber>> for DerivationPath {
+    fn into(self) -> Vec<ChildNumber> {
+        self.0
+    }
+}
+
+impl<'a> From<&'a [ChildNumber]> for DerivationPath {
+    fn from(numbers: &'a [ChildNumber]) -> Self {
+        DerivationPath(numbers.to_vec())
+    }
+}
+
+impl ::std::iter::FromIterator<ChildNumber> for DerivationPath {
+    fn from_iter<T>(iter: T) -> Self
+    where
+        T: IntoIterator<Item = ChildNumber>,
+    {
+        DerivationPath(Vec::from_iter(iter))
+    }
+}
+
+impl<'a> ::std::iter::IntoIterator for &'a DerivationPath {
+    type Item = &'a ChildNumber;
+    type IntoIter = ::std::slice::Iter<'a, ChildNumber>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+impl AsRef<[ChildNumber]> for DerivationPath {
+    fn as_ref(&self) -> &[ChildNumber] {
+        &self.0
+    }
+}
+
+impl FromStr for DerivationPath {
+    type Err = Error;
+
+    fn from_str(path: &str) -> Result<DerivationPath, Error> {
+        let mut parts = path.split('/');
+        // First parts must be `m`.
+        if parts.next().unwrap() != "m" {
+            return Err(Error::InvalidDerivationPathFormat);
+        }
+
+        let ret: Result<Vec<ChildNumber>, Error> = parts.map(str::parse).collect();
+        Ok(DerivationPath(ret?))
+    }
+}
+
+impl DerivationPath {
+    /// Returns the number of child numbers in the path
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Whether this path is the master key itself
+    pub fn is_master(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Create a new [DerivationPath] that is a child of this one.
+    pub fn child(&self, cn: ChildNumber) -> DerivationPath {
+        let mut path = self.0.clone();
+        path.push(cn);
+        DerivationPath(path)
+    }
+
+    /// Concatenate `self` with `path` and return the resulting new path.
+    pub fn extend<T: AsRef<[ChildNumber]>>(&self, path: T) -> DerivationPath {
+        let mut new_path = self.clone();
+        new_path.0.extend_from_slice(path.as_ref());
+        new_path
+    }
+}
+
+impl fmt::Display for DerivationPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("m")?;
+        for cn in self.0.iter() {
+            f.write_str("/")?;
+            fmt::Display::fmt(cn, f)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for DerivationPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self, f)
+    }
+}
+
+/// The first four bytes of the hash160 of a master public key, used to
+/// identify the key a path is derived from.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Fingerprint([u8; 4]);
+
+impl Fingerprint {
+    /// Returns the four bytes of the fingerprint.
+    pub fn as_bytes(&self) -> &[u8; 4] {
+        &self.0
+    }
+
+    /// Creates a fingerprint from a byte slice, returns `None` if the slice
+    /// is not four bytes long.
+    pub fn from_slice(sl: &[u8]) -> Option<Fingerprint> {
+        if sl.len() != 4 {
+            return None;
+        }
+        let mut ret = [0; 4];
+        ret.copy_from_slice(sl);
+        Some(Fingerprint(ret))
+    }
+}
+
+impl From<[u8; 4]> for Fingerprint {
+    fn from(bytes: [u8; 4]) -> Self {
+        Fingerprint(bytes)
+    }
+}
+
+impl From<Fingerprint> for [u8; 4] {
+    fn from(fingerprint: Fingerprint) -> Self {
+        fingerprint.0
+    }
+}
+
+impl AsRef<[u8]> for Fingerprint {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for byte in self.0.iter() {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self, f)
+    }
+}
+
+impl FromStr for Fingerprint {
+    type Err = Error;
+
+    fn from_str(inp: &str) -> Result<Fingerprint, Error> {
+        let bytes: Vec<u8> = Vec::from_hex(inp).map_err(|_| Error::InvalidFingerprint)?;
+        Fingerprint::from_slice(&bytes).ok_or(Error::InvalidFingerprint)
+    }
+}
+
+/// The origin of a derived key: the fingerprint of the master key and the
+/// path from it, written as `deadbeef/84'/0'/0'`.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Debug)]
+pub struct KeySource(pub Fingerprint, pub DerivationPath);
+
+impl KeySource {
+    /// The fingerprint of the master key.
+    pub fn fingerprint(&self) -> Fingerprint {
+        self.0
+    }
+
+    /// The derivation path from the master key.
+    pub fn path(&self) -> &DerivationPath {
+        &self.1
+    }
+}
+
+impl fmt::Display for KeySource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)?;
+        for cn in self.1.into_iter() {
+            f.write_str("/")?;
+            fmt::Display::fmt(cn, f)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for KeySource {
+    type Err = Error;
+
+    fn from_str(inp: &str) -> Result<KeySource, Error> {
+        let mut parts = inp.split('/');
+        let fingerprint = parts.next().unwrap().parse()?;
+        let path: Result<Vec<ChildNumber>, Error> = parts.map(str::parse).collect();
+        Ok(KeySource(fingerprint, DerivationPath(path?)))
+    }
+}
+
+/// A BIP32 error
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Error {
+    /// A child number was provided that was out of range
+    InvalidChildNumber(u32),
+    /// Error creating a child number from a string
+    InvalidChildNumberFormat,
+    /// Invalid derivation path format.
+    InvalidDerivationPathFormat,
+    /// A fingerprint was not four hex-encoded bytes.
+    InvalidFingerprint,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::InvalidChildNumber(ref n) => {
+                write!(
+                    f,
+                    "child number {} is invalid (not within [0, 2^31 - 1])",
+                    n
+                )
+            }
+            Error::InvalidChildNumberFormat => f.write_str("invalid child number format"),
+            Error::InvalidDerivationPathFormat => f.write_str("invalid derivation path format"),
+            Error::InvalidFingerprint => f.write_str("invalid fingerprint"),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn cause(&self) -> Option<&error::Error> {
+        None
+    }
+
+    fn description(&self) -> &str {
+        match *self {
+            Error::InvalidChildNumber(_) => "child number is invalid",
+            Error::InvalidChildNumberFormat => "invalid child number format",
+            Error::InvalidDerivationPathFormat => "invalid derivation path format",
+            Error::InvalidFingerprint => "invalid fingerprint",
+        }
+    }
+}
+
+impl ErrorCode for Error {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Bip32
+    }
+
+    fn number(&self) -> u32 {
+        match *self {
+            Error::InvalidChildNumber(..) => 1,
+            Error::InvalidChildNumberFormat => 2,
+            Error::InvalidDerivationPathFormat => 3,
+            Error::InvalidFingerprint => 4,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ChildNumber::{Hardened, Normal};
+    use super::*;
+
+    use std::str::FromStr;
+
+    #[test]
+    fn test_parse_derivation_path() {
+        assert_eq!(
+            DerivationPath::from_str("42"),
+            Err(Error::InvalidDerivationPathFormat)
+        );
+        assert_eq!(
+            DerivationPath::from_str("n/0'/0"),
+            Err(Error::InvalidDerivationPathFormat)
+        );
+        assert_eq!(
+            DerivationPath::from_str("4/m/5"),
+            Err(Error::InvalidDerivationPathFormat)
+        );
+        assert_eq!(
+            DerivationPath::from_str("m//3/0'"),
+            Err(Error::InvalidChildNumberFormat)
+        );
+        assert_eq!(
+            DerivationPath::from_str("m/0h/0x"),
+            Err(Error::InvalidChildNumberFormat)
+        );
+        assert_eq!(
+            DerivationPath::from_str("m/2147483648"),
+            Err(Error::InvalidChildNumber(2147483648))
+        );
+
+        assert_eq!(DerivationPath::from_str("m"), Ok(vec![].into()));
+        assert_eq!(
+            DerivationPath::from_str("m/0'"),
+            Ok(vec![ChildNumber::from_hardened_idx(0).unwrap()].into())
+        );
+        assert_eq!(
+            DerivationPath::from_str("m/0'/1"),
+            Ok(vec![
+                ChildNumber::from_hardened_idx(0).unwrap(),
+                ChildNumber::from_normal_idx(1).unwrap()
+            ]
+            .into())
+        );
+        assert_eq!(
+            DerivationPath::from_str("m/0h/1/2'"),
+            Ok(vec![
+                ChildNumber::from_hardened_idx(0).unwrap(),
+                ChildNumber::from_normal_idx(1).unwrap(),
+                ChildNumber::from_hardened_idx(2).unwrap(),
+            ]
+            .into())
+        );
+    }
+
+    #[test]
+    fn test_derivation_path_display() {
+        let path = DerivationPath::from_str("m/84h/0'/0'/1/5").unwrap();
+        assert_eq!(path.to_string(), "m/84'/0'/0'/1/5");
+        assert_eq!(format!("{:?}", path), "m/84'/0'/0'/1/5");
+        assert_eq!(path.len(), 5);
+        assert_eq!(path[0], Hardened { index: 84 });
+        assert_eq!(path[3..], [Normal { index: 1 }, Normal { index: 5 }]);
+        assert!(DerivationPath::default().is_master());
+        assert_eq!(
+            DerivationPath::default()
+                .child(Hardened { index: 44 })
+                .extend(&[Normal { index: 0 }][..])
+                .to_string(),
+            "m/44'/0"
+        );
+    }
+
+    #[test]
+    fn test_fromstr_display_roundtrip() {
+        roundtrip_fromstr_display!(
+            ChildNumber;
+            Normal { index: 0 },
+            Normal { index: (1 << 31) - 1 },
+            Hardened { index: 0 },
+            Hardened { index: (1 << 31) - 1 },
+        );
+        roundtrip_fromstr_display!(
+            DerivationPath;
+            DerivationPath::default(),
+            DerivationPath::from(vec![Hardened { index: 84 }, Normal { index: 1 }]),
+        );
+        roundtrip_fromstr_display!(Fingerprint; Fingerprint::default(), [1, 2, 3, 4].into());
+        roundtrip_fromstr_display!(
+            KeySource;
+            KeySource::default(),
+            KeySource(
+                [0xde, 0xad, 0xbe, 0xef].into(),
+                vec![Hardened { index: 0 }, Normal { index: 7 }].into(),
+            ),
+        );
+
+        // Accepted, but displayed with `'`
+        assert_eq!(ChildNumber::from_str("1h").unwrap().to_string(), "1'");
+    }
+
+    #[test]
+    fn test_child_number_u32() {
+        assert_eq!(ChildNumber::from(0x80000001), Hardened { index: 1 });
+        assert_eq!(ChildNumber::from(7), Normal { index: 7 });
+        assert_eq!(u32::from(Hardened { index: 84 }), 0x80000054);
+        assert!(ChildNumber::from_normal_idx(1 << 31).is_err());
+        assert!(Hardened { index: 0 }.is_hardened());
+        assert!(Normal { index: 0 }.is_normal());
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let fp = Fingerprint::from_str("deadBEEF").unwrap();
+        assert_eq!(fp, Fingerprint::from([0xde, 0xad, 0xbe, 0xef]));
+        assert_eq!(fp.to_string(), "deadbeef");
+        assert_eq!(format!("{:?}", fp), "deadbeef");
+        assert_eq!(
+            Fingerprint::from_str("deadbe"),
+            Err(Error::InvalidFingerprint)
+        );
+        assert_eq!(
+            Fingerprint::from_str("deadbeef00"),
+            Err(Error::InvalidFingerprint)
+        );
+        assert_eq!(
+            Fingerprint::from_str("deadbeeg"),
+            Err(Error::InvalidFingerprint)
+        );
+        assert_eq!(Fingerprint::from_slice(&[1, 2, 3]), None);
+    }
+
+    #[test]
+    fn test_key_source() {
+        let source = KeySource::from_str("deadbeef/84h/0'/0'").unwrap();
+        assert_eq!(
+            source.fingerprint(),
+            Fingerprint::from([0xde, 0xad, 0xbe, 0xef])
+        );
+        assert_eq!(
+            source.path(),
+            &DerivationPath::from_str("m/84'/0'/0'").unwrap()
+        );
+        assert_eq!(source.to_string(), "deadbeef/84'/0'/0'");
+
+        let master = KeySource::from_str("00000000").unwrap();
+        assert!(master.path().is_master());
+        assert_eq!(master.to_string(), "00000000");
+
+        assert_eq!(KeySource::from_str("m/84'"), Err(Error::InvalidFingerprint));
+        assert_eq!(
+            KeySource::from_str("deadbeef//0'"),
+            Err(Error::InvalidChildNumberFormat)
+        );
+    }
+}
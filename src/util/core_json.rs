@@ -0,0 +1,843 @@
+//! Bitcoin Core JSON
+//!
+//! Bitcoin Core's RPC describes transactions in JSON, for example in the
+//! output of `decoderawtransaction`. The types here mirror that form, with
+//! the same field names, so tooling which consumes dumps from Core can move
+//! over to this library's types.
+//!
+//! `Transaction::to_core_json` fills in every field. `Transaction::from_core_json`
+//! reads scripts from their `hex` and ignores `asm` and the derived fields,
+//! apart from checking the `txid`. With the `serde` feature these types
+//! serialize to and deserialize from Core's JSON.
+//!
+//! Fields Core added after version 0.21, such as the output descriptor
+//! `desc`, are not produced.
+
+use std::{error, fmt};
+
+use blockdata::opcodes;
+use blockdata::script::{Instruction, Script};
+use blockdata::transaction::{OutPoint, Transaction, TxIn, TxOut};
+use consensus::encode::serialize;
+use error::{ErrorCode, ErrorKind};
+use hash_types::{Txid, Wtxid};
+use network::constants::Network;
+use util::address::Address;
+use util::amount::Amount;
+
+/// A transaction as Bitcoin Core's RPC describes it
+#[derive(Clone, PartialEq, Debug)]
+pub struct CoreTransaction {
+    /// The transaction id
+    pub txid: Txid,
+    /// The witness transaction id, named `hash` by Core
+    pub hash: Wtxid,
+    /// The version
+    pub version: u32,
+    /// The serialized size
+    pub size: usize,
+    /// The virtual size
+    pub vsize: usize,
+    /// The weight
+    pub weight: usize,
+    /// The lock time
+    pub locktime: u32,
+    /// The inputs
+    pub vin: Vec<CoreTxIn>,
+    /// The outputs
+    pub vout: Vec<CoreTxOut>,
+}
+
+/// A transaction input as Bitcoin Core's RPC describes it. Coinbase inputs
+/// only have `coinbase`, all others `txid`, `vout` and `script_sig`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct CoreTxIn {
+    /// The `script_sig` of a coinbase input
+    pub coinbase: Option<Script>,
+    /// The txid of the output spent
+    pub txid: Option<Txid>,
+    /// The index of the output spent
+    pub vout: Option<u32>,
+    /// The `script_sig`, named `scriptSig` by Core
+    pub script_sig: Option<CoreScriptSig>,
+    /// The witness, which Core leaves out when empty
+    pub txinwitness: Vec<Vec<u8>>,
+    /// The sequence number
+    pub sequence: u32,
+}
+
+/// A `script_sig` as Bitcoin Core's RPC describes it
+#[derive(Clone, PartialEq, Debug)]
+pub struct CoreScriptSig {
+    /// The script in Core's assembly notation
+    pub asm: String,
+    /// The script itself, hex-encoded by Core
+    pub hex: Script,
+}
+
+/// A transaction output as Bitcoin Core's RPC describes it
+#[derive(Clone, PartialEq, Debug)]
+pub struct CoreTxOut {
+    /// The value, in BTC in Core's JSON
+    pub value: Amount,
+    /// The index of the output
+    pub n: u32,
+    /// The `script_pubkey`, named `scriptPubKey` by Core
+    pub script_pubkey: CoreScriptPubKey,
+}
+
+/// A `script_pubkey` as Bitcoin Core's RPC describes it
+#[derive(Clone, PartialEq, Debug)]
+pub struct CoreScriptPubKey {
+    /// The script in Core's assembly notation
+    pub asm: String,
+    /// The script itself, hex-encoded by Core
+    pub hex: Script,
+    /// The address the script pays to, if it has one
+    pub address: Option<String>,
+    /// Core's name for the type of the script, named `type` by Core
+    pub script_type: String,
+}
+
+/// An error converting from Bitcoin Core's JSON
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Error {
+    /// The input at this index is neither a coinbase nor has an outpoint
+    /// and `script_sig`
+    IncompleteInput(usize),
+    /// The `txid` does not match the transaction described
+    TxidMismatch {
+        /// The `txid` of the JSON
+        expected: Txid,
+        /// The txid of the transaction described
+        actual: Txid,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::IncompleteInput(i) => write!(f, "input {} is incomplete", i),
+            Error::TxidMismatch {
+                ref expected,
+                ref actual,
+            } => write!(f, "txid {} does not match transaction {}", expected, actual),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn cause(&self) -> Option<&error::Error> {
+        None
+    }
+
+    fn description(&self) -> &str {
+        match *self {
+            Error::IncompleteInput(..) => "incomplete input",
+            Error::TxidMismatch { .. } => "txid mismatch",
+        }
+    }
+}
+
+impl ErrorCode for Error {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::CoreJson
+    }
+
+    fn number(&self) -> u32 {
+        match *self {
+            Error::IncompleteInput(..) => 1,
+            Error::TxidMismatch { .. } => 2,
+        }
+    }
+}
+
+impl Transaction {
+    /// Describes the transaction as Bitcoin Core's `decoderawtransaction`
+    /// does, with the addresses of outputs on `network`
+    pub fn to_core_json(&self, network: Network) -> CoreTransaction {
+        let weight = self.get_weight();
+        CoreTransaction {
+            txid: self.txid(),
+            hash: self.wtxid(),
+            version: self.version,
+            size: serialize(self).len(),
+            vsize: self.get_vsize(),
+            weight,
+            locktime: self.lock_time,
+            vin: self
+                .input
+                .iter()
+                .map(|input| {
+                    let coinbase = self.is_coinbase();
+                    CoreTxIn {
+                        coinbase: if coinbase {
+                            Some(input.script_sig.clone())
+                        } else {
+                            None
+                        },
+                        txid: if coinbase {
+                            None
+                        } else {
+                            Some(input.previous_output.txid)
+                        },
+                        vout: if coinbase {
+                            None
+                        } else {
+                            Some(input.previous_output.vout)
+                        },
+                        script_sig: if coinbase {
+                            None
+                        } else {
+                            Some(CoreScriptSig {
+                                asm: core_asm(&input.script_sig, true),
+                                hex: input.script_sig.clone(),
+                            })
+                        },
+                        txinwitness: input.witness.clone(),
+                        sequence: input.sequence,
+                    }
+                })
+                .collect(),
+            vout: self
+                .output
+                .iter()
+                .enumerate()
+                .map(|(n, output)| CoreTxOut {
+                    value: Amount::from_sat(output.value),
+                    n: n as u32,
+                    script_pubkey: CoreScriptPubKey {
+                        asm: core_asm(&output.script_pubkey, false),
+                        hex: output.script_pubkey.clone(),
+                        address: Address::from_script(&output.script_pubkey, network)
+                            .map(|a| a.to_string()),
+                        script_type: core_script_type(&output.script_pubkey).to_owned(),
+                    },
+                })
+                .collect(),
+        }
+    }
+
+    /// Reads a transaction from Bitcoin Core's description of it
+    pub fn from_core_json(json: &CoreTransaction) -> Result<Transaction, Error> {
+        let mut input = Vec::with_capacity(json.vin.len());
+        for (i, vin) in json.vin.iter().enumerate() {
+            let (previous_output, script_sig) = match (
+                vin.coinbase.as_ref(),
+                vin.txid,
+                vin.vout,
+                vin.script_sig.as_ref(),
+            ) {
+                (Some(script), _, _, _) => (OutPoint::null(), script.clone()),
+                (None, Some(txid), Some(vout), Some(script_sig)) => {
+                    (OutPoint::new(txid, vout), script_sig.hex.clone())
+                }
+                _ => return Err(Error::IncompleteInput(i)),
+            };
+            input.push(TxIn {
+                previous_output,
+                script_sig,
+                sequence: vin.sequence,
+                witness: vin.txinwitness.clone(),
+            });
+        }
+        let tx = Transaction {
+            version: json.version,
+            lock_time: json.locktime,
+            input,
+            output: json
+                .vout
+                .iter()
+                .map(|vout| TxOut {
+                    value: vout.value.as_sat(),
+                    script_pubkey: vout.script_pubkey.hex.clone(),
+                })
+                .collect(),
+        };
+        let txid = tx.txid();
+        if txid != json.txid {
+            return Err(Error::TxidMismatch {
+                expected: json.txid,
+                actual: txid,
+            });
+        }
+        Ok(tx)
+    }
+}
+
+/// Formats `script` in the assembly notation of Bitcoin Core's RPC. For
+/// a `script_sig`, `sighash_decode` replaces the sighash byte of pushes
+/// which look like signatures with the name of the sighash type.
+pub fn core_asm(script: &Script, sighash_decode: bool) -> String {
+    let unspendable = script.is_op_return() || script.len() > 10_000;
+    let mut parts = vec![];
+    for instruction in script.instructions() {
+        match instruction {
+            Ok(Instruction::PushBytes(data)) if data.len() <= 4 => {
+                parts.push(read_scriptnum(data).to_string());
+            }
+            Ok(Instruction::PushBytes(data)) => {
+                let mut hex = String::with_capacity(data.len() * 2 + 20);
+                let mut data = data;
+                let mut decoded = "";
+                if sighash_decode && !unspendable && is_valid_signature_encoding(data) {
+                    decoded = sighash_name(data[data.len() - 1]);
+                    if !decoded.is_empty() {
+                        data = &data[..data.len() - 1];
+                    }
+                }
+                for b in data {
+                    hex.push_str(&format!("{:02x}", b));
+                }
+                if !decoded.is_empty() {
+                    hex.push('[');
+                    hex.push_str(decoded);
+                    hex.push(']');
+                }
+                parts.push(hex);
+            }
+            Ok(Instruction::Op(op)) => parts.push(core_op_name(op)),
+            Err(_) => {
+                parts.push("[error]".to_owned());
+                break;
+            }
+        }
+    }
+    parts.join(" ")
+}
+
+/// Bitcoin Core's name for the type of a script pubkey, as in the `type`
+/// field of its RPC
+pub fn core_script_type(script: &Script) -> &'static str {
+    if script.is_p2pk() {
+        "pubkey"
+    } else if script.is_p2pkh() {
+        "pubkeyhash"
+    } else if script.is_p2sh() {
+        "scripthash"
+    } else if script.is_v0_p2wpkh() {
+        "witness_v0_keyhash"
+    } else if script.is_v0_p2wsh() {
+        "witness_v0_scripthash"
+    } else if script.is_v1_p2tr() {
+        "witness_v1_taproot"
+    } else if script.is_witness_program() {
+        "witness_unknown"
+    } else if script.is_op_return() {
+        "nulldata"
+    } else if is_multisig(script) {
+        "multisig"
+    } else {
+        "nonstandard"
+    }
+}
+
+/// Whether `script` is a bare `m`-of-`n` multisig
+fn is_multisig(script: &Script) -> bool {
+    let instructions: Vec<_> = match script.instructions().collect() {
+        Ok(instructions) => instructions,
+        Err(_) => return false,
+    };
+    if instructions.len() < 4 {
+        return false;
+    }
+    let pushnum = |instruction: &Instruction| match *instruction {
+        Instruction::Op(op) => match op.classify() {
+            opcodes::Class::PushNum(n) if n >= 1 => Some(n as usize),
+            _ => None,
+        },
+        _ => None,
+    };
+    let last = instructions.len() - 1;
+    let (m, n) = match (pushnum(&instructions[0]), pushnum(&instructions[last - 1])) {
+        (Some(m), Some(n)) => (m, n),
+        _ => return false,
+    };
+    m <= n
+        && n == last - 2
+        && instructions[last] == Instruction::Op(opcodes::all::OP_CHECKMULTISIG)
+        && instructions[1..last - 1].iter().all(|i| match *i {
+            Instruction::PushBytes(key) => key.len() == 33 || key.len() == 65,
+            _ => false,
+        })
+}
+
+/// Bitcoin Core's name for an opcode
+fn core_op_name(op: opcodes::All) -> String {
+    if let opcodes::Class::PushNum(n) = op.classify() {
+        return n.to_string();
+    }
+    match op {
+        opcodes::all::OP_CLTV => "OP_CHECKLOCKTIMEVERIFY".to_owned(),
+        opcodes::all::OP_CSV => "OP_CHECKSEQUENCEVERIFY".to_owned(),
+        opcodes::all::OP_RETURN_186 => "OP_CHECKSIGADD".to_owned(),
+        op if op.into_u8() > opcodes::all::OP_RETURN_186.into_u8() => "OP_UNKNOWN".to_owned(),
+        op => format!("{:?}", op),
+    }
+}
+
+/// Reads a number from a push of at most 4 bytes, allowing non-minimal
+/// encodings
+fn read_scriptnum(data: &[u8]) -> i64 {
+    let mut ret = 0i64;
+    for (i, &b) in data.iter().enumerate() {
+        ret |= (b as i64) << (8 * i);
+    }
+    match data.last() {
+        Some(&last) if last & 0x80 != 0 => -(ret & !(0x80 << (8 * (data.len() - 1)))),
+        _ => ret,
+    }
+}
+
+/// The name of a sighash type, or the empty string if it has none
+fn sighash_name(sighash: u8) -> &'static str {
+    match sighash {
+        0x01 => "ALL",
+        0x02 => "NONE",
+        0x03 => "SINGLE",
+        0x81 => "ALL|ANYONECANPAY",
+        0x82 => "NONE|ANYONECANPAY",
+        0x83 => "SINGLE|ANYONECANPAY",
+        _ => "",
+    }
+}
+
+/// Whether `sig` is a DER-encoded signature followed by a sighash byte, as
+/// BIP66 requires
+fn is_valid_signature_encoding(sig: &[u8]) -> bool {
+    if sig.len() < 9 || sig.len() > 73 || sig[0] != 0x30 || sig[1] as usize != sig.len() - 3 {
+        return false;
+    }
+    let len_r = sig[3] as usize;
+    if 5 + len_r >= sig.len() {
+        return false;
+    }
+    let len_s = sig[5 + len_r] as usize;
+    if len_r + len_s + 7 != sig.len() {
+        return false;
+    }
+    if sig[2] != 0x02 || len_r == 0 || sig[4] & 0x80 != 0 {
+        return false;
+    }
+    if len_r > 1 && sig[4] == 0 && sig[5] & 0x80 == 0 {
+        return false;
+    }
+    if sig[len_r + 4] != 0x02 || len_s == 0 || sig[len_r + 6] & 0x80 != 0 {
+        return false;
+    }
+    !(len_s > 1 && sig[len_r + 6] == 0 && sig[len_r + 7] & 0x80 == 0)
+}
+
+#[cfg(feature = "serde")]
+mod serde_impl {
+    use std::fmt;
+
+    use hashes::hex::FromHex;
+    use serde::de::{self, IgnoredAny, MapAccess, Visitor};
+    use serde::ser::{SerializeSeq, SerializeStruct};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::*;
+
+    /// Serializes bytes as a hex string
+    struct Hex<'a>(&'a [u8]);
+
+    impl<'a> Serialize for Hex<'a> {
+        fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+            let mut hex = String::with_capacity(self.0.len() * 2);
+            for b in self.0 {
+                hex.push_str(&format!("{:02x}", b));
+            }
+            s.serialize_str(&hex)
+        }
+    }
+
+    /// Serializes a witness as a list of hex strings
+    struct Witness<'a>(&'a [Vec<u8>]);
+
+    impl<'a> Serialize for Witness<'a> {
+        fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+            let mut seq = s.serialize_seq(Some(self.0.len()))?;
+            for item in self.0 {
+                seq.serialize_element(&Hex(item))?;
+            }
+            seq.end()
+        }
+    }
+
+    fn from_hex<E: de::Error>(hex: &str) -> Result<Vec<u8>, E> {
+        Vec::<u8>::from_hex(hex).map_err(|_| E::custom("invalid hex"))
+    }
+
+    fn txid_from_hex<E: de::Error>(hex: &str) -> Result<Txid, E> {
+        Txid::from_rpc_hex(hex).map_err(|_| E::custom("invalid txid"))
+    }
+
+    impl Serialize for CoreTransaction {
+        fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+            let mut st = s.serialize_struct("CoreTransaction", 9)?;
+            st.serialize_field("txid", &self.txid.to_string())?;
+            st.serialize_field("hash", &self.hash.to_string())?;
+            st.serialize_field("version", &self.version)?;
+            st.serialize_field("size", &self.size)?;
+            st.serialize_field("vsize", &self.vsize)?;
+            st.serialize_field("weight", &self.weight)?;
+            st.serialize_field("locktime", &self.locktime)?;
+            st.serialize_field("vin", &self.vin)?;
+            st.serialize_field("vout", &self.vout)?;
+            st.end()
+        }
+    }
+
+    impl Serialize for CoreTxIn {
+        fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+            let mut st = s.serialize_struct("CoreTxIn", 6)?;
+            if let Some(ref coinbase) = self.coinbase {
+                st.serialize_field("coinbase", &Hex(coinbase.as_bytes()))?;
+            }
+            if let Some(ref txid) = self.txid {
+                st.serialize_field("txid", &txid.to_string())?;
+            }
+            if let Some(ref vout) = self.vout {
+                st.serialize_field("vout", vout)?;
+            }
+            if let Some(ref script_sig) = self.script_sig {
+                st.serialize_field("scriptSig", script_sig)?;
+            }
+            if !self.txinwitness.is_empty() {
+                st.serialize_field("txinwitness", &Witness(&self.txinwitness))?;
+            }
+            st.serialize_field("sequence", &self.sequence)?;
+            st.end()
+        }
+    }
+
+    impl Serialize for CoreScriptSig {
+        fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+            let mut st = s.serialize_struct("CoreScriptSig", 2)?;
+            st.serialize_field("asm", &self.asm)?;
+            st.serialize_field("hex", &Hex(self.hex.as_bytes()))?;
+            st.end()
+        }
+    }
+
+    impl Serialize for CoreTxOut {
+        fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+            let mut st = s.serialize_struct("CoreTxOut", 3)?;
+            st.serialize_field("value", &self.value.as_btc())?;
+            st.serialize_field("n", &self.n)?;
+            st.serialize_field("scriptPubKey", &self.script_pubkey)?;
+            st.end()
+        }
+    }
+
+    impl Serialize for CoreScriptPubKey {
+        fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+            let mut st = s.serialize_struct("CoreScriptPubKey", 4)?;
+            st.serialize_field("asm", &self.asm)?;
+            st.serialize_field("hex", &Hex(self.hex.as_bytes()))?;
+            if let Some(ref address) = self.address {
+                st.serialize_field("address", address)?;
+            }
+            st.serialize_field("type", &self.script_type)?;
+            st.end()
+        }
+    }
+
+    /// Implements `Deserialize` for a struct read from a map, with `$visit`
+    /// as the body of `visit_map`
+    macro_rules! deserialize_map {
+        ($ty:ident, $map:ident => $visit:block) => {
+            impl<'de> Deserialize<'de> for $ty {
+                fn deserialize<D: Deserializer<'de>>(d: D) -> Result<$ty, D::Error> {
+                    struct MapVisitor;
+
+                    impl<'de> Visitor<'de> for MapVisitor {
+                        type Value = $ty;
+
+                        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                            f.write_str(concat!("a Bitcoin Core ", stringify!($ty)))
+                        }
+
+                        fn visit_map<A: MapAccess<'de>>(
+                            self,
+                            mut $map: A,
+                        ) -> Result<$ty, A::Error> $visit
+                    }
+
+                    d.deserialize_map(MapVisitor)
+                }
+            }
+        };
+    }
+
+    fn required<T, E: de::Error>(value: Option<T>, field: &'static str) -> Result<T, E> {
+        value.ok_or_else(|| E::missing_field(field))
+    }
+
+    deserialize_map!(CoreTransaction, map => {
+        let mut txid = None;
+        let mut hash = None;
+        let mut version = None;
+        let mut size = None;
+        let mut vsize = None;
+        let mut weight = None;
+        let mut locktime = None;
+        let mut vin = None;
+        let mut vout = None;
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "txid" => txid = Some(txid_from_hex::<A::Error>(&map.next_value::<String>()?)?),
+                "hash" => {
+                    let hex = map.next_value::<String>()?;
+                    let wtxid = Wtxid::from_rpc_hex(&hex);
+                    hash = Some(wtxid.map_err(|_| <A::Error as de::Error>::custom("invalid hash"))?);
+                }
+                "version" => version = Some(map.next_value()?),
+                "size" => size = Some(map.next_value()?),
+                "vsize" => vsize = Some(map.next_value()?),
+                "weight" => weight = Some(map.next_value()?),
+                "locktime" => locktime = Some(map.next_value()?),
+                "vin" => vin = Some(map.next_value()?),
+                "vout" => vout = Some(map.next_value()?),
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        Ok(CoreTransaction {
+            txid: required::<_, A::Error>(txid, "txid")?,
+            hash: required::<_, A::Error>(hash, "hash")?,
+            version: required::<_, A::Error>(version, "version")?,
+            size: size.unwrap_or(0),
+            vsize: vsize.unwrap_or(0),
+            weight: weight.unwrap_or(0),
+            locktime: required::<_, A::Error>(locktime, "locktime")?,
+            vin: required::<_, A::Error>(vin, "vin")?,
+            vout: required::<_, A::Error>(vout, "vout")?,
+        })
+    });
+
+    deserialize_map!(CoreTxIn, map => {
+        let mut coinbase = None;
+        let mut txid = None;
+        let mut vout = None;
+        let mut script_sig = None;
+        let mut txinwitness = vec![];
+        let mut sequence = None;
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "coinbase" => {
+                    let hex = map.next_value::<String>()?;
+                    coinbase = Some(Script::from(from_hex::<A::Error>(&hex)?));
+                }
+                "txid" => txid = Some(txid_from_hex::<A::Error>(&map.next_value::<String>()?)?),
+                "vout" => vout = Some(map.next_value()?),
+                "scriptSig" => script_sig = Some(map.next_value()?),
+                "txinwitness" => {
+                    for item in map.next_value::<Vec<String>>()? {
+                        txinwitness.push(from_hex::<A::Error>(&item)?);
+                    }
+                }
+                "sequence" => sequence = Some(map.next_value()?),
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        Ok(CoreTxIn {
+            coinbase,
+            txid,
+            vout,
+            script_sig,
+            txinwitness,
+            sequence: required::<_, A::Error>(sequence, "sequence")?,
+        })
+    });
+
+    deserialize_map!(CoreScriptSig, map => {
+        let mut asm = None;
+        let mut hex = None;
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "asm" => asm = Some(map.next_value()?),
+                "hex" => {
+                    let value = map.next_value::<String>()?;
+                    hex = Some(Script::from(from_hex::<A::Error>(&value)?));
+                }
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        Ok(CoreScriptSig {
+            asm: asm.unwrap_or_default(),
+            hex: required::<_, A::Error>(hex, "hex")?,
+        })
+    });
+
+    deserialize_map!(CoreTxOut, map => {
+        let mut value = None;
+        let mut n = None;
+        let mut script_pubkey = None;
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "value" => {
+                    let btc = map.next_value::<f64>()?;
+                    let amount = Amount::from_btc(btc);
+                    value = Some(amount.map_err(<A::Error as de::Error>::custom)?);
+                }
+                "n" => n = Some(map.next_value()?),
+                "scriptPubKey" => script_pubkey = Some(map.next_value()?),
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        Ok(CoreTxOut {
+            value: required::<_, A::Error>(value, "value")?,
+            n: required::<_, A::Error>(n, "n")?,
+            script_pubkey: required::<_, A::Error>(script_pubkey, "scriptPubKey")?,
+        })
+    });
+
+    deserialize_map!(CoreScriptPubKey, map => {
+        let mut asm = None;
+        let mut hex = None;
+        let mut address = None;
+        let mut script_type = None;
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "asm" => asm = Some(map.next_value()?),
+                "hex" => {
+                    let value = map.next_value::<String>()?;
+                    hex = Some(Script::from(from_hex::<A::Error>(&value)?));
+                }
+                "address" => address = Some(map.next_value()?),
+                "type" => script_type = Some(map.next_value()?),
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        Ok(CoreScriptPubKey {
+            asm: asm.unwrap_or_default(),
+            hex: required::<_, A::Error>(hex, "hex")?,
+            address,
+            script_type: script_type.unwrap_or_default(),
+        })
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use consensus::encode::deserialize;
+    use hashes::hex::FromHex;
+
+    const SOME_TX: &str = "0100000001a15d57094aa7a21a28cb20b59aab8fc7d1149a3bdbcddba9c622e4f5f6a99ece010000006c493046022100f93bb0e7d8db7bd46e40132d1f8242026e045f03a0efe71bbb8e3f475e970d790221009337cd7f1f929f00cc6ff01f03729b069a7c21b59b1736ddfee5db5946c5da8c0121033b9b137ee87d5a812d6f506efdd37f0affa7ffc310711c06c7f3e097c9447c52ffffffff0100e1f505000000001976a9140389035a9225b3839e2bbf32d826a1e222031fd888ac00000000";
+
+    #[test]
+    fn transaction_round_trip() {
+        let tx: Transaction = deserialize(&Vec::<u8>::from_hex(SOME_TX).unwrap()).unwrap();
+        let json = tx.to_core_json(Network::Bitcoin);
+
+        assert_eq!(
+            json.txid.to_string(),
+            "a6eab3c14ab5272a58a5ba91505ba1a4b6d7a3a9fcbd187b6cd99a7b6d548cb7"
+        );
+        assert_eq!((json.size, json.vsize, json.weight), (193, 193, 772));
+        assert_eq!(json.vin.len(), 1);
+        assert_eq!(json.vin[0].coinbase, None);
+        assert_eq!(json.vin[0].vout, Some(1));
+        assert_eq!(
+            json.vin[0].script_sig.as_ref().unwrap().asm,
+            "3046022100f93bb0e7d8db7bd46e40132d1f8242026e045f03a0efe71bbb8e3f475e970d790221009337cd7f1f929f00cc6ff01f03729b069a7c21b59b1736ddfee5db5946c5da8c[ALL] 033b9b137ee87d5a812d6f506efdd37f0affa7ffc310711c06c7f3e097c9447c52"
+        );
+        assert_eq!(json.vout[0].value, Amount::ONE_BTC);
+        assert_eq!(
+            json.vout[0].script_pubkey.asm,
+            "OP_DUP OP_HASH160 0389035a9225b3839e2bbf32d826a1e222031fd8 OP_EQUALVERIFY OP_CHECKSIG"
+        );
+        assert_eq!(json.vout[0].script_pubkey.script_type, "pubkeyhash");
+        assert!(json.vout[0].script_pubkey.address.is_some());
+
+        assert_eq!(Transaction::from_core_json(&json), Ok(tx.clone()));
+
+        let mut bad = json.clone();
+        bad.vin[0].script_sig = None;
+        assert_eq!(
+            Transaction::from_core_json(&bad),
+            Err(Error::IncompleteInput(0))
+        );
+        let mut bad = json.clone();
+        bad.locktime = 1;
+        match Transaction::from_core_json(&bad) {
+            Err(Error::TxidMismatch { expected, .. }) => assert_eq!(expected, json.txid),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn coinbase_round_trip() {
+        let tx = Transaction::new_coinbase(
+            227_836,
+            &[0xab; 8],
+            vec![TxOut {
+                value: 25 * 100_000_000,
+                script_pubkey: hex_script!("6a0401020304"),
+            }],
+            None,
+        );
+        let json = tx.to_core_json(Network::Bitcoin);
+        assert_eq!(json.vin[0].coinbase.as_ref(), Some(&tx.input[0].script_sig));
+        assert_eq!(json.vin[0].txid, None);
+        assert_eq!(json.vout[0].script_pubkey.script_type, "nulldata");
+        assert_eq!(json.vout[0].script_pubkey.asm, "OP_RETURN 67305985");
+        assert_eq!(json.vout[0].script_pubkey.address, None);
+        assert_eq!(Transaction::from_core_json(&json), Ok(tx));
+    }
+
+    #[test]
+    fn asm() {
+        assert_eq!(core_asm(&hex_script!("0051604f"), false), "0 1 16 -1");
+        assert_eq!(
+            core_asm(&hex_script!("0281800181b1b2ba"), false),
+            "-129 -1 OP_CHECKLOCKTIMEVERIFY OP_CHECKSEQUENCEVERIFY OP_CHECKSIGADD"
+        );
+        assert_eq!(
+            core_asm(&hex_script!("75bb4c"), false),
+            "OP_DROP OP_UNKNOWN [error]"
+        );
+        assert_eq!(core_asm(&Script::new(), true), "");
+
+        let multisig = hex_script!(
+            "51210279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f8179851ae"
+        );
+        assert_eq!(core_script_type(&multisig), "multisig");
+        assert_eq!(core_script_type(&hex_script!("51ae")), "nonstandard");
+        assert_eq!(
+            core_script_type(&hex_script!(
+                "210279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798ac"
+            )),
+            "pubkey"
+        );
+        assert_eq!(
+            core_script_type(&hex_script!("5210751e76e8199196d454941c45d1b3a323")),
+            "witness_unknown"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn serde_json() {
+        let tx: Transaction = deserialize(&Vec::<u8>::from_hex(SOME_TX).unwrap()).unwrap();
+        let json = tx.to_core_json(Network::Bitcoin);
+        let s = ::serde_json::to_string(&json).unwrap();
+        assert!(s.contains("\"scriptPubKey\":{\"asm\":\"OP_DUP"));
+        assert!(s.contains("\"value\":1.0"));
+        let back: CoreTransaction = ::serde_json::from_str(&s).unwrap();
+        assert_eq!(back, json);
+    }
+}
@@ -0,0 +1,272 @@
+//! Cryptography Backends
+//!
+//! This library does not implement elliptic curve cryptography itself.
+//! Code which needs it, such as transaction validation, is written against
+//! the traits here, and the application supplies an implementation backed
+//! by a library such as libsecp256k1.
+//!
+//! Points are passed as 33-byte compressed public keys and scalars as
+//! 32-byte big-endian numbers, so that the traits do not depend on the
+//! types of any backend.
+
+use std::fmt;
+
+use hashes::{sha256, Hash};
+use util::secret::{DebugSecret, Redacted};
+
+#[cfg(feature = "zeroize")]
+use zeroize::Zeroize;
+
+/// A BIP340 Schnorr signature check: a 32-byte message, an x-only public
+/// key and a 64-byte signature
+pub type SchnorrItem<'a> = (&'a [u8; 32], &'a [u8; 32], &'a [u8; 64]);
+
+/// Verifies BIP340 Schnorr signatures
+pub trait SchnorrVerifier {
+    /// Whether `sig` is a valid signature of `msg` by `pubkey`
+    fn verify_schnorr(&self, msg: &[u8; 32], pubkey: &[u8; 32], sig: &[u8; 64]) -> bool;
+
+    /// Whether every signature in `items` is valid.
+    ///
+    /// The signatures are checked one by one unless the backend overrides
+    /// this with batch verification, which checks many signatures at once
+    /// for less than the cost of checking them separately, as for the
+    /// signatures of a taproot-heavy block. A batch only tells whether all
+    /// signatures are valid, so after a failure the culprit has to be found
+    /// by checking them one by one.
+    fn verify_schnorr_batch(&self, items: &[SchnorrItem]) -> bool {
+        items
+            .iter()
+            .all(|&(msg, pubkey, sig)| self.verify_schnorr(msg, pubkey, sig))
+    }
+}
+
+/// Whether every signature in `items` is valid, using batch verification
+/// if `verifier` supports it
+pub fn verify_schnorr_batch<V: SchnorrVerifier + ?Sized>(
+    verifier: &V,
+    items: &[SchnorrItem],
+) -> bool {
+    verifier.verify_schnorr_batch(items)
+}
+
+/// Verifies ECDSA signatures
+pub trait EcdsaVerifier {
+    /// Whether `sig`, DER encoded and without a sighash byte, is a valid
+    /// signature of `msg` by `pubkey`, a compressed or uncompressed public
+    /// key. How strictly the encodings are checked is up to the backend.
+    fn verify_ecdsa(&self, msg: &[u8; 32], pubkey: &[u8], sig: &[u8]) -> bool;
+}
+
+/// Arithmetic on the secp256k1 curve, for protocols which derive keys
+/// from other keys, such as silent payments
+pub trait CurveArithmetic {
+    /// `scalar`·G, or `None` if `scalar` is zero or not below the curve
+    /// order
+    fn mul_generator(&self, scalar: &[u8; 32]) -> Option<[u8; 33]>;
+
+    /// `scalar`·`point`, or `None` if either is invalid
+    fn mul_point(&self, point: &[u8; 33], scalar: &[u8; 32]) -> Option<[u8; 33]>;
+
+    /// `a` + `b`, or `None` if either is invalid or the sum is the point
+    /// at infinity
+    fn add_points(&self, a: &[u8; 33], b: &[u8; 33]) -> Option<[u8; 33]>;
+
+    /// `a` + `b` modulo the curve order, or `None` if either is invalid or
+    /// the sum is zero
+    fn add_scalars(&self, a: &[u8; 32], b: &[u8; 32]) -> Option<[u8; 32]>;
+}
+
+/// A secret shared by two parties through ECDH: the SHA256 of the point
+/// one's secret key times the other's public key, compressed, as
+/// libsecp256k1 hashes it by default
+#[derive(Clone)]
+pub struct SharedSecret([u8; 32]);
+
+impl SharedSecret {
+    /// The secret shared by the holders of `secret_key` and of the public
+    /// key `public_key`, or `None` if either is invalid
+    pub fn new<C: CurveArithmetic + ?Sized>(
+        crypto: &C,
+        secret_key: &[u8; 32],
+        public_key: &[u8; 33],
+    ) -> Option<SharedSecret> {
+        let point = crypto.mul_point(public_key, secret_key)?;
+        Some(SharedSecret::from_point(&point))
+    }
+
+    /// Hashes a shared point computed by other means
+    pub fn from_point(point: &[u8; 33]) -> SharedSecret {
+        SharedSecret(sha256::Hash::hash(point).into_inner())
+    }
+
+    /// The secret
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
+impl fmt::Debug for SharedSecret {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("SharedSecret").field(&Redacted).finish()
+    }
+}
+
+impl DebugSecret for SharedSecret {
+    fn fmt_secret(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("SharedSecret").field(&self.0).finish()
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for SharedSecret {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::Cell;
+
+    use hashes::hex::{FromHex, ToHex};
+
+    /// Accepts signatures which repeat the first byte of the message, and
+    /// counts the checks made
+    struct Fake {
+        checks: Cell<usize>,
+    }
+
+    impl SchnorrVerifier for Fake {
+        fn verify_schnorr(&self, msg: &[u8; 32], _: &[u8; 32], sig: &[u8; 64]) -> bool {
+            self.checks.set(self.checks.get() + 1);
+            sig.iter().all(|b| *b == msg[0])
+        }
+    }
+
+    /// Checks a batch with a single check, accepting it if every
+    /// signature would be accepted by `Fake`
+    struct FakeBatch;
+
+    impl SchnorrVerifier for FakeBatch {
+        fn verify_schnorr(&self, msg: &[u8; 32], pubkey: &[u8; 32], sig: &[u8; 64]) -> bool {
+            Fake {
+                checks: Cell::new(0),
+            }
+            .verify_schnorr(msg, pubkey, sig)
+        }
+
+        fn verify_schnorr_batch(&self, items: &[SchnorrItem]) -> bool {
+            items.iter().all(|&(msg, _, sig)| sig[0] == msg[0])
+        }
+    }
+
+    #[test]
+    fn batch() {
+        let (msg1, msg2, pubkey) = ([1; 32], [2; 32], [0; 32]);
+        let (sig1, sig2) = ([1; 64], [2; 64]);
+        let valid = [(&msg1, &pubkey, &sig1), (&msg2, &pubkey, &sig2)];
+        let invalid = [
+            (&msg1, &pubkey, &sig1),
+            (&msg2, &pubkey, &sig1),
+            (&msg2, &pubkey, &sig2),
+        ];
+
+        let fake = Fake {
+            checks: Cell::new(0),
+        };
+        assert!(verify_schnorr_batch(&fake, &valid));
+        assert_eq!(fake.checks.get(), 2);
+        // The fallback stops at the first invalid signature
+        assert!(!verify_schnorr_batch(&fake, &invalid));
+        assert_eq!(fake.checks.get(), 4);
+        assert!(verify_schnorr_batch(&fake, &[]));
+
+        let batch: &SchnorrVerifier = &FakeBatch;
+        assert!(verify_schnorr_batch(batch, &valid));
+        assert!(!verify_schnorr_batch(batch, &invalid));
+    }
+
+    /// Takes points and scalars alike as integers modulo a prime, the
+    /// generator being 1, which is enough to show that both parties get the
+    /// same secret
+    struct Toy;
+
+    const P: u64 = (1 << 31) - 1;
+
+    fn value(bytes: &[u8]) -> u64 {
+        bytes.iter().fold(0, |v, b| (v * 256 + *b as u64) % P)
+    }
+
+    fn point(v: u64) -> [u8; 33] {
+        let mut point = [0; 33];
+        point[0] = 2;
+        point[25..].copy_from_slice(&v.to_be_bytes());
+        point
+    }
+
+    fn scalar(v: u64) -> [u8; 32] {
+        let mut scalar = [0; 32];
+        scalar[24..].copy_from_slice(&v.to_be_bytes());
+        scalar
+    }
+
+    impl CurveArithmetic for Toy {
+        fn mul_generator(&self, s: &[u8; 32]) -> Option<[u8; 33]> {
+            Some(point(value(s)))
+        }
+
+        fn mul_point(&self, p: &[u8; 33], s: &[u8; 32]) -> Option<[u8; 33]> {
+            match value(s) {
+                0 => None,
+                s => Some(point(value(&p[1..]) * s % P)),
+            }
+        }
+
+        fn add_points(&self, a: &[u8; 33], b: &[u8; 33]) -> Option<[u8; 33]> {
+            match (value(&a[1..]) + value(&b[1..])) % P {
+                0 => None,
+                sum => Some(point(sum)),
+            }
+        }
+
+        fn add_scalars(&self, a: &[u8; 32], b: &[u8; 32]) -> Option<[u8; 32]> {
+            match (value(a) + value(b)) % P {
+                0 => None,
+                sum => Some(scalar(sum)),
+            }
+        }
+    }
+
+    #[test]
+    fn ecdh() {
+        let (a, b) = ([3; 32], [5; 32]);
+        let (a_pub, b_pub) = (
+            Toy.mul_generator(&a).unwrap(),
+            Toy.mul_generator(&b).unwrap(),
+        );
+        let ab = SharedSecret::new(&Toy, &a, &b_pub).unwrap();
+        let ba = SharedSecret::new(&Toy, &b, &a_pub).unwrap();
+        assert_eq!(ab.as_bytes(), ba.as_bytes());
+        assert!(SharedSecret::new(&Toy, &[0; 32], &b_pub).is_none());
+
+        // Tweaking the secret key tweaks the public key the same way
+        let sum = Toy.add_scalars(&a, &b).unwrap();
+        assert_eq!(Toy.add_points(&a_pub, &b_pub), Toy.mul_generator(&sum));
+        assert!(Toy.add_scalars(&a, &scalar(P - value(&a))).is_none());
+
+        // The hash of the generator point
+        let g = Vec::from_hex("0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798")
+            .unwrap();
+        let mut point = [0; 33];
+        point.copy_from_slice(&g);
+        assert_eq!(
+            SharedSecret::from_point(&point).as_bytes().to_hex(),
+            "0f715baf5d4c2ed329785cef29e562f73488c8a2bb9dbc5700b361d54b9b0554"
+        );
+        assert_eq!(format!("{:?}", ab), "SharedSecret([redacted])");
+        assert!(!format!("{:?}", ab.dangerous_debug()).contains("redacted"));
+    }
+}
@@ -0,0 +1,627 @@
+//! Encrypted Containers
+//!
+//! Password-based encryption of PSBTs, secret keys and other data for
+//! writing them to disk. The key is derived from the password with scrypt,
+//! as BIP38 does, and the data is sealed with ChaCha20-Poly1305 (RFC 8439),
+//! both from the RustCrypto crates. Only built with the `crypto-container`
+//! feature.
+//!
+//! A container is laid out as follows, with the integers little-endian:
+//!
+//! | bytes | field                                        |
+//! |-------|----------------------------------------------|
+//! | 4     | magic, `btce`                                |
+//! | 1     | version, 1                                   |
+//! | 1     | content type                                 |
+//! | 1     | scrypt log2(N)                               |
+//! | 4     | scrypt r                                     |
+//! | 4     | scrypt p                                     |
+//! | 16    | salt                                         |
+//! | 12    | nonce                                        |
+//! | ..    | ciphertext, followed by the 16 byte tag      |
+//!
+//! Everything before the ciphertext is authenticated along with it, so a
+//! container whose header was tampered with fails to decrypt.
+//!
+//! The scrypt parameters are read from the header before the password can
+//! be checked, so a container from an untrusted source could ask for any
+//! amount of memory. `decrypt` refuses more than `MAX_MEMORY`, enough for
+//! the default parameters; `decrypt_with_max_memory` sets another limit.
+
+use std::{error, fmt};
+
+use chacha20poly1305::aead::{AeadInPlace, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Nonce, Tag};
+use scrypt;
+
+use consensus::encode::{self, deserialize, serialize};
+use error::{ErrorCode, ErrorKind};
+use util::psbt::PartiallySignedTransaction;
+use util::rng::Rng;
+use util::secret::{DebugSecret, Redacted};
+
+#[cfg(feature = "zeroize")]
+use zeroize::Zeroize;
+
+/// The first bytes of every container
+pub const MAGIC: [u8; 4] = *b"btce";
+/// The version of the container format written by this library
+pub const VERSION: u8 = 1;
+/// The most memory the scrypt parameters of a container may ask for,
+/// unless decrypted with another limit
+pub const MAX_MEMORY: u64 = 64 << 20;
+/// The largest scrypt parallelization parameter of a container
+pub const MAX_PARALLELISM: u32 = 64;
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+const HEADER_LEN: usize = 4 + 1 + 1 + 1 + 4 + 4 + SALT_LEN + NONCE_LEN;
+
+/// An encrypted container error
+#[derive(Debug)]
+pub enum Error {
+    /// The data does not start with the container magic
+    InvalidMagic,
+    /// The container version is not one this library can read
+    UnsupportedVersion(u8),
+    /// The content type byte is not a known one
+    UnknownContentType(u8),
+    /// The scrypt parameters are invalid or exceed the limits
+    InvalidParams,
+    /// The data is shorter than the smallest container
+    Truncated,
+    /// The password is wrong or the container was modified
+    DecryptionFailed,
+    /// The container holds another type of content than was asked for
+    WrongContentType(ContentType),
+    /// The decrypted content could not be decoded
+    Encode(encode::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::UnsupportedVersion(v) => write!(f, "unsupported container version: {}", v),
+            Error::UnknownContentType(t) => write!(f, "unknown content type: {}", t),
+            Error::WrongContentType(t) => write!(f, "wrong content type: {:?}", t),
+            Error::Encode(ref e) => write!(f, "encoding error: {}", e),
+            Error::InvalidMagic
+            | Error::InvalidParams
+            | Error::Truncated
+            | Error::DecryptionFailed => f.write_str(error::Error::description(self)),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::Encode(ref e) => Some(e),
+            _ => None,
+        }
+    }
+
+    fn description(&self) -> &str {
+        match *self {
+            Error::InvalidMagic => "not an encrypted container",
+            Error::UnsupportedVersion(..) => "unsupported container version",
+            Error::UnknownContentType(..) => "unknown content type",
+            Error::InvalidParams => "invalid scrypt parameters",
+            Error::Truncated => "truncated container",
+            Error::DecryptionFailed => "wrong password or corrupted container",
+            Error::WrongContentType(..) => "wrong content type",
+            Error::Encode(..) => "encoding error",
+        }
+    }
+}
+
+impl ErrorCode for Error {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::CryptoContainer
+    }
+
+    fn number(&self) -> u32 {
+        match *self {
+            Error::InvalidMagic => 1,
+            Error::UnsupportedVersion(..) => 2,
+            Error::UnknownContentType(..) => 3,
+            Error::InvalidParams => 4,
+            Error::Truncated => 5,
+            Error::DecryptionFailed => 6,
+            Error::WrongContentType(..) => 7,
+            Error::Encode(..) => 8,
+        }
+    }
+}
+
+#[doc(hidden)]
+impl From<encode::Error> for Error {
+    fn from(e: encode::Error) -> Error {
+        Error::Encode(e)
+    }
+}
+
+/// What a container holds
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum ContentType {
+    /// Arbitrary data
+    Data = 0,
+    /// A serialized PSBT
+    Psbt = 1,
+    /// A 32 byte secret key
+    SecretKey = 2,
+}
+
+impl ContentType {
+    /// The content type with the given byte
+    pub fn from_u8(b: u8) -> Option<ContentType> {
+        match b {
+            0 => Some(ContentType::Data),
+            1 => Some(ContentType::Psbt),
+            2 => Some(ContentType::SecretKey),
+            _ => None,
+        }
+    }
+}
+
+/// The cost parameters of scrypt
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct ScryptParams {
+    /// log2 of the CPU/memory cost N
+    pub log_n: u8,
+    /// The block size
+    pub r: u32,
+    /// The parallelization
+    pub p: u32,
+}
+
+impl Default for ScryptParams {
+    /// The parameters of BIP38: N = 16384, r = 8, p = 8
+    fn default() -> ScryptParams {
+        ScryptParams {
+            log_n: 14,
+            r: 8,
+            p: 8,
+        }
+    }
+}
+
+impl ScryptParams {
+    /// The memory scrypt takes with these parameters, in bytes
+    pub fn memory(&self) -> u64 {
+        128 * self.r as u64 * (1u64 << self.log_n.min(63))
+    }
+
+    /// Whether the parameters are valid and within `MAX_MEMORY` and
+    /// `MAX_PARALLELISM`
+    pub fn is_valid(&self) -> bool {
+        self.is_valid_within(MAX_MEMORY)
+    }
+
+    /// Whether the parameters are valid and within `max_memory` and
+    /// `MAX_PARALLELISM`
+    pub fn is_valid_within(&self, max_memory: u64) -> bool {
+        self.log_n > 0
+            && self.log_n < 32
+            && self.p <= MAX_PARALLELISM
+            && self.memory() <= max_memory
+            && self.to_scrypt().is_some()
+    }
+
+    /// The parameters for a 32 byte key, if scrypt accepts them
+    fn to_scrypt(self) -> Option<scrypt::Params> {
+        scrypt::Params::new(self.log_n, self.r, self.p, 32).ok()
+    }
+}
+
+/// Decrypted content
+pub struct Plaintext {
+    content_type: ContentType,
+    data: Vec<u8>,
+}
+
+impl Plaintext {
+    /// What the container held
+    pub fn content_type(&self) -> ContentType {
+        self.content_type
+    }
+
+    /// The decrypted bytes
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl fmt::Debug for Plaintext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Plaintext")
+            .field("content_type", &self.content_type)
+            .field("data", &Redacted)
+            .finish()
+    }
+}
+
+impl DebugSecret for Plaintext {
+    fn fmt_secret(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Plaintext")
+            .field("content_type", &self.content_type)
+            .field("data", &self.data)
+            .finish()
+    }
+}
+
+impl Drop for Plaintext {
+    fn drop(&mut self) {
+        wipe(&mut self.data);
+    }
+}
+
+/// Encrypts `plaintext` under `password` into a container
+pub fn encrypt<R: Rng>(
+    password: &[u8],
+    content_type: ContentType,
+    plaintext: &[u8],
+    params: ScryptParams,
+    mut rng: R,
+) -> Result<Vec<u8>, Error> {
+    if !params.is_valid() {
+        return Err(Error::InvalidParams);
+    }
+    let mut salt = [0; SALT_LEN];
+    rng.fill_bytes(&mut salt);
+    let mut nonce = [0; NONCE_LEN];
+    rng.fill_bytes(&mut nonce);
+
+    let mut ret = Vec::with_capacity(HEADER_LEN + plaintext.len() + TAG_LEN);
+    ret.extend_from_slice(&MAGIC);
+    ret.push(VERSION);
+    ret.push(content_type as u8);
+    ret.push(params.log_n);
+    ret.extend_from_slice(&serialize(&params.r));
+    ret.extend_from_slice(&serialize(&params.p));
+    ret.extend_from_slice(&salt);
+    ret.extend_from_slice(&nonce);
+
+    let mut key = derive_key(password, &salt, params);
+    ret.extend_from_slice(plaintext);
+    let tag = seal(&key, &nonce, &mut ret, HEADER_LEN);
+    ret.extend_from_slice(&tag);
+    wipe(&mut key);
+    Ok(ret)
+}
+
+/// Decrypts a container with `password`, refusing scrypt parameters which
+/// take more than `MAX_MEMORY`
+pub fn decrypt(password: &[u8], data: &[u8]) -> Result<Plaintext, Error> {
+    decrypt_with_max_memory(password, data, MAX_MEMORY)
+}
+
+/// Decrypts a container with `password`, refusing scrypt parameters which
+/// take more than `max_memory` bytes
+pub fn decrypt_with_max_memory(
+    password: &[u8],
+    data: &[u8],
+    max_memory: u64,
+) -> Result<Plaintext, Error> {
+    if data.len() < MAGIC.len() || data[..MAGIC.len()] != MAGIC {
+        return Err(Error::InvalidMagic);
+    }
+    if data.len() < MAGIC.len() + 1 {
+        return Err(Error::Truncated);
+    }
+    if data[4] != VERSION {
+        return Err(Error::UnsupportedVersion(data[4]));
+    }
+    if data.len() < HEADER_LEN + TAG_LEN {
+        return Err(Error::Truncated);
+    }
+    let content_type = ContentType::from_u8(data[5]).ok_or(Error::UnknownContentType(data[5]))?;
+    let params = ScryptParams {
+        log_n: data[6],
+        r: deserialize(&data[7..11])?,
+        p: deserialize(&data[11..15])?,
+    };
+    if !params.is_valid_within(max_memory) {
+        return Err(Error::InvalidParams);
+    }
+    let salt = &data[15..15 + SALT_LEN];
+    let mut nonce = [0; NONCE_LEN];
+    nonce.copy_from_slice(&data[15 + SALT_LEN..HEADER_LEN]);
+
+    let mut key = derive_key(password, salt, params);
+    let (header, sealed) = data.split_at(HEADER_LEN);
+    let (ciphertext, tag) = sealed.split_at(sealed.len() - TAG_LEN);
+    // Only the ciphertext is copied, so that the plaintext is decrypted
+    // into the buffer returned and nowhere else
+    let mut buf = ciphertext.to_vec();
+    let opened = open(&key, &nonce, header, &mut buf, tag);
+    wipe(&mut key);
+    if !opened {
+        return Err(Error::DecryptionFailed);
+    }
+    Ok(Plaintext {
+        content_type,
+        data: buf,
+    })
+}
+
+/// Encrypts a PSBT under `password`
+pub fn encrypt_psbt<R: Rng>(
+    password: &[u8],
+    psbt: &PartiallySignedTransaction,
+    params: ScryptParams,
+    rng: R,
+) -> Result<Vec<u8>, Error> {
+    encrypt(password, ContentType::Psbt, &serialize(psbt), params, rng)
+}
+
+/// Decrypts a container holding a PSBT
+pub fn decrypt_psbt(password: &[u8], data: &[u8]) -> Result<PartiallySignedTransaction, Error> {
+    let plaintext = expect(decrypt(password, data)?, ContentType::Psbt)?;
+    Ok(deserialize(plaintext.as_bytes())?)
+}
+
+/// Encrypts a 32 byte secret key under `password`
+pub fn encrypt_secret_key<R: Rng>(
+    password: &[u8],
+    secret: &[u8; 32],
+    params: ScryptParams,
+    rng: R,
+) -> Result<Vec<u8>, Error> {
+    encrypt(password, ContentType::SecretKey, secret, params, rng)
+}
+
+/// Decrypts a container holding a secret key
+pub fn decrypt_secret_key(password: &[u8], data: &[u8]) -> Result<[u8; 32], Error> {
+    let plaintext = expect(decrypt(password, data)?, ContentType::SecretKey)?;
+    if plaintext.as_bytes().len() != 32 {
+        return Err(Error::Encode(encode::Error::ParseFailed(
+            "secret key must be 32 bytes",
+        )));
+    }
+    let mut ret = [0; 32];
+    ret.copy_from_slice(plaintext.as_bytes());
+    Ok(ret)
+}
+
+/// Checks that `plaintext` is of the content type `expected`
+fn expect(plaintext: Plaintext, expected: ContentType) -> Result<Plaintext, Error> {
+    if plaintext.content_type != expected {
+        return Err(Error::WrongContentType(plaintext.content_type));
+    }
+    Ok(plaintext)
+}
+
+/// Derives the 32 byte encryption key with scrypt, from valid parameters
+fn derive_key(password: &[u8], salt: &[u8], params: ScryptParams) -> [u8; 32] {
+    let params = params.to_scrypt().expect("valid scrypt parameters");
+    let mut key = [0; 32];
+    scrypt::scrypt(password, salt, &params, &mut key).expect("valid key length");
+    key
+}
+
+/// Overwrites secret bytes with zeros if the `zeroize` feature is enabled
+fn wipe(_data: &mut [u8]) {
+    #[cfg(feature = "zeroize")]
+    _data.zeroize();
+}
+
+/// Encrypts `buf[aad_len..]` in place with ChaCha20-Poly1305, taking
+/// `buf[..aad_len]` as the additional data, and returns the tag
+fn seal(key: &[u8; 32], nonce: &[u8; NONCE_LEN], buf: &mut [u8], aad_len: usize) -> [u8; TAG_LEN] {
+    let (aad, data) = buf.split_at_mut(aad_len);
+    let tag = ChaCha20Poly1305::new(key.into())
+        .encrypt_in_place_detached(Nonce::from_slice(nonce), aad, data)
+        .expect("container within the ChaCha20 limit");
+    let mut ret = [0; TAG_LEN];
+    ret.copy_from_slice(&tag);
+    ret
+}
+
+/// Checks `tag` and decrypts `buf` in place, the reverse of `seal` with
+/// the additional data `aad` kept apart. Leaves `buf` untouched and returns
+/// false if the tag is wrong.
+fn open(key: &[u8; 32], nonce: &[u8; NONCE_LEN], aad: &[u8], buf: &mut [u8], tag: &[u8]) -> bool {
+    ChaCha20Poly1305::new(key.into())
+        .decrypt_in_place_detached(Nonce::from_slice(nonce), aad, buf, Tag::from_slice(tag))
+        .is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blockdata::transaction::{OutPoint, Transaction, TxIn, TxOut};
+    use hashes::hex::FromHex;
+    use util::rng::SeededRng;
+
+    const FAST: ScryptParams = ScryptParams {
+        log_n: 4,
+        r: 1,
+        p: 1,
+    };
+
+    #[test]
+    fn primitives() {
+        // RFC 7914 section 12, of which the key is the first half
+        assert_eq!(
+            derive_key(
+                b"password",
+                b"NaCl",
+                ScryptParams {
+                    log_n: 10,
+                    r: 8,
+                    p: 16
+                }
+            )[..],
+            Vec::<u8>::from_hex("fdbabe1c9d3472007856e7190d01e9fe7c6ad7cbc8237830e77376634b373162")
+                .unwrap()[..]
+        );
+
+        // RFC 8439 section 2.8.2
+        let mut key = [0; 32];
+        for (i, b) in key.iter_mut().enumerate() {
+            *b = 0x80 + i as u8;
+        }
+        let mut nonce = [0; NONCE_LEN];
+        nonce.copy_from_slice(&Vec::<u8>::from_hex("070000004041424344454647").unwrap());
+        let mut buf = Vec::<u8>::from_hex("50515253c0c1c2c3c4c5c6c7").unwrap();
+        let plaintext: &[u8] = b"Ladies and Gentlemen of the class of '99: If I could offer you \
+            only one tip for the future, sunscreen would be it.";
+        buf.extend_from_slice(plaintext);
+        let tag = seal(&key, &nonce, &mut buf, 12);
+        assert_eq!(
+            tag[..],
+            Vec::<u8>::from_hex("1ae10b594f09e26a7e902ecbd0600691").unwrap()[..]
+        );
+        let (aad, ciphertext) = buf.split_at_mut(12);
+        assert!(open(&key, &nonce, aad, ciphertext, &tag));
+        assert_eq!(ciphertext, plaintext);
+    }
+
+    #[test]
+    fn container_roundtrip() {
+        let data = encrypt(
+            b"hunter2",
+            ContentType::Data,
+            b"backup",
+            FAST,
+            SeededRng::new(1),
+        )
+        .unwrap();
+        assert_eq!(data.len(), HEADER_LEN + 6 + TAG_LEN);
+        assert_eq!(data[..4], MAGIC);
+        assert!(!data.windows(6).any(|w| w == b"backup"));
+
+        let plaintext = decrypt(b"hunter2", &data).unwrap();
+        assert_eq!(plaintext.content_type(), ContentType::Data);
+        assert_eq!(plaintext.as_bytes(), b"backup");
+        assert_eq!(
+            format!("{:?}", plaintext),
+            "Plaintext { content_type: Data, data: [redacted] }"
+        );
+
+        // Another salt and nonce every time
+        let again = encrypt(
+            b"hunter2",
+            ContentType::Data,
+            b"backup",
+            FAST,
+            SeededRng::new(2),
+        )
+        .unwrap();
+        assert_ne!(data, again);
+
+        match decrypt(b"hunter3", &data) {
+            Err(Error::DecryptionFailed) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+        // The header is authenticated
+        let mut tampered = data.clone();
+        tampered[5] = ContentType::Psbt as u8;
+        match decrypt(b"hunter2", &tampered) {
+            Err(Error::DecryptionFailed) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+        let mut tampered = data.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert_eq!(decrypt(b"hunter2", &tampered).unwrap_err().code(), 24006);
+    }
+
+    #[test]
+    fn container_errors() {
+        let data = encrypt(b"pw", ContentType::Data, b"", FAST, SeededRng::new(1)).unwrap();
+        let err = |data: &[u8]| decrypt(b"pw", data).unwrap_err().code();
+
+        assert_eq!(err(b"nope"), 24001);
+        let mut bad = data.clone();
+        bad[4] = 2;
+        assert_eq!(err(&bad), 24002);
+        let mut bad = data.clone();
+        bad[5] = 9;
+        assert_eq!(err(&bad), 24003);
+        let mut bad = data.clone();
+        bad[6] = 30;
+        assert_eq!(err(&bad), 24004);
+        let mut bad = data.clone();
+        bad[11] = 0;
+        assert_eq!(err(&bad), 24004);
+        assert_eq!(err(&data[..data.len() - 1]), 24005);
+
+        match encrypt(
+            b"pw",
+            ContentType::Data,
+            b"",
+            ScryptParams {
+                log_n: 0,
+                r: 1,
+                p: 1,
+            },
+            SeededRng::new(1),
+        ) {
+            Err(Error::InvalidParams) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+        assert!(ScryptParams::default().is_valid());
+        assert_eq!(ScryptParams::default().memory(), 16 << 20);
+    }
+
+    #[test]
+    fn memory_limit() {
+        // A header asking for 128 MiB, rejected before deriving the key
+        let data = encrypt(b"pw", ContentType::Data, b"", FAST, SeededRng::new(1)).unwrap();
+        let mut greedy = data.clone();
+        greedy[6] = 17;
+        greedy[7] = 8;
+        match decrypt(b"pw", &greedy) {
+            Err(Error::InvalidParams) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+        match decrypt_with_max_memory(b"pw", &data, FAST.memory() - 1) {
+            Err(Error::InvalidParams) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+        assert!(decrypt_with_max_memory(b"pw", &data, FAST.memory()).is_ok());
+
+        // Nor are such containers written
+        let params = ScryptParams {
+            log_n: 20,
+            r: 8,
+            p: 1,
+        };
+        assert!(!params.is_valid());
+        assert!(params.is_valid_within(1 << 30));
+        match encrypt(b"pw", ContentType::Data, b"", params, SeededRng::new(1)) {
+            Err(Error::InvalidParams) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn psbt_and_secret_key() {
+        let psbt = PartiallySignedTransaction::from_unsigned_tx(Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint::default(),
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value: 1000,
+                ..Default::default()
+            }],
+        })
+        .unwrap();
+        let data = encrypt_psbt(b"pw", &psbt, FAST, SeededRng::new(3)).unwrap();
+        assert_eq!(decrypt_psbt(b"pw", &data).unwrap(), psbt);
+        match decrypt_secret_key(b"pw", &data) {
+            Err(Error::WrongContentType(ContentType::Psbt)) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+
+        let secret = [7; 32];
+        let data = encrypt_secret_key(b"pw", &secret, FAST, SeededRng::new(4)).unwrap();
+        assert_eq!(decrypt_secret_key(b"pw", &data).unwrap(), secret);
+        assert_eq!(decrypt_psbt(b"pw", &data).unwrap_err().code(), 24007);
+    }
+}
@@ -0,0 +1,354 @@
+//! Fee Rates
+//!
+//! The fee a transaction pays per unit of its weight. Fee rates are kept in
+//! satoshi per 1000 weight units, which is precise enough to express a rate
+//! of a quarter of a satoshi per virtual byte. Multiplying a `FeeRate` by a
+//! `Weight` gives an `Amount`, and dividing an `Amount` by a `Weight` gives
+//! a `FeeRate`, so that fees, weights and rates cannot be mixed up.
+//!
+//! Fee rates are parsed from a number and a unit, such as "12.5 sat/vB",
+//! "3125 sat/kwu" or "1000 sat/kvB", so that a rate is never read in the
+//! wrong unit. They are displayed in sat/kwu, which any rate can be written
+//! in exactly.
+
+use std::str::FromStr;
+use std::{error, fmt, ops};
+
+use error::{ErrorCode, ErrorKind};
+use util::amount::Amount;
+use util::weight::Weight;
+
+/// A fee rate, in satoshi per 1000 weight units
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+pub struct FeeRate(u64);
+
+impl FeeRate {
+    /// A fee rate of zero.
+    pub const ZERO: FeeRate = FeeRate(0);
+    /// The smallest non-zero fee rate.
+    pub const MIN: FeeRate = FeeRate(1);
+    /// The largest fee rate.
+    pub const MAX: FeeRate = FeeRate(u64::max_value());
+    /// The minimum fee rate Bitcoin Core relays by default, 1 sat/vB.
+    pub const BROADCAST_MIN: FeeRate = FeeRate(250);
+    /// The fee rate Bitcoin Core uses to decide whether an output is dust,
+    /// 3 sat/vB.
+    pub const DUST_RELAY: FeeRate = FeeRate(750);
+
+    /// Creates a fee rate of `sat_kwu` satoshi per 1000 weight units.
+    pub fn from_sat_per_kwu(sat_kwu: u64) -> FeeRate {
+        FeeRate(sat_kwu)
+    }
+
+    /// Creates a fee rate of `sat_vb` satoshi per virtual byte, or `None`
+    /// on overflow.
+    pub fn from_sat_per_vb(sat_vb: u64) -> Option<FeeRate> {
+        // 1 vB is 4 wu, so 1 sat/vB is 1000 / 4 sat/kwu
+        sat_vb
+            .checked_mul(1000 / Weight::WITNESS_SCALE_FACTOR)
+            .map(FeeRate)
+    }
+
+    /// Creates a fee rate of `sat_kvb` satoshi per 1000 virtual bytes, the
+    /// unit of Bitcoin Core's RPC, rounded down to the next sat/kwu.
+    pub fn from_sat_per_kvb(sat_kvb: u64) -> FeeRate {
+        FeeRate(sat_kvb / Weight::WITNESS_SCALE_FACTOR)
+    }
+
+    /// The fee rate in satoshi per 1000 weight units.
+    pub fn to_sat_per_kwu(self) -> u64 {
+        self.0
+    }
+
+    /// The fee rate in satoshi per virtual byte, rounded down.
+    pub fn to_sat_per_vb_floor(self) -> u64 {
+        self.0 / (1000 / Weight::WITNESS_SCALE_FACTOR)
+    }
+
+    /// The fee rate in satoshi per virtual byte, rounded up.
+    pub fn to_sat_per_vb_ceil(self) -> u64 {
+        let kwu_per_vb = 1000 / Weight::WITNESS_SCALE_FACTOR;
+        self.0 / kwu_per_vb + if self.0 % kwu_per_vb == 0 { 0 } else { 1 }
+    }
+
+    /// The fee rate in satoshi per 1000 virtual bytes, or `None` on
+    /// overflow.
+    pub fn to_sat_per_kvb(self) -> Option<u64> {
+        self.0.checked_mul(Weight::WITNESS_SCALE_FACTOR)
+    }
+
+    /// Checked multiplication. Returns `None` if overflow occurred.
+    pub fn checked_mul(self, rhs: u64) -> Option<FeeRate> {
+        self.0.checked_mul(rhs).map(FeeRate)
+    }
+
+    /// Checked integer division. Returns `None` if `rhs == 0`.
+    pub fn checked_div(self, rhs: u64) -> Option<FeeRate> {
+        self.0.checked_div(rhs).map(FeeRate)
+    }
+
+    /// The fee paid at this rate by something of the given weight, rounded
+    /// up to the next satoshi. Returns `None` if overflow occurred.
+    pub fn fee_wu(self, weight: Weight) -> Option<Amount> {
+        let sat_kwu = self.0.checked_mul(weight.to_wu())?;
+        Some(Amount::from_sat(
+            sat_kwu / 1000 + if sat_kwu % 1000 == 0 { 0 } else { 1 },
+        ))
+    }
+
+    /// The fee paid at this rate by something of the given weight, as the
+    /// checked form of `FeeRate * Weight`. Returns `None` if overflow
+    /// occurred.
+    pub fn checked_mul_by_weight(self, weight: Weight) -> Option<Amount> {
+        self.fee_wu(weight)
+    }
+
+    /// The fee paid at this rate by something of `vb` virtual bytes.
+    /// Returns `None` if overflow occurred.
+    pub fn fee_vb(self, vb: u64) -> Option<Amount> {
+        self.fee_wu(Weight::from_vb(vb)?)
+    }
+}
+
+impl fmt::Display for FeeRate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} sat/kwu", self.0)
+    }
+}
+
+/// Parses a decimal number followed by a unit, one of "sat/vB", "sat/kwu"
+/// and "sat/kvB" in any case, such as "12.5 sat/vB". The rate has to be a
+/// whole number of sat/kwu.
+impl FromStr for FeeRate {
+    type Err = ParseFeeRateError;
+
+    fn from_str(s: &str) -> Result<FeeRate, ParseFeeRateError> {
+        let mut parts = s.split_whitespace();
+        let (number, unit) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(number), Some(unit), None) => (number, unit),
+            (Some(_), None, None) => return Err(ParseFeeRateError::MissingUnit),
+            _ => return Err(ParseFeeRateError::InvalidFormat),
+        };
+        // The rate in sat/kwu is the number times `mul` divided by `div`
+        let (mul, div): (u128, u128) = match unit.to_lowercase().as_str() {
+            "sat/kwu" => (1, 1),
+            "sat/vb" => (1000 / Weight::WITNESS_SCALE_FACTOR as u128, 1),
+            "sat/kvb" => (1, Weight::WITNESS_SCALE_FACTOR as u128),
+            _ => return Err(ParseFeeRateError::UnknownUnit(unit.to_owned())),
+        };
+
+        let (integer, fraction) = match number.find('.') {
+            Some(dot) => (&number[..dot], &number[dot + 1..]),
+            None => (number, ""),
+        };
+        let all_digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+        if (integer.is_empty() && fraction.is_empty())
+            || !all_digits(integer)
+            || !all_digits(fraction)
+        {
+            return Err(ParseFeeRateError::InvalidFormat);
+        }
+        let mut numerator: u128 = 0;
+        let mut denominator: u128 = div;
+        for digit in integer.bytes().chain(fraction.bytes()) {
+            numerator = numerator
+                .checked_mul(10)
+                .and_then(|n| n.checked_add(u128::from(digit - b'0')))
+                .ok_or(ParseFeeRateError::TooBig)?;
+        }
+        for _ in 0..fraction.len() {
+            denominator = denominator
+                .checked_mul(10)
+                .ok_or(ParseFeeRateError::TooPrecise)?;
+        }
+        let scaled = numerator
+            .checked_mul(mul)
+            .ok_or(ParseFeeRateError::TooBig)?;
+        if scaled % denominator != 0 {
+            return Err(ParseFeeRateError::TooPrecise);
+        }
+        let sat_kwu = scaled / denominator;
+        if sat_kwu > u128::from(u64::max_value()) {
+            return Err(ParseFeeRateError::TooBig);
+        }
+        Ok(FeeRate(sat_kwu as u64))
+    }
+}
+
+/// An error in parsing a [FeeRate] from a string
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseFeeRateError {
+    /// The string is not a number followed by a unit
+    InvalidFormat,
+    /// The number is not followed by a unit
+    MissingUnit,
+    /// The unit is not one of "sat/vB", "sat/kwu" or "sat/kvB"
+    UnknownUnit(String),
+    /// The rate is not a whole number of sat/kwu
+    TooPrecise,
+    /// The rate does not fit in a `FeeRate`
+    TooBig,
+}
+
+impl fmt::Display for ParseFeeRateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseFeeRateError::UnknownUnit(ref unit) => {
+                write!(f, "unknown fee rate unit: {:?}", unit)
+            }
+            _ => f.write_str(error::Error::description(self)),
+        }
+    }
+}
+
+impl error::Error for ParseFeeRateError {
+    fn cause(&self) -> Option<&error::Error> {
+        None
+    }
+
+    fn description(&self) -> &str {
+        match *self {
+            ParseFeeRateError::InvalidFormat => "invalid fee rate format",
+            ParseFeeRateError::MissingUnit => "missing fee rate unit",
+            ParseFeeRateError::UnknownUnit(..) => "unknown fee rate unit",
+            ParseFeeRateError::TooPrecise => "fee rate is not a whole number of sat/kwu",
+            ParseFeeRateError::TooBig => "fee rate too big",
+        }
+    }
+}
+
+impl ErrorCode for ParseFeeRateError {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::FeeRate
+    }
+
+    fn number(&self) -> u32 {
+        match *self {
+            ParseFeeRateError::InvalidFormat => 1,
+            ParseFeeRateError::MissingUnit => 2,
+            ParseFeeRateError::UnknownUnit(..) => 3,
+            ParseFeeRateError::TooPrecise => 4,
+            ParseFeeRateError::TooBig => 5,
+        }
+    }
+}
+
+impl From<FeeRate> for u64 {
+    fn from(rate: FeeRate) -> u64 {
+        rate.0
+    }
+}
+
+impl ops::Mul<Weight> for FeeRate {
+    type Output = Amount;
+
+    fn mul(self, rhs: Weight) -> Self::Output {
+        self.fee_wu(rhs).expect("FeeRate multiplication error")
+    }
+}
+
+impl ops::Mul<FeeRate> for Weight {
+    type Output = Amount;
+
+    fn mul(self, rhs: FeeRate) -> Self::Output {
+        rhs * self
+    }
+}
+
+impl ops::Div<Weight> for Amount {
+    type Output = FeeRate;
+
+    /// The fee rate paid by a fee of this amount for the given weight,
+    /// rounded down.
+    fn div(self, rhs: Weight) -> Self::Output {
+        self.as_sat()
+            .checked_mul(1000)
+            .and_then(|sat| sat.checked_div(rhs.to_wu()))
+            .map(FeeRate)
+            .expect("Amount division error")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conversions() {
+        assert_eq!(FeeRate::from_sat_per_vb(1), Some(FeeRate::BROADCAST_MIN));
+        assert_eq!(FeeRate::from_sat_per_vb(3), Some(FeeRate::DUST_RELAY));
+        assert_eq!(FeeRate::from_sat_per_vb(u64::max_value()), None);
+        assert_eq!(FeeRate::from_sat_per_kwu(501).to_sat_per_vb_floor(), 2);
+        assert_eq!(FeeRate::from_sat_per_kwu(501).to_sat_per_vb_ceil(), 3);
+        assert_eq!(FeeRate::from_sat_per_kwu(500).to_sat_per_vb_ceil(), 2);
+        assert_eq!(FeeRate::DUST_RELAY.to_string(), "750 sat/kwu");
+        assert_eq!(FeeRate::from_sat_per_kvb(1000), FeeRate::BROADCAST_MIN);
+        assert_eq!(FeeRate::from_sat_per_kvb(1001).to_sat_per_kwu(), 250);
+        assert_eq!(FeeRate::BROADCAST_MIN.to_sat_per_kvb(), Some(1000));
+        assert_eq!(FeeRate::MAX.to_sat_per_kvb(), None);
+    }
+
+    #[test]
+    fn from_str() {
+        let parse = |s: &str| s.parse::<FeeRate>();
+        assert_eq!(parse("12.5 sat/vB"), Ok(FeeRate::from_sat_per_kwu(3125)));
+        assert_eq!(parse("1 sat/vb"), Ok(FeeRate::BROADCAST_MIN));
+        assert_eq!(parse(" 3 SAT/VB "), Ok(FeeRate::DUST_RELAY));
+        assert_eq!(parse("0.004 sat/vB"), Ok(FeeRate::MIN));
+        assert_eq!(parse("750 sat/kwu"), Ok(FeeRate::DUST_RELAY));
+        assert_eq!(parse("1000 sat/kvB"), Ok(FeeRate::BROADCAST_MIN));
+        assert_eq!(parse(".5 sat/vB"), Ok(FeeRate::from_sat_per_kwu(125)));
+        assert_eq!(parse("2. sat/vB"), Ok(FeeRate::from_sat_per_kwu(500)));
+        assert_eq!(parse("0 sat/kwu"), Ok(FeeRate::ZERO));
+        assert_eq!(parse(&FeeRate::MAX.to_string()), Ok(FeeRate::MAX));
+
+        assert_eq!(parse("12"), Err(ParseFeeRateError::MissingUnit));
+        assert_eq!(parse(""), Err(ParseFeeRateError::InvalidFormat));
+        assert_eq!(parse("1 2 sat/vB"), Err(ParseFeeRateError::InvalidFormat));
+        assert_eq!(parse(". sat/vB"), Err(ParseFeeRateError::InvalidFormat));
+        assert_eq!(parse("-1 sat/vB"), Err(ParseFeeRateError::InvalidFormat));
+        assert_eq!(parse("1e3 sat/vB"), Err(ParseFeeRateError::InvalidFormat));
+        assert_eq!(
+            parse("12 sat/byte"),
+            Err(ParseFeeRateError::UnknownUnit("sat/byte".to_owned()))
+        );
+        assert_eq!(parse("0.001 sat/vB"), Err(ParseFeeRateError::TooPrecise));
+        assert_eq!(parse("1.5 sat/kwu"), Err(ParseFeeRateError::TooPrecise));
+        assert_eq!(parse("1 sat/kvB"), Err(ParseFeeRateError::TooPrecise));
+        assert_eq!(
+            parse("18446744073709551616 sat/kwu"),
+            Err(ParseFeeRateError::TooBig)
+        );
+        assert_eq!(
+            parse("100000000000000000000 sat/vB"),
+            Err(ParseFeeRateError::TooBig)
+        );
+    }
+
+    #[test]
+    fn fees() {
+        let rate = FeeRate::from_sat_per_vb(10).unwrap();
+        assert_eq!(rate.fee_vb(141), Some(Amount::from_sat(1410)));
+        assert_eq!(rate * Weight::from_wu(561), Amount::from_sat(1403));
+        assert_eq!(Weight::from_wu(561) * rate, Amount::from_sat(1403));
+        assert_eq!(FeeRate::MAX.fee_wu(Weight::from_wu(2)), None);
+        assert_eq!(FeeRate::ZERO * Weight::MAX_BLOCK, Amount::ZERO);
+        assert_eq!(
+            rate.checked_mul_by_weight(Weight::from_wu(561)),
+            Some(Amount::from_sat(1403))
+        );
+        assert_eq!(FeeRate::MAX.checked_mul_by_weight(Weight::from_wu(2)), None);
+
+        assert_eq!(Amount::from_sat(1410) / Weight::from_wu(564), rate);
+        assert_eq!(
+            Amount::from_sat(1000) / Weight::from_wu(3),
+            FeeRate::from_sat_per_kwu(333_333)
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn div_by_zero_weight() {
+        let _ = Amount::ONE_SAT / Weight::ZERO;
+    }
+}
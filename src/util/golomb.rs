@@ -0,0 +1,318 @@
+//! Golomb-Rice Coding
+//!
+//! Bit-level readers and writers, and the Golomb-Rice coding built on them,
+//! as used by the compact block filters of
+//! https://github.com/bitcoin/bips/blob/master/bip-0158.mediawiki
+//!
+//! Bits are written and read most significant first. A value `n` is coded
+//! with parameter `p` as the quotient `n >> p` in unary (that many `1` bits
+//! followed by a `0`) and then the low `p` bits of `n`.
+
+use std::{cmp, io};
+
+/// Reads a stream of bits from an underlying reader.
+pub struct BitReader<R> {
+    buffer: [u8; 1],
+    offset: u8,
+    reader: R,
+}
+
+impl<R: io::Read> BitReader<R> {
+    /// Create a new BitReader that reads bits from `reader`
+    pub fn new(reader: R) -> BitReader<R> {
+        BitReader {
+            buffer: [0u8],
+            offset: 8,
+            reader,
+        }
+    }
+
+    /// Read `nbits` bits, returned in the low bits of the result.
+    ///
+    /// # Panics
+    ///
+    /// If `nbits` is greater than 64.
+    pub fn read(&mut self, mut nbits: u8) -> Result<u64, io::Error> {
+        if nbits > 64 {
+            panic!("can not read more than 64 bits at once");
+        }
+        let mut data = 0u64;
+        while nbits > 0 {
+            if self.offset == 8 {
+                self.reader.read_exact(&mut self.buffer)?;
+                self.offset = 0;
+            }
+            let bits = cmp::min(8 - self.offset, nbits);
+            data <<= bits;
+            data |= ((self.buffer[0] << self.offset) >> (8 - bits)) as u64;
+            self.offset += bits;
+            nbits -= bits;
+        }
+        Ok(data)
+    }
+
+    /// Unwraps the underlying reader. Bits of the current byte which have
+    /// not been read yet are lost.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+/// Writes a stream of bits to an underlying writer.
+///
+/// Bits are buffered until a byte is complete, so [`flush`] must be called
+/// once all bits are written.
+///
+/// [`flush`]: #method.flush
+pub struct BitWriter<W> {
+    buffer: [u8; 1],
+    offset: u8,
+    writer: W,
+}
+
+impl<W: io::Write> BitWriter<W> {
+    /// Create a new BitWriter that writes bits to `writer`
+    pub fn new(writer: W) -> BitWriter<W> {
+        BitWriter {
+            buffer: [0u8],
+            offset: 0,
+            writer,
+        }
+    }
+
+    /// Write the low `nbits` bits of `data`, returning the number of bytes
+    /// written to the underlying writer.
+    ///
+    /// # Panics
+    ///
+    /// If `nbits` is greater than 64.
+    pub fn write(&mut self, data: u64, mut nbits: u8) -> Result<usize, io::Error> {
+        if nbits > 64 {
+            panic!("can not write more than 64 bits at once");
+        }
+        let mut wrote = 0;
+        while nbits > 0 {
+            let bits = cmp::min(8 - self.offset, nbits);
+            self.buffer[0] |= ((data << (64 - nbits)) >> (64 - 8 + self.offset)) as u8;
+            self.offset += bits;
+            nbits -= bits;
+            if self.offset == 8 {
+                wrote += self.flush()?;
+            }
+        }
+        Ok(wrote)
+    }
+
+    /// Write the buffered bits, padding the last byte with zeros, and return
+    /// the number of bytes written.
+    pub fn flush(&mut self) -> Result<usize, io::Error> {
+        if self.offset > 0 {
+            self.writer.write_all(&self.buffer)?;
+            self.buffer[0] = 0u8;
+            self.offset = 0;
+            Ok(1)
+        } else {
+            Ok(0)
+        }
+    }
+
+    /// Unwraps the underlying writer. Bits which have not been flushed are
+    /// lost.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Golomb-Rice encode `n` with parameter `p`, returning the number of bytes
+/// written to the underlying writer.
+///
+/// # Panics
+///
+/// If `p` is greater than 64.
+pub fn golomb_rice_encode<W: io::Write>(
+    writer: &mut BitWriter<W>,
+    p: u8,
+    n: u64,
+) -> Result<usize, io::Error> {
+    let mut wrote = 0;
+    let mut q = if p == 64 { 0 } else { n >> p };
+    while q > 0 {
+        let nbits = cmp::min(q, 64) as u8;
+        wrote += writer.write(!0u64, nbits)?;
+        q -= nbits as u64;
+    }
+    wrote += writer.write(0, 1)?;
+    wrote += writer.write(n, p)?;
+    Ok(wrote)
+}
+
+/// Golomb-Rice decode a value with parameter `p`.
+///
+/// Returns an error of kind `InvalidData` if the coded value does not fit
+/// in a `u64`.
+///
+/// # Panics
+///
+/// If `p` is greater than 64.
+pub fn golomb_rice_decode<R: io::Read>(reader: &mut BitReader<R>, p: u8) -> Result<u64, io::Error> {
+    let mut q = 0u64;
+    while reader.read(1)? == 1 {
+        q += 1;
+    }
+    let r = reader.read(p)?;
+    if q == 0 {
+        return Ok(r);
+    }
+    if p == 64 || q > u64::max_value() >> p {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "golomb-rice coded value overflows u64",
+        ));
+    }
+    Ok((q << p) | r)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    /// A small deterministic generator, so that the round-trip tests cover
+    /// many values while staying reproducible.
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn next(&mut self) -> u64 {
+            self.0 = self
+                .0
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            self.0
+        }
+    }
+
+    #[test]
+    fn test_bit_stream() {
+        let mut out = Vec::new();
+        {
+            let mut writer = BitWriter::new(&mut out);
+            writer.write(0, 1).unwrap(); // 0
+            writer.write(2, 2).unwrap(); // 10
+            writer.write(6, 3).unwrap(); // 110
+            writer.write(11, 4).unwrap(); // 1011
+            writer.write(1, 5).unwrap(); // 00001
+            writer.write(32, 6).unwrap(); // 100000
+            writer.write(7, 7).unwrap(); // 0000111
+            writer.flush().unwrap();
+        }
+        assert_eq!(out, vec![0x5a, 0xc3, 0x00, 0x70]);
+
+        let mut input = Cursor::new(out);
+        let mut reader = BitReader::new(&mut input);
+        assert_eq!(reader.read(1).unwrap(), 0);
+        assert_eq!(reader.read(2).unwrap(), 2);
+        assert_eq!(reader.read(3).unwrap(), 6);
+        assert_eq!(reader.read(4).unwrap(), 11);
+        assert_eq!(reader.read(5).unwrap(), 1);
+        assert_eq!(reader.read(6).unwrap(), 32);
+        assert_eq!(reader.read(7).unwrap(), 7);
+        // The padding bits
+        assert_eq!(reader.read(4).unwrap(), 0);
+        assert!(reader.read(1).is_err());
+    }
+
+    #[test]
+    fn test_bit_stream_roundtrip() {
+        let mut rng = Lcg(1);
+        let mut values = Vec::new();
+        for _ in 0..1000 {
+            let nbits = (rng.next() % 65) as u8;
+            let data = match nbits {
+                0 => 0,
+                64 => rng.next(),
+                n => rng.next() & ((1 << n) - 1),
+            };
+            values.push((data, nbits));
+        }
+
+        let mut out = Vec::new();
+        let mut wrote = 0;
+        {
+            let mut writer = BitWriter::new(&mut out);
+            for &(data, nbits) in values.iter() {
+                wrote += writer.write(data, nbits).unwrap();
+            }
+            wrote += writer.flush().unwrap();
+        }
+        let total_bits: usize = values.iter().map(|&(_, n)| n as usize).sum();
+        assert_eq!(out.len(), (total_bits + 7) / 8);
+        assert_eq!(wrote, out.len());
+
+        let mut reader = BitReader::new(Cursor::new(out));
+        for &(data, nbits) in values.iter() {
+            assert_eq!(reader.read(nbits).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn test_golomb_rice() {
+        let mut out = Vec::new();
+        {
+            let mut writer = BitWriter::new(&mut out);
+            for n in [0, 1, 5, 12].iter() {
+                golomb_rice_encode(&mut writer, 2, *n).unwrap();
+            }
+            writer.flush().unwrap();
+        }
+        // 000 001 1001 111000
+        assert_eq!(out, vec![0x06, 0x78]);
+
+        let mut reader = BitReader::new(Cursor::new(out));
+        for n in [0, 1, 5, 12].iter() {
+            assert_eq!(golomb_rice_decode(&mut reader, 2).unwrap(), *n);
+        }
+
+        // A quotient of 2 with p = 63 does not fit in a u64
+        let mut reader = BitReader::new(Cursor::new(vec![0xc0; 9]));
+        assert_eq!(
+            golomb_rice_decode(&mut reader, 63).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+
+    #[test]
+    fn test_golomb_rice_roundtrip() {
+        let mut rng = Lcg(2);
+        for &p in [0u8, 1, 19, 20, 63, 64].iter() {
+            let mut values = Vec::new();
+            for _ in 0..200 {
+                // Keep the quotients, and so the unary codes, small
+                let n = match p {
+                    64 => rng.next(),
+                    p => rng.next() >> (55 - cmp::min(p, 55)),
+                };
+                values.push(n);
+            }
+
+            let mut out = Vec::new();
+            {
+                let mut writer = BitWriter::new(&mut out);
+                for n in values.iter() {
+                    golomb_rice_encode(&mut writer, p, *n).unwrap();
+                }
+                writer.flush().unwrap();
+            }
+            let total_bits: u64 = values
+                .iter()
+                .map(|&n| if p == 64 { 65 } else { (n >> p) + 1 + p as u64 })
+                .sum();
+            assert_eq!(out.len() as u64, (total_bits + 7) / 8);
+
+            let mut reader = BitReader::new(Cursor::new(out));
+            for n in values.iter() {
+                assert_eq!(golomb_rice_decode(&mut reader, p).unwrap(), *n);
+            }
+        }
+    }
+}
@@ -0,0 +1,202 @@
+//! Transaction Index
+//!
+//! Looking up a transaction by its txid needs an index from txids to the
+//! blocks they were confirmed in, as Bitcoin Core's `-txindex` keeps. The
+//! index here is filled block by block, from whatever source the blocks
+//! come from, and blocks can be removed again when they are reorganized
+//! out of the best chain.
+//!
+//! The index is persisted as the txids of each block, in block order, so a
+//! transaction's position is implied by where its txid is stored and takes
+//! no space.
+
+use std::collections::HashMap;
+use std::io;
+
+use consensus::encode::{self, Decodable, Encodable};
+use hash_types::{BlockHash, Txid};
+
+/// Where a transaction is confirmed
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct TxLocation {
+    /// The block the transaction is in
+    pub block: BlockHash,
+    /// The index of the transaction in the block, the coinbase being 0
+    pub position: u32,
+}
+
+/// An index from txids to the blocks they are confirmed in
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct TxIndex {
+    blocks: HashMap<BlockHash, Vec<Txid>>,
+    locations: HashMap<Txid, TxLocation>,
+}
+
+impl TxIndex {
+    /// Creates an empty index
+    pub fn new() -> TxIndex {
+        TxIndex::default()
+    }
+
+    /// Indexes the transactions of a block, given by their txids in block
+    /// order. Returns `false`, leaving the index unchanged, if the block is
+    /// indexed already.
+    ///
+    /// A txid already indexed in another block is moved to this one, as
+    /// Bitcoin Core does for the two historic duplicate coinbase
+    /// transactions.
+    pub fn add_block(&mut self, block: BlockHash, txids: Vec<Txid>) -> bool {
+        if self.blocks.contains_key(&block) {
+            return false;
+        }
+        for (position, txid) in txids.iter().enumerate() {
+            let location = TxLocation {
+                block,
+                position: position as u32,
+            };
+            self.locations.insert(*txid, location);
+        }
+        self.blocks.insert(block, txids);
+        true
+    }
+
+    /// Removes the transactions of a block from the index, as when the
+    /// block is reorganized out of the best chain. Returns `false` if the
+    /// block was not indexed.
+    pub fn remove_block(&mut self, block: &BlockHash) -> bool {
+        let txids = match self.blocks.remove(block) {
+            Some(txids) => txids,
+            None => return false,
+        };
+        for txid in txids {
+            if self.locations.get(&txid).map(|l| l.block) == Some(*block) {
+                self.locations.remove(&txid);
+            }
+        }
+        true
+    }
+
+    /// Where the transaction with the given txid is confirmed
+    pub fn get(&self, txid: &Txid) -> Option<TxLocation> {
+        self.locations.get(txid).cloned()
+    }
+
+    /// Whether the transaction with the given txid is indexed
+    pub fn contains(&self, txid: &Txid) -> bool {
+        self.locations.contains_key(txid)
+    }
+
+    /// The txids of an indexed block, in block order
+    pub fn block_txids(&self, block: &BlockHash) -> Option<&[Txid]> {
+        self.blocks.get(block).map(|txids| &txids[..])
+    }
+
+    /// Whether the block is indexed
+    pub fn contains_block(&self, block: &BlockHash) -> bool {
+        self.blocks.contains_key(block)
+    }
+
+    /// The number of indexed transactions
+    pub fn len(&self) -> usize {
+        self.locations.len()
+    }
+
+    /// Whether no transactions are indexed
+    pub fn is_empty(&self) -> bool {
+        self.locations.is_empty()
+    }
+
+    /// The number of indexed blocks
+    pub fn block_count(&self) -> usize {
+        self.blocks.len()
+    }
+}
+
+impl Encodable for TxIndex {
+    fn consensus_encode<S: io::Write>(&self, s: S) -> Result<usize, encode::Error> {
+        self.blocks.consensus_encode(s)
+    }
+}
+
+impl Decodable for TxIndex {
+    fn consensus_decode<D: io::Read>(d: D) -> Result<TxIndex, encode::Error> {
+        let blocks: HashMap<BlockHash, Vec<Txid>> = Decodable::consensus_decode(d)?;
+        // Blocks are encoded in hash order, so which of two blocks with the
+        // same txid it is found in is arbitrary, but that only happens for
+        // the duplicate coinbases, whose first copies are unspendable
+        let mut index = TxIndex::new();
+        for (block, txids) in blocks {
+            index.add_block(block, txids);
+        }
+        Ok(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use consensus::encode::{deserialize, serialize};
+    use hashes::Hash;
+
+    fn txids(seed: u8, n: u8) -> Vec<Txid> {
+        (0..n).map(|i| Txid::hash(&[seed, i])).collect()
+    }
+
+    #[test]
+    fn lookup() {
+        let mut index = TxIndex::new();
+        assert!(index.is_empty());
+        let block1 = BlockHash::hash(&[1]);
+        let block2 = BlockHash::hash(&[2]);
+        assert!(index.add_block(block1, txids(1, 3)));
+        assert!(index.add_block(block2, txids(2, 2)));
+        assert!(!index.add_block(block2, txids(3, 2)));
+        assert_eq!(index.len(), 5);
+        assert_eq!(index.block_count(), 2);
+        assert_eq!(
+            index.get(&Txid::hash(&[1, 2])),
+            Some(TxLocation {
+                block: block1,
+                position: 2,
+            })
+        );
+        assert_eq!(index.block_txids(&block2), Some(&txids(2, 2)[..]));
+        assert!(!index.contains(&Txid::hash(&[3, 0])));
+
+        // Reorganizing out a block
+        assert!(index.remove_block(&block2));
+        assert!(!index.remove_block(&block2));
+        assert!(!index.contains(&Txid::hash(&[2, 0])));
+        assert!(!index.contains_block(&block2));
+        assert_eq!(index.len(), 3);
+    }
+
+    #[test]
+    fn duplicate_txid() {
+        let mut index = TxIndex::new();
+        let block1 = BlockHash::hash(&[1]);
+        let block2 = BlockHash::hash(&[2]);
+        let coinbase = Txid::hash(&[0]);
+        index.add_block(block1, vec![coinbase]);
+        index.add_block(block2, vec![coinbase, Txid::hash(&[1])]);
+        assert_eq!(index.get(&coinbase).map(|l| l.block), Some(block2));
+
+        // Removing the older block keeps the later copy
+        index.remove_block(&block1);
+        assert_eq!(index.get(&coinbase).map(|l| l.block), Some(block2));
+        index.remove_block(&block2);
+        assert!(index.is_empty());
+    }
+
+    #[test]
+    fn roundtrip() {
+        let mut index = TxIndex::new();
+        index.add_block(BlockHash::hash(&[1]), txids(1, 3));
+        index.add_block(BlockHash::hash(&[2]), txids(2, 1));
+        let encoded = serialize(&index);
+        // Two blocks of a hash, a count and their txids
+        assert_eq!(encoded.len(), 1 + 2 * (32 + 1) + 4 * 32);
+        assert_eq!(deserialize::<TxIndex>(&encoded).unwrap(), index);
+    }
+}
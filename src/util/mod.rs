@@ -1,4 +1,26 @@
 //! Utility functions needed to make bitcoin work
 
+pub mod address;
 pub mod amount;
+pub mod base58;
+pub mod bip158;
+pub mod bech32;
+pub mod bip32;
+pub mod core_json;
+pub mod crypto;
+#[cfg(feature = "crypto-container")]
+pub mod crypto_container;
+pub mod fee_rate;
+pub mod golomb;
+pub mod index;
+pub mod psbt;
+pub mod rng;
+pub mod secret;
 pub(crate) mod endian;
+pub mod sigcache;
+pub mod sighash;
+pub mod silentpayments;
+pub mod siphash;
+pub mod txgraph;
+pub mod uint;
+pub mod weight;
@@ -1,7 +1,8 @@
 use std::error;
 use std::fmt;
 
-use blockdata::transaction::Transaction;
+use blockdata::transaction::OutPoint;
+use error::{ErrorCode, ErrorKind};
 use util::psbt::raw;
 
 /// Ways that a partially Signed Transaction might fail.
@@ -10,4 +11,177 @@ pub enum Error {
     /// Magic bytes for a PSBT must be the ASCII for "psbt" serialized
     /// in most significant byte order
     InvalidMagic,
+    /// The separator for a PSBT must be `0xff`.
+    InvalidSeparator,
+    /// Known keys must be according to spec.
+    InvalidKey(raw::Key),
+    /// Keys within key-value map should never be duplicated.
+    DuplicateKey(raw::Key),
+    /// The scriptSigs for the unsigned transaction must be empty.
+    UnsignedTxHasScriptSigs,
+    /// The scriptWitnesses for the unsigned transaction must be empty.
+    UnsignedTxHasScriptWitnesses,
+    /// A PSBT must have an unsigned transaction.
+    MustHaveUnsignedTx,
+    /// Signals that there are no more key-value pairs in a key-value map.
+    NoMorePairs,
+    /// Unable to parse as a standard SigHash type.
+    NonStandardSigHashType(u32),
+    /// The input at this index does not exist.
+    InputIndexOutOfBounds(usize),
+    /// The input at this index has already been finalized, so no more
+    /// information can be added to it.
+    InputAlreadyFinalized(usize),
+    /// The input at this index has not been finalized, so it cannot be
+    /// extracted into a network transaction.
+    InputNotFinalized(usize),
+    /// The input at this index does not carry the output it spends, which
+    /// a signer needs to produce a signature.
+    MissingUtxo(usize),
+    /// The `non_witness_utxo` of the input at this index is not the
+    /// transaction its previous output refers to.
+    NonWitnessUtxoTxidMismatch(usize),
+    /// The previous output of the input at this index refers to an output
+    /// its `non_witness_utxo` does not have.
+    PrevoutIndexOutOfBounds(usize),
+    /// The `witness_utxo` of the input at this index differs from the
+    /// output of its `non_witness_utxo` that it spends.
+    WitnessUtxoMismatch(usize),
+    /// The output spent by the input at this index claims more than the
+    /// maximum amount of money.
+    UtxoAmountOutOfRange(usize),
+    /// None of the script leaves of the input at this index can be
+    /// satisfied with its signatures.
+    NoSatisfyingLeaf(usize),
+    /// The outputs of the transaction are worth more than its inputs.
+    NegativeFee,
+    /// The inputs or outputs of the transaction add up to more than an
+    /// amount can hold.
+    FeeOutOfRange,
+    /// The transactions being merged have different versions or lock
+    /// times.
+    UnsignedTxMismatch,
+    /// The transactions being merged both spend this outpoint.
+    DuplicateOutpoint(OutPoint),
+    /// The signatures of the input at this index of a merged PSBT would
+    /// no longer be valid, or cannot be checked as it is finalized.
+    IncompatibleSigHash(usize),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::InvalidKey(ref rkey) => write!(f, "invalid key: {}", rkey),
+            Error::DuplicateKey(ref rkey) => write!(f, "duplicate key: {}", rkey),
+            Error::NonStandardSigHashType(ref sht) => {
+                write!(f, "non-standard sighash type: {}", sht)
+            }
+            Error::InputIndexOutOfBounds(i) => write!(f, "input {} does not exist", i),
+            Error::InputAlreadyFinalized(i) => write!(f, "input {} is already finalized", i),
+            Error::InputNotFinalized(i) => write!(f, "input {} is not finalized", i),
+            Error::MissingUtxo(i) => write!(f, "input {} has no UTXO", i),
+            Error::NonWitnessUtxoTxidMismatch(i) => {
+                write!(f, "non-witness UTXO of input {} has the wrong txid", i)
+            }
+            Error::PrevoutIndexOutOfBounds(i) => write!(
+                f,
+                "input {} spends an output its non-witness UTXO does not have",
+                i
+            ),
+            Error::WitnessUtxoMismatch(i) => write!(
+                f,
+                "witness UTXO of input {} does not match its non-witness UTXO",
+                i
+            ),
+            Error::UtxoAmountOutOfRange(i) => {
+                write!(f, "UTXO of input {} has an amount out of range", i)
+            }
+            Error::NoSatisfyingLeaf(i) => {
+                write!(f, "no script leaf of input {} can be satisfied", i)
+            }
+            Error::DuplicateOutpoint(ref outpoint) => {
+                write!(f, "outpoint {} is spent twice", outpoint)
+            }
+            Error::IncompatibleSigHash(i) => {
+                write!(f, "signatures of input {} do not allow the merge", i)
+            }
+            Error::InvalidMagic
+            | Error::InvalidSeparator
+            | Error::UnsignedTxHasScriptSigs
+            | Error::UnsignedTxHasScriptWitnesses
+            | Error::MustHaveUnsignedTx
+            | Error::NoMorePairs
+            | Error::NegativeFee
+            | Error::FeeOutOfRange
+            | Error::UnsignedTxMismatch => f.write_str(error::Error::description(self)),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::InvalidMagic => "invalid magic",
+            Error::InvalidSeparator => "invalid separator",
+            Error::InvalidKey(..) => "invalid key",
+            Error::DuplicateKey(..) => "duplicate key",
+            Error::UnsignedTxHasScriptSigs => "the unsigned transaction has script sigs",
+            Error::UnsignedTxHasScriptWitnesses => "the unsigned transaction has script witnesses",
+            Error::MustHaveUnsignedTx => {
+                "partially signed transactions must have an unsigned transaction"
+            }
+            Error::NoMorePairs => "no more key-value pairs for this psbt map",
+            Error::NonStandardSigHashType(..) => "non-standard sighash type",
+            Error::InputIndexOutOfBounds(..) => "input index out of bounds",
+            Error::InputAlreadyFinalized(..) => "input already finalized",
+            Error::InputNotFinalized(..) => "input not finalized",
+            Error::MissingUtxo(..) => "input has no UTXO",
+            Error::NonWitnessUtxoTxidMismatch(..) => "non-witness UTXO txid mismatch",
+            Error::PrevoutIndexOutOfBounds(..) => "previous output index out of bounds",
+            Error::WitnessUtxoMismatch(..) => "witness UTXO mismatch",
+            Error::UtxoAmountOutOfRange(..) => "UTXO amount out of range",
+            Error::NoSatisfyingLeaf(..) => "no satisfiable script leaf",
+            Error::NegativeFee => "outputs are worth more than inputs",
+            Error::FeeOutOfRange => "fee out of range",
+            Error::UnsignedTxMismatch => {
+                "unsigned transactions have different versions or lock times"
+            }
+            Error::DuplicateOutpoint(..) => "outpoint spent twice",
+            Error::IncompatibleSigHash(..) => "signatures incompatible with the merge",
+        }
+    }
+}
+
+impl ErrorCode for Error {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Psbt
+    }
+
+    fn number(&self) -> u32 {
+        match *self {
+            Error::InvalidMagic => 1,
+            Error::InvalidSeparator => 2,
+            Error::InvalidKey(..) => 3,
+            Error::DuplicateKey(..) => 4,
+            Error::UnsignedTxHasScriptSigs => 5,
+            Error::UnsignedTxHasScriptWitnesses => 6,
+            Error::MustHaveUnsignedTx => 7,
+            Error::NoMorePairs => 8,
+            Error::NonStandardSigHashType(..) => 9,
+            Error::InputIndexOutOfBounds(..) => 10,
+            Error::InputAlreadyFinalized(..) => 11,
+            Error::InputNotFinalized(..) => 12,
+            Error::MissingUtxo(..) => 13,
+            Error::NonWitnessUtxoTxidMismatch(..) => 14,
+            Error::PrevoutIndexOutOfBounds(..) => 15,
+            Error::WitnessUtxoMismatch(..) => 16,
+            Error::UtxoAmountOutOfRange(..) => 17,
+            Error::NoSatisfyingLeaf(..) => 18,
+            Error::NegativeFee => 19,
+            Error::FeeOutOfRange => 20,
+            Error::UnsignedTxMismatch => 21,
+            Error::DuplicateOutpoint(..) => 22,
+            Error::IncompatibleSigHash(..) => 23,
+        }
+    }
 }
@@ -0,0 +1,293 @@
+//! PSBT Inspection
+//!
+//! A coordinator passing a PSBT between parties has to show each of them
+//! what it is about to sign, and what the last party changed. A summary
+//! lists the inputs and outputs with their amounts and signatures, and a
+//! diff lists the key-value pairs added, removed or changed in every map,
+//! including pairs this library does not know the meaning of.
+
+use std::collections::BTreeMap;
+
+use blockdata::script::Script;
+use blockdata::transaction::OutPoint;
+use util::amount::Amount;
+use util::psbt::map::Map;
+use util::psbt::raw;
+use util::psbt::PartiallySignedTransaction;
+
+/// The keys that differ between two versions of a PSBT key-value map
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MapDiff {
+    /// Keys only in the newer map
+    pub added: Vec<raw::Key>,
+    /// Keys only in the older map
+    pub removed: Vec<raw::Key>,
+    /// Keys in both maps, with different values
+    pub changed: Vec<raw::Key>,
+}
+
+impl MapDiff {
+    /// Whether the maps are the same
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+    /// The difference from `old` to `new`, where a missing map has no keys
+    pub(crate) fn between<M: Map>(old: Option<&M>, new: Option<&M>) -> MapDiff {
+        let old = pairs(old);
+        let mut new = pairs(new);
+        let mut diff = MapDiff::default();
+        for (key, value) in old {
+            match new.remove(&key) {
+                None => diff.removed.push(key),
+                Some(ref new_value) if *new_value != value => diff.changed.push(key),
+                Some(_) => {}
+            }
+        }
+        for (key, _) in new {
+            diff.added.push(key);
+        }
+        diff
+    }
+}
+
+/// The key-value pairs of a map, sorted by key
+fn pairs<M: Map>(map: Option<&M>) -> BTreeMap<raw::Key, Vec<u8>> {
+    match map {
+        Some(map) => map
+            .get_pairs()
+            .expect("in-memory encoding does not fail")
+            .into_iter()
+            .map(|pair| (pair.key, pair.value))
+            .collect(),
+        None => BTreeMap::new(),
+    }
+}
+
+/// What changed between two versions of a PSBT, map by map
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PsbtDiff {
+    /// Changes to the global map, which includes the unsigned transaction
+    pub global: MapDiff,
+    /// Changes to the map of each input. If the number of inputs changed,
+    /// inputs only in one version are compared against an empty map.
+    pub inputs: Vec<MapDiff>,
+    /// Changes to the map of each output, like `inputs`
+    pub outputs: Vec<MapDiff>,
+}
+
+impl PsbtDiff {
+    /// Whether the PSBTs are the same
+    pub fn is_empty(&self) -> bool {
+        self.global.is_empty()
+            && self.inputs.iter().all(MapDiff::is_empty)
+            && self.outputs.iter().all(MapDiff::is_empty)
+    }
+}
+
+/// Compares the maps of two lists of maps pairwise
+fn diff_maps<M: Map>(old: &[M], new: &[M]) -> Vec<MapDiff> {
+    (0..old.len().max(new.len()))
+        .map(|i| MapDiff::between(old.get(i), new.get(i)))
+        .collect()
+}
+
+/// An input of a PSBT, as listed in its summary
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InputSummary {
+    /// The output the input spends
+    pub previous_output: OutPoint,
+    /// The amount of that output, if the PSBT carries it
+    pub value: Option<Amount>,
+    /// The number of signatures collected: partial ECDSA signatures and
+    /// taproot key and script path signatures
+    pub signatures: usize,
+    /// Whether the input has been finalized
+    pub finalized: bool,
+}
+
+/// An output of a PSBT, as listed in its summary
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OutputSummary {
+    /// The amount of the output
+    pub value: Amount,
+    /// The script the output pays to
+    pub script_pubkey: Script,
+}
+
+/// An overview of a PSBT
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PsbtSummary {
+    /// The inputs, in order
+    pub inputs: Vec<InputSummary>,
+    /// The outputs, in order
+    pub outputs: Vec<OutputSummary>,
+    /// The fee paid, if it can be computed by
+    /// `PartiallySignedTransaction::fee`
+    pub fee: Option<Amount>,
+}
+
+impl PartiallySignedTransaction {
+    /// The key-value pairs added, removed or changed in `other` compared to
+    /// this PSBT
+    pub fn diff(&self, other: &PartiallySignedTransaction) -> PsbtDiff {
+        PsbtDiff {
+            global: MapDiff::between(Some(&self.global), Some(&other.global)),
+            inputs: diff_maps(&self.inputs, &other.inputs),
+            outputs: diff_maps(&self.outputs, &other.outputs),
+        }
+    }
+
+    /// An overview of the inputs, outputs and fee of this PSBT
+    pub fn summary(&self) -> PsbtSummary {
+        let tx = &self.global.unsigned_tx;
+        let inputs: Vec<InputSummary> = tx
+            .input
+            .iter()
+            .zip(self.inputs.iter())
+            .enumerate()
+            .map(|(index, (txin, input))| InputSummary {
+                previous_output: txin.previous_output,
+                value: self
+                    .spent_utxo(index)
+                    .map(|utxo| Amount::from_sat(utxo.value)),
+                signatures: input.partial_sigs.len()
+                    + input.tap_key_sig.iter().count()
+                    + input.tap_script_sigs.len(),
+                finalized: input.is_finalized(),
+            })
+            .collect();
+        let outputs: Vec<OutputSummary> = tx
+            .output
+            .iter()
+            .map(|txout| OutputSummary {
+                value: Amount::from_sat(txout.value),
+                script_pubkey: txout.script_pubkey.clone(),
+            })
+            .collect();
+
+        PsbtSummary {
+            inputs,
+            outputs,
+            fee: self.fee().ok(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use blockdata::transaction::{Transaction, TxIn, TxOut};
+    use hash_types::Txid;
+    use hashes::Hash;
+
+    fn psbt() -> PartiallySignedTransaction {
+        let tx = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![
+                TxIn {
+                    previous_output: OutPoint::new(Txid::hash(&[1]), 0),
+                    ..Default::default()
+                },
+                TxIn {
+                    previous_output: OutPoint::new(Txid::hash(&[2]), 1),
+                    ..Default::default()
+                },
+            ],
+            output: vec![TxOut {
+                value: 15_000,
+                script_pubkey: hex_script!("0014be18d152a9b012039daf3da7de4f53349eecb985"),
+            }],
+        };
+        PartiallySignedTransaction::from_unsigned_tx(tx).unwrap()
+    }
+
+    fn utxo(value: u64) -> TxOut {
+        TxOut {
+            value,
+            script_pubkey: hex_script!("0014339725ba21efd62ac753a9bcd067d6c7a6a39d05"),
+        }
+    }
+
+    #[test]
+    fn diff() {
+        let old = psbt();
+        assert!(old.diff(&old).is_empty());
+
+        let mut new = old.clone();
+        new.inputs[0].witness_utxo = Some(utxo(10_000));
+        new.inputs[0].partial_sigs.insert(vec![2; 33], vec![0x30]);
+        new.outputs[0].redeem_script = Some(Script::new());
+        let diff = old.diff(&new);
+        assert!(!diff.is_empty());
+        assert!(diff.global.is_empty());
+        assert_eq!(diff.inputs.len(), 2);
+        assert_eq!(
+            diff.inputs[0]
+                .added
+                .iter()
+                .map(|k| k.type_value)
+                .collect::<Vec<_>>(),
+            vec![0x01, 0x02]
+        );
+        assert!(diff.inputs[1].is_empty());
+        assert_eq!(diff.outputs[0].added.len(), 1);
+
+        // Changing and removing
+        let mut newer = new.clone();
+        newer.inputs[0].witness_utxo = Some(utxo(20_000));
+        newer.inputs[0].partial_sigs.clear();
+        let diff = new.diff(&newer);
+        assert_eq!(diff.inputs[0].changed[0].type_value, 0x01);
+        assert_eq!(diff.inputs[0].removed[0].key, vec![2; 33]);
+        assert!(diff.inputs[0].added.is_empty());
+
+        // A different transaction
+        let mut other = old.clone();
+        other.global.unsigned_tx.output.push(utxo(1));
+        other.outputs.push(Default::default());
+        other.outputs[1].redeem_script = Some(Script::new());
+        let diff = old.diff(&other);
+        assert_eq!(diff.global.changed.len(), 1);
+        assert_eq!(diff.outputs.len(), 2);
+        assert_eq!(diff.outputs[1].added.len(), 1);
+        assert_eq!(other.diff(&old).outputs[1].removed.len(), 1);
+    }
+
+    #[test]
+    fn summary() {
+        let mut psbt = psbt();
+        let summary = psbt.summary();
+        assert_eq!(summary.inputs.len(), 2);
+        assert_eq!(summary.inputs[1].previous_output.vout, 1);
+        assert_eq!(summary.inputs[0].value, None);
+        assert_eq!(summary.outputs[0].value, Amount::from_sat(15_000));
+        assert_eq!(summary.fee, None);
+
+        psbt.inputs[0].witness_utxo = Some(utxo(10_000));
+        psbt.inputs[0].partial_sigs.insert(vec![2; 33], vec![0x30]);
+        psbt.inputs[0].tap_key_sig = Some(vec![0; 64]);
+        let mut prev_tx = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![],
+            output: vec![utxo(1), utxo(6_000)],
+        };
+        psbt.inputs[1].non_witness_utxo = Some(prev_tx.clone());
+        psbt.inputs[1].final_script_sig = Some(Script::new());
+        let summary = psbt.summary();
+        assert_eq!(summary.inputs[0].value, Some(Amount::from_sat(10_000)));
+        assert_eq!(summary.inputs[0].signatures, 2);
+        assert!(!summary.inputs[0].finalized);
+        assert_eq!(summary.inputs[1].value, Some(Amount::from_sat(6_000)));
+        assert!(summary.inputs[1].finalized);
+        assert_eq!(summary.fee, Some(Amount::from_sat(1_000)));
+
+        // Outputs worth more than the inputs
+        prev_tx.output[1].value = 4_000;
+        psbt.inputs[1].non_witness_utxo = Some(prev_tx);
+        assert_eq!(psbt.summary().fee, None);
+    }
+}
@@ -0,0 +1,137 @@
+use std::io::{self, Cursor};
+
+use blockdata::transaction::Transaction;
+use consensus::encode::{self, Decodable, Encodable};
+use util::psbt;
+use util::psbt::map::{Map, OrderedMap};
+use util::psbt::raw;
+
+/// Type: Unsigned Transaction PSBT_GLOBAL_UNSIGNED_TX = 0x00
+const PSBT_GLOBAL_UNSIGNED_TX: u8 = 0x00;
+
+/// A key-value map for global data.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Global {
+    /// The unsigned transaction, scriptSigs and witnesses for each input must be
+    /// empty.
+    pub unsigned_tx: Transaction,
+    /// Unknown global key-value pairs, in the order they were decoded.
+    pub unknown: OrderedMap<raw::Key, Vec<u8>>,
+}
+
+impl Global {
+    /// Create a Global from an unsigned transaction, error if not unsigned
+    pub fn from_unsigned_tx(tx: Transaction) -> Result<Self, psbt::Error> {
+        for txin in &tx.input {
+            if !txin.script_sig.is_empty() {
+                return Err(psbt::Error::UnsignedTxHasScriptSigs);
+            }
+
+            if !txin.witness.is_empty() {
+                return Err(psbt::Error::UnsignedTxHasScriptWitnesses);
+            }
+        }
+
+        Ok(Global {
+            unsigned_tx: tx,
+            unknown: Default::default(),
+        })
+    }
+}
+
+impl Map for Global {
+    fn insert_pair(&mut self, pair: raw::Pair) -> Result<(), encode::Error> {
+        let raw::Pair {
+            key: raw_key,
+            value: raw_value,
+        } = pair;
+
+        match raw_key.type_value {
+            PSBT_GLOBAL_UNSIGNED_TX => return Err(psbt::Error::DuplicateKey(raw_key).into()),
+            _ => super::insert_unknown(&mut self.unknown, raw_key, raw_value)?,
+        }
+
+        Ok(())
+    }
+
+    fn get_pairs(&self) -> Result<Vec<raw::Pair>, encode::Error> {
+        let mut rv: Vec<raw::Pair> = Default::default();
+
+        rv.push(raw::Pair {
+            key: raw::Key {
+                type_value: PSBT_GLOBAL_UNSIGNED_TX,
+                key: vec![],
+            },
+            value: {
+                let mut ret = Vec::new();
+                self.unsigned_tx.version.consensus_encode(&mut ret)?;
+                self.unsigned_tx.input.consensus_encode(&mut ret)?;
+                self.unsigned_tx.output.consensus_encode(&mut ret)?;
+                self.unsigned_tx.lock_time.consensus_encode(&mut ret)?;
+                ret
+            },
+        });
+
+        super::push_unknown(&mut rv, &self.unknown);
+
+        Ok(rv)
+    }
+}
+
+impl_psbtmap_consensus_encoding!(Global);
+
+impl Decodable for Global {
+    fn consensus_decode<D: io::Read>(mut d: D) -> Result<Self, encode::Error> {
+        let mut tx: Option<Transaction> = None;
+        let mut unknowns: OrderedMap<raw::Key, Vec<u8>> = Default::default();
+
+        loop {
+            match raw::Pair::consensus_decode(&mut d) {
+                Ok(pair) => {
+                    match pair.key.type_value {
+                        PSBT_GLOBAL_UNSIGNED_TX => {
+                            // key has to be empty
+                            if !pair.key.key.is_empty() {
+                                return Err(psbt::Error::InvalidKey(pair.key).into());
+                            }
+                            // there can only be one unsigned transaction
+                            if tx.is_some() {
+                                return Err(psbt::Error::DuplicateKey(pair.key).into());
+                            }
+
+                            let vlen: usize = pair.value.len();
+                            let mut decoder = Cursor::new(pair.value);
+
+                            // Manually deserialized to ensure 0-input
+                            // txs without witnesses are deserialized
+                            // properly.
+                            tx = Some(Transaction {
+                                version: Decodable::consensus_decode(&mut decoder)?,
+                                input: Decodable::consensus_decode(&mut decoder)?,
+                                output: Decodable::consensus_decode(&mut decoder)?,
+                                lock_time: Decodable::consensus_decode(&mut decoder)?,
+                            });
+
+                            if decoder.position() != vlen as u64 {
+                                return Err(encode::Error::ParseFailed(
+                                    "data not consumed entirely when explicitly deserializing",
+                                ));
+                            }
+                        }
+                        _ => super::insert_unknown(&mut unknowns, pair.key, pair.value)?,
+                    }
+                }
+                Err(encode::Error::Psbt(psbt::Error::NoMorePairs)) => break,
+                Err(e) => return Err(e),
+            }
+        }
+
+        if let Some(tx) = tx {
+            let mut rv: Global = Global::from_unsigned_tx(tx)?;
+            rv.unknown = unknowns;
+            Ok(rv)
+        } else {
+            Err(psbt::Error::MustHaveUnsignedTx.into())
+        }
+    }
+}
@@ -0,0 +1,596 @@
+use std::collections::BTreeMap;
+
+use blockdata::opcodes;
+use blockdata::script::{self, Instruction, Script};
+use blockdata::transaction::{SigHashType, Transaction, TxOut};
+use consensus::encode;
+use hash_types::{TapBranchHash, TapLeafHash};
+use hashes::{hash160, ripemd160, sha256, sha256d, Hash};
+use util::bip32::KeySource;
+use util::psbt;
+use util::psbt::map::{Map, OrderedMap};
+use util::psbt::raw;
+
+/// Type: Non-Witness UTXO PSBT_IN_NON_WITNESS_UTXO = 0x00
+const PSBT_IN_NON_WITNESS_UTXO: u8 = 0x00;
+/// Type: Witness UTXO PSBT_IN_WITNESS_UTXO = 0x01
+const PSBT_IN_WITNESS_UTXO: u8 = 0x01;
+/// Type: Partial Signature PSBT_IN_PARTIAL_SIG = 0x02
+const PSBT_IN_PARTIAL_SIG: u8 = 0x02;
+/// Type: Sighash Type PSBT_IN_SIGHASH_TYPE = 0x03
+const PSBT_IN_SIGHASH_TYPE: u8 = 0x03;
+/// Type: Redeem Script PSBT_IN_REDEEM_SCRIPT = 0x04
+const PSBT_IN_REDEEM_SCRIPT: u8 = 0x04;
+/// Type: Witness Script PSBT_IN_WITNESS_SCRIPT = 0x05
+const PSBT_IN_WITNESS_SCRIPT: u8 = 0x05;
+/// Type: BIP 32 Derivation Path PSBT_IN_BIP32_DERIVATION = 0x06
+const PSBT_IN_BIP32_DERIVATION: u8 = 0x06;
+/// Type: Finalized scriptSig PSBT_IN_FINAL_SCRIPTSIG = 0x07
+const PSBT_IN_FINAL_SCRIPTSIG: u8 = 0x07;
+/// Type: Finalized scriptWitness PSBT_IN_FINAL_SCRIPTWITNESS = 0x08
+const PSBT_IN_FINAL_SCRIPTWITNESS: u8 = 0x08;
+/// Type: RIPEMD160 Preimage PSBT_IN_RIPEMD160 = 0x0a
+const PSBT_IN_RIPEMD160: u8 = 0x0a;
+/// Type: SHA256 Preimage PSBT_IN_SHA256 = 0x0b
+const PSBT_IN_SHA256: u8 = 0x0b;
+/// Type: HASH160 Preimage PSBT_IN_HASH160 = 0x0c
+const PSBT_IN_HASH160: u8 = 0x0c;
+/// Type: HASH256 Preimage PSBT_IN_HASH256 = 0x0d
+const PSBT_IN_HASH256: u8 = 0x0d;
+/// Type: Taproot Key Path Signature PSBT_IN_TAP_KEY_SIG = 0x13
+const PSBT_IN_TAP_KEY_SIG: u8 = 0x13;
+/// Type: Taproot Script Path Signature PSBT_IN_TAP_SCRIPT_SIG = 0x14
+const PSBT_IN_TAP_SCRIPT_SIG: u8 = 0x14;
+/// Type: Taproot Leaf Script PSBT_IN_TAP_LEAF_SCRIPT = 0x15
+const PSBT_IN_TAP_LEAF_SCRIPT: u8 = 0x15;
+/// Type: Taproot Key BIP 32 Derivation Path PSBT_IN_TAP_BIP32_DERIVATION = 0x16
+const PSBT_IN_TAP_BIP32_DERIVATION: u8 = 0x16;
+/// Type: Taproot Internal Key PSBT_IN_TAP_INTERNAL_KEY = 0x17
+const PSBT_IN_TAP_INTERNAL_KEY: u8 = 0x17;
+/// Type: Taproot Merkle Root PSBT_IN_TAP_MERKLE_ROOT = 0x18
+const PSBT_IN_TAP_MERKLE_ROOT: u8 = 0x18;
+
+/// OP_CHECKSIGADD, which tapscript defines in place of OP_RETURN_186
+const OP_CHECKSIGADD: opcodes::All = opcodes::all::OP_RETURN_186;
+
+/// A key-value map for an input of the corresponding index in the unsigned
+/// transaction.
+#[derive(Clone, Default, Debug, PartialEq)]
+pub struct Input {
+    /// The non-witness transaction this input spends from. Should only be
+    /// [std::option::Option::Some] for inputs which spend non-segwit outputs or
+    /// if it is unknown whether an input spends a segwit output.
+    pub non_witness_utxo: Option<Transaction>,
+    /// The transaction output this input spends from. Should only be
+    /// [std::option::Option::Some] for inputs which spend segwit outputs,
+    /// including P2SH embedded ones.
+    pub witness_utxo: Option<TxOut>,
+    /// A map from serialized public keys to their corresponding signature as
+    /// would be pushed to the stack from a scriptSig or witness.
+    pub partial_sigs: BTreeMap<Vec<u8>, Vec<u8>>,
+    /// The sighash type to be used for this input. Signatures for this input
+    /// must use the sighash type.
+    pub sighash_type: Option<SigHashType>,
+    /// The redeem script for this input.
+    pub redeem_script: Option<Script>,
+    /// The witness script for this input.
+    pub witness_script: Option<Script>,
+    /// A map from serialized public keys used in this input to the
+    /// fingerprint of their master key and their derivation path.
+    pub bip32_derivation: BTreeMap<Vec<u8>, KeySource>,
+    /// The finalized, fully-constructed scriptSig with signatures and any other
+    /// scripts necessary for this input to pass validation.
+    pub final_script_sig: Option<Script>,
+    /// The finalized, fully-constructed scriptWitness with signatures and any
+    /// other scripts necessary for this input to pass validation.
+    pub final_script_witness: Option<Vec<Vec<u8>>>,
+    /// Preimages of RIPEMD160 hashes, by hash.
+    pub ripemd160_preimages: BTreeMap<ripemd160::Hash, Vec<u8>>,
+    /// Preimages of SHA256 hashes, by hash.
+    pub sha256_preimages: BTreeMap<sha256::Hash, Vec<u8>>,
+    /// Preimages of HASH160 (RIPEMD160 of SHA256) hashes, by hash.
+    pub hash160_preimages: BTreeMap<hash160::Hash, Vec<u8>>,
+    /// Preimages of HASH256 (double SHA256) hashes, by hash.
+    pub hash256_preimages: BTreeMap<sha256d::Hash, Vec<u8>>,
+    /// The Schnorr signature for a taproot key path spend.
+    pub tap_key_sig: Option<Vec<u8>>,
+    /// Schnorr signatures for a taproot script path spend, by x-only public
+    /// key and hash of the leaf they sign for.
+    pub tap_script_sigs: BTreeMap<([u8; 32], TapLeafHash), Vec<u8>>,
+    /// The script leaves that can spend this input, with their leaf
+    /// versions, by the control block proving them part of the script tree.
+    pub tap_scripts: BTreeMap<Vec<u8>, (Script, u8)>,
+    /// A map from x-only public keys used in this input to the leaves they
+    /// appear in, the fingerprint of their master key and their derivation
+    /// path.
+    pub tap_key_origins: BTreeMap<[u8; 32], (Vec<TapLeafHash>, KeySource)>,
+    /// The x-only internal key of the taproot output spent by this input.
+    pub tap_internal_key: Option<[u8; 32]>,
+    /// The merkle root of the script tree of the taproot output spent by
+    /// this input.
+    pub tap_merkle_root: Option<TapBranchHash>,
+    /// Unknown key-value pairs for this input, in the order they were
+    /// decoded.
+    pub unknown: OrderedMap<raw::Key, Vec<u8>>,
+}
+
+/// A signature that a signer still has to add to an input, as reported by
+/// `Input::signing_requests`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SigningRequest<'a> {
+    /// An ECDSA signature by a key known by its BIP32 origin.
+    Ecdsa {
+        /// The serialized public key.
+        pubkey: &'a [u8],
+        /// The fingerprint of the master key and the derivation path.
+        source: &'a KeySource,
+    },
+    /// A Schnorr signature by the internal key, for a taproot key path spend.
+    TaprootKey {
+        /// The x-only public key.
+        pubkey: &'a [u8; 32],
+        /// The fingerprint of the master key and the derivation path.
+        source: &'a KeySource,
+    },
+    /// A Schnorr signature for the script leaf with the given hash, for a
+    /// taproot script path spend.
+    TaprootScript {
+        /// The x-only public key.
+        pubkey: &'a [u8; 32],
+        /// The hash of the leaf to sign for.
+        leaf_hash: TapLeafHash,
+        /// The fingerprint of the master key and the derivation path.
+        source: &'a KeySource,
+    },
+}
+
+impl Input {
+    /// Whether a finalizer has already produced the scriptSig or witness of
+    /// this input.
+    pub fn is_finalized(&self) -> bool {
+        self.final_script_sig.is_some() || self.final_script_witness.is_some()
+    }
+
+    /// The sighash types of the signatures collected for this input, from
+    /// their last byte. A 64-byte Schnorr signature uses the default
+    /// sighash, which signs like SIGHASH_ALL.
+    pub fn signature_sighash_types(&self) -> Vec<SigHashType> {
+        let ecdsa = self.partial_sigs.values();
+        let schnorr = self.tap_key_sig.iter().chain(self.tap_script_sigs.values());
+        ecdsa
+            .filter_map(|sig| sig.last().map(|&byte| SigHashType::from_u32(byte as u32)))
+            .chain(schnorr.filter_map(|sig| match sig.len() {
+                64 => Some(SigHashType::All),
+                _ => sig.last().map(|&byte| SigHashType::from_u32(byte as u32)),
+            }))
+            .collect()
+    }
+
+    /// Adds a preimage of a RIPEMD160 hash lock, returning its hash.
+    pub fn add_ripemd160_preimage(&mut self, preimage: Vec<u8>) -> ripemd160::Hash {
+        let hash = ripemd160::Hash::hash(&preimage);
+        self.ripemd160_preimages.insert(hash, preimage);
+        hash
+    }
+
+    /// Adds a preimage of a SHA256 hash lock, returning its hash.
+    pub fn add_sha256_preimage(&mut self, preimage: Vec<u8>) -> sha256::Hash {
+        let hash = sha256::Hash::hash(&preimage);
+        self.sha256_preimages.insert(hash, preimage);
+        hash
+    }
+
+    /// Adds a preimage of a HASH160 hash lock, returning its hash.
+    pub fn add_hash160_preimage(&mut self, preimage: Vec<u8>) -> hash160::Hash {
+        let hash = hash160::Hash::hash(&preimage);
+        self.hash160_preimages.insert(hash, preimage);
+        hash
+    }
+
+    /// Adds a preimage of a HASH256 hash lock, returning its hash.
+    pub fn add_hash256_preimage(&mut self, preimage: Vec<u8>) -> sha256d::Hash {
+        let hash = sha256d::Hash::hash(&preimage);
+        self.hash256_preimages.insert(hash, preimage);
+        hash
+    }
+
+    /// The preimage of `hash` under the hash function of `hash_op`, one of
+    /// OP_RIPEMD160, OP_SHA256, OP_HASH160 and OP_HASH256, if this input
+    /// has it.
+    pub fn preimage(&self, hash_op: opcodes::All, hash: &[u8]) -> Option<&[u8]> {
+        let preimage = match hash_op {
+            opcodes::all::OP_RIPEMD160 => self
+                .ripemd160_preimages
+                .get(&ripemd160::Hash::from_slice(hash).ok()?),
+            opcodes::all::OP_SHA256 => self
+                .sha256_preimages
+                .get(&sha256::Hash::from_slice(hash).ok()?),
+            opcodes::all::OP_HASH160 => self
+                .hash160_preimages
+                .get(&hash160::Hash::from_slice(hash).ok()?),
+            opcodes::all::OP_HASH256 => self
+                .hash256_preimages
+                .get(&sha256d::Hash::from_slice(hash).ok()?),
+            _ => None,
+        };
+        preimage.map(|p| &p[..])
+    }
+
+    /// Records the final scriptSig and witness of this input and clears the
+    /// data that was only needed to produce them, as the BIP174 finalizer
+    /// does. The UTXOs and unknown pairs are kept.
+    pub fn finalize(&mut self, script_sig: Script, witness: Vec<Vec<u8>>) {
+        self.final_script_witness = if witness.is_empty() {
+            None
+        } else {
+            Some(witness)
+        };
+        self.final_script_sig = if script_sig.is_empty() && self.final_script_witness.is_some() {
+            None
+        } else {
+            Some(script_sig)
+        };
+
+        self.partial_sigs.clear();
+        self.sighash_type = None;
+        self.redeem_script = None;
+        self.witness_script = None;
+        self.bip32_derivation.clear();
+        self.ripemd160_preimages.clear();
+        self.sha256_preimages.clear();
+        self.hash160_preimages.clear();
+        self.hash256_preimages.clear();
+        self.tap_key_sig = None;
+        self.tap_script_sigs.clear();
+        self.tap_scripts.clear();
+        self.tap_key_origins.clear();
+        self.tap_internal_key = None;
+        self.tap_merkle_root = None;
+    }
+
+    /// Lists the signatures that are still missing from this input: one for
+    /// each key with a known origin which has not signed yet. A finalized
+    /// input needs no more signatures.
+    pub fn signing_requests(&self) -> Vec<SigningRequest<'_>> {
+        let mut requests = vec![];
+        if self.is_finalized() {
+            return requests;
+        }
+
+        for (pubkey, source) in self.bip32_derivation.iter() {
+            if !self.partial_sigs.contains_key(pubkey) {
+                requests.push(SigningRequest::Ecdsa {
+                    pubkey: &pubkey[..],
+                    source,
+                });
+            }
+        }
+
+        for (pubkey, (leaf_hashes, source)) in self.tap_key_origins.iter() {
+            if self.tap_internal_key == Some(*pubkey) && self.tap_key_sig.is_none() {
+                requests.push(SigningRequest::TaprootKey { pubkey, source });
+            }
+            for leaf_hash in leaf_hashes.iter() {
+                if !self.tap_script_sigs.contains_key(&(*pubkey, *leaf_hash)) {
+                    requests.push(SigningRequest::TaprootScript {
+                        pubkey,
+                        leaf_hash: *leaf_hash,
+                        source,
+                    });
+                }
+            }
+        }
+
+        requests
+    }
+
+    /// The cheapest witness spending this input through one of the leaves
+    /// in `tap_scripts` with the signatures in `tap_script_sigs`, if any
+    /// leaf can be satisfied.
+    ///
+    /// Leaves are recognized as a single-key or n-of-n multisig, a chain of
+    /// `<key> CHECKSIGVERIFY` ending in `<key> CHECKSIG`, or a k-of-n
+    /// multisig, `<key> CHECKSIG` followed by `<key> CHECKSIGADD` for every
+    /// other key and `<k> NUMEQUAL`. The witness of a k-of-n multisig uses
+    /// the shortest k signatures available.
+    ///
+    /// Either may be preceded by hash locks, `<hash op> <hash> EQUALVERIFY`,
+    /// optionally after `SIZE 32 EQUALVERIFY`, as in HTLCs; such a leaf can
+    /// only be satisfied if the input has the preimages.
+    pub fn tap_script_path_witness(&self) -> Option<Vec<Vec<u8>>> {
+        let mut best: Option<(usize, Vec<Vec<u8>>)> = None;
+        for (control_block, &(ref script, leaf_version)) in self.tap_scripts.iter() {
+            let leaf_hash = script.tapleaf_hash(leaf_version);
+            let mut witness = match self.satisfy_leaf(script, leaf_hash) {
+                Some(stack) => stack,
+                None => continue,
+            };
+            witness.push(script.to_bytes());
+            witness.push(control_block.clone());
+            let size = encode::serialize(&witness).len();
+            let better = match best {
+                Some((best_size, _)) => size < best_size,
+                None => true,
+            };
+            if better {
+                best = Some((size, witness));
+            }
+        }
+        best.map(|(_, witness)| witness)
+    }
+
+    /// The signatures and preimages satisfying a leaf script, in witness
+    /// order, if it is of a form `tap_script_path_witness` knows and the
+    /// input has enough signatures for the leaf and every preimage.
+    fn satisfy_leaf(&self, script: &Script, leaf_hash: TapLeafHash) -> Option<Vec<Vec<u8>>> {
+        let instructions: Vec<Instruction> =
+            script.instructions().collect::<Result<_, _>>().ok()?;
+
+        // Take the hash locks off the front of the script
+        let mut preimages = vec![];
+        let mut rest = &instructions[..];
+        loop {
+            let size_check = rest.len() >= 3
+                && rest[0] == Instruction::Op(opcodes::all::OP_SIZE)
+                && rest[1] == Instruction::PushBytes(&[32][..])
+                && rest[2] == Instruction::Op(opcodes::all::OP_EQUALVERIFY);
+            let lock = if size_check { &rest[3..] } else { rest };
+            if lock.len() < 3 || lock[2] != Instruction::Op(opcodes::all::OP_EQUALVERIFY) {
+                break;
+            }
+            let (hash_op, hash) = match (&lock[0], &lock[1]) {
+                (&Instruction::Op(op), &Instruction::PushBytes(hash)) if is_hash_op(op) => {
+                    (op, hash)
+                }
+                _ => break,
+            };
+            let preimage = self.preimage(hash_op, hash)?;
+            if size_check && preimage.len() != 32 {
+                return None;
+            }
+            preimages.push(preimage.to_vec());
+            rest = &lock[3..];
+        }
+
+        // Split the rest into `<key> <checksig opcode>` pairs and the rest
+        let mut keys = vec![];
+        let mut ops = vec![];
+        while rest.len() >= 2 {
+            match (&rest[0], &rest[1]) {
+                (&Instruction::PushBytes(key), &Instruction::Op(op)) if key.len() == 32 => {
+                    keys.push(key);
+                    ops.push(op);
+                    rest = &rest[2..];
+                }
+                _ => break,
+            }
+        }
+        if keys.is_empty() {
+            return None;
+        }
+
+        let last = ops.len() - 1;
+        let threshold = if rest.is_empty()
+            && ops[..last]
+                .iter()
+                .all(|&op| op == opcodes::all::OP_CHECKSIGVERIFY)
+            && ops[last] == opcodes::all::OP_CHECKSIG
+        {
+            keys.len()
+        } else if rest.len() == 2
+            && rest[1] == Instruction::Op(opcodes::all::OP_NUMEQUAL)
+            && ops[0] == opcodes::all::OP_CHECKSIG
+            && ops[1..].iter().all(|&op| op == OP_CHECKSIGADD)
+        {
+            let k = match rest[0] {
+                Instruction::Op(op) => match op.classify() {
+                    opcodes::Class::PushNum(k) => k as i64,
+                    _ => return None,
+                },
+                Instruction::PushBytes(bytes) => script::read_scriptint(bytes).ok()?,
+            };
+            if k < 1 || k > keys.len() as i64 {
+                return None;
+            }
+            k as usize
+        } else {
+            return None;
+        };
+
+        let sigs: Vec<Option<&Vec<u8>>> = keys
+            .iter()
+            .map(|key| {
+                let mut pubkey = [0; 32];
+                pubkey.copy_from_slice(key);
+                self.tap_script_sigs.get(&(pubkey, leaf_hash))
+            })
+            .collect();
+        let mut signers: Vec<usize> = (0..keys.len()).filter(|&i| sigs[i].is_some()).collect();
+        if signers.len() < threshold {
+            return None;
+        }
+        signers.sort_by_key(|&i| sigs[i].map(Vec::len));
+        signers.truncate(threshold);
+
+        // The first key checks the signature on top of the stack, which is
+        // the last element of the witness, once the hash locks have taken
+        // their preimages off it
+        Some(
+            (0..keys.len())
+                .rev()
+                .map(|i| match sigs[i] {
+                    Some(sig) if signers.contains(&i) => sig.clone(),
+                    _ => vec![],
+                })
+                .chain(preimages.into_iter().rev())
+                .collect(),
+        )
+    }
+}
+
+/// Whether `op` is one of the opcodes hashing the top of the stack
+fn is_hash_op(op: opcodes::All) -> bool {
+    op == opcodes::all::OP_RIPEMD160
+        || op == opcodes::all::OP_SHA256
+        || op == opcodes::all::OP_HASH160
+        || op == opcodes::all::OP_HASH256
+}
+
+/// Decodes a preimage into the map of preimages of a hash function. The
+/// key is invalid unless it is the hash of the preimage.
+fn insert_preimage<H: Hash + Ord>(
+    map: &mut BTreeMap<H, Vec<u8>>,
+    raw_key: raw::Key,
+    raw_value: &[u8],
+) -> Result<(), encode::Error> {
+    let hash = match H::from_slice(&raw_key.key) {
+        Ok(hash) if <H as Hash>::hash(raw_value) == hash => hash,
+        _ => return Err(psbt::Error::InvalidKey(raw_key).into()),
+    };
+    super::insert_keyed(map, hash, raw_key, raw_value)
+}
+
+impl Map for Input {
+    fn insert_pair(&mut self, pair: raw::Pair) -> Result<(), encode::Error> {
+        let raw::Pair {
+            key: raw_key,
+            value: raw_value,
+        } = pair;
+
+        match raw_key.type_value {
+            PSBT_IN_NON_WITNESS_UTXO => {
+                super::insert_unkeyed(&mut self.non_witness_utxo, raw_key, &raw_value)?
+            }
+            PSBT_IN_WITNESS_UTXO => {
+                super::insert_unkeyed(&mut self.witness_utxo, raw_key, &raw_value)?
+            }
+            PSBT_IN_SIGHASH_TYPE => {
+                super::insert_unkeyed(&mut self.sighash_type, raw_key, &raw_value)?
+            }
+            PSBT_IN_REDEEM_SCRIPT => {
+                super::insert_unkeyed(&mut self.redeem_script, raw_key, &raw_value)?
+            }
+            PSBT_IN_WITNESS_SCRIPT => {
+                super::insert_unkeyed(&mut self.witness_script, raw_key, &raw_value)?
+            }
+            PSBT_IN_FINAL_SCRIPTSIG => {
+                super::insert_unkeyed(&mut self.final_script_sig, raw_key, &raw_value)?
+            }
+            PSBT_IN_FINAL_SCRIPTWITNESS => {
+                super::insert_unkeyed(&mut self.final_script_witness, raw_key, &raw_value)?
+            }
+            PSBT_IN_PARTIAL_SIG => {
+                let pubkey = super::pubkey_key(&raw_key)?;
+                super::insert_keyed(&mut self.partial_sigs, pubkey, raw_key, &raw_value)?
+            }
+            PSBT_IN_BIP32_DERIVATION => {
+                let pubkey = super::pubkey_key(&raw_key)?;
+                super::insert_keyed(&mut self.bip32_derivation, pubkey, raw_key, &raw_value)?
+            }
+            PSBT_IN_RIPEMD160 => {
+                insert_preimage(&mut self.ripemd160_preimages, raw_key, &raw_value)?
+            }
+            PSBT_IN_SHA256 => insert_preimage(&mut self.sha256_preimages, raw_key, &raw_value)?,
+            PSBT_IN_HASH160 => insert_preimage(&mut self.hash160_preimages, raw_key, &raw_value)?,
+            PSBT_IN_HASH256 => insert_preimage(&mut self.hash256_preimages, raw_key, &raw_value)?,
+            PSBT_IN_TAP_KEY_SIG => {
+                super::insert_unkeyed(&mut self.tap_key_sig, raw_key, &raw_value)?
+            }
+            PSBT_IN_TAP_SCRIPT_SIG => {
+                if raw_key.key.len() != 64 {
+                    return Err(psbt::Error::InvalidKey(raw_key).into());
+                }
+                let mut pubkey = [0; 32];
+                pubkey.copy_from_slice(&raw_key.key[..32]);
+                let leaf_hash = TapLeafHash::from_slice(&raw_key.key[32..]).unwrap();
+                super::insert_keyed(
+                    &mut self.tap_script_sigs,
+                    (pubkey, leaf_hash),
+                    raw_key,
+                    &raw_value,
+                )?
+            }
+            PSBT_IN_TAP_LEAF_SCRIPT => {
+                let control_block = super::control_block_key(&raw_key)?;
+                super::insert_keyed(&mut self.tap_scripts, control_block, raw_key, &raw_value)?
+            }
+            PSBT_IN_TAP_BIP32_DERIVATION => {
+                let pubkey = super::xonly_key(&raw_key)?;
+                super::insert_keyed(&mut self.tap_key_origins, pubkey, raw_key, &raw_value)?
+            }
+            PSBT_IN_TAP_INTERNAL_KEY => {
+                super::insert_unkeyed(&mut self.tap_internal_key, raw_key, &raw_value)?
+            }
+            PSBT_IN_TAP_MERKLE_ROOT => {
+                super::insert_unkeyed(&mut self.tap_merkle_root, raw_key, &raw_value)?
+            }
+            _ => super::insert_unknown(&mut self.unknown, raw_key, raw_value)?,
+        }
+
+        Ok(())
+    }
+
+    fn get_pairs(&self) -> Result<Vec<raw::Pair>, encode::Error> {
+        let mut rv: Vec<raw::Pair> = Default::default();
+
+        super::push_unkeyed(&mut rv, PSBT_IN_NON_WITNESS_UTXO, &self.non_witness_utxo);
+        super::push_unkeyed(&mut rv, PSBT_IN_WITNESS_UTXO, &self.witness_utxo);
+        for (pubkey, sig) in self.partial_sigs.iter() {
+            super::push_keyed(&mut rv, PSBT_IN_PARTIAL_SIG, pubkey.clone(), sig);
+        }
+        super::push_unkeyed(&mut rv, PSBT_IN_SIGHASH_TYPE, &self.sighash_type);
+        super::push_unkeyed(&mut rv, PSBT_IN_REDEEM_SCRIPT, &self.redeem_script);
+        super::push_unkeyed(&mut rv, PSBT_IN_WITNESS_SCRIPT, &self.witness_script);
+        for (pubkey, source) in self.bip32_derivation.iter() {
+            super::push_keyed(&mut rv, PSBT_IN_BIP32_DERIVATION, pubkey.clone(), source);
+        }
+        super::push_unkeyed(&mut rv, PSBT_IN_FINAL_SCRIPTSIG, &self.final_script_sig);
+        super::push_unkeyed(
+            &mut rv,
+            PSBT_IN_FINAL_SCRIPTWITNESS,
+            &self.final_script_witness,
+        );
+        for (hash, preimage) in self.ripemd160_preimages.iter() {
+            super::push_keyed(&mut rv, PSBT_IN_RIPEMD160, hash[..].to_vec(), preimage);
+        }
+        for (hash, preimage) in self.sha256_preimages.iter() {
+            super::push_keyed(&mut rv, PSBT_IN_SHA256, hash[..].to_vec(), preimage);
+        }
+        for (hash, preimage) in self.hash160_preimages.iter() {
+            super::push_keyed(&mut rv, PSBT_IN_HASH160, hash[..].to_vec(), preimage);
+        }
+        for (hash, preimage) in self.hash256_preimages.iter() {
+            super::push_keyed(&mut rv, PSBT_IN_HASH256, hash[..].to_vec(), preimage);
+        }
+        super::push_unkeyed(&mut rv, PSBT_IN_TAP_KEY_SIG, &self.tap_key_sig);
+        for (&(pubkey, leaf_hash), sig) in self.tap_script_sigs.iter() {
+            let mut key = pubkey.to_vec();
+            key.extend_from_slice(&leaf_hash[..]);
+            super::push_keyed(&mut rv, PSBT_IN_TAP_SCRIPT_SIG, key, sig);
+        }
+        for (control_block, leaf) in self.tap_scripts.iter() {
+            super::push_keyed(
+                &mut rv,
+                PSBT_IN_TAP_LEAF_SCRIPT,
+                control_block.clone(),
+                leaf,
+            );
+        }
+        for (pubkey, origin) in self.tap_key_origins.iter() {
+            super::push_keyed(
+                &mut rv,
+                PSBT_IN_TAP_BIP32_DERIVATION,
+                pubkey.to_vec(),
+                origin,
+            );
+        }
+        super::push_unkeyed(&mut rv, PSBT_IN_TAP_INTERNAL_KEY, &self.tap_internal_key);
+        super::push_unkeyed(&mut rv, PSBT_IN_TAP_MERKLE_ROOT, &self.tap_merkle_root);
+        super::push_unknown(&mut rv, &self.unknown);
+
+        Ok(rv)
+    }
+}
+
+impl_psbtmap_consensus_encoding!(Input);
+impl_psbtmap_consensus_decoding!(Input);
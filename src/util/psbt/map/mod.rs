@@ -0,0 +1,168 @@
+use std::collections::BTreeMap;
+
+use consensus::encode;
+use util::psbt;
+use util::psbt::raw;
+use util::psbt::serialize::{Deserialize, Serialize, TAPROOT_CONTROL_MAX_NODE_COUNT};
+
+/// A trait that describes a PSBT key-value map.
+pub trait Map {
+    /// Attempt to insert a key-value pair.
+    fn insert_pair(&mut self, pair: raw::Pair) -> Result<(), encode::Error>;
+
+    /// Attempt to get all key-value pairs.
+    fn get_pairs(&self) -> Result<Vec<raw::Pair>, encode::Error>;
+}
+
+/// Decodes a value whose key carries nothing besides its type into a field
+/// that may only be set once.
+fn insert_unkeyed<T: Deserialize>(
+    field: &mut Option<T>,
+    key: raw::Key,
+    value: &[u8],
+) -> Result<(), encode::Error> {
+    if !key.key.is_empty() {
+        return Err(psbt::Error::InvalidKey(key).into());
+    }
+    if field.is_some() {
+        return Err(psbt::Error::DuplicateKey(key).into());
+    }
+    *field = Some(Deserialize::deserialize(value)?);
+    Ok(())
+}
+
+/// Encodes a field set by `insert_unkeyed`, if present.
+fn push_unkeyed<T: Serialize>(rv: &mut Vec<raw::Pair>, type_value: u8, field: &Option<T>) {
+    if let Some(ref value) = *field {
+        rv.push(raw::Pair {
+            key: raw::Key {
+                type_value,
+                key: vec![],
+            },
+            value: value.serialize(),
+        });
+    }
+}
+
+/// Decodes a value into a map field, under a key already parsed from the
+/// raw key.
+fn insert_keyed<K: Ord, V: Deserialize>(
+    map: &mut BTreeMap<K, V>,
+    key: K,
+    raw_key: raw::Key,
+    value: &[u8],
+) -> Result<(), encode::Error> {
+    if map.contains_key(&key) {
+        return Err(psbt::Error::DuplicateKey(raw_key).into());
+    }
+    map.insert(key, Deserialize::deserialize(value)?);
+    Ok(())
+}
+
+/// Encodes one entry of a map field.
+fn push_keyed<V: Serialize>(rv: &mut Vec<raw::Pair>, type_value: u8, key: Vec<u8>, value: &V) {
+    rv.push(raw::Pair {
+        key: raw::Key { type_value, key },
+        value: value.serialize(),
+    });
+}
+
+/// Checks that a raw key holds a compressed or uncompressed public key.
+fn pubkey_key(raw_key: &raw::Key) -> Result<Vec<u8>, encode::Error> {
+    match raw_key.key.len() {
+        33 | 65 => Ok(raw_key.key.clone()),
+        _ => Err(psbt::Error::InvalidKey(raw_key.clone()).into()),
+    }
+}
+
+/// Checks that a raw key holds an x-only public key.
+fn xonly_key(raw_key: &raw::Key) -> Result<[u8; 32], encode::Error> {
+    if raw_key.key.len() != 32 {
+        return Err(psbt::Error::InvalidKey(raw_key.clone()).into());
+    }
+    let mut key = [0; 32];
+    key.copy_from_slice(&raw_key.key);
+    Ok(key)
+}
+
+/// Checks that a raw key holds a taproot control block: the leaf version and
+/// parity byte, the internal key and up to 128 hashes of the merkle path.
+fn control_block_key(raw_key: &raw::Key) -> Result<Vec<u8>, encode::Error> {
+    let len = raw_key.key.len();
+    if len < 33 || (len - 33) % 32 != 0 || (len - 33) / 32 > TAPROOT_CONTROL_MAX_NODE_COUNT as usize
+    {
+        return Err(psbt::Error::InvalidKey(raw_key.clone()).into());
+    }
+    Ok(raw_key.key.clone())
+}
+
+/// Keeps a pair of an unrecognized type so that it survives a round trip.
+fn insert_unknown(
+    unknown: &mut OrderedMap<raw::Key, Vec<u8>>,
+    key: raw::Key,
+    value: Vec<u8>,
+) -> Result<(), encode::Error> {
+    unknown
+        .insert_unique(key, value)
+        .map_err(|key| psbt::Error::DuplicateKey(key).into())
+}
+
+/// Encodes the pairs kept by `insert_unknown`.
+fn push_unknown(rv: &mut Vec<raw::Pair>, unknown: &OrderedMap<raw::Key, Vec<u8>>) {
+    for (key, value) in unknown.iter() {
+        rv.push(raw::Pair {
+            key: key.clone(),
+            value: value.clone(),
+        });
+    }
+}
+
+macro_rules! impl_psbtmap_consensus_encoding {
+    ($thing:ty) => {
+        impl ::consensus::Encodable for $thing {
+            fn consensus_encode<S: ::std::io::Write>(
+                &self,
+                mut s: S,
+            ) -> Result<usize, ::consensus::encode::Error> {
+                let mut len = 0;
+                for pair in ::util::psbt::map::Map::get_pairs(self)? {
+                    len += ::consensus::Encodable::consensus_encode(&pair, &mut s)?;
+                }
+
+                Ok(len + ::consensus::Encodable::consensus_encode(&0x00_u8, s)?)
+            }
+        }
+    };
+}
+
+macro_rules! impl_psbtmap_consensus_decoding {
+    ($thing:ty) => {
+        impl ::consensus::Decodable for $thing {
+            fn consensus_decode<D: ::std::io::Read>(
+                mut d: D,
+            ) -> Result<Self, ::consensus::encode::Error> {
+                let mut rv: Self = ::std::default::Default::default();
+
+                loop {
+                    match ::consensus::Decodable::consensus_decode(&mut d) {
+                        Ok(pair) => ::util::psbt::map::Map::insert_pair(&mut rv, pair)?,
+                        Err(::consensus::encode::Error::Psbt(::util::psbt::Error::NoMorePairs)) => {
+                            return Ok(rv)
+                        }
+                        Err(e) => return Err(e),
+                    }
+                }
+            }
+        }
+    };
+}
+
+mod global;
+mod input;
+mod ordered;
+mod output;
+
+pub use self::global::Global;
+pub use self::input::{Input, SigningRequest};
+pub use self::ordered::OrderedMap;
+pub use self::output::Output;
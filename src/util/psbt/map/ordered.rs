@@ -0,0 +1,161 @@
+use std::iter::FromIterator;
+use std::slice;
+
+/// A map which keeps its entries in the order they were inserted.
+///
+/// PSBT maps hold pairs this library does not understand, which have to be
+/// passed on exactly as they were received. Keeping them in insertion order
+/// means a PSBT that is decoded and encoded again keeps its unknown pairs
+/// in their original order. Lookups are linear, as PSBT maps are small.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OrderedMap<K, V> {
+    entries: Vec<(K, V)>,
+}
+
+impl<K, V> Default for OrderedMap<K, V> {
+    fn default() -> Self {
+        OrderedMap { entries: vec![] }
+    }
+}
+
+impl<K: PartialEq, V> OrderedMap<K, V> {
+    /// Creates an empty map.
+    pub fn new() -> Self {
+        OrderedMap::default()
+    }
+
+    /// The number of entries in the map.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether the map has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The value stored under `key`, if any.
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.entries.iter().find(|e| e.0 == *key).map(|e| &e.1)
+    }
+
+    /// Whether a value is stored under `key`.
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Stores `value` under `key`, returning the value it replaced. A
+    /// replaced entry keeps its place in the order.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        match self.entries.iter_mut().find(|e| e.0 == key) {
+            Some(entry) => Some(::std::mem::replace(&mut entry.1, value)),
+            None => {
+                self.entries.push((key, value));
+                None
+            }
+        }
+    }
+
+    /// Stores `value` under `key` unless a value is already stored under
+    /// it, in which case the key is handed back.
+    pub fn insert_unique(&mut self, key: K, value: V) -> Result<(), K> {
+        if self.contains_key(&key) {
+            return Err(key);
+        }
+        self.entries.push((key, value));
+        Ok(())
+    }
+
+    /// Removes and returns the value stored under `key`, if any.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let index = self.entries.iter().position(|e| e.0 == *key)?;
+        Some(self.entries.remove(index).1)
+    }
+
+    /// Removes every entry.
+    pub fn clear(&mut self) {
+        self.entries.clear()
+    }
+
+    /// Iterates over the entries, in insertion order.
+    pub fn iter<'a>(&'a self) -> Iter<'a, K, V> {
+        Iter {
+            inner: self.entries.iter(),
+        }
+    }
+}
+
+/// An iterator over the entries of an `OrderedMap`, in insertion order.
+#[derive(Clone, Debug)]
+pub struct Iter<'a, K: 'a, V: 'a> {
+    inner: slice::Iter<'a, (K, V)>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|e| (&e.0, &e.1))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, K: PartialEq, V> IntoIterator for &'a OrderedMap<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<K: PartialEq, V> FromIterator<(K, V)> for OrderedMap<K, V> {
+    /// Collects the entries, a later value under the same key replacing an
+    /// earlier one.
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut map = OrderedMap::new();
+        for (key, value) in iter {
+            map.insert(key, value);
+        }
+        map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::OrderedMap;
+
+    #[test]
+    fn insertion_order() {
+        let mut map = OrderedMap::new();
+        assert!(map.is_empty());
+        assert_eq!(map.insert(3, "c"), None);
+        assert_eq!(map.insert(1, "a"), None);
+        assert_eq!(map.insert_unique(2, "b"), Ok(()));
+        assert_eq!(map.insert_unique(1, "x"), Err(1));
+        assert_eq!(map.get(&1), Some(&"a"));
+
+        // Replacing keeps the position
+        assert_eq!(map.insert(3, "z"), Some("c"));
+        assert_eq!(
+            map.iter().collect::<Vec<_>>(),
+            vec![(&3, &"z"), (&1, &"a"), (&2, &"b")]
+        );
+
+        assert_eq!(map.remove(&1), Some("a"));
+        assert_eq!(map.remove(&1), None);
+        assert!(!map.contains_key(&1));
+        assert_eq!(map.len(), 2);
+        assert_eq!(
+            vec![(2, "b"), (3, "z")]
+                .into_iter()
+                .collect::<OrderedMap<_, _>>()
+                .iter()
+                .count(),
+            2
+        );
+    }
+}
@@ -0,0 +1,106 @@
+use std::collections::BTreeMap;
+
+use blockdata::script::Script;
+use consensus::encode;
+use hash_types::TapLeafHash;
+use util::bip32::KeySource;
+use util::psbt::map::{Map, OrderedMap};
+use util::psbt::raw;
+
+/// Type: Redeem Script PSBT_OUT_REDEEM_SCRIPT = 0x00
+const PSBT_OUT_REDEEM_SCRIPT: u8 = 0x00;
+/// Type: Witness Script PSBT_OUT_WITNESS_SCRIPT = 0x01
+const PSBT_OUT_WITNESS_SCRIPT: u8 = 0x01;
+/// Type: BIP 32 Derivation Path PSBT_OUT_BIP32_DERIVATION = 0x02
+const PSBT_OUT_BIP32_DERIVATION: u8 = 0x02;
+/// Type: Taproot Internal Key PSBT_OUT_TAP_INTERNAL_KEY = 0x05
+const PSBT_OUT_TAP_INTERNAL_KEY: u8 = 0x05;
+/// Type: Taproot Tree PSBT_OUT_TAP_TREE = 0x06
+const PSBT_OUT_TAP_TREE: u8 = 0x06;
+/// Type: Taproot Key BIP 32 Derivation Path PSBT_OUT_TAP_BIP32_DERIVATION = 0x07
+const PSBT_OUT_TAP_BIP32_DERIVATION: u8 = 0x07;
+
+/// A key-value map for an output of the corresponding index in the unsigned
+/// transaction.
+#[derive(Clone, Default, Debug, PartialEq)]
+pub struct Output {
+    /// The redeem script for this output.
+    pub redeem_script: Option<Script>,
+    /// The witness script for this output.
+    pub witness_script: Option<Script>,
+    /// A map from serialized public keys used in this output to the
+    /// fingerprint of their master key and their derivation path.
+    pub bip32_derivation: BTreeMap<Vec<u8>, KeySource>,
+    /// The x-only internal key of this taproot output.
+    pub tap_internal_key: Option<[u8; 32]>,
+    /// The script tree of this taproot output, as its leaves in depth-first
+    /// order, each with its depth in the tree, its leaf version and its
+    /// script.
+    pub tap_tree: Option<Vec<(u8, u8, Script)>>,
+    /// A map from x-only public keys used in this output to the leaves they
+    /// appear in, the fingerprint of their master key and their derivation
+    /// path.
+    pub tap_key_origins: BTreeMap<[u8; 32], (Vec<TapLeafHash>, KeySource)>,
+    /// Unknown key-value pairs for this output, in the order they were
+    /// decoded.
+    pub unknown: OrderedMap<raw::Key, Vec<u8>>,
+}
+
+impl Map for Output {
+    fn insert_pair(&mut self, pair: raw::Pair) -> Result<(), encode::Error> {
+        let raw::Pair {
+            key: raw_key,
+            value: raw_value,
+        } = pair;
+
+        match raw_key.type_value {
+            PSBT_OUT_REDEEM_SCRIPT => {
+                super::insert_unkeyed(&mut self.redeem_script, raw_key, &raw_value)?
+            }
+            PSBT_OUT_WITNESS_SCRIPT => {
+                super::insert_unkeyed(&mut self.witness_script, raw_key, &raw_value)?
+            }
+            PSBT_OUT_BIP32_DERIVATION => {
+                let pubkey = super::pubkey_key(&raw_key)?;
+                super::insert_keyed(&mut self.bip32_derivation, pubkey, raw_key, &raw_value)?
+            }
+            PSBT_OUT_TAP_INTERNAL_KEY => {
+                super::insert_unkeyed(&mut self.tap_internal_key, raw_key, &raw_value)?
+            }
+            PSBT_OUT_TAP_TREE => super::insert_unkeyed(&mut self.tap_tree, raw_key, &raw_value)?,
+            PSBT_OUT_TAP_BIP32_DERIVATION => {
+                let pubkey = super::xonly_key(&raw_key)?;
+                super::insert_keyed(&mut self.tap_key_origins, pubkey, raw_key, &raw_value)?
+            }
+            _ => super::insert_unknown(&mut self.unknown, raw_key, raw_value)?,
+        }
+
+        Ok(())
+    }
+
+    fn get_pairs(&self) -> Result<Vec<raw::Pair>, encode::Error> {
+        let mut rv: Vec<raw::Pair> = Default::default();
+
+        super::push_unkeyed(&mut rv, PSBT_OUT_REDEEM_SCRIPT, &self.redeem_script);
+        super::push_unkeyed(&mut rv, PSBT_OUT_WITNESS_SCRIPT, &self.witness_script);
+        for (pubkey, source) in self.bip32_derivation.iter() {
+            super::push_keyed(&mut rv, PSBT_OUT_BIP32_DERIVATION, pubkey.clone(), source);
+        }
+        super::push_unkeyed(&mut rv, PSBT_OUT_TAP_INTERNAL_KEY, &self.tap_internal_key);
+        super::push_unkeyed(&mut rv, PSBT_OUT_TAP_TREE, &self.tap_tree);
+        for (pubkey, origin) in self.tap_key_origins.iter() {
+            super::push_keyed(
+                &mut rv,
+                PSBT_OUT_TAP_BIP32_DERIVATION,
+                pubkey.to_vec(),
+                origin,
+            );
+        }
+        super::push_unknown(&mut rv, &self.unknown);
+
+        Ok(rv)
+    }
+}
+
+impl_psbtmap_consensus_encoding!(Output);
+impl_psbtmap_consensus_decoding!(Output);
@@ -1,7 +1,1256 @@
 //! Partially Signed Transactions
 //!
 //! Implementation of BIP174 Partially Signed Bitcoin Transaction Format
-//! as defined at //! defined at https://github.com/bitcoin/bips/blob/master/bip-0174.mediawiki
+//! as defined at https://github.com/bitcoin/bips/blob/master/bip-0174.mediawiki
 //! except we define PSBTs containing non-standard SigHash types as invalid.
+//!
+//! BIP174 splits the life of a PSBT between roles: the creator builds it from
+//! an unsigned transaction, updaters add the data signers need, signers add
+//! partial signatures, the finalizer turns those into a final scriptSig and
+//! witness, and the extractor produces the network transaction. The methods
+//! of `PartiallySignedTransaction` check that each step is valid for the
+//! state the PSBT is in, instead of silently producing an invalid
+//! transaction.
+
+use std::collections::HashSet;
+use std::io;
 
+use blockdata::script::Script;
+use blockdata::transaction::{SigHashType, Transaction, TxOut};
+use consensus::encode::{self, Decodable, Encodable};
+use util::amount::Amount;
+use util::fee_rate::FeeRate;
+use util::weight::Weight;
+
+mod error;
 pub use self::error::Error;
+
+pub mod raw;
+
+pub mod serialize;
+
+mod map;
+pub use self::map::{Global, Input, Map, OrderedMap, Output, SigningRequest};
+
+mod inspect;
+pub use self::inspect::{InputSummary, MapDiff, OutputSummary, PsbtDiff, PsbtSummary};
+
+pub mod payjoin;
+
+/// A Partially Signed Transaction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PartiallySignedTransaction {
+    /// The key-value pairs for all global data.
+    pub global: Global,
+    /// The corresponding key-value map for each input in the unsigned
+    /// transaction.
+    pub inputs: Vec<Input>,
+    /// The corresponding key-value map for each output in the unsigned
+    /// transaction.
+    pub outputs: Vec<Output>,
+}
+
+impl PartiallySignedTransaction {
+    /// Create a PartiallySignedTransaction from an unsigned transaction, error
+    /// if not unsigned. This is the BIP174 creator role.
+    pub fn from_unsigned_tx(tx: Transaction) -> Result<Self, Error> {
+        Ok(PartiallySignedTransaction {
+            inputs: vec![Default::default(); tx.input.len()],
+            outputs: vec![Default::default(); tx.output.len()],
+            global: Global::from_unsigned_tx(tx)?,
+        })
+    }
+
+    /// Checks that an updater may still add data to this PSBT, which is
+    /// only the case while none of its inputs have been finalized.
+    pub fn assert_updatable(&self) -> Result<(), Error> {
+        match self.inputs.iter().position(Input::is_finalized) {
+            Some(index) => Err(Error::InputAlreadyFinalized(index)),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks that a signer can sign the input at `index`: it must exist,
+    /// not be finalized yet, and carry the output it spends.
+    pub fn assert_signable(&self, index: usize) -> Result<(), Error> {
+        let input = self.input(index)?;
+        if input.is_finalized() {
+            return Err(Error::InputAlreadyFinalized(index));
+        }
+        if input.witness_utxo.is_none() && input.non_witness_utxo.is_none() {
+            return Err(Error::MissingUtxo(index));
+        }
+        Ok(())
+    }
+
+    /// Records the final scriptSig and witness of the input at `index`, as
+    /// the BIP174 finalizer does. See `Input::finalize`.
+    pub fn finalize_input(
+        &mut self,
+        index: usize,
+        script_sig: Script,
+        witness: Vec<Vec<u8>>,
+    ) -> Result<(), Error> {
+        if self.input(index)?.is_finalized() {
+            return Err(Error::InputAlreadyFinalized(index));
+        }
+        self.inputs[index].finalize(script_sig, witness);
+        Ok(())
+    }
+
+    /// Finalizes the input at `index` as a taproot script path spend,
+    /// using the cheapest of its leaves that its signatures and preimages
+    /// satisfy. See `Input::tap_script_path_witness`.
+    pub fn finalize_tap_script_path(&mut self, index: usize) -> Result<(), Error> {
+        let input = self.input(index)?;
+        if input.is_finalized() {
+            return Err(Error::InputAlreadyFinalized(index));
+        }
+        let witness = input
+            .tap_script_path_witness()
+            .ok_or(Error::NoSatisfyingLeaf(index))?;
+        self.inputs[index].finalize(Script::new(), witness);
+        Ok(())
+    }
+
+    /// Combines the PSBTs of several parties spending unrelated coins into
+    /// one, for collaborative transactions such as CoinJoins. The inputs
+    /// and outputs of each PSBT are appended in turn, with their maps.
+    ///
+    /// The transactions must have the same version and lock time, no
+    /// outpoint may be spent twice, and global pairs with the same key must
+    /// have the same value. Signatures already made must stay valid, which
+    /// in a merge with other parties takes SIGHASH_ANYONECANPAY and, with
+    /// SIGHASH_ALL or SIGHASH_SINGLE, outputs that do not change or move.
+    /// Finalized inputs are rejected, as their signatures cannot be
+    /// checked, so merge before finalizing.
+    pub fn merge_unrelated(psbts: &[PartiallySignedTransaction]) -> Result<Self, Error> {
+        let first = &psbts.first().ok_or(Error::MustHaveUnsignedTx)?.global;
+        let mut merged = PartiallySignedTransaction::from_unsigned_tx(Transaction {
+            version: first.unsigned_tx.version,
+            lock_time: first.unsigned_tx.lock_time,
+            input: vec![],
+            output: vec![],
+        })?;
+
+        let mut outpoints = HashSet::new();
+        for psbt in psbts {
+            let tx = &psbt.global.unsigned_tx;
+            if tx.version != first.unsigned_tx.version
+                || tx.lock_time != first.unsigned_tx.lock_time
+            {
+                return Err(Error::UnsignedTxMismatch);
+            }
+            for txin in &tx.input {
+                if !outpoints.insert(txin.previous_output) {
+                    return Err(Error::DuplicateOutpoint(txin.previous_output));
+                }
+            }
+            for (key, value) in psbt.global.unknown.iter() {
+                match merged.global.unknown.get(key) {
+                    Some(merged_value) if merged_value != value => {
+                        return Err(Error::DuplicateKey(key.clone()))
+                    }
+                    Some(_) => {}
+                    None => {
+                        merged.global.unknown.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+            merged.global.unsigned_tx.input.extend_from_slice(&tx.input);
+            merged
+                .global
+                .unsigned_tx
+                .output
+                .extend_from_slice(&tx.output);
+            merged.inputs.extend_from_slice(&psbt.inputs);
+            merged.outputs.extend_from_slice(&psbt.outputs);
+        }
+
+        let mut merged_index = 0;
+        for psbt in psbts {
+            for (index, input) in psbt.inputs.iter().enumerate() {
+                let keeps_signatures = !input.is_finalized()
+                    && input.signature_sighash_types().into_iter().all(|sighash| {
+                        keeps_signature(
+                            sighash,
+                            &psbt.global.unsigned_tx,
+                            index,
+                            &merged.global.unsigned_tx,
+                            merged_index,
+                        )
+                    });
+                if !keeps_signatures {
+                    return Err(Error::IncompatibleSigHash(merged_index));
+                }
+                merged_index += 1;
+            }
+        }
+        Ok(merged)
+    }
+
+    /// Extract the network transaction, as the BIP174 extractor does, failing
+    /// if any input has not been finalized.
+    pub fn extract_tx_checked(self) -> Result<Transaction, Error> {
+        match self.inputs.iter().position(|input| !input.is_finalized()) {
+            Some(index) => Err(Error::InputNotFinalized(index)),
+            None => Ok(self.extract_tx()),
+        }
+    }
+
+    /// Extract the network transaction without checking that every input
+    /// has been finalized. Inputs that were not finalized are left with an
+    /// empty scriptSig and witness.
+    pub fn extract_tx(self) -> Transaction {
+        let mut tx: Transaction = self.global.unsigned_tx;
+
+        for (vin, psbtin) in tx.input.iter_mut().zip(self.inputs.into_iter()) {
+            vin.script_sig = psbtin.final_script_sig.unwrap_or_else(Script::new);
+            vin.witness = psbtin.final_script_witness.unwrap_or_else(Vec::new);
+        }
+
+        tx
+    }
+
+    /// Checks that the UTXOs recorded for each input are consistent with
+    /// the unsigned transaction: a `non_witness_utxo` must be the transaction
+    /// the input spends from and have the spent output, a `witness_utxo`
+    /// must match that output when both are present, and no UTXO may claim
+    /// more than the maximum amount of money.
+    pub fn validate(&self) -> Result<(), Error> {
+        let txins = self.global.unsigned_tx.input.iter();
+        for (index, (txin, input)) in txins.zip(self.inputs.iter()).enumerate() {
+            let prevout = txin.previous_output;
+
+            if let Some(ref utxo_tx) = input.non_witness_utxo {
+                if utxo_tx.txid() != prevout.txid {
+                    return Err(Error::NonWitnessUtxoTxidMismatch(index));
+                }
+                let spent = match utxo_tx.output.get(prevout.vout as usize) {
+                    Some(spent) => spent,
+                    None => return Err(Error::PrevoutIndexOutOfBounds(index)),
+                };
+                if spent.value > Amount::MAX_MONEY.as_sat() {
+                    return Err(Error::UtxoAmountOutOfRange(index));
+                }
+                if let Some(ref witness_utxo) = input.witness_utxo {
+                    if witness_utxo != spent {
+                        return Err(Error::WitnessUtxoMismatch(index));
+                    }
+                }
+            }
+
+            if let Some(ref witness_utxo) = input.witness_utxo {
+                if witness_utxo.value > Amount::MAX_MONEY.as_sat() {
+                    return Err(Error::UtxoAmountOutOfRange(index));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Deserialize a PSBT and check its UTXOs with `validate`, rejecting
+    /// PSBTs that parse but describe inconsistent inputs.
+    pub fn deserialize_strict(data: &[u8]) -> Result<Self, encode::Error> {
+        let psbt: PartiallySignedTransaction = encode::deserialize(data)?;
+        psbt.validate()?;
+        Ok(psbt)
+    }
+
+    /// The fee paid by the transaction: the amounts of the outputs its
+    /// inputs spend, less the amounts of its outputs. Every input must carry
+    /// its `witness_utxo` or `non_witness_utxo`.
+    pub fn fee(&self) -> Result<Amount, Error> {
+        let mut input_value = Amount::ZERO;
+        for index in 0..self.global.unsigned_tx.input.len() {
+            let utxo = self.spent_utxo(index).ok_or(Error::MissingUtxo(index))?;
+            input_value = input_value
+                .checked_add(Amount::from_sat(utxo.value))
+                .ok_or(Error::FeeOutOfRange)?;
+        }
+        let mut output_value = Amount::ZERO;
+        for txout in &self.global.unsigned_tx.output {
+            output_value = output_value
+                .checked_add(Amount::from_sat(txout.value))
+                .ok_or(Error::FeeOutOfRange)?;
+        }
+        input_value
+            .checked_sub(output_value)
+            .ok_or(Error::NegativeFee)
+    }
+
+    /// The fee rate the transaction pays once signed, given a prediction of
+    /// its final weight including signatures, rounded down.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `weight_prediction` is zero.
+    pub fn fee_rate(&self, weight_prediction: Weight) -> Result<FeeRate, Error> {
+        let fee = self.fee()?;
+        let sat_kwu = fee.as_sat().checked_mul(1000).ok_or(Error::FeeOutOfRange)?;
+        Ok(FeeRate::from_sat_per_kwu(
+            sat_kwu / weight_prediction.to_wu(),
+        ))
+    }
+
+    /// The output spent by the input at `index`, from its `witness_utxo` or
+    /// else its `non_witness_utxo`, if either is known.
+    fn spent_utxo(&self, index: usize) -> Option<&TxOut> {
+        let input = self.inputs.get(index)?;
+        if let Some(ref utxo) = input.witness_utxo {
+            return Some(utxo);
+        }
+        let vout = self
+            .global
+            .unsigned_tx
+            .input
+            .get(index)?
+            .previous_output
+            .vout;
+        input
+            .non_witness_utxo
+            .as_ref()
+            .and_then(|tx| tx.output.get(vout as usize))
+    }
+
+    fn input(&self, index: usize) -> Result<&Input, Error> {
+        self.inputs
+            .get(index)
+            .ok_or(Error::InputIndexOutOfBounds(index))
+    }
+}
+
+/// Whether a signature with `sighash` for the input at `index` of `tx` is
+/// still valid for the same input at `merged_index` of `merged`
+fn keeps_signature(
+    sighash: SigHashType,
+    tx: &Transaction,
+    index: usize,
+    merged: &Transaction,
+    merged_index: usize,
+) -> bool {
+    match sighash.split_anyonecanpay_flag() {
+        (_, false) => tx.input == merged.input && tx.output == merged.output,
+        (SigHashType::None, true) => true,
+        (SigHashType::Single, true) => tx.output.get(index) == merged.output.get(merged_index),
+        (_, true) => tx.output == merged.output,
+    }
+}
+
+impl Encodable for PartiallySignedTransaction {
+    fn consensus_encode<S: io::Write>(&self, mut s: S) -> Result<usize, encode::Error> {
+        let mut len = 0;
+        len += b"psbt".consensus_encode(&mut s)?;
+
+        len += 0xff_u8.consensus_encode(&mut s)?;
+
+        len += self.global.consensus_encode(&mut s)?;
+
+        for i in &self.inputs {
+            len += i.consensus_encode(&mut s)?;
+        }
+
+        for i in &self.outputs {
+            len += i.consensus_encode(&mut s)?;
+        }
+
+        Ok(len)
+    }
+}
+
+impl Decodable for PartiallySignedTransaction {
+    fn consensus_decode<D: io::Read>(mut d: D) -> Result<Self, encode::Error> {
+        let magic: [u8; 4] = Decodable::consensus_decode(&mut d)?;
+
+        if *b"psbt" != magic {
+            return Err(Error::InvalidMagic.into());
+        }
+
+        if 0xff_u8 != u8::consensus_decode(&mut d)? {
+            return Err(Error::InvalidSeparator.into());
+        }
+
+        let global: Global = Decodable::consensus_decode(&mut d)?;
+
+        let inputs: Vec<Input> = {
+            let inputs_len: usize = (&global.unsigned_tx.input).len();
+
+            let mut inputs: Vec<Input> = Vec::with_capacity(inputs_len);
+
+            for _ in 0..inputs_len {
+                inputs.push(Decodable::consensus_decode(&mut d)?);
+            }
+
+            inputs
+        };
+
+        let outputs: Vec<Output> = {
+            let outputs_len: usize = (&global.unsigned_tx.output).len();
+
+            let mut outputs: Vec<Output> = Vec::with_capacity(outputs_len);
+
+            for _ in 0..outputs_len {
+                outputs.push(Decodable::consensus_decode(&mut d)?);
+            }
+
+            outputs
+        };
+
+        Ok(PartiallySignedTransaction {
+            global,
+            inputs,
+            outputs,
+        })
+    }
+}
+
+impl_consensus_from_hex!(PartiallySignedTransaction);
+
+#[cfg(test)]
+mod tests {
+    use super::raw;
+    use super::serialize::{Deserialize, Serialize};
+    use super::{Error, PartiallySignedTransaction, SigningRequest};
+
+    use std::str::FromStr;
+
+    use blockdata::opcodes;
+    use blockdata::script::{Builder, Script};
+    use blockdata::transaction::{InputType, OutPoint, SigHashType, Transaction, TxIn, TxOut};
+    use consensus::encode::{self, deserialize, serialize};
+    use hash_types::{TapBranchHash, TapLeafHash, Txid};
+    use hashes::hex::FromHex;
+    use hashes::{hash160, ripemd160, sha256, sha256d, Hash};
+    use util::amount::Amount;
+    use util::bip32::KeySource;
+    use util::fee_rate::FeeRate;
+    use util::weight::Weight;
+
+    fn unsigned_tx() -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: 1257139,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid::hash(&[1]), 0),
+                sequence: 4294967294,
+                ..Default::default()
+            }],
+            output: vec![
+                TxOut {
+                    value: 99999699,
+                    script_pubkey: hex_script!(
+                        "76a914d0c59903c5bac2868760e90fd521a4665aa7652088ac"
+                    ),
+                },
+                TxOut {
+                    value: 100000000,
+                    script_pubkey: hex_script!("a9143545e6e33b832c47050f24d3eeb93c9c03948bc787"),
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn trivial_psbt() {
+        let psbt = PartiallySignedTransaction::from_unsigned_tx(Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![],
+            output: vec![],
+        })
+        .unwrap();
+        assert_eq!(
+            serialize(&psbt),
+            Vec::<u8>::from_hex("70736274ff01000a0200000000000000000000").unwrap()
+        );
+    }
+
+    #[test]
+    fn serialize_then_deserialize() {
+        let mut psbt = PartiallySignedTransaction::from_unsigned_tx(unsigned_tx()).unwrap();
+        psbt.inputs[0].witness_utxo = Some(TxOut {
+            value: 200000000,
+            script_pubkey: hex_script!("a914339725ba21efd62ac753a9bcd067d6c7a6a39d0587"),
+        });
+        psbt.inputs[0].sighash_type = Some(SigHashType::All);
+        psbt.inputs[0].redeem_script =
+            Some(hex_script!("0014be18d152a9b012039daf3da7de4f53349eecb985"));
+        psbt.inputs[0]
+            .partial_sigs
+            .insert(vec![2; 33], vec![0x30, 0x01]);
+        psbt.outputs[1].witness_script = Some(hex_script!("51"));
+
+        let ser = serialize(&psbt);
+        let de: PartiallySignedTransaction = deserialize(&ser).unwrap();
+        assert_eq!(de, psbt);
+        assert_eq!(serialize(&de), ser);
+    }
+
+    #[test]
+    fn unknown_pairs() {
+        let pair = |type_value: u8, key: u8| raw::Pair {
+            key: raw::Key {
+                type_value,
+                key: vec![key],
+            },
+            value: vec![key; 2],
+        };
+
+        // Unknown pairs keep the order they were decoded in
+        let mut psbt = PartiallySignedTransaction::from_unsigned_tx(unsigned_tx()).unwrap();
+        for &(type_value, key) in &[(0xfc, 2), (0x30, 9), (0xfc, 1)] {
+            let pair = pair(type_value, key);
+            psbt.global
+                .unknown
+                .insert(pair.key.clone(), pair.value.clone());
+            psbt.inputs[0].unknown.insert(pair.key, pair.value);
+        }
+        let ser = serialize(&psbt);
+        let de: PartiallySignedTransaction = deserialize(&ser).unwrap();
+        assert_eq!(
+            de.inputs[0]
+                .unknown
+                .iter()
+                .map(|(k, _)| k.key[0])
+                .collect::<Vec<_>>(),
+            vec![2, 9, 1]
+        );
+        assert_eq!(de, psbt);
+        assert_eq!(serialize(&de), ser);
+
+        // The same unknown key twice
+        let encoded = serialize(&pair(0x30, 9));
+        let at = ser
+            .windows(encoded.len())
+            .rposition(|w| w == &encoded[..])
+            .unwrap();
+        let mut duplicated = ser.clone();
+        for (i, &b) in encoded.iter().enumerate() {
+            duplicated.insert(at + i, b);
+        }
+        match deserialize::<PartiallySignedTransaction>(&duplicated) {
+            Err(encode::Error::Psbt(Error::DuplicateKey(ref key))) if key.key == vec![9] => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn invalid_psbts() {
+        // Network transaction, not PSBT format
+        let bad = Vec::<u8>::from_hex("0200000001268171371edff285e937adeea4b37b78000c0566cbb3ad64641713ca42171bf6000000006a473044022070b2245123e6bf474d60c5b50c043d4c691a5d2435f09a34a7662a9dc251790a022001329ca9dacf280bdf30740ec0390422422c81cb45839457aeb76fc12edd95b3012102657d118d3357b8e0f4c2cd46db7b39f6d9c38d9a70abcb9b2de5dc8dbfe4ce31feffffff02d3dff505000000001976a914d0c59903c5bac2868760e90fd521a4665aa7652088ac00e1f5050000000017a9143545e6e33b832c47050f24d3eeb93c9c03948bc787b32e1300").unwrap();
+        match deserialize::<PartiallySignedTransaction>(&bad) {
+            Err(encode::Error::Psbt(Error::InvalidMagic)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        // Wrong separator
+        let bad = Vec::<u8>::from_hex("70736274fe01000a0200000000000000000000").unwrap();
+        match deserialize::<PartiallySignedTransaction>(&bad) {
+            Err(encode::Error::Psbt(Error::InvalidSeparator)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        // No unsigned transaction
+        let bad = Vec::<u8>::from_hex("70736274ff00").unwrap();
+        match deserialize::<PartiallySignedTransaction>(&bad) {
+            Err(encode::Error::Psbt(Error::MustHaveUnsignedTx)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        // Unsigned transaction with a scriptSig
+        let mut tx = unsigned_tx();
+        tx.input[0].script_sig = Script::from(vec![0x51]);
+        match PartiallySignedTransaction::from_unsigned_tx(tx) {
+            Err(Error::UnsignedTxHasScriptSigs) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn roles() {
+        let mut psbt = PartiallySignedTransaction::from_unsigned_tx(unsigned_tx()).unwrap();
+        assert!(psbt.assert_updatable().is_ok());
+
+        // A signer needs the spent output
+        match psbt.assert_signable(0) {
+            Err(Error::MissingUtxo(0)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        match psbt.assert_signable(1) {
+            Err(Error::InputIndexOutOfBounds(1)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        psbt.inputs[0].witness_utxo = Some(TxOut {
+            value: 200000000,
+            script_pubkey: hex_script!("0014be18d152a9b012039daf3da7de4f53349eecb985"),
+        });
+        psbt.inputs[0].sighash_type = Some(SigHashType::All);
+        psbt.inputs[0]
+            .partial_sigs
+            .insert(vec![2; 33], vec![0x30, 0x01]);
+        assert!(psbt.assert_signable(0).is_ok());
+
+        // Extracting before finalizing is refused
+        match psbt.clone().extract_tx_checked() {
+            Err(Error::InputNotFinalized(0)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let witness = vec![vec![0x30, 0x01], vec![2; 33]];
+        psbt.finalize_input(0, Script::new(), witness.clone())
+            .unwrap();
+        assert!(psbt.inputs[0].is_finalized());
+        assert!(psbt.inputs[0].partial_sigs.is_empty());
+        assert_eq!(psbt.inputs[0].sighash_type, None);
+        assert!(psbt.inputs[0].witness_utxo.is_some());
+        assert_eq!(psbt.inputs[0].final_script_sig, None);
+
+        match psbt.assert_updatable() {
+            Err(Error::InputAlreadyFinalized(0)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        match psbt.assert_signable(0) {
+            Err(Error::InputAlreadyFinalized(0)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        match psbt.finalize_input(0, Script::new(), witness.clone()) {
+            Err(Error::InputAlreadyFinalized(0)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let tx = psbt.extract_tx_checked().unwrap();
+        assert_eq!(tx.input[0].witness, witness);
+        assert!(tx.input[0].script_sig.is_empty());
+        assert_eq!(tx.txid(), unsigned_tx().txid());
+    }
+
+    #[test]
+    fn finalized_satisfaction_within_estimate() {
+        // The largest low-S signature, with its sighash byte
+        let mut ecdsa_sig = vec![0x30, 0x45, 0x02, 0x21, 0x00];
+        ecdsa_sig.extend_from_slice(&[0x7f; 32]);
+        ecdsa_sig.extend_from_slice(&[0x02, 0x20]);
+        ecdsa_sig.extend_from_slice(&[0x3f; 32]);
+        ecdsa_sig.push(0x01);
+        assert_eq!(ecdsa_sig.len(), 72);
+        let compressed = vec![0x02; 33];
+        let uncompressed = vec![0x04; 65];
+        let mut program = vec![0x00, 0x14];
+        program.extend_from_slice(&[0x11; 20]);
+        let push = |data: &[&[u8]]| {
+            data.iter()
+                .fold(Builder::new(), |builder, d| builder.push_slice(d))
+                .into_script()
+        };
+        let segwit_v0 = vec![ecdsa_sig.clone(), compressed.clone()];
+
+        // Each type with a satisfaction as finalized, and whether it is the
+        // largest one, whose size is then fixed
+        let cases = vec![
+            (InputType::P2pk, push(&[&ecdsa_sig]), vec![], true),
+            (
+                InputType::P2pkh,
+                push(&[&ecdsa_sig, &uncompressed]),
+                vec![],
+                true,
+            ),
+            (
+                InputType::P2pkh,
+                push(&[&ecdsa_sig, &compressed]),
+                vec![],
+                false,
+            ),
+            (
+                InputType::P2shP2wpkh,
+                push(&[&program]),
+                segwit_v0.clone(),
+                true,
+            ),
+            (InputType::P2wpkh, Script::new(), segwit_v0, true),
+            (InputType::P2tr, Script::new(), vec![vec![1; 65]], true),
+            (InputType::P2tr, Script::new(), vec![vec![1; 64]], false),
+        ];
+        for (input_type, script_sig, witness, largest) in cases {
+            let mut psbt = PartiallySignedTransaction::from_unsigned_tx(unsigned_tx()).unwrap();
+            psbt.finalize_input(0, script_sig, witness).unwrap();
+            let tx = psbt.extract_tx_checked().unwrap();
+            let txin = &tx.input[0];
+            let script_sig_size = txin.base_size() - 40;
+            let weight = 4 * script_sig_size + txin.witness_size();
+            let size = script_sig_size + txin.witness_size();
+            let max_weight = input_type.max_satisfaction_weight().unwrap();
+            let max_size = input_type.max_satisfaction_size().unwrap();
+            assert!(
+                weight <= max_weight,
+                "{:?}: {} > {}",
+                input_type,
+                weight,
+                max_weight
+            );
+            assert!(
+                size <= max_size,
+                "{:?}: {} > {}",
+                input_type,
+                size,
+                max_size
+            );
+            if largest {
+                assert_eq!(weight, max_weight, "{:?}", input_type);
+                assert_eq!(size, max_size, "{:?}", input_type);
+            }
+        }
+    }
+
+    #[test]
+    fn validate_utxos() {
+        let prev_tx = Transaction {
+            version: 1,
+            lock_time: 0,
+            input: vec![TxIn::default()],
+            output: vec![TxOut {
+                value: 200000000,
+                script_pubkey: hex_script!("a914339725ba21efd62ac753a9bcd067d6c7a6a39d0587"),
+            }],
+        };
+        let mut tx = unsigned_tx();
+        tx.input[0].previous_output = OutPoint::new(prev_tx.txid(), 0);
+
+        let mut psbt = PartiallySignedTransaction::from_unsigned_tx(tx).unwrap();
+        assert!(psbt.validate().is_ok());
+
+        psbt.inputs[0].non_witness_utxo = Some(prev_tx.clone());
+        psbt.inputs[0].witness_utxo = Some(prev_tx.output[0].clone());
+        assert!(psbt.validate().is_ok());
+        let ser = serialize(&psbt);
+        assert_eq!(
+            PartiallySignedTransaction::deserialize_strict(&ser).unwrap(),
+            psbt
+        );
+
+        let mut bad = psbt.clone();
+        bad.inputs[0].witness_utxo.as_mut().unwrap().value += 1;
+        match bad.validate() {
+            Err(Error::WitnessUtxoMismatch(0)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let mut bad = psbt.clone();
+        bad.inputs[0].non_witness_utxo = Some(unsigned_tx());
+        match bad.validate() {
+            Err(Error::NonWitnessUtxoTxidMismatch(0)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        match PartiallySignedTransaction::deserialize_strict(&serialize(&bad)) {
+            Err(encode::Error::Psbt(Error::NonWitnessUtxoTxidMismatch(0))) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let mut bad = psbt.clone();
+        bad.global.unsigned_tx.input[0].previous_output.vout = 1;
+        match bad.validate() {
+            Err(Error::PrevoutIndexOutOfBounds(0)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let mut bad = psbt.clone();
+        bad.inputs[0].non_witness_utxo = None;
+        bad.inputs[0].witness_utxo.as_mut().unwrap().value = 21_000_000 * 100_000_000 + 1;
+        match bad.validate() {
+            Err(Error::UtxoAmountOutOfRange(0)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn fee() {
+        let mut psbt = PartiallySignedTransaction::from_unsigned_tx(unsigned_tx()).unwrap();
+        match psbt.fee() {
+            Err(Error::MissingUtxo(0)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        psbt.inputs[0].witness_utxo = Some(TxOut {
+            value: 200000000,
+            script_pubkey: hex_script!("0014be18d152a9b012039daf3da7de4f53349eecb985"),
+        });
+        assert_eq!(psbt.fee().unwrap(), Amount::from_sat(301));
+        assert_eq!(
+            psbt.fee_rate(Weight::from_wu(1000)).unwrap(),
+            FeeRate::from_sat_per_kwu(301)
+        );
+        assert_eq!(
+            psbt.fee_rate(Weight::from_vb(301).unwrap()).unwrap(),
+            FeeRate::from_sat_per_vb(1).unwrap()
+        );
+
+        let mut bad = psbt.clone();
+        bad.inputs[0].witness_utxo.as_mut().unwrap().value = 199999698;
+        match bad.fee() {
+            Err(Error::NegativeFee) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        match bad.fee_rate(Weight::from_wu(1000)) {
+            Err(Error::NegativeFee) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let mut bad = psbt.clone();
+        bad.global.unsigned_tx.output[0].value = u64::max_value();
+        match bad.fee() {
+            Err(Error::FeeOutOfRange) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn key_origins() {
+        let source = KeySource::from_str("d90c6a4f/0'/0'/2'").unwrap();
+        assert_eq!(
+            source.serialize(),
+            Vec::<u8>::from_hex("d90c6a4f000000800000008002000080").unwrap()
+        );
+        assert_eq!(KeySource::deserialize(&source.serialize()).unwrap(), source);
+        assert!(KeySource::deserialize(&[0xd9, 0x0c, 0x6a]).is_err());
+
+        let ecdsa_key = vec![3; 33];
+        let internal_key = [1; 32];
+        let script_key = [2; 32];
+        let leaf_hash = TapLeafHash::hash(&[0xc0]);
+
+        let mut psbt = PartiallySignedTransaction::from_unsigned_tx(unsigned_tx()).unwrap();
+        {
+            let input = &mut psbt.inputs[0];
+            input
+                .bip32_derivation
+                .insert(ecdsa_key.clone(), source.clone());
+            input
+                .tap_key_origins
+                .insert(internal_key, (vec![], source.clone()));
+            input
+                .tap_key_origins
+                .insert(script_key, (vec![leaf_hash], source.clone()));
+            input.tap_internal_key = Some(internal_key);
+            input.tap_merkle_root = Some(TapBranchHash::hash(&[1]));
+        }
+        psbt.outputs[0]
+            .bip32_derivation
+            .insert(ecdsa_key.clone(), source.clone());
+        psbt.outputs[1].tap_internal_key = Some(internal_key);
+        psbt.outputs[1]
+            .tap_key_origins
+            .insert(internal_key, (vec![], source.clone()));
+
+        let de: PartiallySignedTransaction = deserialize(&serialize(&psbt)).unwrap();
+        assert_eq!(de, psbt);
+
+        assert_eq!(
+            psbt.inputs[0].signing_requests(),
+            vec![
+                SigningRequest::Ecdsa {
+                    pubkey: &ecdsa_key[..],
+                    source: &source,
+                },
+                SigningRequest::TaprootKey {
+                    pubkey: &internal_key,
+                    source: &source,
+                },
+                SigningRequest::TaprootScript {
+                    pubkey: &script_key,
+                    leaf_hash,
+                    source: &source,
+                },
+            ]
+        );
+
+        let input = &mut psbt.inputs[0];
+        input.partial_sigs.insert(ecdsa_key.clone(), vec![0x30]);
+        input.tap_key_sig = Some(vec![0; 64]);
+        input
+            .tap_script_sigs
+            .insert((script_key, leaf_hash), vec![0; 64]);
+        assert!(input.signing_requests().is_empty());
+
+        input.bip32_derivation.insert(vec![4; 33], source.clone());
+        assert_eq!(input.signing_requests().len(), 1);
+        input.finalize(Script::new(), vec![vec![0; 64]]);
+        assert!(input.signing_requests().is_empty());
+        assert!(input.tap_key_origins.is_empty());
+        assert_eq!(input.tap_internal_key, None);
+    }
+
+    #[test]
+    fn invalid_key_origin_keys() {
+        // A BIP32 derivation keyed by something that is not a public key,
+        // smuggled in as an unknown pair
+        let mut psbt = PartiallySignedTransaction::from_unsigned_tx(unsigned_tx()).unwrap();
+        psbt.inputs[0].unknown.insert(
+            raw::Key {
+                type_value: 0x06,
+                key: vec![2; 20],
+            },
+            vec![0; 4],
+        );
+        match deserialize::<PartiallySignedTransaction>(&serialize(&psbt)) {
+            Err(encode::Error::Psbt(Error::InvalidKey(_))) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        // A taproot internal key with key data
+        let mut psbt = PartiallySignedTransaction::from_unsigned_tx(unsigned_tx()).unwrap();
+        psbt.inputs[0].unknown.insert(
+            raw::Key {
+                type_value: 0x17,
+                key: vec![1],
+            },
+            vec![0; 32],
+        );
+        match deserialize::<PartiallySignedTransaction>(&serialize(&psbt)) {
+            Err(encode::Error::Psbt(Error::InvalidKey(_))) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        // A leaf script keyed by a control block with a partial path
+        let mut psbt = PartiallySignedTransaction::from_unsigned_tx(unsigned_tx()).unwrap();
+        psbt.inputs[0].unknown.insert(
+            raw::Key {
+                type_value: 0x15,
+                key: vec![0xc0; 34],
+            },
+            vec![0x51, 0xc0],
+        );
+        match deserialize::<PartiallySignedTransaction>(&serialize(&psbt)) {
+            Err(encode::Error::Psbt(Error::InvalidKey(_))) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn tap_script_path() {
+        let keys = [[1; 32], [2; 32], [3; 32]];
+        // 2-of-3 multisig
+        let multisig = Builder::new()
+            .push_slice(&keys[0])
+            .push_opcode(opcodes::all::OP_CHECKSIG)
+            .push_slice(&keys[1])
+            .push_opcode(opcodes::all::OP_RETURN_186)
+            .push_slice(&keys[2])
+            .push_opcode(opcodes::all::OP_RETURN_186)
+            .push_int(2)
+            .push_opcode(opcodes::all::OP_NUMEQUAL)
+            .into_script();
+        let single = Builder::new()
+            .push_slice(&keys[2])
+            .push_opcode(opcodes::all::OP_CHECKSIG)
+            .into_script();
+        let multisig_hash = multisig.tapleaf_hash(0xc0);
+        let single_hash = single.tapleaf_hash(0xc0);
+        let multisig_control = vec![0xc0; 65];
+        let mut single_control = vec![0xc1; 65];
+        single_control.extend_from_slice(&[4; 32]);
+
+        let mut psbt = PartiallySignedTransaction::from_unsigned_tx(unsigned_tx()).unwrap();
+        psbt.inputs[0]
+            .tap_scripts
+            .insert(multisig_control.clone(), (multisig.clone(), 0xc0));
+        psbt.inputs[0]
+            .tap_scripts
+            .insert(single_control.clone(), (single.clone(), 0xc0));
+        psbt.outputs[0].tap_tree = Some(vec![
+            (1, 0xc0, multisig.clone()),
+            (2, 0xc0, single.clone()),
+            (2, 0xc0, Script::new()),
+        ]);
+        let de: PartiallySignedTransaction = deserialize(&serialize(&psbt)).unwrap();
+        assert_eq!(de, psbt);
+
+        match psbt.finalize_tap_script_path(0) {
+            Err(Error::NoSatisfyingLeaf(0)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        let sign = |psbt: &mut PartiallySignedTransaction,
+                    key: usize,
+                    leaf_hash: TapLeafHash,
+                    len: usize| {
+            psbt.inputs[0]
+                .tap_script_sigs
+                .insert((keys[key], leaf_hash), vec![key as u8; len]);
+        };
+        sign(&mut psbt, 0, multisig_hash, 64);
+        assert_eq!(psbt.inputs[0].tap_script_path_witness(), None);
+
+        // The signatures are consumed in key order from the top of the stack
+        sign(&mut psbt, 1, multisig_hash, 65);
+        let witness = psbt.inputs[0].tap_script_path_witness().unwrap();
+        assert_eq!(
+            witness,
+            vec![
+                vec![],
+                vec![1; 65],
+                vec![0; 64],
+                multisig.to_bytes(),
+                multisig_control.clone(),
+            ]
+        );
+
+        // The shortest signatures are used
+        sign(&mut psbt, 2, multisig_hash, 64);
+        let witness = psbt.inputs[0].tap_script_path_witness().unwrap();
+        assert_eq!(&witness[..3], &[vec![2; 64], vec![], vec![0; 64]]);
+
+        // A single signature is cheaper, even with a longer control block
+        sign(&mut psbt, 2, single_hash, 64);
+        psbt.finalize_tap_script_path(0).unwrap();
+        let input = &psbt.inputs[0];
+        assert!(input.tap_scripts.is_empty());
+        assert_eq!(
+            input.final_script_witness,
+            Some(vec![vec![2; 64], single.to_bytes(), single_control])
+        );
+        assert_eq!(input.final_script_sig, None);
+
+        // Trees must have leaves no deeper than 128
+        assert!(Vec::<(u8, u8, Script)>::deserialize(&[]).is_err());
+        assert!(Vec::<(u8, u8, Script)>::deserialize(&[129, 0xc0, 0]).is_err());
+        assert!(Vec::<(u8, u8, Script)>::deserialize(&[128, 0xc0, 1]).is_err());
+    }
+
+    #[test]
+    fn decode_hash_preimages() {
+        // Each preimage key as an unknown pair, serialized as is
+        let secret = b"preimage".to_vec();
+        let keys = vec![
+            (0x0a, ripemd160::Hash::hash(&secret)[..].to_vec()),
+            (0x0b, sha256::Hash::hash(&secret)[..].to_vec()),
+            (0x0c, hash160::Hash::hash(&secret)[..].to_vec()),
+            (0x0d, sha256d::Hash::hash(&secret)[..].to_vec()),
+        ];
+        let with_pairs = |pairs: &[(u8, Vec<u8>, Vec<u8>)]| {
+            let mut psbt = PartiallySignedTransaction::from_unsigned_tx(unsigned_tx()).unwrap();
+            for &(type_value, ref key, ref value) in pairs {
+                let key = raw::Key {
+                    type_value,
+                    key: key.clone(),
+                };
+                psbt.inputs[0].unknown.insert(key, value.clone());
+            }
+            serialize(&psbt)
+        };
+
+        let pairs: Vec<_> = keys
+            .iter()
+            .map(|&(type_value, ref key)| (type_value, key.clone(), secret.clone()))
+            .collect();
+        let psbt: PartiallySignedTransaction = deserialize(&with_pairs(&pairs)).unwrap();
+        let input = &psbt.inputs[0];
+        assert!(input.unknown.is_empty());
+        assert_eq!(input.ripemd160_preimages.len(), 1);
+        assert_eq!(input.sha256_preimages.len(), 1);
+        assert_eq!(input.hash160_preimages.len(), 1);
+        assert_eq!(input.hash256_preimages.len(), 1);
+        let ops = [
+            opcodes::all::OP_RIPEMD160,
+            opcodes::all::OP_SHA256,
+            opcodes::all::OP_HASH160,
+            opcodes::all::OP_HASH256,
+        ];
+        for (&op, &(_, ref key)) in ops.iter().zip(keys.iter()) {
+            assert_eq!(input.preimage(op, key), Some(&secret[..]));
+        }
+
+        // A preimage must hash to its key, of the length of the hash
+        for &(type_value, ref key) in keys.iter() {
+            let mut short = key.clone();
+            short.pop();
+            for &(ref key, ref value) in
+                &[(key.clone(), b"other".to_vec()), (short, secret.clone())]
+            {
+                let data = with_pairs(&[(type_value, key.clone(), value.clone())]);
+                match deserialize::<PartiallySignedTransaction>(&data) {
+                    Err(encode::Error::Psbt(Error::InvalidKey(_))) => {}
+                    other => panic!("unexpected result: {:?}", other),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn hash_preimages() {
+        let mut psbt = PartiallySignedTransaction::from_unsigned_tx(unsigned_tx()).unwrap();
+        let secret = vec![7; 32];
+        let sha256 = psbt.inputs[0].add_sha256_preimage(secret.clone());
+        let hash160 = psbt.inputs[0].add_hash160_preimage(b"short".to_vec());
+        psbt.inputs[0].add_ripemd160_preimage(vec![1]);
+        psbt.inputs[0].add_hash256_preimage(vec![2]);
+        let de: PartiallySignedTransaction = deserialize(&serialize(&psbt)).unwrap();
+        assert_eq!(de, psbt);
+        assert_eq!(
+            psbt.inputs[0].preimage(opcodes::all::OP_SHA256, &sha256[..]),
+            Some(&secret[..])
+        );
+        assert_eq!(
+            psbt.inputs[0].preimage(opcodes::all::OP_HASH256, &sha256[..]),
+            None
+        );
+
+        // An HTLC claim leaf, as a payment hash and the receiver's key
+        let key = [1; 32];
+        let claim = Builder::new()
+            .push_opcode(opcodes::all::OP_SIZE)
+            .push_int(32)
+            .push_opcode(opcodes::all::OP_EQUALVERIFY)
+            .push_opcode(opcodes::all::OP_SHA256)
+            .push_slice(&sha256[..])
+            .push_opcode(opcodes::all::OP_EQUALVERIFY)
+            .push_slice(&key)
+            .push_opcode(opcodes::all::OP_CHECKSIG)
+            .into_script();
+        let control = vec![0xc0; 33];
+        let mut psbt = PartiallySignedTransaction::from_unsigned_tx(unsigned_tx()).unwrap();
+        psbt.inputs[0]
+            .tap_scripts
+            .insert(control.clone(), (claim.clone(), 0xc0));
+        psbt.inputs[0]
+            .tap_script_sigs
+            .insert((key, claim.tapleaf_hash(0xc0)), vec![3; 64]);
+        assert_eq!(psbt.inputs[0].tap_script_path_witness(), None);
+
+        // The size check rules out preimages of the wrong length
+        psbt.inputs[0].sha256_preimages.insert(sha256, vec![7; 31]);
+        assert_eq!(psbt.inputs[0].tap_script_path_witness(), None);
+
+        // The preimage goes on top of the signature
+        psbt.inputs[0].add_sha256_preimage(secret.clone());
+        psbt.finalize_tap_script_path(0).unwrap();
+        let input = &psbt.inputs[0];
+        assert_eq!(
+            input.final_script_witness,
+            Some(vec![vec![3; 64], secret.clone(), claim.to_bytes(), control])
+        );
+        assert!(input.sha256_preimages.is_empty());
+
+        // Locks without a size check take preimages of any length, and are
+        // satisfied from the top of the stack in script order
+        let double = Builder::new()
+            .push_opcode(opcodes::all::OP_HASH160)
+            .push_slice(&hash160[..])
+            .push_opcode(opcodes::all::OP_EQUALVERIFY)
+            .push_opcode(opcodes::all::OP_SHA256)
+            .push_slice(&sha256[..])
+            .push_opcode(opcodes::all::OP_EQUALVERIFY)
+            .push_slice(&key)
+            .push_opcode(opcodes::all::OP_CHECKSIG)
+            .into_script();
+        let mut psbt = PartiallySignedTransaction::from_unsigned_tx(unsigned_tx()).unwrap();
+        psbt.inputs[0]
+            .tap_scripts
+            .insert(vec![0xc0; 33], (double.clone(), 0xc0));
+        psbt.inputs[0]
+            .tap_script_sigs
+            .insert((key, double.tapleaf_hash(0xc0)), vec![3; 64]);
+        psbt.inputs[0].add_sha256_preimage(secret.clone());
+        assert_eq!(psbt.inputs[0].tap_script_path_witness(), None);
+        psbt.inputs[0].add_hash160_preimage(b"short".to_vec());
+        let witness = psbt.inputs[0].tap_script_path_witness().unwrap();
+        assert_eq!(&witness[..3], &[vec![3; 64], secret, b"short".to_vec()]);
+    }
+
+    #[test]
+    fn merge_unrelated() {
+        let party = |n: u8, value: u64| {
+            let tx = Transaction {
+                version: 2,
+                lock_time: 0,
+                input: vec![TxIn {
+                    previous_output: OutPoint::new(Txid::hash(&[n]), 0),
+                    ..Default::default()
+                }],
+                output: vec![TxOut {
+                    value,
+                    script_pubkey: Script::from(vec![n]),
+                }],
+            };
+            PartiallySignedTransaction::from_unsigned_tx(tx).unwrap()
+        };
+        let merge = |a: &PartiallySignedTransaction, b: &PartiallySignedTransaction| {
+            PartiallySignedTransaction::merge_unrelated(&[a.clone(), b.clone()])
+        };
+        let a = party(1, 10_000);
+        let mut b = party(2, 20_000);
+        b.inputs[0].witness_utxo = Some(TxOut::default());
+
+        let merged = merge(&a, &b).unwrap();
+        let tx = &merged.global.unsigned_tx;
+        assert_eq!(tx.input[0], a.global.unsigned_tx.input[0]);
+        assert_eq!(tx.input[1], b.global.unsigned_tx.input[0]);
+        assert_eq!(tx.output[1].value, 20_000);
+        assert_eq!(merged.inputs[1], b.inputs[0]);
+        assert_eq!(merged.outputs.len(), 2);
+
+        match PartiallySignedTransaction::merge_unrelated(&[]) {
+            Err(Error::MustHaveUnsignedTx) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        match merge(&a, &party(1, 5_000)) {
+            Err(Error::DuplicateOutpoint(outpoint)) => {
+                assert_eq!(outpoint, a.global.unsigned_tx.input[0].previous_output)
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        let mut late = party(3, 5_000);
+        late.global.unsigned_tx.lock_time = 1;
+        match merge(&a, &late) {
+            Err(Error::UnsignedTxMismatch) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+
+        // Global pairs must agree
+        let key = raw::Key {
+            type_value: 0xfc,
+            key: vec![1],
+        };
+        let mut a_pair = a.clone();
+        a_pair.global.unknown.insert(key.clone(), vec![1]);
+        let mut b_pair = b.clone();
+        b_pair.global.unknown.insert(key.clone(), vec![1]);
+        assert_eq!(merge(&a_pair, &b_pair).unwrap().global.unknown.len(), 1);
+        b_pair.global.unknown.insert(key, vec![2]);
+        match merge(&a_pair, &b_pair) {
+            Err(Error::DuplicateKey(_)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+
+        // Signatures must survive the merge
+        let signed = |psbt: &PartiallySignedTransaction, sighash: u8| {
+            let mut psbt = psbt.clone();
+            psbt.inputs[0]
+                .partial_sigs
+                .insert(vec![2; 33], vec![0x30, sighash]);
+            psbt
+        };
+        let incompatible = |result: Result<PartiallySignedTransaction, Error>| match result {
+            Err(Error::IncompatibleSigHash(index)) => index,
+            other => panic!("unexpected {:?}", other),
+        };
+        assert_eq!(incompatible(merge(&signed(&a, 0x01), &b)), 0);
+        assert_eq!(incompatible(merge(&a, &signed(&b, 0x81))), 1);
+        assert!(merge(&signed(&a, 0x82), &signed(&b, 0x82)).is_ok());
+        assert!(merge(&signed(&a, 0x83), &signed(&b, 0x83)).is_ok());
+        let mut two_outputs = a.clone();
+        two_outputs.global.unsigned_tx.output.push(TxOut::default());
+        two_outputs.outputs.push(Default::default());
+        assert_eq!(incompatible(merge(&two_outputs, &signed(&b, 0x83))), 1);
+        let lone = PartiallySignedTransaction::merge_unrelated(&[signed(&a, 0x01)]).unwrap();
+        assert_eq!(lone, signed(&a, 0x01));
+
+        let mut schnorr = b.clone();
+        schnorr.inputs[0].tap_key_sig = Some(vec![0; 64]);
+        assert_eq!(incompatible(merge(&a, &schnorr)), 1);
+        schnorr.inputs[0].tap_key_sig = Some(vec![0x82; 65]);
+        assert!(merge(&a, &schnorr).is_ok());
+        let mut finalized = b.clone();
+        finalized
+            .finalize_input(0, Script::new(), vec![vec![1]])
+            .unwrap();
+        assert_eq!(incompatible(merge(&a, &finalized)), 1);
+    }
+}
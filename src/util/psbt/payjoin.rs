@@ -0,0 +1,745 @@
+//! Payjoin
+//!
+//! BIP78 lets the receiver of a payment add inputs of its own to the
+//! sender's transaction, so that the common-input-ownership heuristic no
+//! longer holds. The sender sends a finalized original PSBT, the receiver
+//! answers with a proposal spending some of its coins too, and the sender
+//! checks the proposal before signing it in place of the original.
+//!
+//! Both sides have to check what the other did. The receiver builds its
+//! proposal with a `Receiver`, which checks the original and keeps its
+//! changes within the bounds the sender asked for in its `Params`. The
+//! sender checks the proposal with `check_proposal`, which makes sure that
+//! the receiver took nothing from it but the fee contribution it allowed.
+
+use std::{error, fmt};
+
+use blockdata::script::{Builder, Script};
+use blockdata::transaction::{InputType, InputWeightPrediction, OutPoint, TxIn};
+use error::{ErrorCode, ErrorKind};
+use util::amount::Amount;
+use util::fee_rate::FeeRate;
+use util::psbt::inspect::MapDiff;
+use util::psbt::{self, Input, PartiallySignedTransaction};
+use util::rng::Rng;
+use util::weight::Weight;
+
+/// Type: Non-Witness UTXO PSBT_IN_NON_WITNESS_UTXO = 0x00
+const PSBT_IN_NON_WITNESS_UTXO: u8 = 0x00;
+/// Type: Witness UTXO PSBT_IN_WITNESS_UTXO = 0x01
+const PSBT_IN_WITNESS_UTXO: u8 = 0x01;
+
+/// Ways a payjoin might fail
+#[derive(Debug)]
+pub enum Error {
+    /// The PSBT itself is invalid
+    Psbt(psbt::Error),
+    /// The input at this index of the original PSBT is not finalized.
+    OriginalInputNotFinalized(usize),
+    /// The inputs do not all spend the same type of output.
+    MixedInputTypes,
+    /// The original PSBT has no output paying the receiver.
+    NoPayeeOutput,
+    /// The payee output was replaced or reduced although the sender
+    /// disabled output substitution.
+    OutputSubstitutionDisabled,
+    /// The receiver's input at this index of the proposal is not
+    /// finalized.
+    ReceiverInputNotFinalized(usize),
+    /// The receiver's input at this index of the proposal has a sequence
+    /// number other than the sender's.
+    ReceiverInputSequence(usize),
+    /// The sender's input at this index of the original is not in the
+    /// proposal.
+    SenderInputMissing(usize),
+    /// The sender's input at this index of the proposal has a different
+    /// sequence number, or carries more than its UTXO.
+    SenderInputModified(usize),
+    /// The version or lock time of the transaction changed.
+    TxModified,
+    /// The output at this index of the proposal carries key paths.
+    OutputKeypaths(usize),
+    /// The sender's output at this index of the original is not in the
+    /// proposal, or its value changed other than by the fee contribution.
+    SenderOutputChanged(usize),
+    /// The sender pays more of the fee than it allowed, or pays more than
+    /// the fee grew by.
+    FeeContributionTooHigh,
+    /// The proposal pays less than the minimum fee rate the sender asked
+    /// for.
+    FeeRateTooLow,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Psbt(ref e) => write!(f, "psbt: {}", e),
+            Error::OriginalInputNotFinalized(i) => {
+                write!(f, "input {} of the original is not finalized", i)
+            }
+            Error::ReceiverInputNotFinalized(i) => {
+                write!(f, "receiver input {} is not finalized", i)
+            }
+            Error::ReceiverInputSequence(i) => {
+                write!(f, "receiver input {} has a different sequence number", i)
+            }
+            Error::SenderInputMissing(i) => {
+                write!(f, "sender input {} is missing from the proposal", i)
+            }
+            Error::SenderInputModified(i) => write!(f, "sender input {} was modified", i),
+            Error::OutputKeypaths(i) => write!(f, "output {} carries key paths", i),
+            Error::SenderOutputChanged(i) => write!(f, "sender output {} was changed", i),
+            Error::MixedInputTypes
+            | Error::NoPayeeOutput
+            | Error::OutputSubstitutionDisabled
+            | Error::TxModified
+            | Error::FeeContributionTooHigh
+            | Error::FeeRateTooLow => f.write_str(error::Error::description(self)),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::Psbt(ref e) => Some(e),
+            _ => None,
+        }
+    }
+
+    fn description(&self) -> &str {
+        match *self {
+            Error::Psbt(..) => "psbt error",
+            Error::OriginalInputNotFinalized(..) => "original input not finalized",
+            Error::MixedInputTypes => "inputs spend different types of outputs",
+            Error::NoPayeeOutput => "no output pays the receiver",
+            Error::OutputSubstitutionDisabled => "output substitution is disabled",
+            Error::ReceiverInputNotFinalized(..) => "receiver input not finalized",
+            Error::ReceiverInputSequence(..) => "receiver input sequence mismatch",
+            Error::SenderInputMissing(..) => "sender input missing",
+            Error::SenderInputModified(..) => "sender input modified",
+            Error::TxModified => "transaction version or lock time changed",
+            Error::OutputKeypaths(..) => "output carries key paths",
+            Error::SenderOutputChanged(..) => "sender output changed",
+            Error::FeeContributionTooHigh => "fee contribution too high",
+            Error::FeeRateTooLow => "fee rate too low",
+        }
+    }
+}
+
+impl ErrorCode for Error {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Payjoin
+    }
+
+    fn number(&self) -> u32 {
+        match *self {
+            Error::Psbt(..) => 1,
+            Error::OriginalInputNotFinalized(..) => 2,
+            Error::MixedInputTypes => 3,
+            Error::NoPayeeOutput => 4,
+            Error::OutputSubstitutionDisabled => 5,
+            Error::ReceiverInputNotFinalized(..) => 6,
+            Error::ReceiverInputSequence(..) => 7,
+            Error::SenderInputMissing(..) => 8,
+            Error::SenderInputModified(..) => 9,
+            Error::TxModified => 10,
+            Error::OutputKeypaths(..) => 11,
+            Error::SenderOutputChanged(..) => 12,
+            Error::FeeContributionTooHigh => 13,
+            Error::FeeRateTooLow => 14,
+        }
+    }
+}
+
+#[doc(hidden)]
+impl From<psbt::Error> for Error {
+    fn from(e: psbt::Error) -> Error {
+        Error::Psbt(e)
+    }
+}
+
+/// What the sender allows the receiver to do, as given in the query
+/// parameters of a BIP78 request
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct Params {
+    /// The index of the sender's output, usually its change, which may be
+    /// reduced to pay for the receiver's inputs
+    pub additional_fee_output_index: Option<usize>,
+    /// The most the sender's output may be reduced by
+    pub max_additional_fee_contribution: Amount,
+    /// Whether the receiver must keep the payee output as it is, rather
+    /// than pay itself to another script
+    pub disable_output_substitution: bool,
+    /// The lowest fee rate the sender accepts for the proposal
+    pub min_fee_rate: FeeRate,
+}
+
+/// Builds a payjoin proposal from the sender's original PSBT
+#[derive(Clone, Debug)]
+pub struct Receiver {
+    original: PartiallySignedTransaction,
+    proposal: PartiallySignedTransaction,
+    params: Params,
+    payee_output: usize,
+    added_weight: usize,
+}
+
+impl Receiver {
+    /// Checks the original PSBT, which pays the receiver to `payee`: its
+    /// inputs must all be finalized, carry their UTXO and spend the same
+    /// type of output.
+    pub fn new(
+        original: PartiallySignedTransaction,
+        params: Params,
+        payee: &Script,
+    ) -> Result<Receiver, Error> {
+        if original.inputs.is_empty() {
+            return Err(psbt::Error::InputIndexOutOfBounds(0).into());
+        }
+        original.validate()?;
+        original.fee()?;
+        if let Some(index) = original.inputs.iter().position(|i| !i.is_finalized()) {
+            return Err(Error::OriginalInputNotFinalized(index));
+        }
+        let sender_type = input_type(&original, 0);
+        if (1..original.inputs.len()).any(|i| input_type(&original, i) != sender_type) {
+            return Err(Error::MixedInputTypes);
+        }
+        let payee_output = original
+            .global
+            .unsigned_tx
+            .output
+            .iter()
+            .position(|txout| txout.script_pubkey == *payee)
+            .ok_or(Error::NoPayeeOutput)?;
+
+        Ok(Receiver {
+            proposal: original.clone(),
+            original,
+            params,
+            payee_output,
+            added_weight: 0,
+        })
+    }
+
+    /// The index of the output paying the receiver
+    pub fn payee_output(&self) -> usize {
+        self.payee_output
+    }
+
+    /// Adds an input of the receiver spending `previous_output`, at a
+    /// position drawn from `rng`, and adds the value it spends to the payee
+    /// output. The input must carry its UTXO and spend the same type of
+    /// output as the sender's inputs, and `weight` predicts its weight once
+    /// signed, to work out the fee it costs.
+    pub fn contribute_input<R: Rng>(
+        &mut self,
+        previous_output: OutPoint,
+        input: Input,
+        weight: InputWeightPrediction,
+        mut rng: R,
+    ) -> Result<(), Error> {
+        let tx = &mut self.proposal.global.unsigned_tx;
+        let position = rng.next_below(tx.input.len() as u64 + 1) as usize;
+        tx.input.insert(
+            position,
+            TxIn {
+                previous_output,
+                sequence: self.original.global.unsigned_tx.input[0].sequence,
+                ..Default::default()
+            },
+        );
+        self.proposal.inputs.insert(position, input);
+
+        let value = match self.proposal.spent_utxo(position) {
+            Some(utxo) if input_type(&self.proposal, position) == input_type(&self.original, 0) => {
+                Ok(utxo.value)
+            }
+            Some(_) => Err(Error::MixedInputTypes),
+            None => Err(psbt::Error::MissingUtxo(position).into()),
+        };
+        let payee = &mut self.proposal.global.unsigned_tx.output[self.payee_output];
+        let value = value.and_then(|value| {
+            payee
+                .value
+                .checked_add(value)
+                .ok_or_else(|| psbt::Error::FeeOutOfRange.into())
+        });
+        match value {
+            Ok(value) => {
+                payee.value = value;
+                self.added_weight += weight.weight();
+                Ok(())
+            }
+            Err(e) => {
+                self.proposal.global.unsigned_tx.input.remove(position);
+                self.proposal.inputs.remove(position);
+                Err(e)
+            }
+        }
+    }
+
+    /// Pays the payee output to `script_pubkey` instead, unless the sender
+    /// disabled output substitution
+    pub fn substitute_output(&mut self, script_pubkey: Script) -> Result<(), Error> {
+        if self.params.disable_output_substitution {
+            return Err(Error::OutputSubstitutionDisabled);
+        }
+        self.proposal.global.unsigned_tx.output[self.payee_output].script_pubkey = script_pubkey;
+        self.proposal.outputs[self.payee_output] = Default::default();
+        Ok(())
+    }
+
+    /// The proposal, for the receiver to sign and finalize its inputs.
+    ///
+    /// The fee the receiver's inputs cost at the original fee rate is taken
+    /// from the sender's additional fee output, up to the maximum the sender
+    /// allowed, and the rest from the payee output. The sender's inputs are
+    /// cleared, as their signatures no longer apply, and so are the key
+    /// paths of the outputs.
+    pub fn into_proposal(mut self) -> Result<PartiallySignedTransaction, Error> {
+        let original_weight = self.original.clone().extract_tx().get_weight();
+        let fee_rate = self
+            .original
+            .fee_rate(Weight::from_wu(original_weight as u64))?;
+        let mut fee = fee_rate
+            .fee_wu(Weight::from_wu(self.added_weight as u64))
+            .ok_or(psbt::Error::FeeOutOfRange)?;
+
+        let outputs = &mut self.proposal.global.unsigned_tx.output;
+        if let Some(index) = self.params.additional_fee_output_index {
+            if index != self.payee_output && index < outputs.len() {
+                let contribution = fee
+                    .min(self.params.max_additional_fee_contribution)
+                    .min(Amount::from_sat(outputs[index].value));
+                outputs[index].value -= contribution.as_sat();
+                fee -= contribution;
+            }
+        }
+        let payee = &mut outputs[self.payee_output];
+        payee.value = payee
+            .value
+            .checked_sub(fee.as_sat())
+            .ok_or(psbt::Error::NegativeFee)?;
+
+        for txin in &self.original.global.unsigned_tx.input {
+            let position = self
+                .proposal
+                .global
+                .unsigned_tx
+                .input
+                .iter()
+                .position(|proposed| proposed.previous_output == txin.previous_output)
+                .expect("sender inputs are kept");
+            self.proposal.inputs[position] = Input::default();
+        }
+        for output in &mut self.proposal.outputs {
+            output.bip32_derivation.clear();
+            output.tap_key_origins.clear();
+        }
+        Ok(self.proposal)
+    }
+}
+
+/// Checks the receiver's `proposal` against the `original` PSBT, which pays
+/// the receiver to `payee`, and returns it ready for the sender to sign.
+///
+/// The sender's inputs and outputs must all be in the proposal, unchanged
+/// except for the additional fee output, which may only be reduced by what
+/// `params` allows and by no more than the fee grew by. The receiver's
+/// inputs must be finalized, spend the same type of output as the sender's
+/// and have the same sequence number. The sender's inputs get back what
+/// they carried in the original, except their final scripts.
+pub fn check_proposal(
+    original: &PartiallySignedTransaction,
+    mut proposal: PartiallySignedTransaction,
+    params: &Params,
+    payee: &Script,
+) -> Result<PartiallySignedTransaction, Error> {
+    let original_tx = &original.global.unsigned_tx;
+    if original_tx.input.is_empty() {
+        return Err(psbt::Error::InputIndexOutOfBounds(0).into());
+    }
+    if proposal.global.unsigned_tx.version != original_tx.version
+        || proposal.global.unsigned_tx.lock_time != original_tx.lock_time
+    {
+        return Err(Error::TxModified);
+    }
+    if proposal.inputs.len() != proposal.global.unsigned_tx.input.len()
+        || proposal.outputs.len() != proposal.global.unsigned_tx.output.len()
+    {
+        return Err(Error::TxModified);
+    }
+    proposal.validate()?;
+
+    // The sender's inputs, which may carry nothing but their UTXO
+    let mut is_sender_input = vec![false; proposal.inputs.len()];
+    let mut signed = proposal.clone();
+    for (index, txin) in original_tx.input.iter().enumerate() {
+        let position = proposal
+            .global
+            .unsigned_tx
+            .input
+            .iter()
+            .position(|proposed| proposed.previous_output == txin.previous_output)
+            .ok_or(Error::SenderInputMissing(index))?;
+        let diff = MapDiff::between(None, Some(&proposal.inputs[position]));
+        let utxo_only = diff.added.iter().all(|key| {
+            key.type_value == PSBT_IN_NON_WITNESS_UTXO || key.type_value == PSBT_IN_WITNESS_UTXO
+        });
+        if proposal.global.unsigned_tx.input[position].sequence != txin.sequence || !utxo_only {
+            return Err(Error::SenderInputModified(position));
+        }
+        is_sender_input[position] = true;
+        signed.inputs[position] = original.inputs[index].clone();
+        proposal.inputs[position] = Input {
+            final_script_sig: None,
+            final_script_witness: None,
+            ..original.inputs[index].clone()
+        };
+    }
+
+    let sender_type = input_type(original, 0);
+    let sequence = original_tx.input[0].sequence;
+    for (position, is_sender) in is_sender_input.into_iter().enumerate() {
+        if is_sender {
+            continue;
+        }
+        if !proposal.inputs[position].is_finalized() {
+            return Err(Error::ReceiverInputNotFinalized(position));
+        }
+        if proposal.global.unsigned_tx.input[position].sequence != sequence {
+            return Err(Error::ReceiverInputSequence(position));
+        }
+        if proposal.spent_utxo(position).is_none() {
+            return Err(psbt::Error::MissingUtxo(position).into());
+        }
+        if input_type(&proposal, position) != sender_type {
+            return Err(Error::MixedInputTypes);
+        }
+    }
+
+    for (index, output) in proposal.outputs.iter().enumerate() {
+        if !output.bip32_derivation.is_empty() || !output.tap_key_origins.is_empty() {
+            return Err(Error::OutputKeypaths(index));
+        }
+    }
+
+    // Every output of the original must be matched by its own output of
+    // the proposal
+    let proposed = &proposal.global.unsigned_tx.output;
+    let mut matched = vec![false; proposed.len()];
+    let mut contribution = Amount::ZERO;
+    for (index, txout) in original_tx.output.iter().enumerate() {
+        let is_payee = txout.script_pubkey == *payee;
+        let position = (0..proposed.len())
+            .find(|&i| !matched[i] && proposed[i].script_pubkey == txout.script_pubkey);
+        let position = match position {
+            Some(position) => position,
+            None if is_payee && !params.disable_output_substitution => continue,
+            None if is_payee => return Err(Error::OutputSubstitutionDisabled),
+            None => return Err(Error::SenderOutputChanged(index)),
+        };
+        matched[position] = true;
+        let value = proposed[position].value;
+        if is_payee {
+            if params.disable_output_substitution && value < txout.value {
+                return Err(Error::OutputSubstitutionDisabled);
+            }
+        } else if Some(index) == params.additional_fee_output_index && value < txout.value {
+            contribution = Amount::from_sat(txout.value - value);
+        } else if value != txout.value {
+            return Err(Error::SenderOutputChanged(index));
+        }
+    }
+
+    let original_fee = original.fee()?;
+    let fee = proposal.fee()?;
+    let fee_increase = fee.checked_sub(original_fee).unwrap_or(Amount::ZERO);
+    if contribution > params.max_additional_fee_contribution || contribution > fee_increase {
+        return Err(Error::FeeContributionTooHigh);
+    }
+
+    // The sender's signatures will be about as large as in the original
+    let weight = signed.extract_tx().get_weight();
+    if proposal.fee_rate(Weight::from_wu(weight as u64))? < params.min_fee_rate {
+        return Err(Error::FeeRateTooLow);
+    }
+
+    Ok(proposal)
+}
+
+/// The type of output the input at `index` spends, telling nested segwit
+/// apart by its final `script_sig` or else its redeem script
+fn input_type(psbt: &PartiallySignedTransaction, index: usize) -> Option<InputType> {
+    let spent = psbt.spent_utxo(index)?;
+    let input = &psbt.inputs[index];
+    let script_sig = if let Some(ref script_sig) = input.final_script_sig {
+        script_sig.clone()
+    } else if let Some(ref redeem_script) = input.redeem_script {
+        Builder::new()
+            .push_slice(redeem_script.as_bytes())
+            .into_script()
+    } else {
+        Script::new()
+    };
+    let txin = TxIn {
+        script_sig,
+        ..Default::default()
+    };
+    Some(txin.input_type(&spent.script_pubkey))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use blockdata::transaction::{Transaction, TxOut};
+    use hash_types::Txid;
+    use hashes::Hash;
+    use std::str::FromStr;
+    use util::bip32::KeySource;
+    use util::rng::SeededRng;
+
+    fn p2wpkh(n: u8) -> Script {
+        let mut script = vec![0x00, 0x14];
+        script.extend_from_slice(&[n; 20]);
+        Script::from(script)
+    }
+
+    fn witness() -> Vec<Vec<u8>> {
+        vec![vec![0x30; 71], vec![2; 33]]
+    }
+
+    fn params() -> Params {
+        Params {
+            additional_fee_output_index: Some(1),
+            max_additional_fee_contribution: Amount::from_sat(300),
+            ..Default::default()
+        }
+    }
+
+    /// Pays 50_000 to `p2wpkh(2)` with 49_000 change and a 1_000 fee
+    fn original() -> PartiallySignedTransaction {
+        let tx = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid::hash(&[1]), 0),
+                sequence: 0xfffffffd,
+                ..Default::default()
+            }],
+            output: vec![
+                TxOut {
+                    value: 50_000,
+                    script_pubkey: p2wpkh(2),
+                },
+                TxOut {
+                    value: 49_000,
+                    script_pubkey: p2wpkh(3),
+                },
+            ],
+        };
+        let mut psbt = PartiallySignedTransaction::from_unsigned_tx(tx).unwrap();
+        psbt.inputs[0].witness_utxo = Some(TxOut {
+            value: 100_000,
+            script_pubkey: p2wpkh(1),
+        });
+        psbt.inputs[0].final_script_witness = Some(witness());
+        psbt
+    }
+
+    fn receiver_input(script_pubkey: Script) -> Input {
+        Input {
+            witness_utxo: Some(TxOut {
+                value: 30_000,
+                script_pubkey,
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn receiver_outpoint() -> OutPoint {
+        OutPoint::new(Txid::hash(&[4]), 1)
+    }
+
+    /// A proposal with one receiver input, signed by the receiver
+    fn proposal() -> PartiallySignedTransaction {
+        let mut receiver = Receiver::new(original(), params(), &p2wpkh(2)).unwrap();
+        receiver
+            .contribute_input(
+                receiver_outpoint(),
+                receiver_input(p2wpkh(4)),
+                InputWeightPrediction::P2WPKH_MAX,
+                SeededRng::new(1),
+            )
+            .unwrap();
+        let mut proposal = receiver.into_proposal().unwrap();
+        let index = receiver_index(&proposal);
+        proposal.inputs[index].final_script_witness = Some(witness());
+        proposal
+    }
+
+    fn receiver_index(psbt: &PartiallySignedTransaction) -> usize {
+        psbt.global
+            .unsigned_tx
+            .input
+            .iter()
+            .position(|txin| txin.previous_output == receiver_outpoint())
+            .unwrap()
+    }
+
+    #[test]
+    fn payjoin() {
+        let proposal = proposal();
+        let tx = &proposal.global.unsigned_tx;
+        assert_eq!(tx.input.len(), 2);
+        let sender = 1 - receiver_index(&proposal);
+        assert_eq!(
+            tx.input[sender].previous_output,
+            original().global.unsigned_tx.input[0].previous_output
+        );
+        assert_eq!(proposal.inputs[sender], Input::default());
+        assert_eq!(tx.input[1 - sender].sequence, 0xfffffffd);
+
+        // The receiver's input costs 485 at the original fee rate, of which
+        // the sender pays its maximum of 300
+        assert_eq!(tx.output[0].value, 50_000 + 30_000 - 185);
+        assert_eq!(tx.output[1].value, 49_000 - 300);
+        assert_eq!(
+            proposal.clone().fee().unwrap_err().to_string(),
+            format!("input {} has no UTXO", sender)
+        );
+
+        let checked = check_proposal(&original(), proposal, &params(), &p2wpkh(2)).unwrap();
+        assert_eq!(checked.fee().unwrap(), Amount::from_sat(1_485));
+        assert_eq!(
+            checked.inputs[sender].witness_utxo,
+            original().inputs[0].witness_utxo
+        );
+        assert!(!checked.inputs[sender].is_finalized());
+        assert!(checked.inputs[1 - sender].is_finalized());
+    }
+
+    #[test]
+    fn receiver_checks() {
+        let mut original = original();
+        original.inputs[0].final_script_witness = None;
+        match Receiver::new(original, params(), &p2wpkh(2)) {
+            Err(Error::OriginalInputNotFinalized(0)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        match Receiver::new(self::original(), params(), &p2wpkh(9)) {
+            Err(Error::NoPayeeOutput) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let mut receiver = Receiver::new(self::original(), params(), &p2wpkh(2)).unwrap();
+        assert_eq!(receiver.payee_output(), 0);
+        let p2pkh = Script::from_str("76a914000000000000000000000000000000000000000088ac").unwrap();
+        match receiver.contribute_input(
+            receiver_outpoint(),
+            receiver_input(p2pkh),
+            InputWeightPrediction::P2PKH_COMPRESSED_MAX,
+            SeededRng::new(1),
+        ) {
+            Err(Error::MixedInputTypes) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        match receiver.contribute_input(
+            receiver_outpoint(),
+            Input::default(),
+            InputWeightPrediction::P2WPKH_MAX,
+            SeededRng::new(1),
+        ) {
+            Err(Error::Psbt(psbt::Error::MissingUtxo(_))) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        // Failed contributions leave the proposal alone
+        assert_eq!(receiver.clone().into_proposal().unwrap().inputs.len(), 1);
+
+        receiver.substitute_output(p2wpkh(5)).unwrap();
+        let proposal = receiver.into_proposal().unwrap();
+        assert_eq!(
+            proposal.global.unsigned_tx.output[0].script_pubkey,
+            p2wpkh(5)
+        );
+
+        let mut params = params();
+        params.disable_output_substitution = true;
+        let mut receiver = Receiver::new(self::original(), params, &p2wpkh(2)).unwrap();
+        match receiver.substitute_output(p2wpkh(5)) {
+            Err(Error::OutputSubstitutionDisabled) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn sender_checks() {
+        let check = |proposal: PartiallySignedTransaction, params: &Params| {
+            check_proposal(&original(), proposal, params, &p2wpkh(2)).map(|_| ())
+        };
+        let receiver = receiver_index(&proposal());
+        let sender = 1 - receiver;
+
+        let mut tampered = proposal();
+        tampered.global.unsigned_tx.lock_time = 1;
+        assert_eq!(check(tampered, &params()).unwrap_err().code(), 22_010);
+
+        let mut tampered = proposal();
+        tampered.global.unsigned_tx.input.remove(sender);
+        tampered.inputs.remove(sender);
+        assert_eq!(check(tampered, &params()).unwrap_err().code(), 22_008);
+
+        let mut tampered = proposal();
+        tampered.inputs[sender]
+            .partial_sigs
+            .insert(vec![2; 33], vec![0x30]);
+        assert_eq!(check(tampered, &params()).unwrap_err().code(), 22_009);
+
+        let mut tampered = proposal();
+        tampered.inputs[receiver].final_script_witness = None;
+        assert_eq!(check(tampered, &params()).unwrap_err().code(), 22_006);
+
+        let mut tampered = proposal();
+        tampered.global.unsigned_tx.input[receiver].sequence = 0xffffffff;
+        assert_eq!(check(tampered, &params()).unwrap_err().code(), 22_007);
+
+        let mut tampered = proposal();
+        let source = KeySource::from_str("d90c6a4f/0'/0'/2'").unwrap();
+        tampered.outputs[1]
+            .bip32_derivation
+            .insert(vec![2; 33], source);
+        assert_eq!(check(tampered, &params()).unwrap_err().code(), 22_011);
+
+        let mut tampered = proposal();
+        tampered.global.unsigned_tx.output[1].script_pubkey = p2wpkh(5);
+        assert_eq!(check(tampered, &params()).unwrap_err().code(), 22_012);
+
+        // Taking more than the allowed contribution, or more than the fee
+        // grew by
+        let mut tampered = proposal();
+        tampered.global.unsigned_tx.output[1].value -= 1;
+        assert_eq!(check(tampered, &params()).unwrap_err().code(), 22_013);
+        let mut params = params();
+        params.max_additional_fee_contribution = Amount::from_sat(10_000);
+        let mut tampered = proposal();
+        tampered.global.unsigned_tx.output[1].value -= 1_000;
+        tampered.global.unsigned_tx.output[0].value += 1_000;
+        assert_eq!(check(tampered, &params).unwrap_err().code(), 22_013);
+
+        params.min_fee_rate = FeeRate::from_sat_per_vb(10).unwrap();
+        assert_eq!(check(proposal(), &params).unwrap_err().code(), 22_014);
+
+        // The payee output may be substituted unless the sender disabled
+        // it
+        let mut substituted = proposal();
+        substituted.global.unsigned_tx.output[0].script_pubkey = p2wpkh(5);
+        assert!(check(substituted.clone(), &self::params()).is_ok());
+        let mut params = self::params();
+        params.disable_output_substitution = true;
+        assert_eq!(check(substituted, &params).unwrap_err().code(), 22_005);
+        assert!(check(proposal(), &params).is_ok());
+    }
+}
@@ -0,0 +1,235 @@
+//! # PSBT Serialization
+//!
+//! Defines traits used for (de)serializing PSBT values into/from raw
+//! bytes in PSBT key-value pairs.
+
+use std::io;
+
+use blockdata::script::Script;
+use blockdata::transaction::{SigHashType, Transaction, TxOut};
+use consensus::encode::{self, deserialize, serialize, Decodable, VarInt};
+use hash_types::{TapBranchHash, TapLeafHash};
+use hashes::Hash;
+use util::bip32::{ChildNumber, Fingerprint, KeySource};
+use util::psbt;
+
+/// The deepest a leaf may be in a taproot script tree
+pub(crate) const TAPROOT_CONTROL_MAX_NODE_COUNT: u8 = 128;
+
+/// A trait for serializing a value as raw data for insertion into PSBT
+/// key-value pairs.
+pub trait Serialize {
+    /// Serialize a value as raw data.
+    fn serialize(&self) -> Vec<u8>;
+}
+
+/// A trait for deserializing a value from raw data in PSBT key-value pairs.
+pub trait Deserialize: Sized {
+    /// Deserialize a value from raw data.
+    fn deserialize(bytes: &[u8]) -> Result<Self, encode::Error>;
+}
+
+macro_rules! impl_psbt_de_serialize {
+    ($thing:ty) => {
+        impl Serialize for $thing {
+            fn serialize(&self) -> Vec<u8> {
+                serialize(self)
+            }
+        }
+
+        impl Deserialize for $thing {
+            fn deserialize(bytes: &[u8]) -> Result<Self, encode::Error> {
+                deserialize(&bytes[..])
+            }
+        }
+    };
+}
+
+impl_psbt_de_serialize!(Transaction);
+impl_psbt_de_serialize!(TxOut);
+impl_psbt_de_serialize!(Vec<Vec<u8>>); // scriptWitness
+
+impl Serialize for Script {
+    fn serialize(&self) -> Vec<u8> {
+        self.to_bytes()
+    }
+}
+
+impl Deserialize for Script {
+    fn deserialize(bytes: &[u8]) -> Result<Self, encode::Error> {
+        Ok(Self::from(bytes.to_vec()))
+    }
+}
+
+impl Serialize for Vec<u8> {
+    fn serialize(&self) -> Vec<u8> {
+        self.clone()
+    }
+}
+
+impl Deserialize for Vec<u8> {
+    fn deserialize(bytes: &[u8]) -> Result<Self, encode::Error> {
+        Ok(bytes.to_vec())
+    }
+}
+
+impl Serialize for SigHashType {
+    fn serialize(&self) -> Vec<u8> {
+        serialize(&self.as_u32())
+    }
+}
+
+impl Deserialize for SigHashType {
+    fn deserialize(bytes: &[u8]) -> Result<Self, encode::Error> {
+        let raw: u32 = deserialize(bytes)?;
+        let rv: SigHashType = SigHashType::from_u32(raw);
+
+        if rv.as_u32() == raw {
+            Ok(rv)
+        } else {
+            Err(psbt::Error::NonStandardSigHashType(raw).into())
+        }
+    }
+}
+
+impl Serialize for [u8; 32] {
+    fn serialize(&self) -> Vec<u8> {
+        self.to_vec()
+    }
+}
+
+impl Deserialize for [u8; 32] {
+    fn deserialize(bytes: &[u8]) -> Result<Self, encode::Error> {
+        if bytes.len() != 32 {
+            return Err(encode::Error::ParseFailed(
+                "invalid x-only public key length",
+            ));
+        }
+        let mut ret = [0; 32];
+        ret.copy_from_slice(bytes);
+        Ok(ret)
+    }
+}
+
+impl Serialize for TapBranchHash {
+    fn serialize(&self) -> Vec<u8> {
+        self[..].to_vec()
+    }
+}
+
+impl Deserialize for TapBranchHash {
+    fn deserialize(bytes: &[u8]) -> Result<Self, encode::Error> {
+        TapBranchHash::from_slice(bytes)
+            .map_err(|_| encode::Error::ParseFailed("invalid taproot merkle root length"))
+    }
+}
+
+impl Serialize for KeySource {
+    fn serialize(&self) -> Vec<u8> {
+        let mut rv: Vec<u8> = Vec::with_capacity(4 + 4 * self.1.len());
+
+        rv.extend_from_slice(self.0.as_bytes());
+
+        for cnum in self.1.into_iter() {
+            rv.append(&mut serialize(&u32::from(*cnum)))
+        }
+
+        rv
+    }
+}
+
+impl Deserialize for KeySource {
+    fn deserialize(bytes: &[u8]) -> Result<Self, encode::Error> {
+        if bytes.len() < 4 || bytes.len() % 4 != 0 {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+
+        let fprint = Fingerprint::from_slice(&bytes[0..4]).unwrap();
+        let mut dpath: Vec<ChildNumber> = Default::default();
+
+        let mut d = &bytes[4..];
+        while !d.is_empty() {
+            match u32::consensus_decode(&mut d) {
+                Ok(index) => dpath.push(index.into()),
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(KeySource(fprint, dpath.into()))
+    }
+}
+
+impl Serialize for (Vec<TapLeafHash>, KeySource) {
+    fn serialize(&self) -> Vec<u8> {
+        let mut rv = serialize(&VarInt(self.0.len() as u64));
+        for leaf_hash in self.0.iter() {
+            rv.extend_from_slice(&leaf_hash[..]);
+        }
+        rv.append(&mut self.1.serialize());
+        rv
+    }
+}
+
+impl Deserialize for (Vec<TapLeafHash>, KeySource) {
+    fn deserialize(bytes: &[u8]) -> Result<Self, encode::Error> {
+        let mut d = bytes;
+        let VarInt(count) = VarInt::consensus_decode(&mut d)?;
+        if count > (d.len() / 32) as u64 {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        let mut leaf_hashes = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            leaf_hashes.push(TapLeafHash::from_slice(&d[..32]).unwrap());
+            d = &d[32..];
+        }
+        Ok((leaf_hashes, Deserialize::deserialize(d)?))
+    }
+}
+
+impl Serialize for (Script, u8) {
+    fn serialize(&self) -> Vec<u8> {
+        let mut rv = self.0.to_bytes();
+        rv.push(self.1);
+        rv
+    }
+}
+
+impl Deserialize for (Script, u8) {
+    fn deserialize(bytes: &[u8]) -> Result<Self, encode::Error> {
+        match bytes.split_last() {
+            Some((&leaf_version, script)) => Ok((Script::from(script.to_vec()), leaf_version)),
+            None => Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+        }
+    }
+}
+
+impl Serialize for Vec<(u8, u8, Script)> {
+    fn serialize(&self) -> Vec<u8> {
+        let mut rv = vec![];
+        for &(depth, leaf_version, ref script) in self.iter() {
+            rv.push(depth);
+            rv.push(leaf_version);
+            rv.append(&mut serialize(script));
+        }
+        rv
+    }
+}
+
+impl Deserialize for Vec<(u8, u8, Script)> {
+    fn deserialize(bytes: &[u8]) -> Result<Self, encode::Error> {
+        if bytes.is_empty() {
+            return Err(encode::Error::ParseFailed("empty taproot tree"));
+        }
+        let mut d = bytes;
+        let mut leaves = vec![];
+        while !d.is_empty() {
+            let depth = u8::consensus_decode(&mut d)?;
+            if depth > TAPROOT_CONTROL_MAX_NODE_COUNT {
+                return Err(encode::Error::ParseFailed("taproot tree too deep"));
+            }
+            let leaf_version = u8::consensus_decode(&mut d)?;
+            leaves.push((depth, leaf_version, Script::consensus_decode(&mut d)?));
+        }
+        Ok(leaves)
+    }
+}
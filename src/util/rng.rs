@@ -0,0 +1,177 @@
+//! Randomness
+//!
+//! Everything in this library which needs random numbers, such as version
+//! nonces and cache salts, takes them from an `Rng`, so that tests can make
+//! them reproducible with a `SeededRng` and applications can supply their
+//! own entropy source.
+//!
+//! `DefaultRng` draws on the random keys std seeds its hash maps with. That
+//! is unpredictable enough for nonces and salts, but private keys should be
+//! generated from a dedicated cryptographic source.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+use util::endian;
+
+/// A source of random numbers
+pub trait Rng {
+    /// The next random 64-bit number
+    fn next_u64(&mut self) -> u64;
+
+    /// Fills `dest` with random bytes
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(8) {
+            let bytes = endian::u64_to_array_le(self.next_u64());
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    /// A random number below `bound`, every one equally likely
+    ///
+    /// # Panics
+    ///
+    /// If `bound` is 0.
+    fn next_below(&mut self, bound: u64) -> u64 {
+        assert!(bound > 0, "no number is below 0");
+        // Draws which fall in the incomplete last range of `bound` numbers
+        // would favour the low numbers, so they are redrawn
+        let zone = u64::max_value() - u64::max_value() % bound;
+        loop {
+            let n = self.next_u64();
+            if n < zone {
+                return n % bound;
+            }
+        }
+    }
+
+    /// Shuffles `items`, every order equally likely
+    fn shuffle<T>(&mut self, items: &mut [T])
+    where
+        Self: Sized,
+    {
+        // Fisher-Yates
+        for i in (1..items.len()).rev() {
+            let j = self.next_below(i as u64 + 1) as usize;
+            items.swap(i, j);
+        }
+    }
+}
+
+impl<R: Rng + ?Sized> Rng for &mut R {
+    fn next_u64(&mut self) -> u64 {
+        (**self).next_u64()
+    }
+}
+
+/// Random numbers from the randomly seeded std hasher
+#[derive(Clone, Debug)]
+pub struct DefaultRng {
+    keys: RandomState,
+    counter: u64,
+}
+
+impl Default for DefaultRng {
+    fn default() -> DefaultRng {
+        DefaultRng {
+            keys: RandomState::new(),
+            counter: 0,
+        }
+    }
+}
+
+impl DefaultRng {
+    /// Creates a generator with fresh random keys
+    pub fn new() -> DefaultRng {
+        DefaultRng::default()
+    }
+}
+
+impl Rng for DefaultRng {
+    fn next_u64(&mut self) -> u64 {
+        let mut hasher = self.keys.build_hasher();
+        hasher.write_u64(self.counter);
+        self.counter = self.counter.wrapping_add(1);
+        hasher.finish()
+    }
+}
+
+/// Deterministic numbers from a seed, for reproducible tests. These are
+/// not random and must not be used for anything secret.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct SeededRng {
+    state: u64,
+}
+
+impl SeededRng {
+    /// Creates a generator which yields the same numbers for the same seed
+    pub fn new(seed: u64) -> SeededRng {
+        SeededRng { state: seed }
+    }
+}
+
+impl Rng for SeededRng {
+    /// The SplitMix64 generator
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seeded() {
+        // The first SplitMix64 output for seed 0
+        assert_eq!(SeededRng::new(0).next_u64(), 0xe220_a839_7b1d_cdaf);
+        let mut a = SeededRng::new(7);
+        let mut b = SeededRng::new(7);
+        assert_eq!(a.next_u64(), b.next_u64());
+
+        let mut bytes = [0; 11];
+        a.fill_bytes(&mut bytes);
+        let mut expected = [0; 16];
+        expected[..8].copy_from_slice(&endian::u64_to_array_le(b.next_u64()));
+        expected[8..].copy_from_slice(&endian::u64_to_array_le(b.next_u64()));
+        assert_eq!(bytes, expected[..11]);
+    }
+
+    #[test]
+    fn below() {
+        let mut rng = SeededRng::new(1);
+        let mut seen = [false; 5];
+        for _ in 0..100 {
+            seen[(&mut rng).next_below(5) as usize] = true;
+        }
+        assert!(seen.iter().all(|&s| s));
+        assert_eq!(rng.next_below(1), 0);
+
+        let mut rng = DefaultRng::new();
+        assert_ne!(rng.next_u64(), rng.next_u64());
+    }
+
+    #[test]
+    fn shuffle() {
+        let mut rng = SeededRng::new(2);
+        let mut seen = vec![];
+        for _ in 0..100 {
+            let mut items = [0, 1, 2];
+            rng.shuffle(&mut items);
+            let mut sorted = items;
+            sorted.sort();
+            assert_eq!(sorted, [0, 1, 2]);
+            if !seen.contains(&items) {
+                seen.push(items);
+            }
+        }
+        assert_eq!(seen.len(), 6);
+
+        let mut empty: [u8; 0] = [];
+        rng.shuffle(&mut empty);
+    }
+}
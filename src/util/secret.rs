@@ -0,0 +1,93 @@
+//! Secrets
+//!
+//! Types holding secret material, such as the salt of a `SigCache`, do not
+//! print it in their `Debug` output: the secret fields are shown as
+//! `[redacted]`, so that logging a value cannot leak them. Such types also
+//! implement `DebugSecret`, and wrapping one in `DangerousDebug` prints it
+//! in full, for the rare debugging session which needs to see the secret.
+//!
+//! With the `zeroize` feature these types also overwrite their secrets
+//! with zeros when dropped.
+
+use std::fmt;
+
+/// The placeholder printed in place of a secret
+pub const REDACTED: &str = "[redacted]";
+
+/// Prints as `[redacted]`, for use in place of a secret field of a
+/// `Debug` implementation
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub struct Redacted;
+
+impl fmt::Debug for Redacted {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+/// A type whose `Debug` output redacts secrets, which can print itself
+/// with the secrets shown
+pub trait DebugSecret {
+    /// Formats the value as `Debug` does, but with its secrets shown
+    fn fmt_secret(&self, f: &mut fmt::Formatter) -> fmt::Result;
+
+    /// Wraps the value so that its `Debug` output shows its secrets
+    fn dangerous_debug<'a>(&'a self) -> DangerousDebug<'a, Self>
+    where
+        Self: Sized,
+    {
+        DangerousDebug(self)
+    }
+}
+
+/// Shows the secrets of a value in its `Debug` output. Never let this
+/// reach a log.
+pub struct DangerousDebug<'a, T: 'a>(pub &'a T);
+
+impl<'a, T: DebugSecret> fmt::Debug for DangerousDebug<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt_secret(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Key {
+        id: u32,
+        secret: u64,
+    }
+
+    impl fmt::Debug for Key {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.debug_struct("Key")
+                .field("id", &self.id)
+                .field("secret", &Redacted)
+                .finish()
+        }
+    }
+
+    impl DebugSecret for Key {
+        fn fmt_secret(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.debug_struct("Key")
+                .field("id", &self.id)
+                .field("secret", &self.secret)
+                .finish()
+        }
+    }
+
+    #[test]
+    fn redaction() {
+        let key = Key { id: 1, secret: 42 };
+        assert_eq!(format!("{:?}", key), "Key { id: 1, secret: [redacted] }");
+        assert_eq!(
+            format!("{:?}", key.dangerous_debug()),
+            "Key { id: 1, secret: 42 }"
+        );
+        assert_eq!(
+            format!("{:#?}", DangerousDebug(&key)),
+            "Key {\n    id: 1,\n    secret: 42,\n}"
+        );
+    }
+}
@@ -0,0 +1,292 @@
+//! Signature Cache
+//!
+//! Most transactions in a block have already been checked when they were
+//! accepted to the mempool, so verifying their signatures again when the
+//! block arrives is wasted work. Like Bitcoin Core's signature cache, a
+//! `SigCache` remembers the signatures found valid, so that the second
+//! check is a lookup.
+//!
+//! Entries are stored as a salted hash of the message, public key and
+//! signature. The salt is random, so that no one can craft signatures
+//! which collide in the cache. Once the cache is full the oldest entries
+//! are evicted. Mempool acceptance and block validation share one cache,
+//! behind a `Mutex` if they run on different threads.
+
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+
+use hashes::{sha256, Hash, HashEngine};
+
+use util::endian;
+use util::rng::{DefaultRng, Rng};
+use util::secret::{DebugSecret, Redacted};
+
+#[cfg(feature = "zeroize")]
+use zeroize::Zeroize;
+
+/// Number of signatures remembered by `SigCache::default()`
+pub const DEFAULT_SIGCACHE_CAPACITY: usize = 1 << 16;
+
+/// How often lookups in a `SigCache` found the signature
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct SigCacheStats {
+    /// Lookups which found the signature
+    pub hits: u64,
+    /// Lookups which did not
+    pub misses: u64,
+}
+
+impl SigCacheStats {
+    /// The share of lookups which found the signature, or 0 if there were
+    /// none
+    pub fn hit_rate(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            0.0
+        } else {
+            self.hits as f64 / lookups as f64
+        }
+    }
+}
+
+/// A bounded cache of valid signatures. Its `Debug` output redacts the
+/// salt; see `util::secret`.
+#[derive(Clone)]
+pub struct SigCache {
+    /// Entries, oldest first
+    recent: VecDeque<sha256::Hash>,
+    /// The same entries, for lookup
+    known: HashSet<sha256::Hash>,
+    capacity: usize,
+    salt: [u8; 16],
+    stats: SigCacheStats,
+}
+
+impl SigCache {
+    fn fmt_with_salt(&self, f: &mut fmt::Formatter, salt: &fmt::Debug) -> fmt::Result {
+        f.debug_struct("SigCache")
+            .field("recent", &self.recent)
+            .field("known", &self.known)
+            .field("capacity", &self.capacity)
+            .field("salt", salt)
+            .field("stats", &self.stats)
+            .finish()
+    }
+}
+
+impl fmt::Debug for SigCache {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_with_salt(f, &Redacted)
+    }
+}
+
+impl DebugSecret for SigCache {
+    fn fmt_secret(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_with_salt(f, &self.salt)
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for SigCache {
+    fn drop(&mut self) {
+        self.salt.zeroize();
+    }
+}
+
+impl Default for SigCache {
+    fn default() -> SigCache {
+        SigCache::new(DEFAULT_SIGCACHE_CAPACITY)
+    }
+}
+
+impl SigCache {
+    /// Creates a cache which remembers the last `capacity` signatures
+    pub fn new(capacity: usize) -> SigCache {
+        SigCache::with_rng(capacity, DefaultRng::new())
+    }
+
+    /// Creates a cache which remembers the last `capacity` signatures,
+    /// salted from `rng`
+    pub fn with_rng<R: Rng>(capacity: usize, mut rng: R) -> SigCache {
+        let mut salt = [0; 16];
+        rng.fill_bytes(&mut salt);
+        SigCache {
+            recent: VecDeque::with_capacity(capacity),
+            known: HashSet::with_capacity(capacity),
+            capacity,
+            salt,
+            stats: SigCacheStats::default(),
+        }
+    }
+
+    fn entry(&self, msg: &[u8], pubkey: &[u8], sig: &[u8]) -> sha256::Hash {
+        let mut engine = sha256::Hash::engine();
+        engine.input(&self.salt);
+        for data in &[msg, pubkey, sig] {
+            engine.input(&endian::u32_to_array_le(data.len() as u32));
+            engine.input(data);
+        }
+        sha256::Hash::from_engine(engine)
+    }
+
+    /// Whether `sig` was recorded as a valid signature of `msg` by
+    /// `pubkey`. With `erase`, a signature found is forgotten, as when a
+    /// block is validated: its transactions leave the mempool, so their
+    /// signatures will not be checked again.
+    pub fn contains(&mut self, msg: &[u8], pubkey: &[u8], sig: &[u8], erase: bool) -> bool {
+        let entry = self.entry(msg, pubkey, sig);
+        let found = if erase {
+            self.known.remove(&entry)
+        } else {
+            self.known.contains(&entry)
+        };
+        if found {
+            self.stats.hits += 1;
+        } else {
+            self.stats.misses += 1;
+        }
+        found
+    }
+
+    /// Records `sig` as a valid signature of `msg` by `pubkey`
+    pub fn insert(&mut self, msg: &[u8], pubkey: &[u8], sig: &[u8]) {
+        if self.capacity == 0 {
+            return;
+        }
+        let entry = self.entry(msg, pubkey, sig);
+        if !self.known.insert(entry) {
+            return;
+        }
+        // Erased entries leave stale ones behind in `recent`, which are
+        // dropped as they come up
+        while self.known.len() > self.capacity {
+            match self.recent.pop_front() {
+                Some(oldest) => {
+                    self.known.remove(&oldest);
+                }
+                None => break,
+            }
+        }
+        self.recent.push_back(entry);
+        if self.recent.len() > 2 * self.capacity {
+            let known = &self.known;
+            self.recent.retain(|e| known.contains(e));
+        }
+    }
+
+    /// Checks a signature, calling `verify` only if it is not cached, and
+    /// caches it if `verify` finds it valid
+    pub fn verify<F>(
+        &mut self,
+        msg: &[u8],
+        pubkey: &[u8],
+        sig: &[u8],
+        erase: bool,
+        verify: F,
+    ) -> bool
+    where
+        F: FnOnce() -> bool,
+    {
+        if self.contains(msg, pubkey, sig, erase) {
+            return true;
+        }
+        let valid = verify();
+        if valid && !erase {
+            self.insert(msg, pubkey, sig);
+        }
+        valid
+    }
+
+    /// The number of signatures cached
+    pub fn len(&self) -> usize {
+        self.known.len()
+    }
+
+    /// Whether no signatures are cached
+    pub fn is_empty(&self) -> bool {
+        self.known.is_empty()
+    }
+
+    /// How often lookups found the signature
+    pub fn stats(&self) -> SigCacheStats {
+        self.stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use util::rng::SeededRng;
+
+    #[test]
+    fn lookup() {
+        let mut cache = SigCache::new(2);
+        assert!(!cache.contains(b"msg", b"key", b"sig", false));
+        cache.insert(b"msg", b"key", b"sig");
+        assert!(cache.contains(b"msg", b"key", b"sig", false));
+        assert!(!cache.contains(b"msg", b"key", b"other", false));
+        // The fields are length-prefixed, so moving bytes between them
+        // makes a different entry
+        assert!(!cache.contains(b"msgk", b"ey", b"sig", false));
+        assert_eq!(cache.stats(), SigCacheStats { hits: 1, misses: 3 });
+        assert_eq!(cache.stats().hit_rate(), 0.25);
+
+        // Erasing on a hit
+        assert!(cache.contains(b"msg", b"key", b"sig", true));
+        assert!(!cache.contains(b"msg", b"key", b"sig", false));
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn eviction() {
+        let mut cache = SigCache::new(2);
+        cache.insert(b"1", b"key", b"sig");
+        cache.insert(b"2", b"key", b"sig");
+        cache.insert(b"3", b"key", b"sig");
+        assert_eq!(cache.len(), 2);
+        assert!(!cache.contains(b"1", b"key", b"sig", false));
+        assert!(cache.contains(b"2", b"key", b"sig", false));
+
+        // An erased entry makes room without evicting
+        assert!(cache.contains(b"2", b"key", b"sig", true));
+        cache.insert(b"4", b"key", b"sig");
+        assert!(cache.contains(b"3", b"key", b"sig", false));
+        assert!(cache.contains(b"4", b"key", b"sig", false));
+
+        let mut empty = SigCache::new(0);
+        empty.insert(b"1", b"key", b"sig");
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn verify() {
+        let mut cache = SigCache::default();
+        let mut calls = 0;
+        assert!(cache.verify(b"msg", b"key", b"sig", false, || {
+            calls += 1;
+            true
+        }));
+        assert!(cache.verify(b"msg", b"key", b"sig", true, || {
+            calls += 1;
+            true
+        }));
+        assert_eq!(calls, 1);
+        assert!(!cache.verify(b"msg", b"key", b"bad", false, || false));
+        assert!(!cache.contains(b"msg", b"key", b"bad", false));
+    }
+
+    #[test]
+    fn debug_redacts_salt() {
+        let cache = SigCache::with_rng(1, SeededRng::new(0));
+        let debug = format!("{:?}", cache);
+        assert!(debug.contains("salt: [redacted]"));
+
+        let revealed = format!("{:?}", cache.dangerous_debug());
+        assert!(revealed.contains(&format!("salt: {:?}", cache.salt)));
+        assert_eq!(
+            revealed.replace(&format!("{:?}", cache.salt), "[redacted]"),
+            debug
+        );
+    }
+}
@@ -0,0 +1,902 @@
+//! Signature hash computation
+//!
+//! This module computes the legacy, BIP143 (segwit v0) and BIP341 (taproot)
+//! signature hashes of transaction inputs. The parts of the message that do
+//! not depend on the input being signed are hashed once per transaction and
+//! reused for every input, and the outputs spent by the transaction are
+//! consumed from an iterator rather than held in memory, so transactions with
+//! thousands of inputs can be signed in a single pass.
+
+use std::borrow::Borrow;
+use std::{error, fmt};
+
+use hashes::{sha256, sha256d, Hash, HashEngine};
+
+use blockdata::script::Script;
+use blockdata::transaction::{SigHashType, Transaction, TxOut};
+use consensus::encode::{Encodable, VarInt};
+use error::{ErrorCode, ErrorKind};
+use hash_types::{SigHash, TapLeafHash, TapSighashHash};
+
+/// The bits of the first byte of a control block giving the leaf version
+const TAPROOT_LEAF_MASK: u8 = 0xfe;
+
+/// Possible errors in computing a signature hash
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// Requested the signature hash of an input that does not exist
+    IndexOutOfInputsBounds {
+        /// Requested index
+        index: usize,
+        /// Number of inputs in the transaction
+        inputs_size: usize,
+    },
+    /// The number of spent outputs differs from the number of inputs
+    PrevoutsSize {
+        /// Number of spent outputs provided
+        prevouts_size: usize,
+        /// Number of inputs in the transaction
+        inputs_size: usize,
+    },
+    /// Used `SIGHASH_SINGLE` on an input without a corresponding output,
+    /// which BIP341 forbids
+    SingleWithoutCorrespondingOutput {
+        /// Requested index
+        index: usize,
+        /// Number of outputs in the transaction
+        outputs_size: usize,
+    },
+    /// The sighash type is not one of the values allowed by BIP341
+    InvalidSighashType(u8),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::IndexOutOfInputsBounds { index, inputs_size } => write!(
+                f,
+                "input index {} out of bounds of {} inputs",
+                index, inputs_size
+            ),
+            Error::PrevoutsSize {
+                prevouts_size,
+                inputs_size,
+            } => write!(
+                f,
+                "{} spent outputs provided for {} inputs",
+                prevouts_size, inputs_size
+            ),
+            Error::SingleWithoutCorrespondingOutput {
+                index,
+                outputs_size,
+            } => write!(
+                f,
+                "SIGHASH_SINGLE for input {} but only {} outputs",
+                index, outputs_size
+            ),
+            Error::InvalidSighashType(t) => write!(f, "invalid taproot sighash type {:#x}", t),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn cause(&self) -> Option<&error::Error> {
+        None
+    }
+
+    fn description(&self) -> &str {
+        match *self {
+            Error::IndexOutOfInputsBounds { .. } => "input index out of bounds",
+            Error::PrevoutsSize { .. } => "number of spent outputs differs from inputs",
+            Error::SingleWithoutCorrespondingOutput { .. } => {
+                "SIGHASH_SINGLE without corresponding output"
+            }
+            Error::InvalidSighashType(..) => "invalid taproot sighash type",
+        }
+    }
+}
+
+impl ErrorCode for Error {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Sighash
+    }
+
+    fn number(&self) -> u32 {
+        match *self {
+            Error::IndexOutOfInputsBounds { .. } => 1,
+            Error::PrevoutsSize { .. } => 2,
+            Error::SingleWithoutCorrespondingOutput { .. } => 3,
+            Error::InvalidSighashType(..) => 4,
+        }
+    }
+}
+
+/// Commitments to the outputs spent by a transaction, as required by the
+/// BIP341 signature message.
+///
+/// The spent outputs are hashed as they are read, so only the two
+/// commitments are kept however many inputs the transaction has.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Prevouts {
+    len: usize,
+    amounts: sha256::Hash,
+    script_pubkeys: sha256::Hash,
+}
+
+impl Prevouts {
+    /// Hashes the outputs spent by each input of a transaction, in input
+    /// order.
+    pub fn from_spent_outputs<I, T>(spent_outputs: I) -> Prevouts
+    where
+        I: IntoIterator<Item = T>,
+        T: Borrow<TxOut>,
+    {
+        let mut len = 0;
+        let mut amounts = sha256::Hash::engine();
+        let mut script_pubkeys = sha256::Hash::engine();
+        for txout in spent_outputs {
+            let txout = txout.borrow();
+            len += 1;
+            txout.value.consensus_encode(&mut amounts).unwrap();
+            txout
+                .script_pubkey
+                .consensus_encode(&mut script_pubkeys)
+                .unwrap();
+        }
+        Prevouts {
+            len,
+            amounts: sha256::Hash::from_engine(amounts),
+            script_pubkeys: sha256::Hash::from_engine(script_pubkeys),
+        }
+    }
+
+    /// The number of spent outputs that were hashed
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether no spent outputs were hashed
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+/// Single-SHA256 hashes shared by the BIP143 and BIP341 messages
+#[derive(Debug, Clone)]
+struct CommonCache {
+    prevouts: sha256::Hash,
+    sequences: sha256::Hash,
+    outputs: sha256::Hash,
+}
+
+/// Double-SHA256 hashes used by the BIP143 message
+#[derive(Debug, Clone)]
+struct SegwitCache {
+    prevouts: sha256d::Hash,
+    sequences: sha256d::Hash,
+    outputs: sha256d::Hash,
+}
+
+/// Computes the signature hashes of the inputs of a transaction, caching the
+/// intermediate values shared between inputs
+#[derive(Debug)]
+pub struct SigHashCache<'a> {
+    tx: &'a Transaction,
+    common: Option<CommonCache>,
+    segwit: Option<SegwitCache>,
+}
+
+impl<'a> SigHashCache<'a> {
+    /// Creates a cache for the signature hashes of `tx`. Nothing is computed
+    /// until the first signature hash is requested.
+    pub fn new(tx: &'a Transaction) -> SigHashCache<'a> {
+        SigHashCache {
+            tx,
+            common: None,
+            segwit: None,
+        }
+    }
+
+    fn common_cache(&mut self) -> &CommonCache {
+        let tx = self.tx;
+        self.common.get_or_insert_with(|| {
+            let mut prevouts = sha256::Hash::engine();
+            let mut sequences = sha256::Hash::engine();
+            for input in &tx.input {
+                input
+                    .previous_output
+                    .consensus_encode(&mut prevouts)
+                    .unwrap();
+                input.sequence.consensus_encode(&mut sequences).unwrap();
+            }
+            let mut outputs = sha256::Hash::engine();
+            for output in &tx.output {
+                output.consensus_encode(&mut outputs).unwrap();
+            }
+            CommonCache {
+                prevouts: sha256::Hash::from_engine(prevouts),
+                sequences: sha256::Hash::from_engine(sequences),
+                outputs: sha256::Hash::from_engine(outputs),
+            }
+        })
+    }
+
+    fn segwit_cache(&mut self) -> &SegwitCache {
+        if self.segwit.is_none() {
+            let common = self.common_cache().clone();
+            // The BIP143 hashes are the double-SHA256 of the same data, so
+            // they only need one more round over the cached single hashes
+            let double = |h: sha256::Hash| {
+                sha256d::Hash::from_inner(sha256::Hash::hash(&h[..]).into_inner())
+            };
+            self.segwit = Some(SegwitCache {
+                prevouts: double(common.prevouts),
+                sequences: double(common.sequences),
+                outputs: double(common.outputs),
+            });
+        }
+        self.segwit.as_ref().unwrap()
+    }
+
+    fn check_index(&self, input_index: usize) -> Result<(), Error> {
+        if input_index >= self.tx.input.len() {
+            return Err(Error::IndexOutOfInputsBounds {
+                index: input_index,
+                inputs_size: self.tx.input.len(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Computes the signature hash of the input at `input_index` of a
+    /// transaction spending a non-segwit output, as signed with the raw
+    /// `sighash_type`.
+    ///
+    /// `script_code` is signed as is: removing the `OP_CODESEPARATOR`s and
+    /// the signature itself from it, as Bitcoin Core does, is left to the
+    /// caller. As in Bitcoin Core, `SIGHASH_SINGLE` without a corresponding
+    /// output signs the hash 1.
+    pub fn legacy_signature_hash(
+        &self,
+        input_index: usize,
+        script_code: &Script,
+        sighash_type: u32,
+    ) -> Result<SigHash, Error> {
+        self.check_index(input_index)?;
+        let tx = self.tx;
+        let anyone_can_pay = sighash_type & 0x80 != 0;
+        let base_type = sighash_type & 0x1f;
+        let (none, single) = (base_type == 0x02, base_type == 0x03);
+        if single && input_index >= tx.output.len() {
+            let mut one = [0; 32];
+            one[0] = 1;
+            return Ok(SigHash::from_inner(one));
+        }
+
+        let mut enc = SigHash::engine();
+        tx.version.consensus_encode(&mut enc).unwrap();
+        let inputs: Vec<usize> = if anyone_can_pay {
+            vec![input_index]
+        } else {
+            (0..tx.input.len()).collect()
+        };
+        VarInt(inputs.len() as u64)
+            .consensus_encode(&mut enc)
+            .unwrap();
+        for i in inputs {
+            let txin = &tx.input[i];
+            txin.previous_output.consensus_encode(&mut enc).unwrap();
+            if i == input_index {
+                script_code.consensus_encode(&mut enc).unwrap();
+                txin.sequence.consensus_encode(&mut enc).unwrap();
+            } else {
+                Script::new().consensus_encode(&mut enc).unwrap();
+                let sequence = if none || single { 0 } else { txin.sequence };
+                sequence.consensus_encode(&mut enc).unwrap();
+            }
+        }
+        if none {
+            VarInt(0).consensus_encode(&mut enc).unwrap();
+        } else if single {
+            // The outputs before this input's are blanked
+            VarInt(input_index as u64 + 1)
+                .consensus_encode(&mut enc)
+                .unwrap();
+            let blank = TxOut {
+                value: u64::max_value(),
+                script_pubkey: Script::new(),
+            };
+            for _ in 0..input_index {
+                blank.consensus_encode(&mut enc).unwrap();
+            }
+            tx.output[input_index].consensus_encode(&mut enc).unwrap();
+        } else {
+            tx.output.consensus_encode(&mut enc).unwrap();
+        }
+        tx.lock_time.consensus_encode(&mut enc).unwrap();
+        sighash_type.consensus_encode(&mut enc).unwrap();
+        Ok(SigHash::from_engine(enc))
+    }
+
+    /// Computes the BIP143 signature hash of the input at `input_index`,
+    /// which spends an output of `value` satoshis and is satisfied by
+    /// `script_code`.
+    pub fn segwit_signature_hash(
+        &mut self,
+        input_index: usize,
+        script_code: &Script,
+        value: u64,
+        sighash_type: SigHashType,
+    ) -> Result<SigHash, Error> {
+        self.check_index(input_index)?;
+        let (base_type, anyone_can_pay) = sighash_type.split_anyonecanpay_flag();
+        let zero_hash = sha256d::Hash::default();
+
+        let (hash_prevouts, hash_sequence, hash_outputs) = {
+            let cache = self.segwit_cache();
+            let hash_prevouts = if anyone_can_pay {
+                zero_hash
+            } else {
+                cache.prevouts
+            };
+            let hash_sequence = if anyone_can_pay
+                || base_type == SigHashType::Single
+                || base_type == SigHashType::None
+            {
+                zero_hash
+            } else {
+                cache.sequences
+            };
+            let hash_outputs = if base_type != SigHashType::Single && base_type != SigHashType::None
+            {
+                cache.outputs
+            } else {
+                zero_hash
+            };
+            (hash_prevouts, hash_sequence, hash_outputs)
+        };
+        let hash_outputs = if base_type == SigHashType::Single && input_index < self.tx.output.len()
+        {
+            let mut single_enc = sha256d::Hash::engine();
+            self.tx.output[input_index]
+                .consensus_encode(&mut single_enc)
+                .unwrap();
+            sha256d::Hash::from_engine(single_enc)
+        } else {
+            hash_outputs
+        };
+
+        let txin = &self.tx.input[input_index];
+        let mut enc = SigHash::engine();
+        self.tx.version.consensus_encode(&mut enc).unwrap();
+        hash_prevouts.consensus_encode(&mut enc).unwrap();
+        hash_sequence.consensus_encode(&mut enc).unwrap();
+        txin.previous_output.consensus_encode(&mut enc).unwrap();
+        script_code.consensus_encode(&mut enc).unwrap();
+        value.consensus_encode(&mut enc).unwrap();
+        txin.sequence.consensus_encode(&mut enc).unwrap();
+        hash_outputs.consensus_encode(&mut enc).unwrap();
+        self.tx.lock_time.consensus_encode(&mut enc).unwrap();
+        sighash_type.as_u32().consensus_encode(&mut enc).unwrap();
+        Ok(SigHash::from_engine(enc))
+    }
+
+    /// Computes the BIP341 signature hash of a key path spend of the input
+    /// at `input_index`, committing to `annex` if the spend is to have one.
+    ///
+    /// `prevouts` commits to every output spent by the transaction, and
+    /// `spent_output` is the one spent by this input; it is only part of the
+    /// message with `SIGHASH_ANYONECANPAY`. `sighash_type` is the raw byte
+    /// appended to the signature, `0x00` for the default type.
+    pub fn taproot_key_spend_signature_hash(
+        &mut self,
+        input_index: usize,
+        prevouts: &Prevouts,
+        spent_output: &TxOut,
+        annex: Option<&[u8]>,
+        sighash_type: u8,
+    ) -> Result<TapSighashHash, Error> {
+        self.taproot_signature_hash(
+            input_index,
+            prevouts,
+            spent_output,
+            annex,
+            None,
+            sighash_type,
+        )
+    }
+
+    /// Computes the BIP341 signature hash of the input at `input_index` from
+    /// its witness, for checking its signature: the annex it ends with, if
+    /// any, is committed to, and if it has a control block the hash is the
+    /// one of a script path spend of the script below it.
+    ///
+    /// The witness must be complete, signatures included. Without them an
+    /// annex may be taken for a signature, so signers should use
+    /// `taproot_key_spend_signature_hash` or `taproot_signature_hash`
+    /// instead.
+    pub fn taproot_witness_signature_hash(
+        &mut self,
+        input_index: usize,
+        prevouts: &Prevouts,
+        spent_output: &TxOut,
+        sighash_type: u8,
+    ) -> Result<TapSighashHash, Error> {
+        self.check_index(input_index)?;
+        let tx = self.tx;
+        let txin = &tx.input[input_index];
+        let leaf_hash = match (txin.tapscript(), txin.taproot_control_block()) {
+            (Some(script), Some(control_block)) => {
+                Some(script.tapleaf_hash(control_block[0] & TAPROOT_LEAF_MASK))
+            }
+            _ => None,
+        };
+        self.taproot_signature_hash(
+            input_index,
+            prevouts,
+            spent_output,
+            txin.taproot_annex(),
+            leaf_hash,
+            sighash_type,
+        )
+    }
+
+    /// Computes the BIP341 signature hash of the input at `input_index`,
+    /// committing to `annex` if given, including its `0x50` prefix.
+    ///
+    /// With a `leaf_hash` this is the hash signed by the script path spend of
+    /// that leaf as defined by BIP342, assuming no `OP_CODESEPARATOR` was
+    /// executed; without it, the hash signed by a key path spend.
+    pub fn taproot_signature_hash(
+        &mut self,
+        input_index: usize,
+        prevouts: &Prevouts,
+        spent_output: &TxOut,
+        annex: Option<&[u8]>,
+        leaf_hash: Option<TapLeafHash>,
+        sighash_type: u8,
+    ) -> Result<TapSighashHash, Error> {
+        self.check_index(input_index)?;
+        if prevouts.len() != self.tx.input.len() {
+            return Err(Error::PrevoutsSize {
+                prevouts_size: prevouts.len(),
+                inputs_size: self.tx.input.len(),
+            });
+        }
+        let (base_type, anyone_can_pay) = match sighash_type {
+            0x00 => (SigHashType::All, false),
+            0x01...0x03 | 0x81...0x83 => {
+                SigHashType::from_u32(sighash_type as u32).split_anyonecanpay_flag()
+            }
+            t => return Err(Error::InvalidSighashType(t)),
+        };
+        if base_type == SigHashType::Single && input_index >= self.tx.output.len() {
+            return Err(Error::SingleWithoutCorrespondingOutput {
+                index: input_index,
+                outputs_size: self.tx.output.len(),
+            });
+        }
+
+        let tag = sha256::Hash::hash(b"TapSighash");
+        let mut enc = TapSighashHash::engine();
+        enc.input(&tag[..]);
+        enc.input(&tag[..]);
+
+        // epoch
+        0u8.consensus_encode(&mut enc).unwrap();
+        sighash_type.consensus_encode(&mut enc).unwrap();
+        self.tx.version.consensus_encode(&mut enc).unwrap();
+        self.tx.lock_time.consensus_encode(&mut enc).unwrap();
+        {
+            let tx = self.tx;
+            let common = self.common_cache();
+            if !anyone_can_pay {
+                enc.input(&common.prevouts[..]);
+                enc.input(&prevouts.amounts[..]);
+                enc.input(&prevouts.script_pubkeys[..]);
+                enc.input(&common.sequences[..]);
+            }
+            if base_type != SigHashType::Single && base_type != SigHashType::None {
+                enc.input(&common.outputs[..]);
+            }
+
+            // spend type: the extension flag, 1 for a script path spend,
+            // times two, plus whether there is an annex
+            let spend_type = if leaf_hash.is_some() { 2u8 } else { 0 } + annex.is_some() as u8;
+            spend_type.consensus_encode(&mut enc).unwrap();
+            if anyone_can_pay {
+                let txin = &tx.input[input_index];
+                txin.previous_output.consensus_encode(&mut enc).unwrap();
+                spent_output.value.consensus_encode(&mut enc).unwrap();
+                spent_output
+                    .script_pubkey
+                    .consensus_encode(&mut enc)
+                    .unwrap();
+                txin.sequence.consensus_encode(&mut enc).unwrap();
+            } else {
+                (input_index as u32).consensus_encode(&mut enc).unwrap();
+            }
+            if let Some(annex) = annex {
+                let mut annex_enc = sha256::Hash::engine();
+                VarInt(annex.len() as u64)
+                    .consensus_encode(&mut annex_enc)
+                    .unwrap();
+                annex_enc.input(annex);
+                enc.input(&sha256::Hash::from_engine(annex_enc)[..]);
+            }
+            if base_type == SigHashType::Single {
+                let mut single_enc = sha256::Hash::engine();
+                tx.output[input_index]
+                    .consensus_encode(&mut single_enc)
+                    .unwrap();
+                enc.input(&sha256::Hash::from_engine(single_enc)[..]);
+            }
+        }
+        if let Some(leaf_hash) = leaf_hash {
+            enc.input(&leaf_hash[..]);
+            // key version
+            0u8.consensus_encode(&mut enc).unwrap();
+            // position of the last executed OP_CODESEPARATOR, none
+            0xffffffffu32.consensus_encode(&mut enc).unwrap();
+        }
+        Ok(TapSighashHash::from_engine(enc))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Error, Prevouts, SigHashCache};
+
+    use blockdata::script::Script;
+    use blockdata::transaction::{SigHashType, Transaction, TxOut};
+    use consensus::encode::deserialize;
+    use hash_types::SigHash;
+    use hashes::hex::FromHex;
+
+    fn hex_bytes(s: &str) -> Vec<u8> {
+        Vec::<u8>::from_hex(s).unwrap()
+    }
+
+    fn bip143_tx() -> Transaction {
+        deserialize(&hex_bytes(
+            "0100000002fff7f7881a8099afa6940d42d1e7f6362bec38171ea3edf433541db4e4ad969f0000000000eeffffffef51e1b804cc89d182d279655c3aa89e815b1b309fe287d9b2b55d57b90ec68a0100000000ffffffff02202cb206000000001976a9148280b37df378db99f66f85c95a783a76ac7a6d5988ac9093510d000000001976a9143bde42dbee7e4dbe6a21b2d50ce2f0167faa815988ac11000000",
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn bip143_p2wpkh() {
+        // Native P2WPKH example from BIP143
+        let tx = bip143_tx();
+        let script_code = Script::from(hex_bytes(
+            "76a9141d0f172a0ecb48aee1be1f2687d2963ae33f71a188ac",
+        ));
+        let mut cache = SigHashCache::new(&tx);
+        let sighash = cache
+            .segwit_signature_hash(1, &script_code, 600000000, SigHashType::All)
+            .unwrap();
+        assert_eq!(
+            &sighash[..],
+            &hex_bytes("c37af31116d1b27caf68aae9e3ac82f1477929014d5b917657d0eb49478cb670")[..]
+        );
+
+        {
+            let segwit = cache.segwit.as_ref().unwrap();
+            assert_eq!(
+                &segwit.prevouts[..],
+                &hex_bytes("96b827c8483d4e9b96712b6713a7b68d6e8003a781feba36c31143470b4efd37")[..]
+            );
+            assert_eq!(
+                &segwit.sequences[..],
+                &hex_bytes("52b0a642eea2fb7ae638c36f6252b6750293dbe574a806984b8e4d8548339a3b")[..]
+            );
+            assert_eq!(
+                &segwit.outputs[..],
+                &hex_bytes("863ef3e1a92afbfdb97f31ad0fc7683ee943e9abcf2501590ff8f6551f47e5e5")[..]
+            );
+        }
+
+        // Reusing the cache gives the same result as a fresh one
+        let again = SigHashCache::new(&tx)
+            .segwit_signature_hash(1, &script_code, 600000000, SigHashType::All)
+            .unwrap();
+        assert_eq!(again, sighash);
+        assert_eq!(
+            cache.segwit_signature_hash(2, &script_code, 600000000, SigHashType::All),
+            Err(Error::IndexOutOfInputsBounds {
+                index: 2,
+                inputs_size: 2
+            })
+        );
+    }
+
+    #[test]
+    fn legacy() {
+        let mut tx = bip143_tx();
+        let script_code = Script::from(hex_bytes(
+            "76a9141d0f172a0ecb48aee1be1f2687d2963ae33f71a188ac",
+        ));
+        let sighash = |tx: &Transaction, index: usize, sighash_type: u32| {
+            SigHashCache::new(tx)
+                .legacy_signature_hash(index, &script_code, sighash_type)
+                .unwrap()
+        };
+        let all = sighash(&tx, 0, 0x01);
+        let anyone_can_pay = sighash(&tx, 0, 0x81);
+        let none = sighash(&tx, 0, 0x02);
+        assert!(all != anyone_can_pay && all != none);
+
+        // SIGHASH_SINGLE without a corresponding output signs 1
+        tx.output.truncate(1);
+        let mut one = [0; 32];
+        one[0] = 1;
+        assert_eq!(&sighash(&tx, 1, 0x03)[..], &one[..]);
+        assert!(sighash(&tx, 0, 0x03)[..] != one[..]);
+
+        // The other inputs' scripts are not signed, nor their sequences
+        // with SIGHASH_NONE, nor the outputs
+        let mut other = bip143_tx();
+        other.input[1].script_sig = script_code.clone();
+        assert_eq!(sighash(&other, 0, 0x01), all);
+        other.input[1].sequence = 0;
+        assert!(sighash(&other, 0, 0x01) != all);
+        assert_eq!(sighash(&other, 0, 0x81), anyone_can_pay);
+        other.output.clear();
+        assert_eq!(sighash(&other, 0, 0x02), none);
+
+        assert_eq!(
+            SigHashCache::new(&tx).legacy_signature_hash(2, &script_code, 0x01),
+            Err(Error::IndexOutOfInputsBounds {
+                index: 2,
+                inputs_size: 2
+            })
+        );
+
+        // From Bitcoin Core's sighash.json: SIGHASH_ALL, SIGHASH_SINGLE |
+        // SIGHASH_ANYONECANPAY and SIGHASH_NONE, with random upper bits
+        let vectors = [
+            (
+                "b7877f82019c832707a60cf14fba44cfa254d787501fdd676bd58c744f6e951dbba0b3b77f0200000009ac515263ac53525300a5a36e500148f89c0500000000085265ac6a6a65acab00000000",
+                "6563",
+                0,
+                -1785108415,
+                "cb6e4322955af12eb29613c70e1a00ddbb559c887ba844df0bcdebed736dffbd",
+            ),
+            (
+                "d3b7421e011f4de0f1cea9ba7458bf3486bee722519efab711a963fa8c100970cf7488b7bb0200000003525352dcd61b300148be5d05000000000000000000",
+                "535251536aac536a",
+                0,
+                -1960128125,
+                "29aa6d2d752d3310eba20442770ad345b7f6a35f96161ede5f07b33e92053e2a",
+            ),
+            (
+                "2f7353dd02e395b0a4d16da0f7472db618857cd3de5b9e2789232952a9b154d249102245fd030000000151617fd88f103280b85b0a198198e438e7cab1a4c92ba58409709997cc7a65a619eb9eec3c0200000003636aabffffffff0397481c0200000000045300636a0dc97803000000000009d389030000000003ac6a53134007bb",
+                "0000536552526a",
+                0,
+                -1912746174,
+                "30c4cd4bd6b291f7e9489cc4b4440a083f93a7664ea1f93e77a9597dab8ded9c",
+            ),
+        ];
+        for &(tx, script, index, sighash_type, expected) in vectors.iter() {
+            let tx: Transaction = deserialize(&hex_bytes(tx)).unwrap();
+            let script_code = Script::from(hex_bytes(script));
+            let sighash = SigHashCache::new(&tx)
+                .legacy_signature_hash(index, &script_code, sighash_type as u32)
+                .unwrap();
+            assert_eq!(sighash, SigHash::from_hex(expected).unwrap());
+        }
+    }
+
+    #[test]
+    fn taproot_key_spend() {
+        let tx = bip143_tx();
+        let spent = vec![
+            TxOut {
+                value: 625000000,
+                script_pubkey: Script::from(hex_bytes(
+                    "512053a1f6e454df1aa2776a2814a721372d6258050de330b3c6d10ee8f4e0dda343",
+                )),
+            },
+            TxOut {
+                value: 600000000,
+                script_pubkey: Script::from(hex_bytes(
+                    "5120147c9c57132f6e7ecddba9800bb0c4449251c92a1e60371ee77557b6620f3ea3",
+                )),
+            },
+        ];
+        let prevouts = Prevouts::from_spent_outputs(&spent);
+        assert_eq!(prevouts.len(), 2);
+        // Streaming owned outputs commits to the same data
+        assert_eq!(Prevouts::from_spent_outputs(spent.clone()), prevouts);
+
+        let mut cache = SigHashCache::new(&tx);
+        let expected = [
+            (
+                0,
+                0x00,
+                "8de44b2511aeafa6b1e78f3aa4a73bd294fc9d9d71dab8ee9a2ebdc09fc1b261",
+            ),
+            (
+                1,
+                0x00,
+                "39b6cc92116479431f1db48bbfddf30c8a3f4c8d151fb07a256624eb6c842cba",
+            ),
+            (
+                0,
+                0x01,
+                "d15d3aa8c77e64e0dbda1a05f831319bd9d6cb09ac59be7ac39935911e8780a4",
+            ),
+            (
+                1,
+                0x02,
+                "da35a2a35b20d65ddf0d89e300fd91bc32b55b0c9cbc28c9274844c237ec11ee",
+            ),
+            (
+                1,
+                0x03,
+                "8583cedaaf091757ebf749d618289d5872679fb7e7fc88417abdb4fc34bb10ca",
+            ),
+            (
+                0,
+                0x81,
+                "7ed4d496da5e8463049e0e5fc2ccaa7f16233bebe889c2019396bb7eb0861690",
+            ),
+            (
+                0,
+                0x82,
+                "8166d24aedcded5671ba9b64da9d254171c05d6e5e162ade309cc02026721b5b",
+            ),
+            (
+                1,
+                0x83,
+                "b0ac5943ceb0141379dd628fa819f4c39ca6c9d7f6b996f9cd1def84c021cf31",
+            ),
+        ];
+        for &(index, sighash_type, hash) in expected.iter() {
+            let sighash = cache
+                .taproot_key_spend_signature_hash(
+                    index,
+                    &prevouts,
+                    &spent[index],
+                    None,
+                    sighash_type,
+                )
+                .unwrap();
+            assert_eq!(&sighash[..], &hex_bytes(hash)[..]);
+        }
+
+        assert_eq!(
+            cache.taproot_key_spend_signature_hash(0, &prevouts, &spent[0], None, 0x04),
+            Err(Error::InvalidSighashType(0x04))
+        );
+        let short = Prevouts::from_spent_outputs(&spent[..1]);
+        assert_eq!(
+            cache.taproot_key_spend_signature_hash(0, &short, &spent[0], None, 0x00),
+            Err(Error::PrevoutsSize {
+                prevouts_size: 1,
+                inputs_size: 2
+            })
+        );
+    }
+
+    #[test]
+    fn taproot_annex_and_script_path() {
+        let mut tx = bip143_tx();
+        let spent = vec![
+            TxOut {
+                value: 625000000,
+                script_pubkey: Script::from(hex_bytes(
+                    "512053a1f6e454df1aa2776a2814a721372d6258050de330b3c6d10ee8f4e0dda343",
+                )),
+            },
+            TxOut {
+                value: 600000000,
+                script_pubkey: Script::from(hex_bytes(
+                    "5120147c9c57132f6e7ecddba9800bb0c4449251c92a1e60371ee77557b6620f3ea3",
+                )),
+            },
+        ];
+        let prevouts = Prevouts::from_spent_outputs(&spent);
+        let leaf = Script::from(hex_bytes(
+            "2053a1f6e454df1aa2776a2814a721372d6258050de330b3c6d10ee8f4e0dda343ac",
+        ));
+        let mut control_block = vec![0xc1];
+        control_block.extend_from_slice(&[0x02; 32]);
+        let annex = vec![0x50, 0x01, 0x02];
+        tx.input[0].witness = vec![vec![0; 64], annex.clone()];
+        tx.input[1].witness = vec![
+            vec![0; 64],
+            leaf.to_bytes(),
+            control_block.clone(),
+            annex.clone(),
+        ];
+
+        let key_spend = SigHashCache::new(&tx)
+            .taproot_key_spend_signature_hash(0, &prevouts, &spent[0], None, 0x00)
+            .unwrap();
+        for &sighash_type in [0x00, 0x83].iter() {
+            let mut cache = SigHashCache::new(&tx);
+            assert_eq!(
+                cache.taproot_signature_hash(0, &prevouts, &spent[0], None, None, sighash_type),
+                cache.taproot_key_spend_signature_hash(0, &prevouts, &spent[0], None, sighash_type)
+            );
+
+            // The annex is read from the witness and committed to
+            let with_annex = cache
+                .taproot_witness_signature_hash(0, &prevouts, &spent[0], sighash_type)
+                .unwrap();
+            assert_eq!(
+                Ok(with_annex),
+                cache.taproot_signature_hash(
+                    0,
+                    &prevouts,
+                    &spent[0],
+                    Some(&annex[..]),
+                    None,
+                    sighash_type
+                )
+            );
+            assert!(with_annex != key_spend);
+
+            // So is the leaf, with the leaf version of the control block
+            let script_path = cache
+                .taproot_witness_signature_hash(1, &prevouts, &spent[1], sighash_type)
+                .unwrap();
+            let leaf_hash = leaf.tapleaf_hash(0xc0);
+            assert_eq!(
+                Ok(script_path),
+                cache.taproot_signature_hash(
+                    1,
+                    &prevouts,
+                    &spent[1],
+                    Some(&annex[..]),
+                    Some(leaf_hash),
+                    sighash_type
+                )
+            );
+            assert!(
+                Ok(script_path)
+                    != cache.taproot_signature_hash(
+                        1,
+                        &prevouts,
+                        &spent[1],
+                        None,
+                        Some(leaf_hash),
+                        sighash_type
+                    )
+            );
+        }
+    }
+
+    #[test]
+    fn taproot_annex_before_signing() {
+        let mut tx = bip143_tx();
+        let spent = vec![TxOut {
+            value: 625000000,
+            script_pubkey: Script::from(hex_bytes(
+                "512053a1f6e454df1aa2776a2814a721372d6258050de330b3c6d10ee8f4e0dda343",
+            )),
+        }];
+        tx.input.truncate(1);
+        let prevouts = Prevouts::from_spent_outputs(&spent);
+        let annex = vec![0x50, 0x01, 0x02];
+
+        // The signer has only the annex in the witness so far
+        tx.input[0].witness = vec![annex.clone()];
+        let msg = SigHashCache::new(&tx)
+            .taproot_key_spend_signature_hash(0, &prevouts, &spent[0], Some(&annex[..]), 0x00)
+            .unwrap();
+        assert!(
+            Ok(msg)
+                != SigHashCache::new(&tx)
+                    .taproot_key_spend_signature_hash(0, &prevouts, &spent[0], None, 0x00)
+        );
+
+        // Which is the hash checked once the signature is in place
+        tx.input[0].witness = vec![vec![0; 64], annex.clone()];
+        assert_eq!(
+            SigHashCache::new(&tx).taproot_witness_signature_hash(0, &prevouts, &spent[0], 0x00),
+            Ok(msg)
+        );
+    }
+}
@@ -0,0 +1,658 @@
+//! Silent Payments
+//!
+//! BIP352 silent payments let a receiver publish a single static address
+//! without every payment to it landing on the same output script. The
+//! address holds a scan key and a spend key. The sender derives a fresh
+//! taproot output key for each payment from an ECDH secret shared between
+//! the secret keys of its inputs and the scan key, and the receiver finds
+//! its outputs by computing the same secret from the public keys of the
+//! inputs and its scan secret.
+//!
+//! Receivers which do not see full blocks scan with the tweak data of each
+//! transaction, `input_hash`·A, which an index can serve as returned by
+//! `input_tweak`. Which inputs count towards a silent payment, and how to
+//! get their keys, is left to the caller.
+//!
+//! The curve arithmetic is done by a `CurveArithmetic` backend.
+
+use std::str::FromStr;
+use std::{error, fmt};
+
+use blockdata::transaction::OutPoint;
+use consensus::encode::serialize;
+use error::{ErrorCode, ErrorKind};
+use hashes::{sha256, Hash, HashEngine};
+use network::constants::Network;
+use network::params::NetworkRegistry;
+use util::bech32::{self, u5, FromBase32, ToBase32, Variant};
+use util::crypto::CurveArithmetic;
+use util::secret::{DebugSecret, Redacted};
+
+#[cfg(feature = "zeroize")]
+use zeroize::Zeroize;
+
+/// A silent payment error
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error {
+    /// Bech32 encoding error
+    Bech32(bech32::Error),
+    /// The human-readable part is not one of a silent payment address
+    UnknownPrefix(String),
+    /// Addresses use the bech32m checksum
+    InvalidBech32Variant,
+    /// The address version is not one this library can read
+    InvalidVersion(u8),
+    /// The address carries the wrong number of bytes for its version
+    InvalidLength(usize),
+    /// A key or derived tweak was rejected by the curve arithmetic backend
+    InvalidKey,
+    /// No inputs were given to derive the shared secret from
+    NoInputs,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Bech32(ref e) => write!(f, "bech32: {}", e),
+            Error::UnknownPrefix(ref hrp) => write!(f, "unknown prefix: {}", hrp),
+            Error::InvalidVersion(v) => write!(f, "invalid silent payment version: {}", v),
+            Error::InvalidLength(l) => write!(f, "invalid silent payment data length: {}", l),
+            Error::InvalidBech32Variant | Error::InvalidKey | Error::NoInputs => {
+                f.write_str(error::Error::description(self))
+            }
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::Bech32(ref e) => Some(e),
+            _ => None,
+        }
+    }
+
+    fn description(&self) -> &str {
+        match *self {
+            Error::Bech32(..) => "bech32 error",
+            Error::UnknownPrefix(..) => "unknown silent payment address prefix",
+            Error::InvalidBech32Variant => "silent payment addresses use bech32m",
+            Error::InvalidVersion(..) => "invalid silent payment version",
+            Error::InvalidLength(..) => "invalid silent payment data length",
+            Error::InvalidKey => "invalid key or tweak",
+            Error::NoInputs => "no inputs",
+        }
+    }
+}
+
+impl ErrorCode for Error {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::SilentPayments
+    }
+
+    fn number(&self) -> u32 {
+        match *self {
+            Error::Bech32(..) => 1,
+            Error::UnknownPrefix(..) => 2,
+            Error::InvalidBech32Variant => 3,
+            Error::InvalidVersion(..) => 4,
+            Error::InvalidLength(..) => 5,
+            Error::InvalidKey => 6,
+            Error::NoInputs => 7,
+        }
+    }
+}
+
+#[doc(hidden)]
+impl From<bech32::Error> for Error {
+    fn from(e: bech32::Error) -> Error {
+        Error::Bech32(e)
+    }
+}
+
+/// A silent payment address
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+pub struct SilentPaymentAddress {
+    /// The network the address is for
+    pub network: Network,
+    /// The public key whose secret finds payments
+    pub scan_key: [u8; 33],
+    /// The public key, possibly labelled, whose secret spends payments
+    pub spend_key: [u8; 33],
+}
+
+impl fmt::Display for SilentPaymentAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut keys = self.scan_key.to_vec();
+        keys.extend_from_slice(&self.spend_key);
+        let mut data = vec![u5::try_from_u8(0).expect("0 is a u5")];
+        data.extend(keys.to_base32());
+        let encoded = bech32::encode(
+            self.network.params().silent_payment_hrp,
+            &data,
+            Variant::Bech32m,
+        )
+        .map_err(|_| fmt::Error)?;
+        f.write_str(&encoded)
+    }
+}
+
+impl fmt::Debug for SilentPaymentAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+/// Parses an address of version 0, or of a later version up to 30, whose
+/// first 66 bytes are read as the keys of version 0
+impl FromStr for SilentPaymentAddress {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<SilentPaymentAddress, Error> {
+        let (hrp, data, variant) = bech32::decode(s)?;
+        let network = match NetworkRegistry::default().by_silent_payment_hrp(&hrp) {
+            Some(params) => Network::from_params(params).expect("Bitcoin's networks"),
+            None => return Err(Error::UnknownPrefix(hrp)),
+        };
+        if variant != Variant::Bech32m {
+            return Err(Error::InvalidBech32Variant);
+        }
+        let (version, keys) = match data.split_first() {
+            Some((version, keys)) => (version.to_u8(), Vec::<u8>::from_base32(keys)?),
+            None => return Err(Error::InvalidLength(0)),
+        };
+        match version {
+            0 if keys.len() != 66 => return Err(Error::InvalidLength(keys.len())),
+            1...30 if keys.len() < 66 => return Err(Error::InvalidLength(keys.len())),
+            31 => return Err(Error::InvalidVersion(version)),
+            _ => {}
+        }
+
+        let mut address = SilentPaymentAddress {
+            network,
+            scan_key: [0; 33],
+            spend_key: [0; 33],
+        };
+        address.scan_key.copy_from_slice(&keys[..33]);
+        address.spend_key.copy_from_slice(&keys[33..66]);
+        Ok(address)
+    }
+}
+
+/// The BIP340 tagged hash of `data` under `tag`
+fn tagged_hash(tag: &str, data: &[&[u8]]) -> [u8; 32] {
+    let tag = sha256::Hash::hash(tag.as_bytes());
+    let mut engine = sha256::Hash::engine();
+    engine.input(&tag[..]);
+    engine.input(&tag[..]);
+    for part in data {
+        engine.input(part);
+    }
+    sha256::Hash::from_engine(engine).into_inner()
+}
+
+/// The tweak of the `k`th output paying a scan key with the ECDH secret
+/// `shared_secret`
+fn output_tweak(shared_secret: &[u8; 33], k: u32) -> [u8; 32] {
+    tagged_hash(
+        "BIP0352/SharedSecret",
+        &[&shared_secret[..], &k.to_be_bytes()],
+    )
+}
+
+/// Commits to the inputs of a transaction by their smallest outpoint, as
+/// serialized, and the sum of their public keys
+fn input_hash(outpoints: &[OutPoint], input_key: &[u8; 33]) -> Result<[u8; 32], Error> {
+    let smallest = outpoints
+        .iter()
+        .map(serialize)
+        .min()
+        .ok_or(Error::NoInputs)?;
+    Ok(tagged_hash("BIP0352/Inputs", &[&smallest, &input_key[..]]))
+}
+
+/// The sum of `points`
+fn sum_points<C: CurveArithmetic + ?Sized>(
+    crypto: &C,
+    points: &[[u8; 33]],
+) -> Result<[u8; 33], Error> {
+    let (first, rest) = points.split_first().ok_or(Error::NoInputs)?;
+    rest.iter().try_fold(*first, |sum, point| {
+        crypto.add_points(&sum, point).ok_or(Error::InvalidKey)
+    })
+}
+
+/// The tweak data of a transaction, `input_hash`·A, from the public keys
+/// of its inputs which count towards silent payments and the outpoints of
+/// all its inputs. A receiver scans a transaction with this, and its scan
+/// secret, by `Scanner::scan`.
+pub fn input_tweak<C: CurveArithmetic + ?Sized>(
+    crypto: &C,
+    input_keys: &[[u8; 33]],
+    outpoints: &[OutPoint],
+) -> Result<[u8; 33], Error> {
+    let input_key = sum_points(crypto, input_keys)?;
+    let input_hash = input_hash(outpoints, &input_key)?;
+    crypto
+        .mul_point(&input_key, &input_hash)
+        .ok_or(Error::InvalidKey)
+}
+
+/// Derives the taproot output keys paying `recipients`, in their order,
+/// from the secret keys of the inputs which count towards silent payments
+/// and the outpoints of all inputs.
+///
+/// The secret key of a taproot input must be negated by the caller if its
+/// public key has an odd y coordinate, as BIP352 requires.
+pub fn sender_outputs<C: CurveArithmetic + ?Sized>(
+    crypto: &C,
+    input_secrets: &[[u8; 32]],
+    outpoints: &[OutPoint],
+    recipients: &[SilentPaymentAddress],
+) -> Result<Vec<[u8; 32]>, Error> {
+    let (first, rest) = input_secrets.split_first().ok_or(Error::NoInputs)?;
+    let input_secret = rest.iter().try_fold(*first, |sum, secret| {
+        crypto.add_scalars(&sum, secret).ok_or(Error::InvalidKey)
+    })?;
+    let input_key = crypto
+        .mul_generator(&input_secret)
+        .ok_or(Error::InvalidKey)?;
+    let input_hash = input_hash(outpoints, &input_key)?;
+
+    // Outputs to the same scan key are told apart by their count
+    let mut counts: Vec<([u8; 33], [u8; 33], u32)> = vec![];
+    let mut outputs = Vec::with_capacity(recipients.len());
+    for recipient in recipients {
+        let position = match counts.iter().position(|c| c.0 == recipient.scan_key) {
+            Some(position) => position,
+            None => {
+                let shared_secret = crypto
+                    .mul_point(&recipient.scan_key, &input_secret)
+                    .and_then(|point| crypto.mul_point(&point, &input_hash))
+                    .ok_or(Error::InvalidKey)?;
+                counts.push((recipient.scan_key, shared_secret, 0));
+                counts.len() - 1
+            }
+        };
+        let (_, shared_secret, ref mut k) = counts[position];
+        let tweak = output_tweak(&shared_secret, *k);
+        *k += 1;
+        let output = crypto
+            .mul_generator(&tweak)
+            .and_then(|point| crypto.add_points(&recipient.spend_key, &point))
+            .ok_or(Error::InvalidKey)?;
+        outputs.push(x_only(&output));
+    }
+    Ok(outputs)
+}
+
+/// The x coordinate of a point
+fn x_only(point: &[u8; 33]) -> [u8; 32] {
+    let mut x = [0; 32];
+    x.copy_from_slice(&point[1..]);
+    x
+}
+
+/// An output found by `Scanner::scan`
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct FoundOutput {
+    /// The index of the output among those scanned
+    pub index: usize,
+    /// What to add to the spend secret to get the secret of the output
+    pub tweak: [u8; 32],
+    /// The label the output was paid to, if any
+    pub label: Option<u32>,
+}
+
+/// A label, `m`, with its tweak and the tweak times G
+#[derive(Copy, Clone)]
+struct Label {
+    m: u32,
+    tweak: [u8; 32],
+    point: [u8; 33],
+}
+
+/// Finds the outputs paying a receiver, from its scan secret and spend key
+#[derive(Clone)]
+pub struct Scanner {
+    scan_secret: [u8; 32],
+    spend_key: [u8; 33],
+    labels: Vec<Label>,
+}
+
+impl Scanner {
+    /// A scanner for the receiver with the given scan secret and spend
+    /// public key, which finds no labelled payments yet
+    pub fn new(scan_secret: [u8; 32], spend_key: [u8; 33]) -> Scanner {
+        Scanner {
+            scan_secret,
+            spend_key,
+            labels: vec![],
+        }
+    }
+
+    /// The tweak of label `m`, which is added to the spend key of labelled
+    /// addresses. Label 0 is reserved for change.
+    pub fn label_tweak(&self, m: u32) -> [u8; 32] {
+        tagged_hash("BIP0352/Label", &[&self.scan_secret[..], &m.to_be_bytes()])
+    }
+
+    /// Also finds payments to label `m`
+    pub fn add_label<C: CurveArithmetic + ?Sized>(
+        &mut self,
+        crypto: &C,
+        m: u32,
+    ) -> Result<(), Error> {
+        if self.labels.iter().any(|label| label.m == m) {
+            return Ok(());
+        }
+        let tweak = self.label_tweak(m);
+        let point = crypto.mul_generator(&tweak).ok_or(Error::InvalidKey)?;
+        self.labels.push(Label { m, tweak, point });
+        Ok(())
+    }
+
+    /// The address of the receiver on `network`, with label `m` if given
+    pub fn address<C: CurveArithmetic + ?Sized>(
+        &self,
+        crypto: &C,
+        network: Network,
+        label: Option<u32>,
+    ) -> Result<SilentPaymentAddress, Error> {
+        let scan_key = crypto
+            .mul_generator(&self.scan_secret)
+            .ok_or(Error::InvalidKey)?;
+        let spend_key = match label {
+            Some(m) => crypto
+                .mul_generator(&self.label_tweak(m))
+                .and_then(|point| crypto.add_points(&self.spend_key, &point))
+                .ok_or(Error::InvalidKey)?,
+            None => self.spend_key,
+        };
+        Ok(SilentPaymentAddress {
+            network,
+            scan_key,
+            spend_key,
+        })
+    }
+
+    /// Finds the outputs paying the receiver among the x-only keys of the
+    /// taproot `outputs` of a transaction, given its tweak data as returned
+    /// by `input_tweak`
+    pub fn scan<C: CurveArithmetic + ?Sized>(
+        &self,
+        crypto: &C,
+        tweak: &[u8; 33],
+        outputs: &[[u8; 32]],
+    ) -> Result<Vec<FoundOutput>, Error> {
+        let shared_secret = crypto
+            .mul_point(tweak, &self.scan_secret)
+            .ok_or(Error::InvalidKey)?;
+        let mut found: Vec<FoundOutput> = vec![];
+        // The kth output to the receiver can only be found once the ones
+        // before it have been
+        for k in 0.. {
+            let output_tweak = output_tweak(&shared_secret, k);
+            let output_key = crypto
+                .mul_generator(&output_tweak)
+                .and_then(|point| crypto.add_points(&self.spend_key, &point))
+                .ok_or(Error::InvalidKey)?;
+            let candidates = ::std::iter::once((None, output_key, output_tweak));
+            let labelled = self.labels.iter().filter_map(|label| {
+                let key = crypto.add_points(&output_key, &label.point)?;
+                let tweak = crypto.add_scalars(&output_tweak, &label.tweak)?;
+                Some((Some(label.m), key, tweak))
+            });
+
+            let mut found_k = None;
+            'candidates: for (label, key, tweak) in candidates.chain(labelled) {
+                let x = x_only(&key);
+                for (index, output) in outputs.iter().enumerate() {
+                    if *output == x && found.iter().all(|f| f.index != index) {
+                        found_k = Some(FoundOutput {
+                            index,
+                            tweak,
+                            label,
+                        });
+                        break 'candidates;
+                    }
+                }
+            }
+            match found_k {
+                Some(output) => found.push(output),
+                None => break,
+            }
+        }
+        Ok(found)
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for Scanner {
+    fn drop(&mut self) {
+        self.scan_secret.zeroize();
+        for label in &mut self.labels {
+            label.tweak.zeroize();
+        }
+    }
+}
+
+impl fmt::Debug for Scanner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_with_secret(f, &Redacted)
+    }
+}
+
+impl DebugSecret for Scanner {
+    fn fmt_secret(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_with_secret(f, &self.scan_secret)
+    }
+}
+
+impl Scanner {
+    fn fmt_with_secret(&self, f: &mut fmt::Formatter, scan_secret: &fmt::Debug) -> fmt::Result {
+        let labels: Vec<u32> = self.labels.iter().map(|label| label.m).collect();
+        f.debug_struct("Scanner")
+            .field("scan_secret", scan_secret)
+            .field("spend_key", &&self.spend_key[..])
+            .field("labels", &labels)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use hash_types::Txid;
+    use hashes::hex::ToHex;
+
+    /// The integers modulo a prime, standing in for the curve: the point
+    /// s·G is encoded as s, so sums and products can be checked without a
+    /// real backend
+    struct Toy;
+
+    const P: u128 = (1 << 61) - 1;
+
+    fn value(bytes: &[u8]) -> u128 {
+        bytes.iter().fold(0, |v, b| (v * 256 + *b as u128) % P)
+    }
+
+    fn scalar(v: u128) -> Option<[u8; 32]> {
+        let mut bytes = [0; 32];
+        bytes[16..].copy_from_slice(&v.to_be_bytes());
+        if v == 0 {
+            None
+        } else {
+            Some(bytes)
+        }
+    }
+
+    fn point(v: u128) -> Option<[u8; 33]> {
+        scalar(v).map(|s| {
+            let mut bytes = [2; 33];
+            bytes[1..].copy_from_slice(&s);
+            bytes
+        })
+    }
+
+    impl CurveArithmetic for Toy {
+        fn mul_generator(&self, s: &[u8; 32]) -> Option<[u8; 33]> {
+            point(value(s))
+        }
+
+        fn mul_point(&self, p: &[u8; 33], s: &[u8; 32]) -> Option<[u8; 33]> {
+            point(value(&p[1..]) * value(s) % P)
+        }
+
+        fn add_points(&self, a: &[u8; 33], b: &[u8; 33]) -> Option<[u8; 33]> {
+            point((value(&a[1..]) + value(&b[1..])) % P)
+        }
+
+        fn add_scalars(&self, a: &[u8; 32], b: &[u8; 32]) -> Option<[u8; 32]> {
+            scalar((value(a) + value(b)) % P)
+        }
+    }
+
+    fn key(n: u128) -> [u8; 32] {
+        scalar(n).unwrap()
+    }
+
+    #[test]
+    fn address() {
+        // From the BIP352 test vectors
+        let s = "sp1qqgste7k9hx0qftg6qmwlkqtwuy6cycyavzmzj85c6qdfhjdpdjtdgqjuexzk6murw56suy3e0rd2cgqvycxttddwsvgxe2usfpxumr70xc9pkqwv";
+        let address = SilentPaymentAddress::from_str(s).unwrap();
+        assert_eq!(address.network, Network::Bitcoin);
+        assert_eq!(
+            address.scan_key.to_hex(),
+            "0220bcfac5b99e04ad1a06ddfb016ee13582609d60b6291e98d01a9bc9a16c96d4"
+        );
+        assert_eq!(
+            address.spend_key.to_hex(),
+            "025cc9856d6f8375350e123978daac200c260cb5b5ae83106cab90484dcd8fcf36"
+        );
+        assert_eq!(address.to_string(), s);
+        assert_eq!(
+            SilentPaymentAddress::from_str(&s.to_uppercase()).unwrap(),
+            address
+        );
+
+        let encode = |hrp: &str, version: u8, len: usize, variant: Variant| {
+            let mut data = vec![u5::try_from_u8(version).unwrap()];
+            data.extend(vec![2; len].to_base32());
+            bech32::encode(hrp, &data, variant).unwrap()
+        };
+        let regtest = encode("sprt", 0, 66, Variant::Bech32m);
+        let address = SilentPaymentAddress::from_str(&regtest).unwrap();
+        assert_eq!(address.network, Network::Regtest);
+        assert_eq!(address.to_string(), regtest);
+        // Later versions may carry more data
+        let v1 = encode("tsp", 1, 70, Variant::Bech32m);
+        assert_eq!(
+            SilentPaymentAddress::from_str(&v1).unwrap().spend_key,
+            [2; 33]
+        );
+
+        let parse = |s: String| SilentPaymentAddress::from_str(&s).unwrap_err();
+        assert_eq!(
+            parse(encode("sp", 0, 67, Variant::Bech32m)),
+            Error::InvalidLength(67)
+        );
+        assert_eq!(
+            parse(encode("sp", 2, 65, Variant::Bech32m)),
+            Error::InvalidLength(65)
+        );
+        assert_eq!(
+            parse(encode("sp", 31, 66, Variant::Bech32m)),
+            Error::InvalidVersion(31)
+        );
+        assert_eq!(
+            parse(encode("sp", 0, 66, Variant::Bech32)),
+            Error::InvalidBech32Variant
+        );
+        assert_eq!(
+            parse(encode("bc", 0, 66, Variant::Bech32m)),
+            Error::UnknownPrefix("bc".to_owned())
+        );
+    }
+
+    #[test]
+    fn send_and_scan() {
+        let mut scanner = Scanner::new(key(3), point(5).unwrap());
+        scanner.add_label(&Toy, 1).unwrap();
+        let address = scanner.address(&Toy, Network::Bitcoin, None).unwrap();
+        assert_eq!(address.scan_key, point(3).unwrap());
+        let labelled = scanner.address(&Toy, Network::Bitcoin, Some(1)).unwrap();
+        let unknown_label = scanner.address(&Toy, Network::Bitcoin, Some(2)).unwrap();
+        let other = Scanner::new(key(7), point(11).unwrap())
+            .address(&Toy, Network::Bitcoin, None)
+            .unwrap();
+
+        let outpoints = [
+            OutPoint::new(Txid::hash(&[2]), 0),
+            OutPoint::new(Txid::hash(&[1]), 3),
+        ];
+        let outputs = sender_outputs(
+            &Toy,
+            &[key(13), key(17)],
+            &outpoints,
+            &[address, other, labelled, address, unknown_label],
+        )
+        .unwrap();
+        assert_eq!(outputs.len(), 5);
+        // Each payment to the same address gets its own output
+        assert_ne!(outputs[0], outputs[3]);
+
+        // The smallest outpoint is the same whatever the order
+        let tweak = input_tweak(
+            &Toy,
+            &[point(13).unwrap(), point(17).unwrap()],
+            &[outpoints[1], outpoints[0]],
+        )
+        .unwrap();
+        let mut scanned = vec![[9; 32]];
+        scanned.extend_from_slice(&outputs);
+        let found = scanner.scan(&Toy, &tweak, &scanned).unwrap();
+        assert_eq!(
+            found.iter().map(|f| (f.index, f.label)).collect::<Vec<_>>(),
+            vec![(1, None), (3, Some(1)), (4, None)]
+        );
+        // The tweak added to the spend secret gives the secret of the output
+        for output in &found {
+            let secret = Toy.add_scalars(&key(5), &output.tweak).unwrap();
+            assert_eq!(
+                Toy.mul_generator(&secret).unwrap()[1..],
+                scanned[output.index][..]
+            );
+        }
+
+        // A transaction paying someone else, or with other inputs
+        let other_tweak = input_tweak(&Toy, &[point(13).unwrap()], &outpoints).unwrap();
+        assert!(scanner
+            .scan(&Toy, &other_tweak, &scanned)
+            .unwrap()
+            .is_empty());
+        assert!(scanner
+            .scan(&Toy, &tweak, &scanned[2..3])
+            .unwrap()
+            .is_empty());
+
+        assert_eq!(
+            sender_outputs(&Toy, &[], &outpoints, &[address]),
+            Err(Error::NoInputs)
+        );
+        assert_eq!(
+            input_tweak(&Toy, &[point(1).unwrap()], &[]),
+            Err(Error::NoInputs)
+        );
+    }
+
+    #[test]
+    fn debug_redacts_scan_secret() {
+        let scanner = Scanner::new(key(3), [2; 33]);
+        let debug = format!("{:?}", scanner);
+        assert!(debug.contains("scan_secret: [redacted]"));
+        assert!(!format!("{:?}", scanner.dangerous_debug()).contains("[redacted]"));
+    }
+}
@@ -0,0 +1,275 @@
+//! SipHash 2-4
+//!
+//! The SipHash-2-4 keyed hash function with 64-bit output, as specified in
+//! the paper at https://131002.net/siphash/siphash.pdf. It is used for the
+//! short transaction IDs of BIP152 and the element hashing of BIP158.
+
+use std::hash;
+
+use util::endian;
+
+macro_rules! compress {
+    ($v0:expr, $v1:expr, $v2:expr, $v3:expr) => {{
+        $v0 = $v0.wrapping_add($v1);
+        $v1 = $v1.rotate_left(13);
+        $v1 ^= $v0;
+        $v0 = $v0.rotate_left(32);
+        $v2 = $v2.wrapping_add($v3);
+        $v3 = $v3.rotate_left(16);
+        $v3 ^= $v2;
+        $v0 = $v0.wrapping_add($v3);
+        $v3 = $v3.rotate_left(21);
+        $v3 ^= $v0;
+        $v2 = $v2.wrapping_add($v1);
+        $v1 = $v1.rotate_left(17);
+        $v1 ^= $v2;
+        $v2 = $v2.rotate_left(32);
+    }};
+}
+
+/// A SipHash-2-4 hasher, keyed with a 128-bit key.
+///
+/// Data is fed in with [`write`] and the 64-bit hash of everything written
+/// so far is returned by [`finish`]. The hasher also implements
+/// `std::hash::Hasher`.
+///
+/// [`write`]: #method.write
+/// [`finish`]: #method.finish
+#[derive(Clone, Debug)]
+pub struct SipHasher24 {
+    v0: u64,
+    v1: u64,
+    v2: u64,
+    v3: u64,
+    /// Unprocessed bytes, little endian
+    tail: u64,
+    /// How many bytes in `tail` are valid
+    ntail: usize,
+    /// Total number of bytes written
+    length: usize,
+}
+
+impl SipHasher24 {
+    /// Creates a new hasher keyed with the two halves of the key.
+    pub fn new_with_keys(k0: u64, k1: u64) -> SipHasher24 {
+        SipHasher24 {
+            v0: k0 ^ 0x736f6d6570736575,
+            v1: k1 ^ 0x646f72616e646f6d,
+            v2: k0 ^ 0x6c7967656e657261,
+            v3: k1 ^ 0x7465646279746573,
+            tail: 0,
+            ntail: 0,
+            length: 0,
+        }
+    }
+
+    /// Creates a new hasher keyed with a 16-byte key, read as two
+    /// little-endian halves.
+    pub fn new_with_key(key: &[u8; 16]) -> SipHasher24 {
+        SipHasher24::new_with_keys(
+            endian::slice_to_u64_le(&key[0..8]),
+            endian::slice_to_u64_le(&key[8..16]),
+        )
+    }
+
+    /// Hashes `data` with the two halves of the key in one go.
+    pub fn hash_with_keys(k0: u64, k1: u64, data: &[u8]) -> u64 {
+        let mut hasher = SipHasher24::new_with_keys(k0, k1);
+        hasher.write(data);
+        hasher.finish()
+    }
+
+    #[inline]
+    fn c_rounds(&mut self, m: u64) {
+        self.v3 ^= m;
+        compress!(self.v0, self.v1, self.v2, self.v3);
+        compress!(self.v0, self.v1, self.v2, self.v3);
+        self.v0 ^= m;
+    }
+
+    /// Feeds `msg` into the hasher.
+    pub fn write(&mut self, msg: &[u8]) {
+        self.length += msg.len();
+
+        let mut i = 0;
+        // Top up the tail first
+        while self.ntail > 0 && self.ntail < 8 && i < msg.len() {
+            self.tail |= (msg[i] as u64) << (8 * self.ntail);
+            self.ntail += 1;
+            i += 1;
+        }
+        if self.ntail == 8 {
+            let m = self.tail;
+            self.c_rounds(m);
+            self.tail = 0;
+            self.ntail = 0;
+        }
+        if self.ntail > 0 {
+            // `msg` is used up without completing a word
+            return;
+        }
+
+        while i + 8 <= msg.len() {
+            let m = endian::slice_to_u64_le(&msg[i..i + 8]);
+            self.c_rounds(m);
+            i += 8;
+        }
+
+        for (n, byte) in msg[i..].iter().enumerate() {
+            self.tail |= (*byte as u64) << (8 * n);
+        }
+        self.ntail = msg.len() - i;
+    }
+
+    /// Returns the hash of the data written so far. The hasher can still
+    /// be written to afterwards.
+    pub fn finish(&self) -> u64 {
+        let mut v0 = self.v0;
+        let mut v1 = self.v1;
+        let mut v2 = self.v2;
+        let mut v3 = self.v3;
+
+        let b: u64 = ((self.length as u64 & 0xff) << 56) | self.tail;
+
+        v3 ^= b;
+        compress!(v0, v1, v2, v3);
+        compress!(v0, v1, v2, v3);
+        v0 ^= b;
+
+        v2 ^= 0xff;
+        compress!(v0, v1, v2, v3);
+        compress!(v0, v1, v2, v3);
+        compress!(v0, v1, v2, v3);
+        compress!(v0, v1, v2, v3);
+
+        v0 ^ v1 ^ v2 ^ v3
+    }
+}
+
+impl hash::Hasher for SipHasher24 {
+    fn write(&mut self, msg: &[u8]) {
+        SipHasher24::write(self, msg)
+    }
+
+    fn finish(&self) -> u64 {
+        SipHasher24::finish(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::hash::Hasher;
+
+    #[test]
+    fn test_siphash_2_4() {
+        // Test vectors from the reference implementation accompanying the
+        // paper: the key is 00..0f and the i-th message is 00..(i-1). The
+        // outputs are little endian.
+        let vecs: [[u8; 8]; 64] = [
+            [0x31, 0x0e, 0x0e, 0xdd, 0x47, 0xdb, 0x6f, 0x72],
+            [0xfd, 0x67, 0xdc, 0x93, 0xc5, 0x39, 0xf8, 0x74],
+            [0x5a, 0x4f, 0xa9, 0xd9, 0x09, 0x80, 0x6c, 0x0d],
+            [0x2d, 0x7e, 0xfb, 0xd7, 0x96, 0x66, 0x67, 0x85],
+            [0xb7, 0x87, 0x71, 0x27, 0xe0, 0x94, 0x27, 0xcf],
+            [0x8d, 0xa6, 0x99, 0xcd, 0x64, 0x55, 0x76, 0x18],
+            [0xce, 0xe3, 0xfe, 0x58, 0x6e, 0x46, 0xc9, 0xcb],
+            [0x37, 0xd1, 0x01, 0x8b, 0xf5, 0x00, 0x02, 0xab],
+            [0x62, 0x24, 0x93, 0x9a, 0x79, 0xf5, 0xf5, 0x93],
+            [0xb0, 0xe4, 0xa9, 0x0b, 0xdf, 0x82, 0x00, 0x9e],
+            [0xf3, 0xb9, 0xdd, 0x94, 0xc5, 0xbb, 0x5d, 0x7a],
+            [0xa7, 0xad, 0x6b, 0x22, 0x46, 0x2f, 0xb3, 0xf4],
+            [0xfb, 0xe5, 0x0e, 0x86, 0xbc, 0x8f, 0x1e, 0x75],
+            [0x90, 0x3d, 0x84, 0xc0, 0x27, 0x56, 0xea, 0x14],
+            [0xee, 0xf2, 0x7a, 0x8e, 0x90, 0xca, 0x23, 0xf7],
+            [0xe5, 0x45, 0xbe, 0x49, 0x61, 0xca, 0x29, 0xa1],
+            [0xdb, 0x9b, 0xc2, 0x57, 0x7f, 0xcc, 0x2a, 0x3f],
+            [0x94, 0x47, 0xbe, 0x2c, 0xf5, 0xe9, 0x9a, 0x69],
+            [0x9c, 0xd3, 0x8d, 0x96, 0xf0, 0xb3, 0xc1, 0x4b],
+            [0xbd, 0x61, 0x79, 0xa7, 0x1d, 0xc9, 0x6d, 0xbb],
+            [0x98, 0xee, 0xa2, 0x1a, 0xf2, 0x5c, 0xd6, 0xbe],
+            [0xc7, 0x67, 0x3b, 0x2e, 0xb0, 0xcb, 0xf2, 0xd0],
+            [0x88, 0x3e, 0xa3, 0xe3, 0x95, 0x67, 0x53, 0x93],
+            [0xc8, 0xce, 0x5c, 0xcd, 0x8c, 0x03, 0x0c, 0xa8],
+            [0x94, 0xaf, 0x49, 0xf6, 0xc6, 0x50, 0xad, 0xb8],
+            [0xea, 0xb8, 0x85, 0x8a, 0xde, 0x92, 0xe1, 0xbc],
+            [0xf3, 0x15, 0xbb, 0x5b, 0xb8, 0x35, 0xd8, 0x17],
+            [0xad, 0xcf, 0x6b, 0x07, 0x63, 0x61, 0x2e, 0x2f],
+            [0xa5, 0xc9, 0x1d, 0xa7, 0xac, 0xaa, 0x4d, 0xde],
+            [0x71, 0x65, 0x95, 0x87, 0x66, 0x50, 0xa2, 0xa6],
+            [0x28, 0xef, 0x49, 0x5c, 0x53, 0xa3, 0x87, 0xad],
+            [0x42, 0xc3, 0x41, 0xd8, 0xfa, 0x92, 0xd8, 0x32],
+            [0xce, 0x7c, 0xf2, 0x72, 0x2f, 0x51, 0x27, 0x71],
+            [0xe3, 0x78, 0x59, 0xf9, 0x46, 0x23, 0xf3, 0xa7],
+            [0x38, 0x12, 0x05, 0xbb, 0x1a, 0xb0, 0xe0, 0x12],
+            [0xae, 0x97, 0xa1, 0x0f, 0xd4, 0x34, 0xe0, 0x15],
+            [0xb4, 0xa3, 0x15, 0x08, 0xbe, 0xff, 0x4d, 0x31],
+            [0x81, 0x39, 0x62, 0x29, 0xf0, 0x90, 0x79, 0x02],
+            [0x4d, 0x0c, 0xf4, 0x9e, 0xe5, 0xd4, 0xdc, 0xca],
+            [0x5c, 0x73, 0x33, 0x6a, 0x76, 0xd8, 0xbf, 0x9a],
+            [0xd0, 0xa7, 0x04, 0x53, 0x6b, 0xa9, 0x3e, 0x0e],
+            [0x92, 0x59, 0x58, 0xfc, 0xd6, 0x42, 0x0c, 0xad],
+            [0xa9, 0x15, 0xc2, 0x9b, 0xc8, 0x06, 0x73, 0x18],
+            [0x95, 0x2b, 0x79, 0xf3, 0xbc, 0x0a, 0xa6, 0xd4],
+            [0xf2, 0x1d, 0xf2, 0xe4, 0x1d, 0x45, 0x35, 0xf9],
+            [0x87, 0x57, 0x75, 0x19, 0x04, 0x8f, 0x53, 0xa9],
+            [0x10, 0xa5, 0x6c, 0xf5, 0xdf, 0xcd, 0x9a, 0xdb],
+            [0xeb, 0x75, 0x09, 0x5c, 0xcd, 0x98, 0x6c, 0xd0],
+            [0x51, 0xa9, 0xcb, 0x9e, 0xcb, 0xa3, 0x12, 0xe6],
+            [0x96, 0xaf, 0xad, 0xfc, 0x2c, 0xe6, 0x66, 0xc7],
+            [0x72, 0xfe, 0x52, 0x97, 0x5a, 0x43, 0x64, 0xee],
+            [0x5a, 0x16, 0x45, 0xb2, 0x76, 0xd5, 0x92, 0xa1],
+            [0xb2, 0x74, 0xcb, 0x8e, 0xbf, 0x87, 0x87, 0x0a],
+            [0x6f, 0x9b, 0xb4, 0x20, 0x3d, 0xe7, 0xb3, 0x81],
+            [0xea, 0xec, 0xb2, 0xa3, 0x0b, 0x22, 0xa8, 0x7f],
+            [0x99, 0x24, 0xa4, 0x3c, 0xc1, 0x31, 0x57, 0x24],
+            [0xbd, 0x83, 0x8d, 0x3a, 0xaf, 0xbf, 0x8d, 0xb7],
+            [0x0b, 0x1a, 0x2a, 0x32, 0x65, 0xd5, 0x1a, 0xea],
+            [0x13, 0x50, 0x79, 0xa3, 0x23, 0x1c, 0xe6, 0x60],
+            [0x93, 0x2b, 0x28, 0x46, 0xe4, 0xd7, 0x06, 0x66],
+            [0xe1, 0x91, 0x5f, 0x5c, 0xb1, 0xec, 0xa4, 0x6c],
+            [0xf3, 0x25, 0x96, 0x5c, 0xa1, 0x6d, 0x62, 0x9f],
+            [0x57, 0x5f, 0xf2, 0x8e, 0x60, 0x38, 0x1b, 0xe5],
+            [0x72, 0x45, 0x06, 0xeb, 0x4c, 0x32, 0x8a, 0x95],
+        ];
+
+        let mut key = [0u8; 16];
+        for i in 0..16 {
+            key[i] = i as u8;
+        }
+        let k0 = endian::slice_to_u64_le(&key[0..8]);
+        let k1 = endian::slice_to_u64_le(&key[8..16]);
+
+        let mut msg = Vec::new();
+        let mut incremental = SipHasher24::new_with_key(&key);
+        for i in 0..64 {
+            let expected = endian::slice_to_u64_le(&vecs[i]);
+
+            assert_eq!(SipHasher24::hash_with_keys(k0, k1, &msg), expected);
+            // Fed in one byte at a time
+            assert_eq!(incremental.finish(), expected);
+            // Fed in through the std trait, split unevenly
+            let mut hasher = SipHasher24::new_with_keys(k0, k1);
+            let (a, b) = msg.split_at(i / 3);
+            Hasher::write(&mut hasher, a);
+            Hasher::write(&mut hasher, b);
+            assert_eq!(Hasher::finish(&hasher), expected);
+
+            msg.push(i as u8);
+            incremental.write(&[i as u8]);
+        }
+    }
+
+    #[test]
+    fn test_paper_example() {
+        // The worked example in appendix A of the paper
+        let k0 = 0x0706050403020100;
+        let k1 = 0x0f0e0d0c0b0a0908;
+        let msg: Vec<u8> = (0..15).collect();
+        assert_eq!(
+            SipHasher24::hash_with_keys(k0, k1, &msg),
+            0xa129ca6149be45e5
+        );
+    }
+}
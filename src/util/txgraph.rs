@@ -0,0 +1,378 @@
+//! Transaction Graph
+//!
+//! Transactions spending the outputs of other transactions form a graph,
+//! and both mempool policy and chain analysis are about its shape: a node
+//! limits how many ancestors and descendants an unconfirmed transaction may
+//! have, miners choose transactions by the fee rate of each together with
+//! its unconfirmed ancestors, and a wallet bumps a stuck parent by paying
+//! for it in a child (CPFP).
+//!
+//! `TxGraph` holds transactions with their fees and links each to the
+//! transactions in the graph it spends and is spent by, whichever order
+//! they are inserted in.
+
+use std::collections::{HashMap, HashSet};
+
+use blockdata::transaction::Transaction;
+use hash_types::Txid;
+use util::amount::Amount;
+use util::fee_rate::FeeRate;
+use util::weight::Weight;
+
+/// The total fee and weight of a set of transactions
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Package {
+    /// The number of transactions
+    pub count: usize,
+    /// Their total fee
+    pub fee: Amount,
+    /// Their total weight
+    pub weight: Weight,
+}
+
+impl Package {
+    /// The fee rate of the transactions taken together
+    pub fn fee_rate(&self) -> FeeRate {
+        self.fee / self.weight
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Entry {
+    tx: Transaction,
+    fee: Amount,
+    weight: Weight,
+}
+
+/// A graph of transactions linked by the outputs they spend
+#[derive(Clone, Debug, Default)]
+pub struct TxGraph {
+    entries: HashMap<Txid, Entry>,
+    /// The transactions spending each txid, which need not be in the graph
+    spenders: HashMap<Txid, HashSet<Txid>>,
+}
+
+impl TxGraph {
+    /// Creates an empty graph
+    pub fn new() -> TxGraph {
+        TxGraph::default()
+    }
+
+    /// Adds a transaction paying `fee`, returning false if it is already
+    /// in the graph
+    pub fn insert(&mut self, tx: Transaction, fee: Amount) -> bool {
+        let txid = tx.txid();
+        if self.entries.contains_key(&txid) {
+            return false;
+        }
+        for input in &tx.input {
+            self.spenders
+                .entry(input.previous_output.txid)
+                .or_default()
+                .insert(txid);
+        }
+        let weight = Weight::from_wu(tx.get_weight() as u64);
+        self.entries.insert(txid, Entry { tx, fee, weight });
+        true
+    }
+
+    /// Removes a transaction, leaving its relatives in the graph
+    pub fn remove(&mut self, txid: &Txid) -> Option<Transaction> {
+        let entry = self.entries.remove(txid)?;
+        for input in &entry.tx.input {
+            let parent = input.previous_output.txid;
+            let now_empty = match self.spenders.get_mut(&parent) {
+                Some(spenders) => {
+                    spenders.remove(txid);
+                    spenders.is_empty()
+                }
+                None => false,
+            };
+            if now_empty {
+                self.spenders.remove(&parent);
+            }
+        }
+        Some(entry.tx)
+    }
+
+    /// Whether a transaction is in the graph
+    pub fn contains(&self, txid: &Txid) -> bool {
+        self.entries.contains_key(txid)
+    }
+
+    /// A transaction in the graph
+    pub fn get(&self, txid: &Txid) -> Option<&Transaction> {
+        self.entries.get(txid).map(|e| &e.tx)
+    }
+
+    /// The fee of a transaction in the graph
+    pub fn fee(&self, txid: &Txid) -> Option<Amount> {
+        self.entries.get(txid).map(|e| e.fee)
+    }
+
+    /// The number of transactions in the graph
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether the graph is empty
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The transactions in the graph which a transaction spends
+    pub fn parents(&self, txid: &Txid) -> HashSet<Txid> {
+        match self.entries.get(txid) {
+            Some(entry) => entry
+                .tx
+                .input
+                .iter()
+                .map(|input| input.previous_output.txid)
+                .filter(|parent| self.entries.contains_key(parent))
+                .collect(),
+            None => HashSet::new(),
+        }
+    }
+
+    /// The transactions in the graph which spend a transaction
+    pub fn children(&self, txid: &Txid) -> HashSet<Txid> {
+        match self.spenders.get(txid) {
+            Some(spenders) if self.entries.contains_key(txid) => spenders.clone(),
+            _ => HashSet::new(),
+        }
+    }
+
+    /// The transactions a transaction spends, directly or not, leaving
+    /// out the transaction itself
+    pub fn ancestors(&self, txid: &Txid) -> HashSet<Txid> {
+        self.reachable(txid, |graph, txid| graph.parents(txid))
+    }
+
+    /// The transactions which spend a transaction, directly or not,
+    /// leaving out the transaction itself
+    pub fn descendants(&self, txid: &Txid) -> HashSet<Txid> {
+        self.reachable(txid, |graph, txid| graph.children(txid))
+    }
+
+    /// The transactions connected to a transaction by spending in either
+    /// direction, including the transaction itself if it is in the graph
+    pub fn cluster(&self, txid: &Txid) -> HashSet<Txid> {
+        let mut cluster = self.reachable(txid, |graph, txid| {
+            let mut relatives = graph.parents(txid);
+            relatives.extend(graph.children(txid));
+            relatives
+        });
+        if self.contains(txid) {
+            cluster.insert(*txid);
+        }
+        cluster
+    }
+
+    /// Every cluster in the graph, each in an order in which parents come
+    /// before their children
+    pub fn clusters(&self) -> Vec<Vec<Txid>> {
+        let mut txids: Vec<&Txid> = self.entries.keys().collect();
+        txids.sort();
+        let mut seen = HashSet::new();
+        let mut clusters = vec![];
+        for txid in txids {
+            if seen.contains(txid) {
+                continue;
+            }
+            let cluster = self.cluster(txid);
+            seen.extend(cluster.iter().cloned());
+            // A transaction has more ancestors than any of its ancestors
+            let mut cluster: Vec<(usize, Txid)> = cluster
+                .into_iter()
+                .map(|txid| (self.ancestors(&txid).len(), txid))
+                .collect();
+            cluster.sort();
+            clusters.push(cluster.into_iter().map(|(_, txid)| txid).collect());
+        }
+        clusters
+    }
+
+    /// A transaction together with its ancestors, which miners consider
+    /// as one when choosing transactions
+    pub fn ancestor_package(&self, txid: &Txid) -> Option<Package> {
+        if !self.contains(txid) {
+            return None;
+        }
+        let mut package = self.ancestors(txid);
+        package.insert(*txid);
+        self.package(&package)
+    }
+
+    /// A transaction together with its descendants, which are evicted
+    /// along with it
+    pub fn descendant_package(&self, txid: &Txid) -> Option<Package> {
+        if !self.contains(txid) {
+            return None;
+        }
+        let mut package = self.descendants(txid);
+        package.insert(*txid);
+        self.package(&package)
+    }
+
+    /// The fee a new child of `child_weight` spending a transaction must
+    /// pay for the transaction, its ancestors and the child to reach the
+    /// fee rate `target` together, which is zero if they already do
+    /// without the child paying anything
+    pub fn cpfp_fee(&self, txid: &Txid, child_weight: Weight, target: FeeRate) -> Option<Amount> {
+        let package = self.ancestor_package(txid)?;
+        let needed = target.fee_wu(package.weight.checked_add(child_weight)?)?;
+        Some(needed.checked_sub(package.fee).unwrap_or(Amount::ZERO))
+    }
+
+    /// The total fee and weight of transactions in the graph
+    fn package(&self, txids: &HashSet<Txid>) -> Option<Package> {
+        let mut package = Package {
+            count: 0,
+            fee: Amount::ZERO,
+            weight: Weight::from_wu(0),
+        };
+        for txid in txids {
+            let entry = self.entries.get(txid)?;
+            package.count += 1;
+            package.fee = package.fee.checked_add(entry.fee)?;
+            package.weight = package.weight.checked_add(entry.weight)?;
+        }
+        Some(package)
+    }
+
+    /// The transactions reached from `start` by following `next`
+    fn reachable<F>(&self, start: &Txid, next: F) -> HashSet<Txid>
+    where
+        F: Fn(&TxGraph, &Txid) -> HashSet<Txid>,
+    {
+        let mut reached = HashSet::new();
+        let mut queue = vec![*start];
+        while let Some(txid) = queue.pop() {
+            for relative in next(self, &txid) {
+                if relative != *start && reached.insert(relative) {
+                    queue.push(relative);
+                }
+            }
+        }
+        reached
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use blockdata::transaction::{OutPoint, TxIn, TxOut};
+    use hashes::Hash;
+
+    /// A transaction spending the first outputs of `parents`, with
+    /// `tag` making it unique
+    fn spend(parents: &[&Transaction], tag: u8) -> Transaction {
+        let mut input: Vec<TxIn> = parents
+            .iter()
+            .map(|parent| TxIn {
+                previous_output: OutPoint::new(parent.txid(), 0),
+                ..Default::default()
+            })
+            .collect();
+        if input.is_empty() {
+            input.push(TxIn {
+                previous_output: OutPoint::new(Txid::hash(&[tag]), 0),
+                ..Default::default()
+            });
+        }
+        Transaction {
+            version: 2,
+            lock_time: tag as u32,
+            input,
+            output: vec![TxOut::default()],
+        }
+    }
+
+    fn set(txids: &[&Transaction]) -> HashSet<Txid> {
+        txids.iter().map(|tx| tx.txid()).collect()
+    }
+
+    #[test]
+    fn relatives() {
+        // a <- b <- c, and d spending both a and the unrelated x
+        let a = spend(&[], 1);
+        let b = spend(&[&a], 2);
+        let c = spend(&[&b], 3);
+        let x = spend(&[], 4);
+        let d = spend(&[&a, &x], 5);
+        let e = spend(&[], 6);
+
+        let mut graph = TxGraph::new();
+        // Children before their parents
+        for tx in &[&c, &d, &b, &a, &e] {
+            assert!(graph.insert((*tx).clone(), Amount::from_sat(100)));
+        }
+        assert!(!graph.insert(a.clone(), Amount::ZERO));
+        assert_eq!(graph.len(), 5);
+
+        assert_eq!(graph.parents(&d.txid()), set(&[&a]));
+        assert_eq!(graph.children(&a.txid()), set(&[&b, &d]));
+        assert_eq!(graph.ancestors(&c.txid()), set(&[&a, &b]));
+        assert_eq!(graph.descendants(&a.txid()), set(&[&b, &c, &d]));
+        assert_eq!(graph.descendants(&x.txid()), set(&[]));
+        assert_eq!(graph.cluster(&c.txid()), set(&[&a, &b, &c, &d]));
+
+        let clusters = graph.clusters();
+        assert_eq!(clusters.len(), 2);
+        let big = clusters.iter().find(|c| c.len() == 4).unwrap();
+        let position = |tx: &Transaction| big.iter().position(|t| *t == tx.txid());
+        assert_eq!(position(&a), Some(0));
+        assert!(position(&b) < position(&c));
+        assert!(clusters.contains(&vec![e.txid()]));
+
+        // Removing b splits its cluster
+        assert_eq!(graph.remove(&b.txid()), Some(b.clone()));
+        assert_eq!(graph.remove(&b.txid()), None);
+        assert_eq!(graph.ancestors(&c.txid()), set(&[]));
+        assert_eq!(graph.children(&a.txid()), set(&[&d]));
+        assert_eq!(graph.clusters().len(), 3);
+
+        graph.insert(b.clone(), Amount::ZERO);
+        assert_eq!(graph.ancestors(&c.txid()), set(&[&a, &b]));
+    }
+
+    #[test]
+    fn packages() {
+        let a = spend(&[], 1);
+        let b = spend(&[&a], 2);
+        let c = spend(&[&b], 3);
+        let weight = Weight::from_wu(a.get_weight() as u64);
+
+        let mut graph = TxGraph::new();
+        graph.insert(a.clone(), Amount::ZERO);
+        graph.insert(b.clone(), Amount::from_sat(1000));
+        graph.insert(c.clone(), Amount::from_sat(500));
+
+        let package = graph.ancestor_package(&c.txid()).unwrap();
+        assert_eq!(package.count, 3);
+        assert_eq!(package.fee, Amount::from_sat(1500));
+        assert_eq!(package.weight, weight * 3);
+        assert_eq!(package.fee_rate(), Amount::from_sat(1500) / (weight * 3));
+        let package = graph.descendant_package(&b.txid()).unwrap();
+        assert_eq!(package.count, 2);
+        assert_eq!(package.fee, Amount::from_sat(1500));
+        assert_eq!(graph.ancestor_package(&Txid::hash(&[9])), None);
+
+        // Bumping a to 10 sat/vB with a child of the same weight
+        let target = FeeRate::from_sat_per_vb(10).unwrap();
+        assert_eq!(
+            graph.cpfp_fee(&a.txid(), weight, target),
+            Some(target * (weight * 2))
+        );
+        // b already pays enough for a at a low rate
+        assert_eq!(
+            graph.cpfp_fee(&b.txid(), Weight::from_wu(0), FeeRate::MIN),
+            Some(Amount::ZERO)
+        );
+        assert_eq!(
+            graph.cpfp_fee(&c.txid(), weight, target),
+            Some(target * (weight * 4) - Amount::from_sat(1500))
+        );
+    }
+}
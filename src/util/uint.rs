@@ -0,0 +1,292 @@
+//! Big unsigned integer types
+//!
+//! Implementation of a 256-bit unsigned integer, as needed for proof-of-work
+//! targets and cumulative chain work. Only the arithmetic those need is
+//! provided.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, Div, Not, Shl, Shr, Sub};
+
+/// A 256-bit unsigned integer, stored as four little-endian 64-bit words.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub struct Uint256(pub [u64; 4]);
+
+impl Uint256 {
+    /// Zero
+    pub fn zero() -> Uint256 {
+        Uint256([0; 4])
+    }
+
+    /// Create a value from a `u64`
+    pub fn from_u64(n: u64) -> Uint256 {
+        Uint256([n, 0, 0, 0])
+    }
+
+    /// Interpret 32 little-endian bytes, such as a hash, as an integer
+    pub fn from_le_bytes(bytes: [u8; 32]) -> Uint256 {
+        let mut ret = [0u64; 4];
+        for (i, word) in ret.iter_mut().enumerate() {
+            for j in 0..8 {
+                *word |= (bytes[i * 8 + j] as u64) << (j * 8);
+            }
+        }
+        Uint256(ret)
+    }
+
+    /// The value as 32 little-endian bytes
+    pub fn to_le_bytes(&self) -> [u8; 32] {
+        let mut ret = [0u8; 32];
+        for (i, word) in self.0.iter().enumerate() {
+            for j in 0..8 {
+                ret[i * 8 + j] = (word >> (j * 8)) as u8;
+            }
+        }
+        ret
+    }
+
+    /// The low 64 bits of the value
+    pub fn low_u64(&self) -> u64 {
+        self.0[0]
+    }
+
+    /// Whether the value is zero
+    pub fn is_zero(&self) -> bool {
+        self.0.iter().all(|&w| w == 0)
+    }
+
+    /// The number of bits needed to represent the value
+    pub fn bits(&self) -> usize {
+        for i in (0..4).rev() {
+            if self.0[i] > 0 {
+                return 64 * i + (64 - self.0[i].leading_zeros() as usize);
+            }
+        }
+        0
+    }
+
+    /// Add, returning the result and whether it overflowed
+    pub fn overflowing_add(self, other: Uint256) -> (Uint256, bool) {
+        let mut ret = [0u64; 4];
+        let mut carry = false;
+        for i in 0..4 {
+            let (sum, c1) = self.0[i].overflowing_add(other.0[i]);
+            let (sum, c2) = sum.overflowing_add(carry as u64);
+            ret[i] = sum;
+            carry = c1 || c2;
+        }
+        (Uint256(ret), carry)
+    }
+
+    /// Subtract, returning the result and whether it underflowed
+    pub fn overflowing_sub(self, other: Uint256) -> (Uint256, bool) {
+        let mut ret = [0u64; 4];
+        let mut borrow = false;
+        for i in 0..4 {
+            let (diff, b1) = self.0[i].overflowing_sub(other.0[i]);
+            let (diff, b2) = diff.overflowing_sub(borrow as u64);
+            ret[i] = diff;
+            borrow = b1 || b2;
+        }
+        (Uint256(ret), borrow)
+    }
+}
+
+impl Add for Uint256 {
+    type Output = Uint256;
+
+    /// # Panics
+    ///
+    /// On overflow.
+    fn add(self, other: Uint256) -> Uint256 {
+        let (ret, overflow) = self.overflowing_add(other);
+        assert!(!overflow, "Uint256 addition overflow");
+        ret
+    }
+}
+
+impl Sub for Uint256 {
+    type Output = Uint256;
+
+    /// # Panics
+    ///
+    /// On underflow.
+    fn sub(self, other: Uint256) -> Uint256 {
+        let (ret, underflow) = self.overflowing_sub(other);
+        assert!(!underflow, "Uint256 subtraction underflow");
+        ret
+    }
+}
+
+impl Div for Uint256 {
+    type Output = Uint256;
+
+    /// Long division, one bit at a time.
+    ///
+    /// # Panics
+    ///
+    /// On division by zero.
+    fn div(self, other: Uint256) -> Uint256 {
+        assert!(!other.is_zero(), "Uint256 division by zero");
+        let mut rem = self;
+        let mut ret = Uint256::zero();
+        let my_bits = self.bits();
+        let your_bits = other.bits();
+        if my_bits < your_bits {
+            return ret;
+        }
+        let mut shift = my_bits - your_bits;
+        let mut divisor = other << shift;
+        loop {
+            if rem >= divisor {
+                rem = rem - divisor;
+                ret.0[shift / 64] |= 1 << (shift % 64);
+            }
+            if shift == 0 {
+                break;
+            }
+            divisor = divisor >> 1;
+            shift -= 1;
+        }
+        ret
+    }
+}
+
+impl Not for Uint256 {
+    type Output = Uint256;
+
+    fn not(self) -> Uint256 {
+        let Uint256(ref arr) = self;
+        Uint256([!arr[0], !arr[1], !arr[2], !arr[3]])
+    }
+}
+
+impl Shl<usize> for Uint256 {
+    type Output = Uint256;
+
+    fn shl(self, shift: usize) -> Uint256 {
+        let mut ret = [0u64; 4];
+        let word_shift = shift / 64;
+        let bit_shift = shift % 64;
+        for i in 0..4 {
+            if i + word_shift >= 4 {
+                break;
+            }
+            ret[i + word_shift] |= self.0[i] << bit_shift;
+            if bit_shift > 0 && i + word_shift + 1 < 4 {
+                ret[i + word_shift + 1] |= self.0[i] >> (64 - bit_shift);
+            }
+        }
+        Uint256(ret)
+    }
+}
+
+impl Shr<usize> for Uint256 {
+    type Output = Uint256;
+
+    fn shr(self, shift: usize) -> Uint256 {
+        let mut ret = [0u64; 4];
+        let word_shift = shift / 64;
+        let bit_shift = shift % 64;
+        for i in word_shift..4 {
+            ret[i - word_shift] |= self.0[i] >> bit_shift;
+            if bit_shift > 0 && i > word_shift {
+                ret[i - word_shift - 1] |= self.0[i] << (64 - bit_shift);
+            }
+        }
+        Uint256(ret)
+    }
+}
+
+impl Ord for Uint256 {
+    fn cmp(&self, other: &Uint256) -> Ordering {
+        for i in (0..4).rev() {
+            match self.0[i].cmp(&other.0[i]) {
+                Ordering::Equal => continue,
+                ord => return ord,
+            }
+        }
+        Ordering::Equal
+    }
+}
+
+impl PartialOrd for Uint256 {
+    fn partial_cmp(&self, other: &Uint256) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Debug for Uint256 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "0x{:016x}{:016x}{:016x}{:016x}",
+            self.0[3], self.0[2], self.0[1], self.0[0]
+        )
+    }
+}
+
+display_from_debug!(Uint256);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uint256_bits_and_cmp() {
+        assert_eq!(Uint256::zero().bits(), 0);
+        assert_eq!(Uint256::from_u64(1).bits(), 1);
+        assert_eq!(Uint256::from_u64(255).bits(), 8);
+        assert_eq!(Uint256([0, 0, 1, 0]).bits(), 129);
+        assert_eq!((!Uint256::zero()).bits(), 256);
+
+        assert!(Uint256([0, 0, 0, 1]) > Uint256([!0, !0, !0, 0]));
+        assert!(Uint256::from_u64(10) < Uint256::from_u64(11));
+    }
+
+    #[test]
+    fn uint256_arithmetic() {
+        let a = Uint256([!0, 0, 0, 0]);
+        assert_eq!(a + Uint256::from_u64(1), Uint256([0, 1, 0, 0]));
+        assert_eq!(Uint256([0, 1, 0, 0]) - Uint256::from_u64(1), a);
+        assert!((!Uint256::zero()).overflowing_add(Uint256::from_u64(1)).1);
+        assert!(Uint256::zero().overflowing_sub(Uint256::from_u64(1)).1);
+
+        assert_eq!(Uint256::from_u64(1) << 64, Uint256([0, 1, 0, 0]));
+        assert_eq!(Uint256::from_u64(3) << 127, Uint256([0, 1 << 63, 1, 0]));
+        assert_eq!(Uint256([0, 1 << 63, 1, 0]) >> 127, Uint256::from_u64(3));
+        assert_eq!(Uint256::from_u64(1) << 256, Uint256::zero());
+
+        assert_eq!(
+            Uint256::from_u64(1000) / Uint256::from_u64(7),
+            Uint256::from_u64(142)
+        );
+        assert_eq!(
+            Uint256([0, 0, 0, 1]) / Uint256([0, 1, 0, 0]),
+            Uint256([0, 0, 1, 0])
+        );
+        assert_eq!(Uint256::from_u64(3) / Uint256::from_u64(4), Uint256::zero());
+        // 2^256 / 2^224 == 2^32, computed as !x / (x + 1) + 1
+        let target = Uint256::from_u64(1) << 224;
+        let target = target - Uint256::from_u64(1);
+        assert_eq!(
+            !target / (target + Uint256::from_u64(1)) + Uint256::from_u64(1),
+            Uint256::from_u64(1 << 32)
+        );
+    }
+
+    #[test]
+    fn uint256_bytes() {
+        let mut bytes = [0u8; 32];
+        bytes[0] = 0x01;
+        bytes[9] = 0x02;
+        bytes[31] = 0x80;
+        let n = Uint256::from_le_bytes(bytes);
+        assert_eq!(n, Uint256([1, 0x200, 0, 1 << 63]));
+        assert_eq!(n.to_le_bytes(), bytes);
+        assert_eq!(
+            format!("{}", Uint256::from_u64(0xabc)),
+            "0x0000000000000000000000000000000000000000000000000000000000000abc"
+        );
+    }
+}
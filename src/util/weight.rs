@@ -0,0 +1,196 @@
+//! Weight
+//!
+//! The weight of a transaction or block, as defined by BIP141, in weight
+//! units. Non-witness data weighs 4 units per byte and witness data 1.
+
+use std::{fmt, ops};
+
+/// The weight of a transaction or block, in weight units
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+pub struct Weight(u64);
+
+impl Weight {
+    /// Zero weight units.
+    pub const ZERO: Weight = Weight(0);
+    /// The maximum weight of a block.
+    pub const MAX_BLOCK: Weight = Weight(4_000_000);
+    /// The number of weight units in a virtual byte, and the weight of a
+    /// byte of non-witness data.
+    pub const WITNESS_SCALE_FACTOR: u64 = 4;
+
+    /// Creates a weight of `wu` weight units.
+    pub fn from_wu(wu: u64) -> Weight {
+        Weight(wu)
+    }
+
+    /// Creates the weight of `vb` virtual bytes, or `None` on overflow.
+    pub fn from_vb(vb: u64) -> Option<Weight> {
+        vb.checked_mul(Weight::WITNESS_SCALE_FACTOR).map(Weight)
+    }
+
+    /// Creates the weight of `size` bytes of non-witness data, or `None`
+    /// on overflow.
+    pub fn from_non_witness_data_size(size: u64) -> Option<Weight> {
+        Weight::from_vb(size)
+    }
+
+    /// Creates the weight of `size` bytes of witness data.
+    pub fn from_witness_data_size(size: u64) -> Weight {
+        Weight(size)
+    }
+
+    /// The number of weight units.
+    pub fn to_wu(self) -> u64 {
+        self.0
+    }
+
+    /// The number of virtual bytes, rounded down.
+    pub fn to_vbytes_floor(self) -> u64 {
+        self.0 / Weight::WITNESS_SCALE_FACTOR
+    }
+
+    /// The number of virtual bytes, rounded up, as used for the virtual size
+    /// of a transaction.
+    pub fn to_vbytes_ceil(self) -> u64 {
+        (self.0 + Weight::WITNESS_SCALE_FACTOR - 1) / Weight::WITNESS_SCALE_FACTOR
+    }
+
+    /// Checked addition. Returns `None` if overflow occurred.
+    pub fn checked_add(self, rhs: Weight) -> Option<Weight> {
+        self.0.checked_add(rhs.0).map(Weight)
+    }
+
+    /// Checked subtraction. Returns `None` if overflow occurred.
+    pub fn checked_sub(self, rhs: Weight) -> Option<Weight> {
+        self.0.checked_sub(rhs.0).map(Weight)
+    }
+
+    /// Checked multiplication. Returns `None` if overflow occurred.
+    pub fn checked_mul(self, rhs: u64) -> Option<Weight> {
+        self.0.checked_mul(rhs).map(Weight)
+    }
+
+    /// Checked integer division. Returns `None` if `rhs == 0`.
+    pub fn checked_div(self, rhs: u64) -> Option<Weight> {
+        self.0.checked_div(rhs).map(Weight)
+    }
+}
+
+impl fmt::Display for Weight {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} wu", self.0)
+    }
+}
+
+impl From<Weight> for u64 {
+    fn from(weight: Weight) -> u64 {
+        weight.0
+    }
+}
+
+impl ops::Add for Weight {
+    type Output = Weight;
+
+    fn add(self, rhs: Weight) -> Self::Output {
+        self.checked_add(rhs).expect("Weight addition error")
+    }
+}
+
+impl ops::AddAssign for Weight {
+    fn add_assign(&mut self, other: Weight) {
+        *self = *self + other
+    }
+}
+
+impl ops::Sub for Weight {
+    type Output = Weight;
+
+    fn sub(self, rhs: Weight) -> Self::Output {
+        self.checked_sub(rhs).expect("Weight subtraction error")
+    }
+}
+
+impl ops::SubAssign for Weight {
+    fn sub_assign(&mut self, other: Weight) {
+        *self = *self - other
+    }
+}
+
+impl ops::Mul<u64> for Weight {
+    type Output = Weight;
+
+    fn mul(self, rhs: u64) -> Self::Output {
+        self.checked_mul(rhs).expect("Weight multiplication error")
+    }
+}
+
+impl ops::MulAssign<u64> for Weight {
+    fn mul_assign(&mut self, rhs: u64) {
+        *self = *self * rhs
+    }
+}
+
+impl ops::Div<u64> for Weight {
+    type Output = Weight;
+
+    fn div(self, rhs: u64) -> Self::Output {
+        self.checked_div(rhs).expect("Weight division error")
+    }
+}
+
+impl ops::DivAssign<u64> for Weight {
+    fn div_assign(&mut self, rhs: u64) {
+        *self = *self / rhs
+    }
+}
+
+impl ::std::iter::Sum for Weight {
+    fn sum<I: Iterator<Item = Weight>>(iter: I) -> Weight {
+        iter.fold(Weight::ZERO, |a, b| a + b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conversions() {
+        assert_eq!(Weight::from_vb(250), Some(Weight::from_wu(1000)));
+        assert_eq!(Weight::from_vb(u64::max_value()), None);
+        assert_eq!(
+            Weight::from_non_witness_data_size(10).unwrap() + Weight::from_witness_data_size(3),
+            Weight::from_wu(43)
+        );
+        assert_eq!(Weight::from_wu(43).to_vbytes_floor(), 10);
+        assert_eq!(Weight::from_wu(43).to_vbytes_ceil(), 11);
+        assert_eq!(Weight::from_wu(44).to_vbytes_ceil(), 11);
+        assert_eq!(Weight::MAX_BLOCK.to_vbytes_floor(), 1_000_000);
+        assert_eq!(Weight::from_wu(43).to_string(), "43 wu");
+    }
+
+    #[test]
+    fn arithmetic() {
+        let w = Weight::from_wu;
+        assert_eq!(w(10) + w(5), w(15));
+        assert_eq!(w(10) - w(5), w(5));
+        assert_eq!(w(10) * 3, w(30));
+        assert_eq!(w(10) / 3, w(3));
+        assert_eq!(w(10).checked_sub(w(11)), None);
+        assert_eq!(w(10).checked_div(0), None);
+        assert_eq!(vec![w(1), w(2), w(3)].into_iter().sum::<Weight>(), w(6));
+
+        let mut x = w(10);
+        x += w(2);
+        x -= w(1);
+        x *= 2;
+        x /= 11;
+        assert_eq!(x, w(2));
+    }
+
+    #[test]
+    #[should_panic]
+    fn overflow() {
+        let _ = Weight::from_wu(u64::max_value()) + Weight::from_wu(1);
+    }
+}
@@ -0,0 +1,14 @@
+#!/bin/sh
+# Downloads the Bitcoin Core test vectors the `vectors` tests run into
+# tests/vectors/data. The Core release can be given as the first argument.
+
+set -e
+
+VERSION=${1:-v25.0}
+DATA=$(dirname "$0")/data
+
+mkdir -p "$DATA"
+for FILE in tx_valid.json tx_invalid.json script_tests.json sighash.json; do
+    curl -sSfL -o "$DATA/$FILE" \
+        "https://raw.githubusercontent.com/bitcoin/bitcoin/$VERSION/src/test/data/$FILE"
+done
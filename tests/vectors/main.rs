@@ -0,0 +1,227 @@
+//! Bitcoin Core test vectors
+//!
+//! Runs the JSON test vectors Bitcoin Core keeps in `src/test/data` against
+//! this library, as a check that it reads transactions and scripts the way
+//! Core does. The files are read from `tests/vectors/data`, where
+//! `tests/vectors/fetch.sh` downloads them, or from the directory
+//! `BITCOIN_CORE_TEST_DATA` names, such as Core's own `src/test/data`. A
+//! file which is not found fails its test, unless
+//! `BITCOIN_CORE_TEST_DATA_OPTIONAL` is set to skip it.
+//!
+//! This library has no script interpreter, so for now the vectors only
+//! check what it does have:
+//! * `tx_valid.json` and `tx_invalid.json`: every transaction of
+//!   `tx_valid.json` decodes and encodes back to the same bytes, as does any
+//!   transaction of `tx_invalid.json` which decodes, and every previous
+//!   output script parses;
+//! * `script_tests.json`: every script signature and output script parses;
+//! * `sighash.json`: every legacy signature hash matches.
+
+#![allow(ellipsis_inclusive_range_patterns)]
+
+extern crate bitcoin;
+extern crate serde_json;
+
+use std::cmp;
+use std::env;
+use std::fs::File;
+use std::io::Read;
+use std::path::PathBuf;
+
+use bitcoin::consensus::encode::{deserialize, serialize};
+use bitcoin::hashes::hex::{FromHex, ToHex};
+use bitcoin::util::sighash::SigHashCache;
+use bitcoin::{Script, Transaction};
+use serde_json::Value;
+
+/// The directory the vector files are read from
+fn data_dir() -> PathBuf {
+    match env::var_os("BITCOIN_CORE_TEST_DATA") {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/vectors/data"),
+    }
+}
+
+/// The vectors of a file, without the comments. Core writes a comment as
+/// an array holding a single string.
+///
+/// Panics if the file cannot be read, unless missing files are allowed, in
+/// which case `None` is returned.
+fn load(name: &str) -> Option<Vec<Vec<Value>>> {
+    let path = data_dir().join(name);
+    let mut json = String::new();
+    match File::open(&path) {
+        Ok(mut file) => file.read_to_string(&mut json).unwrap(),
+        Err(e) => {
+            if env::var_os("BITCOIN_CORE_TEST_DATA_OPTIONAL").is_none() {
+                panic!(
+                    "cannot read {}: {}; run tests/vectors/fetch.sh, or set \
+                     BITCOIN_CORE_TEST_DATA_OPTIONAL to skip it",
+                    path.display(),
+                    e
+                );
+            }
+            eprintln!("skipping {}: {}", path.display(), e);
+            return None;
+        }
+    };
+    Some(parse(&json))
+}
+
+fn parse(json: &str) -> Vec<Vec<Value>> {
+    let vectors: Vec<Vec<Value>> = serde_json::from_str(json).expect("vector file is valid JSON");
+    vectors
+        .into_iter()
+        .filter(|v| !(v.len() == 1 && v[0].is_string()))
+        .collect()
+}
+
+fn str_at(vector: &[Value], index: usize) -> &str {
+    vector[index]
+        .as_str()
+        .unwrap_or_else(|| panic!("vector {:?}: item {} is not a string", vector, index))
+}
+
+fn decode_tx(hex: &str) -> Result<Transaction, bitcoin::consensus::encode::Error> {
+    let bytes = Vec::<u8>::from_hex(hex).expect("transaction hex");
+    let tx: Transaction = deserialize(&bytes)?;
+    assert_eq!(
+        serialize(&tx),
+        bytes,
+        "transaction {} encodes back differently",
+        hex
+    );
+    Ok(tx)
+}
+
+/// Checks a `tx_valid.json` or `tx_invalid.json` vector, which is
+/// `[[[prevout hash, prevout index, prevout script asm, amount?]...],
+/// serialized transaction, verify flags]`, and returns whether the
+/// transaction decoded.
+fn check_tx_vector(vector: &[Value]) -> bool {
+    let prevouts = vector[0].as_array().expect("prevouts");
+    for prevout in prevouts {
+        let asm = prevout[2].as_str().expect("prevout script");
+        if let Err(e) = Script::from_asm(asm) {
+            panic!("prevout script {:?}: {}", asm, e);
+        }
+    }
+    decode_tx(str_at(vector, 1)).is_ok()
+}
+
+#[test]
+fn tx_valid() {
+    if let Some(vectors) = load("tx_valid.json") {
+        for vector in vectors {
+            assert!(
+                check_tx_vector(&vector),
+                "vector {:?} does not decode",
+                vector
+            );
+        }
+    }
+}
+
+#[test]
+fn tx_invalid() {
+    // These transactions break consensus rules other than the encoding, so
+    // some may fail to decode, but those which decode must encode back
+    if let Some(vectors) = load("tx_invalid.json") {
+        for vector in vectors {
+            check_tx_vector(&vector);
+        }
+    }
+}
+
+#[test]
+fn script_tests() {
+    // A vector is `[[witness..., amount]?, script sig asm, script pubkey
+    // asm, flags, expected result, comment?]`
+    if let Some(vectors) = load("script_tests.json") {
+        let mut skipped = 0;
+        for vector in vectors {
+            let offset = if vector[0].is_array() { 1 } else { 0 };
+            for asm in &[str_at(&vector, offset), str_at(&vector, offset + 1)] {
+                // Quoted string pushes are only written by Core's tests
+                if asm.contains('\'') {
+                    skipped += 1;
+                    continue;
+                }
+                if let Err(e) = Script::from_asm(asm) {
+                    panic!("vector {:?}: script {:?}: {}", vector, asm, e);
+                }
+            }
+        }
+        eprintln!(
+            "script_tests.json: skipped {} scripts with strings",
+            skipped
+        );
+    }
+}
+
+/// The script code as Core signs it, without its `OP_CODESEPARATOR`s
+fn strip_codeseparators(script: &[u8]) -> Script {
+    let mut ret = Vec::with_capacity(script.len());
+    let mut i = 0;
+    while i < script.len() {
+        let op = script[i];
+        // The length of the instruction, with the data it pushes
+        let len = match op {
+            0x01...0x4b => 1 + op as usize,
+            0x4c if i + 2 <= script.len() => 2 + script[i + 1] as usize,
+            0x4d if i + 3 <= script.len() => {
+                3 + (script[i + 1] as usize | (script[i + 2] as usize) << 8)
+            }
+            0x4e if i + 5 <= script.len() => {
+                let mut n = 0;
+                for j in (1..5).rev() {
+                    n = n << 8 | script[i + j] as usize;
+                }
+                5 + n
+            }
+            _ => 1,
+        };
+        let end = cmp::min(i + len, script.len());
+        if op != 0xab {
+            ret.extend_from_slice(&script[i..end]);
+        }
+        i = end;
+    }
+    Script::from(ret)
+}
+
+#[test]
+fn sighash() {
+    // A vector is `[raw transaction, script hex, input index, hash type,
+    // signature hash]`
+    if let Some(vectors) = load("sighash.json") {
+        for vector in vectors {
+            let tx = decode_tx(str_at(&vector, 0)).expect("transaction decodes");
+            let script = Vec::<u8>::from_hex(str_at(&vector, 1)).expect("script hex");
+            let index = vector[2].as_u64().expect("input index") as usize;
+            let sighash_type = vector[3].as_i64().expect("hash type") as u32;
+            let sighash = SigHashCache::new(&tx)
+                .legacy_signature_hash(index, &strip_codeseparators(&script), sighash_type)
+                .unwrap_or_else(|e| panic!("vector {:?}: {}", vector, e));
+            assert_eq!(
+                sighash.to_hex(),
+                str_at(&vector, 4),
+                "vector {:?}: signature hash",
+                vector
+            );
+        }
+    }
+}
+
+#[test]
+fn loader() {
+    let json = r#"[
+        ["A comment"],
+        [[["0000000000000000000000000000000000000000000000000000000000000100", 0, "DUP HASH160 0x14 0x5b6462475454710f3c22f5fdf0b40704c92f25c3 EQUALVERIFY CHECKSIG"]],
+        "0100000001a15d57094aa7a21a28cb20b59aab8fc7d1149a3bdbcddba9c622e4f5f6a99ece010000006c493046022100f93bb0e7d8db7bd46e40132d1f8242026e045f03a0efe71bbb8e3f475e970d790221009337cd7f1f929f00cc6ff01f03729b069a7c21b59b1736ddfee5db5946c5da8c0121033b9b137ee87d5a812d6f506efdd37f0affa7ffc310711c06c7f3e097c9447c52ffffffff0100e1f505000000001976a9140389035a9225b3839e2bbf32d826a1e222031fd888ac00000000",
+        "P2SH"]
+    ]"#;
+    let vectors = parse(json);
+    assert_eq!(vectors.len(), 1);
+    assert!(check_tx_vector(&vectors[0]));
+}
@@ -0,0 +1,187 @@
+//! WebAssembly Bindings
+//!
+//! Exposes transactions, PSBTs, addresses and amounts to JavaScript through
+//! wasm-bindgen, so that PSBT tooling can run in the browser. Build with
+//! `wasm-pack build wasm`.
+//!
+//! Failures are thrown as JavaScript `Error`s whose `code` property is the
+//! stable `ErrorCode` of the library error.
+
+use std::fmt;
+use std::str::FromStr;
+
+use js_sys::Reflect;
+use wasm_bindgen::prelude::*;
+
+use bitcoin::consensus::encode::{deserialize, serialize};
+use bitcoin::network::constants::Network;
+use bitcoin::util::address;
+use bitcoin::util::amount::Denomination;
+use bitcoin::util::psbt::PartiallySignedTransaction;
+use bitcoin::{Amount, ErrorCode, Script};
+
+/// A JavaScript `Error` carrying the code of a library error
+fn js_error<E: fmt::Display + ErrorCode>(e: E) -> JsValue {
+    let error = js_sys::Error::new(&e.to_string());
+    // Setting a property of a plain error object cannot fail
+    let _ = Reflect::set(&error, &JsValue::from_str("code"), &JsValue::from(e.code()));
+    error.into()
+}
+
+/// A transaction
+#[wasm_bindgen]
+pub struct Transaction(bitcoin::Transaction);
+
+#[wasm_bindgen]
+impl Transaction {
+    /// Decodes a transaction from its consensus encoding
+    #[wasm_bindgen(js_name = fromBytes)]
+    pub fn from_bytes(bytes: &[u8]) -> Result<Transaction, JsValue> {
+        deserialize(bytes).map(Transaction).map_err(js_error)
+    }
+
+    /// The consensus encoding of the transaction
+    #[wasm_bindgen(js_name = toBytes)]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        serialize(&self.0)
+    }
+
+    /// The txid, in hex
+    pub fn txid(&self) -> String {
+        self.0.txid().to_string()
+    }
+
+    /// The wtxid, in hex
+    pub fn wtxid(&self) -> String {
+        self.0.wtxid().to_string()
+    }
+
+    /// The weight, in weight units
+    pub fn weight(&self) -> usize {
+        self.0.get_weight()
+    }
+
+    /// The virtual size, in vbytes
+    pub fn vsize(&self) -> usize {
+        self.0.get_vsize()
+    }
+}
+
+/// A PSBT
+#[wasm_bindgen]
+pub struct Psbt(PartiallySignedTransaction);
+
+#[wasm_bindgen]
+impl Psbt {
+    /// Decodes a PSBT from its binary encoding, checking that the UTXOs of
+    /// its inputs are consistent with its transaction
+    #[wasm_bindgen(js_name = fromBytes)]
+    pub fn from_bytes(bytes: &[u8]) -> Result<Psbt, JsValue> {
+        PartiallySignedTransaction::deserialize_strict(bytes)
+            .map(Psbt)
+            .map_err(js_error)
+    }
+
+    /// The binary encoding of the PSBT
+    #[wasm_bindgen(js_name = toBytes)]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        serialize(&self.0)
+    }
+
+    /// The number of inputs
+    #[wasm_bindgen(js_name = inputCount)]
+    pub fn input_count(&self) -> usize {
+        self.0.inputs.len()
+    }
+
+    /// The number of outputs
+    #[wasm_bindgen(js_name = outputCount)]
+    pub fn output_count(&self) -> usize {
+        self.0.outputs.len()
+    }
+
+    /// Whether the input at `index` exists and has been finalized
+    #[wasm_bindgen(js_name = isFinalized)]
+    pub fn is_finalized(&self, index: usize) -> bool {
+        self.0
+            .inputs
+            .get(index)
+            .map_or(false, |input| input.is_finalized())
+    }
+
+    /// The fee paid, in satoshis
+    pub fn fee(&self) -> Result<u64, JsValue> {
+        self.0.fee().map(Amount::as_sat).map_err(js_error)
+    }
+
+    /// The network transaction, once every input has been finalized
+    #[wasm_bindgen(js_name = extractTx)]
+    pub fn extract_tx(&self) -> Result<Transaction, JsValue> {
+        self.0
+            .clone()
+            .extract_tx_checked()
+            .map(Transaction)
+            .map_err(js_error)
+    }
+}
+
+/// An address
+#[wasm_bindgen]
+pub struct Address(address::Address);
+
+#[wasm_bindgen]
+impl Address {
+    /// Parses an address
+    #[wasm_bindgen(constructor)]
+    pub fn new(s: &str) -> Result<Address, JsValue> {
+        address::Address::from_str(s)
+            .map(Address)
+            .map_err(js_error)
+    }
+
+    /// The address paying to a script, on a network named as in
+    /// "bitcoin", "testnet" or "regtest", if the script has an address
+    #[wasm_bindgen(js_name = fromScript)]
+    pub fn from_script(script: &[u8], network: &str) -> Result<Option<Address>, JsValue> {
+        let network = Network::from_str(network).map_err(|e| JsValue::from(e.to_string()))?;
+        let script = Script::from(script.to_vec());
+        Ok(address::Address::from_script(&script, network).map(Address))
+    }
+
+    /// The script paying to the address
+    #[wasm_bindgen(js_name = scriptPubkey)]
+    pub fn script_pubkey(&self) -> Vec<u8> {
+        self.0.script_pubkey().to_bytes()
+    }
+
+    /// The network of the address
+    pub fn network(&self) -> String {
+        self.0.network.to_string()
+    }
+
+    /// The type of the address, such as "p2wpkh", if it is standard
+    #[wasm_bindgen(js_name = addressType)]
+    pub fn address_type(&self) -> Option<String> {
+        self.0.address_type().map(|t| t.to_string())
+    }
+
+    /// The address as a string
+    #[wasm_bindgen(js_name = toString)]
+    pub fn to_js_string(&self) -> String {
+        self.0.to_string()
+    }
+}
+
+/// Parses an amount with its denomination, such as "0.1 BTC", into
+/// satoshis
+#[wasm_bindgen(js_name = parseAmount)]
+pub fn parse_amount(s: &str) -> Result<u64, JsValue> {
+    Amount::from_str(s).map(Amount::as_sat).map_err(js_error)
+}
+
+/// Formats an amount of satoshis in a denomination, such as "BTC"
+#[wasm_bindgen(js_name = formatAmount)]
+pub fn format_amount(sat: u64, denomination: &str) -> Result<String, JsValue> {
+    let denomination = Denomination::from_str(denomination).map_err(js_error)?;
+    Ok(Amount::from_sat(sat).to_string_with_denomination(denomination))
+}